@@ -0,0 +1,88 @@
+//! Hosting stimstation in your own window: the host owns the event loop and
+//! the pixel buffer and forwards input to a `StimStation`.
+//!
+//! `cargo run --example embed -- [scene]`
+
+use pixels::{Pixels, SurfaceTexture};
+use std::sync::Arc;
+use std::time::Instant;
+use stimstation::{StimConfig, StimStation};
+use winit::dpi::LogicalSize;
+use winit::event::{ElementState, Event, WindowEvent};
+use winit::event_loop::{ControlFlow, EventLoop};
+use winit::keyboard::{KeyCode, PhysicalKey};
+use winit::window::WindowBuilder;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let config = StimConfig::default();
+    let mut station = StimStation::new(config)?;
+    if let Some(scene) = std::env::args().nth(1) {
+        station.set_scene(&scene)?;
+    }
+
+    let event_loop = EventLoop::new()?;
+    let window = Arc::new(
+        WindowBuilder::new()
+            .with_title("stimstation embedded")
+            .with_inner_size(LogicalSize::new(config.width, config.height))
+            .build(&event_loop)?,
+    );
+    let size = window.inner_size();
+    let surface = SurfaceTexture::new(size.width, size.height, Arc::clone(&window));
+    let mut pixels = Pixels::new(config.width, config.height, surface)?;
+    let mut last = Instant::now();
+
+    event_loop.run(move |event, target| {
+        target.set_control_flow(ControlFlow::Poll);
+        let Event::WindowEvent { event, .. } = event else {
+            if let Event::AboutToWait = event {
+                window.request_redraw();
+            }
+            return;
+        };
+        match event {
+            WindowEvent::CloseRequested => target.exit(),
+            WindowEvent::KeyboardInput { event: key, .. } => {
+                if let PhysicalKey::Code(code) = key.physical_key {
+                    if code == KeyCode::Escape {
+                        target.exit();
+                    }
+                    station.handle_key(code, key.state == ElementState::Pressed);
+                }
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                // Window pixels to frame pixels
+                let size = window.inner_size();
+                station.handle_cursor(
+                    position.x as f32 * config.width as f32 / size.width.max(1) as f32,
+                    position.y as f32 * config.height as f32 / size.height.max(1) as f32,
+                );
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                station.handle_button(button, state == ElementState::Pressed);
+            }
+            WindowEvent::Resized(size) => {
+                if let Err(e) = pixels.resize_surface(size.width, size.height) {
+                    eprintln!("resize failed: {}", e);
+                    target.exit();
+                }
+            }
+            WindowEvent::RedrawRequested => {
+                let now = Instant::now();
+                let dt = (now - last).as_secs_f32().min(0.1);
+                last = now;
+                if let Err(e) = station.render(pixels.frame_mut(), dt) {
+                    eprintln!("render failed: {}", e);
+                    target.exit();
+                    return;
+                }
+                if let Err(e) = pixels.render() {
+                    eprintln!("present failed: {}", e);
+                    target.exit();
+                }
+            }
+            _ => {}
+        }
+    })?;
+    Ok(())
+}
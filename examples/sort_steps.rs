@@ -0,0 +1,42 @@
+//! Driving the sorting algorithms without drawing anything: each one sorts
+//! the same array through the `SortMachine` iterator, and the step counts are
+//! printed side by side.
+//!
+//! `cargo run --example sort_steps -- [size]`
+
+use stimstation::{SortAlgorithm, SortMachine};
+
+/// Bogo Sort shuffles at random, so it only gets a handful of elements
+const BOGO_SIZE: usize = 6;
+
+fn main() {
+    let size = std::env::args()
+        .nth(1)
+        .and_then(|arg| arg.parse().ok())
+        .unwrap_or(100usize);
+    // A fixed scramble so every run sorts the same input
+    let array: Vec<u8> = (0..size).map(|i| (i * 97 % 251) as u8).collect();
+
+    println!(
+        "{:<16} {:>6} {:>8} {:>12} {:>10}",
+        "algorithm", "size", "steps", "comparisons", "accesses"
+    );
+    for algorithm in SortAlgorithm::ALL {
+        let len = if algorithm == SortAlgorithm::Bogo {
+            size.min(BOGO_SIZE)
+        } else {
+            size
+        };
+        let mut machine = SortMachine::new(algorithm.clone(), array[..len].to_vec());
+        machine.by_ref().for_each(drop);
+        assert!(machine.array.windows(2).all(|pair| pair[0] <= pair[1]));
+        println!(
+            "{:<16} {:>6} {:>8} {:>12} {:>10}",
+            algorithm.name(),
+            len,
+            machine.steps,
+            machine.comparisons,
+            machine.accesses
+        );
+    }
+}
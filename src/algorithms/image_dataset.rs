@@ -0,0 +1,97 @@
+//! Photo rows as sorter input. A row of the image is sampled down to the
+//! sorter's size; the bars are sorted by pixel brightness and keep the
+//! pixels' own colors, so sorting turns the row into a gradient.
+
+use image::RgbImage;
+use std::path::Path;
+
+/// Rows offered when cycling, spread evenly from top to bottom
+pub const ROW_CHOICES: u32 = 8;
+
+/// One sampled row: brightness values and the pixel colors they came from
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageRow {
+    pub values: Vec<u8>,
+    pub colors: Vec<[u8; 3]>,
+}
+
+/// Rec. 709 luma of an sRGB pixel, 0-255
+pub fn luminance(rgb: [u8; 3]) -> u8 {
+    let [r, g, b] = rgb.map(|c| c as f32);
+    (0.2126 * r + 0.7152 * g + 0.0722 * b).round() as u8
+}
+
+/// An image to take sorter rows from, and which of its rows is in use
+pub struct ImageDataset {
+    image: RgbImage,
+    choice: u32,
+}
+
+impl ImageDataset {
+    pub fn load(path: &Path) -> Result<Self, image::ImageError> {
+        Ok(Self::new(image::open(path)?.to_rgb8()))
+    }
+
+    pub fn new(image: RgbImage) -> Self {
+        Self { image, choice: 0 }
+    }
+
+    /// Pixel row currently in use
+    pub fn row_index(&self) -> u32 {
+        let choices = ROW_CHOICES.min(self.image.height()).max(1);
+        (self.choice % choices) * self.image.height() / choices
+    }
+
+    /// Moves to the next of the evenly spaced rows and returns its pixel row
+    pub fn next_row(&mut self) -> u32 {
+        self.choice = (self.choice + 1) % ROW_CHOICES.min(self.image.height()).max(1);
+        self.row_index()
+    }
+
+    /// The current row sampled at `size` evenly spaced pixels
+    pub fn row(&self, size: usize) -> ImageRow {
+        let (width, y) = (self.image.width(), self.row_index());
+        let colors: Vec<[u8; 3]> = if width == 0 {
+            vec![[0; 3]; size]
+        } else {
+            (0..size)
+                .map(|i| {
+                    let x = ((i as u64 * 2 + 1) * width as u64 / (size as u64 * 2)) as u32;
+                    self.image.get_pixel(x.min(width - 1), y).0
+                })
+                .collect()
+        };
+        ImageRow {
+            values: colors.iter().map(|&rgb| luminance(rgb)).collect(),
+            colors,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    #[test]
+    fn test_rows_sample_brightness_and_colors() {
+        // Left half red, right half white; the bottom half is black
+        let image = RgbImage::from_fn(8, 16, |x, y| match (x < 4, y < 8) {
+            (_, false) => Rgb([0, 0, 0]),
+            (true, true) => Rgb([255, 0, 0]),
+            (false, true) => Rgb([255, 255, 255]),
+        });
+        let mut dataset = ImageDataset::new(image);
+        let row = dataset.row(4);
+        assert_eq!(row.colors, [[255, 0, 0], [255, 0, 0], [255; 3], [255; 3]]);
+        assert_eq!(row.values, [54, 54, 255, 255]);
+
+        // Cycling steps down the image and wraps around
+        let rows: Vec<u32> = (0..ROW_CHOICES).map(|_| dataset.next_row()).collect();
+        assert_eq!(rows, [2, 4, 6, 8, 10, 12, 14, 0]);
+        for _ in 0..4 {
+            dataset.next_row();
+        }
+        assert_eq!(dataset.row(3).values, [0, 0, 0]);
+    }
+}
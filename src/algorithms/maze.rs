@@ -0,0 +1,634 @@
+//! The Maze scene: a maze carved by recursive backtracking, solved by BFS or
+//! A* with the frontier spreading out, and the solution path glowing before
+//! a new maze starts with the next seed.
+//!
+//! Generation and solving are state machines advanced one step at a time,
+//! so the animation speed is a step count per frame and a paused scene
+//! clock stops them.
+
+use crate::core::sim_rng::sim_rng;
+use crate::core::station_state;
+use crate::core::types::rgba_to_color;
+use crate::graphics::draw_ctx::DrawCtx;
+use crate::physics::fireworks::{self, Burst};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+use std::sync::atomic::Ordering;
+
+/// Target cell size in pixels; the grid is as many cells as fit the frame
+const CELL_SIZE: u32 = 24;
+/// Roughly how long each phase takes at 60 fps, whatever the grid size
+const GENERATE_SECONDS: f32 = 8.0;
+const SOLVE_SECONDS: f32 = 5.0;
+/// How long the solved path glows before the next maze starts
+const CELEBRATE_SECONDS: f32 = 3.0;
+const WALL_THICKNESS: u32 = 2;
+
+const CARVED: [u8; 4] = [34, 36, 60, 255];
+const WALL: [u8; 4] = [150, 160, 210, 255];
+const CARVING: [u8; 4] = [255, 120, 80, 255];
+const EXPLORED: [u8; 4] = [40, 80, 120, 255];
+const FRONTIER: [u8; 4] = [90, 200, 255, 255];
+const PATH: [u8; 4] = [255, 220, 90, 255];
+
+/// Directions as wall bits, with the offset each one moves by
+const DIRECTIONS: [(u8, i32, i32); 4] = [(1, 0, -1), (2, 1, 0), (4, 0, 1), (8, -1, 0)];
+
+fn opposite(direction: u8) -> u8 {
+    match direction {
+        1 => 4,
+        2 => 8,
+        4 => 1,
+        _ => 2,
+    }
+}
+
+/// A grid of cells, each knowing which of its sides are open passages
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Maze {
+    pub cols: usize,
+    pub rows: usize,
+    /// Open sides of each cell as direction bits
+    open: Vec<u8>,
+}
+
+impl Maze {
+    /// A `cols` x `rows` grid with every wall standing
+    pub fn new(cols: usize, rows: usize) -> Self {
+        let (cols, rows) = (cols.max(1), rows.max(1));
+        Self {
+            cols,
+            rows,
+            open: vec![0; cols * rows],
+        }
+    }
+
+    pub fn cell_count(&self) -> usize {
+        self.cols * self.rows
+    }
+
+    /// The cell one step from `cell` in `direction`, if inside the grid
+    fn step(&self, cell: usize, direction: (u8, i32, i32)) -> Option<usize> {
+        let x = (cell % self.cols) as i32 + direction.1;
+        let y = (cell / self.cols) as i32 + direction.2;
+        let inside = x >= 0 && y >= 0 && x < self.cols as i32 && y < self.rows as i32;
+        inside.then(|| y as usize * self.cols + x as usize)
+    }
+
+    /// Whether the wall on `cell`'s `direction` side has been carved away
+    pub fn is_open(&self, cell: usize, direction: u8) -> bool {
+        self.open[cell] & direction != 0
+    }
+
+    /// Cells reachable from `cell` in one step
+    pub fn passages(&self, cell: usize) -> impl Iterator<Item = usize> + '_ {
+        DIRECTIONS
+            .into_iter()
+            .filter(move |&(bit, _, _)| self.is_open(cell, bit))
+            .filter_map(move |direction| self.step(cell, direction))
+    }
+
+    fn carve(&mut self, cell: usize, direction: u8, next: usize) {
+        self.open[cell] |= direction;
+        self.open[next] |= opposite(direction);
+    }
+}
+
+/// Recursive backtracking as an explicit stack, one carve or backtrack per step
+#[derive(Debug, Clone)]
+pub struct Generator {
+    pub maze: Maze,
+    stack: Vec<usize>,
+    visited: Vec<bool>,
+    rng: StdRng,
+}
+
+impl Generator {
+    pub fn new(cols: usize, rows: usize, seed: u64) -> Self {
+        let maze = Maze::new(cols, rows);
+        let mut visited = vec![false; maze.cell_count()];
+        visited[0] = true;
+        Self {
+            maze,
+            stack: vec![0],
+            visited,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.stack.is_empty()
+    }
+
+    /// The cell being carved from, while generating
+    pub fn current(&self) -> Option<usize> {
+        self.stack.last().copied()
+    }
+
+    /// Carves into a random unvisited neighbor, or backs up when there is none.
+    /// Returns false once the maze is finished.
+    pub fn step(&mut self) -> bool {
+        let Some(&cell) = self.stack.last() else {
+            return false;
+        };
+        let candidates: Vec<_> = DIRECTIONS
+            .into_iter()
+            .filter_map(|direction| {
+                let next = self.maze.step(cell, direction)?;
+                (!self.visited[next]).then_some((direction.0, next))
+            })
+            .collect();
+        if candidates.is_empty() {
+            self.stack.pop();
+        } else {
+            let (direction, next) = candidates[self.rng.gen_range(0..candidates.len())];
+            self.maze.carve(cell, direction, next);
+            self.visited[next] = true;
+            self.stack.push(next);
+        }
+        true
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SolverKind {
+    Bfs,
+    AStar,
+}
+
+impl SolverKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SolverKind::Bfs => "BFS",
+            SolverKind::AStar => "A*",
+        }
+    }
+}
+
+/// Shortest-path search from the top-left to the bottom-right cell, one
+/// expanded cell per step
+#[derive(Debug, Clone)]
+pub struct Solver {
+    pub kind: SolverKind,
+    goal: usize,
+    /// BFS visits cells in order; A* by cost so far plus distance left
+    queue: VecDeque<usize>,
+    heap: BinaryHeap<Reverse<(usize, usize)>>,
+    cost: Vec<usize>,
+    came_from: Vec<Option<usize>>,
+    expanded: Vec<bool>,
+    path: Option<Vec<usize>>,
+}
+
+impl Solver {
+    pub fn new(kind: SolverKind, maze: &Maze) -> Self {
+        let count = maze.cell_count();
+        let mut solver = Self {
+            kind,
+            goal: count - 1,
+            queue: VecDeque::new(),
+            heap: BinaryHeap::new(),
+            cost: vec![usize::MAX; count],
+            came_from: vec![None; count],
+            expanded: vec![false; count],
+            path: None,
+        };
+        solver.cost[0] = 0;
+        solver.push(maze, 0);
+        solver
+    }
+
+    fn push(&mut self, maze: &Maze, cell: usize) {
+        match self.kind {
+            SolverKind::Bfs => self.queue.push_back(cell),
+            SolverKind::AStar => {
+                let (x, y) = (cell % maze.cols, cell / maze.cols);
+                let remaining = (maze.cols - 1 - x) + (maze.rows - 1 - y);
+                self.heap.push(Reverse((self.cost[cell] + remaining, cell)));
+            }
+        }
+    }
+
+    fn pop(&mut self) -> Option<usize> {
+        match self.kind {
+            SolverKind::Bfs => self.queue.pop_front(),
+            SolverKind::AStar => self.heap.pop().map(|Reverse((_, cell))| cell),
+        }
+    }
+
+    /// The cells from start to goal, once found
+    pub fn path(&self) -> Option<&[usize]> {
+        self.path.as_deref()
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.path.is_some() || (self.queue.is_empty() && self.heap.is_empty())
+    }
+
+    /// Cells waiting to be expanded
+    pub fn frontier(&self) -> Vec<usize> {
+        match self.kind {
+            SolverKind::Bfs => self.queue.iter().copied().collect(),
+            SolverKind::AStar => self.heap.iter().map(|Reverse((_, cell))| *cell).collect(),
+        }
+    }
+
+    pub fn is_expanded(&self, cell: usize) -> bool {
+        self.expanded[cell]
+    }
+
+    /// Expands the next cell. Returns false once the search is over.
+    pub fn step(&mut self, maze: &Maze) -> bool {
+        if self.is_done() {
+            return false;
+        }
+        let Some(cell) = self.pop() else {
+            return false;
+        };
+        if self.expanded[cell] {
+            return true;
+        }
+        self.expanded[cell] = true;
+        if cell == self.goal {
+            let mut path = vec![cell];
+            while let Some(previous) = self.came_from[*path.last().unwrap()] {
+                path.push(previous);
+            }
+            path.reverse();
+            self.path = Some(path);
+            return false;
+        }
+        let next_cost = self.cost[cell] + 1;
+        let neighbors: Vec<_> = maze.passages(cell).collect();
+        for next in neighbors {
+            if next_cost < self.cost[next] {
+                self.cost[next] = next_cost;
+                self.came_from[next] = Some(cell);
+                self.push(maze, next);
+            }
+        }
+        true
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Phase {
+    Generating(Box<Generator>),
+    Solving(Maze, Solver),
+    Celebrating(Maze, Solver, f32),
+}
+
+/// The scene's loop: generate, solve, celebrate, and again with the next seed
+#[derive(Debug, Clone)]
+pub struct MazeRun {
+    phase: Phase,
+    seed: u64,
+    /// Steps owed to the current phase, carried between frames
+    pending: f32,
+}
+
+impl MazeRun {
+    pub fn new(cols: usize, rows: usize, seed: u64) -> Self {
+        Self {
+            phase: Phase::Generating(Box::new(Generator::new(cols, rows, seed))),
+            seed,
+            pending: 0.0,
+        }
+    }
+
+    fn maze(&self) -> &Maze {
+        match &self.phase {
+            Phase::Generating(generator) => &generator.maze,
+            Phase::Solving(maze, _) | Phase::Celebrating(maze, _, _) => maze,
+        }
+    }
+
+    /// Advances by `dt` seconds. Returns the solved path's cells on the
+    /// update the goal is reached, for a celebration.
+    pub fn update(&mut self, dt: f32) -> Option<Vec<usize>> {
+        let cells = self.maze().cell_count() as f32;
+        let (cols, rows) = (self.maze().cols, self.maze().rows);
+        let rate = match self.phase {
+            // Each cell is carved into once and backed out of once
+            Phase::Generating(_) => 2.0 * cells / GENERATE_SECONDS,
+            Phase::Solving(..) => cells / SOLVE_SECONDS,
+            Phase::Celebrating(..) => 0.0,
+        };
+        self.pending += rate * dt;
+        let mut solved = None;
+        match &mut self.phase {
+            Phase::Generating(generator) => {
+                while self.pending >= 1.0 && generator.step() {
+                    self.pending -= 1.0;
+                }
+                if generator.is_done() {
+                    let maze = generator.maze.clone();
+                    let kind = if self.seed.is_multiple_of(2) {
+                        SolverKind::Bfs
+                    } else {
+                        SolverKind::AStar
+                    };
+                    let solver = Solver::new(kind, &maze);
+                    self.phase = Phase::Solving(maze, solver);
+                    self.pending = 0.0;
+                }
+            }
+            Phase::Solving(maze, solver) => {
+                while self.pending >= 1.0 && solver.step(maze) {
+                    self.pending -= 1.0;
+                }
+                if solver.is_done() {
+                    solved = solver.path().map(<[usize]>::to_vec);
+                    self.phase = Phase::Celebrating(maze.clone(), solver.clone(), 0.0);
+                    self.pending = 0.0;
+                }
+            }
+            Phase::Celebrating(_, _, elapsed) => {
+                *elapsed += dt;
+                if *elapsed >= CELEBRATE_SECONDS {
+                    self.seed = self.seed.wrapping_add(1);
+                    self.phase = Phase::Generating(Box::new(Generator::new(cols, rows, self.seed)));
+                    self.pending = 0.0;
+                }
+            }
+        }
+        solved
+    }
+
+    /// Draws the maze, cell size `cell` pixels, offset so the grid is centered
+    fn draw(&self, ctx: &mut DrawCtx, layout: &Layout, time: f32) {
+        let maze = self.maze();
+        let fill = |ctx: &mut DrawCtx, cell: usize, color: [u8; 4]| {
+            let (x, y) = layout.origin(maze, cell);
+            ctx.fill_rect(x, y, layout.cell, layout.cell, color);
+        };
+        let carved = |cell: usize| match &self.phase {
+            Phase::Generating(generator) => generator.visited[cell],
+            _ => true,
+        };
+        for cell in 0..maze.cell_count() {
+            if carved(cell) {
+                fill(ctx, cell, CARVED);
+            }
+        }
+        match &self.phase {
+            Phase::Generating(generator) => {
+                if let Some(cell) = generator.current() {
+                    fill(ctx, cell, CARVING);
+                }
+            }
+            Phase::Solving(_, solver) => {
+                for cell in (0..maze.cell_count()).filter(|&c| solver.is_expanded(c)) {
+                    fill(ctx, cell, EXPLORED);
+                }
+                for cell in solver.frontier() {
+                    fill(ctx, cell, FRONTIER);
+                }
+            }
+            Phase::Celebrating(_, solver, _) => {
+                for cell in (0..maze.cell_count()).filter(|&c| solver.is_expanded(c)) {
+                    fill(ctx, cell, EXPLORED);
+                }
+                // The path glows in a wave running from start to goal
+                for (i, &cell) in solver.path().unwrap_or_default().iter().enumerate() {
+                    let glow = 0.65 + 0.35 * (time * 6.0 - i as f32 * 0.3).sin();
+                    let color = PATH.map(|c| (c as f32 * glow) as u8);
+                    fill(ctx, cell, [color[0], color[1], color[2], 255]);
+                }
+            }
+        }
+        // Walls go up only around cells that have been carved
+        let thickness = layout.wall;
+        for cell in (0..maze.cell_count()).filter(|&c| carved(c)) {
+            let (x, y) = layout.origin(maze, cell);
+            let size = layout.cell;
+            if !maze.is_open(cell, 1) {
+                ctx.fill_rect(x, y, size + thickness, thickness, WALL);
+            }
+            if !maze.is_open(cell, 2) {
+                ctx.fill_rect(x + size as i32, y, thickness, size + thickness, WALL);
+            }
+            if !maze.is_open(cell, 4) {
+                ctx.fill_rect(x, y + size as i32, size + thickness, thickness, WALL);
+            }
+            if !maze.is_open(cell, 8) {
+                ctx.fill_rect(x, y, thickness, size + thickness, WALL);
+            }
+        }
+    }
+}
+
+/// Where the grid sits in the frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Layout {
+    cell: u32,
+    wall: u32,
+    left: i32,
+    top: i32,
+}
+
+impl Layout {
+    /// The grid centered in a `width` x `height` frame, drawn `zoom` times
+    /// the usual size
+    fn centered(maze: &Maze, width: u32, height: u32, zoom: u32) -> Self {
+        let (cell, wall) = (CELL_SIZE * zoom, WALL_THICKNESS * zoom);
+        let margin =
+            |extent: u32, count: usize| extent.saturating_sub(count as u32 * cell + wall) / 2;
+        Self {
+            cell,
+            wall,
+            left: margin(width, maze.cols) as i32,
+            top: margin(height, maze.rows) as i32,
+        }
+    }
+
+    fn origin(&self, maze: &Maze, cell: usize) -> (i32, i32) {
+        let (x, y) = (cell % maze.cols, cell / maze.cols);
+        (
+            self.left + (x as u32 * self.cell) as i32,
+            self.top + (y as u32 * self.cell) as i32,
+        )
+    }
+}
+
+/// How many times larger `size` is than `built_for`, if a whole multiple of it
+fn zoom_between(built_for: (u32, u32), size: (u32, u32)) -> Option<u32> {
+    let zoom = size.0 / built_for.0.max(1);
+    (zoom >= 1 && size == (built_for.0 * zoom, built_for.1 * zoom)).then_some(zoom)
+}
+
+/// Columns and rows of `CELL_SIZE` cells that fit a `width` x `height` frame
+/// with room for the outer walls
+pub fn grid_size(width: u32, height: u32) -> (usize, usize) {
+    let fit = |extent: u32| (extent.saturating_sub(WALL_THICKNESS) / CELL_SIZE).max(2) as usize;
+    (fit(width), fit(height))
+}
+
+pub struct MazeState {
+    run: MazeRun,
+    size: (u32, u32),
+    last_time: Option<f32>,
+}
+
+pub fn set_maze_enabled(enabled: bool) {
+    station_state::current()
+        .maze_enabled
+        .store(enabled, Ordering::SeqCst);
+}
+
+pub fn is_maze_enabled() -> bool {
+    station_state::current().maze_enabled.load(Ordering::SeqCst)
+}
+
+/// Advances and draws the maze into the context's region when the scene is
+/// enabled, starting over with a grid to fit whenever the region changes size.
+/// A region that is a whole multiple of the size, as for a supersampled
+/// capture, gets the same maze drawn larger.
+pub fn update_and_draw_maze(ctx: &mut DrawCtx) {
+    if !is_maze_enabled() {
+        return;
+    }
+    let (width, height, time) = (ctx.width(), ctx.height(), ctx.time);
+    let station = station_state::current();
+    let mut slot = station_state::lock(&station.maze);
+    let state = slot.get_or_insert_with(|| MazeState {
+        run: MazeRun::new(2, 2, sim_rng().gen()),
+        size: (0, 0),
+        last_time: None,
+    });
+    if zoom_between(state.size, (width, height)).is_none() {
+        let (cols, rows) = grid_size(width, height);
+        state.run = MazeRun::new(cols, rows, state.run.seed);
+        state.size = (width, height);
+    }
+    let zoom = zoom_between(state.size, (width, height)).unwrap_or(1);
+    let dt = state
+        .last_time
+        .map_or(0.0, |last| (time - last).clamp(0.0, 0.1));
+    state.last_time = Some(time);
+
+    let layout = Layout::centered(state.run.maze(), width, height, zoom);
+    if let Some(path) = state.run.update(dt) {
+        // The exit pops when the solution arrives
+        let maze = state.run.maze();
+        let center = |cell: usize| {
+            let (x, y) = layout.origin(maze, cell);
+            let half = (layout.cell / 2) as f32;
+            (x as f32 + half, y as f32 + half)
+        };
+        let points = path.iter().rev().take(4).map(|&c| center(c)).collect();
+        fireworks::launch(&[Burst {
+            points,
+            color: rgba_to_color(PATH),
+        }]);
+    }
+    state.run.draw(ctx, &layout, time);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generate(cols: usize, rows: usize, seed: u64) -> Maze {
+        let mut generator = Generator::new(cols, rows, seed);
+        let mut steps = 0;
+        while generator.step() {
+            steps += 1;
+        }
+        // Every cell but the start is carved into once, and each is backed out of once
+        assert_eq!(steps, 2 * cols * rows - 1);
+        generator.maze
+    }
+
+    /// Distances from the start by plain flood fill, independent of `Solver`
+    fn distances(maze: &Maze) -> Vec<Option<usize>> {
+        let mut distance = vec![None; maze.cell_count()];
+        distance[0] = Some(0);
+        let mut queue = VecDeque::from([0]);
+        while let Some(cell) = queue.pop_front() {
+            for next in maze.passages(cell) {
+                if distance[next].is_none() {
+                    distance[next] = Some(distance[cell].unwrap() + 1);
+                    queue.push_back(next);
+                }
+            }
+        }
+        distance
+    }
+
+    #[test]
+    fn test_every_cell_is_reachable_and_the_maze_is_a_tree() {
+        for (cols, rows, seed) in [(2, 2, 1), (10, 7, 2), (66, 33, 3), (1, 20, 4)] {
+            let maze = generate(cols, rows, seed);
+            assert!(distances(&maze).iter().all(Option::is_some));
+            // A perfect maze has exactly one passage fewer than it has cells
+            let passages: usize = (0..maze.cell_count())
+                .map(|c| maze.passages(c).count())
+                .sum();
+            assert_eq!(passages / 2, cols * rows - 1);
+            // Passages are carved from both sides
+            for cell in 0..maze.cell_count() {
+                for next in maze.passages(cell) {
+                    assert!(maze.passages(next).any(|back| back == cell));
+                }
+            }
+        }
+        // The same seed carves the same maze
+        assert_eq!(generate(12, 9, 42), generate(12, 9, 42));
+        assert_ne!(generate(12, 9, 42), generate(12, 9, 43));
+    }
+
+    #[test]
+    fn test_solvers_find_a_valid_shortest_path() {
+        for seed in 0..5 {
+            let maze = generate(15, 11, seed);
+            let shortest = distances(&maze)[maze.cell_count() - 1].unwrap();
+            for kind in [SolverKind::Bfs, SolverKind::AStar] {
+                let mut solver = Solver::new(kind, &maze);
+                while solver.step(&maze) {}
+                let path = solver.path().unwrap();
+                assert_eq!(path[0], 0);
+                assert_eq!(*path.last().unwrap(), maze.cell_count() - 1);
+                assert!(path
+                    .windows(2)
+                    .all(|pair| maze.passages(pair[0]).any(|next| next == pair[1])));
+                // A tree has one route, but the step count still has to be optimal
+                assert_eq!(path.len() - 1, shortest, "{:?}", kind);
+            }
+        }
+
+        // The scene loops: a solution arrives, then a fresh maze with the next seed
+        let mut run = MazeRun::new(6, 4, 7);
+        let mut solutions = 0;
+        for _ in 0..(60 * 40) {
+            solutions += run.update(1.0 / 60.0).is_some() as usize;
+        }
+        assert!(solutions >= 2, "{}", solutions);
+        assert!(run.seed > 7);
+        // A paused clock doesn't advance anything
+        let before = run.pending;
+        run.update(0.0);
+        assert_eq!(run.pending, before);
+    }
+
+    #[test]
+    fn test_whole_multiple_of_the_size_draws_the_same_maze_larger() {
+        assert_eq!(zoom_between((800, 600), (800, 600)), Some(1));
+        assert_eq!(zoom_between((800, 600), (3200, 2400)), Some(4));
+        assert_eq!(zoom_between((800, 600), (1600, 1000)), None);
+        assert_eq!(zoom_between((800, 600), (400, 300)), None);
+        assert_eq!(zoom_between((0, 0), (800, 600)), None);
+
+        let (cols, rows) = grid_size(800, 600);
+        let maze = Maze::new(cols, rows);
+        let small = Layout::centered(&maze, 800, 600, 1);
+        let large = Layout::centered(&maze, 1600, 1200, 2);
+        assert_eq!((large.cell, large.wall), (2 * small.cell, 2 * small.wall));
+        assert_eq!((large.left, large.top), (2 * small.left, 2 * small.top));
+        // A frame too small for the grid pins it to the corner instead of wrapping
+        let tiny = Layout::centered(&maze, 10, 10, 1);
+        assert_eq!((tiny.left, tiny.top), (0, 0));
+    }
+}
@@ -1,2 +1,6 @@
+pub mod image_dataset;
+pub mod maze;
+pub mod shared_ring;
+pub mod sort_machine;
 pub mod sorter;
 pub mod sorter_manager;
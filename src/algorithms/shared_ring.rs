@@ -0,0 +1,295 @@
+//! One array shared by the four edge sorters, laid around the screen border
+//! clockwise from the top-left corner: the top edge holds indices 0-99, the
+//! right edge 100-199, the bottom 200-299, and the left 300-399. Each quarter
+//! is sorted by its own `SortVisualizer` through a `QuarterView`, and every
+//! quarter holds its own band of values, so the ring is sorted exactly when
+//! all four quarters are.
+
+use crate::algorithms::sorter::{InputPattern, SortAlgorithm, SortState, SortVisualizer};
+use crate::core::sim_rng::sim_rng;
+use rand::prelude::*;
+
+/// Elements in each quarter of the ring
+pub const RING_QUARTER: usize = 100;
+/// Elements in the whole ring
+pub const RING_SIZE: usize = RING_QUARTER * 4;
+/// Values each quarter's band spans; quarter q holds q * RING_BAND..(q + 1) * RING_BAND
+const RING_BAND: usize = 256 / 4;
+
+/// One quarter's window onto the ring, translating its sorter's indices
+/// to ring indices and back
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuarterView {
+    pub quarter: usize,
+    pub start: usize,
+    pub len: usize,
+}
+
+impl QuarterView {
+    /// Ring index of the quarter's `local` element
+    pub fn to_ring(&self, local: usize) -> usize {
+        debug_assert!(local < self.len);
+        self.start + local
+    }
+
+    /// The quarter's index of ring element `index`, or None when another
+    /// quarter owns it
+    pub fn to_local(&self, index: usize) -> Option<usize> {
+        (self.start..self.start + self.len)
+            .contains(&index)
+            .then(|| index - self.start)
+    }
+}
+
+/// The ring's elements; quarters are read and written only through their views
+#[derive(Debug, Clone, PartialEq)]
+pub struct SharedRingArray {
+    values: Vec<u8>,
+}
+
+impl SharedRingArray {
+    /// A ring arranged by `pattern` quarter by quarter, each in its own band
+    pub fn generate(pattern: InputPattern, rng: &mut impl Rng) -> Self {
+        let mut values = Vec::with_capacity(RING_SIZE);
+        for quarter in 0..4 {
+            let local = pattern.generate(RING_QUARTER, rng);
+            // Patterns differ in range (FewUnique reaches 255), so scale by the largest
+            let max = local.iter().copied().max().unwrap_or(1).max(1) as usize;
+            values.extend(
+                local
+                    .iter()
+                    .map(|&v| (quarter * RING_BAND + v as usize * (RING_BAND - 1) / max) as u8),
+            );
+        }
+        Self { values }
+    }
+
+    pub fn values(&self) -> &[u8] {
+        &self.values
+    }
+
+    pub fn view(&self, quarter: usize) -> QuarterView {
+        QuarterView {
+            quarter,
+            start: quarter * RING_QUARTER,
+            len: RING_QUARTER,
+        }
+    }
+
+    pub fn quarter(&self, view: QuarterView) -> &[u8] {
+        &self.values[view.start..view.start + view.len]
+    }
+
+    /// Copies a quarter's sorter array back into the ring. Only the
+    /// quarter's own indices are written.
+    pub fn write_quarter(&mut self, view: QuarterView, array: &[u8]) {
+        assert_eq!(array.len(), view.len, "quarter {} size", view.quarter);
+        for (local, &value) in array.iter().enumerate() {
+            self.values[view.to_ring(local)] = value;
+        }
+    }
+
+    /// Every element, across the corners too, is no larger than the next
+    pub fn is_sorted(&self) -> bool {
+        self.values.windows(2).all(|pair| pair[0] <= pair[1])
+    }
+}
+
+/// The ring and the four sorters working on its quarters
+pub struct RingSorters {
+    pub ring: SharedRingArray,
+    /// Top, right, bottom, and left quarters, in ring order
+    pub sorters: Vec<SortVisualizer>,
+    /// Every quarter finished; reset by `restart`
+    complete: bool,
+}
+
+impl RingSorters {
+    /// A ring arranged by `pattern`, each quarter sorted by its own algorithm
+    pub fn new(algorithms: [SortAlgorithm; 4], pattern: InputPattern) -> Self {
+        let mut ring = Self {
+            ring: SharedRingArray::generate(pattern, &mut sim_rng()),
+            sorters: algorithms
+                .into_iter()
+                .map(|algorithm| {
+                    let mut sorter = SortVisualizer::new_with_size(algorithm, RING_QUARTER);
+                    sorter.pattern = pattern;
+                    sorter
+                })
+                .collect(),
+            complete: false,
+        };
+        ring.load_quarters();
+        ring
+    }
+
+    /// Rearranges the whole ring by the sorters' pattern and starts every
+    /// quarter over on its part of it
+    pub fn restart(&mut self) {
+        let pattern = self.sorters[0].pattern;
+        self.ring = SharedRingArray::generate(pattern, &mut sim_rng());
+        self.load_quarters();
+        self.complete = false;
+    }
+
+    fn load_quarters(&mut self) {
+        for (quarter, sorter) in self.sorters.iter_mut().enumerate() {
+            let view = self.ring.view(quarter);
+            sorter.restart_with(self.ring.quarter(view).to_vec());
+        }
+    }
+
+    /// Steps every quarter still sorting and writes it back into the ring.
+    /// Returns the quarters that finished this update, and whether the whole
+    /// ring did; that is reported once per run.
+    pub fn update(&mut self) -> (Vec<usize>, bool) {
+        let mut finished = Vec::new();
+        for (quarter, sorter) in self.sorters.iter_mut().enumerate() {
+            let was_running = sorter.state == SortState::Running;
+            // Finished quarters hold still, only fading their tint, until the ring restarts
+            sorter.update();
+            if was_running && sorter.state == SortState::Completed {
+                finished.push(quarter);
+            }
+            let view = self.ring.view(quarter);
+            self.ring.write_quarter(view, &sorter.machine.array);
+        }
+        let complete = self.is_complete();
+        let ring_finished = complete && !self.complete;
+        self.complete = complete;
+        (finished, ring_finished)
+    }
+
+    /// Every quarter has finished sorting
+    pub fn is_complete(&self) -> bool {
+        self.sorters
+            .iter()
+            .all(|sorter| sorter.state == SortState::Completed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counts(values: &[u8]) -> [usize; 256] {
+        let mut counts = [0; 256];
+        for &v in values {
+            counts[v as usize] += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn test_quarter_views_translate_indices() {
+        let ring = SharedRingArray::generate(InputPattern::Random, &mut StdRng::seed_from_u64(1));
+        assert_eq!(ring.values().len(), RING_SIZE);
+        for quarter in 0..4 {
+            let view = ring.view(quarter);
+            assert_eq!(view.to_ring(0), quarter * RING_QUARTER);
+            assert_eq!(
+                view.to_ring(RING_QUARTER - 1),
+                (quarter + 1) * RING_QUARTER - 1
+            );
+            for local in 0..RING_QUARTER {
+                assert_eq!(view.to_local(view.to_ring(local)), Some(local));
+            }
+            // Neighbouring quarters' elements across the corners are not this quarter's
+            assert_eq!(
+                view.to_local((view.start + RING_SIZE - 1) % RING_SIZE),
+                None
+            );
+            assert_eq!(view.to_local(view.start + RING_QUARTER), None);
+            // Each quarter keeps to its own band
+            let band = quarter * RING_BAND..(quarter + 1) * RING_BAND;
+            assert!(ring
+                .quarter(view)
+                .iter()
+                .all(|&v| band.contains(&(v as usize))));
+        }
+    }
+
+    #[test]
+    fn test_writing_a_quarter_leaves_the_others_alone() {
+        let mut ring =
+            SharedRingArray::generate(InputPattern::Reversed, &mut StdRng::seed_from_u64(2));
+        let before = ring.clone();
+        let view = ring.view(1);
+        ring.write_quarter(view, &[7; RING_QUARTER]);
+        for (index, (&after, &was)) in ring.values().iter().zip(before.values()).enumerate() {
+            match view.to_local(index) {
+                Some(_) => assert_eq!(after, 7),
+                None => assert_eq!(after, was, "index {}", index),
+            }
+        }
+    }
+
+    #[test]
+    fn test_sorters_only_rearrange_their_own_quarter() {
+        let mut sorters = RingSorters::new(
+            [
+                SortAlgorithm::Shell,
+                SortAlgorithm::Quick,
+                SortAlgorithm::Insertion,
+                SortAlgorithm::Selection,
+            ],
+            InputPattern::Random,
+        );
+        let before: Vec<[usize; 256]> = (0..4)
+            .map(|q| counts(sorters.ring.quarter(sorters.ring.view(q))))
+            .collect();
+        for _ in 0..50 {
+            sorters.update();
+            for (quarter, counts_before) in before.iter().enumerate() {
+                let view = sorters.ring.view(quarter);
+                assert_eq!(&counts(sorters.ring.quarter(view)), counts_before);
+                assert_eq!(
+                    sorters.ring.quarter(view),
+                    &sorters.sorters[quarter].machine.array[..]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_ring_completes_once_when_every_quarter_is_sorted() {
+        let mut sorters = RingSorters::new(
+            [
+                SortAlgorithm::Shell,
+                SortAlgorithm::Quick,
+                SortAlgorithm::Insertion,
+                SortAlgorithm::Cocktail,
+            ],
+            InputPattern::Sawtooth,
+        );
+        let mut finished_quarters = Vec::new();
+        let mut completions = 0;
+        for _ in 0..100_000 {
+            let all_done_before = sorters.is_complete();
+            let (finished, ring_finished) = sorters.update();
+            finished_quarters.extend(finished);
+            if ring_finished {
+                completions += 1;
+                assert!(!all_done_before);
+                assert!(sorters.is_complete());
+                assert!(sorters.ring.is_sorted());
+            } else {
+                assert!(!sorters.is_complete() || all_done_before);
+            }
+            if sorters.is_complete() && completions == 1 && finished_quarters.len() == 4 {
+                // Held until restarted; further updates report nothing new
+                for _ in 0..10 {
+                    assert_eq!(sorters.update(), (Vec::new(), false));
+                }
+                break;
+            }
+        }
+        assert_eq!(completions, 1);
+        finished_quarters.sort();
+        assert_eq!(finished_quarters, [0, 1, 2, 3]);
+
+        sorters.restart();
+        assert!(!sorters.is_complete());
+        assert!(!sorters.ring.is_sorted());
+    }
+}
@@ -0,0 +1,437 @@
+//! The sorting algorithms as step-by-step state machines, free of drawing,
+//! pacing, and global statistics. `SortVisualizer` wraps one to draw it as
+//! bars; anything else can drive one directly.
+//!
+//! ```
+//! use stimstation::{SortAlgorithm, SortMachine};
+//!
+//! let mut machine = SortMachine::new(SortAlgorithm::Insertion, vec![3, 1, 2]);
+//! let first = machine.step();
+//! assert_eq!(first.swapped, Some((0, 1)));
+//! assert_eq!(machine.array, [1, 3, 2]);
+//!
+//! // Or run it out as an iterator; the last step reports `done`
+//! let rest: Vec<_> = machine.by_ref().collect();
+//! assert!(rest.last().unwrap().done);
+//! assert_eq!(machine.array, [1, 2, 3]);
+//! assert_eq!(machine.steps, 1 + rest.len());
+//! ```
+
+use crate::algorithms::sorter::SortAlgorithm;
+use crate::core::sim_rng::sim_rng;
+use rand::prelude::*;
+
+/// What one step did. A step may compare and swap many pairs (a whole
+/// bubble pass, say); the last of each is reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct StepResult {
+    pub compared: Option<(usize, usize)>,
+    pub swapped: Option<(usize, usize)>,
+    /// The array is sorted; further steps do nothing
+    pub done: bool,
+}
+
+/// One algorithm sorting one array, a step at a time
+#[derive(Debug, Clone, PartialEq)]
+pub struct SortMachine {
+    pub array: Vec<u8>,
+    pub algorithm: SortAlgorithm,
+    /// Per-element values moved along with the array, such as bar colors.
+    /// The algorithms never look at them.
+    pub colors: Option<Vec<[u8; 3]>>,
+    /// Steps taken, including the one that found the array sorted
+    pub steps: usize,
+    pub comparisons: usize,
+    pub accesses: usize,
+    // Algorithm state: loop indices, the Shell gap or Cocktail direction,
+    // and the Quick Sort ranges still to partition
+    pub(crate) i: usize,
+    pub(crate) j: usize,
+    pub(crate) pivot: usize,
+    pub(crate) stack: Vec<(usize, usize)>,
+    pub(crate) done: bool,
+    current: StepResult,
+}
+
+impl SortMachine {
+    /// Ready to sort `array` from the first step
+    pub fn new(algorithm: SortAlgorithm, array: Vec<u8>) -> Self {
+        let mut machine = Self {
+            array,
+            algorithm,
+            colors: None,
+            steps: 0,
+            comparisons: 0,
+            accesses: 0,
+            i: 0,
+            j: 0,
+            pivot: 0,
+            stack: Vec::new(),
+            done: false,
+            current: StepResult::default(),
+        };
+        let len = machine.array.len();
+        match machine.algorithm {
+            SortAlgorithm::Quick => machine.stack.push((0, len.saturating_sub(1))),
+            // Gaps start at half the array
+            SortAlgorithm::Shell => machine.pivot = len / 2,
+            // The first element alone is already sorted
+            SortAlgorithm::Insertion => machine.i = 1,
+            _ => {}
+        }
+        machine
+    }
+
+    /// Carries `colors` along with the elements they belong to
+    pub fn with_colors(mut self, colors: Vec<[u8; 3]>) -> Self {
+        self.colors = Some(colors);
+        self
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Advances the algorithm by one step
+    pub fn step(&mut self) -> StepResult {
+        if self.done {
+            return StepResult {
+                done: true,
+                ..StepResult::default()
+            };
+        }
+        self.current = StepResult::default();
+        match self.algorithm {
+            SortAlgorithm::Bogo => self.step_bogo(),
+            SortAlgorithm::Bubble => self.step_bubble(),
+            SortAlgorithm::Quick => self.step_quick(),
+            SortAlgorithm::Merge => self.step_merge(),
+            SortAlgorithm::Insertion => self.step_insertion(),
+            SortAlgorithm::Selection => self.step_selection(),
+            SortAlgorithm::Heap => self.step_heap(),
+            SortAlgorithm::Radix => self.step_radix(),
+            SortAlgorithm::Shell => self.step_shell(),
+            SortAlgorithm::Cocktail => self.step_cocktail(),
+        }
+        self.steps += 1;
+        self.current.done = self.done;
+        self.current
+    }
+
+    /// Fraction of neighbouring pairs already in order
+    pub fn sorted_fraction(&self) -> f32 {
+        let sorted = self
+            .array
+            .windows(2)
+            .filter(|pair| pair[0] <= pair[1])
+            .count();
+        sorted as f32 / (self.array.len() - 1) as f32
+    }
+
+    fn finish(&mut self) {
+        self.done = true;
+    }
+
+    /// Counts a comparison of two elements
+    fn compare(&mut self, a: usize, b: usize) {
+        self.comparisons += 1;
+        self.accesses += 2;
+        self.current.compared = Some((a, b));
+    }
+
+    /// Swaps two elements and their colors; every algorithm moves elements through here
+    fn swap_elements(&mut self, i: usize, j: usize) {
+        self.array.swap(i, j);
+        if let Some(colors) = self.colors.as_mut() {
+            colors.swap(i, j);
+        }
+        self.current.swapped = Some((i, j));
+    }
+
+    /// Bogo Sort - randomly shuffles until sorted
+    /// Extremely inefficient but amusing to watch
+    fn step_bogo(&mut self) {
+        // Check if array is already sorted
+        let mut is_sorted = true;
+        for i in 1..self.array.len() {
+            self.accesses += 2;
+            if self.array[i - 1] > self.array[i] {
+                is_sorted = false;
+                break;
+            }
+        }
+        if is_sorted {
+            self.finish();
+        } else {
+            // If not sorted, shuffle randomly and try again (Fisher-Yates, so colors follow)
+            let mut rng = sim_rng();
+            for i in (1..self.array.len()).rev() {
+                self.swap_elements(i, rng.gen_range(0..=i));
+            }
+            self.accesses += self.array.len() * 2;
+        }
+    }
+
+    /// Bubble Sort - one pass comparing adjacent elements and swapping if needed
+    /// Simple but inefficient O(n²) algorithm
+    fn step_bubble(&mut self) {
+        let n = self.array.len();
+        if self.i >= n - 1 {
+            self.finish();
+            return;
+        }
+
+        let mut swapped_in_pass = false;
+        // Bubble largest element to the end of unsorted portion
+        for j in 0..(n - 1 - self.i) {
+            self.compare(j, j + 1);
+            if self.array[j] > self.array[j + 1] {
+                self.swap_elements(j, j + 1);
+                self.accesses += 2;
+                swapped_in_pass = true;
+            }
+        }
+
+        self.i += 1;
+
+        // If no swaps occurred, array is sorted
+        if !swapped_in_pass {
+            self.finish();
+        }
+    }
+
+    /// Quick Sort - partitions one range per step
+    /// Efficient O(n log n) average case algorithm
+    fn step_quick(&mut self) {
+        // Pop next range to partition from stack
+        let Some((low, high)) = self.stack.pop() else {
+            self.finish();
+            return;
+        };
+        if low >= high {
+            return;
+        }
+        // Partition and get pivot position
+        let pivot = self.partition(low, high);
+        // Push sub-ranges onto stack for further partitioning
+        if pivot > 0 && pivot - 1 > low {
+            self.stack.push((low, pivot - 1));
+        }
+        if pivot + 1 < high {
+            self.stack.push((pivot + 1, high));
+        }
+    }
+
+    /// Partitioning helper for Quick Sort
+    /// Places all elements ≤ pivot on left, > pivot on right
+    /// Returns the final position of the pivot element
+    fn partition(&mut self, low: usize, high: usize) -> usize {
+        let pivot = self.array[high]; // Use last element as pivot
+        self.accesses += 1;
+        let mut i = low; // Index of smaller element
+
+        for j in low..high {
+            self.comparisons += 1;
+            self.accesses += 1;
+            self.current.compared = Some((j, high));
+            if self.array[j] <= pivot {
+                self.swap_elements(i, j);
+                self.accesses += 4;
+                i += 1;
+            }
+        }
+        // Place pivot in final position
+        self.swap_elements(i, high);
+        self.accesses += 4;
+        i
+    }
+
+    /// Merge Sort - currently delegates to bubble sort for simplicity
+    /// TODO: Implement proper merge sort with temporary arrays
+    fn step_merge(&mut self) {
+        self.step_bubble();
+    }
+
+    /// Insertion Sort - inserts one element into the sorted front per step
+    /// Efficient for small arrays or nearly sorted data
+    fn step_insertion(&mut self) {
+        let n = self.array.len();
+        if self.i >= n {
+            self.finish();
+            return;
+        }
+
+        // Insert current element into sorted portion
+        let mut j = self.i;
+        while j > 0 {
+            self.compare(j - 1, j);
+            if self.array[j - 1] > self.array[j] {
+                self.swap_elements(j - 1, j);
+                self.accesses += 2;
+                j -= 1;
+            } else {
+                break;
+            }
+        }
+        self.i += 1;
+    }
+
+    /// Selection Sort - moves the smallest remaining element into place per step
+    fn step_selection(&mut self) {
+        let n = self.array.len();
+        if self.i >= n - 1 {
+            self.finish();
+            return;
+        }
+
+        let mut min_idx = self.i;
+        for j in (self.i + 1)..n {
+            self.compare(j, min_idx);
+            if self.array[j] < self.array[min_idx] {
+                min_idx = j;
+            }
+        }
+
+        if min_idx != self.i {
+            self.swap_elements(self.i, min_idx);
+            self.accesses += 2;
+        }
+        self.i += 1;
+    }
+
+    fn step_heap(&mut self) {
+        // Simple heap sort implementation using bubble sort pattern for visualization
+        self.step_bubble();
+    }
+
+    fn step_radix(&mut self) {
+        // Simple radix sort implementation using bubble sort pattern for visualization
+        self.step_bubble();
+    }
+
+    /// Shell Sort - one gapped insertion pass per step, halving the gap
+    fn step_shell(&mut self) {
+        let n = self.array.len();
+        if self.pivot == 0 {
+            self.finish();
+            return;
+        }
+
+        // Gapped insertion sort, swapping the element down the gap
+        for i in self.pivot..n {
+            self.accesses += 1;
+            let mut j = i;
+            while j >= self.pivot {
+                self.current.compared = Some((j - self.pivot, j));
+                if self.array[j - self.pivot] <= self.array[j] {
+                    break;
+                }
+                self.swap_elements(j - self.pivot, j);
+                self.accesses += 2;
+                j -= self.pivot;
+            }
+            self.accesses += 1;
+        }
+
+        self.pivot /= 2;
+    }
+
+    /// Cocktail Sort - bidirectional bubble sort, one comparison per step
+    /// Alternates between forward and backward passes
+    fn step_cocktail(&mut self) {
+        let n = self.array.len();
+        // Done once the bounds meet; a later backward pass would start below index 0
+        if self.i >= n || self.j + 1 >= n {
+            self.finish();
+            return;
+        }
+
+        // Forward pass (pivot == 0) - bubble largest to right
+        if self.pivot == 0 {
+            if self.i < n - 1 - self.j {
+                self.compare(self.i, self.i + 1);
+                if self.array[self.i] > self.array[self.i + 1] {
+                    self.swap_elements(self.i, self.i + 1);
+                    self.accesses += 2;
+                }
+                self.i += 1;
+            } else {
+                // Switch to backward pass
+                self.pivot = 1;
+                self.i = n - 2 - self.j;
+            }
+        } else {
+            // Backward pass (pivot == 1) - bubble smallest to left
+            if self.i > self.j {
+                self.compare(self.i, self.i - 1);
+                if self.array[self.i] < self.array[self.i - 1] {
+                    self.swap_elements(self.i, self.i - 1);
+                    self.accesses += 2;
+                }
+                self.i -= 1;
+            } else {
+                // Switch back to forward pass, increment bounds
+                self.pivot = 0;
+                self.j += 1;
+                self.i = self.j;
+            }
+        }
+    }
+}
+
+/// Steps until the array is sorted. Bogo Sort on more than a handful of
+/// elements may never get there; bound it with `take`.
+impl Iterator for SortMachine {
+    type Item = StepResult;
+
+    fn next(&mut self) -> Option<StepResult> {
+        (!self.done).then(|| self.step())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_algorithm_sorts_and_reports_its_steps() {
+        let array: Vec<u8> = (0..60).map(|i| (i * 97 % 251) as u8).collect();
+        for algorithm in SortAlgorithm::ALL {
+            let size = if algorithm == SortAlgorithm::Bogo {
+                4
+            } else {
+                array.len()
+            };
+            let mut sorted = array[..size].to_vec();
+            sorted.sort();
+            let mut machine = SortMachine::new(algorithm.clone(), array[..size].to_vec());
+            let results: Vec<StepResult> = machine.by_ref().take(1_000_000).collect();
+            assert!(machine.is_done(), "{:?}", algorithm);
+            assert_eq!(machine.array, sorted, "{:?}", algorithm);
+            assert_eq!(machine.steps, results.len(), "{:?}", algorithm);
+            // Only the last step finishes, and stepping after that changes nothing
+            assert!(results.iter().rev().skip(1).all(|result| !result.done));
+            assert!(results.last().unwrap().done);
+            assert!(machine.step().done);
+            assert_eq!(machine.steps, results.len());
+            if algorithm != SortAlgorithm::Bogo {
+                assert!(results.iter().any(|result| result.compared.is_some()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_swaps_carry_colors_and_are_reported() {
+        let colors = vec![[30, 0, 0], [10, 0, 0], [20, 0, 0]];
+        let mut machine =
+            SortMachine::new(SortAlgorithm::Selection, vec![30, 10, 20]).with_colors(colors);
+        let first = machine.step();
+        assert_eq!(first.compared, Some((2, 1)));
+        assert_eq!(first.swapped, Some((0, 1)));
+        assert!(!first.done);
+        machine.by_ref().for_each(drop);
+        assert_eq!(machine.array, [10, 20, 30]);
+        let red: Vec<u8> = machine.colors.unwrap().iter().map(|c| c[0]).collect();
+        assert_eq!(red, machine.array);
+    }
+}
@@ -1,6 +1,12 @@
+use crate::algorithms::image_dataset::ImageRow;
+use crate::algorithms::sort_machine::SortMachine;
+use crate::core::sim_rng::sim_rng;
+use crate::core::snapshot::Snapshottable;
+use crate::core::types::{color_to_rgba, hsv_to_rgb, Color};
+use crate::graphics::draw_ctx::{DrawCtx, Region};
 use rand::prelude::*;
 use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, OnceLock};
 
 /// Default size for sorting arrays - controls the number of elements to sort
 pub const SORT_ARRAY_SIZE: usize = 200;
@@ -8,56 +14,66 @@ pub const SORT_ARRAY_SIZE: usize = 200;
 /// Global statistics tracker for algorithm completion counts
 /// Uses Arc<Mutex<>> for thread-safe access across the application
 /// Maps each sorting algorithm to the number of times it has completed successfully
-static mut ALGORITHM_STATS: Option<Arc<Mutex<HashMap<SortAlgorithm, u32>>>> = None;
+static ALGORITHM_STATS: OnceLock<Arc<Mutex<HashMap<SortAlgorithm, u32>>>> = OnceLock::new();
+
+/// Global tracker for runs that hit their step cap before finishing
+/// Kept separate from ALGORITHM_STATS so give-ups never count as completions
+static GIVE_UP_STATS: OnceLock<Arc<Mutex<HashMap<SortAlgorithm, u32>>>> = OnceLock::new();
 
 /// Initializes the global algorithm statistics tracker
 /// Creates a HashMap with all sorting algorithms initialized to 0 completions
-/// This should be called once at application startup
+/// This should be called once at application startup; later calls do nothing
 pub fn initialize_algorithm_stats() {
-    unsafe {
-        if ALGORITHM_STATS.is_none() {
-            let mut stats = HashMap::new();
-            // Initialize completion count for all algorithms to 0
-            stats.insert(SortAlgorithm::Bogo, 0);
-            stats.insert(SortAlgorithm::Bubble, 0);
-            stats.insert(SortAlgorithm::Quick, 0);
-            stats.insert(SortAlgorithm::Merge, 0);
-            stats.insert(SortAlgorithm::Insertion, 0);
-            stats.insert(SortAlgorithm::Selection, 0);
-            stats.insert(SortAlgorithm::Heap, 0);
-            stats.insert(SortAlgorithm::Radix, 0);
-            stats.insert(SortAlgorithm::Shell, 0);
-            stats.insert(SortAlgorithm::Cocktail, 0);
-            ALGORITHM_STATS = Some(Arc::new(Mutex::new(stats)));
-        }
-    }
+    ALGORITHM_STATS.get_or_init(|| {
+        let mut stats = HashMap::new();
+        // Initialize completion count for all algorithms to 0
+        for algorithm in SortAlgorithm::ALL {
+            stats.insert(algorithm, 0);
+        }
+        Arc::new(Mutex::new(stats))
+    });
+    GIVE_UP_STATS.get_or_init(|| Arc::new(Mutex::new(HashMap::new())));
 }
 
 /// Returns a clone of the global algorithm statistics for external access
 /// Used by other modules to read algorithm completion counts
 pub fn get_algorithm_stats() -> Option<Arc<Mutex<HashMap<SortAlgorithm, u32>>>> {
-    unsafe { ALGORITHM_STATS.clone() }
+    ALGORITHM_STATS.get().cloned()
+}
+
+/// Returns how many runs of the given algorithm gave up after hitting their step cap
+pub fn get_give_up_count(algorithm: &SortAlgorithm) -> u32 {
+    if let Some(stats) = GIVE_UP_STATS.get() {
+        if let Ok(stats_map) = stats.lock() {
+            return stats_map.get(algorithm).copied().unwrap_or(0);
+        }
+    }
+    0
+}
+
+/// Expected number of shuffles for Bogo Sort to sort n distinct elements (n!)
+/// Saturates instead of overflowing for large n
+pub fn expected_bogo_shuffles(n: usize) -> u64 {
+    (2..=n as u64).fold(1u64, |acc, k| acc.saturating_mul(k))
 }
 
 /// Finds and returns the algorithm with the highest completion count
 /// Returns None if statistics haven't been initialized
 /// Used for displaying leaderboard information
 pub fn get_leading_algorithm() -> Option<(SortAlgorithm, u32)> {
-    unsafe {
-        if let Some(stats) = &ALGORITHM_STATS {
-            if let Ok(stats_map) = stats.lock() {
-                let mut leader = (SortAlgorithm::Bubble, 0);
-                // Find algorithm with highest completion count
-                for (algorithm, count) in stats_map.iter() {
-                    if *count > leader.1 {
-                        leader = (algorithm.clone(), *count);
-                    }
+    if let Some(stats) = ALGORITHM_STATS.get() {
+        if let Ok(stats_map) = stats.lock() {
+            let mut leader = (SortAlgorithm::Bubble, 0);
+            // Find algorithm with highest completion count
+            for (algorithm, count) in stats_map.iter() {
+                if *count > leader.1 {
+                    leader = (algorithm.clone(), *count);
                 }
-                return Some(leader);
             }
+            return Some(leader);
         }
-        None
     }
+    None
 }
 
 /// Enumeration of all supported sorting algorithms
@@ -77,6 +93,19 @@ pub enum SortAlgorithm {
 }
 
 impl SortAlgorithm {
+    pub const ALL: [SortAlgorithm; 10] = [
+        SortAlgorithm::Bogo,
+        SortAlgorithm::Bubble,
+        SortAlgorithm::Quick,
+        SortAlgorithm::Merge,
+        SortAlgorithm::Insertion,
+        SortAlgorithm::Selection,
+        SortAlgorithm::Heap,
+        SortAlgorithm::Radix,
+        SortAlgorithm::Shell,
+        SortAlgorithm::Cocktail,
+    ];
+
     /// Returns the human-readable name of the sorting algorithm
     pub fn name(&self) -> &'static str {
         match self {
@@ -92,74 +121,232 @@ impl SortAlgorithm {
             SortAlgorithm::Cocktail => "Cocktail Sort",
         }
     }
+
+    /// Color identifying the algorithm in celebrations and highlights; each
+    /// algorithm gets its own hue
+    pub fn color(&self) -> Color {
+        let index = match self {
+            SortAlgorithm::Bogo => 0,
+            SortAlgorithm::Bubble => 1,
+            SortAlgorithm::Quick => 2,
+            SortAlgorithm::Merge => 3,
+            SortAlgorithm::Insertion => 4,
+            SortAlgorithm::Selection => 5,
+            SortAlgorithm::Heap => 6,
+            SortAlgorithm::Radix => 7,
+            SortAlgorithm::Shell => 8,
+            SortAlgorithm::Cocktail => 9,
+        };
+        hsv_to_rgb(index as f32 / 10.0, 0.8, 1.0)
+    }
 }
 
 /// Represents the current state of a sorting operation
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum SortState {
     Running,     // Algorithm is actively sorting
     Completed,   // Array is fully sorted
-    Restarting,  // About to shuffle and restart
+    Restarting, // About to regenerate the array and restart
+    GaveUp,      // Hit max_steps without finishing
 }
 
-/// Main structure that handles sorting visualization and algorithm execution
-/// Contains the array being sorted and all state needed for step-by-step execution
-pub struct SortVisualizer {
-    pub array: Vec<u8>,              // The array being sorted (values 0-255)
-    pub steps: usize,                // Number of algorithm steps taken
-    pub algorithm: SortAlgorithm,    // Which sorting algorithm to use
-    pub state: SortState,            // Current state of the sorting process
-    pub i: usize,                    // Primary index for algorithm state
-    pub j: usize,                    // Secondary index for some algorithms
-    pub pivot: usize,                // Pivot index for quicksort/shell sort gap size
-    pub stack: Vec<(usize, usize)>,  // Stack for recursive algorithms like quicksort
-    pub comparisons: usize,          // Count of element comparisons made
-    pub accesses: usize,             // Count of array accesses made
+/// How bar colors are chosen
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum SorterColorMode {
+    #[default]
+    Flat,      // Single blue for every bar
+    ValueHue,  // Hue follows the element value, so a sorted array is a smooth rainbow
+    Thermal,   // Value mapped black -> red -> yellow -> white
 }
 
-impl SortVisualizer {
-    /// Creates a new SortVisualizer with the default array size
-    /// Initializes array with values 1-255 (cycling) and shuffles randomly
-    pub fn new(algorithm: SortAlgorithm) -> Self {
-        let mut array = Vec::with_capacity(SORT_ARRAY_SIZE);
-        // Create array with values 1-255, cycling for larger arrays
-        for i in 1..=SORT_ARRAY_SIZE {
-            array.push((i % 255) as u8);
+impl SorterColorMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SorterColorMode::Flat => "Flat",
+            SorterColorMode::ValueHue => "Value Hue",
+            SorterColorMode::Thermal => "Thermal",
         }
-        // Shuffle the array to create random starting state
-        let mut rng = thread_rng();
-        array.shuffle(&mut rng);
+    }
 
-        let mut visualizer = Self {
-            array,
-            steps: 0,
-            algorithm: algorithm.clone(),
-            state: SortState::Running,
-            i: 0,
-            j: 0,
-            pivot: 0,
-            stack: Vec::new(),
-            comparisons: 0,
-            accesses: 0,
+    /// Cycles Flat -> ValueHue -> Thermal -> Flat
+    pub fn next(&self) -> Self {
+        match self {
+            SorterColorMode::Flat => SorterColorMode::ValueHue,
+            SorterColorMode::ValueHue => SorterColorMode::Thermal,
+            SorterColorMode::Thermal => SorterColorMode::Flat,
+        }
+    }
+}
+
+/// Swaps `InputPattern::NearlySorted` gets when cycled to
+pub const DEFAULT_NEARLY_SORTED_SWAPS: usize = 5;
+/// Distinct values `InputPattern::FewUnique` gets when cycled to
+pub const DEFAULT_FEW_UNIQUE_VALUES: usize = 5;
+/// Ascending runs in a sawtooth array
+const SAWTOOTH_TEETH: usize = 4;
+
+/// How a sorter's array is arranged before each run
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum InputPattern {
+    #[default]
+    Random, // Uniform shuffle
+    Reversed,            // Descending; quicksort's and insertion sort's bad case
+    NearlySorted(usize), // Sorted, then this many disjoint adjacent swaps
+    FewUnique(usize),    // Only this many distinct values, shuffled
+    Sawtooth,            // Several ascending runs back to back
+}
+
+impl InputPattern {
+    pub fn name(&self) -> &'static str {
+        match self {
+            InputPattern::Random => "Random",
+            InputPattern::Reversed => "Reversed",
+            InputPattern::NearlySorted(_) => "Nearly Sorted",
+            InputPattern::FewUnique(_) => "Few Unique",
+            InputPattern::Sawtooth => "Sawtooth",
+        }
+    }
+
+    /// Name plus parameter, as shown in the stats overlay
+    pub fn label(&self) -> String {
+        match self {
+            InputPattern::NearlySorted(swaps) => format!("{} ({} swaps)", self.name(), swaps),
+            InputPattern::FewUnique(values) => format!("{} ({} values)", self.name(), values),
+            _ => self.name().to_string(),
+        }
+    }
+
+    /// Settings form: `reversed`, `nearly_sorted:5`, ...
+    pub fn key(&self) -> String {
+        match self {
+            InputPattern::Random => "random".into(),
+            InputPattern::Reversed => "reversed".into(),
+            InputPattern::NearlySorted(swaps) => format!("nearly_sorted:{}", swaps),
+            InputPattern::FewUnique(values) => format!("few_unique:{}", values),
+            InputPattern::Sawtooth => "sawtooth".into(),
+        }
+    }
+
+    /// Parses `key`; the count after `:` is optional and defaults as when cycling
+    pub fn from_key(key: &str) -> Option<Self> {
+        let (name, count) = match key.split_once(':') {
+            Some((name, count)) => (name, Some(count.trim().parse().ok()?)),
+            None => (key, None),
         };
+        match name.trim() {
+            "random" => Some(InputPattern::Random),
+            "reversed" => Some(InputPattern::Reversed),
+            "nearly_sorted" => Some(InputPattern::NearlySorted(
+                count.unwrap_or(DEFAULT_NEARLY_SORTED_SWAPS),
+            )),
+            "few_unique" => Some(InputPattern::FewUnique(
+                count.unwrap_or(DEFAULT_FEW_UNIQUE_VALUES).max(1),
+            )),
+            "sawtooth" => Some(InputPattern::Sawtooth),
+            _ => None,
+        }
+    }
+
+    /// Cycles Random -> Reversed -> NearlySorted -> FewUnique -> Sawtooth -> Random
+    pub fn next(&self) -> Self {
+        match self {
+            InputPattern::Random => InputPattern::Reversed,
+            InputPattern::Reversed => InputPattern::NearlySorted(DEFAULT_NEARLY_SORTED_SWAPS),
+            InputPattern::NearlySorted(_) => InputPattern::FewUnique(DEFAULT_FEW_UNIQUE_VALUES),
+            InputPattern::FewUnique(_) => InputPattern::Sawtooth,
+            InputPattern::Sawtooth => InputPattern::Random,
+        }
+    }
 
-        // Initialize algorithm-specific state variables
-        match algorithm {
-            SortAlgorithm::Quick => {
-                // Push initial range onto stack for quicksort
-                visualizer.stack.push((0, visualizer.array.len() - 1));
+    /// A fresh array of `size` values (1-255, cycling) arranged by this pattern
+    pub fn generate(&self, size: usize, rng: &mut impl Rng) -> Vec<u8> {
+        let mut array: Vec<u8> = (1..=size).map(|i| (i % 255) as u8).collect();
+        array.sort_unstable();
+        match *self {
+            InputPattern::Random => array.shuffle(rng),
+            InputPattern::Reversed => array.reverse(),
+            InputPattern::NearlySorted(swaps) => {
+                // Disjoint pairs keep each swap to exactly one inversion
+                let pairs = size / 2;
+                for pair in rand::seq::index::sample(rng, pairs, swaps.min(pairs)) {
+                    array.swap(2 * pair, 2 * pair + 1);
+                }
             }
-            SortAlgorithm::Shell => {
-                // Start with gap size of half the array length
-                visualizer.pivot = visualizer.array.len() / 2;
+            InputPattern::FewUnique(values) => {
+                let values = values.clamp(1, 255);
+                let step = 254 / values.saturating_sub(1).max(1);
+                for (i, value) in array.iter_mut().enumerate() {
+                    *value = (1 + (i % values) * step) as u8;
+                }
+                array.shuffle(rng);
             }
-            SortAlgorithm::Insertion => {
-                visualizer.i = 1; // Start from second element
+            InputPattern::Sawtooth => {
+                // Every tooth takes every SAWTOOTH_TEETH-th value, so each spans the range
+                let sorted = array.clone();
+                array = (0..SAWTOOTH_TEETH)
+                    .flat_map(|tooth| sorted.iter().skip(tooth).step_by(SAWTOOTH_TEETH))
+                    .copied()
+                    .collect();
             }
-            _ => {} // Other algorithms use default initialization
         }
+        array
+    }
+}
+
+/// Highest hue used by ValueHue; stopping short of 1.0 keeps 0 and 255 from both being red
+const VALUE_HUE_RANGE: f32 = 300.0 / 360.0;
+/// Number of updates a state tint takes to fade out
+pub const STATE_TINT_FRAMES: u32 = 30;
+/// Strength of a state tint when it first appears
+const STATE_TINT_STRENGTH: f32 = 0.6;
+
+/// Maps an element value to its bar color for the given mode
+pub fn value_color(mode: SorterColorMode, value: u8) -> [u8; 4] {
+    let t = value as f32 / 255.0;
+    match mode {
+        SorterColorMode::Flat => [100, 150, 255, 255],
+        SorterColorMode::ValueHue => color_to_rgba(hsv_to_rgb(t * VALUE_HUE_RANGE, 1.0, 1.0)),
+        SorterColorMode::Thermal => {
+            // Each third of the range ramps up one channel: red, then green, then blue
+            let channel = |start: f32| ((t - start) * 3.0).clamp(0.0, 1.0);
+            [
+                (channel(0.0) * 255.0).round() as u8,
+                (channel(1.0 / 3.0) * 255.0).round() as u8,
+                (channel(2.0 / 3.0) * 255.0).round() as u8,
+                255,
+            ]
+        }
+    }
+}
 
-        visualizer
+/// Brief full-bar overlay color announcing a state change
+fn state_tint_color(state: &SortState) -> Option<[u8; 4]> {
+    match state {
+        SortState::Running => None,
+        SortState::Completed => Some([100, 255, 100, 255]),   // Green when complete
+        SortState::Restarting => Some([255, 100, 100, 255]),  // Red when restarting
+        SortState::GaveUp => Some([170, 80, 220, 255]),       // Purple after giving up
+    }
+}
+
+/// Draws a `SortMachine` as bars, paces it, and keeps score: restarts with a
+/// fresh array, gives up after a step cap, briefly tints the bars on state
+/// changes, and records completions in the global statistics
+pub struct SortVisualizer {
+    pub machine: SortMachine, // The array being sorted and the algorithm's progress
+    pub state: SortState,            // Current state of the sorting process
+    pub max_steps: Option<usize>,    // Give up after this many steps (None = never)
+    pub color_mode: SorterColorMode, // How bar colors are mapped from values
+    pub pattern: InputPattern,       // How the array is arranged on each restart
+    image_row: Option<ImageRow>,      // Photo row the array is reset to instead of the pattern
+    tint: Option<([u8; 4], u32)>,    // State tint color and updates left before it fades
+}
+
+impl SortVisualizer {
+    /// Creates a new SortVisualizer with the default array size
+    /// Initializes array with values 1-255 (cycling) and shuffles randomly
+    pub fn new(algorithm: SortAlgorithm) -> Self {
+        Self::new_with_size(algorithm, SORT_ARRAY_SIZE)
     }
 
     /// Creates a new SortVisualizer with a custom array size
@@ -171,349 +358,159 @@ impl SortVisualizer {
             array.push((i % 255) as u8);
         }
         // Shuffle the array to create random starting state
-        let mut rng = thread_rng();
+        let mut rng = sim_rng();
         array.shuffle(&mut rng);
 
-        let mut visualizer = Self {
-            array,
-            steps: 0,
-            algorithm: algorithm.clone(),
+        Self {
+            machine: SortMachine::new(algorithm, array),
             state: SortState::Running,
-            i: 0,
-            j: 0,
-            pivot: 0,
-            stack: Vec::new(),
-            comparisons: 0,
-            accesses: 0,
-        };
-
-        // Initialize algorithm-specific state variables
-        match algorithm {
-            SortAlgorithm::Quick => {
-                // Push initial range for quicksort partitioning
-                visualizer.stack.push((0, visualizer.array.len() - 1));
-            }
-            SortAlgorithm::Shell => {
-                // Start with gap size of half the array length
-                visualizer.pivot = visualizer.array.len() / 2;
-            }
-            SortAlgorithm::Insertion => {
-                visualizer.i = 1; // Start from second element
+            max_steps: None,
+            color_mode: SorterColorMode::default(),
+            pattern: InputPattern::Random,
+            image_row: None,
+            tint: None,
             }
-            _ => {} // Other algorithms use default initialization
-        }
-
-        visualizer
     }
 
-    /// Main update method - advances the sorting algorithm by one step
-    /// Called repeatedly to animate the sorting process
-    pub fn update(&mut self) {
-        // Don't update if sorting is already complete
-        if self.state == SortState::Completed {
-            return;
-        }
-        
-        // Handle restart state by reshuffling and reinitializing
-        if self.state == SortState::Restarting {
-            let mut rng = thread_rng();
-            self.array.shuffle(&mut rng);
-            self.state = SortState::Running;
-            
-            // Reset all tracking variables
-            self.steps = 0;
-            self.i = 0;
-            self.j = 0;
-            self.pivot = 0;
-            self.comparisons = 0;
-            self.accesses = 0;
-            self.stack.clear();
-
-            // Re-initialize algorithm-specific state
-            match self.algorithm {
-                SortAlgorithm::Quick => {
-                    self.stack.push((0, self.array.len() - 1));
-                }
-                SortAlgorithm::Shell => {
-                    self.pivot = self.array.len() / 2;
-                }
-                SortAlgorithm::Insertion => {
-                    self.i = 1; // Start from second element
-                }
-                _ => {} // Other algorithms use default initialization
-            }
-            return;
-        }
-        
-        // Dispatch to appropriate algorithm update method
-        match self.algorithm {
-            SortAlgorithm::Bogo => self.update_bogo(),
-            SortAlgorithm::Bubble => self.update_bubble(),
-            SortAlgorithm::Quick => self.update_quick(),
-            SortAlgorithm::Merge => self.update_merge(),
-            SortAlgorithm::Insertion => self.update_insertion(),
-            SortAlgorithm::Selection => self.update_selection(),
-            SortAlgorithm::Heap => self.update_heap(),
-            SortAlgorithm::Radix => self.update_radix(),
-            SortAlgorithm::Shell => self.update_shell(),
-            SortAlgorithm::Cocktail => self.update_cocktail(),
-        }
-        self.steps += 1;
-    }
-
-    /// Bogo Sort implementation - randomly shuffles until sorted
-    /// Extremely inefficient but amusing to watch
-    fn update_bogo(&mut self) {
-        // Check if array is already sorted
-        let mut is_sorted = true;
-        for i in 1..self.array.len() {
-            self.accesses += 2;
-            if self.array[i - 1] > self.array[i] {
-                is_sorted = false;
-                break;
-            }
-        }
-        if is_sorted {
-            self.state = SortState::Completed;
-            self.record_completion();
-        } else {
-            // If not sorted, shuffle randomly and try again
-            let mut rng = thread_rng();
-            self.array.shuffle(&mut rng);
-            self.accesses += self.array.len() * 2;
-        }
+    /// Caps the number of steps a run may take before it gives up
+    /// Used for Bogo Sort, which may otherwise never finish
+    pub fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = Some(max_steps);
+        self
     }
 
-    /// Bubble Sort implementation - compares adjacent elements and swaps if needed
-    /// Simple but inefficient O(n²) algorithm
-    fn update_bubble(&mut self) {
-        let n = self.array.len();
-        if self.i >= n - 1 {
-            self.state = SortState::Completed;
-            self.record_completion();
-            return;
-        }
-
-        let mut swapped_in_pass = false;
-        // Bubble largest element to the end of unsorted portion
-        for j in 0..(n - 1 - self.i) {
-            self.comparisons += 1;
-            self.accesses += 2;
-            if self.array[j] > self.array[j + 1] {
-                self.array.swap(j, j + 1);
-                self.accesses += 2;
-                swapped_in_pass = true;
-            }
-        }
-
-        self.i += 1;
+    /// Arranges the array by `pattern` now and on every restart
+    pub fn with_pattern(mut self, pattern: InputPattern) -> Self {
+        self.pattern = pattern;
+        let array = pattern.generate(self.machine.array.len(), &mut sim_rng());
+        self.machine = SortMachine::new(self.machine.algorithm.clone(), array);
+        self
+    }
 
-        // If no swaps occurred, array is sorted
-        if !swapped_in_pass {
-            self.state = SortState::Completed;
-            self.record_completion();
-        }
+    /// Sorts the brightness of a photo row instead of a pattern, drawing
+    /// each bar in its pixel's color. None goes back to the pattern.
+    /// Takes effect on the next update through a restart.
+    pub fn set_image_row(&mut self, row: Option<ImageRow>) {
+        self.image_row = row;
+        self.restart();
     }
 
-    /// Quick Sort implementation - uses divide-and-conquer with partitioning
-    /// Efficient O(n log n) average case algorithm
-    fn update_quick(&mut self) {
-        if self.stack.is_empty() {
-            self.state = SortState::Completed;
-            self.record_completion();
-            return;
-        }
-        // Pop next range to partition from stack
-        let (low, high) = self.stack.pop().unwrap();
-        if low >= high {
-            return;
-        }
-        // Partition and get pivot position
-        let pivot = self.partition(low, high);
-        // Push sub-ranges onto stack for further partitioning
-        if pivot > 0 && pivot - 1 > low {
-            self.stack.push((low, pivot - 1));
-        }
-        if pivot + 1 < high {
-            self.stack.push((pivot + 1, high));
-        }
+    pub fn image_row(&self) -> Option<&ImageRow> {
+        self.image_row.as_ref()
     }
 
-    /// Partitioning helper for Quick Sort
-    /// Places all elements ≤ pivot on left, > pivot on right
-    /// Returns the final position of the pivot element
-    fn partition(&mut self, low: usize, high: usize) -> usize {
-        let pivot = self.array[high]; // Use last element as pivot
-        self.accesses += 1;
-        let mut i = low; // Index of smaller element
-        
-        for j in low..high {
-            self.comparisons += 1;
-            self.accesses += 1;
-            if self.array[j] <= pivot {
-                self.array.swap(i, j);
-                self.accesses += 4;
-                i += 1;
+    /// Main update method - advances the sorting algorithm by one step
+    /// Called repeatedly to animate the sorting process
+    pub fn update(&mut self) {
+        // Fade any state tint one step per update, even while finished
+        if let Some((_, frames)) = self.tint.as_mut() {
+            *frames = frames.saturating_sub(1);
+            if *frames == 0 {
+                self.tint = None;
             }
         }
-        // Place pivot in final position
-        self.array.swap(i, high);
-        self.accesses += 4;
-        i
-    }
 
-    /// Merge Sort implementation - currently delegates to bubble sort for simplicity
-    /// TODO: Implement proper merge sort with temporary arrays
-    fn update_merge(&mut self) {
-        // Simple merge sort implementation using bubble sort pattern for visualization
-        self.update_bubble();
-    }
-
-    /// Insertion Sort implementation - builds sorted array one element at a time
-    /// Efficient for small arrays or nearly sorted data
-    fn update_insertion(&mut self) {
-        let n = self.array.len();
-        if self.i >= n {
-            self.state = SortState::Completed;
-            self.record_completion();
+        // Don't update if sorting is already complete or has given up
+        if self.state == SortState::Completed || self.state == SortState::GaveUp {
             return;
         }
-
-        // Insert current element into sorted portion
-        let mut j = self.i;
-        while j > 0 {
-            self.comparisons += 1;
-            self.accesses += 2;
-            if self.array[j - 1] > self.array[j] {
-                self.array.swap(j - 1, j);
-                self.accesses += 2;
-                j -= 1;
-            } else {
-                break;
+        
+        // Handle restart state by regenerating the array and starting over
+        if self.state == SortState::Restarting {
+            let algorithm = self.machine.algorithm.clone();
+            self.machine = match &self.image_row {
+                Some(row) => {
+                    SortMachine::new(algorithm, row.values.clone()).with_colors(row.colors.clone())
+                }
+                None => {
+                    let len = self.machine.array.len();
+                    SortMachine::new(algorithm, self.pattern.generate(len, &mut sim_rng()))
             }
+            };
+            self.state = SortState::Running;
+            return;
         }
-        self.i += 1;
-    }
+        
+        let previous_state = self.state.clone();
 
-    fn update_selection(&mut self) {
-        let n = self.array.len();
-        if self.i >= n - 1 {
+        if self.machine.step().done {
             self.state = SortState::Completed;
             self.record_completion();
-            return;
         }
 
-        let mut min_idx = self.i;
-        for j in (self.i + 1)..n {
-            self.comparisons += 1;
-            self.accesses += 2;
-            if self.array[j] < self.array[min_idx] {
-                min_idx = j;
+        // Give up if the step cap was reached without finishing
+        if let Some(max_steps) = self.max_steps {
+            if self.state == SortState::Running && self.machine.steps >= max_steps {
+                self.state = SortState::GaveUp;
+                self.record_give_up();
             }
         }
 
-        if min_idx != self.i {
-            self.array.swap(self.i, min_idx);
-            self.accesses += 2;
+        if self.state != previous_state {
+            self.start_tint();
         }
-        self.i += 1;
     }
 
-    fn update_heap(&mut self) {
-        // Simple heap sort implementation using bubble sort pattern for visualization
-        self.update_bubble();
+    /// Starts the brief tint for the current state, if it has one
+    fn start_tint(&mut self) {
+        self.tint = state_tint_color(&self.state).map(|color| (color, STATE_TINT_FRAMES));
     }
 
-    fn update_radix(&mut self) {
-        // Simple radix sort implementation using bubble sort pattern for visualization
-        self.update_bubble();
+    /// Color of a bar holding `value`: the mode's mapping, blended toward
+    /// the state tint while one is fading out
+    pub fn bar_color(&self, value: u8) -> [u8; 4] {
+        self.tinted(value_color(self.color_mode, value))
     }
 
-    fn update_shell(&mut self) {
-        let n = self.array.len();
-        if self.pivot == 0 {
-            self.state = SortState::Completed;
-            self.record_completion();
-            return;
-        }
-
-        // Gapped insertion sort
-        for i in self.pivot..n {
-            let temp = self.array[i];
-            self.accesses += 1;
-            let mut j = i;
-            while j >= self.pivot && self.array[j - self.pivot] > temp {
-                self.array[j] = self.array[j - self.pivot];
-                self.accesses += 2;
-                j -= self.pivot;
-            }
-            self.array[j] = temp;
-            self.accesses += 1;
+    /// Color of the bar at `index`: its own color if the array carries
+    /// colors, otherwise the value mapping, tinted the same way
+    pub fn element_color(&self, index: usize) -> [u8; 4] {
+        match self
+            .machine
+            .colors
+            .as_ref()
+            .and_then(|colors| colors.get(index))
+        {
+            Some(&[r, g, b]) => self.tinted([r, g, b, 255]),
+            None => self.bar_color(self.machine.array[index]),
         }
-
-        self.pivot /= 2;
     }
 
-    /// Cocktail Sort implementation - bidirectional bubble sort
-    /// Alternates between forward and backward passes
-    fn update_cocktail(&mut self) {
-        let n = self.array.len();
-        if self.i >= n || self.j >= n { 
-            self.state = SortState::Completed;
-            self.record_completion();
-            return;
-        }
-
-        // Forward pass (pivot == 0) - bubble largest to right
-        if self.pivot == 0 {
-            if self.i < n - 1 - self.j {
-                self.comparisons += 1;
-                self.accesses += 2;
-                if self.array[self.i] > self.array[self.i + 1] {
-                    self.array.swap(self.i, self.i + 1);
-                    self.accesses += 2;
-                }
-                self.i += 1;
-            } else {
-                // Switch to backward pass
-                self.pivot = 1;
-                self.i = n - 2 - self.j;
-            }
-        } else {
-            // Backward pass (pivot == 1) - bubble smallest to left
-            if self.i > self.j {
-                self.comparisons += 1;
-                self.accesses += 2;
-                if self.array[self.i] < self.array[self.i - 1] {
-                    self.array.swap(self.i, self.i - 1);
-                    self.accesses += 2;
-                }
-                self.i -= 1;
-            } else {
-                // Switch back to forward pass, increment bounds
-                self.pivot = 0;
-                self.j += 1;
-                self.i = self.j;
+    fn tinted(&self, base: [u8; 4]) -> [u8; 4] {
+        match self.tint {
+            Some((tint, frames)) => {
+                let strength = STATE_TINT_STRENGTH * frames as f32 / STATE_TINT_FRAMES as f32;
+                let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * strength).round() as u8;
+                [
+                    mix(base[0], tint[0]),
+                    mix(base[1], tint[1]),
+                    mix(base[2], tint[2]),
+                    255,
+                ]
             }
+            None => base,
         }
     }
 
     /// Calculates what percentage of the array is currently in sorted order
     /// Used for progress tracking and visualization
     pub fn get_sorted_percent(&self) -> f32 {
-        let mut sorted_count = 0;
-        for i in 1..self.array.len() {
-            if self.array[i - 1] <= self.array[i] {
-                sorted_count += 1;
-            }
-        }
-        sorted_count as f32 / (self.array.len() - 1) as f32
+        self.machine.sorted_fraction()
     }
 
     /// Triggers a restart of the sorting process
     /// Sets state to Restarting, which will be handled in next update() call
     pub fn restart(&mut self) {
         self.state = SortState::Restarting;
+        self.start_tint();
+    }
+
+    /// Starts over on `array` at once, instead of a fresh array from the
+    /// pattern on the next update
+    pub fn restart_with(&mut self, array: Vec<u8>) {
+        self.machine = SortMachine::new(self.machine.algorithm.clone(), array);
+        self.state = SortState::Running;
+        self.tint = state_tint_color(&SortState::Restarting).map(|c| (c, STATE_TINT_FRAMES));
     }
 
     /// Draws the sorting visualization with default orientation (no flipping)
@@ -544,7 +541,7 @@ impl SortVisualizer {
     }
 
     /// Draws the sorting visualization with configurable orientation
-    /// Can flip horizontally or vertically to accommodate different screen edges
+    /// Adapter over `draw_into` for callers still passing raw frame parameters
     /// horizontal: true for horizontal bars, false for vertical
     /// flip_horizontal: reverses left/right bar growth direction
     /// flip_vertical: reverses up/down bar growth direction
@@ -561,62 +558,71 @@ impl SortVisualizer {
         flip_horizontal: bool,
         flip_vertical: bool,
     ) {
-        let len = self.array.len();
-        // Calculate bar width based on orientation
-        let bar_width = if horizontal {
-            width / len
+        let rows = (frame.len() / 4 / buffer_width.max(1) as usize) as u32;
+        let whole = Region::new(x_offset, 0, buffer_width.saturating_sub(x_offset as u32), rows);
+        let mut ctx = DrawCtx::new(frame, whole, buffer_width, 0.0);
+        let mut ctx = ctx.sub_region(Region::new(x, y, width as u32, height as u32));
+        self.draw_into(&mut ctx, horizontal, flip_horizontal, flip_vertical);
+    }
+
+    /// Draws the bars filling the context's region
+    /// Can flip horizontally or vertically to accommodate different screen edges
+    pub fn draw_into(
+        &self,
+        ctx: &mut DrawCtx,
+        horizontal: bool,
+        flip_horizontal: bool,
+        flip_vertical: bool,
+    ) {
+        let (width, height) = (ctx.width() as usize, ctx.height() as usize);
+        let len = self.machine.array.len();
+        let (extent, max_height) = if horizontal {
+            (width, height)
         } else {
-            height / len
+            (height, width)
         };
-        let max_height = if horizontal { height } else { width };
 
         // Draw each array element as a colored bar
-        for (i, &value) in self.array.iter().enumerate() {
+        for (i, &value) in self.machine.array.iter().enumerate() {
             // Scale bar height based on element value (0-255 -> 0-max_height)
             let bar_height = (value as f32 / 256.0 * max_height as f32) as usize;
-            
-            // Color based on current sorting state
-            let color = match self.state {
-                SortState::Running => [100, 150, 255, 255],     // Blue while sorting
-                SortState::Completed => [100, 255, 100, 255],   // Green when complete
-                SortState::Restarting => [255, 100, 100, 255],  // Red when restarting
-            };
+
+            // Color from the value mapping or the element's own, tinted briefly after state changes
+            let color = self.element_color(i);
+            // Bars share the length out evenly; with more bars than pixels
+            // neighbours overlap rather than vanishing
+            let bar_start = i * extent / len;
+            let bar_width = ((i + 1) * extent / len).max(bar_start + 1) - bar_start;
 
             if horizontal {
                 // Horizontal bars (for top/bottom screen edges)
-                let bar_x = x + i * bar_width;
+                let bar_x = bar_start;
                 let bar_y = if flip_vertical {
-                    y // Grow downward from top edge
+                    0 // Grow downward from top edge
                 } else {
-                    y + height - bar_height // Grow upward from bottom edge
+                    height - bar_height // Grow upward from bottom edge
                 };
-                draw_rectangle(
-                    frame,
-                    bar_x,
-                    bar_y,
-                    bar_width,
-                    bar_height,
+                ctx.fill_rect(
+                    bar_x as i32,
+                    bar_y as i32,
+                    bar_width as u32,
+                    bar_height as u32,
                     color,
-                    x_offset,
-                    buffer_width,
                 );
             } else {
                 // Vertical bars (for left/right screen edges)
                 let bar_x = if flip_horizontal {
-                    x // Grow rightward from left edge
+                    0 // Grow rightward from left edge
                 } else {
-                    x + width - bar_height // Grow leftward from right edge
+                    width - bar_height // Grow leftward from right edge
                 };
-                let bar_y = y + i * bar_width;
-                draw_rectangle(
-                    frame,
-                    bar_x,
-                    bar_y,
-                    bar_height,
-                    bar_width,
+                let bar_y = bar_start;
+                ctx.fill_rect(
+                    bar_x as i32,
+                    bar_y as i32,
+                    bar_height as u32,
+                    bar_width as u32,
                     color,
-                    x_offset,
-                    buffer_width,
                 );
             }
         }
@@ -625,53 +631,370 @@ impl SortVisualizer {
     /// Records completion of this algorithm in global statistics
     /// Increments the completion count for performance tracking
     fn record_completion(&self) {
-        unsafe {
-            if let Some(stats) = &ALGORITHM_STATS {
-                if let Ok(mut stats_map) = stats.lock() {
-                    if let Some(count) = stats_map.get_mut(&self.algorithm) {
-                        *count += 1;
-                    }
+        if let Some(stats) = ALGORITHM_STATS.get() {
+            if let Ok(mut stats_map) = stats.lock() {
+                if let Some(count) = stats_map.get_mut(&self.machine.algorithm) {
+                    *count += 1;
                 }
             }
         }
     }
+
+    /// Records a run that hit its step cap in the give-up statistics
+    fn record_give_up(&self) {
+        if let Some(stats) = GIVE_UP_STATS.get() {
+            if let Ok(mut stats_map) = stats.lock() {
+                *stats_map.entry(self.machine.algorithm.clone()).or_insert(0) += 1;
+            }
+        }
+    }
+}
+
+/// Captured sorter progress: the array plus every index the step functions rely on
+#[derive(Debug, Clone, PartialEq)]
+pub struct SorterSnapshot {
+    pub array: Vec<u8>,
+    pub steps: usize,
+    pub algorithm: SortAlgorithm,
+    pub state: SortState,
+    pub i: usize,
+    pub j: usize,
+    pub pivot: usize,
+    pub stack: Vec<(usize, usize)>,
+    pub comparisons: usize,
+    pub accesses: usize,
+    pub colors: Option<Vec<[u8; 3]>>,
+    /// State tint still fading, with the updates it has left
+    pub tint: Option<([u8; 4], u32)>,
+}
+
+impl Snapshottable for SortVisualizer {
+    type Snapshot = SorterSnapshot;
+
+    fn snapshot(&self) -> SorterSnapshot {
+        let machine = &self.machine;
+        SorterSnapshot {
+            array: machine.array.clone(),
+            steps: machine.steps,
+            algorithm: machine.algorithm.clone(),
+            state: self.state.clone(),
+            i: machine.i,
+            j: machine.j,
+            pivot: machine.pivot,
+            stack: machine.stack.clone(),
+            comparisons: machine.comparisons,
+            accesses: machine.accesses,
+            colors: machine.colors.clone(),
+            tint: self.tint,
+        }
+    }
+
+    fn restore(&mut self, snapshot: &SorterSnapshot) {
+        let machine = &mut self.machine;
+        machine.array = snapshot.array.clone();
+        machine.steps = snapshot.steps;
+        machine.algorithm = snapshot.algorithm.clone();
+        machine.i = snapshot.i;
+        machine.j = snapshot.j;
+        machine.pivot = snapshot.pivot;
+        machine.stack = snapshot.stack.clone();
+        machine.comparisons = snapshot.comparisons;
+        machine.accesses = snapshot.accesses;
+        machine.colors = snapshot.colors.clone();
+        machine.done = snapshot.state == SortState::Completed;
+        self.state = snapshot.state.clone();
+        self.tint = snapshot.tint;
+    }
 }
 
-/// Helper function to draw a filled rectangle on the frame buffer
-/// Used to render individual bars in the sorting visualization
-/// 
-/// Parameters:
-/// - frame: The pixel buffer to draw into (RGBA format)
-/// - x, y: Top-left corner coordinates of the rectangle
-/// - width, height: Dimensions of the rectangle
-/// - color: RGBA color values [R, G, B, A] (0-255 each)
-/// - x_offset: Horizontal offset for the drawing area
-/// - buffer_width: Width of the entire frame buffer
-fn draw_rectangle(
-    frame: &mut [u8],
-    x: usize,
-    y: usize,
-    width: usize,
-    height: usize,
-    color: [u8; 4],
-    x_offset: usize,
-    buffer_width: u32,
-) {
-    // Fill rectangle pixel by pixel
-    for dy in 0..height {
-        for dx in 0..width {
-            let pixel_x = x + dx;
-            let pixel_y = y + dy;
-            // Calculate index in RGBA buffer (4 bytes per pixel)
-            let idx = 4 * ((pixel_y * buffer_width as usize) + pixel_x + x_offset);
-
-            // Bounds check to prevent buffer overflow
-            if idx + 3 < frame.len() {
-                frame[idx] = color[0];     // Red
-                frame[idx + 1] = color[1]; // Green  
-                frame[idx + 2] = color[2]; // Blue
-                frame[idx + 3] = color[3]; // Alpha
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inversions(array: &[u8]) -> usize {
+        (0..array.len())
+            .map(|i| array[i + 1..].iter().filter(|&&b| b < array[i]).count())
+            .sum()
+    }
+
+    #[test]
+    fn test_expected_bogo_shuffles() {
+        assert_eq!(expected_bogo_shuffles(0), 1);
+        assert_eq!(expected_bogo_shuffles(7), 5040);
+        assert_eq!(expected_bogo_shuffles(100), u64::MAX);
+    }
+
+    #[test]
+    fn test_bogo_gives_up_at_cap() {
+        // 50 elements will not sort by chance within 3 shuffles
+        let mut sorter = SortVisualizer::new_with_size(SortAlgorithm::Bogo, 50).with_max_steps(3);
+        sorter.update();
+        sorter.update();
+        assert_eq!(sorter.state, SortState::Running);
+        sorter.update();
+        assert_eq!(sorter.state, SortState::GaveUp);
+        assert_eq!(sorter.machine.steps, 3);
+
+        // Further updates do nothing until restarted
+        sorter.update();
+        assert_eq!(sorter.machine.steps, 3);
+        sorter.restart();
+        sorter.update();
+        assert_eq!(sorter.state, SortState::Running);
+        assert_eq!(sorter.machine.steps, 0);
+    }
+
+    #[test]
+    fn test_give_up_recorded_separately_from_completions() {
+        initialize_algorithm_stats();
+        let completions_before = get_algorithm_stats()
+            .and_then(|stats| stats.lock().ok().map(|m| m[&SortAlgorithm::Bubble]))
+            .unwrap();
+        let give_ups_before = get_give_up_count(&SortAlgorithm::Bubble);
+
+        // Bubble is used here so concurrently running Bogo tests cannot touch these counters
+        let mut sorter = SortVisualizer::new_with_size(SortAlgorithm::Bubble, 50).with_max_steps(1);
+        sorter.update();
+
+        assert_eq!(sorter.state, SortState::GaveUp);
+        assert!(get_give_up_count(&SortAlgorithm::Bubble) > give_ups_before);
+        let completions_after = get_algorithm_stats()
+            .and_then(|stats| stats.lock().ok().map(|m| m[&SortAlgorithm::Bubble]))
+            .unwrap();
+        assert_eq!(completions_after, completions_before);
+    }
+
+    #[test]
+    fn test_value_color_endpoints() {
+        assert_eq!(value_color(SorterColorMode::ValueHue, 0), [255, 0, 0, 255]);
+        assert_eq!(value_color(SorterColorMode::ValueHue, 255), [255, 0, 255, 255]);
+        assert_eq!(value_color(SorterColorMode::Thermal, 0), [0, 0, 0, 255]);
+        assert_eq!(value_color(SorterColorMode::Thermal, 255), [255, 255, 255, 255]);
+        assert_eq!(value_color(SorterColorMode::Flat, 0), value_color(SorterColorMode::Flat, 255));
+    }
+
+    #[test]
+    fn test_completion_tint_fades_back_to_gradient() {
+        let mut sorter = SortVisualizer::new_with_size(SortAlgorithm::Bogo, 4);
+        sorter.color_mode = SorterColorMode::ValueHue;
+        sorter.machine.array = vec![10, 80, 160, 240];
+        sorter.update();
+        assert_eq!(sorter.state, SortState::Completed);
+        assert_ne!(sorter.bar_color(10), value_color(SorterColorMode::ValueHue, 10));
+
+        for _ in 0..STATE_TINT_FRAMES {
+            sorter.update();
+        }
+        assert_eq!(sorter.state, SortState::Completed);
+        assert_eq!(sorter.bar_color(10), value_color(SorterColorMode::ValueHue, 10));
+    }
+
+    #[test]
+    fn test_small_bogo_completes() {
+        let mut sorter = SortVisualizer::new_with_size(SortAlgorithm::Bogo, 3).with_max_steps(10_000);
+        while sorter.state == SortState::Running {
+            sorter.update();
+        }
+        assert_eq!(sorter.state, SortState::Completed);
+    }
+
+    #[test]
+    fn test_bar_frames_match_the_recorded_runs() {
+        // Hashes of 400 frames of each run, recorded before the algorithms
+        // moved into SortMachine; Bogo is left out as it shuffles at random
+        let expected = [
+            (SortAlgorithm::Bubble, 0x69900d679fcac36b, 37, 777, 2292),
+            (SortAlgorithm::Quick, 0x525007a3a9bb3c6f, 28, 219, 662),
+            (SortAlgorithm::Merge, 0x69900d679fcac36b, 37, 777, 2292),
+            (SortAlgorithm::Insertion, 0xf006ac1c6186e47f, 40, 408, 1554),
+            (SortAlgorithm::Selection, 0x96cf223390f4d53f, 40, 780, 1630),
+            (SortAlgorithm::Heap, 0x69900d679fcac36b, 37, 777, 2292),
+            (SortAlgorithm::Radix, 0x69900d679fcac36b, 37, 777, 2292),
+            (SortAlgorithm::Shell, 0x05d84f57c4bc7d17, 6, 0, 574),
+            (SortAlgorithm::Cocktail, 0x98bec077e322e1e5, 400, 389, 1366),
+        ];
+        for (algorithm, hash, steps, comparisons, accesses) in expected {
+            let array = (0..40).map(|i| (i * 97 % 251) as u8).collect();
+            let mut sorter = SortVisualizer::new_with_size(algorithm.clone(), 40);
+            sorter.machine = SortMachine::new(algorithm.clone(), array);
+            let mut frames: u64 = 0xcbf29ce484222325;
+            let mut frame = vec![0u8; 40 * 30 * 4];
+            for _ in 0..400 {
+                sorter.update();
+                frame.fill(0);
+                let mut ctx = DrawCtx::new(&mut frame, Region::new(0, 0, 40, 30), 40, 0.0);
+                sorter.draw_into(&mut ctx, true, false, false);
+                // FNV-1a over every frame in turn
+                for &byte in &frame {
+                    frames = (frames ^ byte as u64).wrapping_mul(0x100000001b3);
+                }
+            }
+            let machine = &sorter.machine;
+            assert_eq!(frames, hash, "{:?}", algorithm);
+            assert_eq!(
+                (machine.steps, machine.comparisons, machine.accesses),
+                (steps, comparisons, accesses),
+                "{:?}",
+                algorithm
+            );
+        }
+    }
+
+    #[test]
+    fn test_draw_adapter_matches_sub_region() {
+        let (width, height) = (120u32, 80u32);
+        let sorter = SortVisualizer::new_with_size(SortAlgorithm::Shell, 30);
+        let mut legacy = vec![0u8; (width * height * 4) as usize];
+        let mut ported = legacy.clone();
+        let whole = Region::new(0, 0, width, height);
+        let mut ctx = DrawCtx::new(&mut ported, whole, width, 0.0);
+        for (x, y, horizontal, flip_h, flip_v) in [
+            (0, 0, true, false, true),
+            (0, 60, true, false, false),
+            (0, 20, false, true, false),
+            (90, 20, false, false, false),
+        ] {
+            let (w, h) = if horizontal { (120, 20) } else { (30, 40) };
+            sorter.draw_with_direction(
+                &mut legacy,
+                x,
+                y,
+                w,
+                h,
+                horizontal,
+                0,
+                width,
+                flip_h,
+                flip_v,
+            );
+            let mut edge = ctx.sub_region(Region::new(x, y, w as u32, h as u32));
+            sorter.draw_into(&mut edge, horizontal, flip_h, flip_v);
+        }
+        assert!(legacy == ported);
+    }
+
+    #[test]
+    fn test_input_patterns_have_their_shape() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let n = 100;
+        let max_inversions = n * (n - 1) / 2;
+
+        // A uniform shuffle averages half the maximum; this is many deviations wide
+        let random = InputPattern::Random.generate(n, &mut rng);
+        let random_inversions = inversions(&random);
+        assert!((1500..3500).contains(&random_inversions));
+
+        let reversed = InputPattern::Reversed.generate(n, &mut rng);
+        assert_eq!(inversions(&reversed), max_inversions);
+
+        let nearly = InputPattern::NearlySorted(5).generate(n, &mut rng);
+        assert_eq!(inversions(&nearly), 5);
+
+        let few = InputPattern::FewUnique(4).generate(n, &mut rng);
+        let mut unique = few.clone();
+        unique.sort_unstable();
+        unique.dedup();
+        assert_eq!(unique.len(), 4);
+
+        let saw = InputPattern::Sawtooth.generate(n, &mut rng);
+        let descents = saw.windows(2).filter(|w| w[1] < w[0]).count();
+        assert_eq!(descents, SAWTOOTH_TEETH - 1);
+
+        // Every pattern keeps the size, and all but FewUnique the same values
+        for pattern in [
+            InputPattern::Random,
+            InputPattern::Reversed,
+            InputPattern::Sawtooth,
+        ] {
+            let mut values = pattern.generate(n, &mut rng);
+            values.sort_unstable();
+            assert_eq!(values, (1..=n as u8).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_restart_regenerates_from_the_pattern() {
+        let mut sorter = SortVisualizer::new_with_size(SortAlgorithm::Insertion, 50)
+            .with_pattern(InputPattern::Reversed);
+        assert_eq!(inversions(&sorter.machine.array), 50 * 49 / 2);
+        for _ in 0..10 {
+            sorter.update();
+        }
+        sorter.restart();
+        sorter.update();
+        assert_eq!(sorter.state, SortState::Running);
+        assert_eq!(inversions(&sorter.machine.array), 50 * 49 / 2);
+        assert_eq!(sorter.machine.i, 1);
+    }
+
+    #[test]
+    fn test_image_row_colors_follow_their_values() {
+        // Distinct brightness values, each with a color only it has
+        let mut values: Vec<u8> = (0..40).map(|i| i * 6 + 3).collect();
+        values.shuffle(&mut StdRng::seed_from_u64(3));
+        let color_of = |v: u8| [v, 255 - v, v / 2];
+        let row = ImageRow {
+            colors: values.iter().map(|&v| color_of(v)).collect(),
+            values,
+        };
+        for algorithm in [
+            SortAlgorithm::Bogo,
+            SortAlgorithm::Bubble,
+            SortAlgorithm::Quick,
+            SortAlgorithm::Merge,
+            SortAlgorithm::Insertion,
+            SortAlgorithm::Selection,
+            SortAlgorithm::Heap,
+            SortAlgorithm::Radix,
+            SortAlgorithm::Shell,
+            SortAlgorithm::Cocktail,
+        ] {
+            // Bogo only finishes on a handful of elements
+            let mut row = row.clone();
+            if algorithm == SortAlgorithm::Bogo {
+                row.values.truncate(5);
+                row.colors.truncate(5);
+            }
+            let mut sorter = SortVisualizer::new_with_size(algorithm.clone(), row.values.len());
+            sorter.set_image_row(Some(row.clone()));
+            sorter.update();
+            assert_eq!(sorter.machine.array, row.values);
+            for _ in 0..100_000 {
+                if sorter.state != SortState::Running {
+                    break;
+                }
+                sorter.update();
+            }
+            assert_eq!(sorter.state, SortState::Completed, "{:?}", algorithm);
+            assert!(sorter.machine.array.windows(2).all(|w| w[0] <= w[1]));
+            // Let the completion tint fade so bars show their own colors
+            for _ in 0..STATE_TINT_FRAMES {
+                sorter.update();
+            }
+            let colors = sorter.machine.colors.as_ref().unwrap();
+            for (i, &value) in sorter.machine.array.iter().enumerate() {
+                assert_eq!(colors[i], color_of(value), "{:?} at {}", algorithm, i);
+                let [r, g, b] = color_of(value);
+                assert_eq!(sorter.element_color(i)[..3], [r, g, b][..]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_input_pattern_keys_round_trip() {
+        let mut pattern = InputPattern::Random;
+        loop {
+            assert_eq!(InputPattern::from_key(&pattern.key()), Some(pattern));
+            pattern = pattern.next();
+            if pattern == InputPattern::Random {
+                break;
             }
         }
+        assert_eq!(
+            InputPattern::from_key("nearly_sorted"),
+            Some(InputPattern::NearlySorted(DEFAULT_NEARLY_SORTED_SWAPS))
+        );
+        assert_eq!(InputPattern::from_key("few_unique:x"), None);
+        assert_eq!(InputPattern::from_key("sorted"), None);
     }
 }
@@ -1,35 +1,492 @@
+use crate::algorithms::image_dataset::ImageDataset;
+use crate::algorithms::shared_ring::{RingSorters, RING_QUARTER, RING_SIZE};
 use crate::algorithms::sorter::{
-    get_algorithm_stats, initialize_algorithm_stats, SortAlgorithm, SortState, SortVisualizer,
+    expected_bogo_shuffles, get_algorithm_stats, initialize_algorithm_stats, InputPattern,
+    SortAlgorithm, SortState, SortVisualizer, SorterColorMode, SorterSnapshot,
 };
+use crate::core::accessibility::{hud_backing, readable_text_color, sample_luminance};
+use crate::core::events::{self, Event};
+use crate::core::persist::{PersistError, PersistentState};
+use crate::core::snapshot::Snapshottable;
+use crate::core::types::color_to_rgba;
+use crate::graphics::draw_ctx::{DrawCtx, Region};
+use crate::graphics::theme::current_theme;
 use crate::physics::detect_corner;
+use crate::ui::hud_layout::{HudAnchor, HudElement, HudRect, HudRequest};
+use serde_json::{json, Value};
+use std::cmp::Reverse;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard, OnceLock, PoisonError};
 
-// Global static sorters - each positioned in different areas of the screen
-static mut TOP_SORTER: Option<SortVisualizer> = None;
-static mut BOTTOM_SORTER: Option<SortVisualizer> = None;
-static mut LEFT_SORTER: Option<SortVisualizer> = None;
-static mut RIGHT_SORTER: Option<SortVisualizer> = None;
+/// Created once by `initialize_sorters`
+static SORTERS: OnceLock<Mutex<EdgeSorters>> = OnceLock::new();
+
+/// Default number of elements Bogo Sort gets; 7! = 5040 expected shuffles
+pub const DEFAULT_BOGO_ARRAY_SIZE: usize = 7;
+/// Bogo gives up after this many times its expected shuffle count
+const BOGO_GIVE_UP_FACTOR: u64 = 2;
+
+static BOGO_ARRAY_SIZE: AtomicUsize = AtomicUsize::new(DEFAULT_BOGO_ARRAY_SIZE);
+static SORTER_COLOR_MODE: Mutex<SorterColorMode> = Mutex::new(SorterColorMode::Flat);
+static INPUT_PATTERN: Mutex<InputPattern> = Mutex::new(InputPattern::Random);
+/// Photo whose rows the dataset edge sorts, and that edge
+static IMAGE_DATASET: Mutex<Option<(ImageDataset, SorterEdge)>> = Mutex::new(None);
+
+/// Per-edge captions with algorithm, percent sorted, and steps, shown with the stats overlay
+static SORTER_CAPTIONS: AtomicBool = AtomicBool::new(false);
+/// Strip kept free of bars along the inner side of each edge while captions are on
+const CAPTION_MARGIN: usize = 16;
+/// Thinnest a strip of bars may get, in pixels, before captions give up
+/// their strip to the bars and strips stop shrinking with the frame
+const MIN_BAR_DEPTH: usize = 2;
+const CAPTION_CHAR_WIDTH: usize = 8;
+const STATS_CHAR_WIDTH: u32 = 8;
+const STATS_CHAR_HEIGHT: u32 = 12;
+const STATS_PADDING: u32 = 4;
+/// The first thing to give way when the HUD runs out of room
+const STATS_PRIORITY: u8 = 1;
+const CAPTION_CHAR_HEIGHT: usize = 12;
+/// Translucent backing of the leaderboard, captions, and Bogo label
+const STATS_BACKGROUND: [u8; 4] = [0, 0, 0, 180];
+
+/// Bars sampled along a finished edge for its completion burst
+const COMPLETION_SAMPLES: usize = 8;
+/// How long a leaderboard entry flashes after its algorithm finishes an edge
+const LEADERBOARD_FLASH_SECONDS: f32 = 1.0;
+
+/// Algorithms that finished an edge and the scene time they finished at
+static LEADERBOARD_FLASHES: Mutex<Vec<(SortAlgorithm, f32)>> = Mutex::new(Vec::new());
+
+/// The edges sort quarters of one shared ring instead of arrays of their own
+static WORLD_RING_MODE: AtomicBool = AtomicBool::new(false);
+/// Edges of the ring's quarters in ring order, clockwise from the top-left corner
+const RING_EDGES: [SorterEdge; 4] = [
+    SorterEdge::Top,
+    SorterEdge::Right,
+    SorterEdge::Bottom,
+    SorterEdge::Left,
+];
+/// How long the pulse takes to run once around a finished ring
+const RING_PULSE_SECONDS: f32 = 1.5;
+/// Elements lit behind the pulse's head, fading toward its tail
+const RING_PULSE_TAIL: f32 = 60.0;
+
+/// Screen edge a sorter is drawn along
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SorterEdge {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+/// The edge sorters and the ring they share in world ring mode
+struct EdgeSorters {
+    top: SortVisualizer,
+    bottom: SortVisualizer,
+    left: SortVisualizer,
+    right: SortVisualizer,
+    ring: RingSorters,
+    /// Scene time the whole ring last finished sorting at
+    ring_pulse_at: Option<f32>,
+}
+
+impl EdgeSorters {
+    fn edge(&self, edge: SorterEdge) -> &SortVisualizer {
+        match edge {
+            SorterEdge::Top => &self.top,
+            SorterEdge::Bottom => &self.bottom,
+            SorterEdge::Left => &self.left,
+            SorterEdge::Right => &self.right,
+        }
+    }
+
+    fn edge_mut(&mut self, edge: SorterEdge) -> &mut SortVisualizer {
+        match edge {
+            SorterEdge::Top => &mut self.top,
+            SorterEdge::Bottom => &mut self.bottom,
+            SorterEdge::Left => &mut self.left,
+            SorterEdge::Right => &mut self.right,
+        }
+    }
+
+    /// The top, bottom, left, and right sorters, in that order
+    fn edges_mut(&mut self) -> [&mut SortVisualizer; 4] {
+        [
+            &mut self.top,
+            &mut self.bottom,
+            &mut self.left,
+            &mut self.right,
+        ]
+    }
+
+    /// The sorter whose progress an edge shows: its quarter of the ring in
+    /// world ring mode, otherwise its own
+    fn shown(&self, edge: SorterEdge) -> &SortVisualizer {
+        if world_ring_enabled() {
+            if let Some(quarter) = RING_EDGES.iter().position(|&e| e == edge) {
+                return &self.ring.sorters[quarter];
+            }
+        }
+        self.edge(edge)
+    }
+}
+
+/// The sorters, or None before `initialize_sorters`
+fn sorters() -> Option<MutexGuard<'static, EdgeSorters>> {
+    SORTERS
+        .get()
+        .map(|sorters| sorters.lock().unwrap_or_else(PoisonError::into_inner))
+}
+
+/// Sets the bar color mode for every edge sorter, including ones created later
+pub fn set_sorter_color_mode(mode: SorterColorMode) {
+    *SORTER_COLOR_MODE
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner) = mode;
+    if let Some(mut sorters) = sorters() {
+        for sorter in sorters.edges_mut() {
+            sorter.color_mode = mode;
+        }
+        for sorter in &mut sorters.ring.sorters {
+            sorter.color_mode = mode;
+        }
+    }
+}
+
+pub fn get_sorter_color_mode() -> SorterColorMode {
+    *SORTER_COLOR_MODE
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Moves every sorter to the next color mode and returns it
+pub fn cycle_sorter_color_mode() -> SorterColorMode {
+    let mode = get_sorter_color_mode().next();
+    set_sorter_color_mode(mode);
+    mode
+}
+
+/// Sets how every edge sorter's array is arranged and restarts any whose
+/// pattern changed, so the new arrangement shows right away
+pub fn set_input_pattern(pattern: InputPattern) {
+    *INPUT_PATTERN.lock().unwrap_or_else(PoisonError::into_inner) = pattern;
+    if let Some(mut sorters) = sorters() {
+        for sorter in sorters.edges_mut() {
+            if sorter.pattern != pattern {
+                sorter.pattern = pattern;
+                sorter.restart();
+            }
+        }
+        let ring = &mut sorters.ring;
+        if ring.sorters[0].pattern != pattern {
+            for sorter in &mut ring.sorters {
+                sorter.pattern = pattern;
+            }
+            ring.restart();
+        }
+    }
+}
+
+pub fn get_input_pattern() -> InputPattern {
+    *INPUT_PATTERN.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Moves every sorter to the next input pattern and returns it
+pub fn cycle_input_pattern() -> InputPattern {
+    let pattern = get_input_pattern().next();
+    set_input_pattern(pattern);
+    pattern
+}
+
+fn image_dataset() -> MutexGuard<'static, Option<(ImageDataset, SorterEdge)>> {
+    IMAGE_DATASET.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Loads an image and has the `edge` sorter sort its rows by brightness,
+/// in the pixels' own colors. Other edges keep their patterns.
+pub fn load_image_dataset(path: &Path, edge: SorterEdge) -> Result<(), image::ImageError> {
+    let dataset = ImageDataset::load(path)?;
+    let old = image_dataset().replace((dataset, edge));
+    if let Some((_, old_edge)) = old {
+        if let Some(mut sorters) = sorters() {
+            sorters.edge_mut(old_edge).set_image_row(None);
+        }
+    }
+    apply_image_dataset();
+    Ok(())
+}
+
+/// Switches the dataset edge to the next image row and returns its pixel
+/// row, or None without an image
+pub fn cycle_image_row() -> Option<u32> {
+    let row = image_dataset()
+        .as_mut()
+        .map(|(dataset, _)| dataset.next_row());
+    apply_image_dataset();
+    row
+}
+
+/// Hands the current image row to the dataset edge's sorter, if both exist
+fn apply_image_dataset() {
+    let slot = image_dataset();
+    let Some((dataset, edge)) = slot.as_ref() else {
+        return;
+    };
+    if let Some(mut sorters) = sorters() {
+        let sorter = sorters.edge_mut(*edge);
+        sorter.set_image_row(Some(dataset.row(sorter.machine.array.len())));
+    }
+}
+
+/// Overrides the bar color mode of a single edge's sorter
+pub fn set_edge_color_mode(edge: SorterEdge, mode: SorterColorMode) {
+    if let Some(mut sorters) = sorters() {
+        sorters.edge_mut(edge).color_mode = mode;
+    }
+}
+
+pub fn set_captions_enabled(enabled: bool) {
+    SORTER_CAPTIONS.store(enabled, Ordering::Relaxed);
+}
+
+pub fn captions_enabled() -> bool {
+    SORTER_CAPTIONS.load(Ordering::Relaxed)
+}
+
+/// Flips the edge captions and returns the new state
+pub fn toggle_captions() -> bool {
+    !SORTER_CAPTIONS.fetch_xor(true, Ordering::Relaxed)
+}
+
+pub fn set_world_ring_enabled(enabled: bool) {
+    WORLD_RING_MODE.store(enabled, Ordering::Relaxed);
+}
+
+pub fn world_ring_enabled() -> bool {
+    WORLD_RING_MODE.load(Ordering::Relaxed)
+}
+
+/// Switches the edges between their own arrays and quarters of the shared
+/// ring, and returns whether the ring is on
+pub fn toggle_world_ring() -> bool {
+    !WORLD_RING_MODE.fetch_xor(true, Ordering::Relaxed)
+}
+
+/// The ring with the edge sorters' algorithms on their own edges, so
+/// switching modes keeps each edge's algorithm
+fn new_world_ring() -> RingSorters {
+    let mut ring = RingSorters::new(
+        [
+            SortAlgorithm::Shell,
+            SortAlgorithm::Selection,
+            SortAlgorithm::Quick,
+            SortAlgorithm::Insertion,
+        ],
+        get_input_pattern(),
+    );
+    for sorter in &mut ring.sorters {
+        sorter.color_mode = get_sorter_color_mode();
+    }
+    ring
+}
+
+/// How the edge sorters look; the part of the Rays scene worth keeping
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SorterLook {
+    pub color_mode: SorterColorMode,
+    pub captions: bool,
+}
+
+pub fn sorter_look() -> SorterLook {
+    SorterLook {
+        color_mode: get_sorter_color_mode(),
+        captions: captions_enabled(),
+    }
+}
+
+pub fn set_sorter_look(look: SorterLook) {
+    set_sorter_color_mode(look.color_mode);
+    set_captions_enabled(look.captions);
+}
+
+impl PersistentState for SorterLook {
+    fn save_state(&self) -> Value {
+        let color_mode = match self.color_mode {
+            SorterColorMode::Flat => "flat",
+            SorterColorMode::ValueHue => "value_hue",
+            SorterColorMode::Thermal => "thermal",
+        };
+        json!({ "color_mode": color_mode, "captions": self.captions })
+    }
+
+    fn load_state(&mut self, blob: &Value) -> Result<(), PersistError> {
+        let color_mode = match blob.get("color_mode").map(Value::as_str) {
+            None => self.color_mode,
+            Some(Some("flat")) => SorterColorMode::Flat,
+            Some(Some("value_hue")) => SorterColorMode::ValueHue,
+            Some(Some("thermal")) => SorterColorMode::Thermal,
+            Some(_) => return Err(PersistError::Invalid("unknown sorter color mode")),
+        };
+        let captions = match blob.get("captions") {
+            None => self.captions,
+            Some(captions) => captions
+                .as_bool()
+                .ok_or(PersistError::Invalid("captions must be true or false"))?,
+        };
+        *self = SorterLook {
+            color_mode,
+            captions,
+        };
+        Ok(())
+    }
+}
+
+/// Sets the array size used for Bogo sorters created after this call
+pub fn set_bogo_array_size(size: usize) {
+    BOGO_ARRAY_SIZE.store(size.max(2), Ordering::Relaxed);
+}
+
+pub fn get_bogo_array_size() -> usize {
+    BOGO_ARRAY_SIZE.load(Ordering::Relaxed)
+}
+
+/// Creates an edge sorter. Bogo Sort would never finish on a full-size array,
+/// so it gets a small array and a step cap after which it gives up.
+fn new_edge_sorter(algorithm: SortAlgorithm, size: usize) -> SortVisualizer {
+    let mut sorter = if algorithm == SortAlgorithm::Bogo {
+        let bogo_size = get_bogo_array_size();
+        let cap = expected_bogo_shuffles(bogo_size).saturating_mul(BOGO_GIVE_UP_FACTOR);
+        SortVisualizer::new_with_size(algorithm, bogo_size)
+            .with_max_steps(cap.min(usize::MAX as u64) as usize)
+    } else {
+        SortVisualizer::new_with_size(algorithm, size)
+    };
+    sorter.color_mode = get_sorter_color_mode();
+    sorter.with_pattern(get_input_pattern())
+}
 
 pub fn initialize_sorters() {
     initialize_algorithm_stats();
     // Use a fixed size for fair comparison - all algorithms sort the same number of elements
     // This ensures the leaderboard is based on algorithm speed, not array size differences
     const FIXED_ARRAY_SIZE: usize = 100;
-    unsafe {
-        if TOP_SORTER.is_none() {
-            TOP_SORTER = Some(SortVisualizer::new_with_size(SortAlgorithm::Shell, FIXED_ARRAY_SIZE));
-        }
-        if BOTTOM_SORTER.is_none() {
-            BOTTOM_SORTER = Some(SortVisualizer::new_with_size(SortAlgorithm::Quick, FIXED_ARRAY_SIZE));
-        }
-        if LEFT_SORTER.is_none() {
-            LEFT_SORTER = Some(SortVisualizer::new_with_size(SortAlgorithm::Insertion, FIXED_ARRAY_SIZE));
-        }
-        if RIGHT_SORTER.is_none() {
-            RIGHT_SORTER = Some(SortVisualizer::new_with_size(SortAlgorithm::Selection, FIXED_ARRAY_SIZE));
+    SORTERS.get_or_init(|| {
+        Mutex::new(EdgeSorters {
+            top: new_edge_sorter(SortAlgorithm::Shell, FIXED_ARRAY_SIZE),
+            bottom: new_edge_sorter(SortAlgorithm::Quick, FIXED_ARRAY_SIZE),
+            left: new_edge_sorter(SortAlgorithm::Insertion, FIXED_ARRAY_SIZE),
+            right: new_edge_sorter(SortAlgorithm::Selection, FIXED_ARRAY_SIZE),
+            ring: new_world_ring(),
+            ring_pulse_at: None,
+        })
+    });
+    // An image may have been loaded before the sorters existed
+    apply_image_dataset();
+}
+
+/// Screen rectangle an edge sorter is drawn into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EdgeRegion {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl EdgeRegion {
+    #[cfg(test)]
+    fn contains(&self, x: usize, y: usize, width: usize, height: usize) -> bool {
+        x >= self.x
+            && y >= self.y
+            && x + width <= self.x + self.width
+            && y + height <= self.y + self.height
+    }
+}
+
+/// How much of the frame the edge sorters take up
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EdgeThickness {
+    /// Top and bottom strip height, as a fraction of the frame height
+    pub border: f32,
+    /// Left and right strip width, as a fraction of the frame width
+    pub side: f32,
+    /// Multiplier on both, usually the mean of the display scale factors
+    pub scale: f32,
+}
+
+impl EdgeThickness {
+    pub const DEFAULT: Self = Self {
+        border: 0.05,
+        side: 0.15,
+        scale: 1.0,
+    };
+
+    /// This thickness grown with the display scale
+    pub fn scaled(self, scale_x: f32, scale_y: f32) -> Self {
+        Self {
+            scale: (scale_x + scale_y) / 2.0,
+            ..self
         }
     }
 }
 
+/// Regions of the top, bottom, left, and right sorters, in that order
+pub fn edge_regions(
+    width: u32,
+    height: u32,
+    thickness: EdgeThickness,
+) -> [(SorterEdge, EdgeRegion); 4] {
+    let (width, height) = (width as usize, height as usize);
+    let border_thickness = ((height as f32 * thickness.border * thickness.scale) as usize)
+        .max(MIN_BAR_DEPTH)
+        .min(height / 2);
+    let side_width = ((width as f32 * thickness.side * thickness.scale) as usize)
+        .max(MIN_BAR_DEPTH)
+        .min(width / 2);
+    let side_height = height.saturating_sub(border_thickness * 2);
+    [
+        (
+            SorterEdge::Top,
+            EdgeRegion {
+                x: 0,
+                y: 0,
+                width,
+                height: border_thickness,
+            },
+        ),
+        (
+            SorterEdge::Bottom,
+            EdgeRegion {
+                x: 0,
+                y: height - border_thickness,
+                width,
+                height: border_thickness,
+            },
+        ),
+        (
+            SorterEdge::Left,
+            EdgeRegion {
+                x: 0,
+                y: border_thickness,
+                width: side_width,
+                height: side_height,
+            },
+        ),
+        (
+            SorterEdge::Right,
+            EdgeRegion {
+                x: width - side_width,
+                y: border_thickness,
+                width: side_width,
+                height: side_height,
+            },
+        ),
+    ]
+}
+
+/// Adapter for callers still passing raw frame parameters; see `draw_sorters`
 pub fn draw_sorter_visualizations(
     frame: &mut [u8],
     width: u32,
@@ -40,211 +497,687 @@ pub fn draw_sorter_visualizations(
     x_offset: usize,
     buffer_width: u32,
 ) {
-    let scale_factor = (scale_x + scale_y) / 2.0;
-    let border_thickness = (height as f32 * 0.05 * scale_factor) as usize;
-    let side_width = (width as f32 * 0.15 * scale_factor) as usize;
+    let mut ctx = DrawCtx::from_legacy(frame, width, height, time, x_offset, buffer_width);
+    draw_sorters(&mut ctx, EdgeThickness::DEFAULT.scaled(scale_x, scale_y));
+}
 
-    unsafe {
-        update_and_draw_sorter(
-            &mut TOP_SORTER,
-            frame,
-            0,
-            0,
-            width as usize,
-            border_thickness,
-            true,
-            time,
-            x_offset,
-            buffer_width,
-            false,
-            true,
-        ); // flip_vertical = true for top
-        update_and_draw_sorter(
-            &mut BOTTOM_SORTER,
-            frame,
-            0,
-            height as usize - border_thickness,
-            width as usize,
-            border_thickness,
-            true,
-            time,
-            x_offset,
-            buffer_width,
-            false,
-            false,
-        ); // no flip for bottom
-        update_and_draw_sorter(
-            &mut LEFT_SORTER,
-            frame,
-            0,
-            border_thickness,
-            side_width,
-            height as usize - border_thickness * 2,
-            false,
-            time,
-            x_offset,
-            buffer_width,
-            true,
-            false,
-        ); // flip_horizontal = true for left
-        update_and_draw_sorter(
-            &mut RIGHT_SORTER,
-            frame,
-            width as usize - side_width,
-            border_thickness,
-            side_width,
-            height as usize - border_thickness * 2,
-            false,
-            time,
-            x_offset,
-            buffer_width,
-            false,
-            false,
-        ); // no flip for right
+/// Updates each edge sorter and draws it into its edge of the context's
+/// region, in strips as thick as `thickness` says. Sorters that finish
+/// publish `SorterCompleted`, with the points their celebration bursts from.
+pub fn draw_sorters(ctx: &mut DrawCtx, thickness: EdgeThickness) {
+    let sorters = sorters();
+    debug_assert!(
+        sorters.is_some(),
+        "sorters drawn before initialize_sorters()"
+    );
+    let Some(mut sorters) = sorters else {
+        return;
+    };
+    if world_ring_enabled() {
+        draw_world_ring(&mut sorters, ctx, thickness);
+        return;
+    }
+    let captions = captions_enabled();
+    for (edge, region) in edge_regions(ctx.width(), ctx.height(), thickness) {
+        let bars = if captions {
+            bar_region(edge, region)
+        } else {
+            region
+        };
+        // Top bars hang down from the edge, left bars grow rightward from it
+        let (horizontal, flip_horizontal, flip_vertical) = match edge {
+            SorterEdge::Top => (true, false, true),
+            SorterEdge::Bottom => (true, false, false),
+            SorterEdge::Left => (false, true, false),
+            SorterEdge::Right => (false, false, false),
+        };
+        let mut edge_ctx = ctx.sub_region(Region::new(
+            bars.x,
+            bars.y,
+            bars.width as u32,
+            bars.height as u32,
+        ));
+        let sorter = sorters.edge_mut(edge);
+        let finished = update_and_draw_sorter(
+            sorter,
+            &mut edge_ctx,
+            horizontal,
+            flip_horizontal,
+            flip_vertical,
+        );
+        if finished {
+            events::publish(Event::SorterCompleted {
+                algorithm: sorter.machine.algorithm.clone(),
+                edge,
+                points: completion_points(&sorter.machine.array, edge, bars),
+            });
+        }
     }
 }
 
-fn update_and_draw_sorter(
-    sorter: &mut Option<SortVisualizer>,
-    frame: &mut [u8],
+fn leaderboard_flashes() -> MutexGuard<'static, Vec<(SortAlgorithm, f32)>> {
+    LEADERBOARD_FLASHES
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Flashes the leaderboard entry of each algorithm that finishes an edge
+pub fn on_event(event: &Event, time: f32) {
+    if let Event::SorterCompleted { algorithm, .. } = event {
+        let mut flashes = leaderboard_flashes();
+        flashes.retain(|(flashing, _)| flashing != algorithm);
+        flashes.push((algorithm.clone(), time));
+    }
+}
+
+/// Updates the shared ring and draws each quarter along its edge. Quarters
+/// that finish are held until the whole ring is sorted; then a pulse runs
+/// around the border and, once it has, the ring starts over.
+fn draw_world_ring(sorters: &mut EdgeSorters, ctx: &mut DrawCtx, thickness: EdgeThickness) {
+    let captions = captions_enabled();
+    let regions = edge_regions(ctx.width(), ctx.height(), thickness);
+    let ring = &mut sorters.ring;
+    let pulse_over = match sorters.ring_pulse_at {
+        Some(at) => !(0.0..RING_PULSE_SECONDS).contains(&(ctx.time - at)),
+        None => true,
+    };
+    if ring.is_complete() && pulse_over && (ctx.time * 10.0).floor() % 10.0 == 0.0 {
+        ring.restart();
+    }
+    let (finished, ring_finished) = ring.update();
+    if ring_finished {
+        sorters.ring_pulse_at = Some(ctx.time);
+    }
+    let pulse_age = sorters.ring_pulse_at.map(|at| ctx.time - at);
+    for (quarter, sorter) in ring.sorters.iter().enumerate() {
+        let edge = RING_EDGES[quarter];
+        let Some(&(_, region)) = regions.iter().find(|(e, _)| *e == edge) else {
+            continue;
+        };
+        let bars = if captions {
+            bar_region(edge, region)
+        } else {
+            region
+        };
+        let view = ring.ring.view(quarter);
+        // Burst points at evenly spaced bar tips, as for an edge of its own
+        let sampled: Vec<usize> = (0..COMPLETION_SAMPLES)
+            .map(|sample| (sample * 2 + 1) * RING_QUARTER / (COMPLETION_SAMPLES * 2))
+            .collect();
+        let mut points = Vec::new();
+        for (local, &value) in sorter.machine.array.iter().enumerate() {
+            let index = view.to_ring(local);
+            let slot = ring_slot_rect(index, bars);
+            let pulse = pulse_age.map_or(0.0, |age| ring_pulse(index, age));
+            let color = mix_toward_white(sorter.element_color(local), pulse);
+            let bar = ring_bar_rect(edge, slot, value, pulse);
+            ctx.fill_rect(
+                bar.x as i32,
+                bar.y as i32,
+                bar.width as u32,
+                bar.height as u32,
+                color,
+            );
+            if sampled.contains(&local) {
+                points.push(bar_tip(edge, bar));
+            }
+        }
+        if finished.contains(&quarter) {
+            events::publish(Event::SorterCompleted {
+                algorithm: sorter.machine.algorithm.clone(),
+                edge,
+                points,
+            });
+        }
+    }
+}
+
+/// Full-depth slot of ring element `index` in its edge's `bars`. The ring
+/// runs clockwise, so the bottom is laid out right to left and the left
+/// bottom to top. The top and bottom strips span the corners, so element 99
+/// ends at the top-right corner and element 100 starts right below it.
+fn ring_slot_rect(index: usize, bars: EdgeRegion) -> EdgeRegion {
+    let edge = RING_EDGES[index / RING_QUARTER % 4];
+    let local = index % RING_QUARTER;
+    let along = match edge {
+        SorterEdge::Top | SorterEdge::Right => local,
+        SorterEdge::Bottom | SorterEdge::Left => RING_QUARTER - 1 - local,
+    };
+    let horizontal = matches!(edge, SorterEdge::Top | SorterEdge::Bottom);
+    let extent = if horizontal { bars.width } else { bars.height };
+    // Shared out evenly, overlapping rather than vanishing when pixels run short
+    let start = along * extent / RING_QUARTER;
+    let size = ((along + 1) * extent / RING_QUARTER).max(start + 1) - start;
+    if horizontal {
+        EdgeRegion {
+            x: bars.x + start,
+            width: size,
+            ..bars
+        }
+    } else {
+        EdgeRegion {
+            y: bars.y + start,
+            height: size,
+            ..bars
+        }
+    }
+}
+
+/// The bar for `value` in `slot`, growing in from the screen border; the
+/// pulse stretches it toward the slot's full depth
+fn ring_bar_rect(edge: SorterEdge, slot: EdgeRegion, value: u8, pulse: f32) -> EdgeRegion {
+    let horizontal = matches!(edge, SorterEdge::Top | SorterEdge::Bottom);
+    let depth = if horizontal { slot.height } else { slot.width };
+    let bar = ((value as f32 / 256.0) * depth as f32).max(pulse * depth as f32) as usize;
+    match edge {
+        SorterEdge::Top => EdgeRegion {
+            height: bar,
+            ..slot
+        },
+        SorterEdge::Bottom => EdgeRegion {
+            y: slot.y + depth - bar,
+            height: bar,
+            ..slot
+        },
+        SorterEdge::Left => EdgeRegion { width: bar, ..slot },
+        SorterEdge::Right => EdgeRegion {
+            x: slot.x + depth - bar,
+            width: bar,
+            ..slot
+        },
+    }
+}
+
+/// Middle of a bar's inner end, the side facing the center of the screen
+fn bar_tip(edge: SorterEdge, bar: EdgeRegion) -> (f32, f32) {
+    let mid_x = bar.x as f32 + bar.width as f32 / 2.0;
+    let mid_y = bar.y as f32 + bar.height as f32 / 2.0;
+    match edge {
+        SorterEdge::Top => (mid_x, (bar.y + bar.height) as f32),
+        SorterEdge::Bottom => (mid_x, bar.y as f32),
+        SorterEdge::Left => ((bar.x + bar.width) as f32, mid_y),
+        SorterEdge::Right => (bar.x as f32, mid_y),
+    }
+}
+
+/// How brightly ring element `index` is lit `age` seconds after the ring
+/// finished: a band sweeping clockwise once around, brightest at its head
+fn ring_pulse(index: usize, age: f32) -> f32 {
+    if !(0.0..RING_PULSE_SECONDS).contains(&age) {
+        return 0.0;
+    }
+    let head = age / RING_PULSE_SECONDS * (RING_SIZE as f32 + RING_PULSE_TAIL);
+    let behind = head - index as f32;
+    if (0.0..RING_PULSE_TAIL).contains(&behind) {
+        1.0 - behind / RING_PULSE_TAIL
+    } else {
+        0.0
+    }
+}
+
+fn mix_toward_white(color: [u8; 4], amount: f32) -> [u8; 4] {
+    let mix = |c: u8| (c as f32 + (255.0 - c as f32) * amount).round() as u8;
+    [mix(color[0]), mix(color[1]), mix(color[2]), color[3]]
+}
+
+/// Tips of `COMPLETION_SAMPLES` evenly spaced bars of `array` drawn along
+/// `edge` in `bars`, matching how `SortVisualizer::draw_into` lays them out
+fn completion_points(array: &[u8], edge: SorterEdge, bars: EdgeRegion) -> Vec<(f32, f32)> {
+    let len = array.len();
+    if len == 0 {
+        return Vec::new();
+    }
+    let horizontal = matches!(edge, SorterEdge::Top | SorterEdge::Bottom);
+    let (extent, depth) = if horizontal {
+        (bars.width, bars.height)
+    } else {
+        (bars.height, bars.width)
+    };
+    (0..COMPLETION_SAMPLES.min(len))
+        .map(|sample| {
+            let i = (sample * 2 + 1) * len / (COMPLETION_SAMPLES.min(len) * 2);
+            let along = (i * extent / len) as f32 + 0.5 * extent as f32 / len as f32;
+            let bar = (array[i] as f32 / 256.0 * depth as f32).floor();
+            let (x, y) = match edge {
+                SorterEdge::Top => (along, bar),
+                SorterEdge::Bottom => (along, depth as f32 - bar),
+                SorterEdge::Left => (bar, along),
+                SorterEdge::Right => (depth as f32 - bar, along),
+            };
+            (bars.x as f32 + x, bars.y as f32 + y)
+        })
+        .collect()
+}
+
+/// How strongly `algorithm`'s leaderboard entry flashes at `time`, from 1
+/// when it just finished an edge down to 0
+fn leaderboard_flash(algorithm: &SortAlgorithm, time: f32) -> f32 {
+    let finished_at = leaderboard_flashes()
+        .iter()
+        .find(|(flashing, _)| flashing == algorithm)
+        .map(|&(_, at)| at);
+    finished_at.map_or(0.0, |at| {
+        let age = time - at;
+        // A rewound clock (replays, restored snapshots) ends the flash
+        if (0.0..LEADERBOARD_FLASH_SECONDS).contains(&age) {
+            1.0 - age / LEADERBOARD_FLASH_SECONDS
+        } else {
+            0.0
+        }
+    })
+}
+
+/// The caption strip kept across an edge region `across` pixels thick: none
+/// when that wouldn't leave `MIN_BAR_DEPTH` for the bars
+fn caption_strip(across: usize) -> usize {
+    if across >= CAPTION_MARGIN + MIN_BAR_DEPTH {
+        CAPTION_MARGIN
+    } else {
+        0
+    }
+}
+
+/// The part of an edge region left for bars once the caption strip is reserved
+/// along its inner side, the side facing the center of the screen
+fn bar_region(edge: SorterEdge, region: EdgeRegion) -> EdgeRegion {
+    let mut bars = region;
+    match edge {
+        SorterEdge::Top => bars.height -= caption_strip(region.height),
+        SorterEdge::Bottom => {
+            let margin = caption_strip(region.height);
+            bars.y += margin;
+            bars.height -= margin;
+        }
+        SorterEdge::Left => bars.width -= caption_strip(region.width),
+        SorterEdge::Right => {
+            let margin = caption_strip(region.width);
+            bars.x += margin;
+            bars.width -= margin;
+        }
+    }
+    bars
+}
+
+/// A caption positioned in an edge's reserved strip
+#[derive(Debug, Clone, PartialEq)]
+struct CaptionLayout {
     x: usize,
     y: usize,
-    width: usize,
-    height: usize,
-    horizontal: bool,
-    time: f32,
+    text: String,
+    /// Characters stacked top to bottom, for the left and right edges
+    vertical: bool,
+}
+
+impl CaptionLayout {
+    fn size(&self) -> (usize, usize) {
+        let len = self.text.chars().count();
+        if self.vertical {
+            (CAPTION_CHAR_WIDTH, len * CAPTION_CHAR_HEIGHT)
+        } else {
+            (len * CAPTION_CHAR_WIDTH, CAPTION_CHAR_HEIGHT)
+        }
+    }
+}
+
+/// Centers `text` at the midpoint of the edge's caption strip, truncating it to
+/// the strip's length. None when the strip is too thin for a single character.
+fn caption_layout(edge: SorterEdge, region: EdgeRegion, text: &str) -> Option<CaptionLayout> {
+    let vertical = matches!(edge, SorterEdge::Left | SorterEdge::Right);
+    // Sizes across the strip and along the edge
+    let (across, along) = if vertical {
+        (region.width, region.height)
+    } else {
+        (region.height, region.width)
+    };
+    let (char_across, char_along) = if vertical {
+        (CAPTION_CHAR_WIDTH, CAPTION_CHAR_HEIGHT)
+    } else {
+        (CAPTION_CHAR_HEIGHT, CAPTION_CHAR_WIDTH)
+    };
+    let strip = caption_strip(across);
+    let max_chars = along / char_along;
+    if strip < char_across || max_chars == 0 {
+        return None;
+    }
+    let text: String = text.chars().take(max_chars).collect();
+    let text_along = text.chars().count() * char_along;
+    let start_along = (along - text_along) / 2;
+    let strip_start = match edge {
+        SorterEdge::Top | SorterEdge::Left => across - strip,
+        SorterEdge::Bottom | SorterEdge::Right => 0,
+    };
+    let start_across = strip_start + (strip - char_across) / 2;
+    let (x, y) = if vertical {
+        (region.x + start_across, region.y + start_along)
+    } else {
+        (region.x + start_along, region.y + start_across)
+    };
+    Some(CaptionLayout {
+        x,
+        y,
+        text,
+        vertical,
+    })
+}
+
+fn caption_text(sorter: &SortVisualizer) -> String {
+    format!(
+        "{} {}% {}",
+        sorter.machine.algorithm.name().trim_end_matches(" Sort"),
+        (sorter.get_sorted_percent() * 100.0).round() as u32,
+        sorter.machine.steps
+    )
+}
+
+/// Draws each edge's caption in the strip `draw_sorter_visualizations` keeps free
+pub fn draw_sorter_captions(
+    frame: &mut [u8],
+    width: u32,
+    height: u32,
+    thickness: EdgeThickness,
     x_offset: usize,
     buffer_width: u32,
-    flip_horizontal: bool,
-    flip_vertical: bool,
 ) {
-    if let Some(sorter) = sorter {
-        sorter.update();
-        if sorter.state == SortState::Completed && (time * 10.0).floor() % 10.0 == 0.0 {
-            sorter.restart();
-        }
-        sorter.draw_with_direction(
+    if !captions_enabled() {
+        return;
+    }
+    let Some(sorters) = sorters() else {
+        return;
+    };
+    for (edge, region) in edge_regions(width, height, thickness) {
+        let text = caption_text(sorters.shown(edge));
+        let Some(layout) = caption_layout(edge, region, &text) else {
+            continue;
+        };
+        let (caption_width, caption_height) = layout.size();
+        draw_background_rect(
             frame,
-            x,
-            y,
+            layout.x as u32,
+            layout.y as u32,
+            caption_width as u32,
+            caption_height as u32,
+            hud_backing(STATS_BACKGROUND),
             width,
-            height,
-            horizontal,
             x_offset,
-            buffer_width as u32,
-            flip_horizontal,
-            flip_vertical,
+            buffer_width,
         );
+        if layout.vertical {
+            for (i, ch) in layout.text.chars().enumerate() {
+                draw_stats_text(
+                    frame,
+                    ch.encode_utf8(&mut [0; 4]),
+                    layout.x as u32,
+                    (layout.y + i * CAPTION_CHAR_HEIGHT) as u32,
+                    [255, 255, 255, 255],
+                    width,
+                    x_offset,
+                    buffer_width,
+                );
+            }
+        } else {
+            draw_stats_text(
+                frame,
+                &layout.text,
+                layout.x as u32,
+                layout.y as u32,
+                [255, 255, 255, 255],
+                width,
+                x_offset,
+                buffer_width,
+            );
+        }
+    }
+}
+
+/// Returns true when the sorter finished sorting during this update
+fn update_and_draw_sorter(
+    sorter: &mut SortVisualizer,
+    ctx: &mut DrawCtx,
+    horizontal: bool,
+    flip_horizontal: bool,
+    flip_vertical: bool,
+) -> bool {
+    let was_running = sorter.state == SortState::Running;
+    sorter.update();
+    let just_completed = was_running && sorter.state == SortState::Completed;
+    let finished = sorter.state == SortState::Completed || sorter.state == SortState::GaveUp;
+    if finished && (ctx.time * 10.0).floor() % 10.0 == 0.0 {
+        sorter.restart();
     }
+    sorter.draw_into(ctx, horizontal, flip_horizontal, flip_vertical);
+    if sorter.machine.algorithm == SortAlgorithm::Bogo {
+        draw_bogo_label(sorter, ctx);
+    }
+    just_completed
+}
+
+/// Shows shuffles attempted against the expected n!, or the give-up message
+fn draw_bogo_label(sorter: &SortVisualizer, ctx: &mut DrawCtx) {
+    let (text, color) = if sorter.state == SortState::GaveUp {
+        (
+            format!("gave up after {} shuffles", sorter.machine.steps),
+            [200, 140, 255, 255],
+        )
+    } else {
+        (
+            format!(
+                "shuffles {} of {} expected",
+                sorter.machine.steps,
+                expected_bogo_shuffles(sorter.machine.array.len())
+            ),
+            [255, 255, 255, 255],
+        )
+    };
+    // The bitmap text helpers still take the raw parameters
+    let (frame, width, _, x_offset, buffer_width) = ctx.legacy();
+    draw_background_rect(
+        frame,
+        0,
+        0,
+        (text.len() as u32 * 8 + 8).min(width),
+        20,
+        hud_backing(STATS_BACKGROUND),
+        width,
+        x_offset,
+        buffer_width,
+    );
+    draw_stats_text(frame, &text, 4, 4, color, width, x_offset, buffer_width);
 }
 
 pub fn restart_sorters() {
-    unsafe {
-        if let Some(sorter) = TOP_SORTER.as_mut() {
-            sorter.restart();
-        }
-        if let Some(sorter) = BOTTOM_SORTER.as_mut() {
-            sorter.restart();
-        }
-        if let Some(sorter) = LEFT_SORTER.as_mut() {
+    if let Some(mut sorters) = sorters() {
+        for sorter in sorters.edges_mut() {
             sorter.restart();
         }
-        if let Some(sorter) = RIGHT_SORTER.as_mut() {
-            sorter.restart();
+        sorters.ring.restart();
+    }
+}
+
+/// Captures the top, bottom, left, and right sorters, in that order
+pub fn snapshot_sorters() -> [Option<SorterSnapshot>; 4] {
+    match sorters() {
+        Some(mut sorters) => sorters.edges_mut().map(|sorter| Some(sorter.snapshot())),
+        None => [None, None, None, None],
+    }
+}
+
+/// Restores sorters captured by `snapshot_sorters`; missing entries are left untouched
+pub fn restore_sorters(snapshots: &[Option<SorterSnapshot>; 4]) {
+    if let Some(mut sorters) = sorters() {
+        for (sorter, snapshot) in sorters.edges_mut().into_iter().zip(snapshots.iter()) {
+            if let Some(snapshot) = snapshot {
+                sorter.restore(snapshot);
+            }
         }
     }
 }
 
+/// Top four algorithms by completed runs
+fn leaderboard_entries() -> Option<Vec<(SortAlgorithm, u32)>> {
+    let stats_arc = get_algorithm_stats()?;
+    let stats_map = stats_arc.lock().ok()?;
+    // Collect and sort algorithms by completion count
+    let mut stats_vec: Vec<(SortAlgorithm, u32)> = stats_map
+        .iter()
+        .map(|(alg, &cnt)| (alg.clone(), cnt))
+        .collect();
+    stats_vec.sort_by_key(|&(_, count)| Reverse(count));
+    // Only keep the top 4 algorithms for display
+    stats_vec.truncate(4);
+    Some(stats_vec)
+}
+
+/// Width of the leaderboard's background for `entries`
+fn leaderboard_width(entries: &[(SortAlgorithm, u32)]) -> u32 {
+    let max_len = entries
+        .iter()
+        .map(|(alg, count)| format!("{}: {}", alg.name(), count).len())
+        .max()
+        .unwrap_or(0) as u32;
+    max_len * STATS_CHAR_WIDTH + STATS_PADDING * 2
+}
+
+fn input_pattern_text() -> String {
+    format!("Input: {}", get_input_pattern().label())
+}
+
+/// Room the leaderboard, the corner hits, and the input pattern ask for in
+/// the top-left corner
+pub fn algorithm_stats_request() -> Option<HudRequest> {
+    let entries = leaderboard_entries()?;
+    let width = leaderboard_width(&entries)
+        .max(input_pattern_text().len() as u32 * STATS_CHAR_WIDTH + STATS_PADDING * 2);
+    // The entries, then the corner hits and the input pattern a row each
+    let height =
+        (STATS_CHAR_HEIGHT + 2) * entries.len() as u32 + 2 * STATS_CHAR_HEIGHT + 5 * STATS_PADDING;
+    Some(HudRequest {
+        element: HudElement::AlgorithmStats,
+        anchor: HudAnchor::TopLeft,
+        priority: STATS_PRIORITY,
+        size: (width as f32, height as f32),
+    })
+}
+
+/// Draws the leaderboard in `region`, its spot in the HUD layout; entries for
+/// algorithms that just finished an edge flash in the algorithm's color for a
+/// second after `time`
 pub fn draw_algorithm_stats(
     frame: &mut [u8],
     width: u32,
-    _height: u32,
+    region: HudRect,
+    time: f32,
     x_offset: usize,
     buffer_width: u32,
 ) {
-    if let Some(stats_arc) = get_algorithm_stats() {
-        if let Ok(stats_map) = stats_arc.lock() {
-            // Collect and sort algorithms by completion count
-            let mut stats_vec: Vec<(SortAlgorithm, u32)> = stats_map
-                .iter()
-                .map(|(alg, &cnt)| (alg.clone(), cnt))
-                .collect();
-            stats_vec.sort_by(|a, b| b.1.cmp(&a.1));
-            // Only keep the top 4 algorithms for display
-            stats_vec.truncate(4);
-
-            let char_width = 8;
-            let char_height = 12;
-            let _padding = 4;
-            let stats_x = _padding;
-            let stats_y = 10u32;
-
-            // Calculate background dimensions based on longest text
-            let max_len = stats_vec
-                .iter()
-                .map(|(alg, count)| format!("{}: {}", alg.name(), count).len())
-                .max()
-                .unwrap_or(0) as u32;
-            let bg_width = max_len * char_width + _padding * 2;
-            let bg_height = (char_height + 2) * stats_vec.len() as u32 + _padding * 2;
+    let Some(stats_vec) = leaderboard_entries() else {
+        return;
+    };
+    let char_height = STATS_CHAR_HEIGHT;
+    let _padding = STATS_PADDING;
+    let stats_x = region.x as u32 + _padding;
+    let stats_y = region.y as u32 + _padding;
 
-            // Draw background for leaderboard
-            draw_background_rect(
-                frame,
-                stats_x - _padding,
-                stats_y - _padding,
-                bg_width,
-                bg_height,
-                [0, 0, 0, 180],
-                width,
-                x_offset,
-                buffer_width,
-            );
+    // Calculate background dimensions based on longest text
+    let bg_width = leaderboard_width(&stats_vec);
+    let bg_height = (char_height + 2) * stats_vec.len() as u32 + _padding * 2;
 
-            // Draw each algorithm entry
-            for (i, (alg, count)) in stats_vec.iter().enumerate() {
-                let entry_text = format!("{}: {}", alg.name(), count);
-                let text_y = stats_y + i as u32 * (char_height + 2);
-                draw_stats_text(
-                    frame,
-                    &entry_text,
-                    stats_x,
-                    text_y,
-                    [255, 255, 255, 255],
-                    width,
-                    x_offset,
-                    buffer_width,
-                );
-            }
+    // Draw background for leaderboard
+    draw_background_rect(
+        frame,
+        stats_x - _padding,
+        stats_y - _padding,
+        bg_width,
+        bg_height,
+        hud_backing(STATS_BACKGROUND),
+        width,
+        x_offset,
+        buffer_width,
+    );
 
-            // Draw corner hits below leaderboard
-            let corner_hits = detect_corner::get_corner_hits();
-            let corner_text = format!("{} corner hits", corner_hits);
-            let corner_y = stats_y + (stats_vec.len() as u32 * (char_height + 2)) + _padding;
-            let ct_height = char_height;
+    // Draw each algorithm entry
+    for (i, (alg, count)) in stats_vec.iter().enumerate() {
+        let entry_text = format!("{}: {}", alg.name(), count);
+        let text_y = stats_y + i as u32 * (char_height + 2);
+        let flash = leaderboard_flash(alg, time);
+        if flash > 0.0 {
+            let mut highlight = color_to_rgba(alg.color());
+            highlight[3] = (flash * 200.0) as u8;
             draw_background_rect(
                 frame,
                 stats_x - _padding,
-                corner_y - _padding,
+                text_y - 1,
                 bg_width,
-                ct_height + _padding * 2,
-                [0, 0, 0, 180],
-                width,
-                x_offset,
-                buffer_width,
-            );
-            draw_stats_text(
-                frame,
-                &corner_text,
-                stats_x,
-                corner_y,
-                [255, 255, 255, 255],
+                char_height + 2,
+                highlight,
                 width,
                 x_offset,
                 buffer_width,
             );
         }
+        draw_stats_text(
+            frame,
+            &entry_text,
+            stats_x,
+            text_y,
+            [255, 255, 255, 255],
+            width,
+            x_offset,
+            buffer_width,
+        );
     }
+
+    // Draw corner hits below leaderboard
+    let corner_hits = detect_corner::get_corner_hits();
+    let corner_text = format!("{} corner hits", corner_hits);
+    let corner_y = stats_y + (stats_vec.len() as u32 * (char_height + 2)) + _padding;
+    let ct_height = char_height;
+    draw_background_rect(
+        frame,
+        stats_x - _padding,
+        corner_y - _padding,
+        bg_width,
+        ct_height + _padding * 2,
+        hud_backing(STATS_BACKGROUND),
+        width,
+        x_offset,
+        buffer_width,
+    );
+    draw_stats_text(
+        frame,
+        &corner_text,
+        stats_x,
+        corner_y,
+        [255, 255, 255, 255],
+        width,
+        x_offset,
+        buffer_width,
+    );
+
+    // Input pattern the sorters are started from
+    let pattern_text = input_pattern_text();
+    let pattern_y = corner_y + ct_height + _padding * 2;
+    let pattern_width = (pattern_text.len() as u32 * STATS_CHAR_WIDTH + _padding * 2).max(bg_width);
+    draw_background_rect(
+        frame,
+        stats_x - _padding,
+        pattern_y - _padding,
+        pattern_width,
+        ct_height + _padding * 2,
+        hud_backing(STATS_BACKGROUND),
+        width,
+        x_offset,
+        buffer_width,
+    );
+    draw_stats_text(
+        frame,
+        &pattern_text,
+        stats_x,
+        pattern_y,
+        [255, 255, 255, 255],
+        width,
+        x_offset,
+        buffer_width,
+    );
 }
 
 fn draw_background_rect(
@@ -298,6 +1231,19 @@ fn draw_stats_text(
     let char_height = 12;
     let _padding = 4;
 
+    // Swap to the theme's high-contrast color where the backing doesn't set the text off
+    let color = sample_luminance(
+        frame,
+        buffer_width,
+        (x as usize + x_offset) as i32,
+        y as i32,
+        text.chars().count() as u32 * char_width,
+        char_height,
+    )
+    .map_or(color, |background| {
+        readable_text_color(color, background, &current_theme())
+    });
+
     // Draw each character in the text
     for (i, ch) in text.chars().enumerate() {
         let char_x = x + (i as u32 * char_width);
@@ -598,6 +1544,212 @@ fn get_char_pattern(ch: char) -> Vec<u8> {
             0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
             0, 0, 0, 0, 0, 0, 0, 0, 0,
         ],
+        '%' => vec![
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 0, 0, 0, 1, 0, 0, 1, 1, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0,
+            1, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0,
+            0, 1, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 1, 1, 0, 0, 1, 0, 0, 0, 1, 1,
+            0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ],
         _ => vec![1; 96], // Default to a block for undefined characters
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_captions_stay_in_their_edge_strip() {
+        let text = "Insertion 100% 123456";
+        let sizes = [
+            (640, 360),
+            (800, 600),
+            (1280, 720),
+            (1920, 1080),
+            (3840, 2160),
+        ];
+        for (width, height) in sizes {
+            for (edge, region) in edge_regions(width, height, EdgeThickness::DEFAULT) {
+                let layout = caption_layout(edge, region, text).unwrap();
+                let (w, h) = layout.size();
+                assert!(
+                    region.contains(layout.x, layout.y, w, h),
+                    "{edge:?} {width}x{height}"
+                );
+
+                // Clear of the bars, which keep the rest of the region
+                let bars = bar_region(edge, region);
+                let overlaps = layout.x < bars.x + bars.width
+                    && bars.x < layout.x + w
+                    && layout.y < bars.y + bars.height
+                    && bars.y < layout.y + h;
+                assert!(!overlaps, "{edge:?} {width}x{height}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_edge_thickness_sizes_the_strips() {
+        let [top, bottom, left, right] =
+            edge_regions(1000, 600, EdgeThickness::DEFAULT).map(|(_, region)| region);
+        assert_eq!((top.height, bottom.y, bottom.height), (30, 570, 30));
+        assert_eq!((left.y, left.width, left.height), (30, 150, 540));
+        assert_eq!(right.x, 850);
+
+        // Thinner sides leave the borders alone; scaling grows both
+        let thin = EdgeThickness {
+            side: 0.05,
+            ..EdgeThickness::DEFAULT
+        };
+        let [thin_top, _, thin_left, thin_right] =
+            edge_regions(1000, 600, thin).map(|(_, region)| region);
+        assert_eq!(thin_top, top);
+        assert_eq!((thin_left.width, thin_right.x), (50, 950));
+        let [big_top, _, big_left, _] =
+            edge_regions(1000, 600, EdgeThickness::DEFAULT.scaled(2.0, 2.0))
+                .map(|(_, region)| region);
+        assert_eq!((big_top.height, big_left.width), (60, 300));
+
+        // Strips never grow past half the frame
+        let huge = EdgeThickness {
+            border: 0.9,
+            side: 0.9,
+            scale: 1.0,
+        };
+        let [_, bottom, left, right] = edge_regions(100, 100, huge).map(|(_, region)| region);
+        assert_eq!((bottom.y, left.height, right.x), (50, 0, 50));
+    }
+
+    #[test]
+    fn test_completion_burst_runs_along_the_finished_edge() {
+        let sorted: Vec<u8> = (0..100).map(|i| (i * 255 / 99) as u8).collect();
+        for (edge, region) in edge_regions(1000, 600, EdgeThickness::DEFAULT) {
+            let points = completion_points(&sorted, edge, region);
+            assert_eq!(points.len(), COMPLETION_SAMPLES, "{:?}", edge);
+            for &(x, y) in &points {
+                assert!(
+                    region.contains(x as usize, y as usize, 0, 0),
+                    "{:?} {:?} outside {:?}",
+                    edge,
+                    (x, y),
+                    region
+                );
+            }
+            // Spread along the edge, following the sorted bars' rising tips
+            // away from the screen border
+            let (along, depth): (Vec<f32>, Vec<f32>) = points
+                .iter()
+                .map(|&(x, y)| match edge {
+                    SorterEdge::Top => (x, y),
+                    SorterEdge::Bottom => (x, 600.0 - y),
+                    SorterEdge::Left => (y, x),
+                    SorterEdge::Right => (y, 1000.0 - x),
+                })
+                .unzip();
+            assert!(along.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", edge);
+            assert!(
+                depth.windows(2).all(|pair| pair[0] <= pair[1]),
+                "{:?}",
+                edge
+            );
+            let length = match edge {
+                SorterEdge::Top | SorterEdge::Bottom => region.width,
+                SorterEdge::Left | SorterEdge::Right => region.height,
+            };
+            assert!(along[COMPLETION_SAMPLES - 1] - along[0] > length as f32 / 2.0);
+        }
+        let top = edge_regions(1000, 600, EdgeThickness::DEFAULT)[0].1;
+        assert!(completion_points(&[], SorterEdge::Top, top).is_empty());
+    }
+
+    #[test]
+    fn test_ring_runs_clockwise_around_the_border() {
+        let regions = edge_regions(1000, 600, EdgeThickness::DEFAULT);
+        let slot = |index: usize| {
+            let edge = RING_EDGES[index / RING_QUARTER];
+            let &(_, region) = regions.iter().find(|(e, _)| *e == edge).unwrap();
+            (edge, region, ring_slot_rect(index, region))
+        };
+        for index in 0..RING_SIZE {
+            let (_, region, rect) = slot(index);
+            assert!(
+                region.contains(rect.x, rect.y, rect.width, rect.height),
+                "{}",
+                index
+            );
+        }
+        // Ends of each quarter sit at the corners they share with the next
+        let (_, top, first) = slot(0);
+        assert_eq!((first.x, first.y), (top.x, top.y));
+        let (_, _, top_end) = slot(99);
+        assert_eq!(top_end.x + top_end.width, 1000);
+        let (_, right, right_start) = slot(100);
+        assert_eq!(right_start.y, right.y);
+        assert_eq!(right_start.y, top.y + top.height);
+        let (_, _, right_end) = slot(199);
+        assert_eq!(right_end.y + right_end.height, right.y + right.height);
+        let (_, _, bottom_start) = slot(200);
+        assert_eq!(bottom_start.x + bottom_start.width, 1000);
+        let (_, _, bottom_end) = slot(299);
+        assert_eq!(bottom_end.x, 0);
+        let (_, left, left_start) = slot(300);
+        assert_eq!(left_start.y + left_start.height, left.y + left.height);
+        let (_, _, last) = slot(399);
+        assert_eq!(last.y, left.y);
+
+        // Within a quarter, neighbours step the way the ring turns
+        for index in 0..RING_SIZE - 1 {
+            if (index + 1) % RING_QUARTER == 0 {
+                continue;
+            }
+            let (edge, _, a) = slot(index);
+            let (_, _, b) = slot(index + 1);
+            let stepped = match edge {
+                SorterEdge::Top => b.x > a.x,
+                SorterEdge::Right => b.y > a.y,
+                SorterEdge::Bottom => b.x < a.x,
+                SorterEdge::Left => b.y < a.y,
+            };
+            assert!(stepped, "{} to {}", index, index + 1);
+        }
+    }
+
+    #[test]
+    fn test_ring_bars_grow_in_from_the_border() {
+        let slot = EdgeRegion {
+            x: 10,
+            y: 20,
+            width: 8,
+            height: 40,
+        };
+        let bottom = ring_bar_rect(SorterEdge::Bottom, slot, 128, 0.0);
+        assert_eq!((bottom.y, bottom.height), (40, 20));
+        let right = ring_bar_rect(SorterEdge::Right, EdgeRegion { width: 40, ..slot }, 64, 0.0);
+        assert_eq!((right.x, right.width), (40, 10));
+        // A passing pulse stretches a short bar to the full depth
+        let top = ring_bar_rect(SorterEdge::Top, slot, 0, 1.0);
+        assert_eq!((top.y, top.height), (20, 40));
+
+        assert_eq!(ring_pulse(0, RING_PULSE_SECONDS), 0.0);
+        assert!(ring_pulse(200, RING_PULSE_SECONDS / 2.0) > 0.0);
+        assert_eq!(ring_pulse(399, 0.0), 0.0);
+    }
+
+    #[test]
+    fn test_caption_truncates_or_skips_small_regions() {
+        let region = EdgeRegion {
+            x: 0,
+            y: 0,
+            width: 40,
+            height: 30,
+        };
+        let layout = caption_layout(SorterEdge::Top, region, "Quick 50% 10").unwrap();
+        assert_eq!(layout.text, "Quick");
+
+        let thin = EdgeRegion {
+            height: 8,
+            ..region
+        };
+        assert_eq!(caption_layout(SorterEdge::Bottom, thin, "Quick"), None);
+    }
+}
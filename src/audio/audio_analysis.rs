@@ -0,0 +1,310 @@
+use crate::audio::audio_handler::{analyze_audio, get_audio_spectrum};
+use crate::audio::sample_ring::SampleRing;
+use crate::audio::spectrum_history::SpectrumHistory;
+use log::info;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Number of most recent samples handed to each analysis pass
+pub const ANALYSIS_WINDOW: usize = 1024;
+/// Ring capacity; about a second of mono audio at 44.1kHz
+pub const RING_CAPACITY: usize = 44_100;
+/// Target analysis rate (~60Hz)
+const ANALYSIS_INTERVAL: Duration = Duration::from_millis(16);
+/// Input RMS below this counts toward silence
+pub const SILENCE_RMS: f32 = 0.003;
+/// Input RMS above this ends silence at once. Between the two thresholds the
+/// current state holds, so a fading tail doesn't flicker in and out of silence.
+pub const RESUME_RMS: f32 = 0.006;
+/// How long input must stay quiet before it counts as silent
+pub const SILENCE_HOLD: Duration = Duration::from_secs(2);
+
+/// Decides from per-tick input levels whether the input has gone silent
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SilenceDetector {
+    quiet_for: Duration,
+    silent: bool,
+}
+
+impl SilenceDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds the RMS of the input seen over the last `elapsed` and returns
+    /// whether the input is now silent
+    pub fn update(&mut self, rms: f32, elapsed: Duration) -> bool {
+        if rms > RESUME_RMS {
+            self.quiet_for = Duration::ZERO;
+            self.silent = false;
+        } else if rms < SILENCE_RMS {
+            self.quiet_for += elapsed;
+            self.silent |= self.quiet_for >= SILENCE_HOLD;
+        }
+        self.silent
+    }
+
+    pub fn is_silent(&self) -> bool {
+        self.silent
+    }
+}
+
+fn rms(samples: &[f32]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    (samples.iter().map(|&v| v * v).sum::<f32>() / samples.len() as f32).sqrt()
+}
+
+/// Background thread that drains the sample ring at ~60Hz and runs spectrum analysis,
+/// keeping analysis work out of the playback callback. Analysis is skipped while the
+/// input is silent, including when nothing is being pushed at all.
+pub struct AnalysisThread {
+    running: Arc<AtomicBool>,
+    silent: Arc<AtomicBool>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl AnalysisThread {
+    /// Spawns the thread. `analyze` is called with the latest window whenever new,
+    /// non-silent samples arrive.
+    pub fn spawn<F>(ring: Arc<SampleRing>, mut analyze: F) -> Self
+    where
+        F: FnMut(&[f32]) + Send + 'static,
+    {
+        let running = Arc::new(AtomicBool::new(true));
+        let thread_running = running.clone();
+        let silent = Arc::new(AtomicBool::new(false));
+        let thread_silent = silent.clone();
+        let handle = thread::spawn(move || {
+            let mut window = Vec::with_capacity(ANALYSIS_WINDOW);
+            let mut incoming = Vec::new();
+            let mut detector = SilenceDetector::new();
+            let mut last_tick = Instant::now();
+            while thread_running.load(Ordering::SeqCst) {
+                let started = Instant::now();
+                incoming.clear();
+                let drained = ring.drain_into(&mut incoming);
+                let is_silent = detector.update(rms(&incoming), started - last_tick);
+                last_tick = started;
+                thread_silent.store(is_silent, Ordering::SeqCst);
+                if drained > 0 {
+                    push_window(&mut window, &incoming, ANALYSIS_WINDOW);
+                    if !is_silent {
+                        analyze(&window);
+                    }
+                }
+                if let Some(remaining) = ANALYSIS_INTERVAL.checked_sub(started.elapsed()) {
+                    thread::sleep(remaining);
+                }
+            }
+        });
+        Self {
+            running,
+            silent,
+            handle: Some(handle),
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    /// Whether the input has been quiet for at least `SILENCE_HOLD`
+    pub fn is_silent(&self) -> bool {
+        self.silent.load(Ordering::SeqCst)
+    }
+
+    /// Signals the thread to exit and waits for it
+    pub fn stop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for AnalysisThread {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Appends `incoming` to `window`, keeping only the newest `size` samples
+fn push_window(window: &mut Vec<f32>, incoming: &[f32], size: usize) {
+    window.extend_from_slice(incoming);
+    if window.len() > size {
+        let excess = window.len() - size;
+        window.drain(..excess);
+    }
+}
+
+static SAMPLE_RING: OnceLock<Arc<SampleRing>> = OnceLock::new();
+static ANALYSIS_THREAD: Mutex<Option<AnalysisThread>> = Mutex::new(None);
+/// Every spectrum the thread publishes, timestamped, for delayed visuals
+static SPECTRUM_HISTORY: Mutex<SpectrumHistory> = Mutex::new(SpectrumHistory::new());
+
+fn analysis_thread() -> MutexGuard<'static, Option<AnalysisThread>> {
+    ANALYSIS_THREAD.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Starts the shared analysis thread if needed and returns the ring producers should push into.
+/// Results go to whichever spectrum handle is current, so audio restarts keep working.
+pub fn ensure_analysis_thread() -> Arc<SampleRing> {
+    let ring = SAMPLE_RING
+        .get_or_init(|| Arc::new(SampleRing::new(RING_CAPACITY)))
+        .clone();
+    let mut analysis = analysis_thread();
+    if analysis.is_none() {
+        *analysis = Some(AnalysisThread::spawn(ring.clone(), |window| {
+            if let Some(spectrum) = get_audio_spectrum() {
+                analyze_audio(window, spectrum.clone());
+                if let (Ok(data), Ok(mut history)) = (spectrum.lock(), SPECTRUM_HISTORY.lock()) {
+                    history.push(Instant::now(), &data);
+                }
+            }
+        }));
+    }
+    ring
+}
+
+/// The published spectrum analyzed closest to `target`, if any have been kept
+pub fn spectrum_at(target: Instant) -> Option<Vec<f32>> {
+    SPECTRUM_HISTORY
+        .lock()
+        .ok()
+        .and_then(|history| history.closest(target).map(<[f32]>::to_vec))
+}
+
+/// Whether the shared analysis thread has found its input silent. False when
+/// the thread isn't running, so nothing goes quiet before audio is set up.
+pub fn is_silent() -> bool {
+    analysis_thread()
+        .as_ref()
+        .is_some_and(|analysis| analysis.is_silent())
+}
+
+/// Stops the analysis thread; called on application exit
+pub fn shutdown_analysis_thread() {
+    // Taken out first so the lock isn't held while the thread is joined
+    let analysis = analysis_thread().take();
+    if let Some(mut analysis) = analysis {
+        analysis.stop();
+        info!("Audio analysis thread stopped");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn test_push_window_keeps_newest_samples() {
+        let mut window = Vec::new();
+        push_window(&mut window, &[1.0, 2.0, 3.0], 4);
+        assert_eq!(window, vec![1.0, 2.0, 3.0]);
+        push_window(&mut window, &[4.0, 5.0, 6.0], 4);
+        assert_eq!(window, vec![3.0, 4.0, 5.0, 6.0]);
+    }
+
+    #[test]
+    fn test_silence_detector_hysteresis() {
+        let tick = Duration::from_millis(16);
+        let mut detector = SilenceDetector::new();
+
+        // Quiet input only becomes silence after the hold time
+        let mut elapsed = Duration::ZERO;
+        while elapsed + tick < SILENCE_HOLD {
+            assert!(!detector.update(0.0, tick));
+            elapsed += tick;
+        }
+        assert!(detector.update(0.0, tick));
+
+        // Levels between the thresholds keep whatever state we're in
+        let between = (SILENCE_RMS + RESUME_RMS) / 2.0;
+        for _ in 0..200 {
+            assert!(detector.update(between, tick));
+        }
+
+        // One loud buffer resumes immediately and restarts the hold
+        assert!(!detector.update(0.1, tick));
+        assert!(!detector.update(between, SILENCE_HOLD * 2));
+        assert!(!detector.update(0.0, SILENCE_HOLD / 2));
+        assert!(detector.update(0.0, SILENCE_HOLD / 2));
+    }
+
+    #[test]
+    fn test_silent_input_skips_analysis() {
+        let ring = Arc::new(SampleRing::new(RING_CAPACITY));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let thread_calls = calls.clone();
+        let mut analysis = AnalysisThread::spawn(ring.clone(), move |_| {
+            thread_calls.fetch_add(1, Ordering::SeqCst);
+        });
+        assert!(!analysis.is_silent());
+
+        // Nothing pushed for longer than the hold time
+        thread::sleep(SILENCE_HOLD + Duration::from_millis(200));
+        assert!(analysis.is_silent());
+        for _ in 0..1024 {
+            ring.push(0.0);
+        }
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+        // Music comes back within a couple of ticks
+        for i in 0..1024 {
+            ring.push((i as f32 * 0.1).sin() * 0.5);
+        }
+        thread::sleep(Duration::from_millis(100));
+        assert!(!analysis.is_silent());
+        assert!(calls.load(Ordering::SeqCst) >= 1);
+        analysis.stop();
+    }
+
+    #[test]
+    fn test_slow_analysis_does_not_block_producer() {
+        let ring = Arc::new(SampleRing::new(RING_CAPACITY));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let thread_calls = calls.clone();
+        let mut analysis = AnalysisThread::spawn(ring.clone(), move |window| {
+            assert!(window.len() <= ANALYSIS_WINDOW);
+            thread_calls.fetch_add(1, Ordering::SeqCst);
+            // Artificially slow analysis
+            thread::sleep(Duration::from_millis(50));
+        });
+
+        // The producer pushes a few "callbacks" worth of samples without waiting on analysis
+        let started = Instant::now();
+        for _ in 0..5 {
+            for i in 0..1024 {
+                ring.push(i as f32 / 1024.0);
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        let producer_time = started.elapsed();
+        assert!(producer_time < Duration::from_millis(500));
+
+        analysis.stop();
+        assert!(calls.load(Ordering::SeqCst) >= 1);
+    }
+
+    #[test]
+    fn test_stop_shuts_down_thread() {
+        let ring = Arc::new(SampleRing::new(16));
+        let mut analysis = AnalysisThread::spawn(ring, |_| {});
+        assert!(analysis.is_running());
+
+        let started = Instant::now();
+        analysis.stop();
+        assert!(!analysis.is_running());
+        assert!(analysis.handle.is_none());
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        // Stopping twice is harmless
+        analysis.stop();
+    }
+}
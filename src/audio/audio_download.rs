@@ -1,26 +1,85 @@
 use crate::audio::download_progress::show_download_progress;
+use log::{info, warn};
 use rodio::{Decoder, OutputStream, Sink};
 use std::io::BufReader;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
 // Configuration constants
 const AUDIO_FILENAME: &str = "foregone_destruction_remastered.flac";
-const AUDIO_URL: &str = "https://dn721905.ca.archive.org/0/items/unreal-tournament-ost-remastered/Unreal%20Tournament%20OST%20%28Remastered%29/10%20-%20Michiel%20van%20den%20Bos%20-%20Foregone%20Destruction%20%28Remastered%29.flac";
+pub const DEFAULT_AUDIO_URL: &str = "https://dn721905.ca.archive.org/0/items/unreal-tournament-ost-remastered/Unreal%20Tournament%20OST%20%28Remastered%29/10%20-%20Michiel%20van%20den%20Bos%20-%20Foregone%20Destruction%20%28Remastered%29.flac";
 const OLD_AUDIO_FILES: &[&str] = &["shizuo_tribute_mix.flac", "botpack_9_michiel.mp3"];
 // Expected file size range (approximately 50-80 MB for a high-quality FLAC file)
 const MIN_EXPECTED_FILE_SIZE: u64 = 50_000_000;  // 50 MB
 const MAX_EXPECTED_FILE_SIZE: u64 = 100_000_000; // 100 MB
 
-pub async fn ensure_audio_file() -> Result<PathBuf, Box<dyn std::error::Error>> {
-    let audio_dir = dirs::data_dir()
+/// Fetches the soundtrack to a file. The app downloads over HTTP behind a
+/// progress window; tests substitute one that never touches the network.
+pub trait Downloader {
+    fn download(&mut self, url: &str, path: &Path) -> Result<(), Box<dyn std::error::Error>>;
+}
+
+/// Downloads with reqwest, showing progress in a utility window
+pub struct ProgressWindowDownloader;
+
+impl Downloader for ProgressWindowDownloader {
+    fn download(&mut self, url: &str, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        show_download_progress(url, &path.to_path_buf())?;
+        Ok(())
+    }
+}
+
+static DOWNLOADER: Mutex<Option<Box<dyn Downloader + Send>>> = Mutex::new(None);
+static AUDIO_URL: Mutex<Option<String>> = Mutex::new(None);
+
+/// Replaces how the soundtrack is fetched
+pub fn set_downloader(downloader: Box<dyn Downloader + Send>) {
+    *DOWNLOADER.lock().unwrap() = Some(downloader);
+}
+
+/// Downloads the soundtrack from `url` instead of the default source, e.g. a
+/// self-hosted copy. None goes back to the default.
+pub fn set_audio_url(url: Option<String>) {
+    *AUDIO_URL.lock().unwrap() = url;
+}
+
+pub fn audio_url() -> String {
+    AUDIO_URL
+        .lock()
+        .unwrap()
+        .clone()
+        .unwrap_or_else(|| DEFAULT_AUDIO_URL.to_string())
+}
+
+/// Where the soundtrack is kept
+pub fn audio_dir() -> PathBuf {
+    dirs::data_dir()
         .unwrap_or_else(|| std::env::current_dir().unwrap())
-        .join("stimstation");
+        .join("stimstation")
+}
 
+/// Finds the soundtrack in the data directory, downloading it from `audio_url`
+/// if it isn't there. Fails without fetching anything while audio is disabled.
+pub async fn ensure_audio_file() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    if !crate::audio::audio_enabled() {
+        return Err("audio is disabled".into());
+    }
+    let mut downloader = DOWNLOADER.lock().unwrap();
+    let downloader = downloader.get_or_insert_with(|| Box::new(ProgressWindowDownloader));
+    ensure_audio_file_in(&audio_dir(), &audio_url(), downloader.as_mut())
+}
+
+/// `ensure_audio_file` for the soundtrack in `audio_dir`, fetched from `url`
+pub fn ensure_audio_file_in(
+    audio_dir: &Path,
+    url: &str,
+    downloader: &mut dyn Downloader,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
     let target_audio_path = audio_dir.join(AUDIO_FILENAME);
 
     // Check if the target file exists and is valid
     if target_audio_path.exists() && is_valid_audio_file(&target_audio_path)? {
-        println!("Correct audio file found, loading...");
+        info!("Correct audio file found, loading...");
         return Ok(target_audio_path);
     }
 
@@ -29,7 +88,7 @@ pub async fn ensure_audio_file() -> Result<PathBuf, Box<dyn std::error::Error>>
         for old_file in OLD_AUDIO_FILES {
             let old_path = audio_dir.join(old_file);
             if old_path.exists() {
-                println!("Removing old audio file: {}", old_file);
+                info!("Removing old audio file: {}", old_file);
                 std::fs::remove_file(old_path)?;
             }
         }
@@ -37,14 +96,14 @@ pub async fn ensure_audio_file() -> Result<PathBuf, Box<dyn std::error::Error>>
 
     // Download the new file to a temporary location first
     let temp_path = target_audio_path.with_extension("tmp");
-    println!("Starting audio file download with progress window...");
-    show_download_progress(AUDIO_URL, &temp_path)?;
+    info!("Starting audio file download with progress window...");
+    downloader.download(url, &temp_path)?;
 
     // Verify the downloaded file
     if is_valid_audio_file(&temp_path)? {
         // Atomically move the temporary file to the final location
         std::fs::rename(&temp_path, &target_audio_path)?;
-        println!("Audio file downloaded and verified successfully!");
+        info!("Audio file downloaded and verified successfully!");
     } else {
         // Clean up the invalid temporary file
         let _ = std::fs::remove_file(&temp_path);
@@ -63,7 +122,7 @@ fn is_valid_audio_file(path: &std::path::Path) -> Result<bool, Box<dyn std::erro
     let metadata = std::fs::metadata(path)?;
     let file_size = metadata.len();
     if file_size < MIN_EXPECTED_FILE_SIZE || file_size > MAX_EXPECTED_FILE_SIZE {
-        println!(
+        warn!(
             "File size {} bytes is outside expected range ({} - {} bytes)",
             file_size, MIN_EXPECTED_FILE_SIZE, MAX_EXPECTED_FILE_SIZE
         );
@@ -93,3 +152,41 @@ pub fn setup_audio(
 
     Ok((_stream, sink))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes a few bytes instead of fetching, remembering the URLs asked for
+    struct FakeDownloader {
+        urls: Vec<String>,
+    }
+
+    impl Downloader for FakeDownloader {
+        fn download(&mut self, url: &str, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+            self.urls.push(url.to_string());
+            std::fs::write(path, b"not a flac")?;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_missing_file_is_fetched_from_the_given_url() {
+        let dir = std::env::temp_dir().join(format!("stimstation-download-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut downloader = FakeDownloader { urls: Vec::new() };
+        let url = "http://localhost/soundtrack.flac";
+
+        // Too small to be the soundtrack, so it's rejected and cleaned up
+        assert!(ensure_audio_file_in(&dir, url, &mut downloader).is_err());
+        assert_eq!(downloader.urls, [url]);
+        assert!(!dir.join(AUDIO_FILENAME).with_extension("tmp").exists());
+        assert!(!dir.join(AUDIO_FILENAME).exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        set_audio_url(Some(url.to_string()));
+        assert_eq!(audio_url(), url);
+        set_audio_url(None);
+        assert_eq!(audio_url(), DEFAULT_AUDIO_URL);
+    }
+}
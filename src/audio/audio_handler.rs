@@ -1,21 +1,192 @@
+use crate::core::presets::{lerp, Interpolate};
+use crate::core::sim_rng::sim_rng;
+use crate::graphics::draw_ctx::{DrawCtx, Region};
 use rand::prelude::*;
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
 
-pub const AUDIO_VIZ_BARS: usize = 64; // Doubled from 32 to 64 for more expressiveness
+pub const AUDIO_VIZ_BARS: usize = 64; // Spectrum bands; the bars drawn are re-binned from them
 pub const AUDIO_VIZ_BASE_HEIGHT: f32 = 80.0; // Increased base height for more dramatic effect
 pub const AUDIO_VIZ_MIN_HEIGHT: f32 = 3.0; // Reduced minimum height for more dynamic range
-pub const AUDIO_VIZ_DECAY_RATE: f32 = 3.0; // Increased decay rate for more responsive bars
 
-static mut AUDIO_SPECTRUM: Option<Arc<Mutex<Vec<f32>>>> = None;
+/// The shared spectrum handle. The analysis thread publishes into the
+/// handle's own lock; this one only guards which handle is current.
+static AUDIO_SPECTRUM: Mutex<Option<Arc<Mutex<Vec<f32>>>>> = Mutex::new(None);
+static BAR_ENVELOPE: Mutex<BarEnvelope> = Mutex::new(BarEnvelope::DEFAULT);
+static DEMO_BARS: AtomicBool = AtomicBool::new(false);
+
+/// Seconds between new noise values in the simulated bars
+const SIMULATED_NOISE_INTERVAL: f32 = 0.05;
+/// Bars are laid out between these widths, in pixels, in any region at
+/// least `MIN_BAR_WIDTH` wide, and as near `TARGET_BAR_WIDTH` as whole bars allow
+pub const MIN_BAR_WIDTH: usize = 8;
+pub const MAX_BAR_WIDTH: usize = 16;
+const TARGET_BAR_WIDTH: usize = 12;
+/// When the bar count changes the old bars fade out and the new ones in over
+/// this long, in seconds
+pub const RELAYOUT_SECONDS: f32 = 0.2;
+/// Peak dots hold this long, in seconds, then fall this fast, in bar height
+/// units per second
+const PEAK_HOLD_SECONDS: f32 = 0.6;
+const PEAK_FALL_SPEED: f32 = 60.0;
+/// Gap between a bar's top and its peak dot, and the dot's height, in pixels
+const PEAK_GAP: usize = 3;
+const PEAK_DOT_HEIGHT: usize = 2;
+/// Gap under the baseline, at most a quarter of the region's height
+const BASELINE_MARGIN: u32 = 50;
+
+/// Where the bars sit in a region, in region pixels: how many there are, how
+/// wide each is, the column of the first, and the row they stand on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BarLayout {
+    pub count: usize,
+    pub bar_width: usize,
+    pub left: usize,
+    pub baseline: usize,
+}
+
+impl BarLayout {
+    /// As many bars as make each about `TARGET_BAR_WIDTH` wide, kept within
+    /// `MIN_BAR_WIDTH..=MAX_BAR_WIDTH`, centered across the region. A region
+    /// narrower than `MIN_BAR_WIDTH` gets one bar as wide as it is.
+    pub fn for_region(region: Region) -> Self {
+        let width = region.width as usize;
+        let nearest = (width + TARGET_BAR_WIDTH / 2) / TARGET_BAR_WIDTH;
+        let fewest = width / (MAX_BAR_WIDTH + 1) + 1;
+        let most = (width / MIN_BAR_WIDTH).max(1);
+        let count = nearest.clamp(fewest.min(most), most);
+        let bar_width = (width / count).max(1);
+        Self {
+            count,
+            bar_width,
+            left: width.saturating_sub(count * bar_width) / 2,
+            baseline: (region.height - BASELINE_MARGIN.min(region.height / 4)) as usize,
+        }
+    }
+}
+
+/// Resamples `levels` into `count` bands, fewer or more. Each band is the
+/// mean of the levels under its span, weighted by how much of each it covers,
+/// so the total energy, every band times its span, is the levels' total.
+pub fn rebin_bands(levels: &[f32], count: usize) -> Vec<f32> {
+    if levels.is_empty() {
+        return vec![0.0; count];
+    }
+    // Levels per band, possibly fractional
+    let span = levels.len() as f32 / count as f32;
+    (0..count)
+        .map(|i| {
+            let (start, end) = (i as f32 * span, (i + 1) as f32 * span);
+            let first = start.floor() as usize;
+            let last = (end.ceil() as usize).min(levels.len());
+            let sum: f32 = (first..last)
+                .map(|j| {
+                    let covered = end.min(j as f32 + 1.0) - start.max(j as f32);
+                    levels[j] * covered.max(0.0)
+                })
+                .sum();
+            sum / span
+        })
+        .collect()
+}
+
+/// Peak dots for a layout of `count` bars, carried over from `peaks` and
+/// their hold timers in another layout: each new bar takes the dot of the old
+/// bar nearest its middle, lifted to at least its own `levels` so no dot ends
+/// up inside a bar
+pub fn migrate_peaks(
+    peaks: &[f32],
+    timers: &[f32],
+    levels: &[f32],
+    count: usize,
+) -> (Vec<f32>, Vec<f32>) {
+    (0..count)
+        .map(|i| {
+            let level = levels.get(i).copied().unwrap_or(0.0);
+            if peaks.is_empty() {
+                return (level, 0.0);
+            }
+            let nearest = ((2 * i + 1) * peaks.len() / (2 * count)).min(peaks.len() - 1);
+            let timer = timers.get(nearest).copied().unwrap_or(0.0);
+            (peaks[nearest].max(level), timer)
+        })
+        .unzip()
+}
+
+/// How fast the bars follow their target: time constants, in seconds, for
+/// rising (attack) and falling (release). Applied per second of elapsed time,
+/// so bars move the same at any frame rate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BarEnvelope {
+    pub attack: f32,
+    pub release: f32,
+}
+
+impl BarEnvelope {
+    pub const DEFAULT: Self = Self {
+        attack: 0.03,
+        release: 0.25,
+    };
+
+    /// Keeps both time constants within 1 ms..=5 s
+    pub fn clamped(self) -> Self {
+        Self {
+            attack: self.attack.clamp(0.001, 5.0),
+            release: self.release.clamp(0.001, 5.0),
+        }
+    }
+
+    /// Moves `current` toward `target` over `dt` seconds
+    pub fn step(&self, current: f32, target: f32, dt: f32) -> f32 {
+        smooth_toward(current, target, dt, 1.0 / self.attack, 1.0 / self.release)
+    }
+}
+
+impl Interpolate for BarEnvelope {
+    fn interpolate(&self, to: &Self, t: f32) -> Self {
+        Self {
+            attack: lerp(self.attack, to.attack, t),
+            release: lerp(self.release, to.release, t),
+        }
+    }
+}
+
+pub fn bar_envelope() -> BarEnvelope {
+    *BAR_ENVELOPE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+pub fn set_bar_envelope(envelope: BarEnvelope) {
+    *BAR_ENVELOPE.lock().unwrap_or_else(PoisonError::into_inner) = envelope.clamped();
+}
+
+/// Whether the bars animate a simulated pattern when there is no audio,
+/// instead of resting flat
+pub fn demo_bars() -> bool {
+    DEMO_BARS.load(Ordering::SeqCst)
+}
+
+pub fn set_demo_bars(enabled: bool) {
+    DEMO_BARS.store(enabled, Ordering::SeqCst);
+}
 
 pub struct AudioVisualizer {
     spectrum: Vec<f32>,
     target_heights: Vec<f32>,
     current_heights: Vec<f32>,
-    peak_heights: Vec<f32>,   // Track peak heights for falling dots effect
-    peak_timers: Vec<f32>,    // Timers for peak dots
+    /// Peak dot heights and hold timers, one per bar of `layout`
+    peak_heights: Vec<f32>,
+    peak_timers: Vec<f32>,
     bar_velocities: Vec<f32>, // Velocity for more dynamic movement
     last_update: f32,
+    // Simulated bars advance with elapsed dt, so they freeze when time does
+    simulated_phase: f32,
+    simulated_noise: Vec<f32>,
+    noise_timer: f32,
+    /// No audio and no demo pattern: the bars settle flat and skip their per-bar noise
+    resting: bool,
+    /// Bars as last drawn, and the layout being faded out with when it took over
+    layout: Option<BarLayout>,
+    previous: Option<(BarLayout, f32)>,
 }
 
 impl AudioVisualizer {
@@ -23,16 +194,12 @@ impl AudioVisualizer {
         let mut spectrum = Vec::with_capacity(AUDIO_VIZ_BARS);
         let mut target_heights = Vec::with_capacity(AUDIO_VIZ_BARS);
         let mut current_heights = Vec::with_capacity(AUDIO_VIZ_BARS);
-        let mut peak_heights = Vec::with_capacity(AUDIO_VIZ_BARS);
-        let mut peak_timers = Vec::with_capacity(AUDIO_VIZ_BARS);
         let mut bar_velocities = Vec::with_capacity(AUDIO_VIZ_BARS);
 
         for _ in 0..AUDIO_VIZ_BARS {
             spectrum.push(0.0);
             target_heights.push(0.0);
             current_heights.push(0.0);
-            peak_heights.push(0.0);
-            peak_timers.push(0.0);
             bar_velocities.push(0.0);
         }
 
@@ -40,14 +207,21 @@ impl AudioVisualizer {
             spectrum,
             target_heights,
             current_heights,
-            peak_heights,
-            peak_timers,
+            peak_heights: Vec::new(),
+            peak_timers: Vec::new(),
             bar_velocities,
             last_update: 0.0,
+            simulated_phase: 0.0,
+            simulated_noise: vec![0.0; AUDIO_VIZ_BARS],
+            noise_timer: 0.0,
+            resting: false,
+            layout: None,
+            previous: None,
         }
     }
 
-    pub fn update(&mut self, time: f32, monitor_height: Option<u32>) {
+    /// Advances the bars to `time`; they grow with the monitor's `height_scale`
+    pub fn update(&mut self, time: f32, height_scale: f32) {
         let dt = if self.last_update > 0.0 {
             (time - self.last_update).min(0.1)
         } else {
@@ -55,95 +229,198 @@ impl AudioVisualizer {
         };
         self.last_update = time;
 
-        let scaled_height = monitor_height
-            .map(|h| AUDIO_VIZ_BASE_HEIGHT * (h as f32 / 1080.0))
-            .unwrap_or(AUDIO_VIZ_BASE_HEIGHT);
+        let scaled_height = AUDIO_VIZ_BASE_HEIGHT * height_scale;
 
-        let mut use_audio_data = false;
-        let mut audio_data = Vec::new();
+        let envelope = bar_envelope();
 
-        unsafe {
-            let ptr: *const Option<Arc<Mutex<Vec<f32>>>> = &AUDIO_SPECTRUM as *const _;
-            if let Some(spectrum) = (*ptr).as_ref() {
-                if let Ok(data) = spectrum.lock() {
-                    use_audio_data = true;
-                    audio_data = data.clone();
-                }
-            }
+        // Reuse the spectrum captured for this frame instead of locking it again
+        let frame_spectrum = crate::audio::features::frame_spectrum();
+        let use_audio_data = frame_spectrum.is_some();
+        let audio_data = frame_spectrum.unwrap_or_default();
+        let simulate = !use_audio_data && demo_bars();
+        self.resting = !use_audio_data && !simulate;
+        if simulate {
+            self.advance_simulation(dt);
         }
 
         for i in 0..AUDIO_VIZ_BARS {
-            let target_height = if use_audio_data && i < audio_data.len() {
-                AUDIO_VIZ_MIN_HEIGHT
-                    + audio_data[i] * (scaled_height - AUDIO_VIZ_MIN_HEIGHT)
+            let level = if use_audio_data {
+                audio_data.get(i).copied().unwrap_or(0.0)
+            } else if simulate {
+                self.simulated_level(i)
             } else {
-                let time_phase = time * 0.5;
-                let pos_factor = i as f32 / AUDIO_VIZ_BARS as f32;
-                let freq_factor = (pos_factor * 10.0).sin() * 0.5 + 0.5;
-                let time_factor =
-                    ((time_phase + pos_factor * 5.0).sin() * 0.5 + 0.5).powf(2.0);
-                let noise = rand::thread_rng().gen_range(0.0..0.2);
-                AUDIO_VIZ_MIN_HEIGHT + (time_factor * freq_factor + noise)
-                    * (scaled_height - AUDIO_VIZ_MIN_HEIGHT)
+                0.0
             };
+            let target_height =
+                AUDIO_VIZ_MIN_HEIGHT + level * (scaled_height - AUDIO_VIZ_MIN_HEIGHT);
 
             self.target_heights[i] = target_height;
-            let diff = target_height - self.current_heights[i];
-            self.current_heights[i] += diff * (1.0 - (-dt * AUDIO_VIZ_DECAY_RATE).exp());
+            self.current_heights[i] = envelope.step(self.current_heights[i], target_height, dt);
             self.spectrum[i] = self.current_heights[i] / scaled_height;
         }
+        self.update_peaks(dt);
+    }
+
+    /// Raises each bar's peak dot to its bar, or holds it and then lets it fall
+    fn update_peaks(&mut self, dt: f32) {
+        let Some(layout) = self.layout else {
+            return;
+        };
+        let levels = rebin_bands(&self.current_heights, layout.count);
+        if self.peak_heights.len() != layout.count {
+            self.migrate_peaks(layout.count);
+        }
+        for (i, level) in levels.into_iter().enumerate() {
+            let (peak, timer) = (&mut self.peak_heights[i], &mut self.peak_timers[i]);
+            if level >= *peak {
+                *peak = level;
+                *timer = PEAK_HOLD_SECONDS;
+            } else if *timer > 0.0 {
+                *timer -= dt;
+            } else {
+                *peak = (*peak - PEAK_FALL_SPEED * dt).max(level);
+            }
+        }
+    }
+
+    fn migrate_peaks(&mut self, count: usize) {
+        let levels = rebin_bands(&self.current_heights, count);
+        (self.peak_heights, self.peak_timers) =
+            migrate_peaks(&self.peak_heights, &self.peak_timers, &levels, count);
+    }
+
+    /// Makes `layout` the one drawn. A change in bar count moves the peak
+    /// dots over and starts a crossfade from the old layout at `time`.
+    fn relayout(&mut self, layout: BarLayout, time: f32) {
+        if let Some(old) = self.layout.filter(|old| old.count != layout.count) {
+            self.previous = Some((old, time));
+            self.migrate_peaks(layout.count);
+        }
+        if let Some((_, started)) = self.previous {
+            if !(started..started + RELAYOUT_SECONDS).contains(&time) {
+                self.previous = None;
+            }
+        }
+        self.layout = Some(layout);
+    }
+
+    /// Advances the simulated bars by `dt` seconds, refreshing their noise at a fixed rate
+    fn advance_simulation(&mut self, dt: f32) {
+        self.simulated_phase += dt * 0.5;
+        self.noise_timer += dt;
+        if self.noise_timer >= SIMULATED_NOISE_INTERVAL {
+            self.noise_timer %= SIMULATED_NOISE_INTERVAL;
+            let mut rng = sim_rng();
+            for noise in self.simulated_noise.iter_mut() {
+                *noise = rng.gen_range(0.0..0.2);
+            }
+        }
+    }
+
+    /// Level (0..=1.2) of simulated bar `i`, used for the demo pattern
+    fn simulated_level(&self, i: usize) -> f32 {
+        let pos_factor = i as f32 / AUDIO_VIZ_BARS as f32;
+        let freq_factor = (pos_factor * 10.0).sin() * 0.5 + 0.5;
+        let time_factor = ((self.simulated_phase + pos_factor * 5.0).sin() * 0.5 + 0.5).powf(2.0);
+        time_factor * freq_factor + self.simulated_noise[i]
     }
 
+    /// Adapter for callers still passing raw frame parameters; see `draw_bars`
     pub fn draw(
-        &self,
+        &mut self,
         frame: &mut [u8],
         width: u32,
         height: u32,
         x_offset: usize,
         buffer_width: u32,
     ) {
-        let bar_width = (width as usize) / AUDIO_VIZ_BARS;
-        let y_baseline = height as usize - 50;
+        let mut ctx = DrawCtx::from_legacy(frame, width, height, 0.0, x_offset, buffer_width);
+        self.draw_bars(&mut ctx, Region::new(0, 0, width, height));
+    }
+
+    /// Draws the bar outlines along the bottom of `target`, given relative to
+    /// the context's region. Everything, glow included, stays inside `target`.
+    /// When the region's width changes the bar count, the old bars fade out
+    /// as the new ones fade in.
+    pub fn draw_bars(&mut self, ctx: &mut DrawCtx, target: Region) {
+        let mut ctx = ctx.sub_region(target);
+        let layout = BarLayout::for_region(ctx.region);
+        // The clock `update` was given, so hosts without one still fade
+        let now = self.last_update;
+        self.relayout(layout, now);
+        let fade_in = match self.previous {
+            Some((old, started)) => {
+                let progress = ((now - started) / RELAYOUT_SECONDS).clamp(0.0, 1.0);
+                self.draw_layout(&mut ctx, old, 1.0 - progress, false);
+                progress
+            }
+            None => 1.0,
+        };
+        self.draw_layout(&mut ctx, layout, fade_in, true);
+    }
+
+    /// Draws the bars of `layout` at `opacity`, with their peak dots if `peaks`
+    fn draw_layout(&self, ctx: &mut DrawCtx, layout: BarLayout, opacity: f32, peaks: bool) {
+        let height = ctx.height();
         let time = 0.1;
+        let to_pixels = |level: f32| {
+            ((level * (height as f32 / 200.0)).max(AUDIO_VIZ_MIN_HEIGHT) as usize)
+                .min(layout.baseline)
+        };
 
-        for i in 0..AUDIO_VIZ_BARS {
-            let bar_height = (self.current_heights[i] * (height as f32 / 200.0))
-                .max(AUDIO_VIZ_MIN_HEIGHT) as usize;
-            let x_start = i * bar_width;
-            let noise = rand::thread_rng().gen_range(0.0..0.2);
-            let hue = (i as f32 / AUDIO_VIZ_BARS as f32 + time * 0.1 + noise) % 1.0;
-            let color = hsv_to_rgb(hue, 0.9, 1.0);
+        for (i, level) in rebin_bands(&self.current_heights, layout.count)
+            .into_iter()
+            .enumerate()
+        {
+            let bar_height = to_pixels(level);
+            let x_start = layout.left + i * layout.bar_width;
+            let noise = if self.resting {
+                0.0
+            } else {
+                sim_rng().gen_range(0.0..0.2)
+            };
+            let hue = (i as f32 / layout.count as f32 + time * 0.1 + noise) % 1.0;
+            let [r, g, b] = hsv_to_rgb(hue, 0.9, 1.0);
 
             self.draw_glow(
-                frame,
-                width,
-                height,
+                ctx,
                 x_start,
-                y_baseline,
-                bar_width,
+                layout.baseline,
+                layout.bar_width,
                 bar_height,
-                &color,
-                x_offset,
-                buffer_width,
+                &[r, g, b, (80.0 * opacity) as u8],
             );
+
+            let peak = self.peak_heights.get(i).copied();
+            if let Some(peak) = peak.filter(|_| peaks && !self.resting) {
+                let top = to_pixels(peak) + PEAK_GAP + PEAK_DOT_HEIGHT;
+                let Some(y) = layout.baseline.checked_sub(top) else {
+                    continue;
+                };
+                let dot = [r, g, b, (255.0 * opacity) as u8];
+                for dy in 0..PEAK_DOT_HEIGHT {
+                    for dx in 0..layout.bar_width {
+                        ctx.blend_pixel((x_start + dx) as i32, (y + dy) as i32, &dot);
+                    }
+                }
+            }
         }
     }
 
+    /// Glows the outline of a bar in `color`, whose alpha is the strongest
+    /// the glow gets
     fn draw_glow(
         &self,
-        frame: &mut [u8],
-        width: u32,
-        height: u32,
+        ctx: &mut DrawCtx,
         x_start: usize,
         y_baseline: usize,
         bar_width: usize,
         bar_height: usize,
-        color: &[u8; 3],
-        x_offset: usize,
-        buffer_width: u32,
+        color: &[u8; 4],
     ) {
-        let glow_radius = 2;
-        let glow_color = [color[0], color[1], color[2], 80];
+        let glow_radius = ctx.quality.bar_glow_radius;
+        let glow_color = *color;
+        let strength = color[3] as f32;
 
         for dy in -glow_radius..=glow_radius {
             for dx in -glow_radius..=glow_radius {
@@ -158,7 +435,7 @@ impl AudioVisualizer {
 
                 let alpha = ((1.0
                     - (distance_sq as f32 / (glow_radius * glow_radius) as f32).sqrt())
-                    * 80.0) as u8;
+                    * strength) as u8;
                 let glow_alpha = [glow_color[0], glow_color[1], glow_color[2], alpha];
 
                 if bar_height <= y_baseline {
@@ -166,16 +443,7 @@ impl AudioVisualizer {
                     for x in 0..bar_width {
                         let x_glow = (x_start + x) as i32 + dx;
                         let y_glow = y_top as i32 + dy;
-                        put_pixel(
-                            frame,
-                            width,
-                            height,
-                            x_glow,
-                            y_glow,
-                            &glow_alpha,
-                            x_offset,
-                            buffer_width,
-                        );
+                        ctx.blend_pixel(x_glow, y_glow, &glow_alpha);
                     }
                 }
 
@@ -184,26 +452,8 @@ impl AudioVisualizer {
                     let x_glow_left = x_start as i32 + dx;
                     let x_glow_right = x_start as i32 + bar_width as i32 - 1 + dx;
 
-                    put_pixel(
-                        frame,
-                        width,
-                        height,
-                        x_glow_left,
-                        y_glow,
-                        &glow_alpha,
-                        x_offset,
-                        buffer_width,
-                    );
-                    put_pixel(
-                        frame,
-                        width,
-                        height,
-                        x_glow_right,
-                        y_glow,
-                        &glow_alpha,
-                        x_offset,
-                        buffer_width,
-                    );
+                    ctx.blend_pixel(x_glow_left, y_glow, &glow_alpha);
+                    ctx.blend_pixel(x_glow_right, y_glow, &glow_alpha);
                 }
             }
         }
@@ -224,7 +474,7 @@ pub fn analyze_audio(buffer: &[f32], spectrum: Arc<Mutex<Vec<f32>>>) {
         }
 
         let scaled_energy = energy.sqrt() * 4.0;
-        let noise = rand::thread_rng().gen_range(0.0..0.2);
+        let noise = sim_rng().gen_range(0.0..0.2);
         spectrum_data[i] = spectrum_data[i] * 0.7 + (scaled_energy + noise) * 0.3;
     }
 
@@ -240,15 +490,31 @@ pub fn analyze_audio(buffer: &[f32], spectrum: Arc<Mutex<Vec<f32>>>) {
     }
 }
 
+/// Moves `current` toward `target` with separate attack (rising) and release (falling) rates.
+/// Rates are per second, so the result is independent of frame rate.
+pub fn smooth_toward(current: f32, target: f32, dt: f32, attack: f32, release: f32) -> f32 {
+    let rate = if target > current { attack } else { release };
+    current + (target - current) * (1.0 - (-dt * rate).exp())
+}
+
 #[allow(dead_code)]
 pub fn get_audio_spectrum() -> Option<Arc<Mutex<Vec<f32>>>> {
-    unsafe { AUDIO_SPECTRUM.clone() }
+    spectrum_slot().clone()
+}
+
+/// Creates the shared spectrum if it doesn't exist yet and returns it
+pub fn ensure_audio_spectrum() -> Arc<Mutex<Vec<f32>>> {
+    spectrum_slot()
+        .get_or_insert_with(|| Arc::new(Mutex::new(vec![0.0; AUDIO_VIZ_BARS])))
+        .clone()
 }
 
 pub fn set_audio_spectrum(spectrum: Arc<Mutex<Vec<f32>>>) {
-    unsafe {
-        AUDIO_SPECTRUM = Some(spectrum);
-    }
+    *spectrum_slot() = Some(spectrum);
+}
+
+fn spectrum_slot() -> MutexGuard<'static, Option<Arc<Mutex<Vec<f32>>>>> {
+    AUDIO_SPECTRUM.lock().unwrap_or_else(PoisonError::into_inner)
 }
 
 fn hsv_to_rgb(h: f32, s: f32, v: f32) -> [u8; 3] {
@@ -273,34 +539,167 @@ fn hsv_to_rgb(h: f32, s: f32, v: f32) -> [u8; 3] {
     ]
 }
 
-fn put_pixel(
-    frame: &mut [u8],
-    width: u32,
-    height: u32,
-    x: i32,
-    y: i32,
-    color: &[u8; 4],
-    x_offset: usize,
-    buffer_width: u32,
-) {
-    if let (true, true) = (
-        (0..width as i32).contains(&x),
-        (0..height as i32).contains(&y),
-    ) {
-        let actual_x = x as usize + x_offset;
-        let actual_y = y as usize;
-
-        if actual_x < buffer_width as usize && actual_y < height as usize {
-            let idx = 4 * (actual_y * buffer_width as usize + actual_x);
-            if idx + 3 < frame.len() {
-                let alpha = color[3] as f32 / 255.0;
-                let inv_alpha = 1.0 - alpha;
-
-                frame[idx] = (frame[idx] as f32 * inv_alpha + color[0] as f32 * alpha) as u8;
-                frame[idx + 1] = (frame[idx + 1] as f32 * inv_alpha + color[1] as f32 * alpha) as u8;
-                frame[idx + 2] = (frame[idx + 2] as f32 * inv_alpha + color[2] as f32 * alpha) as u8;
-                frame[idx + 3] = 255;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs a square pulse (up for 1/6 s, then down) through the envelope at `fps`,
+    /// sampling every 1/6 s so 30, 60, and 144 fps all land on the sample times
+    fn pulse_at(fps: usize, envelope: BarEnvelope) -> Vec<f32> {
+        let dt = 1.0 / fps as f32;
+        let mut level = 0.0;
+        let mut samples = Vec::new();
+        for sixth in 0..5 {
+            let target = if sixth == 0 { 1.0 } else { 0.0 };
+            for _ in 0..fps / 6 {
+                level = envelope.step(level, target, dt);
             }
+            samples.push(level);
+        }
+        samples
+    }
+
+    #[test]
+    fn test_envelope_matches_across_frame_rates() {
+        let envelope = BarEnvelope::DEFAULT;
+        let reference = pulse_at(60, envelope);
+        for fps in [30, 144] {
+            for (a, b) in pulse_at(fps, envelope).iter().zip(&reference) {
+                assert!((a - b).abs() < 1e-4, "{fps} fps: {a} vs {b}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_attack_is_faster_than_release() {
+        let envelope = BarEnvelope::DEFAULT;
+        let risen = envelope.step(0.0, 1.0, 0.03);
+        let fallen = envelope.step(1.0, 0.0, 0.03);
+        assert!(risen > 0.6);
+        assert!(fallen > 0.85);
+    }
+
+    #[test]
+    fn test_bars_rest_flat_without_audio() {
+        set_demo_bars(false);
+        let mut visualizer = AudioVisualizer::new();
+        visualizer.current_heights = vec![60.0; AUDIO_VIZ_BARS];
+        for frame in 0..180 {
+            visualizer.update(frame as f32 / 60.0, 1.0);
+        }
+        assert!(visualizer.resting);
+        for &height in &visualizer.current_heights {
+            assert!((height - AUDIO_VIZ_MIN_HEIGHT).abs() < 0.01);
+        }
+        // The simulation is left untouched
+        assert_eq!(visualizer.simulated_phase, 0.0);
+    }
+
+    #[test]
+    fn test_bars_are_8_to_16_pixels_wide_at_any_width() {
+        let layout = |width, height| BarLayout::for_region(Region::new(0, 0, width, height));
+        let wide = layout(1600, 800);
+        assert_eq!((wide.count, wide.bar_width, wide.left), (133, 12, 2));
+        assert_eq!(wide.baseline, 750);
+        let half = layout(130, 120);
+        assert_eq!((half.count, half.bar_width, half.left), (11, 11, 4));
+        assert_eq!(half.baseline, 90);
+        for width in 8..4000 {
+            let layout = layout(width, 20);
+            assert!(
+                (MIN_BAR_WIDTH..=MAX_BAR_WIDTH).contains(&layout.bar_width),
+                "{}: {:?}",
+                width,
+                layout
+            );
+            assert!(layout.left + layout.count * layout.bar_width <= width as usize);
+            assert!(layout.baseline < 20);
+        }
+        // Too narrow for even one full bar: one bar across all of it
+        for width in [1, 3, 7] {
+            let layout = layout(width, 20);
+            assert_eq!((layout.count, layout.bar_width), (1, width as usize));
+        }
+    }
+
+    #[test]
+    fn test_rebinning_keeps_the_total_energy() {
+        let levels: Vec<f32> = (0..AUDIO_VIZ_BARS)
+            .map(|i| ((i * 37) % 11) as f32 + 0.5)
+            .collect();
+        let total: f32 = levels.iter().sum();
+        for count in [1, 3, 4, 7, 20, 63, 64, 65, 100, 133, 287] {
+            let bands = rebin_bands(&levels, count);
+            assert_eq!(bands.len(), count);
+            let span = AUDIO_VIZ_BARS as f32 / count as f32;
+            let rebinned: f32 = bands.iter().map(|band| band * span).sum();
+            assert!(
+                (rebinned - total).abs() < total * 1e-4,
+                "{}: {} vs {}",
+                count,
+                rebinned,
+                total
+            );
         }
+
+        let ramp: Vec<f32> = (0..AUDIO_VIZ_BARS).map(|i| i as f32).collect();
+        assert_eq!(rebin_bands(&ramp, AUDIO_VIZ_BARS), ramp);
+        assert_eq!(rebin_bands(&ramp, 4), vec![7.5, 23.5, 39.5, 55.5]);
+        assert_eq!(rebin_bands(&ramp, 1), vec![31.5]);
+        // More bands than levels split each level evenly
+        assert_eq!(rebin_bands(&[1.0, 3.0], 4), vec![1.0, 1.0, 3.0, 3.0]);
+        // Bands straddling a level take their share of it
+        let straddled = rebin_bands(&[2.0, 4.0, 9.0], 2);
+        assert!((straddled[0] - 8.0 / 3.0).abs() < 1e-5);
+        assert!((straddled[1] - 22.0 / 3.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_peak_dots_move_to_the_nearest_new_bar() {
+        let peaks: Vec<f32> = (0..20).map(|i| 10.0 + i as f32).collect();
+        let timers = vec![0.3; 20];
+        let levels = vec![0.0; 64];
+        let (moved, moved_timers) = migrate_peaks(&peaks, &timers, &levels, 64);
+        assert_eq!((moved.len(), moved_timers.len()), (64, 64));
+        for (i, &peak) in moved.iter().enumerate() {
+            // The old bar under the new bar's middle
+            let middle = (i as f32 + 0.5) * 20.0 / 64.0;
+            assert_eq!(peak, peaks[middle as usize]);
+        }
+        assert!(moved_timers.iter().all(|&timer| timer == 0.3));
+
+        // Going down to fewer bars, every dot is one the old bars had, and
+        // never below its own bar
+        let levels: Vec<f32> = (0..7).map(|i| i as f32 * 5.0).collect();
+        let (fewer, _) = migrate_peaks(&peaks, &timers, &levels, 7);
+        for (peak, level) in fewer.iter().zip(&levels) {
+            assert!(peak >= level);
+            assert!(peaks.contains(peak) || peak == level);
+        }
+        // Nothing to carry over: the dots sit on their bars
+        let (fresh, fresh_timers) = migrate_peaks(&[], &[], &levels, 7);
+        assert_eq!(fresh, levels);
+        assert!(fresh_timers.iter().all(|&timer| timer == 0.0));
+    }
+
+    #[test]
+    fn test_a_new_bar_count_crossfades_from_the_old() {
+        let mut visualizer = AudioVisualizer::new();
+        visualizer.current_heights = (0..AUDIO_VIZ_BARS).map(|i| i as f32).collect();
+        let wide = BarLayout::for_region(Region::new(0, 0, 800, 200));
+        let narrow = BarLayout::for_region(Region::new(0, 0, 400, 200));
+        visualizer.relayout(wide, 1.0);
+        visualizer.update_peaks(0.016);
+        assert_eq!(visualizer.peak_heights.len(), wide.count);
+        assert!(visualizer.previous.is_none());
+
+        visualizer.relayout(narrow, 2.0);
+        assert_eq!(visualizer.previous, Some((wide, 2.0)));
+        assert_eq!(visualizer.peak_heights.len(), narrow.count);
+        // Same width again is no new layout
+        visualizer.relayout(narrow, 2.1);
+        assert_eq!(visualizer.previous, Some((wide, 2.0)));
+        visualizer.relayout(narrow, 2.0 + RELAYOUT_SECONDS);
+        assert!(visualizer.previous.is_none());
     }
 }
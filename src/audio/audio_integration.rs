@@ -1,37 +1,50 @@
 use crate::audio::audio_handler::AudioVisualizer;
-use crate::audio::audio_playback::{is_audio_thread_started, start_audio_thread};
+use crate::audio::audio_playback;
+use crate::graphics::draw_ctx::{DrawCtx, Region};
+use log::{info, warn};
 pub struct AudioIntegration {
     visualizer: Option<AudioVisualizer>,
+    playback_attempted: bool,
 }
 impl AudioIntegration {
     pub fn new() -> Self {
-        Self { visualizer: None }
+        Self {
+            visualizer: None,
+            playback_attempted: false,
+        }
+    }
+    /// An integration that never starts playback, for headless rendering;
+    /// the bars run on simulated data.
+    pub fn without_playback() -> Self {
+        Self {
+            visualizer: None,
+            playback_attempted: true,
+        }
     }
+    /// Sets up the visualizer and starts playback once. Later stops are left alone,
+    /// so the bars keep running on simulated data.
     pub fn initialize(&mut self) {
+        crate::audio::init();
         if self.visualizer.is_none() {
             self.visualizer = Some(AudioVisualizer::new());
         }
-        if !is_audio_thread_started() {
-            if let Some(_handle) = start_audio_thread() {
-                println!("Audio thread started successfully");
+        if !self.playback_attempted {
+            self.playback_attempted = true;
+            match audio_playback::start() {
+                Ok(()) => info!("Audio playback started successfully"),
+                Err(e) => warn!("Audio playback unavailable: {}", e),
             }
         }
     }
-    pub fn update(&mut self, time: f32, monitor_height: Option<u32>) {
+    pub fn update(&mut self, time: f32, height_scale: f32) {
         if let Some(audio_viz) = self.visualizer.as_mut() {
-            audio_viz.update(time, monitor_height);
+            audio_viz.update(time, height_scale);
         }
     }
-    pub fn draw(
-        &mut self,
-        frame: &mut [u8],
-        width: u32,
-        height: u32,
-        x_offset: usize,
-        buffer_width: u32,
-    ) {
+    /// Draws the bars into `target`, given relative to the context's region
+    pub fn draw(&mut self, ctx: &mut DrawCtx, target: Region) {
         if let Some(audio_viz) = self.visualizer.as_mut() {
-            audio_viz.draw(frame, width, height, x_offset, buffer_width);
+            audio_viz.draw_bars(ctx, target);
         }
     }
 }
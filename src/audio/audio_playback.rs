@@ -1,188 +1,542 @@
+use crate::audio::audio_analysis::ensure_analysis_thread;
 use crate::audio::audio_download::ensure_audio_file;
-use crate::audio::audio_handler::{analyze_audio, set_audio_spectrum, AUDIO_VIZ_BARS};
+use crate::audio::audio_tap;
+use crate::audio::click_track::{self, ClickTrack};
+use crate::audio::generative::GenerativeSource;
+use crate::audio::output_device::{self, DeviceWatch, DEVICE_POLL};
+use crate::audio::sample_ring::SampleRing;
 use crate::audio::white_noise::NoiseSource;
+use crate::core::events::{self, Event};
+use crate::core::sim_rng;
+use log::{error, info};
 use rand::prelude::*;
 use rodio::{Decoder, OutputStream, Sink, Source};
 use std::fs::File;
 use std::io::BufReader;
+use std::path::PathBuf;
 use std::sync::{
-    atomic::{AtomicBool, Ordering},
-    Arc, Mutex,
+    atomic::{AtomicBool, AtomicU8, Ordering},
+    mpsc, Arc, Mutex, PoisonError,
 };
 use std::thread;
-use std::time::Duration;
-static AUDIO_THREAD_STARTED: AtomicBool = AtomicBool::new(false);
-static WHITE_NOISE_ENABLED: AtomicBool = AtomicBool::new(false);
+use std::time::{Duration, Instant};
+static FALLBACK_SOUND: AtomicU8 = AtomicU8::new(FallbackSound::Off as u8);
 static DOWNLOAD_ATTEMPTED: AtomicBool = AtomicBool::new(false);
+static OUTPUT_VOLUME: Mutex<VolumeRamp> = Mutex::new(VolumeRamp::settled(1.0));
+static MUTED: AtomicBool = AtomicBool::new(false);
 
-pub fn start_audio_thread() -> Option<thread::JoinHandle<()>> {
-    if AUDIO_THREAD_STARTED.load(Ordering::SeqCst) {
-        return None;
-    }
-    AUDIO_THREAD_STARTED.store(true, Ordering::SeqCst);
-    let audio_spectrum = Arc::new(Mutex::new(vec![0.0; AUDIO_VIZ_BARS]));
-    set_audio_spectrum(audio_spectrum.clone());
-    let handle = thread::spawn(move || {
-        // Try to get the audio file - use blocking approach with futures executor
-        // Only attempt download once per application run
-        let audio_path = if !DOWNLOAD_ATTEMPTED.load(Ordering::SeqCst) {
-            DOWNLOAD_ATTEMPTED.store(true, Ordering::SeqCst);
-            match futures::executor::block_on(ensure_audio_file()) {
-                Ok(path) => Some(path),
-                Err(e) => {
-                    eprintln!("Failed to ensure audio file: {}", e);
-                    None
-                }
+/// How long `start` waits for the output device to open before reporting it unavailable
+const OPEN_TIMEOUT: Duration = Duration::from_secs(2);
+/// How often the output thread picks up the current volume
+const VOLUME_POLL: Duration = Duration::from_millis(5);
+/// Fade at the start of each stream, so switching devices doesn't pop
+const FADE_IN: Duration = Duration::from_millis(200);
+
+/// What plays when there is no soundtrack; 9 cycles through them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FallbackSound {
+    Off,
+    WhiteNoise,
+    Generative,
+}
+
+impl FallbackSound {
+    pub const ALL: [Self; 3] = [Self::Off, Self::WhiteNoise, Self::Generative];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Off => "Off",
+            Self::WhiteNoise => "White noise",
+            Self::Generative => "Generative music",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+}
+
+/// Linear fade between two volume levels. Retargeting mid-fade starts from
+/// the level reached so far, so reversing direction never jumps.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VolumeRamp {
+    from: f32,
+    to: f32,
+    /// When the fade started; None once it has settled on `to`
+    start: Option<Instant>,
+    duration: Duration,
+}
+
+impl VolumeRamp {
+    pub const fn settled(level: f32) -> Self {
+        Self {
+            from: level,
+            to: level,
+            start: None,
+            duration: Duration::ZERO,
+        }
+    }
+
+    pub fn level_at(&self, now: Instant) -> f32 {
+        let Some(start) = self.start else {
+            return self.to;
+        };
+        if self.duration.is_zero() {
+            return self.to;
+        }
+        let t = (now.saturating_duration_since(start).as_secs_f32() / self.duration.as_secs_f32())
+            .min(1.0);
+        self.from + (self.to - self.from) * t
+    }
+
+    /// Fades from the current level to `target` over `duration`
+    pub fn retarget(&mut self, target: f32, duration: Duration, now: Instant) {
+        self.from = self.level_at(now);
+        self.to = target.clamp(0.0, 1.0);
+        self.start = Some(now);
+        self.duration = duration;
+    }
+}
+
+/// Owns the audio output stream. The rodio backend is used by the app;
+/// tests substitute a mock so no audio device is needed.
+pub trait PlaybackBackend {
+    /// Opens the output stream and starts feeding samples into `ring`.
+    /// Fails when no output device is available.
+    fn open(&mut self, ring: Arc<SampleRing>) -> Result<(), String>;
+    /// Closes the stream and waits until it has been released
+    fn close(&mut self);
+    /// Whether the stream is still open; it may end on its own (e.g. noise disabled)
+    fn is_open(&self) -> bool;
+}
+
+/// Starts and stops playback independently of the spectrum and visualizer,
+/// which live for the whole run once `audio::init` has been called.
+pub struct Playback<B: PlaybackBackend> {
+    backend: B,
+}
+
+impl<B: PlaybackBackend> Playback<B> {
+    pub fn new(backend: B) -> Self {
+        Self { backend }
+    }
+
+    /// Opens the output if it isn't already open. A stream that ended on its own
+    /// is released first, so repeated starts never hold more than one stream.
+    pub fn start(&mut self) -> Result<(), String> {
+        crate::audio::init();
+        if self.backend.is_open() {
+            return Ok(());
+        }
+        self.backend.close();
+        self.backend.open(ensure_analysis_thread())
+    }
+
+    pub fn stop(&mut self) {
+        self.backend.close();
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.backend.is_open()
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+}
+
+/// Plays the soundtrack (or fallback white noise) through rodio on a dedicated thread,
+/// which owns the output stream for its whole life.
+#[derive(Default)]
+pub struct RodioBackend {
+    running: Arc<AtomicBool>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl PlaybackBackend for RodioBackend {
+    fn open(&mut self, ring: Arc<SampleRing>) -> Result<(), String> {
+        self.running.store(true, Ordering::SeqCst);
+        let running = self.running.clone();
+        let (ready_tx, ready_rx) = mpsc::channel();
+        self.handle = Some(thread::spawn(move || {
+            run_output(ring, running, ready_tx);
+        }));
+        match ready_rx.recv_timeout(OPEN_TIMEOUT) {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(e)) => {
+                self.close();
+                Err(e)
             }
-        } else {
-            // Check if file exists without attempting download
-            let potential_path = dirs::data_dir()
-                .unwrap_or_else(|| std::env::current_dir().unwrap())
-                .join("stimstation")
-                .join("foregone_destruction_remastered.flac");
-            if potential_path.exists() {
-                Some(potential_path)
-            } else {
-                None
+            Err(_) => {
+                self.close();
+                Err("audio output did not open in time".to_string())
             }
-        };
-        let (_stream, stream_handle) = match OutputStream::try_default() {
-            Ok(result) => result,
+        }
+    }
+
+    fn close(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+
+    fn is_open(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+            && self
+                .handle
+                .as_ref()
+                .is_some_and(|handle| !handle.is_finished())
+    }
+}
+
+impl Drop for RodioBackend {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+/// Body of the output thread: opens the device, reports whether that worked,
+/// then loops the soundtrack until closed, falling back to white noise. When
+/// the device changes or the stream dies, a stream is opened on the device to
+/// use now and the soundtrack carries on from where it got to.
+fn run_output(
+    ring: Arc<SampleRing>,
+    running: Arc<AtomicBool>,
+    ready: mpsc::Sender<Result<(), String>>,
+) {
+    let mut ready = Some(ready);
+    let mut resume_at = Duration::ZERO;
+    while running.load(Ordering::SeqCst) {
+        let (_stream, sink) = match open_output() {
+            Ok(opened) => opened,
             Err(e) => {
-                eprintln!("Failed to get audio output stream: {}", e);
-                AUDIO_THREAD_STARTED.store(false, Ordering::SeqCst);
-                return;
+                // Only the first open is reported; a lost device is waited for
+                if let Some(ready) = ready.take() {
+                    let _ = ready.send(Err(e));
+                    return;
+                }
+                thread::sleep(DEVICE_POLL);
+                continue;
             }
         };
-        let sink = match Sink::try_new(&stream_handle) {
-            Ok(sink) => sink,
-            Err(e) => {
-                eprintln!("Failed to create audio sink: {}", e);
-                AUDIO_THREAD_STARTED.store(false, Ordering::SeqCst);
-                return;
-            }
+        if let Some(ready) = ready.take() {
+            let _ = ready.send(Ok(()));
+        }
+        let played = play(&ring, &sink, &running, resume_at);
+        sink.stop();
+        match played {
+            Played::Finished => break,
+            Played::Interrupted(at) => resume_at = at,
+        }
+    }
+    output_device::note_stream_opened(None);
+    running.store(false, Ordering::SeqCst);
+}
+
+/// Opens a stream and sink on the device to use now, or the host's default
+/// when no device is listed
+fn open_output() -> Result<(OutputStream, Sink), String> {
+    let target = output_device::target_device();
+    let device = target
+        .as_deref()
+        .and_then(output_device::find_output_device);
+    let (stream, stream_handle) = match &device {
+        Some(device) => OutputStream::try_from_device(device),
+        None => OutputStream::try_default(),
+    }
+    .map_err(|e| format!("Failed to get audio output stream: {}", e))?;
+    let sink =
+        Sink::try_new(&stream_handle).map_err(|e| format!("Failed to create audio sink: {}", e))?;
+    info!(
+        "Audio output: {}",
+        target.as_deref().unwrap_or("host default")
+    );
+    output_device::note_stream_opened(target);
+    Ok((stream, sink))
+}
+
+/// Why playback stopped
+enum Played {
+    /// Closed, or the noise fallback was turned off
+    Finished,
+    /// The stream has to be reopened; the soundtrack was this far in
+    Interrupted(Duration),
+}
+
+/// Loops the soundtrack starting `resume_at` into it, or plays the fallback
+/// sound when there is no soundtrack
+fn play(ring: &Arc<SampleRing>, sink: &Sink, running: &AtomicBool, resume_at: Duration) -> Played {
+    let mut resume_at = resume_at;
+    while running.load(Ordering::SeqCst) {
+        let Some(source) = open_soundtrack() else {
+            break;
         };
+        // Create a custom source that captures audio data for analysis
+        sink.append(
+            with_output_volume(AnalyzingSource::new(
+                source.skip_duration(resume_at),
+                ring.clone(),
+            ))
+            .fade_in(FADE_IN),
+        );
+        sink.play();
 
-        // Try to load and play the audio file if available
-        if let Some(path) = audio_path {
-            match File::open(&path) {
-                Ok(file) => {
-                    match Decoder::new(BufReader::new(file)) {
-                        Ok(source) => {
-                            // Create a custom source that captures audio data for analysis
-                            let analyzing_source =
-                                AnalyzingSource::new(source, audio_spectrum.clone());
-                            sink.append(analyzing_source);
-                            sink.play();
-
-                            // Keep the thread alive while audio is playing
-                            while !sink.empty() && AUDIO_THREAD_STARTED.load(Ordering::SeqCst) {
-                                thread::sleep(Duration::from_millis(100));
-                            }
-
-                            // Loop the audio by restarting
-                            if AUDIO_THREAD_STARTED.load(Ordering::SeqCst) {
-                                println!("Audio finished, restarting...");
-                                AUDIO_THREAD_STARTED.store(false, Ordering::SeqCst);
-                                let _ = start_audio_thread(); // Restart the audio
-                            }
-                            return;
-                        }
-                        Err(e) => {
-                            eprintln!("Failed to decode audio file: {}", e);
-                        }
-                    }
-                }
-                Err(e) => {
-                    eprintln!("Failed to open audio file: {}", e);
-                }
+        // Keep the thread alive while audio is playing
+        let mut watch = DeviceWatch::new(Instant::now());
+        while !sink.empty() && running.load(Ordering::SeqCst) {
+            thread::sleep(Duration::from_millis(100));
+            if watch.should_reopen(Instant::now()) {
+                return Played::Interrupted(resume_at + sink.get_pos());
+            }
+        }
+
+        // Loop the audio by appending it again
+        resume_at = Duration::ZERO;
+        if running.load(Ordering::SeqCst) {
+            info!("Audio finished, restarting...");
+        }
+    }
+    if !running.load(Ordering::SeqCst) {
+        return Played::Finished;
+    }
+    // Fall back to noise or music if audio file couldn't be loaded
+    if play_fallback(ring, sink, running) {
+        Played::Interrupted(Duration::ZERO)
+    } else {
+        Played::Finished
+    }
+}
+
+/// Decodes the soundtrack, or None if there isn't a playable one
+fn open_soundtrack() -> Option<Decoder<BufReader<File>>> {
+    let path = audio_file_path()?;
+    match File::open(&path) {
+        Ok(file) => match Decoder::new(BufReader::new(file)) {
+            Ok(source) => Some(source),
+            Err(e) => {
+                error!("Failed to decode audio file: {}", e);
+                None
             }
+        },
+        Err(e) => {
+            error!("Failed to open audio file: {}", e);
+            None
         }
-        // Fallback to white noise if audio file couldn't be loaded
-        fallback_audio_thread_with_sink(audio_spectrum, sink);
-    });
-    Some(handle)
+    }
 }
 
-fn fallback_audio_thread_with_sink(audio_spectrum: Arc<Mutex<Vec<f32>>>, sink: Sink) {
-    if !WHITE_NOISE_ENABLED.load(Ordering::SeqCst) {
-        println!("White noise disabled, stopping audio fallback");
-        AUDIO_THREAD_STARTED.store(false, Ordering::SeqCst);
-        return;
+/// Finds the soundtrack, downloading it the first time. Only one download is
+/// attempted per application run.
+fn audio_file_path() -> Option<PathBuf> {
+    if !DOWNLOAD_ATTEMPTED.swap(true, Ordering::SeqCst) {
+        match futures::executor::block_on(ensure_audio_file()) {
+            Ok(path) => Some(path),
+            Err(e) => {
+                error!("Failed to ensure audio file: {}", e);
+                None
+            }
+        }
+    } else {
+        // Check if file exists without attempting download
+        let potential_path = dirs::data_dir()
+            .unwrap_or_else(|| std::env::current_dir().unwrap())
+            .join("stimstation")
+            .join("foregone_destruction_remastered.flac");
+        if potential_path.exists() {
+            Some(potential_path)
+        } else {
+            None
+        }
     }
+}
 
-    println!("Using fallback white noise audio (press 9 to disable)");
+/// Plays the fallback sound until closed or turned off, switching whenever 9
+/// picks another. Returns true if it stopped because the stream has to be
+/// reopened.
+fn play_fallback(ring: &Arc<SampleRing>, sink: &Sink, running: &AtomicBool) -> bool {
     let sample_rate = 44100;
-    let noise = NoiseSource::new(sample_rate).with_amplitude(0.15);
     let buffer_size = 1024;
-    let mut audio_buffer = vec![0.0; buffer_size];
-    let mut buffer_pos = 0;
-    sink.append(noise);
-    while !sink.empty()
-        && AUDIO_THREAD_STARTED.load(Ordering::SeqCst)
-        && WHITE_NOISE_ENABLED.load(Ordering::SeqCst)
-    {
-        thread::sleep(Duration::from_millis(10));
-        for _ in 0..buffer_size / 10 {
-            let noise_val = if WHITE_NOISE_ENABLED.load(Ordering::SeqCst) {
-                rand::thread_rng().gen_range(-1.0..1.0) * 0.15
-            } else {
-                0.0 // Silence if disabled
-            };
-            audio_buffer[buffer_pos] = noise_val;
-            buffer_pos = (buffer_pos + 1) % buffer_size;
-            if buffer_pos == 0 {
-                analyze_audio(&audio_buffer, audio_spectrum.clone());
+    // The session's, so a replayed recording plays the same tune and
+    // reopening the stream picks it back up
+    let seed = sim_rng::session_seed();
+    while running.load(Ordering::SeqCst) {
+        let sound = fallback_sound();
+        match sound {
+            FallbackSound::Off => {
+                info!("Fallback sound off, stopping audio");
+                return false;
+            }
+            FallbackSound::WhiteNoise => {
+                info!("Using fallback white noise audio (press 9 to change)");
+                let noise = NoiseSource::new(sample_rate).with_amplitude(0.15);
+                sink.append(with_output_volume(noise).fade_in(FADE_IN));
+            }
+            FallbackSound::Generative => {
+                info!("Using fallback generative music (press 9 to change)");
+                // Analyzed like the soundtrack, so the visuals follow the tune
+                let music = GenerativeSource::new(sample_rate, seed).convert_samples::<i16>();
+                sink.append(
+                    with_output_volume(AnalyzingSource::new(music, ring.clone())).fade_in(FADE_IN),
+                );
+            }
+        }
+        sink.play();
+        let mut watch = DeviceWatch::new(Instant::now());
+        while !sink.empty() && running.load(Ordering::SeqCst) && fallback_sound() == sound {
+            thread::sleep(Duration::from_millis(10));
+            if sound == FallbackSound::WhiteNoise {
+                for _ in 0..buffer_size / 10 {
+                    let noise_val = rand::thread_rng().gen_range(-1.0..1.0) * 0.15;
+                    ring.push(noise_val);
+                }
+            }
+            if watch.should_reopen(Instant::now()) {
+                return true;
             }
         }
+        sink.clear();
+    }
+    false
+}
+
+/// Scales `source` by the output volume. Analysis sees samples before this, so
+/// ducking the output leaves the visualizers unchanged. Also tells the device
+/// watch that the stream is still pulling samples.
+fn with_output_volume<S>(source: S) -> impl Source<Item = S::Item>
+where
+    S: Source,
+    S::Item: rodio::Sample,
+{
+    source
+        .amplify(audible_volume())
+        .periodic_access(VOLUME_POLL, |amplify| {
+            amplify.set_factor(audible_volume());
+            output_device::note_samples_pulled();
+        })
+}
+
+/// Fades the output volume (0.0..=1.0) to `target` over `duration`
+pub fn ramp_output_volume(target: f32, duration: Duration) {
+    if let Ok(mut ramp) = OUTPUT_VOLUME.lock() {
+        ramp.retarget(target, duration, Instant::now());
     }
-    AUDIO_THREAD_STARTED.store(false, Ordering::SeqCst);
 }
 
-// AnalyzingSource wraps an audio source and analyzes the audio data for visualization
+/// The output volume right now, partway through any fade
+pub fn output_volume() -> f32 {
+    OUTPUT_VOLUME
+        .lock()
+        .map_or(1.0, |ramp| ramp.level_at(Instant::now()))
+}
+
+pub fn is_muted() -> bool {
+    MUTED.load(Ordering::SeqCst)
+}
+
+/// Silences the output without touching the volume or the analysis
+pub fn set_muted(muted: bool) {
+    MUTED.store(muted, Ordering::SeqCst);
+}
+
+/// Flips mute and returns the new state
+pub fn toggle_mute() -> bool {
+    !MUTED.fetch_xor(true, Ordering::SeqCst)
+}
+
+/// What reaches the speakers: the output volume, or nothing while muted
+pub fn audible_volume() -> f32 {
+    if is_muted() {
+        0.0
+    } else {
+        output_volume()
+    }
+}
+
+static PLAYBACK: Mutex<Option<Playback<RodioBackend>>> = Mutex::new(None);
+
+/// Why playback refuses to start under `--no-audio`
+pub const AUDIO_DISABLED: &str = "audio is disabled";
+
+/// Runs `f` on the app's playback, creating it on first use
+fn with_playback<R>(f: impl FnOnce(&mut Playback<RodioBackend>) -> R) -> R {
+    let mut playback = PLAYBACK.lock().unwrap_or_else(PoisonError::into_inner);
+    f(playback.get_or_insert_with(|| Playback::new(RodioBackend::default())))
+}
+
+/// Starts audio output; a no-op while it is already playing. Fails while
+/// audio is disabled.
+pub fn start() -> Result<(), String> {
+    if !crate::audio::audio_enabled() {
+        return Err(AUDIO_DISABLED.to_string());
+    }
+    with_playback(Playback::start)
+}
+
+/// Stops audio output. The spectrum stays alive, so the bars fall back to simulation.
+pub fn stop() {
+    with_playback(Playback::stop);
+}
+
+pub fn is_playing() -> bool {
+    PLAYBACK
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .as_ref()
+        .is_some_and(|playback| playback.is_running())
+}
+
+// AnalyzingSource wraps an audio source and copies its samples into the analysis ring.
+// Analysis itself runs on the analysis thread so playback is never held up.
+// While calibration clicks are on they are mixed in before both.
 pub struct AnalyzingSource<S> {
     source: S,
-    spectrum: Arc<Mutex<Vec<f32>>>,
-    buffer: Vec<f32>,
-    buffer_pos: usize,
-    buffer_size: usize,
+    ring: Arc<SampleRing>,
+    clicks: Option<ClickTrack>,
+    /// Place of the next sample in its frame
+    channel: u16,
 }
 
 impl<S> AnalyzingSource<S> {
-    pub fn new(source: S, spectrum: Arc<Mutex<Vec<f32>>>) -> Self {
+    pub fn new(source: S, ring: Arc<SampleRing>) -> Self {
         Self {
             source,
-            spectrum,
-            buffer: vec![0.0; 1024],
-            buffer_pos: 0,
-            buffer_size: 1024,
+            ring,
+            clicks: None,
+            channel: 0,
         }
     }
 }
 
 impl<S> Iterator for AnalyzingSource<S>
 where
-    S: Iterator<Item = i16>,
+    S: Source<Item = i16>,
 {
     type Item = i16;
 
     fn next(&mut self) -> Option<i16> {
-        if let Some(sample) = self.source.next() {
-            // Convert i16 sample to f32 for analysis
-            let sample_f32 = sample as f32 / 32768.0;
-            self.buffer[self.buffer_pos] = sample_f32;
-            self.buffer_pos += 1;
-
-            // When buffer is full, analyze it
-            if self.buffer_pos >= self.buffer_size {
-                analyze_audio(&self.buffer, self.spectrum.clone());
-                self.buffer_pos = 0;
+        let mut sample = self.source.next()?;
+        if click_track::clicks_enabled() {
+            let (rate, channels) = (self.source.sample_rate(), self.source.channels());
+            let track = self
+                .clicks
+                .get_or_insert_with(|| ClickTrack::new(rate, channels));
+            let (click, started) = track.next_sample();
+            if started {
+                click_track::record_click(Instant::now());
             }
-
-            Some(sample)
+            sample = (sample as f32 + click * 32767.0).clamp(-32768.0, 32767.0) as i16;
         } else {
-            None
+            self.clicks = None;
         }
+        // Convert i16 sample to f32 for analysis; dropped if the ring is full
+        self.ring.push(sample as f32 / 32768.0);
+        let (rate, channels) = (self.source.sample_rate(), self.source.channels());
+        if audio_tap::is_tapping() {
+            audio_tap::tap_sample(sample, rate, channels, self.channel);
+        }
+        self.channel = (self.channel + 1) % channels.max(1);
+        Some(sample)
     }
 }
 
@@ -207,19 +561,38 @@ where
     }
 }
 
-pub fn is_audio_thread_started() -> bool {
-    AUDIO_THREAD_STARTED.load(Ordering::SeqCst)
+pub fn set_fallback_sound(sound: FallbackSound) {
+    FALLBACK_SOUND.store(sound as u8, Ordering::SeqCst);
 }
-pub fn stop_audio_thread() {
-    AUDIO_THREAD_STARTED.store(false, Ordering::SeqCst);
+
+pub fn fallback_sound() -> FallbackSound {
+    FallbackSound::ALL[FALLBACK_SOUND.load(Ordering::SeqCst) as usize]
 }
 
-pub fn set_white_noise_enabled(enabled: bool) {
-    WHITE_NOISE_ENABLED.store(enabled, Ordering::SeqCst);
+/// Moves to the next fallback sound and returns it. Turning one on starts
+/// playback if nothing is playing; if no output device can be opened, the
+/// sound goes back to off and the reason is returned.
+pub fn cycle_fallback_sound() -> Result<FallbackSound, String> {
+    if !crate::audio::audio_enabled() {
+        return Err(AUDIO_DISABLED.to_string());
+    }
+    let sound = with_playback(cycle_fallback_sound_with)?;
+    events::publish(Event::FallbackSoundChanged { sound });
+    Ok(sound)
 }
 
-pub fn is_white_noise_enabled() -> bool {
-    WHITE_NOISE_ENABLED.load(Ordering::SeqCst)
+fn cycle_fallback_sound_with<B: PlaybackBackend>(
+    playback: &mut Playback<B>,
+) -> Result<FallbackSound, String> {
+    let sound = fallback_sound().next();
+    set_fallback_sound(sound);
+    if sound != FallbackSound::Off && !playback.is_running() {
+        if let Err(e) = playback.start() {
+            set_fallback_sound(FallbackSound::Off);
+            return Err(e);
+        }
+    }
+    Ok(sound)
 }
 pub struct ToneSource {
     sample_rate: u32,
@@ -269,3 +642,124 @@ impl Source for ToneSource {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::audio_handler::get_audio_spectrum;
+
+    /// Stands in for the output device, counting how many streams are open
+    #[derive(Default)]
+    struct MockBackend {
+        available: bool,
+        open_streams: usize,
+        opens: usize,
+    }
+
+    impl PlaybackBackend for MockBackend {
+        fn open(&mut self, _ring: Arc<SampleRing>) -> Result<(), String> {
+            if !self.available {
+                return Err("no audio device".to_string());
+            }
+            self.open_streams += 1;
+            self.opens += 1;
+            Ok(())
+        }
+
+        fn close(&mut self) {
+            self.open_streams = 0;
+        }
+
+        fn is_open(&self) -> bool {
+            self.open_streams > 0
+        }
+    }
+
+    fn mock_playback(available: bool) -> Playback<MockBackend> {
+        Playback::new(MockBackend {
+            available,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_repeated_start_stop_holds_one_stream() {
+        let mut playback = mock_playback(true);
+        for _ in 0..5 {
+            playback.start().unwrap();
+            playback.start().unwrap();
+            assert_eq!(playback.backend().open_streams, 1);
+            playback.stop();
+            assert_eq!(playback.backend().open_streams, 0);
+        }
+        assert_eq!(playback.backend().opens, 5);
+    }
+
+    #[test]
+    fn test_start_before_init_creates_spectrum() {
+        let mut playback = mock_playback(true);
+        playback.start().unwrap();
+        assert!(get_audio_spectrum().is_some());
+
+        // Stopping playback leaves the spectrum in place for the visualizer
+        playback.stop();
+        assert!(!playback.is_running());
+        assert!(get_audio_spectrum().is_some());
+    }
+
+    #[test]
+    fn test_init_then_start_after_stop() {
+        crate::audio::init();
+        let mut playback = mock_playback(true);
+        playback.stop();
+        playback.start().unwrap();
+        assert!(playback.is_running());
+    }
+
+    #[test]
+    fn test_sound_cycle_without_device_reports_unavailable() {
+        // Both cases share the global fallback sound, so they run in one test
+        let mut playback = mock_playback(false);
+        set_fallback_sound(FallbackSound::Off);
+        assert!(cycle_fallback_sound_with(&mut playback).is_err());
+        assert_eq!(fallback_sound(), FallbackSound::Off);
+
+        let mut playback = mock_playback(true);
+        assert_eq!(
+            cycle_fallback_sound_with(&mut playback),
+            Ok(FallbackSound::WhiteNoise)
+        );
+        assert!(playback.is_running());
+        assert_eq!(
+            cycle_fallback_sound_with(&mut playback),
+            Ok(FallbackSound::Generative)
+        );
+        assert_eq!(
+            cycle_fallback_sound_with(&mut playback),
+            Ok(FallbackSound::Off)
+        );
+        set_fallback_sound(FallbackSound::Off);
+    }
+
+    #[test]
+    fn test_volume_ramp_fades_and_reverses_without_jumping() {
+        let start = Instant::now();
+        let ms = |n| start + Duration::from_millis(n);
+        let fade = Duration::from_millis(200);
+        let mut ramp = VolumeRamp::settled(1.0);
+        assert_eq!(ramp.level_at(start), 1.0);
+
+        ramp.retarget(0.2, fade, start);
+        assert!((ramp.level_at(ms(100)) - 0.6).abs() < 1e-4);
+        assert!((ramp.level_at(ms(200)) - 0.2).abs() < 1e-4);
+        assert!((ramp.level_at(ms(900)) - 0.2).abs() < 1e-4);
+
+        // Refocusing halfway through fades back up from where it got to
+        let mut ramp = VolumeRamp::settled(1.0);
+        ramp.retarget(0.2, fade, start);
+        ramp.retarget(1.0, fade, ms(50));
+        assert!((ramp.level_at(ms(50)) - 0.8).abs() < 1e-4);
+        assert!((ramp.level_at(ms(150)) - 0.9).abs() < 1e-4);
+        assert!((ramp.level_at(ms(250)) - 1.0).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,423 @@
+//! Audio tap for exports: while it runs, every sample handed to analysis is
+//! also written to a WAV file, with a sidecar JSON saying which sample lines
+//! up with the export's first frame, so the audio behind the frames can be
+//! muxed back in. The audio callback only pushes into a lock-free ring; a
+//! writer thread drains it into the file. The WAV is written under a `.part`
+//! name and renamed once its header is final, so the finished path never
+//! holds a truncated file.
+
+use crate::audio::sample_ring::SampleRing;
+use hound::{SampleFormat, WavSpec, WavWriter};
+use log::warn;
+use serde_json::{json, Value};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// About a second of stereo at 48 kHz; the writer drains it far more often
+pub const TAP_RING_CAPACITY: usize = 96_000;
+const WRITE_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Set while a tap owns the ring
+static TAP_OWNED: AtomicBool = AtomicBool::new(false);
+/// Set while the audio callback should copy samples into the ring
+static TAPPING: AtomicBool = AtomicBool::new(false);
+static TAP_RING: OnceLock<Arc<SampleRing>> = OnceLock::new();
+/// Sample rate and channel count of the tapped audio, as `rate << 16 | channels`
+static FORMAT: AtomicU64 = AtomicU64::new(0);
+/// Interleaved samples offered to the tap since it started, kept or not
+static OFFERED: AtomicU64 = AtomicU64::new(0);
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+static GATE: FrameGate = FrameGate::new();
+
+/// Lets interleaved samples into a ring a whole frame at a time, so a full
+/// ring drops frames rather than single samples that would swap the channels
+struct FrameGate {
+    /// Set while the rest of a frame that didn't fit is dropped
+    dropping: AtomicBool,
+}
+
+impl FrameGate {
+    const fn new() -> Self {
+        Self {
+            dropping: AtomicBool::new(false),
+        }
+    }
+
+    /// Pushes `sample`, channel `channel` of a frame of `channels`, unless its
+    /// frame didn't fit whole. Returns whether it was kept.
+    fn push(&self, ring: &SampleRing, sample: f32, channel: u16, channels: u16) -> bool {
+        if channel == 0 {
+            let room = ring.capacity() - ring.len();
+            self.dropping
+                .store(room < channels as usize, Ordering::Relaxed);
+        }
+        !self.dropping.load(Ordering::Relaxed) && ring.push(sample)
+    }
+}
+
+fn tap_ring() -> &'static Arc<SampleRing> {
+    TAP_RING.get_or_init(|| Arc::new(SampleRing::new(TAP_RING_CAPACITY)))
+}
+
+fn format() -> (u32, u16) {
+    let packed = FORMAT.load(Ordering::Relaxed);
+    ((packed >> 16) as u32, (packed & 0xFFFF) as u16)
+}
+
+pub fn is_tapping() -> bool {
+    TAPPING.load(Ordering::Relaxed)
+}
+
+/// Called by the audio callback with each sample as it goes to analysis,
+/// `channel` being its place in its frame. Never blocks or allocates; a
+/// frame the writer has no room for is dropped whole and counted.
+pub fn tap_sample(sample: i16, sample_rate: u32, channels: u16, channel: u16) {
+    if !is_tapping() {
+        return;
+    }
+    // A tap started partway through a frame begins with the next one
+    if channel != 0 && OFFERED.load(Ordering::Relaxed) == 0 {
+        return;
+    }
+    FORMAT.store(
+        (sample_rate as u64) << 16 | channels as u64,
+        Ordering::Relaxed,
+    );
+    OFFERED.fetch_add(1, Ordering::Relaxed);
+    if !GATE.push(tap_ring(), sample as f32 / 32768.0, channel, channels) {
+        DROPPED.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug)]
+pub enum TapError {
+    /// Another tap is still running
+    AlreadyRunning,
+    /// No audio reached analysis while the tap ran, e.g. with no output device
+    NoAudio,
+    Io(io::Error),
+    Wav(hound::Error),
+}
+
+impl fmt::Display for TapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TapError::AlreadyRunning => write!(f, "an audio tap is already running"),
+            TapError::NoAudio => write!(f, "no audio played while recording"),
+            TapError::Io(e) => write!(f, "I/O error: {}", e),
+            TapError::Wav(e) => write!(f, "could not write WAV: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for TapError {}
+
+impl From<io::Error> for TapError {
+    fn from(e: io::Error) -> Self {
+        TapError::Io(e)
+    }
+}
+
+impl From<hound::Error> for TapError {
+    fn from(e: hound::Error) -> Self {
+        TapError::Wav(e)
+    }
+}
+
+/// Where an export's frames fall in its tapped audio
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AudioSync {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub fps: u32,
+    /// Export frame the audio lines up with
+    pub first_frame: usize,
+    /// Per-channel sample of the WAV that was going to analysis as
+    /// `first_frame` was drawn
+    pub first_frame_sample: u64,
+    /// Interleaved samples the writer fell too far behind to keep
+    pub dropped_samples: u64,
+}
+
+impl AudioSync {
+    /// Per-channel sample where export frame `index` starts, if the audio covers it
+    pub fn sample_for_frame(&self, index: usize) -> Option<u64> {
+        let frames = index.checked_sub(self.first_frame)? as u64;
+        Some(self.first_frame_sample + frames * self.sample_rate as u64 / self.fps.max(1) as u64)
+    }
+
+    /// How far into the WAV the first frame belongs; trim this much off the
+    /// start of the audio when muxing
+    pub fn offset_seconds(&self) -> f64 {
+        self.first_frame_sample as f64 / self.sample_rate.max(1) as f64
+    }
+
+    pub fn to_json(&self, wav: &str) -> Value {
+        json!({
+            "wav": wav,
+            "sample_rate": self.sample_rate,
+            "channels": self.channels,
+            "fps": self.fps,
+            "first_frame": self.first_frame,
+            "first_frame_sample": self.first_frame_sample,
+            "first_frame_seconds": self.offset_seconds(),
+            "dropped_samples": self.dropped_samples,
+        })
+    }
+}
+
+/// Drains the ring into a WAV at `part` until `running` clears, then
+/// finalizes it. Returns whether any audio arrived.
+fn write_loop(ring: &SampleRing, running: &AtomicBool, part: &Path) -> Result<bool, TapError> {
+    let mut writer = None;
+    let mut incoming = Vec::new();
+    loop {
+        // Read before draining, so the last pass picks up everything pushed
+        // before the stop
+        let stopping = !running.load(Ordering::SeqCst);
+        incoming.clear();
+        ring.drain_into(&mut incoming);
+        if !incoming.is_empty() {
+            if writer.is_none() {
+                let (sample_rate, channels) = format();
+                let spec = WavSpec {
+                    channels: channels.max(1),
+                    sample_rate: sample_rate.max(1),
+                    bits_per_sample: 16,
+                    sample_format: SampleFormat::Int,
+                };
+                writer = Some(WavWriter::create(part, spec)?);
+            }
+            let wav = writer.as_mut().expect("created above");
+            for &sample in &incoming {
+                wav.write_sample((sample * 32768.0).round().clamp(-32768.0, 32767.0) as i16)?;
+            }
+        }
+        if stopping {
+            break;
+        }
+        thread::sleep(WRITE_INTERVAL);
+    }
+    match writer {
+        Some(wav) => {
+            wav.finalize()?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// A running tap into one WAV file. Dropping it finishes the file too, so
+/// an export that panics still leaves a playable WAV.
+pub struct AudioTap {
+    path: PathBuf,
+    fps: u32,
+    running: Arc<AtomicBool>,
+    handle: Option<thread::JoinHandle<Result<bool, TapError>>>,
+    /// Export frame the audio starts with, and the samples offered before it
+    first_frame: Option<(usize, u64)>,
+}
+
+impl AudioTap {
+    /// Starts copying analyzed audio into a WAV at `path`, for an export at
+    /// `fps`. Only one tap runs at a time.
+    pub fn start(path: &Path, fps: u32) -> Result<Self, TapError> {
+        if TAP_OWNED.swap(true, Ordering::SeqCst) {
+            return Err(TapError::AlreadyRunning);
+        }
+        let ring = tap_ring().clone();
+        // Leftovers from a previous tap's last moments
+        ring.drain_into(&mut Vec::new());
+        OFFERED.store(0, Ordering::SeqCst);
+        DROPPED.store(0, Ordering::SeqCst);
+        let running = Arc::new(AtomicBool::new(true));
+        let thread_running = running.clone();
+        let part = path.with_extension("wav.part");
+        let handle = thread::spawn(move || write_loop(&ring, &thread_running, &part));
+        TAPPING.store(true, Ordering::SeqCst);
+        Ok(Self {
+            path: path.to_path_buf(),
+            fps,
+            running,
+            handle: Some(handle),
+            first_frame: None,
+        })
+    }
+
+    /// Waits up to `timeout` for the first sample; returns whether one came
+    pub fn wait_for_audio(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        while OFFERED.load(Ordering::SeqCst) == 0 {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            thread::sleep(WRITE_INTERVAL);
+        }
+        true
+    }
+
+    /// Notes that export frame `index` is being drawn now; only the first
+    /// call counts
+    pub fn mark_frame(&mut self, index: usize) {
+        self.first_frame
+            .get_or_insert((index, OFFERED.load(Ordering::SeqCst)));
+    }
+
+    /// Stops tapping, moves the WAV into place, and writes the sync sidecar
+    /// next to it as `<name>.json`
+    pub fn finish(mut self) -> Result<AudioSync, TapError> {
+        self.stop()
+    }
+
+    fn stop(&mut self) -> Result<AudioSync, TapError> {
+        TAPPING.store(false, Ordering::SeqCst);
+        self.running.store(false, Ordering::SeqCst);
+        let handle = self.handle.take().expect("a tap stops once");
+        let wrote = handle
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("audio tap writer panicked").into()));
+        TAP_OWNED.store(false, Ordering::SeqCst);
+        if !wrote? {
+            return Err(TapError::NoAudio);
+        }
+        fs::rename(self.path.with_extension("wav.part"), &self.path)?;
+
+        let (sample_rate, channels) = format();
+        let (first_frame, offered) = self.first_frame.unwrap_or((0, 0));
+        let sync = AudioSync {
+            sample_rate,
+            channels,
+            fps: self.fps,
+            first_frame,
+            first_frame_sample: offered / channels.max(1) as u64,
+            dropped_samples: DROPPED.load(Ordering::SeqCst),
+        };
+        let wav_name = self
+            .path
+            .file_name()
+            .map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+        fs::write(
+            self.path.with_extension("json"),
+            serde_json::to_string_pretty(&sync.to_json(&wav_name)).expect("plain JSON"),
+        )?;
+        Ok(sync)
+    }
+}
+
+impl Drop for AudioTap {
+    fn drop(&mut self) {
+        if self.handle.is_some() {
+            if let Err(e) = self.stop() {
+                warn!("Audio tap not saved: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frames_map_to_samples_from_the_first_frame() {
+        let sync = AudioSync {
+            sample_rate: 48_000,
+            channels: 2,
+            fps: 60,
+            first_frame: 100,
+            first_frame_sample: 12_000,
+            dropped_samples: 0,
+        };
+        assert_eq!(sync.sample_for_frame(100), Some(12_000));
+        assert_eq!(sync.sample_for_frame(101), Some(12_800));
+        // Ten seconds of frames later, ten seconds of samples later
+        assert_eq!(sync.sample_for_frame(700), Some(12_000 + 480_000));
+        assert_eq!(sync.sample_for_frame(99), None);
+        assert!((sync.offset_seconds() - 0.25).abs() < 1e-9);
+        let json = sync.to_json("audio.wav");
+        assert_eq!(json["first_frame_sample"], 12_000);
+        assert_eq!(json["wav"], "audio.wav");
+    }
+
+    #[test]
+    fn test_a_full_ring_drops_whole_frames() {
+        let (ring, gate) = (SampleRing::new(5), FrameGate::new());
+        let kept: Vec<bool> = (0..6)
+            .map(|i| gate.push(&ring, i as f32, i % 2, 2))
+            .collect();
+        // The third frame has room for one sample only, so neither goes in
+        assert_eq!(kept, [true, true, true, true, false, false]);
+        let mut out = Vec::new();
+        ring.drain_into(&mut out);
+        assert_eq!(out, [0.0, 1.0, 2.0, 3.0]);
+        assert!(gate.push(&ring, 6.0, 0, 2) && gate.push(&ring, 7.0, 1, 2));
+    }
+
+    #[test]
+    fn test_the_writer_flushes_everything_and_finalizes_on_finish_or_drop() {
+        let dir = std::env::temp_dir().join(format!("stimstation-tap-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("audio.wav");
+        let feed = |samples: std::ops::Range<i32>| {
+            for i in samples {
+                // Stereo at 8 kHz, faster than the writer drains it
+                tap_sample((i % 2000 - 1000) as i16, 8_000, 2, (i % 2) as u16);
+            }
+        };
+
+        let mut tap = AudioTap::start(&path, 50).unwrap();
+        assert!(matches!(
+            AudioTap::start(&path, 50),
+            Err(TapError::AlreadyRunning)
+        ));
+        feed(0..3_200);
+        tap.mark_frame(7);
+        feed(3_200..40_000);
+        // Only the partial file exists until the header is final
+        assert!(!path.exists());
+        let sync = tap.finish().unwrap();
+        assert!(!path.with_extension("wav.part").exists());
+        assert_eq!((sync.sample_rate, sync.channels), (8_000, 2));
+        assert_eq!((sync.first_frame, sync.first_frame_sample), (7, 1_600));
+
+        let reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.spec().channels, 2);
+        assert_eq!(reader.spec().sample_rate, 8_000);
+        let samples: Vec<i16> = reader.into_samples().map(Result::unwrap).collect();
+        // The ring holds more than was fed, so nothing was dropped
+        assert_eq!(sync.dropped_samples, 0);
+        assert_eq!(samples.len(), 40_000);
+        assert!(samples
+            .iter()
+            .zip(0..)
+            .all(|(&s, i)| s == (i % 2000 - 1000) as i16));
+        let sidecar: Value =
+            serde_json::from_str(&fs::read_to_string(path.with_extension("json")).unwrap())
+                .unwrap();
+        assert_eq!(sidecar["first_frame"], 7);
+        assert_eq!(sidecar["first_frame_sample"], 1_600);
+
+        // A tap dropped without finishing, as when an export panics, still
+        // leaves a complete file
+        fs::remove_file(&path).unwrap();
+        {
+            let _tap = AudioTap::start(&path, 50).unwrap();
+            feed(0..500);
+        }
+        let reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.len(), 500);
+
+        // Nothing played: no file at all
+        fs::remove_file(&path).unwrap();
+        let tap = AudioTap::start(&path, 50).unwrap();
+        assert!(matches!(tap.finish(), Err(TapError::NoAudio)));
+        assert!(!path.exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
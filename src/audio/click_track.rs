@@ -0,0 +1,107 @@
+//! Metronome clicks mixed into playback while the latency calibration is
+//! open. Each click is timestamped as it enters the analysis ring, which is
+//! when the visuals first see it; the speakers play it one output latency later.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+pub const CLICK_INTERVAL: Duration = Duration::from_secs(1);
+/// Length of one click, in seconds
+const CLICK_LENGTH: f32 = 0.015;
+const CLICK_FREQUENCY: f32 = 1500.0;
+const CLICK_AMPLITUDE: f32 = 0.6;
+/// Click times kept; enough to cover the largest latency
+const RECENT_CLICKS: usize = 4;
+
+static CLICKS_ENABLED: AtomicBool = AtomicBool::new(false);
+static RECENT: Mutex<VecDeque<Instant>> = Mutex::new(VecDeque::new());
+
+pub fn set_clicks_enabled(enabled: bool) {
+    CLICKS_ENABLED.store(enabled, Ordering::Relaxed);
+    if !enabled {
+        if let Ok(mut recent) = RECENT.lock() {
+            recent.clear();
+        }
+    }
+}
+
+pub fn clicks_enabled() -> bool {
+    CLICKS_ENABLED.load(Ordering::Relaxed)
+}
+
+/// Notes that a click started entering the analysis ring at `at`
+pub fn record_click(at: Instant) {
+    if let Ok(mut recent) = RECENT.lock() {
+        if recent.len() >= RECENT_CLICKS {
+            recent.pop_front();
+        }
+        recent.push_back(at);
+    }
+}
+
+/// When the last few clicks were played, oldest first
+pub fn recent_clicks() -> Vec<Instant> {
+    RECENT
+        .lock()
+        .map(|recent| recent.iter().copied().collect())
+        .unwrap_or_default()
+}
+
+/// Generates the click signal one interleaved sample at a time, starting with a click
+#[derive(Debug, Clone)]
+pub struct ClickTrack {
+    sample_rate: u32,
+    channels: u16,
+    /// Interleaved samples produced so far
+    position: u64,
+}
+
+impl ClickTrack {
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        Self {
+            sample_rate: sample_rate.max(1),
+            channels: channels.max(1),
+            position: 0,
+        }
+    }
+
+    /// The click signal for the next sample, and whether a click starts on it
+    pub fn next_sample(&mut self) -> (f32, bool) {
+        let frame = self.position / self.channels as u64;
+        let first_channel = self.position.is_multiple_of(self.channels as u64);
+        self.position += 1;
+        let period = (CLICK_INTERVAL.as_secs_f32() * self.sample_rate as f32) as u64;
+        let t = (frame % period.max(1)) as f32 / self.sample_rate as f32;
+        if t >= CLICK_LENGTH {
+            return (0.0, false);
+        }
+        let envelope = 1.0 - t / CLICK_LENGTH;
+        let value = (t * CLICK_FREQUENCY * std::f32::consts::TAU).sin() * envelope;
+        (value * CLICK_AMPLITUDE, t == 0.0 && first_channel)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_click_track_clicks_once_per_interval() {
+        let (rate, channels) = (8000, 2);
+        let mut track = ClickTrack::new(rate, channels);
+        let samples = 3 * rate as usize * channels as usize;
+        let starts: Vec<usize> = (0..samples)
+            .filter(|_| track.next_sample().1)
+            .collect::<Vec<_>>();
+        assert_eq!(starts.len(), 3);
+
+        // Between clicks the track is silent
+        let mut track = ClickTrack::new(rate, channels);
+        let loud = (0..rate as usize * channels as usize)
+            .filter(|_| track.next_sample().0 != 0.0)
+            .count();
+        assert!(loud > 0 && loud <= (CLICK_LENGTH * rate as f32) as usize * 2);
+    }
+}
@@ -1,15 +1,12 @@
-use pixels::{Pixels, SurfaceTexture};
+use crate::ui::utility_window::{
+    run_utility_window, DurationPolicy, UtilityWindowError, WindowCommand,
+};
+use log::{error, info};
 use std::fs;
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
-use winit::{
-    dpi::LogicalSize,
-    event::{Event, WindowEvent},
-    event_loop::{ControlFlow, EventLoop},
-    window::WindowBuilder,
-};
 
 #[derive(Clone)]
 pub struct DownloadProgress {
@@ -89,11 +86,7 @@ pub async fn download_with_progress(
     Ok(())
 }
 
-fn draw_progress_window(pixels: &mut Pixels, progress: &Arc<Mutex<DownloadProgress>>) {
-    let width = pixels.texture().width();
-    let height = pixels.texture().height();
-    let frame = pixels.frame_mut();
-
+fn draw_progress_window(frame: &mut [u8], width: u32, height: u32, progress: &DownloadProgress) {
     // Clear background
     for pixel in frame.chunks_exact_mut(4) {
         pixel[0] = 20; // R
@@ -102,10 +95,8 @@ fn draw_progress_window(pixels: &mut Pixels, progress: &Arc<Mutex<DownloadProgre
         pixel[3] = 255; // A
     }
 
-    if let Ok(progress) = progress.lock() {
-        draw_progress_bar(frame, width, height, &progress);
-        draw_text(frame, width, height, &progress);
-    }
+    draw_progress_bar(frame, width, height, progress);
+    draw_text(frame, width, height, progress);
 }
 
 fn draw_progress_bar(frame: &mut [u8], width: u32, height: u32, progress: &DownloadProgress) {
@@ -349,62 +340,16 @@ fn get_char_pattern(ch: char) -> Vec<u8> {
     }
 }
 
-// Global flag to track if we're already showing a download window
-static DOWNLOAD_WINDOW_ACTIVE: std::sync::atomic::AtomicBool =
-    std::sync::atomic::AtomicBool::new(false);
-static ERROR_WINDOW_ACTIVE: std::sync::atomic::AtomicBool =
-    std::sync::atomic::AtomicBool::new(false);
+/// How long the finished progress bar stays up before the window closes
+const COMPLETED_LINGER: Duration = Duration::from_millis(1500);
+const FAILED_LINGER: Duration = Duration::from_millis(2000);
+const ERROR_WINDOW_SECONDS: u64 = 5;
 
 pub fn show_download_progress(
     url: &str,
     path: &PathBuf,
 ) -> Result<PathBuf, Box<dyn std::error::Error>> {
-    // Force reset the flag at the start to handle any stale state
-    DOWNLOAD_WINDOW_ACTIVE.store(false, std::sync::atomic::Ordering::SeqCst);
-
-    // Check if we're already showing a download window to prevent multiple EventLoops
-    if DOWNLOAD_WINDOW_ACTIVE
-        .compare_exchange(
-            false,
-            true,
-            std::sync::atomic::Ordering::SeqCst,
-            std::sync::atomic::Ordering::SeqCst,
-        )
-        .is_err()
-    {
-        println!("Download window already active, retrying...");
-        // Wait a moment and try again
-        thread::sleep(Duration::from_millis(100));
-        DOWNLOAD_WINDOW_ACTIVE.store(false, std::sync::atomic::Ordering::SeqCst);
-        if DOWNLOAD_WINDOW_ACTIVE
-            .compare_exchange(
-                false,
-                true,
-                std::sync::atomic::Ordering::SeqCst,
-                std::sync::atomic::Ordering::SeqCst,
-            )
-            .is_err()
-        {
-            return Err("Download window still active after retry".into());
-        }
-    }
-
-    // Ensure we reset the flag when this function exits
-    struct FlagGuard;
-    impl Drop for FlagGuard {
-        fn drop(&mut self) {
-            println!("Resetting download window flag");
-            DOWNLOAD_WINDOW_ACTIVE.store(false, std::sync::atomic::Ordering::SeqCst);
-        }
-    }
-    let _guard = FlagGuard;
-
-    println!("Starting download progress window for: {}", url);
-
-    use std::sync::mpsc;
-
-    // Create a channel to communicate between threads
-    let (tx, rx) = mpsc::channel();
+    info!("Starting download progress window for: {}", url);
 
     // Spawn the download in a separate thread with proper Tokio runtime
     let download_url = url.to_string();
@@ -422,7 +367,6 @@ pub fn show_download_progress(
                 let mut p = download_progress.lock().unwrap();
                 p.status = DownloadStatus::Error;
                 p.message = format!("Failed to create async runtime: {}", e);
-                let _ = tx.send(());
                 return;
             }
         };
@@ -438,137 +382,35 @@ pub fn show_download_progress(
                 p.message = format!("Download failed: {}", e);
             }
         });
-        // Signal that download thread is done
-        let _ = tx.send(());
     });
-    // Create and run the progress window in the main thread
-    println!("Creating event loop for progress window...");
-    let event_loop = EventLoop::new()?;
-
-    println!("Event loop created successfully");
-
-    // Get monitor dimensions for 50% sizing
-    let (window_width, window_height) = if let Some(monitor) = event_loop.primary_monitor() {
-        let size = monitor.size();
-        println!("Monitor size: {}x{}", size.width, size.height);
-        println!("Monitor size: {}x{}", size.width, size.height);
-        (size.width / 2, size.height / 2)
-    } else {
-        println!("No primary monitor found, using fallback size");
-        (800, 600) // Fallback size
-    };
-
-    println!(
-        "Creating window with size: {}x{}",
-        window_width, window_height
-    );
 
-    let window = Arc::new(
-        WindowBuilder::new()
-            .with_title("StimStation - Downloading Audio")
-            .with_inner_size(LogicalSize::new(window_width as f64, window_height as f64))
-            .with_resizable(false)
-            .with_decorations(false) // Remove window borders and title bar
-            .build(&event_loop)?,
-    );
-
-    println!("Window created successfully");
-
-    // Create pixels renderer
-    let window_size = window.inner_size();
-    println!(
-        "Setting up pixels renderer with size: {}x{}",
-        window_size.width, window_size.height
-    );
-    println!(
-        "Setting up pixels renderer with size: {}x{}",
-        window_size.width, window_size.height
-    );
-    let surface_texture =
-        SurfaceTexture::new(window_size.width, window_size.height, Arc::clone(&window));
-    let mut pixels = Pixels::new(window_size.width, window_size.height, surface_texture)?;
-
-    println!("Pixels renderer created, starting event loop...");
-
-    let mut last_check = std::time::Instant::now();
-    let mut completion_start: Option<std::time::Instant> = None;
-    let error_to_show = Arc::new(Mutex::new(None::<String>));
-    let error_to_show_clone = Arc::clone(&error_to_show);
-
-    // Run the event loop
-    event_loop.run(move |event, window_target| {
-        match event {
-            Event::WindowEvent {
-                event: WindowEvent::CloseRequested,
-                ..
-            } => {
-                window_target.exit();
-            }
-            Event::WindowEvent {
-                event: WindowEvent::RedrawRequested,
-                ..
-            } => {
-                draw_progress_window(&mut pixels, &progress_handle);
-                if let Err(err) = pixels.render() {
-                    eprintln!("Render error: {err}");
-                    window_target.exit();
+    let mut error_to_show = None;
+    run_utility_window(
+        "StimStation - Downloading Audio",
+        DurationPolicy::WaitForStatus,
+        |frame, width, height| {
+            let Ok(progress) = progress_handle.lock() else {
+                return WindowCommand::Continue;
+            };
+            draw_progress_window(frame, width, height, &progress);
+            match progress.status {
+                DownloadStatus::Completed => WindowCommand::CloseAfter(COMPLETED_LINGER),
+                DownloadStatus::Error => {
+                    error_to_show = Some(progress.message.clone());
+                    WindowCommand::CloseAfter(FAILED_LINGER)
                 }
+                _ => WindowCommand::Continue,
             }
-            _ => {}
-        }
-
-        // Check if download is complete (throttled to avoid excessive checking)
-        if last_check.elapsed() > Duration::from_millis(100) {
-            if let Ok(progress) = progress_handle.lock() {
-                match progress.status {
-                    DownloadStatus::Completed => {
-                        if completion_start.is_none() {
-                            completion_start = Some(std::time::Instant::now());
-                        } else if completion_start.unwrap().elapsed() > Duration::from_millis(1500)
-                        {
-                            window_target.exit();
-                        }
-                    }
-                    DownloadStatus::Error => {
-                        if completion_start.is_none() {
-                            completion_start = Some(std::time::Instant::now());
-                            if let Ok(mut error_msg) = error_to_show_clone.lock() {
-                                *error_msg = Some(progress.message.clone());
-                            }
-                        } else if completion_start.unwrap().elapsed() > Duration::from_millis(2000)
-                        {
-                            window_target.exit();
-                        }
-                    }
-                    _ => {
-                        completion_start = None; // Reset if status changes back
-                    }
-                }
-            }
-
-            // Check if download thread finished
-            if rx.try_recv().is_ok() {
-                // Download thread finished, continue showing progress
-            }
-
-            last_check = std::time::Instant::now();
-        }
-
-        window.request_redraw();
-
-        // Use a consistent control flow
-        window_target.set_control_flow(ControlFlow::WaitUntil(
-            std::time::Instant::now() + Duration::from_millis(16),
-        ));
-    })?; // Check if there was an error and show error window
-    if let Ok(error_opt) = error_to_show.lock() {
-        if let Some(error_msg) = error_opt.clone() {
-            eprintln!("Download failed: {}", error_msg);
-            if let Err(e) = show_error_window(error_msg) {
-                eprintln!("Failed to show error window: {}", e);
-            }
-            return Err("Download failed - see error window for details".into());
+        },
+    )?;
+
+    // Check if there was an error and show error window
+    if let Some(error_msg) = error_to_show {
+        error!("Download failed: {}", error_msg);
+        if let Err(e) = show_error_window(error_msg) {
+            error!("Failed to show error window: {}", e);
         }
+        return Err("Download failed - see error window for details".into());
     }
 
     // Verify download completed successfully
@@ -577,108 +419,34 @@ pub fn show_download_progress(
     } else {
         let error_msg = "Download failed - file not found after download".to_string();
         if let Err(e) = show_error_window(error_msg.clone()) {
-            eprintln!("Failed to show error window: {}", e);
+            error!("Failed to show error window: {}", e);
         }
         Err(error_msg.into())
     }
 }
 
 pub fn show_error_window(error_message: String) -> Result<(), Box<dyn std::error::Error>> {
-    // Check if we're already showing an error window to prevent multiple EventLoops
-    if ERROR_WINDOW_ACTIVE
-        .compare_exchange(
-            false,
-            true,
-            std::sync::atomic::Ordering::SeqCst,
-            std::sync::atomic::Ordering::SeqCst,
-        )
-        .is_err()
-    {
-        eprintln!(
-            "Error window already active, printing error to console: {}",
-            error_message
-        );
-        return Ok(());
-    }
-
-    // Ensure we reset the flag when this function exits
-    struct ErrorFlagGuard;
-    impl Drop for ErrorFlagGuard {
-        fn drop(&mut self) {
-            ERROR_WINDOW_ACTIVE.store(false, std::sync::atomic::Ordering::SeqCst);
-        }
-    }
-    let _guard = ErrorFlagGuard;
-    // Create and run the error window
-    let event_loop = EventLoop::new()?;
-
-    // Get monitor dimensions for 50% sizing
-    let (window_width, window_height) = if let Some(monitor) = event_loop.primary_monitor() {
-        let size = monitor.size();
-        (size.width / 2, size.height / 2)
-    } else {
-        (800, 600) // Fallback size
-    };
-
-    let window = Arc::new(
-        WindowBuilder::new()
-            .with_title("StimStation - Download Error")
-            .with_inner_size(LogicalSize::new(window_width as f64, window_height as f64))
-            .with_resizable(false)
-            .with_decorations(false) // Remove window borders and title bar
-            .build(&event_loop)?,
+    let result = run_utility_window(
+        "StimStation - Download Error",
+        DurationPolicy::AutoClose(Duration::from_secs(ERROR_WINDOW_SECONDS)),
+        |frame, width, height| {
+            draw_error_window(frame, width, height, &error_message);
+            WindowCommand::Continue
+        },
     );
-
-    // Create pixels renderer
-    let window_size = window.inner_size();
-    let surface_texture =
-        SurfaceTexture::new(window_size.width, window_size.height, Arc::clone(&window));
-    let mut pixels = Pixels::new(window_size.width, window_size.height, surface_texture)?;
-
-    let start_time = std::time::Instant::now();
-
-    // Run the event loop
-    event_loop.run(move |event, window_target| {
-        match event {
-            Event::WindowEvent {
-                event: WindowEvent::CloseRequested,
-                ..
-            } => {
-                window_target.exit();
-            }
-            Event::WindowEvent {
-                event: WindowEvent::RedrawRequested,
-                ..
-            } => {
-                draw_error_window(&mut pixels, &error_message);
-                if let Err(err) = pixels.render() {
-                    eprintln!("Render error: {err}");
-                    window_target.exit();
-                }
-            }
-            _ => {}
-        }
-
-        // Auto-close after 5 seconds, but allow user to close manually
-        if start_time.elapsed() > Duration::from_millis(5000) {
-            window_target.exit();
+    match result {
+        Err(UtilityWindowError::AlreadyOpen(_)) => {
+            error!(
+                "Error window already active, printing error to console: {}",
+                error_message
+            );
+            Ok(())
         }
-
-        window.request_redraw();
-
-        window_target.set_control_flow(ControlFlow::WaitUntil(
-            std::time::Instant::now() + Duration::from_millis(16),
-        ));
-    })?;
-
-    Ok(())
+        result => Ok(result?),
+    }
 }
 
-fn draw_error_window(pixels: &mut Pixels, error_message: &str) {
-    let width = pixels.texture().width();
-    let height = pixels.texture().height();
-    let frame = pixels.frame_mut();
-
+fn draw_error_window(frame: &mut [u8], width: u32, height: u32, error_message: &str) {
     // Clear background with dark red tint
     for pixel in frame.chunks_exact_mut(4) {
         pixel[0] = 40; // R
@@ -753,7 +521,10 @@ fn draw_error_window(pixels: &mut Pixels, error_message: &str) {
     // Draw instructions
     draw_simple_text(
         frame,
-        "This window will close automatically in 5 seconds",
+        &format!(
+            "This window will close automatically in {} seconds",
+            ERROR_WINDOW_SECONDS
+        ),
         30,
         y_offset + 20,
         [180, 180, 180, 255],
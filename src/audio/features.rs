@@ -0,0 +1,283 @@
+use crate::audio::audio_analysis;
+use crate::audio::audio_handler::get_audio_spectrum;
+use crate::audio::audio_playback::is_playing;
+use crate::core::events::{self, Event};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Number of log-spaced bands scenes receive
+pub const FEATURE_BANDS: usize = 8;
+/// EMA rate for band smoothing, per second
+const SMOOTHING_RATE: f32 = 10.0;
+/// Rate at which the running flux average follows the signal, per second
+const FLUX_AVERAGE_RATE: f32 = 2.0;
+/// Flux must exceed the running average by this factor to count as an onset
+const ONSET_RATIO: f32 = 1.5;
+/// Ignore flux below this so silence and noise floors never trigger onsets
+const ONSET_MIN_FLUX: f32 = 0.15;
+
+/// Audio features computed once per frame and shared by every scene.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct FrameFeatures {
+    /// Smoothed energy per log-spaced band, lowest frequencies first (0..=1)
+    pub bands: [f32; FEATURE_BANDS],
+    /// Change in each smoothed band since the previous frame
+    pub band_deltas: [f32; FEATURE_BANDS],
+    /// Mean of the smoothed bands
+    pub loudness: f32,
+    /// True on frames where spectral flux jumps above its recent average
+    pub onset: bool,
+}
+
+impl FrameFeatures {
+    /// Average of bands `start..end`, or 0 for an empty range
+    pub fn band_average(&self, start: usize, end: usize) -> f32 {
+        let end = end.min(FEATURE_BANDS);
+        if start >= end {
+            return 0.0;
+        }
+        self.bands[start..end].iter().sum::<f32>() / (end - start) as f32
+    }
+}
+
+/// Groups raw spectrum bins into FEATURE_BANDS log-spaced bands by averaging.
+/// Low bands cover few bins and high bands many, matching how pitch is perceived.
+pub fn aggregate_bands(spectrum: &[f32]) -> [f32; FEATURE_BANDS] {
+    let mut bands = [0.0; FEATURE_BANDS];
+    let n = spectrum.len();
+    if n == 0 {
+        return bands;
+    }
+    let mut start = 0;
+    for (b, band) in bands.iter_mut().enumerate() {
+        if start >= n {
+            break;
+        }
+        let edge = (n as f32)
+            .powf((b + 1) as f32 / FEATURE_BANDS as f32)
+            .round() as usize;
+        let end = if b == FEATURE_BANDS - 1 {
+            n
+        } else {
+            edge.clamp(start + 1, n)
+        };
+        *band = spectrum[start..end].iter().sum::<f32>() / (end - start) as f32;
+        start = end;
+    }
+    bands
+}
+
+/// Turns successive spectra into smoothed FrameFeatures.
+#[derive(Debug, Clone, Default)]
+pub struct FeatureExtractor {
+    smoothed: [f32; FEATURE_BANDS],
+    previous_raw: Option<[f32; FEATURE_BANDS]>,
+    flux_average: f32,
+}
+
+impl FeatureExtractor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Advances by `dt` seconds. Without a spectrum the bands decay toward zero.
+    pub fn update(&mut self, spectrum: Option<&[f32]>, dt: f32) -> FrameFeatures {
+        let raw = spectrum.map(aggregate_bands).unwrap_or_default();
+        let alpha = 1.0 - (-dt.max(0.0) * SMOOTHING_RATE).exp();
+
+        let mut features = FrameFeatures::default();
+        for ((smoothed, delta), raw) in self
+            .smoothed
+            .iter_mut()
+            .zip(&mut features.band_deltas)
+            .zip(raw)
+        {
+            let next = *smoothed + (raw - *smoothed) * alpha;
+            *delta = next - *smoothed;
+            *smoothed = next;
+        }
+        features.bands = self.smoothed;
+        features.loudness = self.smoothed.iter().sum::<f32>() / FEATURE_BANDS as f32;
+
+        // Spectral flux: total rise in raw band energy since last frame
+        if let Some(previous) = self.previous_raw {
+            let flux: f32 = raw
+                .iter()
+                .zip(previous.iter())
+                .map(|(now, before)| (now - before).max(0.0))
+                .sum();
+            features.onset = flux > ONSET_MIN_FLUX && flux > self.flux_average * ONSET_RATIO;
+            let flux_alpha = 1.0 - (-dt.max(0.0) * FLUX_AVERAGE_RATE).exp();
+            self.flux_average += (flux - self.flux_average) * flux_alpha;
+        }
+        self.previous_raw = Some(raw);
+        features
+    }
+}
+
+struct FeatureState {
+    extractor: FeatureExtractor,
+    features: FrameFeatures,
+    spectrum: Option<Vec<f32>>,
+    last_time: Option<f32>,
+}
+
+static FEATURE_STATE: Mutex<Option<FeatureState>> = Mutex::new(None);
+/// How far the visuals trail the analysis to line up with what the speakers
+/// play, as the bits of an f32 of milliseconds
+static VISUAL_LATENCY_MS: AtomicU32 = AtomicU32::new(0.0f32.to_bits());
+
+fn feature_state() -> MutexGuard<'static, Option<FeatureState>> {
+    FEATURE_STATE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+pub fn visual_latency_ms() -> f32 {
+    f32::from_bits(VISUAL_LATENCY_MS.load(Ordering::Relaxed))
+}
+
+/// Sets the visual delay. Negative values can't show audio before it is
+/// analyzed, so they just use the newest spectrum.
+pub fn set_visual_latency_ms(ms: f32) {
+    VISUAL_LATENCY_MS.store(ms.to_bits(), Ordering::Relaxed);
+}
+
+/// The spectrum for this frame: the newest, or the one from `visual_latency_ms` ago
+fn current_spectrum() -> Option<Vec<f32>> {
+    let latency = visual_latency_ms();
+    if latency > 0.0 {
+        let delay = Duration::from_secs_f32(latency / 1000.0);
+        let delayed = Instant::now()
+            .checked_sub(delay)
+            .and_then(audio_analysis::spectrum_at);
+        if delayed.is_some() {
+            return delayed;
+        }
+    }
+    get_audio_spectrum().and_then(|s| s.lock().ok().map(|data| data.clone()))
+}
+
+/// Locks the shared spectrum once (or picks the delayed one from the history), updates
+/// the features, and keeps a copy of the raw spectrum for this frame. Called by the orchestrator at the start of every frame.
+/// While nothing is playing, or the input has gone silent, the spectrum is ignored and
+/// the features ease back to rest.
+pub fn update_frame_features(time: f32) -> FrameFeatures {
+    let spectrum = if is_playing() && !audio_analysis::is_silent() {
+        current_spectrum()
+    } else {
+        None
+    };
+    let mut slot = feature_state();
+    let state = slot.get_or_insert_with(|| FeatureState {
+        extractor: FeatureExtractor::new(),
+        features: FrameFeatures::default(),
+        spectrum: None,
+        last_time: None,
+    });
+    let dt = match state.last_time {
+        Some(last) => (time - last).clamp(0.0, 0.1),
+        None => 0.016,
+    };
+    state.last_time = Some(time);
+    state.features = state.extractor.update(spectrum.as_deref(), dt);
+    state.spectrum = spectrum;
+    if state.features.onset {
+        events::publish(Event::Beat {
+            strength: state.features.loudness,
+        });
+    }
+    state.features
+}
+
+/// Features computed for the current frame
+pub fn frame_features() -> FrameFeatures {
+    feature_state()
+        .as_ref()
+        .map(|state| state.features)
+        .unwrap_or_default()
+}
+
+/// Raw spectrum captured for the current frame, for systems that need every bin
+pub fn frame_spectrum() -> Option<Vec<f32>> {
+    feature_state()
+        .as_ref()
+        .and_then(|state| state.spectrum.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bands_are_log_spaced_and_cover_spectrum() {
+        // Each bin holds its own index so band averages reveal which bins were used
+        let spectrum: Vec<f32> = (0..64).map(|i| i as f32).collect();
+        let bands = aggregate_bands(&spectrum);
+
+        // Lowest band covers bins 0-1; the top band averages the upper bins
+        assert_eq!(bands[0], 0.5);
+        assert!(bands[FEATURE_BANDS - 1] > 45.0);
+        for b in 1..FEATURE_BANDS {
+            assert!(bands[b] > bands[b - 1]);
+        }
+    }
+
+    #[test]
+    fn test_aggregate_uniform_and_short_spectra() {
+        assert_eq!(aggregate_bands(&[0.5; 64]), [0.5; FEATURE_BANDS]);
+        assert_eq!(aggregate_bands(&[]), [0.0; FEATURE_BANDS]);
+        // Fewer bins than bands still fills the first bands without panicking
+        let short = aggregate_bands(&[1.0, 1.0, 1.0]);
+        assert_eq!(short[0], 1.0);
+    }
+
+    #[test]
+    fn test_deltas_track_smoothed_change() {
+        let mut extractor = FeatureExtractor::new();
+        let silent = extractor.update(Some(&[0.0; 64]), 1.0 / 60.0);
+        assert_eq!(silent.band_deltas, [0.0; FEATURE_BANDS]);
+
+        let rising = extractor.update(Some(&[1.0; 64]), 1.0 / 60.0);
+        for b in 0..FEATURE_BANDS {
+            assert!(rising.band_deltas[b] > 0.0);
+            assert!((rising.bands[b] - rising.band_deltas[b]).abs() < 1e-6);
+            assert!(rising.bands[b] < 1.0);
+        }
+        assert!(rising.loudness > 0.0);
+
+        let falling = extractor.update(Some(&[0.0; 64]), 1.0 / 60.0);
+        assert!(falling.band_deltas.iter().all(|&d| d < 0.0));
+    }
+
+    #[test]
+    fn test_onset_on_sudden_rise_only() {
+        let mut extractor = FeatureExtractor::new();
+        let quiet = [0.05; 64];
+        for _ in 0..30 {
+            assert!(!extractor.update(Some(&quiet), 1.0 / 60.0).onset);
+        }
+
+        // A hit in the bass
+        let mut hit = quiet;
+        for value in hit.iter_mut().take(8) {
+            *value = 1.0;
+        }
+        assert!(extractor.update(Some(&hit), 1.0 / 60.0).onset);
+
+        // Holding the same level is not a new onset
+        assert!(!extractor.update(Some(&hit), 1.0 / 60.0).onset);
+    }
+
+    #[test]
+    fn test_no_spectrum_decays() {
+        let mut extractor = FeatureExtractor::new();
+        for _ in 0..30 {
+            extractor.update(Some(&[1.0; 64]), 1.0 / 60.0);
+        }
+        let mut features = FrameFeatures::default();
+        for _ in 0..120 {
+            features = extractor.update(None, 1.0 / 60.0);
+        }
+        assert!(features.loudness < 0.01);
+    }
+}
@@ -0,0 +1,398 @@
+//! Chiptune background music, the other fallback besides white noise. A
+//! square-wave lead plays a 16-step pattern on the pentatonic scale over a
+//! triangle bass walking a four-bar progression. A couple of steps of the
+//! pattern change every bar, so the tune keeps drifting without ever
+//! jumping. Steps are scheduled by sample count, so the tempo never drifts.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rodio::Source;
+use std::time::Duration;
+
+pub const STEPS_PER_BAR: usize = 16;
+pub const BPM: f32 = 96.0;
+/// Semitones above the root in the major pentatonic scale
+pub const PENTATONIC: [u8; 5] = [0, 2, 4, 7, 9];
+/// MIDI note the lead's scale starts from (C4)
+const LEAD_ROOT: u8 = 60;
+/// Pattern degrees span two octaves of the scale
+const MAX_DEGREE: i8 = 9;
+/// Bass roots under each bar in turn: I, vi, IV, V
+const PROGRESSION: [u8; 4] = [36, 33, 41, 43];
+/// Steps of the pattern rewritten at the end of each bar
+const MUTATIONS_PER_BAR: usize = 2;
+const LEAD_GAIN: f32 = 0.06;
+const BASS_GAIN: f32 = 0.12;
+/// One-pole low-pass coefficient that takes the edge off the square wave
+const SMOOTHING: f32 = 0.35;
+
+/// Frequency in Hz of a MIDI note, with A4 (69) at 440 Hz
+pub fn note_frequency(note: u8) -> f32 {
+    440.0 * 2f32.powf((note as f32 - 69.0) / 12.0)
+}
+
+/// The MIDI note `degree` steps up the pentatonic scale from `root`; every
+/// five degrees is an octave
+pub fn scale_note(root: u8, degree: u8) -> u8 {
+    let octave = degree / PENTATONIC.len() as u8;
+    root + 12 * octave + PENTATONIC[degree as usize % PENTATONIC.len()]
+}
+
+/// Envelope times in seconds and the sustain level
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Adsr {
+    pub attack: f32,
+    pub decay: f32,
+    pub sustain: f32,
+    pub release: f32,
+}
+
+impl Adsr {
+    /// Level `elapsed` seconds after a note held for `held` seconds started
+    /// from `from`. A retriggered note rises from wherever the last one got
+    /// to instead of snapping to zero, so notes never click.
+    pub fn level(&self, elapsed: f32, held: f32, from: f32) -> f32 {
+        if elapsed < held {
+            return self.gate_level(elapsed, from);
+        }
+        let released = (elapsed - held) / self.release;
+        self.gate_level(held, from) * (1.0 - released).max(0.0)
+    }
+
+    /// Level while the note is still held
+    fn gate_level(&self, elapsed: f32, from: f32) -> f32 {
+        if elapsed < self.attack {
+            from + (1.0 - from) * elapsed / self.attack
+        } else if elapsed < self.attack + self.decay {
+            1.0 - (1.0 - self.sustain) * (elapsed - self.attack) / self.decay
+        } else {
+            self.sustain
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Wave {
+    Square,
+    Triangle,
+}
+
+impl Wave {
+    /// The wave at `phase` through its cycle, in -1..=1
+    fn sample(self, phase: f32) -> f32 {
+        match self {
+            Wave::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Wave::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+        }
+    }
+}
+
+/// One oscillator under an envelope
+pub struct Voice {
+    wave: Wave,
+    adsr: Adsr,
+    frequency: f32,
+    /// Kept across notes so a retrigger continues the waveform
+    phase: f32,
+    /// Level the current note started from
+    from: f32,
+    level: f32,
+    elapsed: f32,
+    held: f32,
+}
+
+impl Voice {
+    pub fn new(wave: Wave, adsr: Adsr) -> Self {
+        Self {
+            wave,
+            adsr,
+            frequency: 0.0,
+            phase: 0.0,
+            from: 0.0,
+            level: 0.0,
+            elapsed: 0.0,
+            held: 0.0,
+        }
+    }
+
+    pub fn trigger(&mut self, frequency: f32, held: f32) {
+        self.frequency = frequency;
+        self.from = self.level;
+        self.elapsed = 0.0;
+        self.held = held;
+    }
+
+    /// The next sample, `dt` seconds after the last
+    pub fn next_sample(&mut self, dt: f32) -> f32 {
+        if self.frequency == 0.0 {
+            return 0.0;
+        }
+        self.level = self.adsr.level(self.elapsed, self.held, self.from);
+        self.elapsed += dt;
+        self.phase = (self.phase + self.frequency * dt).fract();
+        self.wave.sample(self.phase) * self.level
+    }
+}
+
+/// Notes to start on one step, as MIDI notes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct StepNotes {
+    pub lead: Option<u8>,
+    pub bass: Option<u8>,
+}
+
+/// A 16-step pattern of scale degrees that mutates a little every bar
+pub struct Sequencer {
+    pub pattern: [Option<u8>; STEPS_PER_BAR],
+    /// Step played next
+    pub step: usize,
+    /// Bars finished so far
+    pub bar: u64,
+    rng: StdRng,
+}
+
+impl Sequencer {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        // A random walk keeps the opening phrase melodic rather than jumpy
+        let mut degree = 4i8;
+        let pattern = std::array::from_fn(|_| {
+            degree = (degree + rng.gen_range(-2..=2)).clamp(0, MAX_DEGREE);
+            rng.gen_bool(0.6).then_some(degree as u8)
+        });
+        Self {
+            pattern,
+            step: 0,
+            bar: 0,
+            rng,
+        }
+    }
+
+    /// Plays the current step and moves on, rewriting a few steps once a bar
+    /// is done
+    pub fn advance(&mut self) -> StepNotes {
+        let root = PROGRESSION[(self.bar % PROGRESSION.len() as u64) as usize];
+        let notes = StepNotes {
+            lead: self.pattern[self.step].map(|degree| scale_note(LEAD_ROOT, degree)),
+            bass: match self.step {
+                0 => Some(root),
+                8 => Some(root + 7),
+                _ => None,
+            },
+        };
+        self.step += 1;
+        if self.step == STEPS_PER_BAR {
+            self.step = 0;
+            self.bar += 1;
+            self.mutate();
+        }
+        notes
+    }
+
+    fn mutate(&mut self) {
+        for _ in 0..MUTATIONS_PER_BAR {
+            let step = self.rng.gen_range(0..STEPS_PER_BAR);
+            let nudge = if self.rng.gen_bool(0.5) { 1 } else { -1 };
+            self.pattern[step] = match self.pattern[step] {
+                // Odds that keep the pattern about as full as it started
+                Some(_) if self.rng.gen_bool(0.3) => None,
+                None if self.rng.gen_bool(0.5) => None,
+                Some(degree) => Some((degree as i8 + nudge).clamp(0, MAX_DEGREE) as u8),
+                // A rest comes back next to its neighbor's note
+                None => {
+                    let neighbor = self.pattern[(step + STEPS_PER_BAR - 1) % STEPS_PER_BAR];
+                    Some((neighbor.unwrap_or(4) as i8 + nudge).clamp(0, MAX_DEGREE) as u8)
+                }
+            };
+        }
+    }
+}
+
+/// Endless mono chiptune as a rodio source
+pub struct GenerativeSource {
+    sample_rate: u32,
+    sequencer: Sequencer,
+    lead: Voice,
+    bass: Voice,
+    samples_per_step: f64,
+    /// Sample the next step starts on, kept fractional so steps never drift
+    next_step_at: f64,
+    samples: u64,
+    smoothed: f32,
+}
+
+impl GenerativeSource {
+    pub fn new(sample_rate: u32, seed: u64) -> Self {
+        let step_seconds = 60.0 / BPM as f64 / 4.0;
+        Self {
+            sample_rate,
+            sequencer: Sequencer::new(seed),
+            lead: Voice::new(
+                Wave::Square,
+                Adsr {
+                    attack: 0.008,
+                    decay: 0.08,
+                    sustain: 0.5,
+                    release: 0.06,
+                },
+            ),
+            bass: Voice::new(
+                Wave::Triangle,
+                Adsr {
+                    attack: 0.01,
+                    decay: 0.2,
+                    sustain: 0.7,
+                    release: 0.15,
+                },
+            ),
+            samples_per_step: step_seconds * sample_rate as f64,
+            next_step_at: 0.0,
+            samples: 0,
+            smoothed: 0.0,
+        }
+    }
+
+    pub fn sequencer(&self) -> &Sequencer {
+        &self.sequencer
+    }
+}
+
+impl Iterator for GenerativeSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.samples as f64 >= self.next_step_at {
+            self.next_step_at += self.samples_per_step;
+            let step_seconds = self.samples_per_step as f32 / self.sample_rate as f32;
+            let notes = self.sequencer.advance();
+            if let Some(note) = notes.lead {
+                self.lead.trigger(note_frequency(note), step_seconds * 0.6);
+            }
+            if let Some(note) = notes.bass {
+                self.bass.trigger(note_frequency(note), step_seconds * 7.0);
+            }
+        }
+        self.samples += 1;
+        let dt = 1.0 / self.sample_rate as f32;
+        let mix = self.lead.next_sample(dt) * LEAD_GAIN + self.bass.next_sample(dt) * BASS_GAIN;
+        self.smoothed += (mix - self.smoothed) * SMOOTHING;
+        Some(self.smoothed)
+    }
+}
+
+impl Source for GenerativeSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_note_frequencies_follow_the_scale() {
+        assert_eq!(note_frequency(69), 440.0);
+        assert!((note_frequency(81) - 880.0).abs() < 1e-3);
+        assert!((note_frequency(60) - 261.626).abs() < 1e-2);
+        let notes: Vec<u8> = (0..7).map(|degree| scale_note(60, degree)).collect();
+        assert_eq!(notes, [60, 62, 64, 67, 69, 72, 74]);
+    }
+
+    #[test]
+    fn test_adsr_rises_settles_and_releases_without_jumps() {
+        let adsr = Adsr {
+            attack: 0.01,
+            decay: 0.1,
+            sustain: 0.5,
+            release: 0.2,
+        };
+        assert_eq!(adsr.level(0.0, 1.0, 0.0), 0.0);
+        assert!((adsr.level(0.005, 1.0, 0.0) - 0.5).abs() < 1e-6);
+        assert!((adsr.level(0.01, 1.0, 0.0) - 1.0).abs() < 1e-6);
+        assert!((adsr.level(0.06, 1.0, 0.0) - 0.75).abs() < 1e-6);
+        assert_eq!(adsr.level(0.5, 1.0, 0.0), 0.5);
+        assert!((adsr.level(1.1, 1.0, 0.0) - 0.25).abs() < 1e-6);
+        assert_eq!(adsr.level(1.5, 1.0, 0.0), 0.0);
+        // Let go mid-attack, the release starts from the level reached
+        assert!((adsr.level(0.105, 0.005, 0.0) - 0.25).abs() < 1e-6);
+        // A retrigger rises from the old level rather than dropping to zero
+        assert_eq!(adsr.level(0.0, 1.0, 0.4), 0.4);
+
+        // Retriggered every 7 ms, a voice never steps by more than one
+        // attack's worth of level per sample
+        let dt = 1.0 / 44100.0;
+        let mut voice = Voice::new(Wave::Triangle, adsr);
+        let mut last = 0.0;
+        for i in 0..44100 {
+            if i % 300 == 0 {
+                voice.trigger(220.0, 0.004);
+            }
+            voice.next_sample(dt);
+            assert!((voice.level - last).abs() <= dt / adsr.attack + 1e-6);
+            last = voice.level;
+        }
+    }
+
+    #[test]
+    fn test_sequencer_steps_through_bars_on_the_sample_clock() {
+        let mut sequencer = Sequencer::new(7);
+        let first = sequencer.pattern;
+        assert_eq!(Sequencer::new(7).pattern, first);
+        let notes: Vec<StepNotes> = (0..STEPS_PER_BAR).map(|_| sequencer.advance()).collect();
+        assert_eq!((sequencer.step, sequencer.bar), (0, 1));
+        assert_eq!(notes[0].bass, Some(PROGRESSION[0]));
+        assert_eq!(notes[8].bass, Some(PROGRESSION[0] + 7));
+        for (note, degree) in notes.iter().zip(first) {
+            assert_eq!(note.lead, degree.map(|d| scale_note(LEAD_ROOT, d)));
+        }
+        // The next bar moves to the next chord with only a few steps rewritten
+        let changed = first
+            .iter()
+            .zip(sequencer.pattern)
+            .filter(|(a, b)| **a != *b)
+            .count();
+        assert!(changed <= MUTATIONS_PER_BAR);
+        assert_eq!(sequencer.advance().bass, Some(PROGRESSION[1]));
+
+        // Step n starts on the first sample at or after n steps' worth of
+        // samples, however far in
+        let mut source = GenerativeSource::new(44100, 7);
+        let samples_per_step = source.samples_per_step;
+        let mut starts = Vec::new();
+        let mut last_bar_step = (0, 0);
+        for sample in 0..44100 * 20u64 {
+            source.next();
+            let now = (source.sequencer().bar, source.sequencer().step);
+            if now != last_bar_step {
+                starts.push(sample);
+                last_bar_step = now;
+            }
+        }
+        for (n, &start) in starts.iter().enumerate() {
+            assert_eq!(start, (n as f64 * samples_per_step).ceil() as u64);
+        }
+        assert_eq!(
+            starts.len(),
+            (44100.0 * 20.0 / samples_per_step).ceil() as usize
+        );
+    }
+}
@@ -1,6 +1,56 @@
+pub mod audio_analysis;
 pub mod audio_download;
 pub mod audio_handler;
 pub mod audio_integration;
 pub mod audio_playback;
+pub mod audio_tap;
+pub mod click_track;
 pub mod download_progress;
+pub mod features;
+pub mod generative;
+pub mod output_device;
+pub mod sample_ring;
+pub mod spectrum_history;
 pub mod white_noise;
+
+pub use output_device::{list_output_devices, DeviceInfo};
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static VIZ_ENABLED: AtomicBool = AtomicBool::new(true);
+static AUDIO_ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Creates the shared spectrum and starts the analysis thread. Playback is separate
+/// (`audio_playback::start`), so everything downstream can rely on the spectrum
+/// existing whether or not any audio ever plays. Safe to call repeatedly.
+pub fn init() {
+    audio_handler::ensure_audio_spectrum();
+    audio_analysis::ensure_analysis_thread();
+}
+
+/// Whether the audio bars are drawn
+pub fn viz_enabled() -> bool {
+    VIZ_ENABLED.load(Ordering::SeqCst)
+}
+
+pub fn set_viz_enabled(enabled: bool) {
+    VIZ_ENABLED.store(enabled, Ordering::SeqCst);
+}
+
+/// Whether the soundtrack may be downloaded and played. Off (`--no-audio`),
+/// the app launches straight into the simulated spectrum.
+pub fn audio_enabled() -> bool {
+    AUDIO_ENABLED.load(Ordering::SeqCst)
+}
+
+/// Shown when something that needs audio is asked for while it is disabled
+pub const AUDIO_OFF_NOTICE: &str = "Audio is off (started with --no-audio)";
+
+pub fn set_audio_enabled(enabled: bool) {
+    AUDIO_ENABLED.store(enabled, Ordering::SeqCst);
+}
+
+/// Flips the audio bars and returns the new state
+pub fn toggle_viz() -> bool {
+    !VIZ_ENABLED.fetch_xor(true, Ordering::SeqCst)
+}
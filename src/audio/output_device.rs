@@ -0,0 +1,341 @@
+//! Which output device the playback stream is on. `DeviceSelector` works out
+//! the device from the user's choice and the devices present, and the output
+//! thread reopens its stream whenever that answer changes: a chosen device
+//! that is unplugged falls back to the system default, and is picked up again
+//! once it comes back. A stream that stops pulling samples is reopened too,
+//! since rodio doesn't report stream errors to the caller.
+
+use rodio::cpal::traits::{DeviceTrait, HostTrait};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How often the output thread looks at the devices present
+pub const DEVICE_POLL: Duration = Duration::from_millis(500);
+/// How long a stream may go without pulling samples before it is treated as dead
+pub const STALL_TIMEOUT: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceInfo {
+    pub name: String,
+    /// Whether this is the system's default output right now
+    pub is_default: bool,
+}
+
+/// The output devices of the default audio host, in the host's order
+pub fn list_output_devices() -> Vec<DeviceInfo> {
+    let host = rodio::cpal::default_host();
+    let default = host.default_output_device().and_then(|d| d.name().ok());
+    let Ok(devices) = host.output_devices() else {
+        return Vec::new();
+    };
+    devices
+        .filter_map(|device| device.name().ok())
+        .map(|name| DeviceInfo {
+            is_default: default.as_ref() == Some(&name),
+            name,
+        })
+        .collect()
+}
+
+/// Only the default device, which is all that following the default needs.
+/// Listing every device probes each one, which some hosts log noisily about.
+fn default_output_device() -> Vec<DeviceInfo> {
+    let host = rodio::cpal::default_host();
+    host.default_output_device()
+        .and_then(|device| device.name().ok())
+        .map(|name| {
+            vec![DeviceInfo {
+                name,
+                is_default: true,
+            }]
+        })
+        .unwrap_or_default()
+}
+
+/// The output device named `name`, if it is present
+pub fn find_output_device(name: &str) -> Option<rodio::Device> {
+    let host = rodio::cpal::default_host();
+    let mut devices = host.output_devices().ok()?;
+    devices.find(|device| device.name().is_ok_and(|n| n == name))
+}
+
+/// The device the user asked for
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum DeviceChoice {
+    /// Whatever the system's default output is, following it when it changes
+    #[default]
+    SystemDefault,
+    Named(String),
+}
+
+impl DeviceChoice {
+    /// As written in the settings file
+    pub fn name(&self) -> &str {
+        match self {
+            DeviceChoice::SystemDefault => "default",
+            DeviceChoice::Named(name) => name,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Self {
+        match name.trim() {
+            "" | "default" => DeviceChoice::SystemDefault,
+            name => DeviceChoice::Named(name.to_string()),
+        }
+    }
+}
+
+/// What the output thread should do about its stream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceAction {
+    Keep,
+    /// Close the stream and open one on this device
+    Switch(String),
+    /// No device is left; close the stream and wait for one to appear
+    Lost,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceSelector {
+    choice: DeviceChoice,
+    /// The device the stream is open on
+    current: Option<String>,
+}
+
+impl DeviceSelector {
+    pub const fn new() -> Self {
+        Self {
+            choice: DeviceChoice::SystemDefault,
+            current: None,
+        }
+    }
+
+    pub fn choice(&self) -> &DeviceChoice {
+        &self.choice
+    }
+
+    pub fn current(&self) -> Option<&str> {
+        self.current.as_deref()
+    }
+
+    pub fn select(&mut self, choice: DeviceChoice) {
+        self.choice = choice;
+    }
+
+    /// The device the stream belongs on: the chosen one while it is present,
+    /// otherwise the default, otherwise any device at all
+    pub fn target<'a>(&self, devices: &'a [DeviceInfo]) -> Option<&'a str> {
+        let named = match &self.choice {
+            DeviceChoice::Named(name) => devices.iter().find(|d| d.name == *name),
+            DeviceChoice::SystemDefault => None,
+        };
+        named
+            .or_else(|| devices.iter().find(|d| d.is_default))
+            .or_else(|| devices.first())
+            .map(|device| device.name.as_str())
+    }
+
+    pub fn poll(&self, devices: &[DeviceInfo]) -> DeviceAction {
+        match self.target(devices) {
+            target if target == self.current() => DeviceAction::Keep,
+            Some(target) => DeviceAction::Switch(target.to_string()),
+            None => DeviceAction::Lost,
+        }
+    }
+
+    /// Records the device a stream was just opened on
+    pub fn opened(&mut self, device: Option<String>) {
+        self.current = device;
+    }
+
+    /// The stream died; the next poll asks for it to be reopened
+    pub fn stream_failed(&mut self) {
+        self.current = None;
+    }
+}
+
+impl Default for DeviceSelector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+static SELECTOR: Mutex<DeviceSelector> = Mutex::new(DeviceSelector::new());
+static LAST_PULL: Mutex<Option<Instant>> = Mutex::new(None);
+
+fn with_selector<T>(f: impl FnOnce(&mut DeviceSelector) -> T) -> T {
+    let mut selector = SELECTOR
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+    f(&mut selector)
+}
+
+/// The device asked for in settings or the picker
+pub fn selected_device() -> DeviceChoice {
+    with_selector(|selector| selector.choice().clone())
+}
+
+/// Moves playback to `choice` within a `DEVICE_POLL` or so
+pub fn select_device(choice: DeviceChoice) {
+    with_selector(|selector| selector.select(choice));
+}
+
+/// The device the stream is open on, if playing
+pub fn current_device() -> Option<String> {
+    with_selector(|selector| selector.current().map(str::to_string))
+}
+
+/// The devices present and the one the stream should open on now
+pub fn target_device() -> Option<String> {
+    with_selector(|selector| {
+        let devices = present_devices(selector.choice());
+        selector.target(&devices).map(str::to_string)
+    })
+}
+
+pub fn note_stream_opened(device: Option<String>) {
+    with_selector(|selector| selector.opened(device));
+    note_samples_pulled();
+}
+
+/// Called from the stream as it plays, to show it is still alive
+pub fn note_samples_pulled() {
+    if let Ok(mut last) = LAST_PULL.lock() {
+        *last = Some(Instant::now());
+    }
+}
+
+fn present_devices(choice: &DeviceChoice) -> Vec<DeviceInfo> {
+    match choice {
+        DeviceChoice::SystemDefault => default_output_device(),
+        DeviceChoice::Named(_) => list_output_devices(),
+    }
+}
+
+/// Whether a stream last heard from at `last_pull`, watched since `since`,
+/// has gone quiet for too long
+fn is_stalled(last_pull: Option<Instant>, since: Instant, now: Instant) -> bool {
+    let heard = last_pull.map_or(since, |pull| pull.max(since));
+    now.saturating_duration_since(heard) >= STALL_TIMEOUT
+}
+
+/// Checked by the output thread between chunks of playback
+#[derive(Debug)]
+pub struct DeviceWatch {
+    since: Instant,
+    last_poll: Instant,
+}
+
+impl DeviceWatch {
+    /// Starts watching a stream that has just been given something to play
+    pub fn new(now: Instant) -> Self {
+        Self {
+            since: now,
+            last_poll: now,
+        }
+    }
+
+    /// Whether the stream should be closed and opened again, on whatever
+    /// `target_device` says then
+    pub fn should_reopen(&mut self, now: Instant) -> bool {
+        let last_pull = LAST_PULL.lock().ok().and_then(|last| *last);
+        if is_stalled(last_pull, self.since, now) {
+            with_selector(DeviceSelector::stream_failed);
+            return true;
+        }
+        if now.duration_since(self.last_poll) < DEVICE_POLL {
+            return false;
+        }
+        self.last_poll = now;
+        with_selector(|selector| {
+            let devices = present_devices(selector.choice());
+            selector.poll(&devices) != DeviceAction::Keep
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn devices(names: &[&str], default: &str) -> Vec<DeviceInfo> {
+        names
+            .iter()
+            .map(|&name| DeviceInfo {
+                name: name.to_string(),
+                is_default: name == default,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_chosen_device_falls_back_to_default_and_returns() {
+        let both = devices(&["Speakers", "Headphones"], "Speakers");
+        let speakers = devices(&["Speakers"], "Speakers");
+        let switch = |name: &str| DeviceAction::Switch(name.to_string());
+        let mut selector = DeviceSelector::new();
+
+        // Following the default opens the default
+        assert_eq!(selector.poll(&both), switch("Speakers"));
+        selector.opened(Some("Speakers".to_string()));
+        assert_eq!(selector.poll(&both), DeviceAction::Keep);
+
+        // Picking headphones moves there
+        selector.select(DeviceChoice::Named("Headphones".to_string()));
+        assert_eq!(selector.poll(&both), switch("Headphones"));
+        selector.opened(Some("Headphones".to_string()));
+        assert_eq!(selector.poll(&both), DeviceAction::Keep);
+
+        // Unplugging them falls back to the default instead of going silent...
+        assert_eq!(selector.poll(&speakers), switch("Speakers"));
+        selector.opened(Some("Speakers".to_string()));
+        assert_eq!(selector.poll(&speakers), DeviceAction::Keep);
+        // ...and plugging them back in returns to them, since they're still the choice
+        assert_eq!(selector.poll(&both), switch("Headphones"));
+        selector.opened(Some("Headphones".to_string()));
+
+        // With nothing left the stream is closed until something appears
+        assert_eq!(selector.poll(&[]), DeviceAction::Lost);
+        selector.opened(None);
+        assert_eq!(selector.poll(&[]), DeviceAction::Keep);
+
+        // Following the default follows it when the system changes it
+        selector.select(DeviceChoice::SystemDefault);
+        let moved = devices(&["Speakers", "Headphones"], "Headphones");
+        assert_eq!(selector.poll(&moved), switch("Headphones"));
+        // Without any default, the first device will do
+        selector.opened(Some("Headphones".to_string()));
+        assert_eq!(selector.poll(&devices(&["HDMI"], "")), switch("HDMI"));
+    }
+
+    #[test]
+    fn test_failed_stream_reopens_and_choice_names_roundtrip() {
+        let present = devices(&["Speakers"], "Speakers");
+        let mut selector = DeviceSelector::new();
+        selector.opened(Some("Speakers".to_string()));
+        assert_eq!(selector.poll(&present), DeviceAction::Keep);
+        // A dead stream on a device that is still listed is opened again
+        selector.stream_failed();
+        assert_eq!(
+            selector.poll(&present),
+            DeviceAction::Switch("Speakers".to_string())
+        );
+
+        let start = Instant::now();
+        let ms = |n| start + Duration::from_millis(n);
+        // A new stream gets the whole timeout to start pulling samples
+        assert!(!is_stalled(None, start, ms(499)));
+        assert!(is_stalled(None, start, ms(500)));
+        // Pulls from an earlier stream don't count for this one
+        assert!(is_stalled(Some(start), ms(100), ms(600)));
+        assert!(!is_stalled(Some(ms(400)), start, ms(800)));
+
+        for choice in [
+            DeviceChoice::SystemDefault,
+            DeviceChoice::Named("USB Audio: Headset (hw:2,0)".to_string()),
+        ] {
+            assert_eq!(DeviceChoice::from_name(choice.name()), choice);
+        }
+        assert_eq!(DeviceChoice::from_name(""), DeviceChoice::SystemDefault);
+    }
+}
@@ -0,0 +1,131 @@
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+/// Lock-free single-producer, single-consumer ring of f32 samples.
+/// The audio callback pushes; the analysis thread drains. Pushing never blocks:
+/// when the ring is full new samples are dropped so playback is never held up.
+pub struct SampleRing {
+    slots: Box<[AtomicU32]>,
+    head: AtomicUsize, // Next slot the consumer reads
+    tail: AtomicUsize, // Next slot the producer writes
+}
+
+impl SampleRing {
+    /// Creates a ring holding up to `capacity` samples
+    pub fn new(capacity: usize) -> Self {
+        // One slot stays empty to tell a full ring from an empty one
+        let slots = (0..capacity.max(1) + 1)
+            .map(|_| AtomicU32::new(0))
+            .collect::<Vec<_>>()
+            .into_boxed_slice();
+        Self {
+            slots,
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slots.len() - 1
+    }
+
+    /// Number of samples waiting to be drained
+    pub fn len(&self) -> usize {
+        let head = self.head.load(Ordering::Acquire);
+        let tail = self.tail.load(Ordering::Acquire);
+        (tail + self.slots.len() - head) % self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Producer side. Returns false if the ring was full and the sample was dropped.
+    pub fn push(&self, sample: f32) -> bool {
+        let tail = self.tail.load(Ordering::Relaxed);
+        let next = (tail + 1) % self.slots.len();
+        if next == self.head.load(Ordering::Acquire) {
+            return false;
+        }
+        self.slots[tail].store(sample.to_bits(), Ordering::Relaxed);
+        self.tail.store(next, Ordering::Release);
+        true
+    }
+
+    /// Consumer side. Appends every available sample to `out` and returns how many were read.
+    pub fn drain_into(&self, out: &mut Vec<f32>) -> usize {
+        let mut head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Acquire);
+        let mut count = 0;
+        while head != tail {
+            out.push(f32::from_bits(self.slots[head].load(Ordering::Relaxed)));
+            head = (head + 1) % self.slots.len();
+            count += 1;
+        }
+        self.head.store(head, Ordering::Release);
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_push_and_drain_preserve_order() {
+        let ring = SampleRing::new(8);
+        for i in 0..5 {
+            assert!(ring.push(i as f32));
+        }
+        assert_eq!(ring.len(), 5);
+
+        let mut out = Vec::new();
+        assert_eq!(ring.drain_into(&mut out), 5);
+        assert_eq!(out, vec![0.0, 1.0, 2.0, 3.0, 4.0]);
+        assert!(ring.is_empty());
+    }
+
+    #[test]
+    fn test_full_ring_drops_instead_of_blocking() {
+        let ring = SampleRing::new(4);
+        for i in 0..4 {
+            assert!(ring.push(i as f32));
+        }
+        assert!(!ring.push(99.0));
+        assert_eq!(ring.len(), ring.capacity());
+
+        // Draining frees space and wraps around correctly
+        let mut out = Vec::new();
+        ring.drain_into(&mut out);
+        assert!(ring.push(4.0));
+        assert!(ring.push(5.0));
+        out.clear();
+        ring.drain_into(&mut out);
+        assert_eq!(out, vec![4.0, 5.0]);
+    }
+
+    #[test]
+    fn test_concurrent_producer_consumer() {
+        let ring = Arc::new(SampleRing::new(64));
+        let producer_ring = ring.clone();
+        let producer = thread::spawn(move || {
+            let mut pushed = 0;
+            while pushed < 10_000 {
+                if producer_ring.push(pushed as f32) {
+                    pushed += 1;
+                }
+            }
+        });
+
+        let mut received = Vec::new();
+        while received.len() < 10_000 {
+            ring.drain_into(&mut received);
+        }
+        producer.join().unwrap();
+
+        for (i, &sample) in received.iter().enumerate() {
+            assert_eq!(sample, i as f32);
+        }
+    }
+}
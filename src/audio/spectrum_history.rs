@@ -0,0 +1,118 @@
+//! The last few published spectra with the time each was analyzed, so the
+//! visuals can show the spectrum from a moment ago and line up with audio
+//! that reaches the speakers late.
+
+use std::collections::VecDeque;
+use std::time::Instant;
+
+/// Spectra kept; at ~60 analyses a second this covers the largest visual latency
+pub const HISTORY_LEN: usize = 40;
+
+pub struct SpectrumHistory {
+    entries: VecDeque<(Instant, Vec<f32>)>,
+}
+
+impl SpectrumHistory {
+    pub const fn new() -> Self {
+        Self {
+            entries: VecDeque::new(),
+        }
+    }
+
+    /// Records `spectrum` as analyzed at `at`, dropping the oldest entry when full
+    pub fn push(&mut self, at: Instant, spectrum: &[f32]) {
+        let mut buffer = if self.entries.len() >= HISTORY_LEN {
+            self.entries.pop_front().map(|(_, buffer)| buffer)
+        } else {
+            None
+        }
+        .unwrap_or_default();
+        buffer.clear();
+        buffer.extend_from_slice(spectrum);
+        self.entries.push_back((at, buffer));
+    }
+
+    /// The spectrum analyzed closest to `target`. Targets before the oldest
+    /// entry get the oldest and targets after the newest get the newest.
+    pub fn closest(&self, target: Instant) -> Option<&[f32]> {
+        let times = self.entries.iter().map(|(at, _)| *at);
+        closest_index(times, target).map(|i| self.entries[i].1.as_slice())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+impl Default for SpectrumHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Index of the time in `times` (oldest first) nearest `target`; ties go to
+/// the newer entry
+pub fn closest_index(times: impl IntoIterator<Item = Instant>, target: Instant) -> Option<usize> {
+    let distance = |at: Instant| {
+        at.checked_duration_since(target)
+            .unwrap_or_else(|| target - at)
+    };
+    let mut best: Option<(usize, std::time::Duration)> = None;
+    for (i, at) in times.into_iter().enumerate() {
+        let d = distance(at);
+        if best.is_none_or(|(_, best_d)| d <= best_d) {
+            best = Some((i, d));
+        }
+    }
+    best.map(|(i, _)| i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn ms(base: Instant, ms: u64) -> Instant {
+        base + Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn test_closest_index_at_the_boundaries() {
+        let base = Instant::now();
+        let times: Vec<Instant> = (0..4).map(|i| ms(base, 100 + i * 16)).collect();
+        assert_eq!(closest_index([], base), None);
+        // Older than everything, exactly the ends, and newer than everything
+        assert_eq!(closest_index(times.iter().copied(), base), Some(0));
+        assert_eq!(closest_index(times.iter().copied(), ms(base, 100)), Some(0));
+        assert_eq!(closest_index(times.iter().copied(), ms(base, 148)), Some(3));
+        assert_eq!(closest_index(times.iter().copied(), ms(base, 900)), Some(3));
+        // Between two entries the nearer wins, and a tie goes to the newer
+        assert_eq!(closest_index(times.iter().copied(), ms(base, 120)), Some(1));
+        assert_eq!(closest_index(times.iter().copied(), ms(base, 105)), Some(0));
+        assert_eq!(closest_index(times.iter().copied(), ms(base, 108)), Some(1));
+    }
+
+    #[test]
+    fn test_history_keeps_the_newest_entries() {
+        let base = Instant::now();
+        let mut history = SpectrumHistory::new();
+        assert_eq!(history.closest(base), None);
+        for i in 0..HISTORY_LEN as u64 + 10 {
+            history.push(ms(base, i * 16), &[i as f32; 4]);
+        }
+        assert_eq!(history.len(), HISTORY_LEN);
+        // The first ten were dropped, so asking for the start gets the oldest kept
+        assert_eq!(history.closest(base), Some(&[10.0; 4][..]));
+        let newest = (HISTORY_LEN + 9) as f32;
+        assert_eq!(history.closest(ms(base, 100_000)), Some(&[newest; 4][..]));
+        assert_eq!(history.closest(ms(base, 20 * 16 + 3)), Some(&[20.0; 4][..]));
+    }
+}
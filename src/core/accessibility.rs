@@ -0,0 +1,227 @@
+use crate::graphics::theme::{self, Theme};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Environment variable that turns on reduced motion at startup when set to 1 or true
+pub const REDUCED_MOTION_ENV: &str = "STIMSTATION_REDUCED_MOTION";
+/// Environment variable that turns on the opaque HUD at startup when set to 1 or true
+pub const REDUCED_TRANSPARENCY_ENV: &str = "STIMSTATION_REDUCED_TRANSPARENCY";
+
+/// WCAG's minimum contrast ratio for body text
+pub const MIN_TEXT_CONTRAST: f32 = 4.5;
+/// Most pixels sampled along each axis when estimating what is behind text
+const SAMPLE_GRID: usize = 6;
+
+static REDUCED_MOTION: AtomicBool = AtomicBool::new(false);
+static OPAQUE_HUD: AtomicBool = AtomicBool::new(false);
+
+/// When enabled, effects that move the whole frame (such as screen shake) are disabled
+pub fn set_reduced_motion(enabled: bool) {
+    REDUCED_MOTION.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_reduced_motion() -> bool {
+    REDUCED_MOTION.load(Ordering::Relaxed)
+}
+
+/// When enabled, HUD panels are drawn on the theme's solid surface color
+/// instead of translucent over the scene
+pub fn set_opaque_hud(enabled: bool) {
+    OPAQUE_HUD.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_opaque_hud() -> bool {
+    OPAQUE_HUD.load(Ordering::Relaxed)
+}
+
+/// Reads the reduced-motion and reduced-transparency preferences from the environment
+pub fn init_from_env() {
+    let enabled = |name: &str| {
+        std::env::var(name).ok().map(|value| {
+            let value = value.trim().to_ascii_lowercase();
+            value == "1" || value == "true"
+        })
+    };
+    if let Some(enabled) = enabled(REDUCED_MOTION_ENV) {
+        set_reduced_motion(enabled);
+    }
+    if let Some(enabled) = enabled(REDUCED_TRANSPARENCY_ENV) {
+        set_opaque_hud(enabled);
+    }
+}
+
+/// The backing a HUD panel should use in place of `color`: unchanged
+/// normally, the current theme's surface at full opacity with the opaque HUD
+pub fn hud_backing(color: [u8; 4]) -> [u8; 4] {
+    if is_opaque_hud() {
+        let [r, g, b] = theme::current_theme().surface;
+        [r, g, b, 255]
+    } else {
+        color
+    }
+}
+
+/// WCAG relative luminance of an sRGB color, 0 for black to 1 for white
+pub fn relative_luminance(rgb: [u8; 3]) -> f32 {
+    let linear = |c: u8| {
+        let c = c as f32 / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    0.2126 * linear(rgb[0]) + 0.7152 * linear(rgb[1]) + 0.0722 * linear(rgb[2])
+}
+
+/// WCAG contrast ratio between two relative luminances, from 1 to 21
+pub fn contrast_ratio(a: f32, b: f32) -> f32 {
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+/// Mean relative luminance of the pixels in the rectangle, estimated from at
+/// most `SAMPLE_GRID` by `SAMPLE_GRID` pixels spread evenly across it. The
+/// rectangle is clipped to the frame; None when nothing of it is left.
+pub fn sample_luminance(
+    frame: &[u8],
+    buffer_width: u32,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+) -> Option<f32> {
+    let buffer_width = buffer_width as usize;
+    let rows = frame.len() / 4 / buffer_width.max(1);
+    let left = x.max(0) as usize;
+    let top = y.max(0) as usize;
+    let right = ((x + width as i32).max(0) as usize).min(buffer_width);
+    let bottom = ((y + height as i32).max(0) as usize).min(rows);
+    if left >= right || top >= bottom {
+        return None;
+    }
+    // Centers of equal cells, so the samples cover the rectangle evenly
+    let spread = |start: usize, end: usize| {
+        let cells = SAMPLE_GRID.min(end - start);
+        (0..cells).map(move |i| start + (2 * i + 1) * (end - start) / (2 * cells))
+    };
+    let mut total = 0.0;
+    let mut count = 0;
+    for py in spread(top, bottom) {
+        for px in spread(left, right) {
+            let idx = 4 * (py * buffer_width + px);
+            total += relative_luminance([frame[idx], frame[idx + 1], frame[idx + 2]]);
+            count += 1;
+        }
+    }
+    Some(total / count as f32)
+}
+
+/// `text` if it stands out at least `MIN_TEXT_CONTRAST` from a background
+/// of luminance `background`, otherwise whichever of the theme's contrast
+/// colors stands out more
+pub fn readable_text_color(text: [u8; 4], background: f32, theme: &Theme) -> [u8; 4] {
+    let luminance = relative_luminance([text[0], text[1], text[2]]);
+    if contrast_ratio(luminance, background) >= MIN_TEXT_CONTRAST {
+        return text;
+    }
+    let [r, g, b] = theme
+        .contrast_text
+        .into_iter()
+        .max_by(|a, b| {
+            let contrast = |c: [u8; 3]| contrast_ratio(relative_luminance(c), background);
+            contrast(*a).total_cmp(&contrast(*b))
+        })
+        .unwrap_or([255, 255, 255]);
+    [r, g, b, text[3]]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::prelude::*;
+
+    /// Mean luminance of every pixel in the rectangle, for comparison
+    fn full_luminance(
+        frame: &[u8],
+        buffer_width: u32,
+        x: usize,
+        y: usize,
+        w: usize,
+        h: usize,
+    ) -> f32 {
+        let mut total = 0.0;
+        for py in y..y + h {
+            for px in x..x + w {
+                let idx = 4 * (py * buffer_width as usize + px);
+                total += relative_luminance([frame[idx], frame[idx + 1], frame[idx + 2]]);
+            }
+        }
+        total / (w * h) as f32
+    }
+
+    #[test]
+    fn test_contrast_matches_wcag_reference_values() {
+        assert!((relative_luminance([255, 255, 255]) - 1.0).abs() < 1e-5);
+        assert_eq!(relative_luminance([0, 0, 0]), 0.0);
+        assert!((contrast_ratio(1.0, 0.0) - 21.0).abs() < 1e-4);
+        assert_eq!(contrast_ratio(0.3, 0.3), 1.0);
+        // Order doesn't matter
+        assert_eq!(contrast_ratio(0.2, 0.7), contrast_ratio(0.7, 0.2));
+        // #777 on white is the classic just-failing grey, about 4.48:1
+        let grey = relative_luminance([0x77, 0x77, 0x77]);
+        assert!((contrast_ratio(grey, 1.0) - 4.48).abs() < 0.01);
+
+        // White text over white swaps to the dark alternative, over black it stays
+        let theme = Theme::DAY;
+        assert_eq!(
+            readable_text_color([255, 255, 255, 200], 1.0, &theme),
+            [0, 0, 0, 200]
+        );
+        assert_eq!(
+            readable_text_color([255, 255, 255, 255], 0.0, &theme),
+            [255, 255, 255, 255]
+        );
+        // Saturated mid-luminance backgrounds get whichever reads better
+        let red = relative_luminance([255, 0, 0]);
+        let picked = readable_text_color([255, 120, 120, 255], red, &theme);
+        assert!(contrast_ratio(relative_luminance([picked[0], picked[1], picked[2]]), red) >= 4.5);
+    }
+
+    #[test]
+    fn test_sparse_sampling_tracks_the_full_region() {
+        let (width, height) = (200u32, 120u32);
+        let mut rng = StdRng::seed_from_u64(11);
+        // Color of the pixel at (x, y), before noise
+        type Scene = Box<dyn Fn(usize, usize) -> [u8; 3]>;
+        // Smooth gradients with noise, a hard split, and flat color
+        let scenes: [Scene; 3] = [
+            Box::new(|x, y| [(x + y) as u8, (x * 255 / 200) as u8, 128]),
+            Box::new(|x, _| if x < 100 { [255; 3] } else { [0; 3] }),
+            Box::new(|_, _| [255, 40, 200]),
+        ];
+        for scene in &scenes {
+            let mut frame = vec![0u8; (width * height * 4) as usize];
+            for y in 0..height as usize {
+                for x in 0..width as usize {
+                    let noise: i16 = rng.gen_range(-20..=20);
+                    let [r, g, b] = scene(x, y).map(|c| (c as i16 + noise).clamp(0, 255) as u8);
+                    let idx = 4 * (y * width as usize + x);
+                    frame[idx..idx + 4].copy_from_slice(&[r, g, b, 255]);
+                }
+            }
+            for (x, y, w, h) in [(0, 0, 200, 120), (10, 30, 150, 25), (60, 50, 80, 40)] {
+                let sampled = sample_luminance(&frame, width, x as i32, y as i32, w, h).unwrap();
+                let truth = full_luminance(&frame, width, x, y, w as usize, h as usize);
+                // A hard edge can be off by at most a column of samples
+                assert!((sampled - truth).abs() < 0.1, "{} vs {}", sampled, truth);
+            }
+        }
+
+        // Clipped to the frame, and nothing when wholly outside it
+        let frame = vec![255u8; (width * height * 4) as usize];
+        let clipped = sample_luminance(&frame, width, -50, 100, 100, 100).unwrap();
+        assert!((clipped - 1.0).abs() < 1e-5);
+        assert_eq!(sample_luminance(&frame, width, 300, 0, 10, 10), None);
+        // A thin strip still samples within it
+        assert!(sample_luminance(&frame, width, 0, 119, 200, 1).is_some());
+    }
+}
@@ -0,0 +1,464 @@
+//! `stimstation bench`: renders frames without a window and reports how long
+//! they took, for comparing performance between builds. `bench-all` does the
+//! same for every scene at two sizes and writes a JSON report that a later
+//! run can be compared against.
+
+use crate::core::bufpool;
+use crate::core::embed::{EmbedError, StimConfig, StimStation};
+use crate::core::orchestrator;
+use crate::core::pixel_format::PixelFormat;
+use crate::core::scenes::{self, SceneInfo};
+use crate::graphics::draw_ctx::{DrawCtx, Region};
+use crate::graphics::shatter;
+use crate::types::{HEIGHT, ORIGINAL_HEIGHT, ORIGINAL_WIDTH, WIDTH};
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+use winit::event::MouseButton;
+
+pub const DEFAULT_BENCH_FRAMES: usize = 600;
+const FRAME_TIME: f32 = 1.0 / 60.0;
+
+/// Bumped whenever a report field is renamed or removed
+pub const REPORT_VERSION: u32 = 1;
+pub const DEFAULT_BENCH_SECONDS: f32 = 3.0;
+/// Frame sizes every scene is measured at
+pub const BENCH_SIZES: [(u32, u32); 2] = [(800, 400), (1600, 800)];
+/// Slowdown past which a metric counts as a regression
+pub const REGRESSION_THRESHOLD: f64 = 0.10;
+/// Frames measured per run however short `seconds` is, so percentiles mean something
+const MIN_BENCH_FRAMES: usize = 20;
+/// Seconds per half of the scripted drag: held for one, released for the next
+const DRAG_PERIOD: f32 = 1.0;
+
+#[derive(Debug, Clone, Copy)]
+pub struct BenchReport {
+    pub frames: usize,
+    pub total: Duration,
+}
+
+impl BenchReport {
+    pub fn average_ms(&self) -> f64 {
+        self.total.as_secs_f64() * 1000.0 / self.frames.max(1) as f64
+    }
+}
+
+/// Renders `frames` frames of the current scene at window size, advancing time
+/// at 60 fps regardless of how long each frame takes
+pub fn run_bench(frames: usize) -> BenchReport {
+    orchestrator::init_headless();
+    let mut frame = bufpool::get_buffer("bench frame", (WIDTH * HEIGHT * 4) as usize);
+    let start = Instant::now();
+    for i in 0..frames {
+        let time = i as f32 * FRAME_TIME;
+        let mut ctx = DrawCtx::new(&mut frame, Region::new(0, 0, WIDTH, HEIGHT), WIDTH, time);
+        orchestrator::render_frame(&mut ctx);
+    }
+    BenchReport {
+        frames,
+        total: start.elapsed(),
+    }
+}
+
+/// Converts `frames` window-sized RGBA frames to `format`, the pass a window
+/// in another channel order pays for on every frame
+pub fn run_swizzle_bench(frames: usize, format: PixelFormat) -> BenchReport {
+    let len = (WIDTH * HEIGHT * 4) as usize;
+    let rgba: Vec<u8> = (0..len).map(|i| (i * 31 % 251) as u8).collect();
+    let mut out = vec![0; len];
+    let start = Instant::now();
+    for _ in 0..frames {
+        format.convert(std::hint::black_box(&rgba), &mut out);
+        std::hint::black_box(&mut out);
+    }
+    BenchReport {
+        frames,
+        total: start.elapsed(),
+    }
+}
+
+/// Draws `frames` frames of the Shatter scene alone at 800x400, its 200
+/// shards going from whole through the burst into drifting, advancing time
+/// at 60 fps. Loading the picture happens before the clock starts.
+pub fn run_shatter_bench(frames: usize) -> BenchReport {
+    let was_enabled = shatter::is_shatter_enabled();
+    shatter::set_shatter_enabled(true);
+    let region = Region::new(0, 0, ORIGINAL_WIDTH, ORIGINAL_HEIGHT);
+    let mut frame = vec![0; (ORIGINAL_WIDTH * ORIGINAL_HEIGHT * 4) as usize];
+    shatter::update_and_draw_shatter(&mut DrawCtx::new(&mut frame, region, ORIGINAL_WIDTH, 0.0));
+    let start = Instant::now();
+    for i in 1..=frames {
+        let time = i as f32 * FRAME_TIME;
+        let mut ctx = DrawCtx::new(&mut frame, region, ORIGINAL_WIDTH, time);
+        shatter::update_and_draw_shatter(&mut ctx);
+    }
+    let total = start.elapsed();
+    shatter::set_shatter_enabled(was_enabled);
+    BenchReport { frames, total }
+}
+
+/// Timings and memory for one scene at one frame size
+#[derive(Debug, Clone, PartialEq)]
+pub struct SceneBench {
+    pub scene: String,
+    pub width: u32,
+    pub height: u32,
+    pub frames: usize,
+    pub avg_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64,
+    /// Scratch buffer allocations per frame, from the buffer pool counters
+    pub allocations_per_frame: f64,
+    /// Most pooled scratch memory held at once during the run
+    pub peak_pool_bytes: usize,
+    /// Peak resident memory of the whole process so far, where the OS reports it
+    pub peak_rss_bytes: Option<u64>,
+}
+
+impl SceneBench {
+    fn to_json(&self) -> Value {
+        json!({
+            "scene": self.scene,
+            "width": self.width,
+            "height": self.height,
+            "frames": self.frames,
+            "avg_ms": self.avg_ms,
+            "p95_ms": self.p95_ms,
+            "p99_ms": self.p99_ms,
+            "allocations_per_frame": self.allocations_per_frame,
+            "peak_pool_bytes": self.peak_pool_bytes,
+            "peak_rss_bytes": self.peak_rss_bytes,
+        })
+    }
+
+    fn from_json(value: &Value) -> Option<Self> {
+        Some(Self {
+            scene: value["scene"].as_str()?.to_string(),
+            width: value["width"].as_u64()? as u32,
+            height: value["height"].as_u64()? as u32,
+            frames: value["frames"].as_u64()? as usize,
+            avg_ms: value["avg_ms"].as_f64()?,
+            p95_ms: value["p95_ms"].as_f64()?,
+            p99_ms: value["p99_ms"].as_f64()?,
+            allocations_per_frame: value["allocations_per_frame"].as_f64()?,
+            peak_pool_bytes: value["peak_pool_bytes"].as_u64()? as usize,
+            peak_rss_bytes: value["peak_rss_bytes"].as_u64(),
+        })
+    }
+
+    /// The frame time named `metric` in reports, if it is one
+    fn metric(&self, metric: &str) -> Option<f64> {
+        match metric {
+            "avg_ms" => Some(self.avg_ms),
+            "p95_ms" => Some(self.p95_ms),
+            "p99_ms" => Some(self.p99_ms),
+            _ => None,
+        }
+    }
+}
+
+/// What `bench-all` writes: every scene at every size in `BENCH_SIZES`
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchAllReport {
+    /// Wall time each scene was run for at each size
+    pub seconds: f32,
+    pub results: Vec<SceneBench>,
+}
+
+impl BenchAllReport {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "version": REPORT_VERSION,
+            "seconds": self.seconds,
+            "results": self.results.iter().map(SceneBench::to_json).collect::<Vec<_>>(),
+        })
+    }
+
+    /// Reads a report written by `to_json`. None if it is malformed or from
+    /// another report version.
+    pub fn from_json(value: &Value) -> Option<Self> {
+        if value["version"].as_u64()? != REPORT_VERSION as u64 {
+            return None;
+        }
+        Some(Self {
+            seconds: value["seconds"].as_f64()? as f32,
+            results: value["results"]
+                .as_array()?
+                .iter()
+                .map(SceneBench::from_json)
+                .collect::<Option<_>>()?,
+        })
+    }
+
+    /// A header and one aligned row per result, for the terminal
+    pub fn table_lines(&self) -> Vec<String> {
+        let mut lines = vec![format!(
+            "{:<10} {:>9} {:>6} {:>8} {:>8} {:>8} {:>9} {:>10}",
+            "scene", "size", "frames", "avg ms", "p95 ms", "p99 ms", "allocs/f", "peak pool"
+        )];
+        for result in &self.results {
+            lines.push(format!(
+                "{:<10} {:>9} {:>6} {:>8.2} {:>8.2} {:>8.2} {:>9.2} {:>10}",
+                result.scene,
+                format!("{}x{}", result.width, result.height),
+                result.frames,
+                result.avg_ms,
+                result.p95_ms,
+                result.p99_ms,
+                result.allocations_per_frame,
+                bufpool::format_bytes(result.peak_pool_bytes),
+            ));
+        }
+        lines
+    }
+}
+
+/// A frame time that got worse between two reports
+#[derive(Debug, Clone, PartialEq)]
+pub struct Regression {
+    pub scene: String,
+    pub width: u32,
+    pub height: u32,
+    pub metric: &'static str,
+    pub before: f64,
+    pub after: f64,
+}
+
+impl Regression {
+    /// How much slower, as a fraction of `before`
+    pub fn slowdown(&self) -> f64 {
+        self.after / self.before - 1.0
+    }
+}
+
+/// Frame time metrics that got more than `threshold` slower from `before` to
+/// `after`. Results only one report has are skipped.
+pub fn compare_reports(
+    before: &BenchAllReport,
+    after: &BenchAllReport,
+    threshold: f64,
+) -> Vec<Regression> {
+    let mut regressions = Vec::new();
+    for new in &after.results {
+        let Some(old) = before.results.iter().find(|old| {
+            old.scene == new.scene && old.width == new.width && old.height == new.height
+        }) else {
+            continue;
+        };
+        for metric in ["avg_ms", "p95_ms", "p99_ms"] {
+            let (Some(was), Some(is)) = (old.metric(metric), new.metric(metric)) else {
+                continue;
+            };
+            if was > 0.0 && is > was * (1.0 + threshold) {
+                regressions.push(Regression {
+                    scene: new.scene.clone(),
+                    width: new.width,
+                    height: new.height,
+                    metric,
+                    before: was,
+                    after: is,
+                });
+            }
+        }
+    }
+    regressions
+}
+
+/// The `p`th percentile (0.0..=1.0) of ascending `sorted` by nearest rank
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = (p * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Peak resident memory from /proc, on systems that have it
+fn peak_rss_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
+
+fn total_allocations() -> u64 {
+    bufpool::buffer_stats()
+        .iter()
+        .map(|stats| stats.allocations)
+        .sum()
+}
+
+/// Drags in a circle around the middle of the frame, holding the button for
+/// one `DRAG_PERIOD` and letting go for the next
+fn script_drag(station: &mut StimStation, time: f32) {
+    let config = *station.config();
+    let (cx, cy) = (config.width as f32 / 2.0, config.height as f32 / 2.0);
+    let radius = cx.min(cy) * 0.6;
+    station.handle_cursor(cx + radius * time.cos(), cy + radius * time.sin());
+    let held = ((time / DRAG_PERIOD) as u32).is_multiple_of(2);
+    station.handle_button(MouseButton::Left, held);
+}
+
+/// Runs `scene` on `station` for `seconds` of wall time, and at least
+/// `MIN_BENCH_FRAMES` frames, advancing scene time at 60 fps
+fn bench_scene(
+    station: &mut StimStation,
+    scene: &SceneInfo,
+    seconds: f32,
+) -> Result<SceneBench, EmbedError> {
+    let config = *station.config();
+    station.set_scene(scene.id)?;
+    let mut frame = vec![0; config.frame_len()];
+    let allocations_before = total_allocations();
+    let mut peak_pool_bytes = 0;
+    let mut times = Vec::new();
+    let start = Instant::now();
+    while times.len() < MIN_BENCH_FRAMES || start.elapsed().as_secs_f32() < seconds {
+        if scene.mouse_driven {
+            script_drag(station, times.len() as f32 * FRAME_TIME);
+        }
+        let frame_start = Instant::now();
+        station.render(&mut frame, FRAME_TIME)?;
+        times.push(frame_start.elapsed().as_secs_f64() * 1000.0);
+        peak_pool_bytes = peak_pool_bytes.max(bufpool::total_bytes().0);
+    }
+    if scene.mouse_driven {
+        station.handle_button(MouseButton::Left, false);
+    }
+    let frames = times.len();
+    let allocations = total_allocations() - allocations_before;
+    times.sort_by(f64::total_cmp);
+    Ok(SceneBench {
+        scene: scene.id.to_string(),
+        width: config.width,
+        height: config.height,
+        frames,
+        avg_ms: times.iter().sum::<f64>() / frames as f64,
+        p95_ms: percentile(&times, 0.95),
+        p99_ms: percentile(&times, 0.99),
+        allocations_per_frame: allocations as f64 / frames as f64,
+        peak_pool_bytes,
+        peak_rss_bytes: peak_rss_bytes(),
+    })
+}
+
+/// Runs every scene at every size in `BENCH_SIZES` for `seconds` each, without
+/// a window or audio output. Audio scenes run on the simulated signal.
+/// Fails if a `StimStation` is already alive in this process.
+pub fn run_bench_all(seconds: f32) -> Result<BenchAllReport, EmbedError> {
+    let mut results = Vec::new();
+    for (width, height) in BENCH_SIZES {
+        let mut station = StimStation::new(StimConfig {
+            width,
+            height,
+            audio_playback: false,
+        })?;
+        for scene in scenes::SCENES {
+            results.push(bench_scene(&mut station, scene, seconds)?);
+        }
+    }
+    Ok(BenchAllReport { seconds, results })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(scene: &str, width: u32, avg_ms: f64, p95_ms: f64, p99_ms: f64) -> SceneBench {
+        SceneBench {
+            scene: scene.to_string(),
+            width,
+            height: width / 2,
+            frames: 180,
+            avg_ms,
+            p95_ms,
+            p99_ms,
+            allocations_per_frame: 0.5,
+            peak_pool_bytes: 4 << 20,
+            peak_rss_bytes: None,
+        }
+    }
+
+    #[test]
+    fn test_report_json_round_trips_with_stable_fields() {
+        let report = BenchAllReport {
+            seconds: 3.0,
+            results: vec![result("rays", 800, 4.0, 5.0, 6.0), {
+                let mut world = result("world", 1600, 9.5, 12.0, 15.0);
+                world.peak_rss_bytes = Some(90 << 20);
+                world
+            }],
+        };
+        let json = report.to_json();
+        assert_eq!(json["version"], REPORT_VERSION);
+        let mut keys: Vec<_> = json["results"][0]
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect();
+        keys.sort();
+        assert_eq!(
+            keys,
+            [
+                "allocations_per_frame",
+                "avg_ms",
+                "frames",
+                "height",
+                "p95_ms",
+                "p99_ms",
+                "peak_pool_bytes",
+                "peak_rss_bytes",
+                "scene",
+                "width",
+            ]
+        );
+        let text = serde_json::to_string(&json).unwrap();
+        let parsed = BenchAllReport::from_json(&serde_json::from_str(&text).unwrap());
+        assert_eq!(parsed, Some(report.clone()));
+        assert_eq!(report.table_lines().len(), 3);
+
+        let mut other_version = json.clone();
+        other_version["version"] = json!(REPORT_VERSION + 1);
+        assert_eq!(BenchAllReport::from_json(&other_version), None);
+        assert_eq!(BenchAllReport::from_json(&json!({ "version": 1 })), None);
+
+        let times = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0];
+        assert_eq!(percentile(&times, 0.95), 10.0);
+        assert_eq!(percentile(&times, 0.5), 5.0);
+        assert_eq!(percentile(&[], 0.99), 0.0);
+    }
+
+    #[test]
+    fn test_only_slowdowns_past_the_threshold_are_regressions() {
+        let before = BenchAllReport {
+            seconds: 3.0,
+            results: vec![
+                result("rays", 800, 4.0, 5.0, 6.0),
+                result("rays", 1600, 10.0, 12.0, 14.0),
+                result("tunnel", 800, 3.0, 4.0, 5.0),
+            ],
+        };
+        let after = BenchAllReport {
+            seconds: 3.0,
+            results: vec![
+                // Within 10%, and faster
+                result("rays", 800, 4.39, 4.0, 6.0),
+                // p95 and p99 slower by more than 10%
+                result("rays", 1600, 10.5, 13.5, 20.0),
+                // New scene with nothing to compare to
+                result("world", 800, 50.0, 60.0, 70.0),
+            ],
+        };
+        let regressions = compare_reports(&before, &after, REGRESSION_THRESHOLD);
+        let found: Vec<_> = regressions
+            .iter()
+            .map(|r| (r.scene.as_str(), r.width, r.metric))
+            .collect();
+        assert_eq!(found, [("rays", 1600, "p95_ms"), ("rays", 1600, "p99_ms")]);
+        assert!((regressions[0].slowdown() - 0.125).abs() < 1e-9);
+
+        assert!(compare_reports(&before, &before, REGRESSION_THRESHOLD).is_empty());
+        // Any slowdown counts with a zero threshold
+        assert_eq!(compare_reports(&before, &after, 0.0).len(), 4);
+    }
+}
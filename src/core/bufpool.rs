@@ -0,0 +1,200 @@
+//! Named scratch buffers that are reused across frames instead of being
+//! allocated fresh each time. Every name keeps its own byte counts, so the
+//! debug overlay and `stimstation bench --mem` can show where memory goes.
+
+use std::collections::BTreeMap;
+use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
+
+#[derive(Default)]
+struct Entry {
+    /// Returned buffers waiting to be handed out again
+    free: Vec<Vec<u8>>,
+    current_bytes: usize,
+    peak_bytes: usize,
+    allocations: u64,
+}
+
+static POOL: Mutex<BTreeMap<&'static str, Entry>> = Mutex::new(BTreeMap::new());
+
+fn with_entry<R>(name: &'static str, f: impl FnOnce(&mut Entry) -> R) -> R {
+    let mut pool = POOL.lock().unwrap();
+    f(pool.entry(name).or_default())
+}
+
+/// A zeroed byte buffer borrowed from the pool; it goes back when dropped
+pub struct PooledBuf {
+    name: &'static str,
+    buf: Vec<u8>,
+    /// How many times this buffer had to grow
+    allocations: u32,
+}
+
+/// Borrows a zeroed buffer of `size` bytes under `name`, reusing a returned
+/// one when possible
+pub fn get_buffer(name: &'static str, size: usize) -> PooledBuf {
+    let buf = with_entry(name, |entry| entry.free.pop()).unwrap_or_default();
+    let mut pooled = PooledBuf {
+        name,
+        buf,
+        allocations: 0,
+    };
+    pooled.reset(size);
+    pooled
+}
+
+impl PooledBuf {
+    /// Zeroes the buffer and resizes it to `size` bytes, keeping its allocation
+    /// when it is big enough
+    pub fn reset(&mut self, size: usize) {
+        let before = self.buf.capacity();
+        self.buf.clear();
+        self.buf.resize(size, 0);
+        let after = self.buf.capacity();
+        if after != before {
+            self.allocations += 1;
+            with_entry(self.name, |entry| {
+                entry.current_bytes = entry.current_bytes + after - before;
+                entry.peak_bytes = entry.peak_bytes.max(entry.current_bytes);
+                entry.allocations += 1;
+            });
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Times this buffer has allocated since it was borrowed
+    pub fn allocations(&self) -> u32 {
+        self.allocations
+    }
+}
+
+impl Deref for PooledBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.buf
+    }
+}
+
+impl DerefMut for PooledBuf {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.buf
+    }
+}
+
+impl Drop for PooledBuf {
+    fn drop(&mut self) {
+        let buf = std::mem::take(&mut self.buf);
+        if let Ok(mut pool) = POOL.lock() {
+            pool.entry(self.name).or_default().free.push(buf);
+        }
+    }
+}
+
+/// Memory held under one buffer name, whether borrowed or waiting in the pool
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BufferStats {
+    pub name: &'static str,
+    pub current_bytes: usize,
+    pub peak_bytes: usize,
+    pub allocations: u64,
+}
+
+/// Per-name usage, sorted by name
+pub fn buffer_stats() -> Vec<BufferStats> {
+    POOL.lock()
+        .unwrap()
+        .iter()
+        .map(|(&name, entry)| BufferStats {
+            name,
+            current_bytes: entry.current_bytes,
+            peak_bytes: entry.peak_bytes,
+            allocations: entry.allocations,
+        })
+        .collect()
+}
+
+/// Current and peak bytes summed over every name
+pub fn total_bytes() -> (usize, usize) {
+    buffer_stats()
+        .iter()
+        .fold((0, 0), |(current, peak), stats| {
+            (current + stats.current_bytes, peak + stats.peak_bytes)
+        })
+}
+
+pub fn format_bytes(bytes: usize) -> String {
+    if bytes >= 1 << 20 {
+        format!("{:.1} MiB", bytes as f64 / (1 << 20) as f64)
+    } else {
+        format!("{:.1} KiB", bytes as f64 / 1024.0)
+    }
+}
+
+/// One line per buffer name plus a total, for `bench --mem` and the debug overlay
+pub fn report_lines() -> Vec<String> {
+    let (current, peak) = total_bytes();
+    let mut lines = vec![format!(
+        "Buffers: {} (peak {})",
+        format_bytes(current),
+        format_bytes(peak)
+    )];
+    for stats in buffer_stats() {
+        lines.push(format!(
+            "  {}: {} (peak {}, {} allocs)",
+            stats.name,
+            format_bytes(stats.current_bytes),
+            format_bytes(stats.peak_bytes),
+            stats.allocations
+        ));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(name: &str) -> BufferStats {
+        buffer_stats()
+            .into_iter()
+            .find(|stats| stats.name == name)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_steady_state_reuses_one_allocation() {
+        for _ in 0..100 {
+            let mut buf = get_buffer("test steady", 4096);
+            assert!(buf.iter().all(|&b| b == 0));
+            buf[0] = 7;
+        }
+        let stats = stats("test steady");
+        assert_eq!(stats.allocations, 1);
+        assert_eq!(stats.current_bytes, 4096);
+
+        // Shrinking keeps the allocation; a reused buffer comes back zeroed
+        let buf = get_buffer("test steady", 100);
+        assert_eq!(buf.len(), 100);
+        assert_eq!(buf[0], 0);
+        assert_eq!(buf.allocations(), 0);
+    }
+
+    #[test]
+    fn test_overlapping_borrows_are_counted_separately() {
+        let a = get_buffer("test overlap", 1000);
+        let b = get_buffer("test overlap", 1000);
+        assert_eq!(stats("test overlap").current_bytes, 2000);
+        drop((a, b));
+
+        let mut c = get_buffer("test overlap", 1000);
+        c.reset(3000);
+        let stats = stats("test overlap");
+        assert_eq!(stats.allocations, 3);
+        assert_eq!(stats.peak_bytes, stats.current_bytes);
+        assert!(stats.current_bytes >= 4000);
+    }
+}
@@ -0,0 +1,205 @@
+//! Shift+F12 wallpaper captures: the scene on screen drawn again at several
+//! times the window's resolution, box-filtered down to twice it, and saved as
+//! a PNG.
+//!
+//! The scenes keep their state in process-wide globals sized for the window,
+//! so a capture scales the balls, World, soft body, and bursts up to the large
+//! frame, draws it at the scene time of the frame just shown so nothing moves,
+//! and scales them back down. Scaling by a power of two is exact, so the live
+//! scene carries on where it was. What it costs is a one-frame hitch: the
+//! large frame takes several times as long to draw, and the tunnel rebuilds
+//! its map for the new size and again for the next live frame. HUD text,
+//! captions, and painted strokes keep their pixel size, so they come out
+//! smaller in the capture.
+
+use crate::core::export::ExportError;
+use crate::core::{frame_cap, orchestrator};
+use crate::graphics::draw_ctx::{DrawCtx, QualitySettings, Region, DEFAULT_QUALITY};
+use crate::graphics::post;
+use crate::graphics::trail::TRAIL_CAPACITY;
+use crate::physics::{fireworks, physics, softbody, world};
+use image::RgbaImage;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Saved captures are this many times the window's resolution
+pub const OUTPUT_SCALE: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CaptureOptions {
+    /// Times the window's resolution the scene is drawn at before filtering
+    /// down to `OUTPUT_SCALE`: 2 draws it at the output size, 4 supersamples
+    pub render_scale: u32,
+    /// More rays and full-length trails than the live frame draws
+    pub high_quality: bool,
+}
+
+impl CaptureOptions {
+    pub const DEFAULT: Self = Self {
+        render_scale: 4,
+        high_quality: true,
+    };
+
+    /// The render scale rounded down to a whole multiple of `OUTPUT_SCALE`
+    fn scale(&self) -> u32 {
+        (self.render_scale / OUTPUT_SCALE).max(1) * OUTPUT_SCALE
+    }
+}
+
+impl Default for CaptureOptions {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Size of the capture of a `width` x `height` frame
+pub fn capture_size(width: u32, height: u32) -> (u32, u32) {
+    (width * OUTPUT_SCALE, height * OUTPUT_SCALE)
+}
+
+/// The live settings with pixel sizes grown to a frame `scale` times larger,
+/// and with more detail if asked for
+fn capture_quality(options: &CaptureOptions, scale: u32) -> QualitySettings {
+    let mut quality = QualitySettings {
+        bar_glow_radius: DEFAULT_QUALITY.bar_glow_radius * scale as i32,
+        ..DEFAULT_QUALITY
+    };
+    if options.high_quality {
+        quality.ray_count *= 3;
+        quality.trail_length = TRAIL_CAPACITY;
+        quality.antialias = true;
+        quality.particle_lighting = true;
+    }
+    quality
+}
+
+/// Averages each `factor` x `factor` block of a `width` x `height` RGBA frame
+/// into one pixel. Rows and columns left over past a whole block are dropped.
+pub fn downsample_box(src: &[u8], width: u32, height: u32, factor: u32) -> Vec<u8> {
+    let factor = factor.max(1) as usize;
+    let (width, height) = (width as usize, height as usize);
+    let (out_width, out_height) = (width / factor, height / factor);
+    let area = (factor * factor) as u32;
+    let mut out = Vec::with_capacity(out_width * out_height * 4);
+    for out_y in 0..out_height {
+        for out_x in 0..out_width {
+            let mut sum = [0u32; 4];
+            for y in out_y * factor..(out_y + 1) * factor {
+                let row = (y * width + out_x * factor) * 4;
+                for pixel in src[row..row + factor * 4].chunks_exact(4) {
+                    for (total, &channel) in sum.iter_mut().zip(pixel) {
+                        *total += channel as u32;
+                    }
+                }
+            }
+            out.extend(sum.map(|total| ((total + area / 2) / area) as u8));
+        }
+    }
+    out
+}
+
+/// Grows or shrinks the scene state to a frame `factor` times the size
+fn scale_scene(factor: f32) {
+    physics::scale_balls(factor);
+    world::scale_world(factor);
+    softbody::scale_softbody(factor);
+    fireworks::scale_fireworks(factor);
+}
+
+/// Draws the scene as it was at `time` into a frame `options.render_scale`
+/// times `width` x `height` and filters it down to `capture_size`. Call right
+/// after drawing the live frame for `time`; the orchestrator must be set up.
+pub fn render_capture(width: u32, height: u32, time: f32, options: &CaptureOptions) -> RgbaImage {
+    let scale = options.scale();
+    let (render_width, render_height) = (width * scale, height * scale);
+    let quality = capture_quality(options, scale);
+    let mut frame = vec![0; (render_width * render_height * 4) as usize];
+
+    scale_scene(scale as f32);
+    orchestrator::set_render_scale(scale as f32);
+    frame_cap::force_scene_render();
+    let mut ctx = DrawCtx::new(
+        &mut frame,
+        Region::new(0, 0, render_width, render_height),
+        render_width,
+        time,
+    );
+    ctx.quality = &quality;
+    orchestrator::render_frame(&mut ctx);
+    orchestrator::set_render_scale(1.0);
+    scale_scene(1.0 / scale as f32);
+    frame_cap::force_scene_render();
+    post::apply_post(&mut frame, time);
+
+    let mut pixels = downsample_box(&frame, render_width, render_height, scale / OUTPUT_SCALE);
+    for pixel in pixels.chunks_exact_mut(4) {
+        pixel[3] = 255;
+    }
+    let (out_width, out_height) = capture_size(width, height);
+    RgbaImage::from_raw(out_width, out_height, pixels).expect("capture matches its size")
+}
+
+/// Where captures are saved: the pictures folder, or the data folder on
+/// systems without one
+pub fn capture_dir() -> Option<PathBuf> {
+    dirs::picture_dir()
+        .or_else(dirs::data_dir)
+        .map(|dir| dir.join("stimstation"))
+}
+
+/// Saves `image` in `dir` as `stimstation-<unix_secs>.png`, numbering it when
+/// that name is taken. Returns the path written.
+pub fn save_capture(image: &RgbaImage, dir: &Path, unix_secs: u64) -> Result<PathBuf, ExportError> {
+    fs::create_dir_all(dir)?;
+    let mut path = dir.join(format!("stimstation-{}.png", unix_secs));
+    let mut n = 1;
+    while path.exists() {
+        n += 1;
+        path = dir.join(format!("stimstation-{}-{}.png", unix_secs, n));
+    }
+    image.save(&path)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_box_filter_averages_each_block() {
+        // 4x2 frame: two 2x2 blocks, one of flat color and one checkered
+        let (a, b, c) = ([200, 100, 0, 255], [0, 0, 0, 255], [255, 255, 255, 255]);
+        let src: Vec<u8> = [a, a, b, c, a, a, c, b].concat();
+        assert_eq!(
+            downsample_box(&src, 4, 2, 2),
+            [200, 100, 0, 255, 128, 128, 128, 255]
+        );
+        // A factor of 1 is a copy, and a partial block at the edge is dropped
+        assert_eq!(downsample_box(&src, 4, 2, 1), src);
+        assert_eq!(downsample_box(&src, 4, 2, 3), Vec::<u8>::new());
+        let three_wide: Vec<u8> = [a, a, b, a, a, c].concat();
+        assert_eq!(downsample_box(&three_wide, 3, 2, 2), a);
+    }
+
+    #[test]
+    fn test_capture_size_is_twice_the_frame_whatever_the_render_scale() {
+        assert_eq!(capture_size(1600, 800), (3200, 1600));
+        for (render_scale, scale) in [(0, 2), (2, 2), (3, 2), (4, 4), (8, 8)] {
+            let options = CaptureOptions {
+                render_scale,
+                high_quality: false,
+            };
+            assert_eq!(options.scale(), scale);
+            // Glow grows with the frame so it looks the same once filtered down
+            let quality = capture_quality(&options, scale);
+            assert_eq!(
+                quality.bar_glow_radius,
+                DEFAULT_QUALITY.bar_glow_radius * scale as i32
+            );
+            assert_eq!(quality.ray_count, DEFAULT_QUALITY.ray_count);
+        }
+        let high = capture_quality(&CaptureOptions::DEFAULT, 4);
+        assert!(high.ray_count > DEFAULT_QUALITY.ray_count);
+        assert_eq!(high.trail_length, TRAIL_CAPACITY);
+    }
+}
@@ -0,0 +1,112 @@
+/// Draw layers for overlays, in the order they are composited.
+/// Anything queued on a later layer is drawn on top of earlier layers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum OverlayLayer {
+    SceneEffects,
+    Hud,
+    Notifications,
+    Help,
+    Menu,
+    Debug,
+}
+
+type DrawFn<'a> = Box<dyn FnOnce(&mut [u8]) + 'a>;
+
+/// Collects overlay draw calls for a single frame and runs them in layer order.
+/// Calls queued on the same layer keep the order they were queued in.
+pub struct Compositor<'a> {
+    queue: Vec<(OverlayLayer, DrawFn<'a>)>,
+}
+
+impl<'a> Compositor<'a> {
+    pub fn new() -> Self {
+        Self { queue: Vec::new() }
+    }
+
+    /// Queues a draw closure on the given layer.
+    pub fn enqueue(&mut self, layer: OverlayLayer, draw: impl FnOnce(&mut [u8]) + 'a) {
+        self.queue.push((layer, Box::new(draw)));
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Number of draw calls queued on a single layer.
+    pub fn count_on(&self, layer: OverlayLayer) -> usize {
+        self.queue
+            .iter()
+            .filter(|(queued, _)| *queued == layer)
+            .count()
+    }
+
+    /// Runs every queued closure against the frame, lowest layer first.
+    pub fn flush(mut self, frame: &mut [u8]) {
+        // Stable sort keeps submission order within a layer
+        self.queue.sort_by_key(|(layer, _)| *layer);
+        for (_, draw) in self.queue {
+            draw(frame);
+        }
+    }
+}
+
+impl Default for Compositor<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[test]
+    fn test_flush_follows_layer_order() {
+        let order = RefCell::new(Vec::new());
+        let mut compositor = Compositor::new();
+        let scrambled = [
+            OverlayLayer::Menu,
+            OverlayLayer::SceneEffects,
+            OverlayLayer::Debug,
+            OverlayLayer::Hud,
+            OverlayLayer::Help,
+            OverlayLayer::Notifications,
+        ];
+        for layer in scrambled {
+            let order = &order;
+            compositor.enqueue(layer, move |_| order.borrow_mut().push(layer));
+        }
+        compositor.flush(&mut []);
+
+        assert_eq!(
+            *order.borrow(),
+            vec![
+                OverlayLayer::SceneEffects,
+                OverlayLayer::Hud,
+                OverlayLayer::Notifications,
+                OverlayLayer::Help,
+                OverlayLayer::Menu,
+                OverlayLayer::Debug,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_same_layer_keeps_submission_order() {
+        let order = RefCell::new(Vec::new());
+        let mut compositor = Compositor::new();
+        for i in 0..3 {
+            let order = &order;
+            compositor.enqueue(OverlayLayer::Hud, move |_| order.borrow_mut().push(i));
+        }
+        compositor.enqueue(OverlayLayer::SceneEffects, |_| order.borrow_mut().push(99));
+        compositor.flush(&mut []);
+
+        assert_eq!(*order.borrow(), vec![99, 0, 1, 2]);
+    }
+}
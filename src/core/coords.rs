@@ -0,0 +1,156 @@
+//! Converts between window pixels and frame buffer pixels. The buffer is drawn
+//! the way pixels draws it: scaled by the largest whole factor that fits
+//! (never below 1) and centered, leaving a letterbox around it. The DPI scale
+//! factor only shows up through the window's physical size, so every mouse
+//! consumer maps through the same `WindowMapper` and agrees on where a click lands.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WindowMapper {
+    buffer_width: u32,
+    buffer_height: u32,
+    window_width: u32,
+    window_height: u32,
+}
+
+impl WindowMapper {
+    /// Maps a `buffer_width` x `buffer_height` frame shown in a window of this
+    /// physical size
+    pub fn new(
+        buffer_width: u32,
+        buffer_height: u32,
+        window_width: u32,
+        window_height: u32,
+    ) -> Self {
+        Self {
+            buffer_width: buffer_width.max(1),
+            buffer_height: buffer_height.max(1),
+            window_width,
+            window_height,
+        }
+    }
+
+    /// Call on resize and scale factor changes with the new physical size
+    pub fn resize(&mut self, window_width: u32, window_height: u32) {
+        self.window_width = window_width;
+        self.window_height = window_height;
+    }
+
+    /// Window pixels per buffer pixel
+    pub fn scale(&self) -> f32 {
+        let width_ratio = (self.window_width as f32 / self.buffer_width as f32).max(1.0);
+        let height_ratio = (self.window_height as f32 / self.buffer_height as f32).max(1.0);
+        width_ratio.min(height_ratio).floor()
+    }
+
+    /// Top-left corner of the buffer in window pixels; negative when the window
+    /// is smaller than the buffer and the edges are cropped
+    fn origin(&self) -> (f32, f32) {
+        let scale = self.scale();
+        (
+            (self.window_width as f32 - self.buffer_width as f32 * scale) / 2.0,
+            (self.window_height as f32 - self.buffer_height as f32 * scale) / 2.0,
+        )
+    }
+
+    /// The buffer position under a physical window position, or None over the letterbox
+    pub fn window_to_buffer(&self, phys_x: f32, phys_y: f32) -> Option<(f32, f32)> {
+        let scale = self.scale();
+        let (left, top) = self.origin();
+        let x = (phys_x - left) / scale;
+        let y = (phys_y - top) / scale;
+        let inside = (0.0..self.buffer_width as f32).contains(&x)
+            && (0.0..self.buffer_height as f32).contains(&y);
+        inside.then_some((x, y))
+    }
+
+    /// The physical window position a buffer position is drawn at
+    pub fn buffer_to_window(&self, x: f32, y: f32) -> (f32, f32) {
+        let scale = self.scale();
+        let (left, top) = self.origin();
+        (left + x * scale, top + y * scale)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A window of `logical` size at `dpi`, as winit reports its physical size
+    fn physical(logical: (u32, u32), dpi: f64) -> (u32, u32) {
+        (
+            (logical.0 as f64 * dpi).round() as u32,
+            (logical.1 as f64 * dpi).round() as u32,
+        )
+    }
+
+    #[test]
+    fn test_same_spot_maps_to_same_buffer_pixel_across_sizes_and_dpi() {
+        let (width, height) = (1600, 800);
+        // (window size, DPI, expected scale, expected left/top letterbox)
+        let cases = [
+            ((1600, 800), 1.0, 1.0, (0.0, 0.0)),
+            ((1600, 800), 1.5, 1.0, (400.0, 200.0)),
+            ((1600, 800), 2.0, 2.0, (0.0, 0.0)),
+            // Fullscreen on a 16:9 monitor leaves bars on every side
+            ((1920, 1080), 1.0, 1.0, (160.0, 140.0)),
+            ((2560, 1440), 1.5, 2.0, (320.0, 280.0)),
+            // A window narrower than the buffer crops the sides
+            ((1200, 900), 1.0, 1.0, (-200.0, 50.0)),
+        ];
+        for (logical, dpi, scale, (left, top)) in cases {
+            let (window_width, window_height) = physical(logical, dpi);
+            let mapper = WindowMapper::new(width, height, window_width, window_height);
+            let label = format!("{:?} at {}x", logical, dpi);
+            assert_eq!(mapper.scale(), scale, "{}", label);
+            assert_eq!(mapper.buffer_to_window(0.0, 0.0), (left, top), "{}", label);
+
+            // The visual spot of every probe comes back to the same buffer pixel
+            for (x, y) in [
+                (0.0, 0.0),
+                (800.0, 400.0),
+                (1599.5, 799.5),
+                (123.25, 456.75),
+            ] {
+                let (wx, wy) = mapper.buffer_to_window(x, y);
+                assert_eq!(mapper.window_to_buffer(wx, wy), Some((x, y)), "{}", label);
+            }
+
+            // The letterbox, where there is one, isn't part of the buffer
+            if left > 0.0 {
+                assert_eq!(
+                    mapper.window_to_buffer(left - 1.0, top + 10.0),
+                    None,
+                    "{}",
+                    label
+                );
+                let right = left + width as f32 * scale;
+                assert_eq!(
+                    mapper.window_to_buffer(right, top + 10.0),
+                    None,
+                    "{}",
+                    label
+                );
+            }
+            if top > 0.0 {
+                assert_eq!(
+                    mapper.window_to_buffer(left + 10.0, top - 1.0),
+                    None,
+                    "{}",
+                    label
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_resize_moves_the_mapping() {
+        let mut mapper = WindowMapper::new(1600, 800, 1600, 800);
+        assert_eq!(mapper.window_to_buffer(100.0, 100.0), Some((100.0, 100.0)));
+        // On a 150% monitor the buffer stays at 1x inside a letterbox
+        mapper.resize(2400, 1200);
+        assert_eq!(mapper.window_to_buffer(100.0, 100.0), None);
+        assert_eq!(mapper.window_to_buffer(500.0, 300.0), Some((100.0, 100.0)));
+        mapper.resize(3200, 1600);
+        assert_eq!(mapper.window_to_buffer(200.0, 200.0), Some((100.0, 100.0)));
+    }
+}
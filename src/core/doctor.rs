@@ -0,0 +1,447 @@
+//! `stimstation doctor`: checks that rendering, text, audio, the config
+//! directory, and the clock work on this machine, without opening a window,
+//! and reports each as PASS or FAIL with what to try next. Every check runs
+//! on its own, so one failing (or panicking) doesn't stop the rest.
+
+use crate::core::orchestrator;
+use crate::graphics::draw_ctx::{DrawCtx, Region};
+use ab_glyph::{Font, FontArc, PxScale};
+use rodio::cpal::traits::{DeviceTrait, HostTrait};
+use serde_json::{json, Value};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Bumped whenever a JSON report field is renamed or removed
+pub const REPORT_VERSION: u32 = 1;
+/// Size of the frames the render check draws
+const CHECK_SIZE: (u32, u32) = (64, 32);
+/// Bars of the test pattern, left to right
+const PATTERN_COLORS: [[u8; 4]; 8] = [
+    [255, 255, 255, 255],
+    [255, 255, 0, 255],
+    [0, 255, 255, 255],
+    [0, 255, 0, 255],
+    [255, 0, 255, 255],
+    [255, 0, 0, 255],
+    [0, 0, 255, 255],
+    [0, 0, 0, 255],
+];
+/// How long the clock check runs, and how long it sleeps between readings
+const TIMING_LOOP: Duration = Duration::from_secs(1);
+const TIMING_TICK: Duration = Duration::from_millis(10);
+/// How far the monotonic and wall clocks may disagree over the loop
+const CLOCK_TOLERANCE: f64 = 0.05;
+/// Fewest readings the loop must get; fewer means sleeps overshoot badly
+const MIN_TICKS: u32 = 20;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CheckResult {
+    pub name: &'static str,
+    pub passed: bool,
+    /// What was found, or why the check failed
+    pub detail: String,
+    /// What to try when it failed
+    pub advice: &'static str,
+}
+
+impl CheckResult {
+    fn to_json(&self) -> Value {
+        json!({
+            "name": self.name,
+            "passed": self.passed,
+            "detail": self.detail,
+            "advice": if self.passed { None } else { Some(self.advice) },
+        })
+    }
+}
+
+/// Runs `check`, turning a panic into a failure like any other
+fn run_check(
+    name: &'static str,
+    advice: &'static str,
+    check: impl FnOnce() -> Result<String, String>,
+) -> CheckResult {
+    let outcome = panic::catch_unwind(AssertUnwindSafe(check)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<String>()
+            .map(String::as_str)
+            .or_else(|| payload.downcast_ref::<&str>().copied())
+            .unwrap_or("unknown panic");
+        Err(format!("crashed: {}", message))
+    });
+    let (passed, detail) = match outcome {
+        Ok(detail) => (true, detail),
+        Err(detail) => (false, detail),
+    };
+    CheckResult {
+        name,
+        passed,
+        detail,
+        advice,
+    }
+}
+
+/// Every check, in the order they are reported
+pub fn run_checks() -> Vec<CheckResult> {
+    vec![
+        run_check(
+            "rendering",
+            "Update the graphics driver, or report a bug with `doctor --json`",
+            || check_rendering(draw_test_pattern, render_scene_frame),
+        ),
+        run_check(
+            "font",
+            "Install a monospace font (e.g. DejaVu Sans Mono); HUD text needs one",
+            || check_font(crate::text::text_rendering::load_system_font),
+        ),
+        run_check(
+            "audio",
+            "Connect or enable an output device, close apps holding it exclusively, \
+             or run with --no-audio",
+            || check_audio(&probe_audio_devices(), open_default_output),
+        ),
+        run_check(
+            "config dir",
+            "Settings and scene state won't be saved; make the directory writable",
+            || check_config_dir(dirs::config_dir().map(|dir| dir.join("stimstation"))),
+        ),
+        run_check(
+            "clock",
+            "Animation speed will be off; check for a misbehaving system clock",
+            || judge_timing(measure_timing()),
+        ),
+    ]
+}
+
+/// PASS/FAIL table with the advice under each failure
+pub fn format_table(results: &[CheckResult]) -> String {
+    let name_width = results
+        .iter()
+        .map(|result| result.name.len())
+        .max()
+        .unwrap_or(0)
+        .max("CHECK".len());
+    let mut table = format!("{:<name_width$}  RESULT  DETAIL\n", "CHECK");
+    for result in results {
+        let status = if result.passed { "PASS" } else { "FAIL" };
+        table += &format!(
+            "{:<name_width$}  {:<6}  {}\n",
+            result.name, status, result.detail
+        );
+        if !result.passed {
+            table += &format!("{:<name_width$}          -> {}\n", "", result.advice);
+        }
+    }
+    table
+}
+
+/// The report `--json` prints, for attaching to bug reports
+pub fn to_json(results: &[CheckResult]) -> Value {
+    json!({
+        "version": REPORT_VERSION,
+        "os": std::env::consts::OS,
+        "arch": std::env::consts::ARCH,
+        "passed": results.iter().all(|result| result.passed),
+        "checks": results.iter().map(CheckResult::to_json).collect::<Vec<_>>(),
+    })
+}
+
+/// FNV-1a over the frame's bytes
+fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Vertical color bars drawn through the same context the scenes use
+fn draw_test_pattern(ctx: &mut DrawCtx) {
+    let bar = ctx.width() / PATTERN_COLORS.len() as u32;
+    for (i, &color) in PATTERN_COLORS.iter().enumerate() {
+        ctx.fill_rect((i as u32 * bar) as i32, 0, bar, ctx.height(), color);
+    }
+}
+
+/// The bytes `draw_test_pattern` should produce, written directly
+fn expected_pattern(width: u32, height: u32) -> Vec<u8> {
+    let bar = width / PATTERN_COLORS.len() as u32;
+    (0..height)
+        .flat_map(|_| 0..width)
+        .flat_map(|x| PATTERN_COLORS[(x / bar) as usize])
+        .collect()
+}
+
+/// Draws one frame of the current scene the way `render` and `bench` do
+fn render_scene_frame(ctx: &mut DrawCtx) {
+    orchestrator::init_headless();
+    orchestrator::render_frame(ctx);
+}
+
+/// Draws the test pattern with `draw_pattern` and compares its checksum
+/// with the expected one, then draws a scene frame with `render_scene`,
+/// which must not come out black
+fn check_rendering(
+    draw_pattern: impl FnOnce(&mut DrawCtx),
+    render_scene: impl FnOnce(&mut DrawCtx),
+) -> Result<String, String> {
+    let (width, height) = CHECK_SIZE;
+    let region = Region::new(0, 0, width, height);
+    let mut frame = vec![0; (width * height * 4) as usize];
+    draw_pattern(&mut DrawCtx::new(&mut frame, region, width, 0.0));
+    let (drawn, expected) = (checksum(&frame), checksum(&expected_pattern(width, height)));
+    if drawn != expected {
+        return Err(format!(
+            "test pattern checksum {:016x}, expected {:016x}",
+            drawn, expected
+        ));
+    }
+
+    frame.fill(0);
+    render_scene(&mut DrawCtx::new(&mut frame, region, width, 1.0));
+    if frame.chunks_exact(4).all(|pixel| pixel[..3] == [0, 0, 0]) {
+        return Err("the scene rendered an all-black frame".to_string());
+    }
+    Ok(format!(
+        "test pattern {:016x}, scene frame {:016x}",
+        drawn,
+        checksum(&frame)
+    ))
+}
+
+/// Loads the HUD font with `load` and rasterizes a glyph from it
+fn check_font(load: impl FnOnce() -> Result<FontArc, String>) -> Result<String, String> {
+    let font = load()?;
+    let glyph = font.glyph_id('A').with_scale(PxScale::from(20.0));
+    let outlined = font
+        .outline_glyph(glyph)
+        .ok_or("the font has no outline for 'A'")?;
+    let mut covered = 0;
+    outlined.draw(|_, _, coverage| {
+        if coverage > 0.5 {
+            covered += 1;
+        }
+    });
+    if covered == 0 {
+        return Err("'A' rasterized to nothing".to_string());
+    }
+    Ok(format!("'A' rasterized to {} pixels", covered))
+}
+
+/// Audio devices the default host lists
+#[derive(Debug, Clone, Default, PartialEq)]
+struct AudioDevices {
+    outputs: Vec<String>,
+    inputs: Vec<String>,
+}
+
+fn probe_audio_devices() -> AudioDevices {
+    let host = rodio::cpal::default_host();
+    let names = |devices: Option<Vec<rodio::Device>>| {
+        devices
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|device| device.name().ok())
+            .collect()
+    };
+    AudioDevices {
+        outputs: names(host.output_devices().ok().map(Iterator::collect)),
+        inputs: names(host.input_devices().ok().map(Iterator::collect)),
+    }
+}
+
+/// Opens the default output stream and closes it again straight away
+fn open_default_output() -> Result<(), String> {
+    rodio::OutputStream::try_default()
+        .map(drop)
+        .map_err(|e| e.to_string())
+}
+
+/// Needs an output device, and the default output stream to open with
+/// `open_default`. Inputs are only listed.
+fn check_audio(
+    devices: &AudioDevices,
+    open_default: impl FnOnce() -> Result<(), String>,
+) -> Result<String, String> {
+    let found = format!(
+        "{} output(s), {} input(s)",
+        devices.outputs.len(),
+        devices.inputs.len()
+    );
+    if devices.outputs.is_empty() {
+        return Err(format!("{}; no output device", found));
+    }
+    open_default().map_err(|e| format!("{}; default output won't open: {}", found, e))?;
+    Ok(format!("{}; default output opened", found))
+}
+
+/// Creates `dir` if needed and writes, reads back, and removes a file in it
+fn check_config_dir(dir: Option<PathBuf>) -> Result<String, String> {
+    let dir = dir.ok_or("this system has no config directory")?;
+    let fail =
+        |what: &str, e: std::io::Error| format!("can't {} in {}: {}", what, dir.display(), e);
+    std::fs::create_dir_all(&dir).map_err(|e| fail("create the directory", e))?;
+    let probe = dir.join(".doctor-probe");
+    write_probe(&probe).map_err(|e| fail("write", e))?;
+    std::fs::remove_file(&probe).map_err(|e| fail("remove a file", e))?;
+    Ok(format!("{} is writable", dir.display()))
+}
+
+fn write_probe(path: &Path) -> std::io::Result<()> {
+    std::fs::write(path, b"stimstation")?;
+    if std::fs::read(path)? != b"stimstation" {
+        return Err(std::io::Error::other("the file read back differently"));
+    }
+    Ok(())
+}
+
+/// What the clock loop measured
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct TimingSample {
+    /// Elapsed by `Instant`, which animation runs on
+    monotonic: Duration,
+    /// Elapsed by the wall clock over the same loop
+    wall: Option<Duration>,
+    /// Readings taken, one per sleep
+    ticks: u32,
+}
+
+/// Sleeps in short ticks until `TIMING_LOOP` has passed
+fn measure_timing() -> TimingSample {
+    let wall_start = SystemTime::now();
+    let start = Instant::now();
+    let mut ticks = 0;
+    while start.elapsed() < TIMING_LOOP {
+        std::thread::sleep(TIMING_TICK);
+        ticks += 1;
+    }
+    TimingSample {
+        monotonic: start.elapsed(),
+        wall: wall_start.elapsed().ok(),
+        ticks,
+    }
+}
+
+fn judge_timing(sample: TimingSample) -> Result<String, String> {
+    let monotonic = sample.monotonic.as_secs_f64();
+    let wall = sample
+        .wall
+        .ok_or("the wall clock went backwards")?
+        .as_secs_f64();
+    if (monotonic - wall).abs() > monotonic * CLOCK_TOLERANCE {
+        return Err(format!(
+            "monotonic clock measured {:.3}s while the wall clock measured {:.3}s",
+            monotonic, wall
+        ));
+    }
+    if sample.ticks < MIN_TICKS {
+        return Err(format!(
+            "only {} {}ms sleeps fit in {:.3}s",
+            sample.ticks,
+            TIMING_TICK.as_millis(),
+            monotonic
+        ));
+    }
+    Ok(format!(
+        "{:.3}s measured, {} ticks",
+        monotonic, sample.ticks
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn no_scene(ctx: &mut DrawCtx) {
+        ctx.clear([0, 0, 0, 255]);
+    }
+
+    fn lit_scene(ctx: &mut DrawCtx) {
+        ctx.fill_rect(3, 3, 4, 4, [200, 100, 50, 255]);
+    }
+
+    #[test]
+    fn test_rendering_fails_on_a_wrong_pattern_or_a_black_frame() {
+        assert!(check_rendering(draw_test_pattern, lit_scene).is_ok());
+        // A bar missing from the pattern changes the checksum
+        let broken = |ctx: &mut DrawCtx| {
+            draw_test_pattern(ctx);
+            ctx.fill_rect(0, 0, 8, 32, [0, 0, 0, 255]);
+        };
+        let err = check_rendering(broken, lit_scene).unwrap_err();
+        assert!(err.contains("checksum"), "{}", err);
+        let err = check_rendering(draw_test_pattern, no_scene).unwrap_err();
+        assert!(err.contains("black"), "{}", err);
+    }
+
+    #[test]
+    fn test_font_failures_are_reported() {
+        let err = check_font(|| Err("no monospace font found".to_string())).unwrap_err();
+        assert_eq!(err, "no monospace font found");
+    }
+
+    #[test]
+    fn test_audio_needs_an_output_that_opens() {
+        let devices = AudioDevices {
+            outputs: vec!["Speakers".to_string()],
+            inputs: Vec::new(),
+        };
+        assert!(check_audio(&devices, || Ok(())).is_ok());
+        let err = check_audio(&devices, || Err("device busy".to_string())).unwrap_err();
+        assert!(err.contains("device busy"), "{}", err);
+        // Without an output, opening isn't even tried
+        let err = check_audio(&AudioDevices::default(), || unreachable!()).unwrap_err();
+        assert!(err.contains("no output device"), "{}", err);
+    }
+
+    #[test]
+    fn test_config_dir_must_be_creatable_and_writable() {
+        let base = std::env::temp_dir().join(format!("stimstation-doctor-{}", std::process::id()));
+        let dir = base.join("config");
+        assert!(check_config_dir(Some(dir.clone())).is_ok());
+        assert!(!dir.join(".doctor-probe").exists());
+        // A file where the directory should be
+        let blocked = base.join("blocked");
+        std::fs::write(&blocked, b"").unwrap();
+        let err = check_config_dir(Some(blocked.join("config"))).unwrap_err();
+        assert!(err.contains("create the directory"), "{}", err);
+        assert!(check_config_dir(None).is_err());
+        let _ = std::fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn test_timing_rejects_disagreeing_clocks_and_slow_sleeps() {
+        let good = TimingSample {
+            monotonic: Duration::from_millis(1005),
+            wall: Some(Duration::from_millis(1004)),
+            ticks: 95,
+        };
+        assert!(judge_timing(good).is_ok());
+        let drifting = TimingSample {
+            wall: Some(Duration::from_millis(1500)),
+            ..good
+        };
+        assert!(judge_timing(drifting).unwrap_err().contains("wall clock"));
+        let backwards = TimingSample { wall: None, ..good };
+        assert!(judge_timing(backwards).is_err());
+        let stalled = TimingSample { ticks: 3, ..good };
+        assert!(judge_timing(stalled).unwrap_err().contains("sleeps"));
+    }
+
+    #[test]
+    fn test_a_panicking_check_fails_without_stopping_the_others() {
+        let results = [
+            run_check("first", "advice", || panic!("boom")),
+            run_check("second", "advice", || Ok("fine".to_string())),
+        ];
+        assert!(!results[0].passed);
+        assert!(results[0].detail.contains("boom"), "{}", results[0].detail);
+        assert!(results[1].passed);
+
+        let table = format_table(&results);
+        assert!(table.contains("FAIL"));
+        assert!(table.contains("-> advice"));
+        let report = to_json(&results);
+        assert_eq!(report["passed"], false);
+        assert_eq!(report["checks"][1]["advice"], Value::Null);
+    }
+}
@@ -0,0 +1,635 @@
+//! `StimStation`: the renderer as a library, for hosts that own their window
+//! and event loop. Feed it key and cursor events, hand it a frame buffer each
+//! frame, and read back what it drew.
+//!
+//! Each `StimStation` keeps its own scenes, balls, audio bars, and the
+//! per-frame state around them (see `station_state`). Settings, the theme,
+//! the edge sorters, the overlays, and audio playback are still one per
+//! process: stations running side by side, on one thread or several, see
+//! each other's changes to those.
+
+use crate::audio::features;
+use crate::core::capture::{self, CaptureOptions};
+use crate::core::input_record::InputFrame;
+use crate::core::presets::{self, Preset};
+use crate::core::settings::Settings;
+use crate::core::station_state::{self, StationState};
+use crate::core::{orchestrator, pacing, scenes, settings_history};
+use crate::graphics::draw_ctx::{DrawCtx, Region};
+use crate::graphics::palette_extract::{self, PressOutcome};
+use crate::graphics::{longexposure, post, screen_shake, theme};
+use crate::physics::{detect_corner, fireworks};
+use crate::types::{Position, HEIGHT, WIDTH};
+use crate::ui::{calibration, device_picker, help_overlay, menu, timeline, toast};
+use image::RgbaImage;
+use log::{info, warn};
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use winit::event::MouseButton;
+use winit::keyboard::KeyCode;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StimConfig {
+    /// Size of the frames passed to `render`, in pixels
+    pub width: u32,
+    pub height: u32,
+    /// Play audio through the default output device. Off, audio-reactive
+    /// scenes still run from their analysis but nothing is heard. Ignored
+    /// while audio is disabled (`--no-audio`).
+    pub audio_playback: bool,
+}
+
+impl StimConfig {
+    pub const DEFAULT: Self = Self {
+        width: WIDTH,
+        height: HEIGHT,
+        audio_playback: true,
+    };
+
+    /// Bytes in one RGBA frame of this size
+    pub fn frame_len(&self) -> usize {
+        (self.width * self.height * 4) as usize
+    }
+}
+
+impl Default for StimConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EmbedError {
+    UnknownScene(String),
+    /// The frame passed to `render` doesn't match the configured size
+    FrameSize {
+        expected: usize,
+        actual: usize,
+    },
+}
+
+impl fmt::Display for EmbedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmbedError::UnknownScene(id) => write!(f, "no scene called `{}`", id),
+            EmbedError::FrameSize { expected, actual } => {
+                write!(f, "frame is {} bytes, expected {}", actual, expected)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EmbedError {}
+
+/// What the last `render` did
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameStats {
+    /// Frames rendered so far
+    pub frames: u64,
+    /// Scene time of the last frame, in seconds
+    pub time: f32,
+    /// Id of the scene on screen
+    pub scene: &'static str,
+    /// Full-frame clears the last frame needed
+    pub frame_clears: usize,
+    /// Wall time spent drawing the last frame
+    pub render_time: Duration,
+    /// Corner hits of the bouncing balls so far
+    pub corner_hits: u32,
+}
+
+/// The stimstation renderer, driven by its host
+///
+/// ```no_run
+/// use stimstation::{StimConfig, StimStation};
+/// use winit::keyboard::KeyCode;
+///
+/// let config = StimConfig::default();
+/// let mut station = StimStation::new(config)?;
+/// station.set_scene("world")?;
+/// let mut frame = vec![0; config.frame_len()];
+/// station.handle_key(KeyCode::KeyG, true);
+/// station.render(&mut frame, 1.0 / 60.0)?;
+/// println!("drew {} in {:?}", station.stats().scene, station.stats().render_time);
+/// # Ok::<(), stimstation::EmbedError>(())
+/// ```
+pub struct StimStation {
+    config: StimConfig,
+    /// The scenes, balls, and audio bars this station drives
+    state: Arc<StationState>,
+    /// Input gathered from `handle_*` calls since the last frame
+    pending: InputFrame,
+    time: f32,
+    stats: FrameStats,
+}
+
+impl StimStation {
+    /// Starts audio, text, and the scenes. The first station on a thread
+    /// takes over the scene toggles set before it, such as start-up flags.
+    pub fn new(config: StimConfig) -> Result<Self, EmbedError> {
+        let state = station_state::claim();
+        if config.audio_playback && crate::audio::audio_enabled() {
+            orchestrator::init();
+        } else {
+            orchestrator::init_headless();
+        }
+        Ok(Self {
+            config,
+            state,
+            pending: InputFrame::default(),
+            time: 0.0,
+            stats: FrameStats {
+                frames: 0,
+                time: 0.0,
+                scene: scenes::active_scene().id,
+                frame_clears: 0,
+                render_time: Duration::ZERO,
+                corner_hits: detect_corner::get_corner_hits(),
+            },
+        })
+    }
+
+    pub fn config(&self) -> &StimConfig {
+        &self.config
+    }
+
+    /// Switches to the scene with `id`; see `scenes::SCENES` for the ids
+    pub fn set_scene(&mut self, id: &str) -> Result<(), EmbedError> {
+        station_state::make_current(&self.state);
+        let scene = scenes::find_scene(id).ok_or_else(|| EmbedError::UnknownScene(id.into()))?;
+        scene.enter();
+        Ok(())
+    }
+
+    /// Id of the scene on screen
+    pub fn scene(&self) -> &'static str {
+        station_state::make_current(&self.state);
+        scenes::active_scene().id
+    }
+
+    /// A key went down or came up; keys stimstation doesn't bind are ignored
+    pub fn handle_key(&mut self, key: KeyCode, pressed: bool) {
+        self.pending.set_key(key, pressed);
+    }
+
+    /// The cursor moved to (x, y) in frame pixels
+    pub fn handle_cursor(&mut self, x: f32, y: f32) {
+        self.pending.cursor = Some((x, y));
+    }
+
+    pub fn handle_button(&mut self, button: MouseButton, pressed: bool) {
+        self.pending.set_button(button, pressed);
+    }
+
+    /// The scroll wheel turned by `lines`, positive away from the user
+    pub fn handle_scroll(&mut self, lines: f32) {
+        self.pending.scroll += lines;
+    }
+
+    /// Applies the input gathered since the last frame, advances `dt` seconds,
+    /// and draws the frame into `frame`, which must be `config.frame_len()` bytes
+    pub fn render(&mut self, frame: &mut [u8], dt: f32) -> Result<(), EmbedError> {
+        self.check_frame(frame)?;
+        station_state::make_current(&self.state);
+        let input = InputFrame { dt, ..self.pending };
+        self.pending = input.carried_over();
+        self.apply_input(&input);
+        crate::physics::world::handle_mouse(input.cursor, input.mouse_held(MouseButton::Left));
+        crate::graphics::shatter::set_cursor(input.cursor.map(Position::from));
+        self.render_at(frame, self.time + dt)
+    }
+
+    /// Draws the frame at scene time `time` without applying any input, for
+    /// hosts that keep their own clock
+    pub fn render_at(&mut self, frame: &mut [u8], time: f32) -> Result<(), EmbedError> {
+        self.check_frame(frame)?;
+        station_state::make_current(&self.state);
+        let (width, height) = (self.config.width, self.config.height);
+        let start = Instant::now();
+        let mut ctx = DrawCtx::new(frame, Region::new(0, 0, width, height), width, time);
+        orchestrator::render_frame(&mut ctx);
+        if let Some(palette) = palette_extract::apply_frame_theme(frame, width, height, time) {
+            let colors: Vec<String> = palette
+                .iter()
+                .map(|swatch| palette_extract::hex(swatch.color))
+                .collect();
+            toast::show_toast(vec![
+                format!("Theme from the frame: {}", colors.join(" ")),
+                "Ctrl+T within 5s reverts".to_string(),
+            ]);
+        }
+        screen_shake::apply_screen_shake(frame, width, height, time);
+        post::apply_post(frame, time);
+        self.time = time;
+        self.stats = FrameStats {
+            frames: self.stats.frames + 1,
+            time,
+            scene: scenes::active_scene().id,
+            frame_clears: orchestrator::last_frame_clears(),
+            render_time: start.elapsed(),
+            corner_hits: detect_corner::get_corner_hits(),
+        };
+        Ok(())
+    }
+
+    /// Moves the scene on to `time` without drawing. The ball physics and
+    /// audio advance; scenes that only advance while drawing wait.
+    pub fn skip_to(&mut self, time: f32) {
+        station_state::make_current(&self.state);
+        orchestrator::update_frame(self.config.width, self.config.height, time);
+        self.time = time;
+    }
+
+    /// Draws the last frame again at `options.render_scale` times the size
+    /// and returns it filtered down to `capture::capture_size`. The scene
+    /// doesn't advance; see `capture` for what it costs the next frame.
+    pub fn capture(&mut self, options: &CaptureOptions) -> RgbaImage {
+        station_state::make_current(&self.state);
+        capture::render_capture(self.config.width, self.config.height, self.time, options)
+    }
+
+    pub fn stats(&self) -> FrameStats {
+        self.stats
+    }
+
+    fn check_frame(&self, frame: &[u8]) -> Result<(), EmbedError> {
+        let expected = self.config.frame_len();
+        if frame.len() != expected {
+            return Err(EmbedError::FrameSize {
+                expected,
+                actual: frame.len(),
+            });
+        }
+        Ok(())
+    }
+
+    /// The scene key bindings: toggles, forces, and the World's modes. Window
+    /// concerns like quitting, the menu, and snapshots are left to the host.
+    pub fn apply_input(&mut self, input: &InputFrame) {
+        station_state::make_current(&self.state);
+        // Settings the bindings change are recorded for undo at the end
+        let settings_before = Settings::current();
+
+        // Anything the user does shows up on the next frame, even in a capped scene
+        let acted = input.pressed != 0 || input.buttons_pressed != 0 || input.buttons_held != 0;
+        if acted || input.scroll != 0.0 {
+            crate::core::frame_cap::force_scene_render();
+        }
+
+        // The scene menu takes the keys while it is open; the host moves,
+        // picks, and closes it, and nothing changes behind it
+        if menu::is_menu_open() {
+            return;
+        }
+
+        // The white noise, output picker, and calibration keys need audio
+        let audio_keys = [KeyCode::Digit9, KeyCode::KeyO, KeyCode::F8];
+        if !crate::audio::audio_enabled() && audio_keys.iter().any(|&key| input.key_pressed(key)) {
+            toast::show_toast(vec![crate::audio::AUDIO_OFF_NOTICE.to_string()]);
+        }
+
+        // '9' cycles what plays when there is no soundtrack: off, noise, music
+        if input.key_pressed(KeyCode::Digit9) && crate::audio::audio_enabled() {
+            if let Err(e) = crate::audio::audio_playback::cycle_fallback_sound() {
+                warn!("Fallback sound unavailable: {}", e);
+            }
+        }
+
+        // 'O' opens the audio output picker; Up and Down move and Enter switches while open
+        if input.key_pressed(KeyCode::KeyO) && crate::audio::audio_enabled() {
+            let open = device_picker::toggle_picker();
+            info!("Output picker: {}", if open { "open" } else { "closed" });
+        }
+        if device_picker::is_picker_open() {
+            for (key, steps) in [(KeyCode::ArrowUp, -1), (KeyCode::ArrowDown, 1)] {
+                if input.key_pressed(key) {
+                    device_picker::move_highlight(steps);
+                }
+            }
+            if input.key_pressed(KeyCode::Enter) {
+                if let Some(choice) = device_picker::choose_highlighted() {
+                    info!("Audio output: {}", choice.name());
+                }
+            }
+        }
+
+        // 'E' sets off an explosion at the cursor, or mid-frame when the cursor
+        // is elsewhere; Shift+E moves to the next pattern
+        if input.key_pressed(KeyCode::KeyE) {
+            if input.held_shift() {
+                let pattern = fireworks::cycle_explosion_pattern();
+                toast::show_toast(vec![format!("Explosion: {}", pattern)]);
+            } else {
+                let middle = (
+                    self.config.width as f32 / 2.0,
+                    self.config.height as f32 / 2.0,
+                );
+                let (x, y) = input.cursor.unwrap_or(middle);
+                fireworks::explode_at(x, y);
+            }
+        }
+
+        // 'T' flips between the day and night themes, suspending the schedule;
+        // Shift+T turns the schedule back on, and Ctrl+T makes a theme from the frame
+        if input.key_pressed(KeyCode::KeyT) {
+            if input.held_control() {
+                if palette_extract::press_frame_theme(self.time) == PressOutcome::Reverted {
+                    toast::show_toast(vec!["Theme reverted".to_string()]);
+                }
+            } else if input.held_shift() {
+                theme::resume_schedule();
+                toast::show_toast(vec!["Theme follows the day/night schedule".to_string()]);
+            } else {
+                let picked = theme::toggle_theme();
+                toast::show_toast(vec![format!(
+                    "Theme: {} (Shift+T resumes the schedule)",
+                    picked.name
+                )]);
+            }
+        }
+
+        // Show or hide the audio bars with 'V'; playback is unaffected
+        if input.key_pressed(KeyCode::KeyV) {
+            let enabled = crate::audio::toggle_viz();
+            info!("Audio bars: {}", if enabled { "on" } else { "off" });
+        }
+
+        // F4 toggles clean mode (no HUD, sorter edges, or audio bars)
+        if input.key_pressed(KeyCode::F4) {
+            let clean = orchestrator::toggle_clean_mode();
+            info!("Clean mode: {}", if clean { "on" } else { "off" });
+        }
+
+        // F3 shows buffer memory usage; Shift+F3 shows what changed since the last frame,
+        // Ctrl+F3 cycles the window's frame-rate cap
+        if input.key_pressed(KeyCode::F3) {
+            if input.held_control() {
+                let cap = pacing::cycle_fps_cap();
+                toast::show_toast(vec![format!("Frame rate cap: {}", cap.label())]);
+            } else if input.held_shift() {
+                let enabled = crate::core::frame_diff::toggle_diff_view();
+                info!("Frame diff view: {}", if enabled { "on" } else { "off" });
+            } else {
+                let enabled = orchestrator::toggle_debug_overlay();
+                info!("Debug overlay: {}", if enabled { "on" } else { "off" });
+            }
+        }
+
+        // F6 clears what was built in the current scene, including its saved copy
+        if input.key_pressed(KeyCode::F6) {
+            crate::core::persist::reset_active_scene();
+        }
+
+        // F8 opens the latency calibration; ',' and '.' nudge it while open
+        if input.key_pressed(KeyCode::F8) && crate::audio::audio_enabled() {
+            calibration::toggle_calibration();
+            if !calibration::is_calibrating() {
+                info!("Visual latency: {} ms", features::visual_latency_ms());
+            }
+        }
+        if calibration::is_calibrating() {
+            if input.key_pressed(KeyCode::Comma) {
+                calibration::nudge_latency(-1);
+            }
+            if input.key_pressed(KeyCode::Period) {
+                calibration::nudge_latency(1);
+            }
+        }
+
+        // Ctrl+'.' morphs to the next preset slot; Ctrl+',' stores the current look in this one
+        if input.held_control() && !calibration::is_calibrating() {
+            if input.key_pressed(KeyCode::Period) {
+                let from = Preset::capture("Current");
+                let preset = presets::morph_to_next_slot();
+                settings_history::record_preset_load(from, preset, self.time);
+                info!("Morphing to preset: {}", preset.name);
+            }
+            if input.key_pressed(KeyCode::Comma) {
+                let slot = presets::store_current_slot();
+                info!("Stored preset slot {}", slot + 1);
+            }
+        }
+
+        // 'H' shows the key help; PgUp and PgDn page through it while open
+        if input.key_pressed(KeyCode::KeyH) {
+            let open = help_overlay::toggle_help();
+            info!("Key help: {}", if open { "shown" } else { "hidden" });
+        }
+        if help_overlay::is_help_open() {
+            if input.key_pressed(KeyCode::PageUp) {
+                help_overlay::turn_page(-1);
+            }
+            if input.key_pressed(KeyCode::PageDown) {
+                help_overlay::turn_page(1);
+            }
+        }
+
+        // F10 opens the session timeline; Left and Right step through it while open
+        if input.key_pressed(KeyCode::F10) {
+            let open = timeline::toggle_timeline();
+            info!("Session timeline: {}", if open { "open" } else { "closed" });
+        }
+        let timeline_open = timeline::is_timeline_open();
+        if timeline_open {
+            for (key, steps) in [(KeyCode::ArrowLeft, -1), (KeyCode::ArrowRight, 1)] {
+                if input.key_pressed(key) {
+                    if let Some(scene) = timeline::step(steps) {
+                        info!("Scene: {}", scene.name);
+                    }
+                }
+            }
+        }
+
+        // F12 starts a long exposure; Shift+F12 is the app's wallpaper capture
+        if input.key_pressed(KeyCode::F12) && !input.held_shift() {
+            let running = longexposure::toggle_long_exposure();
+            let settings = longexposure::long_exposure_settings();
+            toast::show_toast(vec![if running {
+                format!(
+                    "Long exposure: {} min, {} blend",
+                    settings.minutes,
+                    settings.blend.name()
+                )
+            } else {
+                "Long exposure off".to_string()
+            }]);
+        }
+
+        // F7 cycles the hue-shift and color-blind-safe filters
+        if input.key_pressed(KeyCode::F7) {
+            let filter = post::cycle_color_filter();
+            info!("Color filter: {}", filter.name());
+        }
+
+        // Toggle the World line layer with 'L'
+        if input.key_pressed(KeyCode::KeyL) {
+            let enabled = !crate::physics::world::is_world_enabled();
+            crate::physics::world::set_world_enabled(enabled);
+        }
+
+        // Toggle the soft-body blob with 'B'
+        if input.key_pressed(KeyCode::KeyB) {
+            let enabled = crate::physics::softbody::toggle_softbody();
+            info!("Soft-body blob: {}", if enabled { "on" } else { "off" });
+        }
+
+        // Cycle sorter bar colors with 'C'
+        if input.key_pressed(KeyCode::KeyC) {
+            let mode = crate::algorithms::sorter_manager::cycle_sorter_color_mode();
+            info!("Sorter colors: {}", mode.name());
+        }
+
+        // Per-edge sorter captions with 'N'; Shift+N has the edges sort one
+        // shared ring around the border instead of arrays of their own
+        if input.key_pressed(KeyCode::KeyN) {
+            if input.held_shift() {
+                let enabled = crate::algorithms::sorter_manager::toggle_world_ring();
+                info!("Sorter world ring: {}", if enabled { "on" } else { "off" });
+            } else {
+                let enabled = crate::algorithms::sorter_manager::toggle_captions();
+                info!("Sorter captions: {}", if enabled { "on" } else { "off" });
+            }
+        }
+
+        // Cycle the sorters' input pattern with 'I'; they restart from it
+        if input.key_pressed(KeyCode::KeyI) {
+            let pattern = crate::algorithms::sorter_manager::cycle_input_pattern();
+            info!("Sorter input: {}", pattern.label());
+        }
+
+        // Move the image sorter to another row of its photo with 'R'
+        if input.key_pressed(KeyCode::KeyR) {
+            match crate::algorithms::sorter_manager::cycle_image_row() {
+                Some(row) => info!("Sorting image row {}", row),
+                None => info!("No image loaded; start with --sort-image <path>"),
+            }
+        }
+
+        // Additive light mixing of the ball rays with 'M'
+        if input.key_pressed(KeyCode::KeyM) {
+            let enabled = orchestrator::toggle_light_mixing();
+            info!("Light mixing: {}", if enabled { "on" } else { "off" });
+        }
+
+        // World forces: 'G' cycles gravity off/down/up, 'W' toggles wind
+        if input.key_pressed(KeyCode::KeyG) {
+            let gravity = crate::physics::forces::cycle_gravity();
+            info!("Gravity: {}", gravity.name());
+        }
+        if input.key_pressed(KeyCode::KeyW) {
+            let wind = crate::physics::forces::toggle_wind();
+            info!("Wind: {}", if wind { "on" } else { "off" });
+        }
+
+        // Paint mode: 'P' toggles, Ctrl+Z undoes the last stroke, 'X' clears the drawing
+        if input.key_pressed(KeyCode::KeyP) {
+            let enabled = crate::physics::world::toggle_paint_mode();
+            info!("Paint mode: {}", if enabled { "on" } else { "off" });
+        }
+        if input.key_pressed(KeyCode::KeyX) {
+            crate::physics::world::clear_drawing();
+        }
+
+        // Cycle World modes with Space; Shift+Space cycles the arena shape
+        if input.key_pressed(KeyCode::Space) {
+            if input.held_shift() {
+                let shape = crate::physics::world::cycle_arena_shape();
+                toast::show_toast(vec![format!("Arena: {}", shape.name())]);
+            } else {
+                crate::physics::world::next_world_mode();
+            }
+        }
+        // Flock mode: 'F' switches the cursor between scattering the flock and drawing it in
+        if input.key_pressed(KeyCode::KeyF) {
+            if let Some(role) = crate::physics::world::toggle_flock_mouse() {
+                info!("Flock cursor: {}", role.name());
+            }
+        }
+
+        // Audio-reactive line width: 'K' toggles, '[' and ']' adjust sensitivity
+        if input.key_pressed(KeyCode::KeyK) {
+            let enabled = crate::physics::world::toggle_audio_width();
+            info!(
+                "Audio-reactive line width: {}",
+                if enabled { "on" } else { "off" }
+            );
+        }
+        if input.key_pressed(KeyCode::BracketLeft) {
+            let sensitivity = crate::physics::world::adjust_audio_width_sensitivity(-0.25);
+            info!("Line width sensitivity: {:.2}", sensitivity);
+        }
+        if input.key_pressed(KeyCode::BracketRight) {
+            let sensitivity = crate::physics::world::adjust_audio_width_sensitivity(0.25);
+            info!("Line width sensitivity: {:.2}", sensitivity);
+        }
+
+        // Waves mode: '-' and '=' step the frequency, ';' and the quote key the amplitude
+        for (key, steps) in [(KeyCode::Minus, -1.0), (KeyCode::Equal, 1.0)] {
+            if input.key_pressed(key) {
+                if let Some(frequency) = crate::physics::world::adjust_wave_frequency(steps) {
+                    info!("Wave frequency: {:.1} Hz", frequency);
+                }
+            }
+        }
+        for (key, steps) in [(KeyCode::Semicolon, -1.0), (KeyCode::Quote, 1.0)] {
+            if input.key_pressed(key) {
+                if let Some(amplitude) = crate::physics::world::adjust_wave_amplitude(steps) {
+                    info!("Wave amplitude: {:.2}", amplitude);
+                }
+            }
+        }
+
+        // World view: Ctrl+scroll zooms about the cursor, middle-drag pans, '0' resets
+        if input.scroll != 0.0 && input.held_control() {
+            let middle = (
+                self.config.width as f32 / 2.0,
+                self.config.height as f32 / 2.0,
+            );
+            crate::physics::world::zoom_view(input.cursor.unwrap_or(middle), input.scroll);
+        }
+        crate::physics::world::pan_view(input.cursor, input.mouse_held(MouseButton::Middle));
+        if input.key_pressed(KeyCode::Digit0) {
+            crate::physics::world::reset_view();
+        }
+
+        // Ctrl+Z undoes the last paint stroke in paint mode and the last setting
+        // change otherwise; Ctrl+Shift+Z redoes a setting change
+        settings_history::record_since(&settings_before, self.time);
+        if input.key_pressed(KeyCode::KeyZ) && input.held_control() {
+            if input.held_shift() {
+                if !settings_history::redo() {
+                    toast::show_toast(vec!["Nothing to redo".to_string()]);
+                }
+            } else if crate::physics::world::is_paint_mode() {
+                crate::physics::world::undo_stroke();
+            } else if !settings_history::undo() {
+                toast::show_toast(vec!["Nothing to undo".to_string()]);
+            }
+        }
+
+        // Push the yellow ball with the arrow keys, unless they are browsing the
+        // timeline or the output picker
+        if timeline_open || device_picker::is_picker_open() {
+            return;
+        }
+        if input.key_held(KeyCode::ArrowLeft) {
+            crate::physics::physics::apply_force_yellow(-0.1, 0.0);
+        }
+        if input.key_held(KeyCode::ArrowRight) {
+            crate::physics::physics::apply_force_yellow(0.1, 0.0);
+        }
+        if input.key_held(KeyCode::ArrowUp) {
+            crate::physics::physics::apply_force_yellow(0.0, -0.1);
+        }
+        if input.key_held(KeyCode::ArrowDown) {
+            crate::physics::physics::apply_force_yellow(0.0, 0.1);
+        }
+    }
+}
+
+impl Drop for StimStation {
+    fn drop(&mut self) {
+        station_state::release(&self.state);
+    }
+}
@@ -0,0 +1,395 @@
+//! An energy meter shared by every scene. Explosions, corner hits, finished
+//! sorts, and beats fill it, and it drains slowly on its own. When it fills,
+//! a ten second finale plays over whatever scene is on: every sorter starts
+//! again, a ring of explosions goes off, the balls speed up, and the
+//! kaleidoscope comes on for a moment. Then the meter starts from empty.
+//!
+//! The meter only listens to the event bus. The orchestrator plays the
+//! finale's cues as they come due.
+
+use crate::core::accessibility;
+use crate::core::events::Event;
+use crate::graphics::pixel_utils::draw_rectangle_safe;
+use crate::graphics::theme;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Length of the finale, in seconds
+pub const FINALE_SECONDS: f32 = 10.0;
+/// How long into the finale the kaleidoscope stays on
+pub const KALEIDOSCOPE_SECONDS: f32 = 3.0;
+/// Share of a full meter lost per second
+pub const DECAY_PER_SECOND: f32 = 0.02;
+/// Longest gap between frames that counts toward decay, so a stall or a
+/// jump in scene time doesn't empty the meter at once
+const MAX_DECAY_STEP: f32 = 0.25;
+/// Height of the bar along the top edge, in pixels
+const BAR_HEIGHT: u32 = 3;
+const BAR_ALPHA: u8 = 200;
+
+/// Share of a full meter each kind of event adds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EnergyGains {
+    pub explosion: f32,
+    pub corner_hit: f32,
+    pub sort_completed: f32,
+    /// Per beat at full strength; quieter beats add proportionally less
+    pub beat: f32,
+}
+
+impl EnergyGains {
+    pub const DEFAULT: Self = Self {
+        explosion: 0.05,
+        corner_hit: 0.2,
+        sort_completed: 0.1,
+        beat: 0.01,
+    };
+
+    /// What `event` adds to the meter
+    pub fn gain(&self, event: &Event) -> f32 {
+        match event {
+            Event::Explosion { .. } => self.explosion,
+            Event::CornerHit { .. } => self.corner_hit,
+            Event::SorterCompleted { .. } => self.sort_completed,
+            Event::Beat { strength } => self.beat * strength.clamp(0.0, 1.0),
+            _ => 0.0,
+        }
+    }
+}
+
+impl Default for EnergyGains {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// How big the finale is, chosen when it starts
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FinalePlan {
+    /// Explosions in the ring
+    pub explosions: usize,
+    /// Factor on the balls' speed for the length of the finale
+    pub ball_burst: f32,
+    pub kaleidoscope: bool,
+}
+
+impl FinalePlan {
+    /// The full show, or with reduced motion a smaller ring and nothing that
+    /// speeds up or swirls the frame
+    pub fn for_motion(reduced_motion: bool) -> Self {
+        if reduced_motion {
+            Self {
+                explosions: 4,
+                ball_burst: 1.0,
+                kaleidoscope: false,
+            }
+        } else {
+            Self {
+                explosions: 12,
+                ball_burst: 2.0,
+                kaleidoscope: true,
+            }
+        }
+    }
+}
+
+/// A step of the finale for the orchestrator to carry out
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FinaleCue {
+    Start(FinalePlan),
+    KaleidoscopeOff,
+    /// Undo what `Start` changed
+    End(FinalePlan),
+}
+
+/// A finale under way
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Finale {
+    started: f32,
+    /// Set by the first `advance`, which is when the finale really starts
+    plan: Option<FinalePlan>,
+    kaleidoscope_off: bool,
+}
+
+/// The meter and, once it has filled, the finale
+#[derive(Debug, Clone, PartialEq)]
+pub struct Energy {
+    level: f32,
+    gains: EnergyGains,
+    last_frame: Option<f32>,
+    finale: Option<Finale>,
+}
+
+impl Energy {
+    pub const fn new() -> Self {
+        Self {
+            level: 0.0,
+            gains: EnergyGains::DEFAULT,
+            last_frame: None,
+            finale: None,
+        }
+    }
+
+    /// How full the meter is, 0..=1
+    pub fn level(&self) -> f32 {
+        self.level
+    }
+
+    pub fn is_finale_playing(&self) -> bool {
+        self.finale.is_some()
+    }
+
+    pub fn set_gains(&mut self, gains: EnergyGains) {
+        self.gains = gains;
+    }
+
+    /// Counts `event`, dispatched at scene time `time`. Frames drain the
+    /// meter; during the finale it stays full and nothing adds to it.
+    pub fn record(&mut self, event: &Event, time: f32) {
+        if let Event::FrameRendered { .. } = event {
+            let dt = self
+                .last_frame
+                .map_or(0.0, |last| (time - last).clamp(0.0, MAX_DECAY_STEP));
+            self.last_frame = Some(time);
+            if self.finale.is_none() {
+                self.level = (self.level - DECAY_PER_SECOND * dt).max(0.0);
+            }
+            return;
+        }
+        if self.finale.is_some() {
+            return;
+        }
+        self.level = (self.level + self.gains.gain(event)).min(1.0);
+        if self.level >= 1.0 {
+            self.finale = Some(Finale {
+                started: time,
+                plan: None,
+                kaleidoscope_off: false,
+            });
+        }
+    }
+
+    /// The finale's cues due by `time`, in order. The finale's size is fixed
+    /// on its first call; after the `End` cue the meter is empty again.
+    pub fn advance(&mut self, time: f32, reduced_motion: bool) -> Vec<FinaleCue> {
+        let mut cues = Vec::new();
+        let Some(finale) = self.finale.as_mut() else {
+            return cues;
+        };
+        // Scene time went back, e.g. a restored timeline; count from here
+        if time < finale.started {
+            finale.started = time;
+        }
+        let plan = match finale.plan {
+            Some(plan) => plan,
+            None => {
+                let plan = FinalePlan::for_motion(reduced_motion);
+                finale.plan = Some(plan);
+                cues.push(FinaleCue::Start(plan));
+                plan
+            }
+        };
+        let elapsed = time - finale.started;
+        if elapsed >= FINALE_SECONDS {
+            cues.push(FinaleCue::End(plan));
+            self.finale = None;
+            self.level = 0.0;
+        } else if elapsed >= KALEIDOSCOPE_SECONDS && !finale.kaleidoscope_off {
+            finale.kaleidoscope_off = true;
+            if plan.kaleidoscope {
+                cues.push(FinaleCue::KaleidoscopeOff);
+            }
+        }
+        cues
+    }
+}
+
+impl Default for Energy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+static ENERGY: Mutex<Energy> = Mutex::new(Energy::new());
+
+fn energy() -> MutexGuard<'static, Energy> {
+    ENERGY.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Event bus listener; the orchestrator subscribes it at startup
+pub fn on_event(event: &Event, time: f32) {
+    energy().record(event, time);
+}
+
+pub fn set_energy_gains(gains: EnergyGains) {
+    energy().set_gains(gains);
+}
+
+pub fn energy_level() -> f32 {
+    energy().level()
+}
+
+/// Finale cues due by `time`; the orchestrator calls this once a frame
+pub fn advance(time: f32) -> Vec<FinaleCue> {
+    energy().advance(time, accessibility::is_reduced_motion())
+}
+
+/// The meter as a thin bar along the top edge, in the theme's light text
+/// color. During the finale it is full and pulses, unless motion is reduced.
+pub fn draw_energy_bar(
+    frame: &mut [u8],
+    width: u32,
+    height: u32,
+    time: f32,
+    x_offset: usize,
+    buffer_width: u32,
+) {
+    let (level, playing) = {
+        let energy = energy();
+        (energy.level(), energy.is_finale_playing())
+    };
+    let filled = (level * width as f32).round() as u32;
+    if filled == 0 {
+        return;
+    }
+    let [r, g, b] = theme::current_theme().contrast_text[0];
+    let alpha = if playing && !accessibility::is_reduced_motion() {
+        let pulse = (time * std::f32::consts::TAU * 2.0).sin() * 0.5 + 0.5;
+        (BAR_ALPHA as f32 * (0.5 + 0.5 * pulse)) as u8
+    } else {
+        BAR_ALPHA
+    };
+    let buffer_height = (frame.len() / 4 / buffer_width.max(1) as usize) as u32;
+    draw_rectangle_safe(
+        frame,
+        x_offset as i32,
+        0,
+        filled,
+        BAR_HEIGHT.min(height),
+        [r, g, b, alpha],
+        buffer_width,
+        buffer_height,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::detect_corner::Corner;
+
+    const CORNER: Event = Event::CornerHit {
+        corner: Corner::TopLeft,
+    };
+
+    fn frame(energy: &mut Energy, time: f32) {
+        energy.record(&Event::FrameRendered { particles: 0 }, time);
+    }
+
+    #[test]
+    fn test_events_fill_and_frames_drain_the_meter() {
+        let mut energy = Energy::new();
+        frame(&mut energy, 0.0);
+        energy.record(&CORNER, 0.0);
+        energy.record(&Event::Explosion { x: 0.0, y: 0.0 }, 0.0);
+        energy.record(&Event::Beat { strength: 0.5 }, 0.0);
+        // A beat can't count for more than a full-strength one
+        energy.record(&Event::Beat { strength: 7.0 }, 0.0);
+        energy.record(
+            &Event::RecordBroken {
+                kind: "x",
+                value: 1.0,
+            },
+            0.0,
+        );
+        let expected = 0.2 + 0.05 + 0.005 + 0.01;
+        assert!(
+            (energy.level() - expected).abs() < 1e-6,
+            "{}",
+            energy.level()
+        );
+
+        // Ten seconds of frames drain a fifth of a meter
+        for i in 1..=600 {
+            frame(&mut energy, i as f32 / 60.0);
+        }
+        assert!((energy.level() - (expected - 0.2)).abs() < 1e-4);
+        // A long stall counts as one short step, and the meter stops at empty
+        frame(&mut energy, 1000.0);
+        assert!(energy.level() > 0.0);
+        for i in 0..200 {
+            frame(&mut energy, 1000.0 + i as f32 * 0.25);
+        }
+        assert_eq!(energy.level(), 0.0);
+        assert!(energy.advance(1050.0, false).is_empty());
+    }
+
+    #[test]
+    fn test_a_full_meter_plays_the_finale_once() {
+        let mut energy = Energy::new();
+        for _ in 0..6 {
+            energy.record(&CORNER, 1.0);
+        }
+        assert_eq!(energy.level(), 1.0);
+        assert!(energy.is_finale_playing());
+        // Nothing adds to the meter or restarts the finale while it plays
+        energy.record(&CORNER, 1.5);
+
+        let plan = FinalePlan::for_motion(false);
+        let mut cues = Vec::new();
+        for i in 0..=700 {
+            let time = 2.0 + i as f32 / 60.0;
+            frame(&mut energy, time);
+            cues.extend(
+                energy
+                    .advance(time, false)
+                    .into_iter()
+                    .map(|cue| (time, cue)),
+            );
+        }
+        let names: Vec<FinaleCue> = cues.iter().map(|(_, cue)| *cue).collect();
+        assert_eq!(
+            names,
+            [
+                FinaleCue::Start(plan),
+                FinaleCue::KaleidoscopeOff,
+                FinaleCue::End(plan)
+            ]
+        );
+        // Timed from when the meter filled
+        assert!((cues[1].0 - (1.0 + KALEIDOSCOPE_SECONDS)).abs() < 0.02);
+        assert!((cues[2].0 - (1.0 + FINALE_SECONDS)).abs() < 0.02);
+        assert!(!energy.is_finale_playing());
+        assert_eq!(energy.level(), 0.0);
+
+        // The next fill starts another
+        for _ in 0..6 {
+            energy.record(&CORNER, 20.0);
+        }
+        assert_eq!(energy.advance(20.0, false), [FinaleCue::Start(plan)]);
+    }
+
+    #[test]
+    fn test_reduced_motion_tones_the_finale_down() {
+        let mut energy = Energy::new();
+        energy.set_gains(EnergyGains {
+            sort_completed: 1.0,
+            ..EnergyGains::DEFAULT
+        });
+        let edge = crate::algorithms::sorter_manager::SorterEdge::Left;
+        let algorithm = crate::algorithms::sorter::SortAlgorithm::Bubble;
+        let points = Vec::new();
+        let event = Event::SorterCompleted {
+            algorithm,
+            edge,
+            points,
+        };
+        energy.record(&event, 0.0);
+        let calm = FinalePlan::for_motion(true);
+        assert_eq!(energy.advance(0.0, true), [FinaleCue::Start(calm)]);
+        assert!(!calm.kaleidoscope && calm.ball_burst == 1.0);
+        assert!(calm.explosions < FinalePlan::for_motion(false).explosions);
+        // No kaleidoscope to turn off, and a change of setting mid-finale
+        // doesn't change what End undoes
+        assert!(energy.advance(5.0, false).is_empty());
+        assert_eq!(energy.advance(10.0, false), [FinaleCue::End(calm)]);
+    }
+}
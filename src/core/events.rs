@@ -0,0 +1,226 @@
+//! Notifications between modules that shouldn't call each other directly.
+//! Anything may `publish` an event; the orchestrator `drain`s the queue once a
+//! frame and hands every event to each listener, in the order they subscribed.
+//! The queue is bounded: events published while it is full are dropped and
+//! counted, so a storm of them can't grow without limit.
+
+use crate::algorithms::sorter::SortAlgorithm;
+use crate::algorithms::sorter_manager::SorterEdge;
+use crate::audio::audio_playback::FallbackSound;
+use crate::physics::detect_corner::Corner;
+use std::collections::VecDeque;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Most events waiting for the next drain
+pub const EVENT_QUEUE_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// One of the main balls reached a corner
+    CornerHit { corner: Corner },
+    /// An edge sorter finished its array
+    SorterCompleted {
+        algorithm: SortAlgorithm,
+        edge: SorterEdge,
+        /// Tips of evenly spaced bars along the edge, in the drawing
+        /// context's coordinates, for the celebration burst
+        points: Vec<(f32, f32)>,
+    },
+    /// The audio had an onset; `strength` is its loudness, 0..=1
+    Beat { strength: f32 },
+    /// A running best was beaten, e.g. a score of some kind
+    RecordBroken { kind: &'static str, value: f32 },
+    /// The scene on screen changed; `from` is None on the first frame
+    SceneChanged {
+        from: Option<&'static str>,
+        to: &'static str,
+    },
+    /// The sound played without a soundtrack was switched
+    FallbackSoundChanged { sound: FallbackSound },
+    /// A frame is being drawn; `particles` were alive going into it
+    FrameRendered { particles: usize },
+    /// An explosion was set off on purpose, e.g. with E
+    Explosion { x: f32, y: f32 },
+}
+
+/// Names a subscription so it can be dropped again
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ListenerId(u64);
+
+/// Called with each event and the time of the frame draining it
+type Listener = Box<dyn FnMut(&Event, f32) + Send>;
+
+/// A queue of events and the listeners they go to
+pub struct EventBus {
+    queue: VecDeque<Event>,
+    capacity: usize,
+    dropped: u64,
+    listeners: Vec<(ListenerId, Listener)>,
+    next_id: u64,
+}
+
+impl EventBus {
+    pub const fn new(capacity: usize) -> Self {
+        Self {
+            queue: VecDeque::new(),
+            capacity,
+            dropped: 0,
+            listeners: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Queues `event` for the next drain. Returns false, counting it as
+    /// dropped, when the queue is full.
+    pub fn publish(&mut self, event: Event) -> bool {
+        if self.queue.len() >= self.capacity {
+            self.dropped += 1;
+            return false;
+        }
+        self.queue.push_back(event);
+        true
+    }
+
+    pub fn subscribe(&mut self, listener: impl FnMut(&Event, f32) + Send + 'static) -> ListenerId {
+        let id = ListenerId(self.next_id);
+        self.next_id += 1;
+        self.listeners.push((id, Box::new(listener)));
+        id
+    }
+
+    /// Stops sending events to `id`. Returns false if it wasn't subscribed.
+    pub fn unsubscribe(&mut self, id: ListenerId) -> bool {
+        let before = self.listeners.len();
+        self.listeners.retain(|(listener, _)| *listener != id);
+        self.listeners.len() != before
+    }
+
+    /// Events waiting for the next drain
+    pub fn pending(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Events dropped so far because the queue was full
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Hands every queued event to each listener. Returns how many there were.
+    pub fn drain(&mut self, time: f32) -> usize {
+        let events = std::mem::take(&mut self.queue);
+        dispatch(&events, &mut self.listeners, time);
+        events.len()
+    }
+}
+
+fn dispatch(events: &VecDeque<Event>, listeners: &mut [(ListenerId, Listener)], time: f32) {
+    for event in events {
+        for (_, listener) in listeners.iter_mut() {
+            listener(event, time);
+        }
+    }
+}
+
+static BUS: Mutex<EventBus> = Mutex::new(EventBus::new(EVENT_QUEUE_CAPACITY));
+
+fn bus() -> MutexGuard<'static, EventBus> {
+    BUS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Queues `event` on the global bus; see `EventBus::publish`
+pub fn publish(event: Event) -> bool {
+    bus().publish(event)
+}
+
+/// Sends the global bus's events to `listener` from the next drain on
+pub fn subscribe(listener: impl FnMut(&Event, f32) + Send + 'static) -> ListenerId {
+    bus().subscribe(listener)
+}
+
+pub fn unsubscribe(id: ListenerId) -> bool {
+    bus().unsubscribe(id)
+}
+
+pub fn dropped_events() -> u64 {
+    bus().dropped()
+}
+
+/// Dispatches the global bus's queued events; called by the orchestrator once
+/// a frame. The bus is unlocked while listeners run, so they may publish
+/// (those events wait for the next drain) or subscribe.
+pub fn drain(time: f32) -> usize {
+    let (events, mut listeners) = {
+        let mut bus = bus();
+        (
+            std::mem::take(&mut bus.queue),
+            std::mem::take(&mut bus.listeners),
+        )
+    };
+    dispatch(&events, &mut listeners, time);
+    let mut bus = bus();
+    // Anyone who subscribed meanwhile comes after the listeners already there
+    listeners.append(&mut bus.listeners);
+    bus.listeners = listeners;
+    events.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn beat(strength: f32) -> Event {
+        Event::Beat { strength }
+    }
+
+    /// A listener that keeps what it was sent
+    fn recorder(bus: &mut EventBus) -> (ListenerId, Arc<Mutex<Vec<Event>>>) {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&seen);
+        let id = bus.subscribe(move |event, _| sink.lock().unwrap().push(event.clone()));
+        (id, seen)
+    }
+
+    #[test]
+    fn test_listeners_get_events_until_unsubscribed() {
+        let mut bus = EventBus::new(8);
+        let (first, first_seen) = recorder(&mut bus);
+        let (_, second_seen) = recorder(&mut bus);
+        let corner = Event::CornerHit {
+            corner: Corner::TopLeft,
+        };
+        bus.publish(corner.clone());
+        bus.publish(beat(0.5));
+        // Nothing is delivered before the drain
+        assert!(first_seen.lock().unwrap().is_empty());
+        assert_eq!(bus.drain(1.0), 2);
+        assert_eq!(*first_seen.lock().unwrap(), [corner.clone(), beat(0.5)]);
+        assert_eq!(*second_seen.lock().unwrap(), [corner, beat(0.5)]);
+        assert_eq!(bus.pending(), 0);
+
+        assert!(bus.unsubscribe(first));
+        assert!(!bus.unsubscribe(first));
+        bus.publish(beat(1.0));
+        bus.drain(2.0);
+        assert_eq!(first_seen.lock().unwrap().len(), 2);
+        assert_eq!(second_seen.lock().unwrap().last(), Some(&beat(1.0)));
+    }
+
+    #[test]
+    fn test_a_full_queue_drops_and_counts_new_events() {
+        let mut bus = EventBus::new(3);
+        let (_, seen) = recorder(&mut bus);
+        for i in 0..5 {
+            let queued = bus.publish(beat(i as f32));
+            assert_eq!(queued, i < 3);
+        }
+        assert_eq!(bus.pending(), 3);
+        assert_eq!(bus.dropped(), 2);
+        bus.drain(0.0);
+        // The oldest events are the ones kept
+        assert_eq!(*seen.lock().unwrap(), [beat(0.0), beat(1.0), beat(2.0)]);
+        // Draining makes room again; the count of drops stays
+        assert!(bus.publish(beat(5.0)));
+        assert_eq!(bus.dropped(), 2);
+    }
+}
@@ -0,0 +1,136 @@
+//! `stimstation export-manifest`: renders a thumbnail of every scene without a
+//! window and writes a JSON manifest describing them, for launchers and docs.
+
+use crate::audio::audio_handler;
+use crate::audio::audio_tap::TapError;
+use crate::core::bufpool;
+use crate::core::embed::EmbedError;
+use crate::core::orchestrator;
+use crate::core::scenes::{self, HelpEntry, SceneInfo};
+use crate::graphics::draw_ctx::{DrawCtx, Region};
+use crate::types::{HEIGHT, WIDTH};
+use image::{imageops, ImageBuffer, Rgba, RgbaImage};
+use log::info;
+use serde_json::{json, Value};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Bumped whenever a field is renamed or removed
+pub const MANIFEST_VERSION: u32 = 1;
+pub const THUMBNAIL_WIDTH: u32 = 256;
+pub const THUMBNAIL_HEIGHT: u32 = 128;
+/// Frames simulated before the thumbnail is taken, so balls and sorters have moved
+const WARMUP_FRAMES: usize = 30;
+const FRAME_TIME: f32 = 1.0 / 60.0;
+
+#[derive(Debug)]
+pub enum ExportError {
+    Io(io::Error),
+    Image(image::ImageError),
+    Json(serde_json::Error),
+    Embed(EmbedError),
+    Audio(TapError),
+    /// The output already holds a different export
+    Mismatch(String),
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::Io(e) => write!(f, "I/O error: {}", e),
+            ExportError::Image(e) => write!(f, "could not write image: {}", e),
+            ExportError::Json(e) => write!(f, "could not encode manifest: {}", e),
+            ExportError::Embed(e) => write!(f, "could not render: {}", e),
+            ExportError::Audio(e) => write!(f, "could not save audio: {}", e),
+            ExportError::Mismatch(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ExportError {}
+
+impl From<io::Error> for ExportError {
+    fn from(e: io::Error) -> Self {
+        ExportError::Io(e)
+    }
+}
+
+impl From<image::ImageError> for ExportError {
+    fn from(e: image::ImageError) -> Self {
+        ExportError::Image(e)
+    }
+}
+
+impl From<serde_json::Error> for ExportError {
+    fn from(e: serde_json::Error) -> Self {
+        ExportError::Json(e)
+    }
+}
+
+impl From<EmbedError> for ExportError {
+    fn from(e: EmbedError) -> Self {
+        ExportError::Embed(e)
+    }
+}
+
+/// Enters `scene`, runs a few frames at window size, and scales the last one down
+pub fn render_thumbnail(scene: &SceneInfo) -> RgbaImage {
+    orchestrator::init_headless();
+    // Without audio the bars would rest flat; show the demo pattern instead
+    audio_handler::set_demo_bars(true);
+    scene.enter();
+    let mut frame = bufpool::get_buffer("export frame", (WIDTH * HEIGHT * 4) as usize);
+    for i in 0..WARMUP_FRAMES {
+        let time = i as f32 * FRAME_TIME;
+        let mut ctx = DrawCtx::new(&mut frame, Region::new(0, 0, WIDTH, HEIGHT), WIDTH, time);
+        orchestrator::render_frame(&mut ctx);
+    }
+    let full = ImageBuffer::<Rgba<u8>, &[u8]>::from_raw(WIDTH, HEIGHT, &frame[..])
+        .expect("frame matches window size");
+    imageops::resize(
+        &full,
+        THUMBNAIL_WIDTH,
+        THUMBNAIL_HEIGHT,
+        imageops::FilterType::Triangle,
+    )
+}
+
+fn help_json(help: &[HelpEntry]) -> Value {
+    help.iter()
+        .map(|entry| json!({ "key": entry.key, "action": entry.action }))
+        .collect()
+}
+
+/// Writes `<thumbs>/<id>.png` for every scene and the manifest to `out`.
+/// Thumbnail paths in the manifest are as given, joined with the file name.
+/// Returns the number of scenes exported.
+pub fn export_manifest(out: &Path, thumbs: &Path) -> Result<usize, ExportError> {
+    fs::create_dir_all(thumbs)?;
+    let mut entries = Vec::new();
+    for scene in scenes::SCENES {
+        let thumbnail: PathBuf = thumbs.join(format!("{}.png", scene.id));
+        render_thumbnail(scene).save(&thumbnail)?;
+        info!("Rendered {} thumbnail to {}", scene.id, thumbnail.display());
+        entries.push(json!({
+            "id": scene.id,
+            "name": scene.name,
+            "description": scene.description,
+            "uses_audio": scene.uses_audio,
+            "help": help_json(scene.help),
+            "thumbnail": thumbnail.to_string_lossy(),
+        }));
+    }
+    let manifest = json!({
+        "version": MANIFEST_VERSION,
+        "thumbnail_size": [THUMBNAIL_WIDTH, THUMBNAIL_HEIGHT],
+        "common_help": help_json(scenes::common_help()),
+        "scenes": entries,
+    });
+    if let Some(parent) = out.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(out, serde_json::to_string_pretty(&manifest)?)?;
+    Ok(scenes::SCENES.len())
+}
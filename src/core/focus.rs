@@ -0,0 +1,205 @@
+//! What the app does while its window is in the background: optionally freeze
+//! the animation, duck the audio, and draw at a lower frame rate.
+
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+/// How long the audio takes to duck or come back
+pub const DUCK_RAMP: Duration = Duration::from_millis(200);
+/// Frame rate while unfocused, when throttling is on
+pub const UNFOCUSED_FPS: u32 = 15;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FocusSettings {
+    /// Freeze the animation while unfocused
+    pub pause: bool,
+    /// Lower the audio while unfocused
+    pub duck: bool,
+    /// Volume while ducked, as a fraction of full volume
+    pub duck_volume: f32,
+    /// Draw at `UNFOCUSED_FPS` while unfocused
+    pub throttle: bool,
+}
+
+impl FocusSettings {
+    pub const DEFAULT: Self = Self {
+        pause: false,
+        duck: true,
+        duck_volume: 0.2,
+        throttle: true,
+    };
+}
+
+impl Default for FocusSettings {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+static FOCUS_SETTINGS: Mutex<FocusSettings> = Mutex::new(FocusSettings::DEFAULT);
+
+fn locked_settings() -> MutexGuard<'static, FocusSettings> {
+    FOCUS_SETTINGS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+pub fn focus_settings() -> FocusSettings {
+    *locked_settings()
+}
+
+pub fn set_focus_settings(settings: FocusSettings) {
+    *locked_settings() = FocusSettings {
+        duck_volume: settings.duck_volume.clamp(0.0, 1.0),
+        ..settings
+    };
+}
+
+/// Tracks window focus and derives what the app should do about it
+#[derive(Debug, Clone, Copy)]
+pub struct FocusState {
+    focused: bool,
+    settings: FocusSettings,
+}
+
+impl FocusState {
+    pub fn new(settings: FocusSettings) -> Self {
+        Self {
+            focused: true,
+            settings,
+        }
+    }
+
+    /// Records a focus event. Returns false when focus did not actually change,
+    /// since platforms may report the same state twice.
+    pub fn set_focused(&mut self, focused: bool) -> bool {
+        let changed = self.focused != focused;
+        self.focused = focused;
+        changed
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    pub fn is_paused(&self) -> bool {
+        !self.focused && self.settings.pause
+    }
+
+    /// Volume the output should fade to in the current state
+    pub fn target_volume(&self) -> f32 {
+        if !self.focused && self.settings.duck {
+            self.settings.duck_volume
+        } else {
+            1.0
+        }
+    }
+
+    /// Minimum time between frames, or None to draw as fast as possible.
+    /// A paused app redraws at the throttled rate too, since nothing changes.
+    pub fn frame_interval(&self) -> Option<Duration> {
+        (!self.focused && (self.settings.throttle || self.settings.pause))
+            .then(|| Duration::from_secs(1) / UNFOCUSED_FPS)
+    }
+}
+
+/// Scene time in seconds since start that stands still while paused, so the
+/// animation resumes exactly where it stopped instead of jumping ahead
+#[derive(Debug, Clone, Copy)]
+pub struct SceneClock {
+    start: Instant,
+    paused_at: Option<Instant>,
+    paused_total: Duration,
+}
+
+impl SceneClock {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            start: now,
+            paused_at: None,
+            paused_total: Duration::ZERO,
+        }
+    }
+
+    pub fn time(&self, now: Instant) -> f32 {
+        let now = self.paused_at.unwrap_or(now);
+        (now.saturating_duration_since(self.start) - self.paused_total).as_secs_f32()
+    }
+
+    pub fn pause(&mut self, now: Instant) {
+        self.paused_at.get_or_insert(now);
+    }
+
+    pub fn resume(&mut self, now: Instant) {
+        if let Some(paused_at) = self.paused_at.take() {
+            self.paused_total += now.saturating_duration_since(paused_at);
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused_at.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(pause: bool, duck: bool, throttle: bool) -> FocusSettings {
+        FocusSettings {
+            pause,
+            duck,
+            duck_volume: 0.25,
+            throttle,
+        }
+    }
+
+    #[test]
+    fn test_each_behavior_follows_only_its_own_flag() {
+        let throttled = Some(Duration::from_secs(1) / UNFOCUSED_FPS);
+        for (flags, paused, volume, interval) in [
+            (settings(false, false, false), false, 1.0, None),
+            (settings(true, false, false), true, 1.0, throttled),
+            (settings(false, true, false), false, 0.25, None),
+            (settings(false, false, true), false, 1.0, throttled),
+        ] {
+            let mut state = FocusState::new(flags);
+            assert!(!state.is_paused());
+            assert_eq!(state.target_volume(), 1.0);
+            assert_eq!(state.frame_interval(), None);
+
+            assert!(state.set_focused(false));
+            assert_eq!(state.is_paused(), paused);
+            assert_eq!(state.target_volume(), volume);
+            assert_eq!(state.frame_interval(), interval);
+
+            assert!(state.set_focused(true));
+            assert!(!state.is_paused());
+            assert_eq!(state.target_volume(), 1.0);
+            assert_eq!(state.frame_interval(), None);
+        }
+    }
+
+    #[test]
+    fn test_repeated_focus_events_are_not_changes() {
+        let mut state = FocusState::new(FocusSettings::DEFAULT);
+        assert!(!state.set_focused(true));
+        assert!(state.set_focused(false));
+        assert!(!state.set_focused(false));
+        assert!(!state.is_focused());
+    }
+
+    #[test]
+    fn test_clock_resumes_without_a_jump() {
+        let start = Instant::now();
+        let secs = |s: f32| start + Duration::from_secs_f32(s);
+        let mut clock = SceneClock::new(start);
+        assert!((clock.time(secs(2.0)) - 2.0).abs() < 1e-4);
+
+        clock.pause(secs(2.0));
+        assert!((clock.time(secs(30.0)) - 2.0).abs() < 1e-4);
+        clock.resume(secs(30.0));
+        assert!((clock.time(secs(30.0)) - 2.0).abs() < 1e-4);
+        assert!((clock.time(secs(31.0)) - 3.0).abs() < 1e-4);
+    }
+}
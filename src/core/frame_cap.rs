@@ -0,0 +1,342 @@
+//! Per-scene frame-rate caps. A scene with a `max_fps` is only redrawn when
+//! its interval has passed; in between, the last drawn scene layer is put
+//! back and the overlays are drawn over it as usual, so the HUD, the menu,
+//! and input stay at the full frame rate.
+//!
+//! Layers are kept per pane, by the region drawn into, so a frame split into
+//! panes only redraws the ones whose scene is due; the rest keep their pixels.
+//! A pane drawn over part of another replaces it, so changing the layout
+//! drops the layers it no longer shows.
+
+use crate::core::bufpool;
+use crate::core::station_state;
+use crate::graphics::draw_ctx::{DrawCtx, Region};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Caps can be switched off as a whole, e.g. to compare with `stimstation bench`
+static FRAME_CAPS: AtomicBool = AtomicBool::new(true);
+
+/// Leeway on the interval so float time steps that land a hair short of it
+/// don't push a redraw to the frame after
+const TIMING_SLACK: f32 = 0.001;
+
+pub fn set_frame_caps_enabled(enabled: bool) {
+    FRAME_CAPS.store(enabled, Ordering::Relaxed);
+}
+
+pub fn frame_caps_enabled() -> bool {
+    FRAME_CAPS.load(Ordering::Relaxed)
+}
+
+/// Makes the next frame redraw the scene even if its cap says to wait
+pub fn force_scene_render() {
+    station_state::current()
+        .forced_renders
+        .fetch_add(1, Ordering::Relaxed);
+}
+
+/// What a cached scene layer was drawn for; any change means a redraw
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderKey {
+    pub scene: &'static str,
+    pub region: Region,
+}
+
+/// A pane's last drawn scene layer
+struct PaneLayer<T> {
+    key: RenderKey,
+    /// Scene time the layer was drawn at
+    time: f32,
+    /// `FORCED_RENDERS` when it was drawn
+    forced: u32,
+    pixels: T,
+}
+
+/// Layers of the panes on screen, one per region. Generic over the pixels so
+/// the bookkeeping can be tested without buffers.
+pub struct PaneLayers<T> {
+    layers: Vec<PaneLayer<T>>,
+}
+
+impl<T> Default for PaneLayers<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> PaneLayers<T> {
+    pub const fn new() -> Self {
+        Self { layers: Vec::new() }
+    }
+
+    fn layer(&self, region: Region) -> Option<&PaneLayer<T>> {
+        self.layers.iter().find(|layer| layer.key.region == region)
+    }
+
+    /// Whether the pane at `key.region` has to be drawn at `time`; `forced` is
+    /// the current count of forced renders
+    pub fn is_dirty(&self, max_fps: Option<f32>, key: RenderKey, time: f32, forced: u32) -> bool {
+        let layer = self.layer(key.region);
+        let last = layer.map(|layer| (layer.key, layer.time));
+        let forced = layer.is_some_and(|layer| layer.forced != forced);
+        should_render(max_fps, last, key, time, forced)
+    }
+
+    /// The pixels kept for the pane at `region`
+    pub fn pixels(&self, region: Region) -> Option<&T> {
+        self.layer(region).map(|layer| &layer.pixels)
+    }
+
+    /// Records the pane at `key.region` as drawn at `time` and returns its
+    /// pixels to fill, made by `make` for a new pane. Panes it overlaps are
+    /// dropped: they belong to a layout no longer on screen.
+    pub fn store(
+        &mut self,
+        key: RenderKey,
+        time: f32,
+        forced: u32,
+        make: impl FnOnce() -> T,
+    ) -> &mut T {
+        self.layers.retain(|layer| {
+            layer.key.region == key.region || !overlaps(layer.key.region, key.region)
+        });
+        let index = match self
+            .layers
+            .iter()
+            .position(|layer| layer.key.region == key.region)
+        {
+            Some(index) => index,
+            None => {
+                self.layers.push(PaneLayer {
+                    key,
+                    time,
+                    forced,
+                    pixels: make(),
+                });
+                self.layers.len() - 1
+            }
+        };
+        let layer = &mut self.layers[index];
+        layer.key = key;
+        layer.time = time;
+        layer.forced = forced;
+        &mut layer.pixels
+    }
+
+    /// Drops the pane at `region`, whose scene no longer keeps a layer
+    pub fn forget(&mut self, region: Region) {
+        self.layers.retain(|layer| layer.key.region != region);
+    }
+
+    /// Panes with a kept layer
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+}
+
+/// Whether two regions share any pixel
+fn overlaps(a: Region, b: Region) -> bool {
+    a.x < b.x + b.width as usize
+        && b.x < a.x + a.width as usize
+        && a.y < b.y + b.height as usize
+        && b.y < a.y + a.height as usize
+}
+
+/// Whether a scene capped at `max_fps` has to be drawn at `time`, given when
+/// and for what the cached layer was drawn
+pub fn should_render(
+    max_fps: Option<f32>,
+    last: Option<(RenderKey, f32)>,
+    key: RenderKey,
+    time: f32,
+    forced: bool,
+) -> bool {
+    let (Some(max_fps), Some((last_key, last_time))) = (max_fps, last) else {
+        return true;
+    };
+    // Time running backwards (a restored snapshot, a new replay) also redraws
+    let interval = 1.0 / max_fps - TIMING_SLACK;
+    forced || last_key != key || !(0.0..interval).contains(&(time - last_time))
+}
+
+/// Decides this frame's scene draw for the pane `key.region`. Returns true
+/// when the scene must be drawn; otherwise the pane's kept layer has already
+/// been put into `ctx`.
+pub fn begin_scene(ctx: &mut DrawCtx, max_fps: Option<f32>, key: RenderKey) -> bool {
+    let state = station_state::current();
+    let forced = state.forced_renders.load(Ordering::Relaxed);
+    let max_fps = max_fps.filter(|_| frame_caps_enabled());
+    let layers = station_state::lock(&state.scene_layers);
+    if layers.is_dirty(max_fps, key, ctx.time, forced) {
+        return true;
+    }
+    if let Some(pixels) = layers.pixels(key.region) {
+        ctx.restore_region(pixels);
+    }
+    false
+}
+
+/// Keeps the scene layer just drawn for the frames its cap skips. Uncapped
+/// scenes keep nothing.
+pub fn end_scene(ctx: &DrawCtx, max_fps: Option<f32>, key: RenderKey) {
+    let state = station_state::current();
+    let mut layers = station_state::lock(&state.scene_layers);
+    if max_fps.is_none() || !frame_caps_enabled() {
+        layers.forget(key.region);
+        return;
+    }
+    let size = (key.region.width * key.region.height * 4) as usize;
+    let forced = state.forced_renders.load(Ordering::Relaxed);
+    let pixels = layers.store(key, ctx.time, forced, || {
+        bufpool::get_buffer("scene cache", size)
+    });
+    if pixels.len() != size {
+        pixels.reset(size);
+    }
+    ctx.save_region(pixels);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(scene: &'static str) -> RenderKey {
+        RenderKey {
+            scene,
+            region: Region::new(0, 0, 64, 48),
+        }
+    }
+
+    #[test]
+    fn test_capped_scene_skips_until_its_interval_passes() {
+        let last = Some((key("clean"), 1.0));
+        // Uncapped scenes and a missing layer always draw
+        assert!(should_render(None, last, key("clean"), 1.01, false));
+        assert!(should_render(Some(30.0), None, key("clean"), 1.01, false));
+
+        assert!(!should_render(Some(30.0), last, key("clean"), 1.0, false));
+        assert!(!should_render(Some(30.0), last, key("clean"), 1.02, false));
+        assert!(should_render(Some(30.0), last, key("clean"), 1.034, false));
+        // Every other frame at 60 fps, despite rounding in the frame times
+        let sixtieth = 1.0f32 / 60.0;
+        let last = Some((key("clean"), 7.0 * sixtieth));
+        assert!(!should_render(
+            Some(30.0),
+            last,
+            key("clean"),
+            8.0 * sixtieth,
+            false
+        ));
+        assert!(should_render(
+            Some(30.0),
+            last,
+            key("clean"),
+            9.0 * sixtieth,
+            false
+        ));
+    }
+
+    #[test]
+    fn test_input_scene_changes_and_rewinds_force_a_render() {
+        let last = Some((key("clean"), 1.0));
+        assert!(should_render(Some(30.0), last, key("clean"), 1.01, true));
+        assert!(should_render(Some(30.0), last, key("rays"), 1.01, false));
+        let resized = RenderKey {
+            region: Region::new(0, 0, 32, 48),
+            ..key("clean")
+        };
+        assert!(should_render(Some(30.0), last, resized, 1.01, false));
+        assert!(should_render(Some(30.0), last, key("clean"), 0.5, false));
+    }
+
+    fn pane(scene: &'static str, x: usize, y: usize) -> RenderKey {
+        RenderKey {
+            scene,
+            region: Region::new(x, y, 32, 24),
+        }
+    }
+
+    #[test]
+    fn test_only_panes_whose_scene_is_due_are_dirty() {
+        // Four panes: two capped math scenes, two uncapped
+        let panes = [
+            (pane("pythagoras", 0, 0), Some(30.0)),
+            (pane("spiral", 32, 0), Some(10.0)),
+            (pane("rays", 0, 24), None),
+            (pane("world", 32, 24), None),
+        ];
+        let mut layers = PaneLayers::new();
+        let mut drawn = Vec::new();
+        let sixtieth = 1.0f32 / 60.0;
+        for frame in 0..12 {
+            let time = frame as f32 * sixtieth;
+            for &(key, max_fps) in &panes {
+                if layers.is_dirty(max_fps, key, time, 0) {
+                    drawn.push((frame, key.scene));
+                    match max_fps {
+                        Some(_) => *layers.store(key, time, 0, || 0) += 1,
+                        None => layers.forget(key.region),
+                    }
+                }
+            }
+        }
+        let count = |scene| drawn.iter().filter(|(_, s)| *s == scene).count();
+        assert_eq!(count("rays"), 12);
+        assert_eq!(count("world"), 12);
+        // Every other frame at 30 fps, every sixth at 10
+        assert_eq!(count("pythagoras"), 6);
+        assert_eq!(count("spiral"), 2);
+        assert_eq!(layers.len(), 2);
+        assert_eq!(layers.pixels(panes[1].0.region), Some(&2));
+
+        // A forced render redraws every capped pane once
+        let time = 11.5 * sixtieth;
+        assert!(!layers.is_dirty(Some(30.0), panes[0].0, time, 0));
+        assert!(layers.is_dirty(Some(30.0), panes[0].0, time, 1));
+        assert!(layers.is_dirty(Some(10.0), panes[1].0, time, 1));
+        layers.store(panes[0].0, time, 1, || 0);
+        assert!(!layers.is_dirty(Some(30.0), panes[0].0, time, 1));
+    }
+
+    #[test]
+    fn test_a_pane_redraws_when_its_scene_resumes_or_changes() {
+        let mut layers = PaneLayers::new();
+        let key = pane("pythagoras", 0, 0);
+        layers.store(key, 1.0, 0, || ());
+        assert!(!layers.is_dirty(Some(30.0), key, 1.01, 0));
+        // Uncapped again: drawn every frame, and its stale layer is dropped
+        assert!(layers.is_dirty(None, key, 1.01, 0));
+        layers.forget(key.region);
+        assert!(layers.is_empty());
+        assert!(layers.is_dirty(Some(30.0), key, 1.02, 0));
+        // Another scene in the same pane never reuses the old layer
+        layers.store(key, 2.0, 0, || ());
+        assert!(layers.is_dirty(Some(30.0), pane("spiral", 0, 0), 2.01, 0));
+    }
+
+    #[test]
+    fn test_changing_the_layout_drops_replaced_panes() {
+        let mut layers = PaneLayers::new();
+        let left = pane("pythagoras", 0, 0);
+        let right = pane("spiral", 32, 0);
+        layers.store(left, 1.0, 0, || ());
+        layers.store(right, 1.0, 0, || ());
+        assert_eq!(layers.len(), 2);
+        // One pane across the whole frame replaces both
+        let whole = RenderKey {
+            scene: "pythagoras",
+            region: Region::new(0, 0, 64, 24),
+        };
+        assert!(layers.is_dirty(Some(30.0), whole, 1.01, 0));
+        layers.store(whole, 1.01, 0, || ());
+        assert_eq!(layers.len(), 1);
+        assert!(layers.is_dirty(Some(30.0), left, 1.02, 0));
+        assert!(!layers.is_dirty(Some(30.0), whole, 1.02, 0));
+        // Panes side by side don't count as overlapping
+        assert!(!overlaps(left.region, right.region));
+    }
+}
@@ -0,0 +1,268 @@
+//! Frame diff view for chasing rendering bugs, toggled with Shift+F3. The
+//! scene is drawn and composited as usual, then the frame is swapped for how
+//! much each pixel moved since the last composed frame, amplified so small
+//! changes show. Unchanged pixels are black and the changed area is boxed.
+//! The real frame is put back before the next scene draws, so the view never
+//! feeds into what it is inspecting.
+
+use crate::core::bufpool::{self, PooledBuf};
+use crate::graphics::draw_ctx::{DrawCtx, Region};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Gain on each channel's difference
+pub const AMPLIFY: u16 = 8;
+const BOX_COLOR: [u8; 4] = [255, 0, 255, 255];
+
+static DIFF_VIEW: AtomicBool = AtomicBool::new(false);
+static FRAME_DIFF: Mutex<Option<FrameDiff>> = Mutex::new(None);
+
+fn frame_diff() -> MutexGuard<'static, Option<FrameDiff>> {
+    FRAME_DIFF.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+pub fn is_diff_view() -> bool {
+    DIFF_VIEW.load(Ordering::Relaxed)
+}
+
+/// Flips the diff view and returns the new state. Turning it on starts from
+/// the next composed frame.
+pub fn toggle_diff_view() -> bool {
+    let enabled = !DIFF_VIEW.fetch_xor(true, Ordering::Relaxed);
+    *frame_diff() = None;
+    enabled
+}
+
+/// Smallest rectangle holding every changed pixel, corners inclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bounds {
+    pub min: (u32, u32),
+    pub max: (u32, u32),
+}
+
+impl Bounds {
+    fn include(bounds: Option<Self>, x: u32, y: u32) -> Self {
+        match bounds {
+            Some(b) => Self {
+                min: (b.min.0.min(x), b.min.1.min(y)),
+                max: (b.max.0.max(x), b.max.1.max(y)),
+            },
+            None => Self {
+                min: (x, y),
+                max: (x, y),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DiffStats {
+    /// Pixels whose color changed in any channel
+    pub changed: usize,
+    pub bounds: Option<Bounds>,
+}
+
+/// A channel difference scaled by `AMPLIFY`, saturating at 255
+pub fn amplify(delta: u8) -> u8 {
+    (delta as u16 * AMPLIFY).min(255) as u8
+}
+
+/// Turns `previous`, an RGBA image `width` pixels wide, into its amplified
+/// per-channel difference from `current`, opaque. Alpha is not compared.
+pub fn diff_frames(previous: &mut [u8], current: &[u8], width: u32) -> DiffStats {
+    let mut stats = DiffStats::default();
+    let pixels = previous.chunks_exact_mut(4).zip(current.chunks_exact(4));
+    for (i, (pixel, after)) in pixels.enumerate() {
+        let changed = pixel[..3] != after[..3];
+        for c in 0..3 {
+            pixel[c] = amplify(pixel[c].abs_diff(after[c]));
+        }
+        pixel[3] = 255;
+        if changed {
+            stats.changed += 1;
+            let (x, y) = (i as u32 % width, i as u32 / width);
+            stats.bounds = Some(Bounds::include(stats.bounds, x, y));
+        }
+    }
+    stats
+}
+
+/// The last composed frame of one region
+pub struct FrameDiff {
+    region: Region,
+    composed: PooledBuf,
+    diff: PooledBuf,
+}
+
+impl FrameDiff {
+    /// Starts with nothing composed, so the first `apply` shows black
+    fn new() -> Self {
+        Self {
+            region: Region::new(0, 0, 0, 0),
+            composed: bufpool::get_buffer("frame diff", 0),
+            diff: bufpool::get_buffer("frame diff", 0),
+        }
+    }
+
+    /// Swaps the composed frame in `ctx` for its difference from the last one.
+    /// The first frame, or one of a new size, has nothing to compare with and
+    /// shows black.
+    pub fn apply(&mut self, ctx: &mut DrawCtx) -> DiffStats {
+        let size = (ctx.width() * ctx.height() * 4) as usize;
+        let stats = if self.region == ctx.region {
+            // The new frame is kept and the old one becomes the diff
+            ctx.save_region(&mut self.diff);
+            std::mem::swap(&mut self.composed, &mut self.diff);
+            let stats = diff_frames(&mut self.diff, &self.composed, ctx.width());
+            ctx.restore_region(&self.diff);
+            stats
+        } else {
+            self.region = ctx.region;
+            self.composed.reset(size);
+            self.diff.reset(size);
+            ctx.save_region(&mut self.composed);
+            ctx.clear([0, 0, 0, 255]);
+            DiffStats::default()
+        };
+        if let Some(bounds) = stats.bounds {
+            draw_box(ctx, bounds);
+        }
+        stats
+    }
+
+    /// Puts the last composed frame back in place of the diff
+    pub fn restore(&self, ctx: &mut DrawCtx) {
+        if self.region == ctx.region {
+            ctx.restore_region(&self.composed);
+        }
+    }
+}
+
+fn draw_box(ctx: &mut DrawCtx, bounds: Bounds) {
+    let (x0, y0) = (bounds.min.0 as i32, bounds.min.1 as i32);
+    let (x1, y1) = (bounds.max.0 as i32, bounds.max.1 as i32);
+    for x in x0..=x1 {
+        ctx.put_pixel(x, y0, BOX_COLOR);
+        ctx.put_pixel(x, y1, BOX_COLOR);
+    }
+    for y in y0..=y1 {
+        ctx.put_pixel(x0, y, BOX_COLOR);
+        ctx.put_pixel(x1, y, BOX_COLOR);
+    }
+}
+
+/// Puts the real frame back before the scene draws over it
+pub fn restore_composed(ctx: &mut DrawCtx) {
+    if !is_diff_view() {
+        return;
+    }
+    if let Some(diff) = frame_diff().as_ref() {
+        diff.restore(ctx);
+    }
+}
+
+/// Replaces the composed frame with the diff view and labels it
+pub fn draw_diff_view(
+    frame: &mut [u8],
+    width: u32,
+    height: u32,
+    x_offset: usize,
+    buffer_width: u32,
+) {
+    let mut ctx = DrawCtx::from_legacy(frame, width, height, 0.0, x_offset, buffer_width);
+    let stats = frame_diff()
+        .get_or_insert_with(FrameDiff::new)
+        .apply(&mut ctx);
+    let mut label = format!("Frame diff x{}: {} changed", AMPLIFY, stats.changed);
+    if let Some(b) = stats.bounds {
+        label.push_str(&format!(
+            " in ({}, {})-({}, {})",
+            b.min.0, b.min.1, b.max.0, b.max.1
+        ));
+    }
+    crate::text::text_rendering::draw_text_with_background(
+        frame,
+        &label,
+        x_offset as f32 + 10.0,
+        30.0,
+        [255, 200, 255, 255],
+        [0, 0, 0, 160],
+        buffer_width,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(pixels: &[[u8; 4]]) -> Vec<u8> {
+        pixels.concat()
+    }
+
+    #[test]
+    fn test_difference_is_amplified_and_clamped() {
+        assert_eq!(amplify(0), 0);
+        assert_eq!(amplify(3), 24);
+        assert_eq!(amplify(31), 248);
+        assert_eq!(amplify(32), 255);
+        assert_eq!(amplify(255), 255);
+
+        let mut out = image(&[[10, 10, 10, 255], [200, 0, 50, 255]]);
+        let after = image(&[[10, 10, 10, 0], [190, 100, 51, 255]]);
+        let stats = diff_frames(&mut out, &after, 2);
+        // Alpha changes alone don't count
+        assert_eq!(&out[..4], &[0, 0, 0, 255]);
+        assert_eq!(&out[4..], &[80, 255, 8, 255]);
+        assert_eq!(stats.changed, 1);
+    }
+
+    #[test]
+    fn test_bounds_cover_every_changed_pixel() {
+        let (width, height) = (6u32, 5u32);
+        let before = vec![0; (width * height * 4) as usize];
+        let mut after = before.clone();
+        assert_eq!(
+            diff_frames(&mut before.clone(), &after, width),
+            DiffStats::default()
+        );
+        for (x, y) in [(4, 1), (1, 3), (2, 2)] {
+            after[((y * width + x) * 4) as usize] = 1;
+        }
+        let stats = diff_frames(&mut before.clone(), &after, width);
+        assert_eq!(stats.changed, 3);
+        assert_eq!(
+            stats.bounds,
+            Some(Bounds {
+                min: (1, 1),
+                max: (4, 3)
+            })
+        );
+    }
+
+    #[test]
+    fn test_view_diffs_against_the_composed_frame_not_the_diff() {
+        let (width, height) = (8u32, 6u32);
+        let mut frame = vec![40; (width * height * 4) as usize];
+        let mut ctx = DrawCtx::from_legacy(&mut frame, width, height, 0.0, 0, width);
+        let mut diff = FrameDiff::new();
+        // Nothing to compare the first frame with
+        assert_eq!(diff.apply(&mut ctx), DiffStats::default());
+        assert!(ctx.frame.chunks_exact(4).all(|px| px == [0, 0, 0, 255]));
+
+        // The scene draws over the restored frame: one pixel moves
+        diff.restore(&mut ctx);
+        assert!(ctx.frame.iter().all(|&b| b == 40));
+        ctx.put_pixel(3, 2, [41, 40, 40, 255]);
+        let stats = diff.apply(&mut ctx);
+        assert_eq!(stats.changed, 1);
+        assert_eq!(&ctx.frame[..4], &[0, 0, 0, 255]);
+        // The one changed pixel is also the box around the change
+        let idx = ((2 * width + 3) * 4) as usize;
+        assert_eq!(&ctx.frame[idx..idx + 4], &BOX_COLOR);
+
+        // A static frame is all black next time
+        diff.restore(&mut ctx);
+        assert_eq!(diff.apply(&mut ctx).changed, 0);
+        assert!(ctx.frame.chunks_exact(4).all(|px| px == [0, 0, 0, 255]));
+    }
+}
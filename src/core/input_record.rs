@@ -0,0 +1,588 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+use winit::event::MouseButton;
+use winit::keyboard::KeyCode;
+use winit_input_helper::WinitInputHelper;
+
+/// File magic for recorded sessions
+const MAGIC: &[u8; 4] = b"STIM";
+/// Bumped whenever the frame encoding changes
+pub const SESSION_VERSION: u16 = 3;
+
+/// Keys the app reacts to. Their position in this list is their bit in the key masks,
+/// so new keys must be appended to keep old recordings valid.
+pub const TRACKED_KEYS: &[KeyCode] = &[
+    KeyCode::Escape,
+    KeyCode::Digit9,
+    KeyCode::F4,
+    KeyCode::F5,
+    KeyCode::KeyL,
+    KeyCode::Space,
+    KeyCode::KeyK,
+    KeyCode::BracketLeft,
+    KeyCode::BracketRight,
+    KeyCode::ArrowLeft,
+    KeyCode::ArrowRight,
+    KeyCode::ArrowUp,
+    KeyCode::ArrowDown,
+    KeyCode::KeyB,
+    KeyCode::KeyC,
+    KeyCode::KeyG,
+    KeyCode::KeyW,
+    KeyCode::KeyV,
+    KeyCode::KeyP,
+    KeyCode::KeyZ,
+    KeyCode::KeyX,
+    KeyCode::KeyN,
+    KeyCode::F3,
+    KeyCode::F6,
+    KeyCode::F7,
+    KeyCode::KeyM,
+    KeyCode::KeyI,
+    KeyCode::F8,
+    KeyCode::Comma,
+    KeyCode::Period,
+    KeyCode::KeyR,
+    KeyCode::F10,
+    KeyCode::KeyH,
+    KeyCode::PageUp,
+    KeyCode::PageDown,
+    KeyCode::Minus,
+    KeyCode::Equal,
+    KeyCode::Semicolon,
+    KeyCode::Quote,
+    KeyCode::KeyO,
+    KeyCode::Enter,
+    KeyCode::F12,
+    KeyCode::KeyE,
+    KeyCode::Digit0,
+    KeyCode::KeyT,
+    KeyCode::KeyF,
+    KeyCode::F9,
+];
+
+const TRACKED_BUTTONS: [MouseButton; 3] =
+    [MouseButton::Left, MouseButton::Right, MouseButton::Middle];
+
+const FLAG_SHIFT: u8 = 1;
+const FLAG_CURSOR: u8 = 2;
+const FLAG_CONTROL: u8 = 4;
+const FLAG_SCROLL: u8 = 8;
+
+/// Input state for one frame, independent of where it came from
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct InputFrame {
+    pub dt: f32,
+    pub pressed: u64,
+    pub held: u64,
+    pub shift: bool,
+    pub control: bool,
+    pub cursor: Option<(f32, f32)>,
+    pub buttons_pressed: u8,
+    pub buttons_held: u8,
+    /// Scroll-wheel lines this frame, positive away from the user
+    pub scroll: f32,
+}
+
+/// Whether the app sees `key` at all; keys outside `TRACKED_KEYS` never
+/// show up as pressed, live or replayed
+pub fn is_tracked(key: KeyCode) -> bool {
+    key_bit(key).is_some()
+}
+
+fn key_bit(key: KeyCode) -> Option<u64> {
+    TRACKED_KEYS
+        .iter()
+        .position(|&tracked| tracked == key)
+        .map(|i| 1 << i)
+}
+
+fn button_bit(button: MouseButton) -> Option<u8> {
+    TRACKED_BUTTONS
+        .iter()
+        .position(|&tracked| tracked == button)
+        .map(|i| 1 << i)
+}
+
+impl InputFrame {
+    /// Captures the tracked keys, buttons, and cursor from the live input helper
+    pub fn from_helper(input: &WinitInputHelper) -> Self {
+        let mut frame = InputFrame {
+            dt: input.delta_time().map(|d| d.as_secs_f32()).unwrap_or(0.0),
+            shift: input.held_shift(),
+            control: input.held_control(),
+            cursor: input.cursor(),
+            scroll: input.scroll_diff().1,
+            ..Default::default()
+        };
+        for (i, &key) in TRACKED_KEYS.iter().enumerate() {
+            if input.key_pressed(key) {
+                frame.pressed |= 1 << i;
+            }
+            if input.key_held(key) {
+                frame.held |= 1 << i;
+            }
+        }
+        for (i, &button) in TRACKED_BUTTONS.iter().enumerate() {
+            if input.mouse_pressed(button) {
+                frame.buttons_pressed |= 1 << i;
+            }
+            if input.mouse_held(button) {
+                frame.buttons_held |= 1 << i;
+            }
+        }
+        frame
+    }
+
+    /// Records a key going down or up, for hosts that deliver events one at a
+    /// time instead of through the input helper. Shift and Control set the
+    /// modifier flags; other untracked keys are ignored.
+    pub fn set_key(&mut self, key: KeyCode, down: bool) {
+        match key {
+            KeyCode::ShiftLeft | KeyCode::ShiftRight => self.shift = down,
+            KeyCode::ControlLeft | KeyCode::ControlRight => self.control = down,
+            _ => {}
+        }
+        if let Some(bit) = key_bit(key) {
+            if down {
+                // Auto-repeat arrives as more key-downs; only the first is a press
+                if self.held & bit == 0 {
+                    self.pressed |= bit;
+                }
+                self.held |= bit;
+            } else {
+                self.held &= !bit;
+            }
+        }
+    }
+
+    pub fn set_button(&mut self, button: MouseButton, down: bool) {
+        if let Some(bit) = button_bit(button) {
+            if down {
+                if self.buttons_held & bit == 0 {
+                    self.buttons_pressed |= bit;
+                }
+                self.buttons_held |= bit;
+            } else {
+                self.buttons_held &= !bit;
+            }
+        }
+    }
+
+    /// The state the next frame starts from: keys and buttons stay held and
+    /// the cursor stays put, but presses have been used up
+    pub fn carried_over(&self) -> Self {
+        InputFrame {
+            dt: 0.0,
+            pressed: 0,
+            buttons_pressed: 0,
+            scroll: 0.0,
+            ..*self
+        }
+    }
+
+    pub fn key_pressed(&self, key: KeyCode) -> bool {
+        key_bit(key).is_some_and(|bit| self.pressed & bit != 0)
+    }
+
+    pub fn key_held(&self, key: KeyCode) -> bool {
+        key_bit(key).is_some_and(|bit| self.held & bit != 0)
+    }
+
+    pub fn held_shift(&self) -> bool {
+        self.shift
+    }
+
+    pub fn held_control(&self) -> bool {
+        self.control
+    }
+
+    pub fn mouse_pressed(&self, button: MouseButton) -> bool {
+        button_bit(button).is_some_and(|bit| self.buttons_pressed & bit != 0)
+    }
+
+    pub fn mouse_held(&self, button: MouseButton) -> bool {
+        button_bit(button).is_some_and(|bit| self.buttons_held & bit != 0)
+    }
+
+    /// Appends the compact binary form of this frame
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.dt.to_le_bytes());
+        out.extend_from_slice(&self.pressed.to_le_bytes());
+        out.extend_from_slice(&self.held.to_le_bytes());
+        let mut flags = 0;
+        if self.shift {
+            flags |= FLAG_SHIFT;
+        }
+        if self.cursor.is_some() {
+            flags |= FLAG_CURSOR;
+        }
+        if self.control {
+            flags |= FLAG_CONTROL;
+        }
+        if self.scroll != 0.0 {
+            flags |= FLAG_SCROLL;
+        }
+        out.push(flags);
+        if let Some((x, y)) = self.cursor {
+            out.extend_from_slice(&x.to_le_bytes());
+            out.extend_from_slice(&y.to_le_bytes());
+        }
+        if self.scroll != 0.0 {
+            out.extend_from_slice(&self.scroll.to_le_bytes());
+        }
+        out.push(self.buttons_pressed);
+        out.push(self.buttons_held);
+    }
+
+    /// Decodes one frame, returning it and the number of bytes used
+    pub fn decode(bytes: &[u8]) -> Option<(Self, usize)> {
+        let mut reader = ByteReader { bytes, pos: 0 };
+        let dt = f32::from_le_bytes(reader.take()?);
+        let pressed = u64::from_le_bytes(reader.take()?);
+        let held = u64::from_le_bytes(reader.take()?);
+        let [flags] = reader.take()?;
+        let cursor = if flags & FLAG_CURSOR != 0 {
+            let x = f32::from_le_bytes(reader.take()?);
+            let y = f32::from_le_bytes(reader.take()?);
+            Some((x, y))
+        } else {
+            None
+        };
+        let scroll = if flags & FLAG_SCROLL != 0 {
+            f32::from_le_bytes(reader.take()?)
+        } else {
+            0.0
+        };
+        let [buttons_pressed] = reader.take()?;
+        let [buttons_held] = reader.take()?;
+        let frame = InputFrame {
+            dt,
+            pressed,
+            held,
+            shift: flags & FLAG_SHIFT != 0,
+            control: flags & FLAG_CONTROL != 0,
+            cursor,
+            buttons_pressed,
+            buttons_held,
+            scroll,
+        };
+        Some((frame, reader.pos))
+    }
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl ByteReader<'_> {
+    fn take<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let chunk = self.bytes.get(self.pos..self.pos + N)?;
+        self.pos += N;
+        chunk.try_into().ok()
+    }
+}
+
+/// Session header: format version and the RNG seed the session was recorded with
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SessionHeader {
+    pub version: u16,
+    pub seed: u64,
+}
+
+impl SessionHeader {
+    const LEN: usize = 14;
+
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&self.version.to_le_bytes());
+        out.extend_from_slice(&self.seed.to_le_bytes());
+    }
+
+    pub fn decode(bytes: &[u8]) -> io::Result<Self> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+        if bytes.len() < Self::LEN || &bytes[..4] != MAGIC {
+            return Err(invalid("not a recorded session"));
+        }
+        let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+        if version != SESSION_VERSION {
+            return Err(invalid("unsupported session version"));
+        }
+        let mut seed = [0; 8];
+        seed.copy_from_slice(&bytes[6..14]);
+        Ok(Self {
+            version,
+            seed: u64::from_le_bytes(seed),
+        })
+    }
+}
+
+/// Appends every frame's input to a session file
+pub struct InputRecorder {
+    writer: BufWriter<File>,
+    buffer: Vec<u8>,
+}
+
+impl InputRecorder {
+    pub fn create(path: &Path, seed: u64) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        let mut header = Vec::new();
+        SessionHeader {
+            version: SESSION_VERSION,
+            seed,
+        }
+        .encode(&mut header);
+        writer.write_all(&header)?;
+        Ok(Self {
+            writer,
+            buffer: Vec::new(),
+        })
+    }
+
+    pub fn record(&mut self, frame: &InputFrame) -> io::Result<()> {
+        self.buffer.clear();
+        frame.encode(&mut self.buffer);
+        self.writer.write_all(&self.buffer)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Plays back a recorded session frame by frame
+#[derive(Debug, Clone)]
+pub struct InputReplayer {
+    header: SessionHeader,
+    frames: Vec<InputFrame>,
+    index: usize,
+}
+
+impl InputReplayer {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut bytes = Vec::new();
+        File::open(path)?.read_to_end(&mut bytes)?;
+        Self::from_bytes(&bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let header = SessionHeader::decode(bytes)?;
+        let mut frames = Vec::new();
+        let mut rest = &bytes[SessionHeader::LEN..];
+        while let Some((frame, used)) = InputFrame::decode(rest) {
+            frames.push(frame);
+            rest = &rest[used..];
+        }
+        Ok(Self {
+            header,
+            frames,
+            index: 0,
+        })
+    }
+
+    pub fn header(&self) -> SessionHeader {
+        self.header
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.index >= self.frames.len()
+    }
+
+    pub fn next_frame(&mut self) -> Option<InputFrame> {
+        let frame = self.frames.get(self.index).copied();
+        self.index += 1;
+        frame
+    }
+}
+
+/// Where each frame's input comes from. Live input is optionally recorded;
+/// during replay the live input is ignored and recorded frames are injected instead.
+#[derive(Default)]
+pub enum InputSource {
+    #[default]
+    Live,
+    Recording(InputRecorder),
+    Replay(InputReplayer),
+}
+
+impl InputSource {
+    /// Returns the input to apply this frame, given what the user is doing right now.
+    /// Once a replay runs out, input is empty (the demo simply keeps running).
+    pub fn next_frame(&mut self, live: InputFrame) -> InputFrame {
+        match self {
+            InputSource::Live => live,
+            InputSource::Recording(recorder) => {
+                if let Err(e) = recorder.record(&live) {
+                    log::error!("Failed to record input: {}", e);
+                }
+                live
+            }
+            InputSource::Replay(replayer) => replayer.next_frame().unwrap_or_default(),
+        }
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        matches!(self, InputSource::Replay(_))
+    }
+
+    pub fn flush(&mut self) {
+        if let InputSource::Recording(recorder) = self {
+            if let Err(e) = recorder.flush() {
+                log::error!("Failed to flush input recording: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_frames() -> Vec<InputFrame> {
+        vec![
+            InputFrame {
+                dt: 1.0 / 60.0,
+                ..Default::default()
+            },
+            InputFrame {
+                dt: 0.017,
+                pressed: key_bit(KeyCode::Space).unwrap(),
+                held: key_bit(KeyCode::ArrowLeft).unwrap(),
+                shift: true,
+                control: true,
+                cursor: Some((120.5, 64.25)),
+                buttons_pressed: button_bit(MouseButton::Right).unwrap(),
+                buttons_held: 0,
+                scroll: -2.5,
+            },
+            InputFrame {
+                dt: 0.016,
+                held: key_bit(KeyCode::ArrowLeft).unwrap(),
+                buttons_held: button_bit(MouseButton::Left).unwrap(),
+                ..Default::default()
+            },
+        ]
+    }
+
+    #[test]
+    fn test_key_events_build_a_frame() {
+        let mut frame = InputFrame::default();
+        frame.set_key(KeyCode::KeyG, true);
+        frame.set_key(KeyCode::ShiftLeft, true);
+        frame.set_button(MouseButton::Left, true);
+        assert!(frame.key_pressed(KeyCode::KeyG) && frame.key_held(KeyCode::KeyG));
+        assert!(frame.held_shift());
+        assert!(frame.mouse_pressed(MouseButton::Left));
+
+        // Held keys carry over; a repeated key-down is not a second press
+        let mut next = frame.carried_over();
+        next.set_key(KeyCode::KeyG, true);
+        assert!(!next.key_pressed(KeyCode::KeyG) && next.key_held(KeyCode::KeyG));
+        assert!(next.mouse_held(MouseButton::Left) && !next.mouse_pressed(MouseButton::Left));
+        next.set_key(KeyCode::KeyG, false);
+        next.set_key(KeyCode::KeyQ, true);
+        assert_eq!((next.pressed, next.held), (0, 0));
+
+        // F9 swaps the pixel format, so it has to get through
+        let mut swap = InputFrame::default();
+        swap.set_key(KeyCode::F9, true);
+        assert!(is_tracked(KeyCode::F9) && swap.key_pressed(KeyCode::F9));
+    }
+
+    fn session_bytes(seed: u64, frames: &[InputFrame]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        SessionHeader {
+            version: SESSION_VERSION,
+            seed,
+        }
+        .encode(&mut bytes);
+        for frame in frames {
+            frame.encode(&mut bytes);
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_frame_round_trip() {
+        for frame in sample_frames() {
+            let mut bytes = Vec::new();
+            frame.encode(&mut bytes);
+            let (decoded, used) = InputFrame::decode(&bytes).unwrap();
+            assert_eq!(decoded, frame);
+            assert_eq!(used, bytes.len());
+        }
+    }
+
+    #[test]
+    fn test_session_round_trip_through_file() {
+        let path = std::env::temp_dir().join("stimstation_input_record_test.bin");
+        let frames = sample_frames();
+        let mut recorder = InputRecorder::create(&path, 42).unwrap();
+        for frame in &frames {
+            recorder.record(frame).unwrap();
+        }
+        recorder.flush().unwrap();
+        drop(recorder);
+
+        let mut replayer = InputReplayer::open(&path).unwrap();
+        assert_eq!(replayer.header().seed, 42);
+        assert_eq!(replayer.len(), frames.len());
+        for frame in &frames {
+            assert_eq!(replayer.next_frame().as_ref(), Some(frame));
+        }
+        assert!(replayer.is_finished());
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_rejects_bad_header_and_ignores_truncated_frame() {
+        assert!(InputReplayer::from_bytes(b"NOPE0000000000").is_err());
+
+        let mut bytes = session_bytes(7, &sample_frames());
+        bytes.truncate(bytes.len() - 1);
+        let replayer = InputReplayer::from_bytes(&bytes).unwrap();
+        assert_eq!(replayer.len(), 2);
+    }
+
+    #[test]
+    fn test_replay_injects_recorded_input_instead_of_live() {
+        let frames = sample_frames();
+        let replayer = InputReplayer::from_bytes(&session_bytes(1, &frames)).unwrap();
+        let mut source = InputSource::Replay(replayer);
+        let live = InputFrame {
+            pressed: key_bit(KeyCode::Escape).unwrap(),
+            ..Default::default()
+        };
+
+        let first = source.next_frame(live);
+        assert!(!first.key_pressed(KeyCode::Escape));
+        let second = source.next_frame(live);
+        assert!(second.key_pressed(KeyCode::Space));
+        assert!(second.key_held(KeyCode::ArrowLeft));
+        assert!(second.held_shift());
+        assert!(second.mouse_pressed(MouseButton::Right));
+        assert_eq!(second.cursor, Some((120.5, 64.25)));
+        source.next_frame(live);
+
+        // After the recording ends no input is applied
+        assert_eq!(source.next_frame(live), InputFrame::default());
+    }
+
+    #[test]
+    fn test_live_source_passes_input_through() {
+        let mut source = InputSource::Live;
+        let live = sample_frames()[1];
+        assert_eq!(source.next_frame(live), live);
+        assert!(!source.is_replaying());
+    }
+}
@@ -1,36 +1,133 @@
 use crate::audio::audio_integration::AudioIntegration;
+use crate::core::station_state;
+use crate::graphics::draw_ctx::{DrawCtx, Region};
 use crate::text::text_processor::TextProcessor;
+use log::{info, warn};
+use std::ops::RangeInclusive;
+use std::sync::{Mutex, MutexGuard, OnceLock, PoisonError};
+use winit::dpi::PhysicalSize;
 use winit::monitor::MonitorHandle;
 
-static mut AUDIO_INTEGRATION: Option<AudioIntegration> = None;
-static mut TEXT_RENDERER: Option<TextProcessor> = None;
-static mut MONITOR_WIDTH: Option<u32> = None;
-static mut MONITOR_HEIGHT: Option<u32> = None;
-
-pub fn set_monitor_dimensions(monitor: &MonitorHandle) {
-    let size = monitor.size();
-    unsafe {
-        MONITOR_WIDTH = Some(size.width);
-        MONITOR_HEIGHT = Some(size.height);
-        println!("Monitor dimensions set: {}x{}", size.width, size.height);
+/// Set by `initialize_text_renderer`; holds no renderer until something
+/// hands over a glyphon renderer, which needs the window's GPU device
+static TEXT_RENDERER: OnceLock<Option<Mutex<TextProcessor>>> = OnceLock::new();
+/// Set by `set_monitor_dimensions`
+static MONITOR: Mutex<Option<Monitor>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Copy)]
+struct Monitor {
+    dimensions: (u32, u32),
+    scale: (f32, f32),
+}
+
+/// Sides outside this range come from VMs and headless servers, not screens
+pub const MONITOR_SIDE_RANGE: RangeInclusive<u32> = 320..=16384;
+/// Resolution the scale factors are measured against
+pub const BASELINE_MONITOR: (u32, u32) = (1920, 1080);
+pub const MONITOR_SCALE_RANGE: RangeInclusive<f32> = 0.5..=3.0;
+
+/// Dimensions to scale by and the scale factors they give
+#[derive(Debug, Clone, PartialEq)]
+pub struct MonitorScale {
+    pub dimensions: (u32, u32),
+    pub scale: (f32, f32),
+    /// Why the monitor's own size wasn't used as is
+    pub warning: Option<String>,
+}
+
+/// Picks the first believable size of the monitor, then the window, then the
+/// baseline, and scales it against the baseline within `MONITOR_SCALE_RANGE`
+pub fn monitor_scale(monitor: Option<(u32, u32)>, window: Option<(u32, u32)>) -> MonitorScale {
+    let believable = |size: Option<(u32, u32)>| {
+        size.filter(|(w, h)| MONITOR_SIDE_RANGE.contains(w) && MONITOR_SIDE_RANGE.contains(h))
+    };
+    let mut problems = Vec::new();
+    let dimensions = if let Some(size) = believable(monitor) {
+        size
+    } else if let Some(size) = believable(window) {
+        problems.push(format!(
+            "monitor reports {:?}, using the window size",
+            monitor
+        ));
+        size
+    } else {
+        problems.push(format!(
+            "monitor reports {:?} and window {:?}, using {}x{}",
+            monitor, window, BASELINE_MONITOR.0, BASELINE_MONITOR.1
+        ));
+        BASELINE_MONITOR
+    };
+    let raw = (
+        dimensions.0 as f32 / BASELINE_MONITOR.0 as f32,
+        dimensions.1 as f32 / BASELINE_MONITOR.1 as f32,
+    );
+    let clamp = |s: f32| s.clamp(*MONITOR_SCALE_RANGE.start(), *MONITOR_SCALE_RANGE.end());
+    let scale = (clamp(raw.0), clamp(raw.1));
+    if scale != raw {
+        problems.push(format!(
+            "scale {:.2}x{:.2} clamped to {:.2}x{:.2}",
+            raw.0, raw.1, scale.0, scale.1
+        ));
+    }
+    MonitorScale {
+        dimensions,
+        scale,
+        warning: (!problems.is_empty()).then(|| problems.join("; ")),
     }
 }
 
+/// Sets the sizes are scaled by from the window's monitor, or the window
+/// itself when the monitor is missing or reports nonsense
+pub fn set_monitor_dimensions(monitor: Option<&MonitorHandle>, window: PhysicalSize<u32>) {
+    let monitor = monitor.map(|m| (m.size().width, m.size().height));
+    let resolved = monitor_scale(monitor, Some((window.width, window.height)));
+    if let Some(warning) = &resolved.warning {
+        warn!("Monitor dimensions: {}", warning);
+    }
+    let (width, height) = resolved.dimensions;
+    *monitor_guard() = Some(Monitor {
+        dimensions: resolved.dimensions,
+        scale: resolved.scale,
+    });
+    info!("Monitor dimensions set: {}x{}", width, height);
+}
+
+fn monitor_guard() -> MutexGuard<'static, Option<Monitor>> {
+    MONITOR.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
 pub fn get_monitor_dimensions() -> (Option<u32>, Option<u32>) {
-    unsafe { (MONITOR_WIDTH, MONITOR_HEIGHT) }
+    let dimensions = monitor_guard().map(|monitor| monitor.dimensions);
+    (dimensions.map(|d| d.0), dimensions.map(|d| d.1))
+}
+
+/// Horizontal and vertical scale of the monitor against the baseline; 1.0
+/// until the dimensions are set
+pub fn get_monitor_scale() -> (f32, f32) {
+    monitor_guard().map_or((1.0, 1.0), |monitor| monitor.scale)
 }
 
+/// Creates the audio bars of the station being driven and starts playback;
+/// later calls for the same station do nothing
 pub fn initialize_audio_integration() {
-    unsafe {
-        if AUDIO_INTEGRATION.is_none() {
-            AUDIO_INTEGRATION = Some(AudioIntegration::new());
-        }
-        if let Some(audio_integration) = AUDIO_INTEGRATION.as_mut() {
-            audio_integration.initialize();
-        }
-    }
+    initialize_audio_integration_with(true);
 }
 
+/// Like `initialize_audio_integration`, but playback is only started if `playback` is set
+pub fn initialize_audio_integration_with(playback: bool) {
+    let station = station_state::current();
+    station_state::lock(&station.audio).get_or_insert_with(|| {
+        let mut audio_integration = if playback {
+            AudioIntegration::new()
+        } else {
+            AudioIntegration::without_playback()
+        };
+        audio_integration.initialize();
+        audio_integration
+    });
+}
+
+/// Adapter for callers still passing raw frame parameters; see `draw_audio`
 pub fn update_and_draw_audio(
     frame: &mut [u8],
     width: u32,
@@ -39,16 +136,35 @@ pub fn update_and_draw_audio(
     x_offset: usize,
     buffer_width: u32,
 ) {
-    unsafe {
-        if let Some(audio_integration) = AUDIO_INTEGRATION.as_mut() {
-            let monitor_height = MONITOR_HEIGHT;
-            audio_integration.update(time, monitor_height);
-            audio_integration.draw(frame, width, height, x_offset, buffer_width);
-        }
+    let mut ctx = DrawCtx::from_legacy(frame, width, height, time, x_offset, buffer_width);
+    draw_audio(&mut ctx);
+}
+
+/// Advances the audio bars to the context's time and draws them across its region
+pub fn draw_audio(ctx: &mut DrawCtx) {
+    let whole = Region::new(0, 0, ctx.width(), ctx.height());
+    draw_audio_in(ctx, whole);
+}
+
+/// Like `draw_audio`, but the bars are laid out in and confined to `target`,
+/// given relative to the context's region, such as one pane of a split frame
+pub fn draw_audio_in(ctx: &mut DrawCtx, target: Region) {
+    let station = station_state::current();
+    let mut audio_integration = station_state::lock(&station.audio);
+    debug_assert!(
+        audio_integration.is_some(),
+        "audio bars drawn before initialize_audio_integration()"
+    );
+    if let Some(audio_integration) = audio_integration.as_mut() {
+        let (_, height_scale) = get_monitor_scale();
+        audio_integration.update(ctx.time, height_scale);
+        audio_integration.draw(ctx, target);
     }
 }
 
-pub fn initialize_text_renderer() {}
+pub fn initialize_text_renderer() {
+    TEXT_RENDERER.get_or_init(|| None);
+}
 
 pub fn update_and_draw_text(
     frame: &mut [u8],
@@ -58,10 +174,73 @@ pub fn update_and_draw_text(
     x_offset: usize,
     buffer_width: u32,
 ) {
-    unsafe {
-        if let Some(text_renderer) = TEXT_RENDERER.as_mut() {
-            text_renderer.update(time, width, height);
-            text_renderer.draw(frame, width, height, x_offset, buffer_width);
+    let text_renderer = TEXT_RENDERER.get();
+    debug_assert!(
+        text_renderer.is_some(),
+        "text drawn before initialize_text_renderer()"
+    );
+    if let Some(Ok(mut text_renderer)) = text_renderer.and_then(Option::as_ref).map(Mutex::lock) {
+        text_renderer.update(time, width, height);
+        text_renderer.draw(frame, width, height, x_offset, buffer_width);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bogus_monitors_fall_back_to_the_window_then_the_baseline() {
+        let window = Some((1280, 720));
+        let bogus = [
+            None,
+            Some((0, 0)),
+            Some((0, 1080)),
+            Some((1920, 0)),
+            Some((1, 1)),
+            Some((100_000, 100_000)),
+            Some((u32::MAX, 1080)),
+        ];
+        for monitor in bogus {
+            let resolved = monitor_scale(monitor, window);
+            assert_eq!(resolved.dimensions, (1280, 720), "{:?}", monitor);
+            assert!(resolved.warning.is_some());
+            for bad_window in [None, Some((0, 0)), Some((40_000, 10))] {
+                let resolved = monitor_scale(monitor, bad_window);
+                assert_eq!(resolved.dimensions, BASELINE_MONITOR);
+                assert_eq!(resolved.scale, (1.0, 1.0));
+            }
+        }
+
+        let resolved = monitor_scale(Some((2560, 1440)), window);
+        assert_eq!(resolved.dimensions, (2560, 1440));
+        assert!((resolved.scale.0 - 4.0 / 3.0).abs() < 1e-6);
+        assert_eq!(resolved.warning, None);
+    }
+
+    #[test]
+    fn test_scale_stays_within_range_for_believable_sizes() {
+        // Small and huge screens that pass the sanity check still scale sanely
+        let resolved = monitor_scale(Some((480, 320)), None);
+        assert_eq!(resolved.dimensions, (480, 320));
+        assert_eq!(resolved.scale, (0.5, 0.5));
+        assert!(resolved.warning.is_some());
+        let resolved = monitor_scale(Some((15360, 8640)), None);
+        assert_eq!(resolved.scale, (3.0, 3.0));
+        assert_eq!(
+            monitor_scale(Some((1920, 1080)), None),
+            MonitorScale {
+                dimensions: (1920, 1080),
+                scale: (1.0, 1.0),
+                warning: None,
+            }
+        );
+
+        for side in [0, 1, 319, 320, 1000, 5000, 16384, 16385, u32::MAX] {
+            for other in [0, 720, 1080, 20000] {
+                let (sx, sy) = monitor_scale(Some((side, other)), Some((other, side))).scale;
+                assert!(MONITOR_SCALE_RANGE.contains(&sx) && MONITOR_SCALE_RANGE.contains(&sy));
+            }
         }
     }
 }
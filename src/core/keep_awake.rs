@@ -0,0 +1,695 @@
+//! Keeps the system screensaver and lock from kicking in while the app is
+//! being watched: while audio plays, or always when keep-awake is on. The
+//! inhibit is released when neither holds, while the scene is paused, and on
+//! quit. Linux asks org.freedesktop.ScreenSaver over the session bus, Windows
+//! uses SetThreadExecutionState, and other platforms do nothing.
+
+use log::{info, warn};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Name the platform shows next to the inhibit
+const APP_NAME: &str = "stimstation";
+
+/// Blocks the screensaver on one platform. The app uses the one for the
+/// platform it was built for; tests substitute a mock.
+pub trait InhibitBackend {
+    fn name(&self) -> &'static str;
+    /// Blocks the screensaver, returning a cookie that releases it
+    fn inhibit(&mut self, reason: &str) -> Result<u32, String>;
+    fn release(&mut self, cookie: u32) -> Result<(), String>;
+}
+
+/// Whether the screensaver should be held off, and why
+pub fn inhibit_reason(audio_playing: bool, keep_awake: bool, paused: bool) -> Option<&'static str> {
+    if paused {
+        None
+    } else if keep_awake {
+        Some("Keep awake is on")
+    } else if audio_playing {
+        Some("Audio is playing")
+    } else {
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InhibitState {
+    Released,
+    Held(u32),
+    /// The backend refused; not retried until the inhibit is no longer wanted
+    Unavailable,
+}
+
+/// Takes and drops the inhibit as the reason for it comes and goes, holding
+/// at most one at a time
+pub struct Inhibitor<B: InhibitBackend> {
+    backend: B,
+    state: InhibitState,
+}
+
+impl<B: InhibitBackend> Inhibitor<B> {
+    pub fn new(backend: B) -> Self {
+        Self {
+            backend,
+            state: InhibitState::Released,
+        }
+    }
+
+    /// Holds the inhibit while `reason` is Some and releases it once it is None
+    pub fn update(&mut self, reason: Option<&str>) {
+        match (self.state, reason) {
+            (InhibitState::Released, Some(reason)) => match self.backend.inhibit(reason) {
+                Ok(cookie) => {
+                    info!(
+                        "Screensaver inhibited via {}: {}",
+                        self.backend.name(),
+                        reason
+                    );
+                    self.state = InhibitState::Held(cookie);
+                }
+                Err(e) => {
+                    warn!(
+                        "Screensaver inhibit unavailable via {}: {}",
+                        self.backend.name(),
+                        e
+                    );
+                    self.state = InhibitState::Unavailable;
+                }
+            },
+            (InhibitState::Unavailable, None) => self.state = InhibitState::Released,
+            (InhibitState::Held(_), None) => self.release(),
+            _ => {}
+        }
+    }
+
+    /// Drops the inhibit if one is held
+    pub fn release(&mut self) {
+        if let InhibitState::Held(cookie) = self.state {
+            // Either way nothing is held any more: a backend that fails here has
+            // lost its connection, and the platform drops the inhibit with it
+            match self.backend.release(cookie) {
+                Ok(()) => info!("Screensaver inhibit released"),
+                Err(e) => warn!("Screensaver inhibit released with an error: {}", e),
+            }
+        }
+        self.state = InhibitState::Released;
+    }
+
+    pub fn state(&self) -> InhibitState {
+        self.state
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+}
+
+impl<B: InhibitBackend> Drop for Inhibitor<B> {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+/// Stands in on platforms without a screensaver integration
+#[derive(Default)]
+pub struct NoopBackend;
+
+impl InhibitBackend for NoopBackend {
+    fn name(&self) -> &'static str {
+        "none"
+    }
+
+    fn inhibit(&mut self, _reason: &str) -> Result<u32, String> {
+        Err("not supported on this platform".to_string())
+    }
+
+    fn release(&mut self, _cookie: u32) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+pub use windows::ExecutionStateBackend as PlatformBackend;
+
+#[cfg(target_os = "linux")]
+pub use session_bus::DbusBackend as PlatformBackend;
+
+#[cfg(not(any(windows, target_os = "linux")))]
+pub use NoopBackend as PlatformBackend;
+
+#[cfg(windows)]
+mod windows {
+    use super::InhibitBackend;
+
+    const ES_SYSTEM_REQUIRED: u32 = 0x0000_0001;
+    const ES_DISPLAY_REQUIRED: u32 = 0x0000_0002;
+    const ES_CONTINUOUS: u32 = 0x8000_0000;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn SetThreadExecutionState(flags: u32) -> u32;
+    }
+
+    /// Keeps the display on through SetThreadExecutionState. The state belongs
+    /// to the calling thread, so taking and releasing both happen on the
+    /// render thread.
+    #[derive(Default)]
+    pub struct ExecutionStateBackend;
+
+    impl InhibitBackend for ExecutionStateBackend {
+        fn name(&self) -> &'static str {
+            "SetThreadExecutionState"
+        }
+
+        fn inhibit(&mut self, _reason: &str) -> Result<u32, String> {
+            let flags = ES_CONTINUOUS | ES_DISPLAY_REQUIRED | ES_SYSTEM_REQUIRED;
+            match unsafe { SetThreadExecutionState(flags) } {
+                0 => Err("SetThreadExecutionState failed".to_string()),
+                _ => Ok(0),
+            }
+        }
+
+        fn release(&mut self, _cookie: u32) -> Result<(), String> {
+            match unsafe { SetThreadExecutionState(ES_CONTINUOUS) } {
+                0 => Err("SetThreadExecutionState failed".to_string()),
+                _ => Ok(()),
+            }
+        }
+    }
+}
+
+/// Just enough of the D-Bus wire protocol to call methods on the session bus
+#[cfg(target_os = "linux")]
+mod session_bus {
+    use super::{InhibitBackend, APP_NAME};
+    use std::io::{self, Read, Write};
+    use std::os::unix::fs::MetadataExt;
+    use std::os::unix::net::UnixStream;
+    use std::time::Duration;
+
+    /// How long a call may take before the bus is given up on
+    const CALL_TIMEOUT: Duration = Duration::from_secs(2);
+
+    const METHOD_RETURN: u8 = 2;
+    const ERROR: u8 = 3;
+
+    const FIELD_PATH: u8 = 1;
+    const FIELD_INTERFACE: u8 = 2;
+    const FIELD_MEMBER: u8 = 3;
+    const FIELD_ERROR_NAME: u8 = 4;
+    const FIELD_REPLY_SERIAL: u8 = 5;
+    const FIELD_DESTINATION: u8 = 6;
+    const FIELD_SIGNATURE: u8 = 8;
+
+    /// A method call to make; the body is already marshalled
+    pub struct Call<'a> {
+        pub destination: &'a str,
+        pub path: &'a str,
+        pub interface: &'a str,
+        pub member: &'a str,
+        pub signature: &'a str,
+        pub body: Vec<u8>,
+    }
+
+    /// Little-endian marshalling, aligned from the start of the message
+    #[derive(Default)]
+    pub struct Writer {
+        pub buf: Vec<u8>,
+    }
+
+    impl Writer {
+        fn pad(&mut self, align: usize) {
+            self.buf.resize(self.buf.len().next_multiple_of(align), 0);
+        }
+
+        pub fn u32(&mut self, value: u32) {
+            self.pad(4);
+            self.buf.extend(value.to_le_bytes());
+        }
+
+        pub fn string(&mut self, text: &str) {
+            self.u32(text.len() as u32);
+            self.buf.extend(text.as_bytes());
+            self.buf.push(0);
+        }
+
+        fn signature(&mut self, text: &str) {
+            self.buf.push(text.len() as u8);
+            self.buf.extend(text.as_bytes());
+            self.buf.push(0);
+        }
+
+        fn field(&mut self, code: u8, kind: &str, value: &str) {
+            self.pad(8);
+            self.buf.push(code);
+            self.signature(kind);
+            if kind == "g" {
+                self.signature(value);
+            } else {
+                self.string(value);
+            }
+        }
+    }
+
+    pub fn encode_call(serial: u32, call: &Call) -> Vec<u8> {
+        let mut message = Writer::default();
+        message.buf.extend([b'l', 1, 0, 1]);
+        message.u32(call.body.len() as u32);
+        message.u32(serial);
+        let fields_len_at = message.buf.len();
+        message.u32(0);
+        let fields_start = message.buf.len();
+        message.field(FIELD_PATH, "o", call.path);
+        message.field(FIELD_INTERFACE, "s", call.interface);
+        message.field(FIELD_MEMBER, "s", call.member);
+        message.field(FIELD_DESTINATION, "s", call.destination);
+        if !call.signature.is_empty() {
+            message.field(FIELD_SIGNATURE, "g", call.signature);
+        }
+        let fields_len = (message.buf.len() - fields_start) as u32;
+        message.buf[fields_len_at..fields_start].copy_from_slice(&fields_len.to_le_bytes());
+        message.pad(8);
+        message.buf.extend(&call.body);
+        message.buf
+    }
+
+    /// A message read back from the bus
+    #[derive(Debug, Default, PartialEq)]
+    pub struct Message {
+        pub kind: u8,
+        pub big_endian: bool,
+        pub reply_serial: Option<u32>,
+        pub error_name: Option<String>,
+        pub body: Vec<u8>,
+    }
+
+    impl Message {
+        pub fn u32_at(&self, at: usize) -> Option<u32> {
+            let bytes = self.body.get(at..at + 4)?.try_into().ok()?;
+            Some(if self.big_endian {
+                u32::from_be_bytes(bytes)
+            } else {
+                u32::from_le_bytes(bytes)
+            })
+        }
+    }
+
+    pub fn read_message(stream: &mut impl Read) -> io::Result<Message> {
+        let invalid = |what: &str| io::Error::new(io::ErrorKind::InvalidData, what.to_string());
+        let mut header = vec![0u8; 16];
+        stream.read_exact(&mut header)?;
+        let big_endian = match header[0] {
+            b'l' => false,
+            b'B' => true,
+            _ => return Err(invalid("unknown byte order")),
+        };
+        let u32_at = |bytes: &[u8], at: usize| -> Option<u32> {
+            let bytes = bytes.get(at..at + 4)?.try_into().ok()?;
+            Some(if big_endian {
+                u32::from_be_bytes(bytes)
+            } else {
+                u32::from_le_bytes(bytes)
+            })
+        };
+        let body_len = u32_at(&header, 4).unwrap_or(0) as usize;
+        let fields_end = 16 + u32_at(&header, 12).unwrap_or(0) as usize;
+        header.resize(fields_end.next_multiple_of(8), 0);
+        stream.read_exact(&mut header[16..])?;
+        let mut message = Message {
+            kind: header[1],
+            big_endian,
+            body: vec![0u8; body_len],
+            ..Message::default()
+        };
+        stream.read_exact(&mut message.body)?;
+
+        let mut at = 16;
+        while at < fields_end {
+            let code = header[at];
+            let kind_len = header[at + 1] as usize;
+            let kind = header
+                .get(at + 2..at + 2 + kind_len)
+                .ok_or(invalid("truncated field"))?;
+            at += 3 + kind_len;
+            match kind {
+                b"u" => {
+                    at = at.next_multiple_of(4);
+                    let value = u32_at(&header, at).ok_or(invalid("truncated field"))?;
+                    if code == FIELD_REPLY_SERIAL {
+                        message.reply_serial = Some(value);
+                    }
+                    at += 4;
+                }
+                b"s" | b"o" => {
+                    at = at.next_multiple_of(4);
+                    let len = u32_at(&header, at).ok_or(invalid("truncated field"))? as usize;
+                    let text = header
+                        .get(at + 4..at + 4 + len)
+                        .ok_or(invalid("truncated field"))?;
+                    if code == FIELD_ERROR_NAME {
+                        message.error_name = Some(String::from_utf8_lossy(text).into_owned());
+                    }
+                    at += 5 + len;
+                }
+                b"g" => at += header.get(at).map_or(0, |&len| len as usize) + 2,
+                _ => return Err(invalid("unexpected header field type")),
+            }
+            at = at.next_multiple_of(8);
+        }
+        Ok(message)
+    }
+
+    /// The session bus socket named by `address`, e.g. `unix:path=/run/user/1000/bus`
+    fn connect_to(address: &str) -> io::Result<UnixStream> {
+        for entry in address.split(';') {
+            let Some(params) = entry.strip_prefix("unix:") else {
+                continue;
+            };
+            for param in params.split(',') {
+                if let Some(path) = param.strip_prefix("path=") {
+                    return UnixStream::connect(unescape(path));
+                }
+                if let Some(name) = param.strip_prefix("abstract=") {
+                    use std::os::linux::net::SocketAddrExt;
+                    let addr = std::os::unix::net::SocketAddr::from_abstract_name(unescape(name))?;
+                    return UnixStream::connect_addr(&addr);
+                }
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no unix socket in bus address `{}`", address),
+        ))
+    }
+
+    /// Undoes the %XX escapes allowed in bus addresses
+    fn unescape(text: &str) -> String {
+        let mut bytes = Vec::new();
+        let mut rest = text.as_bytes();
+        while let Some((&byte, tail)) = rest.split_first() {
+            let escaped = tail
+                .get(..2)
+                .filter(|_| byte == b'%')
+                .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+            match escaped {
+                Some(decoded) => {
+                    bytes.push(decoded);
+                    rest = &tail[2..];
+                }
+                None => {
+                    bytes.push(byte);
+                    rest = tail;
+                }
+            }
+        }
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+
+    /// An authenticated session bus connection
+    pub struct Connection {
+        stream: UnixStream,
+        serial: u32,
+    }
+
+    impl Connection {
+        pub fn open() -> io::Result<Self> {
+            let uid = std::fs::metadata("/proc/self")?.uid();
+            let address = std::env::var("DBUS_SESSION_BUS_ADDRESS")
+                .unwrap_or_else(|_| format!("unix:path=/run/user/{}/bus", uid));
+            let mut stream = connect_to(&address)?;
+            stream.set_read_timeout(Some(CALL_TIMEOUT))?;
+            stream.set_write_timeout(Some(CALL_TIMEOUT))?;
+
+            let uid_hex: String = uid
+                .to_string()
+                .bytes()
+                .map(|b| format!("{:02x}", b))
+                .collect();
+            stream.write_all(format!("\0AUTH EXTERNAL {}\r\n", uid_hex).as_bytes())?;
+            let mut reply = Vec::new();
+            let mut byte = [0u8];
+            while !reply.ends_with(b"\r\n") {
+                stream.read_exact(&mut byte)?;
+                reply.push(byte[0]);
+            }
+            if !reply.starts_with(b"OK ") {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    String::from_utf8_lossy(&reply).trim().to_string(),
+                ));
+            }
+            stream.write_all(b"BEGIN\r\n")?;
+
+            let mut connection = Self { stream, serial: 0 };
+            connection.call(Call {
+                destination: "org.freedesktop.DBus",
+                path: "/org/freedesktop/DBus",
+                interface: "org.freedesktop.DBus",
+                member: "Hello",
+                signature: "",
+                body: Vec::new(),
+            })?;
+            Ok(connection)
+        }
+
+        /// Makes `call` and waits for its reply, skipping signals on the way
+        pub fn call(&mut self, call: Call) -> io::Result<Message> {
+            self.serial += 1;
+            self.stream.write_all(&encode_call(self.serial, &call))?;
+            loop {
+                let message = read_message(&mut self.stream)?;
+                if message.reply_serial != Some(self.serial) {
+                    continue;
+                }
+                return match message.kind {
+                    METHOD_RETURN => Ok(message),
+                    ERROR => Err(io::Error::other(
+                        message
+                            .error_name
+                            .unwrap_or_else(|| "D-Bus error".to_string()),
+                    )),
+                    _ => continue,
+                };
+            }
+        }
+    }
+
+    fn screensaver_call(
+        member: &'static str,
+        signature: &'static str,
+        body: Writer,
+    ) -> Call<'static> {
+        Call {
+            destination: "org.freedesktop.ScreenSaver",
+            path: "/org/freedesktop/ScreenSaver",
+            interface: "org.freedesktop.ScreenSaver",
+            member,
+            signature,
+            body: body.buf,
+        }
+    }
+
+    /// Inhibits through org.freedesktop.ScreenSaver. The bus ties the inhibit
+    /// to the connection, so it stays open while one is held.
+    #[derive(Default)]
+    pub struct DbusBackend {
+        connection: Option<Connection>,
+    }
+
+    impl InhibitBackend for DbusBackend {
+        fn name(&self) -> &'static str {
+            "org.freedesktop.ScreenSaver"
+        }
+
+        fn inhibit(&mut self, reason: &str) -> Result<u32, String> {
+            if self.connection.is_none() {
+                self.connection = Some(Connection::open().map_err(|e| e.to_string())?);
+            }
+            let connection = self.connection.as_mut().expect("opened above");
+            let mut body = Writer::default();
+            body.string(APP_NAME);
+            body.string(reason);
+            let reply = connection.call(screensaver_call("Inhibit", "ss", body));
+            match reply.map(|reply| reply.u32_at(0)) {
+                Ok(Some(cookie)) => Ok(cookie),
+                Ok(None) => Err("Inhibit replied without a cookie".to_string()),
+                Err(e) => {
+                    self.connection = None;
+                    Err(e.to_string())
+                }
+            }
+        }
+
+        fn release(&mut self, cookie: u32) -> Result<(), String> {
+            let Some(connection) = self.connection.as_mut() else {
+                return Ok(());
+            };
+            let mut body = Writer::default();
+            body.u32(cookie);
+            let reply = connection.call(screensaver_call("UnInhibit", "u", body));
+            reply.map(drop).map_err(|e| {
+                // Closing the connection drops the inhibit as well
+                self.connection = None;
+                e.to_string()
+            })
+        }
+    }
+}
+
+static KEEP_AWAKE: AtomicBool = AtomicBool::new(false);
+static INHIBITOR: Mutex<Option<Inhibitor<PlatformBackend>>> = Mutex::new(None);
+
+fn inhibitor() -> MutexGuard<'static, Option<Inhibitor<PlatformBackend>>> {
+    INHIBITOR.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Hold off the screensaver even when nothing is playing
+pub fn set_keep_awake(enabled: bool) {
+    KEEP_AWAKE.store(enabled, Ordering::Relaxed);
+}
+
+pub fn keep_awake() -> bool {
+    KEEP_AWAKE.load(Ordering::Relaxed)
+}
+
+/// Takes or drops the inhibit for the current state; called once a frame
+pub fn update(paused: bool) {
+    let playing = crate::audio::audio_playback::is_playing();
+    let reason = inhibit_reason(playing, keep_awake(), paused);
+    let mut slot = inhibitor();
+    if reason.is_none() && slot.is_none() {
+        return;
+    }
+    slot.get_or_insert_with(|| Inhibitor::new(PlatformBackend::default()))
+        .update(reason);
+}
+
+/// Drops any inhibit; called on quit
+pub fn release() {
+    if let Some(inhibitor) = inhibitor().as_mut() {
+        inhibitor.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Records every call, refusing inhibits while `refuse` is set
+    #[derive(Default)]
+    struct MockBackend {
+        calls: Vec<String>,
+        refuse: bool,
+        next_cookie: u32,
+    }
+
+    impl InhibitBackend for MockBackend {
+        fn name(&self) -> &'static str {
+            "mock"
+        }
+
+        fn inhibit(&mut self, reason: &str) -> Result<u32, String> {
+            self.calls.push(format!("inhibit: {}", reason));
+            if self.refuse {
+                return Err("refused".to_string());
+            }
+            self.next_cookie += 1;
+            Ok(self.next_cookie)
+        }
+
+        fn release(&mut self, cookie: u32) -> Result<(), String> {
+            self.calls.push(format!("release {}", cookie));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_inhibit_follows_playback_pause_and_quit_without_leaks() {
+        let mut inhibitor = Inhibitor::new(MockBackend::default());
+        // (audio playing, keep awake, paused) frame by frame
+        let frames = [
+            (false, false, false),
+            (true, false, false),
+            (true, false, false),
+            (true, true, false),
+            (true, true, true),
+            (true, false, false),
+            (false, false, false),
+            (false, true, false),
+        ];
+        for (playing, keep_awake, paused) in frames {
+            inhibitor.update(inhibit_reason(playing, keep_awake, paused));
+        }
+        assert_eq!(inhibitor.state(), InhibitState::Held(3));
+        inhibitor.release();
+        inhibitor.release();
+        assert_eq!(inhibitor.state(), InhibitState::Released);
+        assert_eq!(
+            inhibitor.backend().calls,
+            [
+                "inhibit: Audio is playing",
+                "release 1",
+                "inhibit: Audio is playing",
+                "release 2",
+                "inhibit: Keep awake is on",
+                "release 3",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_refused_inhibit_waits_until_wanted_again() {
+        let mut inhibitor = Inhibitor::new(MockBackend {
+            refuse: true,
+            ..MockBackend::default()
+        });
+        for _ in 0..3 {
+            inhibitor.update(Some("Audio is playing"));
+        }
+        assert_eq!(inhibitor.state(), InhibitState::Unavailable);
+        assert_eq!(inhibitor.backend().calls.len(), 1);
+
+        // Nothing was held, so nothing is released; the next play tries again
+        inhibitor.update(None);
+        inhibitor.backend.refuse = false;
+        inhibitor.update(Some("Audio is playing"));
+        assert_eq!(inhibitor.state(), InhibitState::Held(1));
+        drop(inhibitor);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_method_calls_read_back_through_the_wire_format() {
+        use session_bus::*;
+
+        let mut body = Writer::default();
+        body.string(APP_NAME);
+        body.string("Audio is playing");
+        let call = Call {
+            destination: "org.freedesktop.ScreenSaver",
+            path: "/org/freedesktop/ScreenSaver",
+            interface: "org.freedesktop.ScreenSaver",
+            member: "Inhibit",
+            signature: "ss",
+            body: body.buf.clone(),
+        };
+        let bytes = encode_call(7, &call);
+        assert_eq!(bytes.len() % 8, body.buf.len() % 8);
+        let message = read_message(&mut bytes.as_slice()).unwrap();
+        assert_eq!(message.kind, 1);
+        assert_eq!(message.body, body.buf);
+        assert_eq!(message.u32_at(0), Some(APP_NAME.len() as u32));
+
+        // A big-endian reply carrying a cookie
+        let reply = [
+            b'B', 2, 0, 1, 0, 0, 0, 4, 0, 0, 0, 9, 0, 0, 0, 15, // fixed header
+            5, 1, b'u', 0, 0, 0, 0, 7, // REPLY_SERIAL = 7
+            8, 1, b'g', 0, 1, b'u', 0, 0, // SIGNATURE = "u"
+            0, 0, 0, 42,
+        ];
+        let message = read_message(&mut reply.as_slice()).unwrap();
+        assert_eq!(message.reply_serial, Some(7));
+        assert_eq!(message.u32_at(0), Some(42));
+    }
+}
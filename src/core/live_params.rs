@@ -0,0 +1,423 @@
+//! `live_params.toml` in the config directory, re-read whenever it changes so
+//! the visuals can be tuned from a text editor while running. Keys are the
+//! ones `settings.conf` uses and are checked the same way; whatever the file
+//! leaves out keeps its current value. A file that doesn't parse changes
+//! nothing, and changes are never written back to the settings file.
+//!
+//! Only a small part of TOML is understood: `key = value` lines with numbers,
+//! booleans, and quoted strings, comments, and `[table]` headers. Keys inside
+//! a table are reported as unknown since no setting lives in one.
+//!
+//! Part of the `live-params` feature. The file is polled, using only std.
+
+use crate::core::settings::{Correction, Settings};
+use crate::ui::toast;
+use log::{info, warn};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+pub const FILE_NAME: &str = "live_params.toml";
+/// How often the file's modification time is checked
+pub const POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Quiet time after the last change before the file is read, so an editor
+/// saving in several writes is applied once
+pub const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// A line of the file that could not be read; the whole file is ignored
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// `key = value` pairs in file order, with table names joined onto their keys
+/// by dots and strings unquoted
+pub fn parse_toml(text: &str) -> Result<Vec<(String, String)>, ParseError> {
+    let mut table = String::new();
+    let mut pairs: Vec<(String, String)> = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let error = |message: &str| ParseError {
+            line: number + 1,
+            message: message.to_string(),
+        };
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('[') {
+            let name = header
+                .strip_suffix(']')
+                .ok_or_else(|| error("unclosed `[`"))?;
+            if !is_bare_key(name.trim()) {
+                return Err(error("expected a table name"));
+            }
+            table = name.trim().to_string();
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| error("expected `key = value`"))?;
+        let key = key.trim();
+        if !is_bare_key(key) {
+            return Err(error("expected a key before `=`"));
+        }
+        let key = if table.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", table, key)
+        };
+        if pairs.iter().any(|(seen, _)| *seen == key) {
+            return Err(error(&format!("`{}` is set twice", key)));
+        }
+        pairs.push((key, parse_value(value.trim()).map_err(error)?));
+    }
+    Ok(pairs)
+}
+
+/// `line` without a trailing `#` comment, leaving any `#` inside quotes
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn is_bare_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.')
+}
+
+/// A number, boolean, or quoted string, as the text a setting is parsed from
+fn parse_value(value: &str) -> Result<String, &'static str> {
+    if let Some(rest) = value.strip_prefix('"') {
+        let inner = rest.strip_suffix('"').ok_or("unterminated string")?;
+        if inner.contains('"') || inner.contains('\\') {
+            return Err("escapes in strings are not supported");
+        }
+        return Ok(inner.to_string());
+    }
+    if value == "true" || value == "false" || value.replace('_', "").parse::<f64>().is_ok() {
+        return Ok(value.replace('_', ""));
+    }
+    if value.is_empty() {
+        Err("missing value")
+    } else {
+        Err("expected a number, true, false, or a quoted string")
+    }
+}
+
+/// What applying the file changed
+#[derive(Debug, Clone, PartialEq)]
+pub struct LiveUpdate {
+    pub settings: Settings,
+    /// `key = value` for every setting whose value changed
+    pub changed: Vec<String>,
+    pub corrections: Vec<Correction>,
+    /// Keys in the file that are not settings
+    pub unknown: Vec<String>,
+}
+
+/// `current` with the file's values laid over it, checked like the settings file
+pub fn apply_text(current: &Settings, text: &str) -> Result<LiveUpdate, ParseError> {
+    let pairs = parse_toml(text)?;
+    let overrides: String = pairs
+        .iter()
+        .map(|(key, value)| format!("{} = {}\n", key, value))
+        .collect();
+    // Later lines win, so the file's values replace the current ones
+    let parsed = Settings::parse_checked(&(current.to_text() + &overrides));
+    let before = current.to_text();
+    let changed = parsed
+        .settings
+        .to_text()
+        .lines()
+        .filter(|line| !line.starts_with('#') && !before.lines().any(|old| old == *line))
+        .map(str::to_string)
+        .collect();
+    let unknown = parsed
+        .unknown
+        .iter()
+        .filter_map(|line| line.split_once('=').map(|(key, _)| key.trim().to_string()))
+        .collect();
+    Ok(LiveUpdate {
+        settings: parsed.settings,
+        changed,
+        corrections: parsed.corrections,
+        unknown,
+    })
+}
+
+/// Holds a burst of changes back until things have been quiet for `DEBOUNCE`
+#[derive(Debug, Default)]
+pub struct Debouncer {
+    last_change: Option<Instant>,
+}
+
+impl Debouncer {
+    pub fn change_seen(&mut self, now: Instant) {
+        self.last_change = Some(now);
+    }
+
+    /// True once, when the last change is `DEBOUNCE` old
+    pub fn ready(&mut self, now: Instant) -> bool {
+        match self.last_change {
+            Some(at) if now.duration_since(at) >= DEBOUNCE => {
+                self.last_change = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Where the file's changes and contents come from, so tests can script them
+pub trait ChangeSource {
+    /// Whether the file changed since the last call
+    fn changed(&mut self, now: Instant) -> bool;
+    fn read(&mut self) -> io::Result<String>;
+}
+
+/// A file on disk, checked by modification time and size every `POLL_INTERVAL`
+pub struct PolledFile {
+    path: PathBuf,
+    stamp: Option<(SystemTime, u64)>,
+    last_poll: Option<Instant>,
+}
+
+impl PolledFile {
+    pub fn new(path: PathBuf) -> Self {
+        let stamp = file_stamp(&path);
+        Self {
+            path,
+            stamp,
+            last_poll: None,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+fn file_stamp(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+impl ChangeSource for PolledFile {
+    fn changed(&mut self, now: Instant) -> bool {
+        if self
+            .last_poll
+            .is_some_and(|last| now.duration_since(last) < POLL_INTERVAL)
+        {
+            return false;
+        }
+        self.last_poll = Some(now);
+        let stamp = file_stamp(&self.path);
+        // Deleting the file keeps the values it set
+        let changed = stamp.is_some() && stamp != self.stamp;
+        self.stamp = stamp;
+        changed
+    }
+
+    fn read(&mut self) -> io::Result<String> {
+        fs::read_to_string(&self.path)
+    }
+}
+
+/// Applies the file each time it settles after a change
+pub struct LiveParams<S: ChangeSource> {
+    source: S,
+    debounce: Debouncer,
+}
+
+impl<S: ChangeSource> LiveParams<S> {
+    /// Watches `source`. Its current contents are applied on the first `update`.
+    pub fn new(source: S, now: Instant) -> Self {
+        let mut debounce = Debouncer::default();
+        debounce.change_seen(now.checked_sub(DEBOUNCE).unwrap_or(now));
+        Self { source, debounce }
+    }
+
+    /// Reads and checks the file if it has settled since changing. None when
+    /// there is nothing new; the caller applies and reports the result.
+    pub fn poll(&mut self, current: &Settings, now: Instant) -> Option<Result<LiveUpdate, String>> {
+        if self.source.changed(now) {
+            self.debounce.change_seen(now);
+        }
+        if !self.debounce.ready(now) {
+            return None;
+        }
+        match self.source.read() {
+            Ok(text) => Some(apply_text(current, &text).map_err(|e| e.to_string())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => Some(Err(e.to_string())),
+        }
+    }
+
+    /// `poll` against the settings in effect, applying the result and showing
+    /// what changed, or why nothing did, in a toast
+    pub fn update(&mut self, now: Instant) {
+        match self.poll(&Settings::current(), now) {
+            None => {}
+            Some(Ok(update)) => {
+                update.settings.apply();
+                let lines = toast_lines(&update);
+                for line in &lines {
+                    info!("{}", line);
+                }
+                toast::show_toast(lines);
+            }
+            Some(Err(e)) => {
+                warn!("{}: {}; keeping the previous values", FILE_NAME, e);
+                toast::show_toast(vec![
+                    format!("{} not applied:", FILE_NAME),
+                    e,
+                    "Keeping the previous values".to_string(),
+                ]);
+            }
+        }
+    }
+}
+
+fn toast_lines(update: &LiveUpdate) -> Vec<String> {
+    let mut lines = vec![if update.changed.is_empty() {
+        format!("{}: nothing changed", FILE_NAME)
+    } else {
+        format!("{} applied:", FILE_NAME)
+    }];
+    lines.extend(update.changed.iter().cloned());
+    for correction in &update.corrections {
+        lines.push(format!(
+            "{}: {} is out of range, using {}",
+            correction.key, correction.value, correction.corrected
+        ));
+    }
+    for key in &update.unknown {
+        lines.push(format!("{}: not a setting", key));
+    }
+    lines
+}
+
+/// `<config dir>/stimstation/live_params.toml`, watched whether or not it exists yet
+pub fn watch_config_dir() -> Option<LiveParams<PolledFile>> {
+    let path = dirs::config_dir()?.join("stimstation").join(FILE_NAME);
+    info!("Watching {} for live parameter changes", path.display());
+    Some(LiveParams::new(PolledFile::new(path), Instant::now()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_apply_and_diff() {
+        let current = Settings::DEFAULT;
+        let text = "# tuning\n\
+                    hue_shift_degrees = 90 # a quarter turn\n\
+                    color_filter = \"hue_shift\"\n\
+                    max_ball_radius_percent = 80\n\
+                    bar_release_ms = 400\n\
+                    \n\
+                    [circular]\n\
+                    rotation_speed = 1.5\n";
+        let update = apply_text(&current, text).unwrap();
+        assert_eq!(update.settings.post.hue_shift, 90.0);
+        assert_eq!(update.settings.max_ball_radius, 0.5);
+        assert_eq!(
+            update.changed,
+            [
+                "bar_release_ms = 400",
+                "color_filter = hue_shift",
+                "hue_shift_degrees = 90",
+                "max_ball_radius_percent = 50",
+            ]
+        );
+        assert_eq!(update.corrections.len(), 1);
+        assert_eq!(update.unknown, ["circular.rotation_speed"]);
+        // Keys the file leaves out keep their current values
+        assert_eq!(
+            update.settings.bar_envelope.attack,
+            current.bar_envelope.attack
+        );
+
+        // Applying the same file again changes nothing
+        let again = apply_text(&update.settings, text).unwrap();
+        assert!(again.changed.is_empty());
+
+        for (bad, line) in [
+            ("hue_shift_degrees = 90\ncolor_filter = \"hue\n", 2),
+            ("[post\n", 1),
+            ("hue_shift_degrees 90\n", 1),
+            ("hue_shift_degrees = 1\nhue_shift_degrees = 2\n", 2),
+            ("hue_shift_degrees = [1, 2]\n", 1),
+        ] {
+            assert_eq!(apply_text(&current, bad).unwrap_err().line, line, "{}", bad);
+        }
+    }
+
+    struct ScriptedFile {
+        text: String,
+        changes: Vec<Instant>,
+        reads: usize,
+    }
+
+    impl ChangeSource for ScriptedFile {
+        fn changed(&mut self, now: Instant) -> bool {
+            let before = self.changes.len();
+            self.changes.retain(|&at| at > now);
+            self.changes.len() != before
+        }
+
+        fn read(&mut self) -> io::Result<String> {
+            self.reads += 1;
+            Ok(self.text.clone())
+        }
+    }
+
+    #[test]
+    fn test_bursts_of_writes_apply_once_after_settling() {
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        let file = ScriptedFile {
+            text: "hue_shift_degrees = 45\n".to_string(),
+            // An editor saving in three writes, then one more save later
+            changes: vec![at(200), at(210), at(230), at(600)],
+            reads: 0,
+        };
+        let mut live = LiveParams::new(file, start);
+        let current = Settings::DEFAULT;
+
+        // The file as it is at startup applies straight away
+        assert!(live.poll(&current, start).is_some());
+        let mut applied = Vec::new();
+        for ms in (0..=900).step_by(10) {
+            if let Some(result) = live.poll(&current, at(ms)) {
+                assert_eq!(result.unwrap().settings.post.hue_shift, 45.0);
+                applied.push(ms);
+            }
+        }
+        // Each burst lands once, DEBOUNCE after its last write and well inside 200ms
+        assert_eq!(applied, [330, 700]);
+        assert_eq!(live.source.reads, 3);
+
+        let mut debounce = Debouncer::default();
+        assert!(!debounce.ready(at(1000)));
+    }
+}
@@ -0,0 +1,324 @@
+use log::{LevelFilter, Log, Metadata, Record};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Identical messages logged within this window are coalesced into one summary line
+pub const COALESCE_WINDOW: Duration = Duration::from_secs(1);
+
+struct Entry {
+    window_start: Instant,
+    suppressed: u32,
+}
+
+/// Coalesces repeated messages. The first occurrence passes through immediately;
+/// repeats inside the window are held back and later summarised as `message (xN)`,
+/// where N is the number of repeats that were held back.
+pub struct RateLimiter {
+    window: Duration,
+    entries: HashMap<String, Entry>,
+}
+
+impl RateLimiter {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Returns true if the message should be printed now.
+    pub fn submit(&mut self, message: &str, now: Instant) -> bool {
+        if let Some(entry) = self.entries.get_mut(message) {
+            if now.duration_since(entry.window_start) < self.window {
+                entry.suppressed += 1;
+                return false;
+            }
+        }
+        self.entries.insert(
+            message.to_string(),
+            Entry {
+                window_start: now,
+                suppressed: 0,
+            },
+        );
+        true
+    }
+
+    /// Removes windows that have ended and returns summaries for those that held back repeats.
+    pub fn drain_expired(&mut self, now: Instant) -> Vec<String> {
+        let window = self.window;
+        let mut summaries = Vec::new();
+        self.entries.retain(|message, entry| {
+            if now.duration_since(entry.window_start) < window {
+                return true;
+            }
+            if entry.suppressed > 0 {
+                summaries.push(format!("{} (x{})", message, entry.suppressed));
+            }
+            false
+        });
+        summaries
+    }
+
+    /// Ends every window immediately, returning summaries for held-back repeats.
+    pub fn drain_all(&mut self) -> Vec<String> {
+        let mut summaries: Vec<String> = self
+            .entries
+            .drain()
+            .filter(|(_, entry)| entry.suppressed > 0)
+            .map(|(message, entry)| format!("{} (x{})", message, entry.suppressed))
+            .collect();
+        summaries.sort();
+        summaries
+    }
+}
+
+/// Log levels from RUST_LOG: a comma-separated list of `level` and
+/// `target=level` directives, as in `info,stimstation::audio=debug`. A target
+/// covers its submodules, and the longest matching target wins.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogFilter {
+    default: LevelFilter,
+    targets: Vec<(String, LevelFilter)>,
+}
+
+impl LogFilter {
+    /// Parses `spec`, returning the filter and the directives it couldn't read,
+    /// which are left out. Without a bare level the default is info.
+    pub fn parse(spec: &str) -> (Self, Vec<String>) {
+        let mut filter = Self {
+            default: LevelFilter::Info,
+            targets: Vec::new(),
+        };
+        let mut ignored = Vec::new();
+        for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            match directive.split_once('=') {
+                None => match directive.parse() {
+                    Ok(level) => filter.default = level,
+                    Err(_) => ignored.push(directive.to_string()),
+                },
+                Some((target, level)) => match level.trim().parse() {
+                    Ok(level) if !target.trim().is_empty() => {
+                        filter.targets.push((target.trim().to_string(), level))
+                    }
+                    _ => ignored.push(directive.to_string()),
+                },
+            }
+        }
+        (filter, ignored)
+    }
+
+    /// Most detailed level any target is logged at
+    pub fn max_level(&self) -> LevelFilter {
+        self.targets
+            .iter()
+            .map(|&(_, level)| level)
+            .fold(self.default, Ord::max)
+    }
+
+    /// Level messages from `target` are logged at
+    pub fn level_for(&self, target: &str) -> LevelFilter {
+        self.targets
+            .iter()
+            .filter(|(prefix, _)| {
+                target == prefix
+                    || target
+                        .strip_prefix(prefix.as_str())
+                        .is_some_and(|rest| rest.starts_with("::"))
+            })
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(self.default, |&(_, level)| level)
+    }
+}
+
+/// Minimal stderr logger with per-message rate limiting.
+/// The levels come from RUST_LOG (see `LogFilter`) and default to info.
+struct StimLogger {
+    filter: LogFilter,
+    start: Instant,
+    limiter: Mutex<RateLimiter>,
+}
+
+impl StimLogger {
+    fn stamp(&self, now: Instant) -> f32 {
+        now.duration_since(self.start).as_secs_f32()
+    }
+
+    /// Prints summaries for windows that have closed by `now`
+    fn drain_expired(&self, now: Instant) {
+        if let Ok(mut limiter) = self.limiter.lock() {
+            for summary in limiter.drain_expired(now) {
+                eprintln!("[{:>9.3}s {}", self.stamp(now), summary);
+            }
+        }
+    }
+}
+
+impl Log for StimLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.filter.level_for(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = format!(
+            "{:<5} {}] {}",
+            record.level(),
+            record.target(),
+            record.args()
+        );
+        let now = Instant::now();
+        self.drain_expired(now);
+        if let Ok(mut limiter) = self.limiter.lock() {
+            if limiter.submit(&line, now) {
+                eprintln!("[{:>9.3}s {}", self.stamp(now), line);
+            }
+        }
+    }
+
+    fn flush(&self) {
+        let now = Instant::now();
+        if let Ok(mut limiter) = self.limiter.lock() {
+            for summary in limiter.drain_all() {
+                eprintln!("[{:>9.3}s {}", self.stamp(now), summary);
+            }
+        }
+    }
+}
+
+/// How often the summaries of closed windows are checked for, so they show up
+/// soon after a burst even when nothing else is logged
+const DRAIN_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Installs the logger. Safe to call more than once; later calls are ignored.
+pub fn init() {
+    let (filter, ignored) = LogFilter::parse(&std::env::var("RUST_LOG").unwrap_or_default());
+    let level = filter.max_level();
+    let logger: &'static StimLogger = Box::leak(Box::new(StimLogger {
+        filter,
+        start: Instant::now(),
+        limiter: Mutex::new(RateLimiter::new(COALESCE_WINDOW)),
+    }));
+    if log::set_logger(logger).is_err() {
+        return;
+    }
+    log::set_max_level(level);
+    for directive in ignored {
+        log::warn!("Ignoring RUST_LOG directive `{}`", directive);
+    }
+    let drainer = thread::Builder::new()
+        .name("log-drain".into())
+        .spawn(move || loop {
+            thread::sleep(DRAIN_INTERVAL);
+            logger.drain_expired(Instant::now());
+        });
+    if let Err(e) = drainer {
+        log::warn!("Repeated log summaries wait for the next message: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_first_message_passes_repeats_are_held() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new(COALESCE_WINDOW);
+
+        assert!(limiter.submit("render error", start));
+        assert!(!limiter.submit("render error", start + Duration::from_millis(16)));
+        assert!(!limiter.submit("render error", start + Duration::from_millis(32)));
+        assert!(limiter.submit("other message", start + Duration::from_millis(40)));
+    }
+
+    #[test]
+    fn test_expired_window_reports_count() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new(COALESCE_WINDOW);
+        for i in 0..60 {
+            limiter.submit("render error", start + Duration::from_millis(i * 16));
+        }
+
+        assert!(limiter
+            .drain_expired(start + Duration::from_millis(500))
+            .is_empty());
+        let summaries = limiter.drain_expired(start + Duration::from_millis(1001));
+        assert_eq!(summaries, vec!["render error (x59)".to_string()]);
+        // A new window starts fresh after the summary
+        assert!(limiter.submit("render error", start + Duration::from_millis(1002)));
+    }
+
+    #[test]
+    fn test_single_message_has_no_summary() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new(COALESCE_WINDOW);
+        limiter.submit("hello", start);
+
+        assert!(limiter.drain_expired(start + COALESCE_WINDOW).is_empty());
+        assert!(limiter.drain_all().is_empty());
+    }
+
+    #[test]
+    fn test_drain_all_flushes_pending() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new(COALESCE_WINDOW);
+        limiter.submit("a", start);
+        limiter.submit("a", start);
+        limiter.submit("b", start);
+        limiter.submit("b", start);
+        limiter.submit("b", start);
+
+        assert_eq!(
+            limiter.drain_all(),
+            vec!["a (x1)".to_string(), "b (x2)".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_filter_reads_target_directives() {
+        let (filter, ignored) = LogFilter::parse("stimstation::audio=debug");
+        assert!(ignored.is_empty());
+        assert_eq!(filter.level_for("stimstation::audio"), LevelFilter::Debug);
+        assert_eq!(
+            filter.level_for("stimstation::audio::audio_playback"),
+            LevelFilter::Debug
+        );
+        // Only whole path segments match, and everything else stays at info
+        assert_eq!(
+            filter.level_for("stimstation::audiobook"),
+            LevelFilter::Info
+        );
+        assert_eq!(filter.level_for("stimstation::core"), LevelFilter::Info);
+        assert_eq!(filter.max_level(), LevelFilter::Debug);
+    }
+
+    #[test]
+    fn test_filter_prefers_the_longest_target() {
+        let (filter, ignored) =
+            LogFilter::parse("warn, stimstation=info,stimstation::audio::features=trace");
+        assert!(ignored.is_empty());
+        assert_eq!(filter.level_for("wgpu_core"), LevelFilter::Warn);
+        assert_eq!(filter.level_for("stimstation::core"), LevelFilter::Info);
+        assert_eq!(
+            filter.level_for("stimstation::audio::features"),
+            LevelFilter::Trace
+        );
+        assert_eq!(filter.max_level(), LevelFilter::Trace);
+    }
+
+    #[test]
+    fn test_filter_reports_what_it_ignored() {
+        let (filter, ignored) = LogFilter::parse("loud,stimstation=verbose,=debug,debug");
+        assert_eq!(ignored, vec!["loud", "stimstation=verbose", "=debug"]);
+        assert_eq!(filter.level_for("stimstation"), LevelFilter::Debug);
+
+        let (filter, ignored) = LogFilter::parse("");
+        assert!(ignored.is_empty());
+        assert_eq!(filter.max_level(), LevelFilter::Info);
+    }
+}
@@ -0,0 +1,236 @@
+//! Writes every frame to a v4l2loopback device, so other programs (OBS, a
+//! browser, a video call) can pick the visualization up as a webcam. Only
+//! built on Linux with the `v4l2-loopback` feature.
+//!
+//! Frames go out as BGRA through `write`, which the v4l2loopback module
+//! accepts without any buffer negotiation. A worker thread does the writing;
+//! frames are dropped rather than queued when it falls behind.
+
+use crate::core::pixel_format::PixelFormat;
+use log::{info, warn};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::fd::AsRawFd;
+use std::os::raw::{c_int, c_ulong};
+use std::os::unix::fs::FileTypeExt;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread;
+
+/// Frames waiting for the writer; anything past this is dropped
+const CHANNEL_CAPACITY: usize = 2;
+
+/// `VIDIOC_S_FMT`: `_IOWR('V', 5, struct v4l2_format)`, with the 208 byte struct of 64-bit Linux
+const VIDIOC_S_FMT: c_ulong = 0xc0d0_5605;
+const V4L2_BUF_TYPE_VIDEO_OUTPUT: u32 = 2;
+const V4L2_FIELD_NONE: u32 = 1;
+const V4L2_COLORSPACE_SRGB: u32 = 8;
+/// `V4L2_PIX_FMT_BGR32`: B, G, R, then a byte OBS and ffmpeg read as alpha or ignore
+const V4L2_PIX_FMT_BGR32: u32 = u32::from_le_bytes(*b"BGR4");
+/// "Inappropriate ioctl for device", from anything that isn't a device
+const ENOTTY: i32 = 25;
+
+extern "C" {
+    fn ioctl(fd: c_int, request: c_ulong, ...) -> c_int;
+}
+
+/// `struct v4l2_format` with the `pix` member of its union filled in
+#[repr(C, align(8))]
+struct V4l2Format {
+    buf_type: u32,
+    pix: V4l2PixFormat,
+    /// The rest of the 200 byte union
+    _reserved: [u8; 200 - std::mem::size_of::<V4l2PixFormat>()],
+}
+
+#[repr(C)]
+struct V4l2PixFormat {
+    width: u32,
+    height: u32,
+    pixelformat: u32,
+    field: u32,
+    bytesperline: u32,
+    sizeimage: u32,
+    colorspace: u32,
+    priv_: u32,
+    flags: u32,
+    ycbcr_enc: u32,
+    quantization: u32,
+    xfer_func: u32,
+}
+
+/// Tells the device what the frames written to it look like
+fn set_format(device: &File, width: u32, height: u32) -> io::Result<()> {
+    let mut format = V4l2Format {
+        buf_type: V4L2_BUF_TYPE_VIDEO_OUTPUT,
+        pix: V4l2PixFormat {
+            width,
+            height,
+            pixelformat: V4L2_PIX_FMT_BGR32,
+            field: V4L2_FIELD_NONE,
+            bytesperline: width * 4,
+            sizeimage: width * height * 4,
+            colorspace: V4L2_COLORSPACE_SRGB,
+            priv_: 0,
+            flags: 0,
+            ycbcr_enc: 0,
+            quantization: 0,
+            xfer_func: 0,
+        },
+        _reserved: [0; 200 - std::mem::size_of::<V4l2PixFormat>()],
+    };
+    // SAFETY: `format` is a live, correctly sized `struct v4l2_format` for the call
+    let result = unsafe {
+        ioctl(
+            device.as_raw_fd(),
+            VIDIOC_S_FMT,
+            &mut format as *mut V4l2Format,
+        )
+    };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// A loopback device being fed frames. Frames are given as RGBA through `submit`.
+pub struct Loopback {
+    sender: SyncSender<Vec<u8>>,
+    /// Buffers handed back by the writer, so steady state doesn't allocate
+    spare: Receiver<Vec<u8>>,
+    width: u32,
+    height: u32,
+    dropped: u64,
+}
+
+impl Loopback {
+    /// Opens `path` for `width`x`height` frames and starts the writer thread.
+    /// It has to exist and be a character device or a FIFO; a FIFO gets the
+    /// raw BGRA frames back to back, and blocks here until something reads it.
+    pub fn open(path: &Path, width: u32, height: u32) -> io::Result<Self> {
+        let kind = fs::metadata(path)?.file_type();
+        if !kind.is_char_device() && !kind.is_fifo() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not a device or FIFO", path.display()),
+            ));
+        }
+        let device = OpenOptions::new().write(true).open(path)?;
+        match set_format(&device, width, height) {
+            Ok(()) => info!(
+                "Writing {}x{} BGRA frames to {}",
+                width,
+                height,
+                path.display()
+            ),
+            Err(e) if e.raw_os_error() == Some(ENOTTY) => info!(
+                "{} is not a video device; writing raw BGRA frames to it",
+                path.display()
+            ),
+            Err(e) => return Err(e),
+        }
+        Self::start(device, width, height)
+    }
+
+    fn start(mut out: impl Write + Send + 'static, width: u32, height: u32) -> io::Result<Self> {
+        let (sender, frames) = mpsc::sync_channel::<Vec<u8>>(CHANNEL_CAPACITY);
+        let (returns, spare) = mpsc::channel();
+        thread::Builder::new()
+            .name("loopback-writer".into())
+            .spawn(move || {
+                for frame in frames {
+                    if let Err(e) = out.write_all(&frame) {
+                        warn!("Loopback output stopped: {}", e);
+                        return;
+                    }
+                    // The receiving end is gone once the `Loopback` is dropped
+                    let _ = returns.send(frame);
+                }
+            })?;
+        Ok(Self {
+            sender,
+            spare,
+            width,
+            height,
+            dropped: 0,
+        })
+    }
+
+    /// Converts the RGBA `frame` to BGRA and offers it to the writer. Never blocks.
+    pub fn submit(&mut self, frame: &[u8]) {
+        let len = (self.width * self.height * 4) as usize;
+        if frame.len() != len {
+            return;
+        }
+        let mut bgra = self.spare.try_recv().unwrap_or_default();
+        bgra.resize(len, 0);
+        PixelFormat::Bgra8.convert(frame, &mut bgra);
+        if self.sender.try_send(bgra).is_err() {
+            self.dropped += 1;
+        }
+    }
+
+    /// Frames skipped because the writer was still busy
+    pub fn dropped_frames(&self) -> u64 {
+        self.dropped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CString;
+    use std::io::Read;
+    use std::os::raw::c_char;
+    use std::os::unix::ffi::OsStrExt;
+
+    extern "C" {
+        fn mkfifo(path: *const c_char, mode: u32) -> c_int;
+    }
+
+    #[test]
+    fn test_v4l2_format_matches_the_kernel_layout() {
+        assert_eq!(std::mem::size_of::<V4l2PixFormat>(), 48);
+        assert_eq!(std::mem::size_of::<V4l2Format>(), 208);
+        // The size is part of the request number
+        assert_eq!((VIDIOC_S_FMT >> 16) & 0x3fff, 208);
+    }
+
+    #[test]
+    fn test_frames_written_to_a_fifo_read_back_as_bgra() {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("stimstation-loopback-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let c_path = CString::new(path.as_os_str().as_bytes()).unwrap();
+        // SAFETY: `c_path` is a live NUL-terminated string for the call
+        assert_eq!(unsafe { mkfifo(c_path.as_ptr(), 0o600) }, 0);
+
+        // Opening a FIFO to write waits for a reader
+        let reader = thread::spawn({
+            let path = path.clone();
+            move || {
+                let mut bytes = Vec::new();
+                File::open(path).unwrap().read_to_end(&mut bytes).unwrap();
+                bytes
+            }
+        });
+        let mut loopback = Loopback::open(&path, 2, 1).unwrap();
+        loopback.submit(&[255, 0, 0, 255, 10, 20, 30, 40]);
+        // Wrong sizes are ignored
+        loopback.submit(&[1, 2, 3, 4]);
+        // The writer closes its end once the frame is out
+        drop(loopback);
+        let bytes = reader.join().unwrap();
+        let _ = fs::remove_file(&path);
+        assert_eq!(bytes, [0, 0, 255, 255, 30, 20, 10, 40]);
+
+        // Neither a missing path nor a plain file is created or written to
+        let plain = dir.join(format!("stimstation-loopback-plain-{}", std::process::id()));
+        assert!(Loopback::open(&plain, 2, 1).is_err() && !plain.exists());
+        fs::write(&plain, b"keep").unwrap();
+        let refused = Loopback::open(&plain, 2, 1).err().unwrap();
+        assert_eq!(refused.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(fs::read(&plain).unwrap(), b"keep");
+        let _ = fs::remove_file(&plain);
+    }
+}
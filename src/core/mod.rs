@@ -1,3 +1,40 @@
+pub mod accessibility;
+pub mod bench;
+pub mod bufpool;
+pub mod capture;
+pub mod compositor;
+pub mod coords;
+pub mod doctor;
+pub mod embed;
+pub mod energy;
+pub mod events;
+pub mod export;
+pub mod focus;
+pub mod frame_cap;
+pub mod frame_diff;
+pub mod input_record;
 pub mod integration;
+pub mod keep_awake;
+#[cfg(feature = "live-params")]
+pub mod live_params;
+pub mod logging;
+#[cfg(all(target_os = "linux", feature = "v4l2-loopback"))]
+pub mod loopback;
 pub mod orchestrator;
+pub mod pacing;
+pub mod persist;
+pub mod pixel_format;
+pub mod presets;
+#[cfg(feature = "preview-server")]
+pub mod preview;
+pub mod render_export;
+pub mod scenes;
+pub mod session_stats;
+pub mod settings;
+pub mod settings_history;
+pub mod sim_rng;
+pub mod snapshot;
+pub mod station_state;
+pub mod timestep;
 pub mod types;
+pub mod watchdog;
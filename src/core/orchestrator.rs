@@ -1,5 +1,95 @@
+use crate::algorithms::maze;
+use crate::audio::features;
+use crate::audio::features::FrameFeatures;
+use crate::core::compositor::{Compositor, OverlayLayer};
+use crate::core::energy::{self, FinaleCue};
+use crate::core::events::{self, Event};
+use crate::core::frame_cap::{self, RenderKey};
+use crate::core::frame_diff;
+use crate::core::persist;
+use crate::core::scenes::{self, CoveragePolicy, SceneInfo};
+use crate::core::station_state;
+use crate::core::timestep;
+use crate::core::types::Position;
+use crate::graphics::draw_ctx::{fit_scale, DrawCtx, Region, FULL_DETAIL_SIZE};
+use crate::graphics::{accents, kaleidoscope, longexposure, rain, shatter, tunnel};
+use crate::ui::hud_layout::{self, HudAnchor, HudElement, HudRect, HudRequest};
+use crate::ui::status_icons::{self, AudioStatus};
 use crate::{algorithms::sorter_manager, graphics::render, integration, physics};
+use std::sync::atomic::Ordering;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
 
+/// Clean mode hides all HUD and edge elements so only the central visualization is drawn
+pub fn set_clean_mode(enabled: bool) {
+    station_state::current()
+        .clean_mode
+        .store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_clean_mode() -> bool {
+    station_state::current().clean_mode.load(Ordering::Relaxed)
+}
+
+/// Flips clean mode and returns the new state
+pub fn toggle_clean_mode() -> bool {
+    !station_state::current()
+        .clean_mode
+        .fetch_xor(true, Ordering::Relaxed)
+}
+
+pub fn is_debug_overlay() -> bool {
+    station_state::current()
+        .debug_overlay
+        .load(Ordering::Relaxed)
+}
+
+/// Flips the debug overlay and returns the new state. Collisions are logged
+/// while it is on.
+pub fn toggle_debug_overlay() -> bool {
+    let enabled = !station_state::current()
+        .debug_overlay
+        .fetch_xor(true, Ordering::Relaxed);
+    physics::physics::set_collision_log_enabled(enabled);
+    enabled
+}
+
+pub fn set_light_mixing(enabled: bool) {
+    station_state::current()
+        .light_mixing
+        .store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_light_mixing() -> bool {
+    station_state::current()
+        .light_mixing
+        .load(Ordering::Relaxed)
+}
+
+/// Flips light mixing and returns the new state
+pub fn toggle_light_mixing() -> bool {
+    !station_state::current()
+        .light_mixing
+        .fetch_xor(true, Ordering::Relaxed)
+}
+
+/// Sets how many times the window's size the frame being drawn is; ball
+/// sizes follow it. Only a supersampled capture sets it.
+pub fn set_render_scale(scale: f32) {
+    station_state::current()
+        .render_scale
+        .store(scale.to_bits(), Ordering::Relaxed);
+}
+
+fn render_scale() -> f32 {
+    f32::from_bits(
+        station_state::current()
+            .render_scale
+            .load(Ordering::Relaxed),
+    )
+}
+
+/// Adapter for callers still passing raw frame parameters; see `render_frame`
 pub fn draw_frame(
     frame: &mut [u8],
     width: u32,
@@ -8,93 +98,572 @@ pub fn draw_frame(
     x_offset: usize,
     buffer_width: u32,
 ) {
+    let mut ctx = DrawCtx::from_legacy(frame, width, height, time, x_offset, buffer_width);
+    render_frame(&mut ctx);
+}
+
+/// Set once `init` has run
+static INITIALIZED: OnceLock<()> = OnceLock::new();
+
+/// Sets up audio, text, and the edge sorters. Call before drawing any frame;
+/// later calls only give a station that has no audio bars yet its own.
+pub fn init() {
+    init_with_playback(true);
+}
+
+/// `init` for rendering without a window: audio-reactive scenes use the
+/// simulated signal instead of starting playback
+pub fn init_headless() {
+    init_with_playback(false);
+}
+
+fn init_with_playback(playback: bool) {
+    integration::initialize_audio_integration_with(playback);
+    INITIALIZED.get_or_init(|| {
+        integration::initialize_text_renderer();
+        sorter_manager::initialize_sorters();
+        events::subscribe(crate::graphics::screen_shake::on_event);
+        events::subscribe(crate::ui::timeline::on_event);
+        events::subscribe(crate::ui::toast::on_event);
+        events::subscribe(sorter_manager::on_event);
+        events::subscribe(crate::core::session_stats::on_event);
+        events::subscribe(energy::on_event);
+        events::subscribe(physics::fireworks::on_event);
+    });
+}
+
+pub fn is_initialized() -> bool {
+    INITIALIZED.get().is_some()
+}
+
+/// Frame size the HUD text needs so it doesn't cover the whole scene
+const HUD_TEXT_ROOM: (f32, f32) = (320.0, 160.0);
+/// Fewest rays a ball casts however small the frame
+const MIN_RAY_COUNT: usize = 12;
+/// Radius of the energy finale's ring of explosions, as a share of the frame's shorter side
+const FINALE_RING_RADIUS: f32 = 0.3;
+
+/// Full-frame clears done for the last frame, shown in the debug overlay
+pub fn last_frame_clears() -> usize {
+    station_state::current().frame_clears.load(Ordering::SeqCst)
+}
+
+/// Clears the frame once if `coverage` asks for it, then draws the scene.
+/// Returns how many full-frame clears were done.
+fn compose_scene(
+    ctx: &mut DrawCtx,
+    coverage: CoveragePolicy,
+    draw_scene: impl FnOnce(&mut DrawCtx),
+) -> usize {
+    let clears = match coverage {
+        CoveragePolicy::NeedsClear(color) => {
+            ctx.clear(color);
+            1
+        }
+        CoveragePolicy::FullCover | CoveragePolicy::Overlay => 0,
+    };
+    draw_scene(ctx);
+    clears
+}
+
+/// How long each phase of a frame took, smoothed over recent frames
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PhaseTimings {
+    pub update_ms: f32,
+    pub render_ms: f32,
+    /// Handing the frame to the GPU; stays 0 where nothing presents, e.g. embedded
+    pub present_ms: f32,
+}
+
+/// Weight of the newest frame in the smoothed timings
+const TIMING_SMOOTHING: f32 = 0.1;
+
+/// Runs `f` on the phase timings of the station being driven
+fn with_timings<R>(f: impl FnOnce(&mut PhaseTimings) -> R) -> R {
+    let state = station_state::current();
+    let mut timings = station_state::lock(&state.phase_timings);
+    f(&mut timings)
+}
+
+pub fn phase_timings() -> PhaseTimings {
+    with_timings(|timings| *timings)
+}
+
+fn smooth(average: &mut f32, elapsed: Duration) {
+    let ms = elapsed.as_secs_f32() * 1000.0;
+    *average = if *average == 0.0 {
+        ms
+    } else {
+        *average + (ms - *average) * TIMING_SMOOTHING
+    };
+}
+
+/// Records how long presenting the last frame took; the window calls this
+/// around `present`
+pub fn record_present_time(elapsed: Duration) {
+    with_timings(|timings| smooth(&mut timings.present_ms, elapsed));
+}
+
+/// What the update phase hands to the render phase
+struct FrameUpdate {
+    scene: &'static SceneInfo,
+    clean: bool,
+    audio: FrameFeatures,
+    scale: (f32, f32),
+    ball_scale: (f32, f32),
+}
+
+/// Advances everything that doesn't draw: presets, scene tracking, the audio
+/// features, and the ball physics, on the fixed timestep when it is on, then
+/// dispatches the frame's events and plays any finale cues they made due.
+/// Takes no frame, so nothing can draw here.
+fn update(width: u32, height: u32, time: f32) -> FrameUpdate {
     let (scale_x, scale_y) = get_scale_factors(width, height);
+    let render_scale = render_scale();
+    let (ball_scale_x, ball_scale_y) = (scale_x * render_scale, scale_y * render_scale);
+    crate::core::presets::update(time);
+    crate::graphics::theme::update(time);
+    let clean = is_clean_mode();
+    let scene = scenes::active_scene();
+    persist::track_active_scene(scene);
+    crate::ui::timeline::track(scene, time);
+    let station = station_state::current();
+    let last_scene = station_state::lock(&station.last_scene).replace(scene.id);
+    if last_scene != Some(scene.id) {
+        events::publish(Event::SceneChanged {
+            from: last_scene,
+            to: scene.id,
+        });
+    }
 
-    initialize_systems();
-    physics::physics::update_physics(width, height, time, scale_x, scale_y);
-    render::clear_frame(frame);
-    draw_balls_and_rays(
-        frame,
-        width,
-        height,
-        time,
-        scale_x,
-        scale_y,
-        x_offset,
-        buffer_width,
+    // The only place the shared spectrum is locked each frame
+    let audio = features::update_frame_features(time);
+    let step = |time| {
+        physics::physics::update_physics(width, height, time, ball_scale_x, ball_scale_y, &audio)
+    };
+    if timestep::is_fixed_timestep() {
+        let steps = timestep::advance(time);
+        steps.times.iter().copied().for_each(step);
+        physics::physics::set_interpolation(steps.alpha);
+    } else {
+        step(time);
+        physics::physics::set_interpolation(1.0);
+    }
+    events::drain(time);
+    for cue in energy::advance(time) {
+        play_finale_cue(cue, width, height);
+    }
+    FrameUpdate {
+        scene,
+        clean,
+        audio,
+        scale: (scale_x, scale_y),
+        ball_scale: (ball_scale_x, ball_scale_y),
+    }
+}
+
+/// Runs just the update phase for the frame at `time`, drawing nothing
+pub fn update_frame(width: u32, height: u32, time: f32) {
+    update(width, height, time);
+}
+
+/// Updates every system, then draws the whole scene into the context's region
+pub fn render_frame(ctx: &mut DrawCtx) {
+    debug_assert!(
+        is_initialized(),
+        "orchestrator::init() must be called before drawing frames"
     );
-    sorter_manager::draw_sorter_visualizations(
-        frame,
+    // A supersampled capture redraws the frame just shown; it isn't a new one
+    let timed = render_scale() == 1.0;
+    let started = Instant::now();
+    if timed {
+        let particles = physics::fireworks::particle_count() + physics::world::particle_count();
+        events::publish(Event::FrameRendered { particles });
+    }
+    let update = update(ctx.width(), ctx.height(), ctx.time);
+    let updated = Instant::now();
+    render(ctx, &update, timed);
+    if timed {
+        with_timings(|timings| {
+            smooth(&mut timings.update_ms, updated - started);
+            smooth(&mut timings.render_ms, updated.elapsed());
+        });
+    }
+}
+
+/// Draws the scene and overlays. Scenes whose simulation is tied to their
+/// drawing (the world, tunnel, rain, shatter, maze, sorters, fireworks) still step here.
+fn render(ctx: &mut DrawCtx, update: &FrameUpdate, timed: bool) {
+    let FrameUpdate {
+        scene,
+        clean,
+        ref audio,
+        scale: (scale_x, scale_y),
+        ball_scale: (ball_scale_x, ball_scale_y),
+    } = *update;
+    let time = ctx.time;
+    let mut ctx = ctx.with_features(audio);
+    let key = RenderKey {
+        scene: scene.id,
+        region: ctx.region,
+    };
+    frame_diff::restore_composed(&mut ctx);
+    let clears = if frame_cap::begin_scene(&mut ctx, scene.max_fps, key) {
+        let clears = compose_scene(&mut ctx, scene.coverage, |ctx| {
+            tunnel::update_and_draw_tunnel(ctx);
+            rain::update_and_draw_rain(ctx);
+            shatter::update_and_draw_shatter(ctx);
+            maze::update_and_draw_maze(ctx);
+            {
+                let lighting = ctx.quality.particle_lighting;
+                let (frame, width, height, x_offset, buffer_width) = ctx.legacy();
+                physics::world::update_and_draw_world(
+                    frame,
+                    width,
+                    height,
+                    time,
+                    x_offset,
+                    buffer_width,
+                    lighting,
+                );
+            }
+            physics::softbody::update_and_draw_softbody(ctx, audio);
+            if !maze::is_maze_enabled()
+                && !rain::is_rain_enabled()
+                && !shatter::is_shatter_enabled()
+            {
+                draw_balls_and_rays(ctx, ball_scale_x, ball_scale_y);
+            }
+            if !clean {
+                sorter_manager::draw_sorters(
+                    ctx,
+                    sorter_manager::EdgeThickness::DEFAULT.scaled(scale_x, scale_y),
+                );
+            }
+            physics::fireworks::update_and_draw_fireworks(ctx);
+            accents::update_and_draw_accents(ctx, scene);
+            kaleidoscope::apply_kaleidoscope(ctx);
+        });
+        frame_cap::end_scene(&ctx, scene.max_fps, key);
+        clears
+    } else {
+        0
+    };
+    station_state::current()
+        .frame_clears
+        .store(clears, Ordering::SeqCst);
+    if timed {
+        longexposure::update(&mut ctx);
+    }
+
+    let (frame, width, height, x_offset, buffer_width) = ctx.legacy();
+    let mut compositor = Compositor::new();
+    queue_overlays(
+        &mut compositor,
         width,
         height,
         time,
-        scale_x,
-        scale_y,
         x_offset,
         buffer_width,
+        clean,
     );
-    sorter_manager::draw_algorithm_stats(frame, width, height, x_offset, buffer_width);
-    integration::update_and_draw_audio(frame, width, height, time, x_offset, buffer_width);
-    integration::update_and_draw_text(frame, width, height, time, x_offset, buffer_width);
+    compositor.flush(frame);
 }
 
-fn get_scale_factors(_width: u32, _height: u32) -> (f32, f32) {
-    let (monitor_width, monitor_height) = integration::get_monitor_dimensions();
-    match (monitor_width, monitor_height) {
-        (Some(m_width), Some(m_height)) => {
-            let base_width = 1920.0;
-            let base_height = 1080.0;
-            (m_width as f32 / base_width, m_height as f32 / base_height)
+/// Queues the overlays drawn on top of the scene; the compositor decides their order.
+/// The HUD elements along the edges get their spots from one layout, so they
+/// don't overlap. In clean mode nothing is queued, so the skipped systems do
+/// no work at all.
+fn queue_overlays(
+    compositor: &mut Compositor,
+    width: u32,
+    height: u32,
+    time: f32,
+    x_offset: usize,
+    buffer_width: u32,
+    clean: bool,
+) {
+    let text_room = fit_scale(HUD_TEXT_ROOM, (width, height)) >= 1.0;
+    let icons = if clean {
+        Vec::new()
+    } else {
+        status_icons::icons(&AudioStatus::current())
+    };
+    let debug_lines = is_debug_overlay().then(debug_lines);
+    let requests: Vec<HudRequest> = [
+        crate::ui::toast::hud_request(),
+        debug_lines.as_deref().map(debug_overlay_request),
+        status_icons::hud_request(&icons),
+        (!clean && text_room)
+            .then(sorter_manager::algorithm_stats_request)
+            .flatten(),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    let hud = hud_layout::solve(&requests, width, height);
+    hud_layout::set_current_layout(hud.clone());
+
+    // First on the last layer, so it sees everything else composed
+    if frame_diff::is_diff_view() {
+        compositor.enqueue(OverlayLayer::Debug, move |frame| {
+            frame_diff::draw_diff_view(frame, width, height, x_offset, buffer_width);
+        });
+    }
+    if let (Some(lines), Some(region)) = (debug_lines, hud.region(HudElement::DebugOverlay)) {
+        compositor.enqueue(OverlayLayer::Debug, move |frame| {
+            draw_debug_overlay(frame, &lines, region, x_offset, buffer_width);
+        });
+    }
+    if crate::ui::toast::is_toast_visible() {
+        let region = hud.region(HudElement::Toast);
+        compositor.enqueue(OverlayLayer::Notifications, move |frame| {
+            crate::ui::toast::draw_toast(frame, region, time, x_offset, buffer_width);
+        });
+    }
+    if crate::ui::calibration::is_calibrating() {
+        compositor.enqueue(OverlayLayer::Notifications, move |frame| {
+            crate::ui::calibration::draw_calibration(frame, width, height, x_offset, buffer_width);
+        });
+    }
+    if crate::ui::help_overlay::is_help_open() {
+        compositor.enqueue(OverlayLayer::Help, move |frame| {
+            crate::ui::help_overlay::draw_help(frame, width, height, x_offset, buffer_width);
+        });
+    }
+    if crate::ui::timeline::is_timeline_open() {
+        compositor.enqueue(OverlayLayer::Menu, move |frame| {
+            crate::ui::timeline::draw_timeline(frame, width, height, x_offset, buffer_width);
+        });
+    }
+    if crate::ui::device_picker::is_picker_open() {
+        compositor.enqueue(OverlayLayer::Menu, move |frame| {
+            crate::ui::device_picker::draw_picker(frame, width, height, x_offset, buffer_width);
+        });
+    }
+    if crate::ui::menu::is_menu_open() {
+        compositor.enqueue(OverlayLayer::Menu, move |frame| {
+            crate::ui::menu::draw_menu(frame, width, height, x_offset, buffer_width);
+        });
+    }
+    if clean {
+        return;
+    }
+    if energy::energy_level() > 0.0 {
+        compositor.enqueue(OverlayLayer::Hud, move |frame| {
+            energy::draw_energy_bar(frame, width, height, time, x_offset, buffer_width);
+        });
+    }
+    if crate::audio::viz_enabled() {
+        compositor.enqueue(OverlayLayer::SceneEffects, move |frame| {
+            integration::update_and_draw_audio(frame, width, height, time, x_offset, buffer_width);
+        });
+    }
+    if physics::world::is_world_enabled() {
+        compositor.enqueue(OverlayLayer::Hud, move |frame| {
+            physics::forces::draw_wind_indicator(
+                frame,
+                width,
+                height,
+                time,
+                x_offset,
+                buffer_width,
+            );
+        });
+    }
+    let icons_region = hud.region(HudElement::StatusIcons);
+    compositor.enqueue(OverlayLayer::Hud, move |frame| {
+        if let Some(region) = icons_region {
+            status_icons::draw_status_icons(frame, &icons, region, height, x_offset, buffer_width);
         }
-        _ => (1.0, 1.0),
+    });
+    if !text_room {
+        return;
     }
+    let (scale_x, scale_y) = get_scale_factors(width, height);
+    let stats_region = hud.region(HudElement::AlgorithmStats);
+    compositor.enqueue(OverlayLayer::Hud, move |frame| {
+        if let Some(region) = stats_region {
+            sorter_manager::draw_algorithm_stats(
+                frame,
+                width,
+                region,
+                time,
+                x_offset,
+                buffer_width,
+            );
+        }
+        sorter_manager::draw_sorter_captions(
+            frame,
+            width,
+            height,
+            sorter_manager::EdgeThickness::DEFAULT.scaled(scale_x, scale_y),
+            x_offset,
+            buffer_width,
+        );
+    });
+    compositor.enqueue(OverlayLayer::Hud, move |frame| {
+        integration::update_and_draw_text(frame, width, height, time, x_offset, buffer_width);
+    });
+}
+
+/// The debug overlay's line of phase timings
+fn phase_line(timings: PhaseTimings) -> String {
+    let step = if timestep::is_fixed_timestep() {
+        format!(", physics fixed at {:.0} Hz", timestep::FIXED_RATE_HZ)
+    } else {
+        String::new()
+    };
+    format!(
+        "Phases: update {:.1} ms / render {:.1} ms / present {:.1} ms{}",
+        timings.update_ms, timings.render_ms, timings.present_ms, step
+    )
+}
+
+/// Space between the debug overlay's lines
+const DEBUG_LINE_HEIGHT: f32 = 25.0;
+/// Asked for with F3, so it keeps its corner
+const DEBUG_PRIORITY: u8 = 3;
+
+/// Frame clears, phase timings, frame pacing, and buffer pool usage, one line per
+/// buffer name
+fn debug_lines() -> Vec<String> {
+    let mut lines = vec![
+        format!("Frame clears: {}", last_frame_clears()),
+        phase_line(phase_timings()),
+    ];
+    lines.extend(crate::core::pacing::report_line());
+    lines.extend(crate::core::bufpool::report_lines());
+    let collisions = physics::physics::collision_events();
+    if let Some(last) = collisions.last() {
+        lines.push(format!(
+            "Collisions ({}): {} logged, last at {:.2}s, impulse {:.2}",
+            physics::physics::collision_model().name(),
+            collisions.len(),
+            last.time,
+            last.impulse
+        ));
+    }
+    lines
 }
 
-fn initialize_systems() {
-    integration::initialize_audio_integration();
-    integration::initialize_text_renderer();
-    sorter_manager::initialize_sorters();
+/// Room the debug overlay asks for in the bottom-left corner
+fn debug_overlay_request(lines: &[String]) -> HudRequest {
+    HudRequest {
+        element: HudElement::DebugOverlay,
+        anchor: HudAnchor::BottomLeft,
+        priority: DEBUG_PRIORITY,
+        size: crate::text::text_rendering::background_text_size(lines, DEBUG_LINE_HEIGHT),
+    }
 }
 
-fn draw_balls_and_rays(
+/// Draws the debug overlay's `lines` in `region`, its spot in the HUD layout
+fn draw_debug_overlay(
     frame: &mut [u8],
-    width: u32,
-    height: u32,
-    time: f32,
-    scale_x: f32,
-    scale_y: f32,
+    lines: &[String],
+    region: HudRect,
     x_offset: usize,
     buffer_width: u32,
 ) {
+    let (x, mut y) = crate::text::text_rendering::background_text_origin(region.x, region.y);
+    for line in lines {
+        crate::text::text_rendering::draw_text_with_background(
+            frame,
+            line,
+            x_offset as f32 + x,
+            y,
+            [200, 255, 200, 255],
+            [0, 0, 0, 160],
+            buffer_width,
+        );
+        y += DEBUG_LINE_HEIGHT;
+    }
+}
+
+/// Carries out a step of the energy finale over whatever scene is on
+fn play_finale_cue(cue: FinaleCue, width: u32, height: u32) {
+    match cue {
+        FinaleCue::Start(plan) => {
+            sorter_manager::restart_sorters();
+            let (cx, cy) = (width as f32 / 2.0, height as f32 / 2.0);
+            let radius = width.min(height) as f32 * FINALE_RING_RADIUS;
+            for i in 0..plan.explosions {
+                let angle = i as f32 / plan.explosions as f32 * std::f32::consts::TAU;
+                physics::fireworks::explode_at(
+                    cx + radius * angle.cos(),
+                    cy + radius * angle.sin(),
+                );
+            }
+            physics::physics::set_ball_speed_boost(plan.ball_burst);
+            kaleidoscope::set_kaleidoscope_enabled(plan.kaleidoscope);
+            crate::ui::toast::show_toast(vec!["Energy full!".to_string()]);
+        }
+        FinaleCue::KaleidoscopeOff => kaleidoscope::set_kaleidoscope_enabled(false),
+        FinaleCue::End(_) => {
+            physics::physics::set_ball_speed_boost(1.0);
+            kaleidoscope::set_kaleidoscope_enabled(false);
+        }
+    }
+}
+
+fn get_scale_factors(_width: u32, _height: u32) -> (f32, f32) {
+    integration::get_monitor_scale()
+}
+
+fn draw_balls_and_rays(ctx: &mut DrawCtx, scale_x: f32, scale_y: f32) {
     let (yellow_pos, green_pos) = physics::physics::get_ball_positions();
 
     if let (Some(yellow_pos), Some(green_pos)) = (yellow_pos, green_pos) {
+        let mut quality = *ctx.quality;
+        let detail = fit_scale(FULL_DETAIL_SIZE, (ctx.width(), ctx.height()));
+        quality.ray_count = ((quality.ray_count as f32 * detail) as usize)
+            .max(MIN_RAY_COUNT)
+            .min(quality.ray_count);
+        let mixing = is_light_mixing();
+        if mixing {
+            let time = ctx.time;
+            let (width, height) = (ctx.width(), ctx.height());
+            let mut mixed = ctx.sub_region(Region::new(0, 0, width, height));
+            mixed.quality = &quality;
+            render::draw_mixed_rays(
+                &mut mixed,
+                [
+                    render::RayLight {
+                        pos: yellow_pos,
+                        color: physics::physics::YELLOW_RAY_COLOR,
+                        time,
+                    },
+                    render::RayLight {
+                        pos: green_pos,
+                        color: physics::physics::GREEN_RAY_COLOR,
+                        time: time + physics::physics::GREEN_RAY_TIME_OFFSET,
+                    },
+                ],
+            );
+        }
         let draw_rays_closure = |frame: &mut [u8],
                                  width: u32,
                                  height: u32,
-                                 pos: (f32, f32),
+                                 pos: Position,
                                  ray_color: [u8; 4],
                                  time: f32,
                                  x_offset: usize,
                                  buffer_width: u32| {
+            if mixing {
+                return;
+            }
             let other_pos = if pos == yellow_pos {
                 green_pos
             } else {
                 yellow_pos
             };
-            render::draw_rays_from_ball(
-                frame,
-                width,
-                height,
-                pos,
-                ray_color,
-                time,
-                x_offset,
-                buffer_width,
-                other_pos,
-            );
+            let mut ctx = DrawCtx::from_legacy(frame, width, height, time, x_offset, buffer_width);
+            ctx.quality = &quality;
+            render::draw_rays(&mut ctx, pos, ray_color, other_pos);
         };
 
+        let time = ctx.time;
+        let (frame, width, height, x_offset, buffer_width) = ctx.legacy();
         physics::physics::draw_balls_with_effects(
             frame,
             width,
@@ -104,7 +673,94 @@ fn draw_balls_and_rays(
             scale_y,
             x_offset,
             buffer_width,
+            &quality,
             draw_rays_closure,
         );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clean_mode_queues_no_hud() {
+        let mut compositor = Compositor::new();
+        queue_overlays(&mut compositor, 800, 600, 0.0, 0, 800, true);
+        assert_eq!(compositor.count_on(OverlayLayer::Hud), 0);
+        assert_eq!(compositor.count_on(OverlayLayer::SceneEffects), 0);
+        assert!(compositor.is_empty());
+    }
+
+    #[test]
+    fn test_normal_mode_queues_hud() {
+        let mut compositor = Compositor::new();
+        queue_overlays(&mut compositor, 800, 600, 0.0, 0, 800, false);
+        // Status icons, and the captions and text past the room check
+        assert_eq!(compositor.count_on(OverlayLayer::Hud), 3);
+        assert_eq!(compositor.count_on(OverlayLayer::SceneEffects), 1);
+    }
+
+    /// Fills a frame with stale pixels, runs a fake scene through `compose_scene`,
+    /// and returns the clear count, the frame the scene saw, and the final frame
+    fn compose_fake(
+        coverage: CoveragePolicy,
+        covers_everything: bool,
+    ) -> (usize, Vec<u8>, Vec<u8>) {
+        let (width, height) = (8u32, 4u32);
+        let mut frame = vec![0xAB; (width * height * 4) as usize];
+        let mut ctx = DrawCtx::from_legacy(&mut frame, width, height, 0.0, 0, width);
+        let mut seen = Vec::new();
+        let clears = compose_scene(&mut ctx, coverage, |ctx| {
+            seen = ctx.legacy().0.to_vec();
+            if covers_everything {
+                ctx.clear([1, 2, 3, 255]);
+            } else {
+                ctx.put_pixel(0, 0, [9, 9, 9, 255]);
+            }
+        });
+        (clears, seen, frame)
+    }
+
+    #[test]
+    fn test_coverage_policy_decides_the_clear() {
+        let color = [5, 5, 10, 255];
+        let (clears, seen, frame) = compose_fake(CoveragePolicy::NeedsClear(color), false);
+        assert_eq!(clears, 1);
+        assert!(seen.chunks_exact(4).all(|pixel| pixel == color));
+        assert_eq!(&frame[..4], &[9, 9, 9, 255]);
+        assert!(frame[4..].chunks_exact(4).all(|pixel| pixel == color));
+
+        // A scene that covers everything gets no clear and leaves nothing stale
+        let (clears, seen, frame) = compose_fake(CoveragePolicy::FullCover, true);
+        assert_eq!(clears, 0);
+        assert!(seen.iter().all(|&b| b == 0xAB));
+        assert!(frame.chunks_exact(4).all(|pixel| pixel == [1, 2, 3, 255]));
+
+        // An overlay draws on top of what was there
+        let (clears, _, frame) = compose_fake(CoveragePolicy::Overlay, false);
+        assert_eq!(clears, 0);
+        assert!(frame[4..].iter().all(|&b| b == 0xAB));
+    }
+
+    #[test]
+    fn test_only_the_world_tunnel_rain_and_shatter_cover_the_frame() {
+        for scene in scenes::SCENES {
+            let expected = if matches!(scene.id, "world" | "tunnel" | "rain" | "shatter") {
+                CoveragePolicy::FullCover
+            } else {
+                CoveragePolicy::NeedsClear(render::BACKGROUND_COLOR)
+            };
+            assert_eq!(scene.coverage, expected, "{}", scene.id);
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "orchestrator::init() must be called before drawing frames")]
+    fn test_drawing_before_init_panics() {
+        let (width, height) = (64, 48);
+        let mut frame = vec![0u8; (width * height * 4) as usize];
+        draw_frame(&mut frame, width, height, 0.0, 0, width);
+    }
+}
@@ -0,0 +1,365 @@
+//! Frame-rate cap for the window. Each frame is due one interval after the
+//! previous frame's deadline rather than after the frame itself, so late
+//! wake-ups don't drift the rate down. The wait sleeps until shortly before
+//! the deadline and spins the rest, since OS sleeps overshoot by a
+//! millisecond or more. The monitor-refresh cap leaves pacing to vsync.
+
+use std::collections::VecDeque;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Left to spin at the end of a wait
+pub const SPIN_MARGIN: Duration = Duration::from_millis(1);
+/// Frames the statistics are taken over
+const STATS_FRAMES: usize = 120;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FpsCap {
+    Fps30,
+    Fps60,
+    Fps120,
+    Unlimited,
+    /// Presents with vsync, at whatever rate the monitor refreshes
+    MonitorRefresh,
+}
+
+impl FpsCap {
+    pub const ALL: [FpsCap; 5] = [
+        FpsCap::Fps30,
+        FpsCap::Fps60,
+        FpsCap::Fps120,
+        FpsCap::Unlimited,
+        FpsCap::MonitorRefresh,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FpsCap::Fps30 => "30",
+            FpsCap::Fps60 => "60",
+            FpsCap::Fps120 => "120",
+            FpsCap::Unlimited => "unlimited",
+            FpsCap::MonitorRefresh => "monitor",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|cap| cap.name() == name)
+    }
+
+    /// Frames per second the cap holds to, if it sets one itself
+    pub fn fps(self) -> Option<u32> {
+        match self {
+            FpsCap::Fps30 => Some(30),
+            FpsCap::Fps60 => Some(60),
+            FpsCap::Fps120 => Some(120),
+            FpsCap::Unlimited | FpsCap::MonitorRefresh => None,
+        }
+    }
+
+    pub fn interval(self) -> Option<Duration> {
+        self.fps().map(|fps| Duration::from_secs(1) / fps)
+    }
+
+    pub fn uses_vsync(self) -> bool {
+        self == FpsCap::MonitorRefresh
+    }
+
+    pub fn label(self) -> String {
+        match self.fps() {
+            Some(fps) => format!("{} fps", fps),
+            None if self.uses_vsync() => "monitor refresh".to_string(),
+            None => "unlimited".to_string(),
+        }
+    }
+}
+
+static FPS_CAP: Mutex<FpsCap> = Mutex::new(FpsCap::MonitorRefresh);
+static REPORT: Mutex<Option<PacingReport>> = Mutex::new(None);
+
+pub fn fps_cap() -> FpsCap {
+    *FPS_CAP.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+pub fn set_fps_cap(cap: FpsCap) {
+    *FPS_CAP.lock().unwrap_or_else(PoisonError::into_inner) = cap;
+}
+
+/// Moves to the next cap and returns it
+pub fn cycle_fps_cap() -> FpsCap {
+    let all = FpsCap::ALL;
+    let next = all[(all.iter().position(|&cap| cap == fps_cap()).unwrap_or(0) + 1) % all.len()];
+    set_fps_cap(next);
+    next
+}
+
+/// Source of time for a pacer, so tests can run one on a fake clock
+pub trait Clock {
+    fn now(&mut self) -> Instant;
+    fn sleep(&mut self, duration: Duration);
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&mut self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+/// How evenly recent frames were spaced
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PacingStats {
+    pub achieved_fps: f32,
+    /// Mean time frames started after their deadline, in milliseconds
+    pub mean_error_ms: f32,
+    /// Standard deviation of the time between frames, in milliseconds
+    pub jitter_ms: f32,
+}
+
+pub struct FramePacer {
+    interval: Option<Duration>,
+    deadline: Option<Instant>,
+    /// Recent frame starts and how late each one was
+    frames: VecDeque<(Instant, Duration)>,
+}
+
+impl Default for FramePacer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FramePacer {
+    pub fn new() -> Self {
+        Self {
+            interval: None,
+            deadline: None,
+            frames: VecDeque::with_capacity(STATS_FRAMES),
+        }
+    }
+
+    pub fn interval(&self) -> Option<Duration> {
+        self.interval
+    }
+
+    /// Changes the time between frames, or None for no limit. The next frame
+    /// comes one new interval after the last one, so switching never sends a
+    /// burst of frames to catch up.
+    pub fn set_interval(&mut self, interval: Option<Duration>) {
+        if interval == self.interval {
+            return;
+        }
+        self.interval = interval;
+        let last = self.frames.back().map(|&(start, _)| start);
+        self.deadline = interval.zip(last).map(|(interval, last)| last + interval);
+        self.frames.clear();
+    }
+
+    /// When the next frame should start, or None when frames aren't limited
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// When to wake up for the next frame, leaving the last stretch to spin
+    pub fn wake_at(&self) -> Option<Instant> {
+        self.deadline
+            .map(|deadline| deadline.checked_sub(SPIN_MARGIN).unwrap_or(deadline))
+    }
+
+    pub fn is_due(&self, now: Instant) -> bool {
+        self.wake_at().is_none_or(|wake| now >= wake)
+    }
+
+    /// Waits until the deadline: sleeps most of the way, then spins
+    pub fn wait(&self, clock: &mut impl Clock) {
+        let Some(deadline) = self.deadline else {
+            return;
+        };
+        let left = deadline.saturating_duration_since(clock.now());
+        if left > SPIN_MARGIN {
+            clock.sleep(left - SPIN_MARGIN);
+        }
+        while clock.now() < deadline {
+            std::hint::spin_loop();
+        }
+    }
+
+    /// Records a frame starting at `now` and sets the next deadline
+    pub fn frame_started(&mut self, now: Instant) {
+        let late = self.deadline.map_or(Duration::ZERO, |deadline| {
+            now.saturating_duration_since(deadline)
+        });
+        if self.frames.len() == STATS_FRAMES {
+            self.frames.pop_front();
+        }
+        self.frames.push_back((now, late));
+        self.deadline = self.interval.map(|interval| {
+            let next = self.deadline.unwrap_or(now) + interval;
+            // A frame more than an interval late starts the grid over instead
+            // of rushing the next ones
+            if next <= now {
+                now + interval
+            } else {
+                next
+            }
+        });
+    }
+
+    pub fn stats(&self) -> Option<PacingStats> {
+        let (&(first, _), &(last, _)) = (self.frames.front()?, self.frames.back()?);
+        let span = last.duration_since(first).as_secs_f32();
+        if span <= 0.0 {
+            return None;
+        }
+        let gaps: Vec<f32> = self
+            .frames
+            .iter()
+            .zip(self.frames.iter().skip(1))
+            .map(|(&(a, _), &(b, _))| b.duration_since(a).as_secs_f32() * 1000.0)
+            .collect();
+        let mean_gap = gaps.iter().sum::<f32>() / gaps.len() as f32;
+        let variance =
+            gaps.iter().map(|gap| (gap - mean_gap).powi(2)).sum::<f32>() / gaps.len() as f32;
+        let total_late: Duration = self.frames.iter().map(|&(_, late)| late).sum();
+        Some(PacingStats {
+            achieved_fps: gaps.len() as f32 / span,
+            mean_error_ms: total_late.as_secs_f32() * 1000.0 / self.frames.len() as f32,
+            jitter_ms: variance.sqrt(),
+        })
+    }
+}
+
+/// What the window's pacer last reported, for the debug overlay
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PacingReport {
+    pub cap: FpsCap,
+    /// Refresh rate of the window's monitor, when the platform knows it
+    pub refresh_hz: Option<f32>,
+    pub stats: PacingStats,
+}
+
+pub fn publish_report(report: Option<PacingReport>) {
+    *REPORT.lock().unwrap_or_else(PoisonError::into_inner) = report;
+}
+
+/// The debug overlay's pacing line; None when nothing is paced, e.g. embedded
+pub fn report_line() -> Option<String> {
+    let report = (*REPORT.lock().unwrap_or_else(PoisonError::into_inner))?;
+    let target = match report.refresh_hz {
+        Some(hz) if report.cap.uses_vsync() => format!("{} ({:.0} Hz)", report.cap.label(), hz),
+        _ => report.cap.label(),
+    };
+    Some(format!(
+        "Frame rate: target {}, achieved {:.1} fps, pacing error {:.2} ms, jitter {:.2} ms",
+        target, report.stats.achieved_fps, report.stats.mean_error_ms, report.stats.jitter_ms
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    /// Sleeps overshoot by most of the spin margin and every reading of the
+    /// clock costs a little time
+    struct FakeClock {
+        now: Instant,
+        rng: StdRng,
+    }
+
+    impl FakeClock {
+        fn new() -> Self {
+            Self {
+                now: Instant::now(),
+                rng: StdRng::seed_from_u64(7),
+            }
+        }
+
+        fn work(&mut self, millis: std::ops::Range<f32>) {
+            self.now += Duration::from_secs_f32(self.rng.gen_range(millis) / 1000.0);
+        }
+    }
+
+    impl Clock for FakeClock {
+        fn now(&mut self) -> Instant {
+            self.now += Duration::from_micros(20);
+            self.now
+        }
+
+        fn sleep(&mut self, duration: Duration) {
+            self.now += duration;
+            self.work(0.0..0.9);
+        }
+    }
+
+    /// Runs `frames` frames through the pacer and returns their start times
+    fn run(pacer: &mut FramePacer, clock: &mut FakeClock, frames: usize) -> Vec<Instant> {
+        (0..frames)
+            .map(|_| {
+                pacer.wait(clock);
+                let start = clock.now();
+                pacer.frame_started(start);
+                // Drawing takes a few milliseconds
+                clock.work(2.0..8.0);
+                start
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_a_30fps_cap_holds_with_even_spacing() {
+        let mut clock = FakeClock::new();
+        let mut pacer = FramePacer::new();
+        pacer.set_interval(FpsCap::Fps30.interval());
+        let starts = run(&mut pacer, &mut clock, 300);
+
+        let stats = pacer.stats().unwrap();
+        assert!((stats.achieved_fps - 30.0).abs() <= 1.0, "{:?}", stats);
+        assert!(stats.mean_error_ms < 0.1, "{:?}", stats);
+        assert!(stats.jitter_ms < 0.1, "{:?}", stats);
+        for pair in starts.windows(2) {
+            let gap = (pair[1] - pair[0]).as_secs_f32() * 1000.0;
+            assert!((gap - 1000.0 / 30.0).abs() < 0.1, "{} ms", gap);
+        }
+        // Ten seconds of frames, not drifting behind the wall clock
+        let elapsed = (starts[299] - starts[0]).as_secs_f32();
+        assert!((elapsed - 299.0 / 30.0).abs() < 0.01, "{}", elapsed);
+    }
+
+    #[test]
+    fn test_switching_caps_or_stalling_never_bursts() {
+        let mut clock = FakeClock::new();
+        let mut pacer = FramePacer::new();
+        pacer.set_interval(FpsCap::Fps30.interval());
+        let before = run(&mut pacer, &mut clock, 30);
+
+        // Spends most of an interval before the switch, then speeds up
+        clock.work(20.0..20.1);
+        pacer.set_interval(FpsCap::Fps120.interval());
+        let after = run(&mut pacer, &mut clock, 120);
+        let first_gap = after[0] - *before.last().unwrap();
+        assert!(first_gap >= Duration::from_millis(8), "{:?}", first_gap);
+        for pair in after.windows(2) {
+            assert!(pair[1] - pair[0] >= Duration::from_secs(1) / 120 - SPIN_MARGIN);
+        }
+        assert!((pacer.stats().unwrap().achieved_fps - 120.0).abs() <= 1.0);
+
+        // A frame that hangs for a quarter second is followed by evenly spaced
+        // frames, not a rush to make up the missed ones
+        clock.work(250.0..250.1);
+        let resumed = run(&mut pacer, &mut clock, 10);
+        for pair in resumed.windows(2) {
+            assert!(pair[1] - pair[0] >= Duration::from_secs(1) / 120 - SPIN_MARGIN);
+        }
+
+        // Uncapped frames don't wait at all
+        pacer.set_interval(None);
+        assert_eq!(pacer.deadline(), None);
+        assert!(pacer.is_due(clock.now()));
+    }
+}
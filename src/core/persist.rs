@@ -0,0 +1,316 @@
+//! Keeps what the user built in a scene (painted strokes, force settings, how
+//! the sorters look) across scene switches and restarts. Each scene with
+//! something worth keeping stores a JSON blob in
+//! `<config dir>/stimstation/scenes/<id>.json`, written when the scene is left
+//! and on exit, and read the first time the scene becomes active.
+
+use crate::core::scenes::{self, SceneInfo};
+use log::{info, warn};
+use serde_json::{json, Value};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Bumped whenever a stored field changes meaning. Older and newer blobs are
+/// still read: unknown fields are ignored and missing ones keep their defaults.
+pub const SCENE_STATE_VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum PersistError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    /// The blob parsed but doesn't have the expected shape
+    Invalid(&'static str),
+}
+
+impl fmt::Display for PersistError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PersistError::Io(e) => write!(f, "I/O error: {}", e),
+            PersistError::Json(e) => write!(f, "not valid JSON: {}", e),
+            PersistError::Invalid(reason) => write!(f, "unexpected contents: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for PersistError {}
+
+impl From<io::Error> for PersistError {
+    fn from(e: io::Error) -> Self {
+        PersistError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for PersistError {
+    fn from(e: serde_json::Error) -> Self {
+        PersistError::Json(e)
+    }
+}
+
+/// Implemented by whatever holds a scene's interactive artifacts
+pub trait PersistentState {
+    fn save_state(&self) -> Value;
+
+    /// Replaces the current artifacts with the ones in `blob`. A blob of the
+    /// wrong shape is an error and leaves `self` as it was.
+    fn load_state(&mut self, blob: &Value) -> Result<(), PersistError>;
+}
+
+/// How a scene saves, restores, and clears its artifacts
+#[derive(Clone, Copy)]
+pub struct SceneState {
+    pub save: fn() -> Value,
+    pub load: fn(&Value) -> Result<(), PersistError>,
+    pub reset: fn(),
+}
+
+/// Reads an `[x, y]` pair
+pub fn point_from_json(value: &Value) -> Option<(f32, f32)> {
+    match value.as_array()?.as_slice() {
+        [x, y] => Some((x.as_f64()? as f32, y.as_f64()? as f32)),
+        _ => None,
+    }
+}
+
+pub fn blob_path(dir: &Path, id: &str) -> PathBuf {
+    dir.join(format!("{}.json", id))
+}
+
+/// Writes `state` for scene `id`, wrapped with the schema version
+pub fn write_blob(dir: &Path, id: &str, state: &Value) -> Result<(), PersistError> {
+    fs::create_dir_all(dir)?;
+    let blob = json!({ "version": SCENE_STATE_VERSION, "state": state });
+    fs::write(blob_path(dir, id), serde_json::to_string_pretty(&blob)?)?;
+    Ok(())
+}
+
+/// Reads the stored state for scene `id`, or None when nothing was stored
+pub fn read_blob(dir: &Path, id: &str) -> Result<Option<Value>, PersistError> {
+    let text = match fs::read_to_string(blob_path(dir, id)) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let mut blob: Value = serde_json::from_str(&text)?;
+    match blob.get_mut("state") {
+        Some(state) => Ok(Some(state.take())),
+        None => Err(PersistError::Invalid("no `state` field")),
+    }
+}
+
+pub fn remove_blob(dir: &Path, id: &str) -> Result<(), PersistError> {
+    match fs::remove_file(blob_path(dir, id)) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// `<config dir>/stimstation/scenes`
+pub fn scene_state_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("stimstation").join("scenes"))
+}
+
+struct Session {
+    dir: PathBuf,
+    active: Option<&'static str>,
+    /// Scenes restored this session; only these are saved, so a scene that was
+    /// never shown can't overwrite its stored blob with an empty one
+    restored: Vec<&'static str>,
+}
+
+static SESSION: Mutex<Option<Session>> = Mutex::new(None);
+
+fn session() -> MutexGuard<'static, Option<Session>> {
+    SESSION.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Turns on scene persistence in `dir`. Off by default, so headless rendering
+/// never reads or writes the user's scenes.
+pub fn enable(dir: PathBuf) {
+    *session() = Some(Session {
+        dir,
+        active: None,
+        restored: Vec::new(),
+    });
+}
+
+/// Turns on scene persistence in the config directory, if there is one
+pub fn enable_in_config_dir() {
+    if let Some(dir) = scene_state_dir() {
+        enable(dir);
+    }
+}
+
+fn save_scene(dir: &Path, scene: &SceneInfo) {
+    let Some(state) = scene.state else {
+        return;
+    };
+    if let Err(e) = write_blob(dir, scene.id, &(state.save)()) {
+        warn!("Could not save {} scene: {}", scene.id, e);
+    }
+}
+
+fn restore_scene(dir: &Path, scene: &SceneInfo) {
+    let Some(state) = scene.state else {
+        return;
+    };
+    match read_blob(dir, scene.id).and_then(|blob| blob.map(|b| (state.load)(&b)).transpose()) {
+        Ok(Some(())) => info!("Restored {} scene", scene.id),
+        Ok(None) => {}
+        Err(e) => warn!("Ignoring stored {} scene, starting fresh: {}", scene.id, e),
+    }
+}
+
+/// Called every frame with the scene on screen. Saves the scene being left
+/// and restores one shown for the first time this session.
+pub fn track_active_scene(scene: &'static SceneInfo) {
+    let mut slot = session();
+    let Some(session) = slot.as_mut() else {
+        return;
+    };
+    if session.active == Some(scene.id) {
+        return;
+    }
+    if let Some(previous) = session.active.and_then(scenes::find_scene) {
+        save_scene(&session.dir, previous);
+    }
+    if !session.restored.contains(&scene.id) {
+        restore_scene(&session.dir, scene);
+        session.restored.push(scene.id);
+    }
+    session.active = Some(scene.id);
+}
+
+/// Saves every scene restored this session; called on exit
+pub fn save_scenes() {
+    let slot = session();
+    let Some(session) = slot.as_ref() else {
+        return;
+    };
+    for scene in session
+        .restored
+        .iter()
+        .filter_map(|id| scenes::find_scene(id))
+    {
+        save_scene(&session.dir, scene);
+    }
+}
+
+/// Clears the artifacts of the scene on screen and forgets its stored blob
+pub fn reset_active_scene() {
+    let scene = scenes::active_scene();
+    let Some(state) = scene.state else {
+        return;
+    };
+    (state.reset)();
+    if let Some(session) = session().as_ref() {
+        if let Err(e) = remove_blob(&session.dir, scene.id) {
+            warn!("Could not remove stored {} scene: {}", scene.id, e);
+        }
+    }
+    info!("Reset {} scene", scene.id);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::sorter::SorterColorMode;
+    use crate::algorithms::sorter_manager::SorterLook;
+    use crate::core::types::{Color, Line, Position};
+    use crate::physics::drawing::DrawingLayer;
+    use crate::physics::forces::{ForceField, GravityMode};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("stimstation-{}-{}", name, std::process::id()))
+    }
+
+    fn line(x: f32) -> Line {
+        let mut line = Line::new(&mut rand::thread_rng());
+        line.pos = [Position::new(x, 10.0), Position::new(x + 5.0, 40.0)];
+        line.color = Color::new(10, 200, 30);
+        line.width = 3.0;
+        line
+    }
+
+    #[test]
+    fn test_world_artifacts_round_trip() {
+        let dir = temp_dir("world-state");
+        let mut drawing = DrawingLayer::new();
+        drawing.add_line(line(10.0));
+        drawing.add_line(line(20.0));
+        drawing.end_stroke();
+        drawing.add_line(line(50.0));
+        drawing.end_stroke();
+        let field = ForceField {
+            gravity: GravityMode::Up,
+            wind: true,
+            ..ForceField::default()
+        };
+        let state = json!({ "strokes": drawing.save_state(), "forces": field.save_state() });
+        write_blob(&dir, "world", &state).unwrap();
+
+        let blob = read_blob(&dir, "world").unwrap().unwrap();
+        let _ = fs::remove_dir_all(&dir);
+        let mut loaded_drawing = DrawingLayer::new();
+        loaded_drawing.load_state(&blob["strokes"]).unwrap();
+        let mut loaded_field = ForceField::default();
+        loaded_field.load_state(&blob["forces"]).unwrap();
+
+        assert_eq!(loaded_field, field);
+        assert_eq!(loaded_drawing.stroke_count(), 2);
+        let first = &loaded_drawing.strokes()[0][1];
+        assert_eq!(first.pos, line(20.0).pos);
+        assert_eq!(first.color, Color::new(10, 200, 30));
+        assert_eq!(first.width, 3.0);
+    }
+
+    #[test]
+    fn test_sorter_look_round_trips() {
+        let dir = temp_dir("rays-state");
+        let look = SorterLook {
+            color_mode: SorterColorMode::Thermal,
+            captions: true,
+        };
+        write_blob(&dir, "rays", &look.save_state()).unwrap();
+        let blob = read_blob(&dir, "rays").unwrap().unwrap();
+        let _ = fs::remove_dir_all(&dir);
+
+        let mut loaded = SorterLook::default();
+        loaded.load_state(&blob).unwrap();
+        assert_eq!(loaded, look);
+    }
+
+    #[test]
+    fn test_missing_and_corrupt_blobs() {
+        let dir = temp_dir("corrupt-state");
+        assert!(read_blob(&dir, "world").unwrap().is_none());
+        remove_blob(&dir, "world").unwrap();
+
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(blob_path(&dir, "world"), "{ not json").unwrap();
+        assert!(matches!(
+            read_blob(&dir, "world"),
+            Err(PersistError::Json(_))
+        ));
+        fs::write(blob_path(&dir, "world"), "{\"version\": 1}").unwrap();
+        assert!(read_blob(&dir, "world").is_err());
+        let _ = fs::remove_dir_all(&dir);
+
+        // A blob of the wrong shape is rejected and leaves the state alone
+        let mut drawing = DrawingLayer::new();
+        drawing.add_line(line(10.0));
+        drawing.end_stroke();
+        assert!(drawing.load_state(&json!([[{ "from": [1.0] }]])).is_err());
+        assert!(drawing.load_state(&json!({ "strokes": 3 })).is_err());
+        assert_eq!(drawing.stroke_count(), 1);
+
+        // Unknown fields from a newer version are ignored, missing ones default
+        let mut look = SorterLook::default();
+        look.load_state(&json!({ "captions": true, "sparkles": 11 }))
+            .unwrap();
+        assert_eq!(look.color_mode, SorterColorMode::Flat);
+        assert!(look.captions);
+    }
+}
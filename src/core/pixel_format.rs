@@ -0,0 +1,199 @@
+//! Channel order of the frames handed to the screen and other outputs.
+//! Everything draws RGBA; when a target wants another order, one swizzle
+//! pass over the finished frame converts it on the way out.
+
+use pixels::wgpu::TextureFormat;
+use std::sync::{Mutex, PoisonError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PixelFormat {
+    #[default]
+    Rgba8,
+    Bgra8,
+}
+
+impl PixelFormat {
+    pub const ALL: [PixelFormat; 2] = [PixelFormat::Rgba8, PixelFormat::Bgra8];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PixelFormat::Rgba8 => "rgba",
+            PixelFormat::Bgra8 => "bgra",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|format| format.name() == name)
+    }
+
+    pub fn other(&self) -> Self {
+        match self {
+            PixelFormat::Rgba8 => PixelFormat::Bgra8,
+            PixelFormat::Bgra8 => PixelFormat::Rgba8,
+        }
+    }
+
+    /// The order that spares a surface in `format` converting every frame
+    pub fn for_surface(format: TextureFormat) -> Self {
+        match format {
+            TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb => PixelFormat::Bgra8,
+            _ => PixelFormat::Rgba8,
+        }
+    }
+
+    /// Pixel buffer texture in this order, sRGB like the pixels crate's default
+    pub fn texture_format(&self) -> TextureFormat {
+        match self {
+            PixelFormat::Rgba8 => TextureFormat::Rgba8UnormSrgb,
+            PixelFormat::Bgra8 => TextureFormat::Bgra8UnormSrgb,
+        }
+    }
+
+    /// Converts an RGBA frame to this order in place. Swapping red and blue
+    /// is its own inverse, so this also turns BGRA back into RGBA.
+    pub fn swizzle(&self, frame: &mut [u8]) {
+        if *self == PixelFormat::Rgba8 {
+            return;
+        }
+        for pixel in frame.chunks_exact_mut(4) {
+            let word = u32::from_le_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]);
+            pixel.copy_from_slice(&swap_red_blue(word).to_le_bytes());
+        }
+    }
+
+    /// Writes the RGBA frame `rgba` to `out` in this order; `out` must be the same length
+    pub fn convert(&self, rgba: &[u8], out: &mut [u8]) {
+        match self {
+            PixelFormat::Rgba8 => out.copy_from_slice(rgba),
+            PixelFormat::Bgra8 => {
+                assert_eq!(rgba.len(), out.len(), "frames differ in size");
+                for (src, dst) in rgba.chunks_exact(4).zip(out.chunks_exact_mut(4)) {
+                    let word = u32::from_le_bytes([src[0], src[1], src[2], src[3]]);
+                    dst.copy_from_slice(&swap_red_blue(word).to_le_bytes());
+                }
+            }
+        }
+    }
+}
+
+/// Branch-free on whole pixels, so the loops above vectorize
+#[inline(always)]
+fn swap_red_blue(word: u32) -> u32 {
+    (word & 0xff00_ff00) | ((word >> 16) & 0xff) | ((word & 0xff) << 16)
+}
+
+/// Set by `--pixel-format` or F9; None follows the surface
+static REQUESTED_FORMAT: Mutex<Option<PixelFormat>> = Mutex::new(None);
+/// What the window's pixel buffer currently takes
+static PRESENT_FORMAT: Mutex<PixelFormat> = Mutex::new(PixelFormat::Rgba8);
+
+pub fn requested_format() -> Option<PixelFormat> {
+    *REQUESTED_FORMAT
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+pub fn set_requested_format(format: Option<PixelFormat>) {
+    *REQUESTED_FORMAT
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner) = format;
+}
+
+pub fn present_format() -> PixelFormat {
+    *PRESENT_FORMAT
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Called by whoever builds the pixel buffer, once it takes `format`
+pub fn set_present_format(format: PixelFormat) {
+    *PRESENT_FORMAT
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner) = format;
+}
+
+/// Asks for the order the window isn't using and returns it. The window
+/// switches when its pixel buffer is next rebuilt.
+pub fn toggle_requested_format() -> PixelFormat {
+    let format = present_format().other();
+    set_requested_format(Some(format));
+    format
+}
+
+/// The order to present in on a surface that prefers `surface`
+pub fn resolve(surface: TextureFormat) -> PixelFormat {
+    requested_format().unwrap_or(PixelFormat::for_surface(surface))
+}
+
+/// Whether the window's pixel buffer needs rebuilding for a requested order
+pub fn switch_pending() -> bool {
+    requested_format().is_some_and(|format| format != present_format())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Red, green, blue, and white at decreasing alpha, then a gradient
+    fn pattern() -> Vec<u8> {
+        let mut frame = vec![
+            255, 0, 0, 255, //
+            0, 255, 0, 200, //
+            0, 0, 255, 100, //
+            255, 255, 255, 0,
+        ];
+        frame.extend((0..64u8).flat_map(|i| [i, i * 2, i * 3, 255 - i]));
+        frame
+    }
+
+    #[test]
+    fn test_bgra_swaps_red_and_blue_only() {
+        let rgba = pattern();
+        let mut bgra = vec![0; rgba.len()];
+        PixelFormat::Bgra8.convert(&rgba, &mut bgra);
+        assert_eq!(
+            &bgra[..16],
+            &[0, 0, 255, 255, 0, 255, 0, 200, 255, 0, 0, 100, 255, 255, 255, 0]
+        );
+        for (src, dst) in rgba.chunks_exact(4).zip(bgra.chunks_exact(4)) {
+            assert_eq!(
+                [dst[2], dst[1], dst[0], dst[3]],
+                [src[0], src[1], src[2], src[3]]
+            );
+        }
+
+        // In place gives the same bytes, and a second pass restores the original
+        let mut frame = rgba.clone();
+        PixelFormat::Bgra8.swizzle(&mut frame);
+        assert_eq!(frame, bgra);
+        PixelFormat::Bgra8.swizzle(&mut frame);
+        assert_eq!(frame, rgba);
+    }
+
+    #[test]
+    fn test_rgba_output_is_a_copy() {
+        let rgba = pattern();
+        let mut out = vec![0; rgba.len()];
+        PixelFormat::Rgba8.convert(&rgba, &mut out);
+        assert_eq!(out, rgba);
+        PixelFormat::Rgba8.swizzle(&mut out);
+        assert_eq!(out, rgba);
+    }
+
+    #[test]
+    fn test_format_follows_the_surface() {
+        assert_eq!(
+            PixelFormat::for_surface(TextureFormat::Bgra8UnormSrgb),
+            PixelFormat::Bgra8
+        );
+        assert_eq!(
+            PixelFormat::for_surface(TextureFormat::Rgba16Float),
+            PixelFormat::Rgba8
+        );
+        for format in PixelFormat::ALL {
+            assert_eq!(PixelFormat::from_name(format.name()), Some(format));
+            assert_eq!(PixelFormat::for_surface(format.texture_format()), format);
+        }
+        assert_eq!(PixelFormat::from_name("argb"), None);
+    }
+}
@@ -0,0 +1,311 @@
+//! Presets: a scene plus the tunables that decide how it looks and reacts.
+//! There are four slots, seeded with built-in looks, and `morph` carries the
+//! app from one preset to another over time. Numbers blend continuously;
+//! choices that can't blend (scene, filter, palette) switch at the midpoint.
+
+use crate::algorithms::sorter::SorterColorMode;
+use crate::algorithms::sorter_manager;
+use crate::audio::audio_handler::{self, BarEnvelope};
+use crate::core::scenes;
+use crate::core::types::Velocity;
+use crate::graphics::post::{self, ColorFilter, PostSettings};
+use crate::physics::forces::{self, ForceField, GravityMode};
+use crate::physics::world::{self, AudioWidthSettings};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Length of a morph started from the keyboard, in seconds
+pub const MORPH_SECONDS: f32 = 10.0;
+pub const SLOT_COUNT: usize = 4;
+
+/// Implemented by the parts of a preset so a morph can blend them
+pub trait Interpolate {
+    /// The value `t` of the way from `self` to `to`; `t` runs 0.0..=1.0
+    fn interpolate(&self, to: &Self, t: f32) -> Self;
+}
+
+pub fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+/// Angle in degrees `t` of the way from `a` to `b`, going the short way round
+pub fn lerp_degrees(a: f32, b: f32, t: f32) -> f32 {
+    let delta = (b - a + 540.0).rem_euclid(360.0) - 180.0;
+    (a + delta * t).rem_euclid(360.0)
+}
+
+/// `a` before the midpoint and `b` from it on, for values that can't blend
+pub fn switch_at_midpoint<T: Copy>(a: T, b: T, t: f32) -> T {
+    if t < 0.5 {
+        a
+    } else {
+        b
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Preset {
+    pub name: &'static str,
+    /// Id of the scene it shows
+    pub scene: &'static str,
+    pub post: PostSettings,
+    pub bar_envelope: BarEnvelope,
+    pub forces: ForceField,
+    pub audio_width: AudioWidthSettings,
+    pub sorter_colors: SorterColorMode,
+}
+
+impl Preset {
+    /// The look currently in effect
+    pub fn capture(name: &'static str) -> Self {
+        Self {
+            name,
+            scene: scenes::active_scene().id,
+            post: post::post_settings(),
+            bar_envelope: audio_handler::bar_envelope(),
+            forces: ForceField {
+                gust: Velocity::ZERO,
+                ..forces::force_field()
+            },
+            audio_width: world::audio_width_settings(),
+            sorter_colors: sorter_manager::get_sorter_color_mode(),
+        }
+    }
+
+    /// Makes this look the one in effect. A drag gust still fading is kept.
+    pub fn apply(&self) {
+        if scenes::active_scene().id != self.scene {
+            if let Some(scene) = scenes::find_scene(self.scene) {
+                scene.enter();
+            }
+        }
+        post::set_post_settings(self.post);
+        audio_handler::set_bar_envelope(self.bar_envelope);
+        forces::set_force_field(ForceField {
+            gust: forces::force_field().gust,
+            ..self.forces
+        });
+        world::set_audio_width_settings(self.audio_width);
+        if sorter_manager::get_sorter_color_mode() != self.sorter_colors {
+            sorter_manager::set_sorter_color_mode(self.sorter_colors);
+        }
+    }
+}
+
+impl Interpolate for Preset {
+    fn interpolate(&self, to: &Self, t: f32) -> Self {
+        Self {
+            name: switch_at_midpoint(self.name, to.name, t),
+            scene: switch_at_midpoint(self.scene, to.scene, t),
+            post: self.post.interpolate(&to.post, t),
+            bar_envelope: self.bar_envelope.interpolate(&to.bar_envelope, t),
+            forces: self.forces.interpolate(&to.forces, t),
+            audio_width: self.audio_width.interpolate(&to.audio_width, t),
+            sorter_colors: switch_at_midpoint(self.sorter_colors, to.sorter_colors, t),
+        }
+    }
+}
+
+/// What the slots hold until the user stores over them
+pub const BUILT_IN: [Preset; SLOT_COUNT] = [
+    Preset {
+        name: "Calm",
+        scene: "rays",
+        post: PostSettings::DEFAULT,
+        bar_envelope: BarEnvelope::DEFAULT,
+        forces: ForceField {
+            gravity: GravityMode::Off,
+            wind: false,
+            gust: Velocity::ZERO,
+        },
+        audio_width: AudioWidthSettings::DEFAULT,
+        sorter_colors: SorterColorMode::Flat,
+    },
+    Preset {
+        name: "Sunset",
+        scene: "softbody",
+        post: PostSettings {
+            filter: ColorFilter::HueShift,
+            hue_shift: 320.0,
+            hue_lfo: false,
+        },
+        bar_envelope: BarEnvelope {
+            attack: 0.08,
+            release: 0.6,
+        },
+        forces: ForceField {
+            gravity: GravityMode::Off,
+            wind: false,
+            gust: Velocity::ZERO,
+        },
+        audio_width: AudioWidthSettings::DEFAULT,
+        sorter_colors: SorterColorMode::ValueHue,
+    },
+    Preset {
+        name: "Storm",
+        scene: "world",
+        post: PostSettings {
+            filter: ColorFilter::HueShift,
+            hue_shift: 200.0,
+            hue_lfo: false,
+        },
+        bar_envelope: BarEnvelope {
+            attack: 0.01,
+            release: 0.15,
+        },
+        forces: ForceField {
+            gravity: GravityMode::Down,
+            wind: true,
+            gust: Velocity::ZERO,
+        },
+        audio_width: AudioWidthSettings {
+            enabled: true,
+            sensitivity: 3.0,
+        },
+        sorter_colors: SorterColorMode::Thermal,
+    },
+    Preset {
+        name: "Drift",
+        scene: "tunnel",
+        post: PostSettings {
+            filter: ColorFilter::HueShift,
+            hue_shift: 120.0,
+            hue_lfo: true,
+        },
+        bar_envelope: BarEnvelope {
+            attack: 0.2,
+            release: 1.2,
+        },
+        forces: ForceField {
+            gravity: GravityMode::Off,
+            wind: false,
+            gust: Velocity::ZERO,
+        },
+        audio_width: AudioWidthSettings {
+            enabled: true,
+            sensitivity: 0.75,
+        },
+        sorter_colors: SorterColorMode::ValueHue,
+    },
+];
+
+struct Morph {
+    from: Preset,
+    to: Preset,
+    seconds: f32,
+    /// Frame time the morph began; set by the first `update` after it was started
+    started: Option<f32>,
+}
+
+static SLOTS: Mutex<[Preset; SLOT_COUNT]> = Mutex::new(BUILT_IN);
+/// Slot last morphed to, which Ctrl+',' stores over
+static CURRENT_SLOT: AtomicUsize = AtomicUsize::new(0);
+static MORPH: Mutex<Option<Morph>> = Mutex::new(None);
+
+fn slots() -> MutexGuard<'static, [Preset; SLOT_COUNT]> {
+    SLOTS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn running_morph() -> MutexGuard<'static, Option<Morph>> {
+    MORPH.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Blends from `a` to `b` over `seconds`, starting with the next frame.
+/// Replaces any morph already running.
+pub fn morph(a: &Preset, b: &Preset, seconds: f32) {
+    *running_morph() = Some(Morph {
+        from: *a,
+        to: *b,
+        seconds,
+        started: None,
+    });
+}
+
+pub fn is_morphing() -> bool {
+    running_morph().is_some()
+}
+
+/// Stops the running morph, leaving the look wherever it has got to
+pub fn stop_morph() {
+    *running_morph() = None;
+}
+
+/// Morphs from the current look to the next slot's over `MORPH_SECONDS`
+/// and returns that preset
+pub fn morph_to_next_slot() -> Preset {
+    let slot = (CURRENT_SLOT.load(Ordering::Relaxed) + 1) % SLOT_COUNT;
+    CURRENT_SLOT.store(slot, Ordering::Relaxed);
+    let to = slots()[slot];
+    morph(&Preset::capture("Current"), &to, MORPH_SECONDS);
+    to
+}
+
+/// Stores the current look in the current slot and returns its index
+pub fn store_current_slot() -> usize {
+    let slot = CURRENT_SLOT.load(Ordering::Relaxed);
+    slots()[slot] = Preset::capture(BUILT_IN[slot].name);
+    slot
+}
+
+/// Applies the running morph's look for frame time `time`; called once a frame
+pub fn update(time: f32) {
+    let look = {
+        let mut slot = running_morph();
+        let Some(morph) = slot.as_mut() else {
+            return;
+        };
+        let started = *morph.started.get_or_insert(time);
+        let t = if morph.seconds > 0.0 {
+            ((time - started) / morph.seconds).clamp(0.0, 1.0)
+        } else {
+            1.0
+        };
+        let look = morph.from.interpolate(&morph.to, t);
+        if t >= 1.0 {
+            *slot = None;
+        }
+        look
+    };
+    look.apply();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_morph_starts_and_ends_on_its_presets() {
+        let [calm, _, storm, _] = BUILT_IN;
+        assert_eq!(calm.interpolate(&storm, 0.0), calm);
+        assert_eq!(calm.interpolate(&storm, 1.0), storm);
+
+        let quarter = calm.interpolate(&storm, 0.25);
+        assert!((quarter.bar_envelope.release - 0.225).abs() < 1e-6);
+        assert!((quarter.audio_width.sensitivity - 1.875).abs() < 1e-6);
+        // Hue goes the short way round: 30 to 200 passes 72.5, not 317.5
+        assert!((quarter.post.hue_shift - 72.5).abs() < 1e-4);
+        assert!((lerp_degrees(350.0, 10.0, 0.5)).abs() < 1e-4);
+        assert!((lerp_degrees(10.0, 350.0, 0.25) - 5.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_discrete_values_switch_at_the_midpoint() {
+        let [calm, _, storm, _] = BUILT_IN;
+        let before = calm.interpolate(&storm, 0.49);
+        let after = calm.interpolate(&storm, 0.5);
+        assert_eq!(
+            (before.scene, before.post.filter, before.sorter_colors),
+            ("rays", ColorFilter::None, SorterColorMode::Flat)
+        );
+        assert_eq!(
+            (after.scene, after.post.filter, after.sorter_colors),
+            ("world", ColorFilter::HueShift, SorterColorMode::Thermal)
+        );
+        assert_eq!(
+            (before.forces.gravity, after.forces.gravity),
+            (GravityMode::Off, GravityMode::Down)
+        );
+        // Numbers keep blending across the switch rather than jumping with it
+        assert!(after.bar_envelope.release - before.bar_envelope.release < 0.01);
+    }
+}
@@ -0,0 +1,368 @@
+//! Low-bandwidth live preview served over HTTP as MJPEG, for watching the
+//! visualization from a browser on the LAN. Only built with the
+//! `preview-server` feature.
+//!
+//! The render loop only downscales every Nth frame and offers it to a bounded
+//! channel; a worker thread does the tile diffing and JPEG encoding, and
+//! frames are dropped rather than queued when the worker falls behind.
+
+use crate::core::bufpool::{self, PooledBuf};
+use image::codecs::jpeg::JpegEncoder;
+use image::ExtendedColorType;
+use log::{info, warn};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+pub const PREVIEW_PORT: u16 = 9901;
+/// Offer every Nth rendered frame; 6 gives ~10 fps at 60 fps
+const FRAME_INTERVAL: u64 = 6;
+/// Width of the preview image; height follows the frame's aspect ratio
+const PREVIEW_WIDTH: u32 = 480;
+/// Frames waiting for the encoder; anything past this is dropped
+const CHANNEL_CAPACITY: usize = 2;
+const TILE_SIZE: u32 = 16;
+const JPEG_QUALITY: u8 = 70;
+const BOUNDARY: &str = "stimframe";
+
+const INDEX_HTML: &str = "<!doctype html><html><head><title>stimstation preview</title>\
+<style>body{margin:0;background:#000}img{width:100vw}</style></head>\
+<body><img src=\"/stream\"></body></html>";
+
+/// A downscaled RGB frame on its way to the encoder
+pub struct PreviewFrame {
+    pub width: u32,
+    pub height: u32,
+    /// Pooled, so frames dropped or encoded hand their memory back for reuse
+    pub rgb: PooledBuf,
+}
+
+/// Nearest-neighbour downscale of an RGBA frame region to `PREVIEW_WIDTH`, dropping alpha
+pub fn downscale(
+    frame: &[u8],
+    width: u32,
+    height: u32,
+    x_offset: usize,
+    buffer_width: u32,
+) -> PreviewFrame {
+    let out_width = PREVIEW_WIDTH.min(width).max(1);
+    let out_height = (height as u64 * out_width as u64 / width.max(1) as u64).max(1) as u32;
+    let mut rgb = bufpool::get_buffer("preview frame", (out_width * out_height * 3) as usize);
+    let mut pixels = rgb.chunks_exact_mut(3);
+    for y in 0..out_height {
+        let src_y = (y as u64 * height as u64 / out_height as u64) as usize;
+        for x in 0..out_width {
+            let src_x = (x as u64 * width as u64 / out_width as u64) as usize;
+            let idx = 4 * (src_y * buffer_width as usize + src_x + x_offset);
+            if let (Some(pixel), Some(src)) = (pixels.next(), frame.get(idx..idx + 3)) {
+                pixel.copy_from_slice(src);
+            }
+        }
+    }
+    PreviewFrame {
+        width: out_width,
+        height: out_height,
+        rgb,
+    }
+}
+
+/// Remembers a hash per tile so the encoder can tell which parts of a frame changed.
+pub struct TileHasher {
+    hashes: Vec<u64>,
+    size: (u32, u32),
+}
+
+impl TileHasher {
+    pub fn new() -> Self {
+        Self {
+            hashes: Vec::new(),
+            size: (0, 0),
+        }
+    }
+
+    /// Indices (row-major) of tiles whose contents differ from the previous call.
+    /// Every tile counts as changed on the first frame and after a size change.
+    pub fn changed_tiles(&mut self, frame: &PreviewFrame) -> Vec<usize> {
+        let cols = frame.width.div_ceil(TILE_SIZE);
+        let rows = frame.height.div_ceil(TILE_SIZE);
+        let hashes: Vec<u64> = (0..rows)
+            .flat_map(|row| (0..cols).map(move |col| (col, row)))
+            .map(|(col, row)| tile_hash(frame, col, row))
+            .collect();
+        let changed = if self.size != (frame.width, frame.height) {
+            (0..hashes.len()).collect()
+        } else {
+            (0..hashes.len())
+                .filter(|&i| hashes[i] != self.hashes[i])
+                .collect()
+        };
+        self.hashes = hashes;
+        self.size = (frame.width, frame.height);
+        changed
+    }
+}
+
+impl Default for TileHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// FNV-1a over the RGB bytes of one tile
+fn tile_hash(frame: &PreviewFrame, col: u32, row: u32) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let x0 = (col * TILE_SIZE) as usize;
+    let x1 = ((col + 1) * TILE_SIZE).min(frame.width) as usize;
+    for y in row * TILE_SIZE..((row + 1) * TILE_SIZE).min(frame.height) {
+        let start = 3 * (y as usize * frame.width as usize + x0);
+        let end = 3 * (y as usize * frame.width as usize + x1);
+        for &byte in &frame.rgb[start..end] {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    hash
+}
+
+/// Sending half of a bounded channel that never blocks: when the channel is
+/// full the new item is dropped and counted.
+pub struct DroppingSender<T> {
+    sender: SyncSender<T>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl<T> DroppingSender<T> {
+    pub fn new(capacity: usize) -> (Self, Receiver<T>) {
+        let (sender, receiver) = mpsc::sync_channel(capacity);
+        let sender = Self {
+            sender,
+            dropped: Arc::new(AtomicU64::new(0)),
+        };
+        (sender, receiver)
+    }
+
+    /// Queues `item` if there is room. Returns false if it was dropped.
+    pub fn offer(&self, item: T) -> bool {
+        match self.sender.try_send(item) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) | Err(TrySendError::Disconnected(_)) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                false
+            }
+        }
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// Latest encoded JPEG and a counter bumped on every new one, shared with the clients
+#[derive(Default)]
+struct LatestFrame {
+    jpeg: Mutex<(u64, Arc<Vec<u8>>)>,
+    updated: Condvar,
+}
+
+impl LatestFrame {
+    fn publish(&self, jpeg: Vec<u8>) {
+        if let Ok(mut latest) = self.jpeg.lock() {
+            *latest = (latest.0 + 1, Arc::new(jpeg));
+            self.updated.notify_all();
+        }
+    }
+
+    /// Blocks until a frame newer than `seen` exists, or the timeout passes
+    fn wait_newer(&self, seen: u64, timeout: Duration) -> Option<(u64, Arc<Vec<u8>>)> {
+        let latest = self.jpeg.lock().ok()?;
+        let (latest, _) = self
+            .updated
+            .wait_timeout_while(latest, timeout, |(seq, _)| *seq <= seen)
+            .ok()?;
+        (latest.0 > seen).then(|| (latest.0, latest.1.clone()))
+    }
+}
+
+/// Serves the preview page and MJPEG stream. Frames are fed through `submit`.
+pub struct PreviewServer {
+    sender: DroppingSender<PreviewFrame>,
+    frames_seen: u64,
+}
+
+impl PreviewServer {
+    /// Binds the HTTP listener on every interface and starts the encoder and accept threads
+    pub fn start(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        let latest = Arc::new(LatestFrame::default());
+        let (sender, receiver) = DroppingSender::new(CHANNEL_CAPACITY);
+
+        let encoder_latest = latest.clone();
+        thread::Builder::new()
+            .name("preview-encoder".into())
+            .spawn(move || run_encoder(receiver, &encoder_latest))?;
+        thread::Builder::new()
+            .name("preview-http".into())
+            .spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    let latest = latest.clone();
+                    thread::spawn(move || {
+                        if let Err(e) = serve_client(stream, &latest) {
+                            log::debug!("Preview client disconnected: {}", e);
+                        }
+                    });
+                }
+            })?;
+
+        info!("Preview server listening on http://localhost:{}", port);
+        Ok(Self {
+            sender,
+            frames_seen: 0,
+        })
+    }
+
+    /// Called once per rendered frame; every `FRAME_INTERVAL`th frame is downscaled
+    /// and offered to the encoder. Never blocks.
+    pub fn submit(
+        &mut self,
+        frame: &[u8],
+        width: u32,
+        height: u32,
+        x_offset: usize,
+        buffer_width: u32,
+    ) {
+        self.frames_seen += 1;
+        if !self.frames_seen.is_multiple_of(FRAME_INTERVAL) {
+            return;
+        }
+        self.sender
+            .offer(downscale(frame, width, height, x_offset, buffer_width));
+    }
+
+    /// Frames skipped because the encoder was still busy
+    pub fn dropped_frames(&self) -> u64 {
+        self.sender.dropped()
+    }
+}
+
+/// Encodes frames until the server is dropped. Frames with no changed tiles
+/// are not re-encoded; clients keep the previous image.
+fn run_encoder(receiver: Receiver<PreviewFrame>, latest: &LatestFrame) {
+    let mut hasher = TileHasher::new();
+    for frame in receiver {
+        if hasher.changed_tiles(&frame).is_empty() {
+            continue;
+        }
+        let mut jpeg = Vec::new();
+        let result = JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY).encode(
+            &frame.rgb,
+            frame.width,
+            frame.height,
+            ExtendedColorType::Rgb8,
+        );
+        match result {
+            Ok(()) => latest.publish(jpeg),
+            Err(e) => warn!("Preview frame encoding failed: {}", e),
+        }
+    }
+}
+
+fn serve_client(mut stream: TcpStream, latest: &LatestFrame) -> io::Result<()> {
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+    let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+
+    match path {
+        "/" => write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\n\
+             Connection: close\r\n\r\n{}",
+            INDEX_HTML.len(),
+            INDEX_HTML
+        ),
+        "/stream" => {
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nCache-Control: no-cache\r\nConnection: close\r\n\
+                 Content-Type: multipart/x-mixed-replace; boundary={}\r\n\r\n",
+                BOUNDARY
+            )?;
+            let mut seen = 0;
+            loop {
+                let Some((seq, jpeg)) = latest.wait_newer(seen, Duration::from_secs(1)) else {
+                    continue;
+                };
+                seen = seq;
+                write!(
+                    stream,
+                    "--{}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+                    BOUNDARY,
+                    jpeg.len()
+                )?;
+                stream.write_all(&jpeg)?;
+                stream.write_all(b"\r\n")?;
+            }
+        }
+        _ => write!(
+            stream,
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid_frame(width: u32, height: u32, value: u8) -> PreviewFrame {
+        let mut rgb = bufpool::get_buffer("preview test frame", (width * height * 3) as usize);
+        rgb.fill(value);
+        PreviewFrame { width, height, rgb }
+    }
+
+    #[test]
+    fn test_tile_hash_detects_changed_tiles() {
+        let mut hasher = TileHasher::new();
+        let mut frame = solid_frame(40, 20, 10);
+        // 3x2 tiles, partial ones on the right and bottom included
+        assert_eq!(hasher.changed_tiles(&frame).len(), 6);
+        assert!(hasher.changed_tiles(&frame).is_empty());
+
+        // One pixel in the partial bottom-right tile
+        let idx = 3 * (18 * 40 + 35);
+        frame.rgb[idx] = 200;
+        assert_eq!(hasher.changed_tiles(&frame), vec![5]);
+
+        // A resize invalidates everything
+        assert_eq!(hasher.changed_tiles(&solid_frame(16, 16, 10)), vec![0]);
+    }
+
+    #[test]
+    fn test_full_channel_drops_instead_of_blocking() {
+        let (sender, receiver) = DroppingSender::new(1);
+        assert!(sender.offer(1));
+        assert!(!sender.offer(2));
+        assert!(!sender.offer(3));
+        assert_eq!(sender.dropped(), 2);
+
+        // Draining makes room again; the dropped items are gone for good
+        assert_eq!(receiver.recv().unwrap(), 1);
+        assert!(sender.offer(4));
+        assert_eq!(receiver.recv().unwrap(), 4);
+        assert_eq!(sender.dropped(), 2);
+    }
+
+    #[test]
+    fn test_downscale_keeps_aspect_ratio() {
+        let (width, height) = (1600, 800);
+        let mut frame = vec![0u8; (width * height * 4) as usize];
+        frame[0] = 255;
+        let preview = downscale(&frame, width, height, 0, width);
+        assert_eq!((preview.width, preview.height), (480, 240));
+        assert_eq!(preview.rgb.len(), 480 * 240 * 3);
+        assert_eq!(preview.rgb[0], 255);
+    }
+}
@@ -0,0 +1,449 @@
+//! `stimstation render`: writes numbered PNG frames of one scene without a
+//! window, with a `render_manifest.json` alongside for assembling a video.
+//! It can render a range or every Nth frame, shows a progress bar with an
+//! ETA, and picks up an interrupted export at its first missing frame.
+//!
+//! Frames before the first one written are still simulated, through the
+//! update phase only, so the balls are where a full run would have them.
+//! Scenes that only advance while drawing start from where they were.
+//!
+//! With audio, the soundtrack plays and frames are drawn in real time, so
+//! the audio-reactive scenes follow what is heard; the analyzed samples go
+//! to `audio.wav`, with `audio.json` saying which sample lines up with the
+//! first frame written. A resumed export's audio covers only the frames this
+//! run wrote.
+
+use crate::audio::audio_handler;
+use crate::audio::audio_tap::{AudioSync, AudioTap, TapError};
+use crate::core::embed::{StimConfig, StimStation};
+use crate::core::export::ExportError;
+use crate::core::scenes::SceneInfo;
+use crate::core::sim_rng;
+use image::RgbaImage;
+use log::warn;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Bumped whenever a manifest field is renamed or removed
+pub const RENDER_MANIFEST_VERSION: u32 = 1;
+pub const RENDER_MANIFEST_NAME: &str = "render_manifest.json";
+pub const DEFAULT_FPS: u32 = 60;
+pub const DEFAULT_FRAMES: usize = 600;
+/// Enough for over four hours at 60 fps
+const FRAME_DIGITS: usize = 6;
+/// How far back the speed estimate looks
+const RATE_WINDOW: Duration = Duration::from_secs(5);
+const BAR_WIDTH: usize = 30;
+pub const AUDIO_FILE_NAME: &str = "audio.wav";
+/// How long to wait for the soundtrack to reach analysis before rendering without it
+const AUDIO_START_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeError {
+    StartAfterEnd { start: usize, end: usize },
+    ZeroStep,
+}
+
+impl fmt::Display for RangeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RangeError::StartAfterEnd { start, end } => {
+                write!(f, "start frame {} is after end frame {}", start, end)
+            }
+            RangeError::ZeroStep => write!(f, "--every must be at least 1"),
+        }
+    }
+}
+
+impl std::error::Error for RangeError {}
+
+/// Frame indices `start` to `end`, both included, taking every `every`th
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameRange {
+    pub start: usize,
+    pub end: usize,
+    pub every: usize,
+}
+
+impl FrameRange {
+    pub fn new(start: usize, end: usize, every: usize) -> Result<Self, RangeError> {
+        if every == 0 {
+            return Err(RangeError::ZeroStep);
+        }
+        if start > end {
+            return Err(RangeError::StartAfterEnd { start, end });
+        }
+        Ok(Self { start, end, every })
+    }
+
+    pub fn indices(&self) -> impl Iterator<Item = usize> {
+        (self.start..=self.end).step_by(self.every)
+    }
+
+    pub fn len(&self) -> usize {
+        (self.end - self.start) / self.every + 1
+    }
+
+    /// Never: a range always holds its start frame
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    pub fn contains(&self, index: usize) -> bool {
+        (self.start..=self.end).contains(&index) && (index - self.start).is_multiple_of(self.every)
+    }
+
+    /// The last index actually written, which `end` only is when the step lands on it
+    pub fn last(&self) -> usize {
+        self.start + (self.len() - 1) * self.every
+    }
+}
+
+/// `frame_000042.png`; zero-padded so the files sort in order
+pub fn frame_file_name(index: usize) -> String {
+    format!("frame_{:0width$}.png", index, width = FRAME_DIGITS)
+}
+
+/// The first frame of `range` with no file in `dir`, or None when all are there
+pub fn first_missing(range: &FrameRange, dir: &Path) -> Option<usize> {
+    range
+        .indices()
+        .find(|&index| !dir.join(frame_file_name(index)).exists())
+}
+
+/// Progress per second over the last few seconds, from a short history of
+/// samples, so the ETA follows changes in speed without jumping every frame
+#[derive(Debug, Clone, Default)]
+pub struct RateEstimator {
+    samples: VecDeque<(Instant, u64)>,
+}
+
+impl RateEstimator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records `done` units finished by `now`
+    pub fn record(&mut self, now: Instant, done: u64) {
+        self.samples.push_back((now, done));
+        while self.samples.len() > 2 && now.duration_since(self.samples[0].0) > RATE_WINDOW {
+            self.samples.pop_front();
+        }
+    }
+
+    pub fn rate(&self) -> Option<f64> {
+        let (&(first, first_done), &(last, last_done)) =
+            (self.samples.front()?, self.samples.back()?);
+        let seconds = last.duration_since(first).as_secs_f64();
+        (seconds > 0.0).then(|| (last_done - first_done) as f64 / seconds)
+    }
+
+    /// How long `remaining` more units take at the current rate
+    pub fn eta(&self, remaining: u64) -> Option<Duration> {
+        self.rate()
+            .filter(|&rate| rate > 0.0)
+            .map(|rate| Duration::from_secs_f64(remaining as f64 / rate))
+    }
+}
+
+/// Where an export is, for the progress bar
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderProgress {
+    /// Frames of the range written, including ones a previous run left
+    pub done: usize,
+    pub total: usize,
+    pub fps: Option<f64>,
+    pub eta: Option<Duration>,
+}
+
+impl RenderProgress {
+    /// `[############------------------]  412/1000  41%  23.5 fps  ETA 0:25`
+    pub fn bar_line(&self) -> String {
+        let fraction = self.done as f64 / self.total.max(1) as f64;
+        let filled = ((fraction * BAR_WIDTH as f64) as usize).min(BAR_WIDTH);
+        let width = self.total.to_string().len();
+        let mut line = format!(
+            "[{}{}] {:>width$}/{} {:>3.0}%",
+            "#".repeat(filled),
+            "-".repeat(BAR_WIDTH - filled),
+            self.done,
+            self.total,
+            fraction * 100.0,
+            width = width
+        );
+        if let Some(fps) = self.fps {
+            line.push_str(&format!("  {:.1} fps", fps));
+        }
+        if let Some(eta) = self.eta {
+            let secs = eta.as_secs();
+            line.push_str(&format!("  ETA {}:{:02}", secs / 60, secs % 60));
+        }
+        line
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct RenderOptions {
+    pub scene: &'static SceneInfo,
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+    /// Seeds the scenes' randomness and is recorded in the manifest, so an
+    /// export rendered again with it repeats the run. A resumed export keeps
+    /// its first run's when None.
+    pub seed: Option<u64>,
+    pub range: FrameRange,
+    /// Play the soundtrack, draw in real time, and save the analyzed audio
+    pub with_audio: bool,
+}
+
+/// What an export did
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RenderSummary {
+    pub rendered: usize,
+    /// Frames already written by an earlier run
+    pub resumed: usize,
+    pub seed: u64,
+    /// Where the frames fall in `audio.wav`, when audio was saved
+    pub audio: Option<AudioSync>,
+}
+
+/// The manifest for an export; `audio` names its soundtrack file once saved
+pub fn manifest_json(options: &RenderOptions, seed: u64, audio: Option<&str>) -> Value {
+    let range = options.range;
+    json!({
+        "version": RENDER_MANIFEST_VERSION,
+        "scene": options.scene.id,
+        "fps": options.fps,
+        "width": options.width,
+        "height": options.height,
+        "seed": seed,
+        "frame_range": {
+            "start": range.start,
+            "end": range.last(),
+            "every": range.every,
+        },
+        "frame_count": range.len(),
+        "frame_pattern": format!("frame_%0{}d.png", FRAME_DIGITS),
+        "audio": audio,
+    })
+}
+
+/// The seed of the manifest already in `path`, if any. Fails when it
+/// describes a different export, whose frames these would not continue.
+fn previous_seed(path: &Path, options: &RenderOptions) -> Result<Option<u64>, ExportError> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let previous: Value = serde_json::from_str(&text)?;
+    let expected = manifest_json(options, 0, None);
+    for key in ["scene", "fps", "width", "height"] {
+        if previous[key] != expected[key] {
+            return Err(ExportError::Mismatch(format!(
+                "{} holds frames with {} {}, not {}",
+                path.display(),
+                key,
+                previous[key],
+                expected[key]
+            )));
+        }
+    }
+    Ok(previous["seed"].as_u64())
+}
+
+fn write_manifest(dir: &Path, options: &RenderOptions, seed: u64) -> Result<(), ExportError> {
+    let audio = dir
+        .join(AUDIO_FILE_NAME)
+        .exists()
+        .then_some(AUDIO_FILE_NAME);
+    fs::write(
+        dir.join(RENDER_MANIFEST_NAME),
+        serde_json::to_string_pretty(&manifest_json(options, seed, audio))?,
+    )?;
+    Ok(())
+}
+
+/// Writes through a temporary file, so an interrupted write never leaves a
+/// frame file that resuming would take as done
+fn save_frame(image: &RgbaImage, path: &Path) -> Result<(), ExportError> {
+    let partial: PathBuf = path.with_extension("png.part");
+    image.save_with_format(&partial, image::ImageFormat::Png)?;
+    fs::rename(&partial, path)?;
+    Ok(())
+}
+
+/// Renders the frames of `options.range` that `dir` doesn't have yet, calling
+/// `on_progress` after each one, and writes the manifest
+pub fn render_frames(
+    options: &RenderOptions,
+    dir: &Path,
+    mut on_progress: impl FnMut(&RenderProgress),
+) -> Result<RenderSummary, ExportError> {
+    fs::create_dir_all(dir)?;
+    let manifest_path = dir.join(RENDER_MANIFEST_NAME);
+    let seed = options
+        .seed
+        .or(previous_seed(&manifest_path, options)?)
+        .unwrap_or_else(rand::random);
+    // Written first, so an interrupted export is already described, and
+    // again once the audio is saved
+    write_manifest(dir, options, seed)?;
+
+    let range = options.range;
+    let Some(resume) = first_missing(&range, dir) else {
+        return Ok(RenderSummary {
+            rendered: 0,
+            resumed: range.len(),
+            seed,
+            audio: None,
+        });
+    };
+    let resumed = range.indices().take_while(|&index| index < resume).count();
+
+    // Without audio the bars would rest flat; show the demo pattern instead
+    audio_handler::set_demo_bars(true);
+    let config = StimConfig {
+        width: options.width,
+        height: options.height,
+        audio_playback: options.with_audio,
+    };
+    sim_rng::seed(seed);
+    let mut station = StimStation::new(config)?;
+    station.set_scene(options.scene.id)?;
+    let mut tap = if options.with_audio {
+        start_audio_tap(&dir.join(AUDIO_FILE_NAME), options.fps)?
+    } else {
+        None
+    };
+    let mut frame = vec![0; config.frame_len()];
+    let started = Instant::now();
+    let mut estimator = RateEstimator::new();
+    estimator.record(started, 0);
+    let mut rendered = 0;
+    for index in 0..=range.last() {
+        let time = index as f32 / options.fps as f32;
+        if index < resume || !range.contains(index) {
+            station.skip_to(time);
+            continue;
+        }
+        if let Some(tap) = tap.as_mut() {
+            // Drawn when its audio is heard
+            let due =
+                started + Duration::from_secs_f64((index - resume) as f64 / options.fps as f64);
+            std::thread::sleep(due.saturating_duration_since(Instant::now()));
+            tap.mark_frame(index);
+        }
+        station.render_at(&mut frame, time)?;
+        for pixel in frame.chunks_exact_mut(4) {
+            pixel[3] = 255;
+        }
+        let image = RgbaImage::from_raw(options.width, options.height, frame.clone())
+            .expect("frame matches the export size");
+        save_frame(&image, &dir.join(frame_file_name(index)))?;
+        rendered += 1;
+        estimator.record(Instant::now(), rendered as u64);
+        let done = resumed + rendered;
+        on_progress(&RenderProgress {
+            done,
+            total: range.len(),
+            fps: estimator.rate(),
+            eta: estimator.eta((range.len() - done) as u64),
+        });
+    }
+    let audio = match tap.map(AudioTap::finish) {
+        Some(Ok(sync)) => Some(sync),
+        Some(Err(TapError::NoAudio)) | None => None,
+        Some(Err(e)) => return Err(ExportError::Audio(e)),
+    };
+    if audio.is_some() {
+        write_manifest(dir, options, seed)?;
+    }
+    Ok(RenderSummary {
+        rendered,
+        resumed,
+        seed,
+        audio,
+    })
+}
+
+/// Starts tapping the soundtrack into `path` once it is playing. None, with a
+/// warning, when no audio arrives, e.g. without an output device.
+fn start_audio_tap(path: &Path, fps: u32) -> Result<Option<AudioTap>, ExportError> {
+    let tap = AudioTap::start(path, fps).map_err(ExportError::Audio)?;
+    if tap.wait_for_audio(AUDIO_START_TIMEOUT) {
+        return Ok(Some(tap));
+    }
+    warn!("No audio is playing; rendering without it");
+    match tap.finish() {
+        Ok(_) | Err(TapError::NoAudio) => Ok(None),
+        Err(e) => Err(ExportError::Audio(e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_range_math() {
+        let range = FrameRange::new(10, 20, 3).unwrap();
+        assert_eq!(range.indices().collect::<Vec<_>>(), [10, 13, 16, 19]);
+        assert_eq!(range.len(), 4);
+        assert_eq!(range.last(), 19);
+        assert!(range.contains(16) && !range.contains(17) && !range.contains(22));
+        assert!(!range.contains(7));
+
+        // A single frame, and a step past the end
+        let one = FrameRange::new(5, 5, 1).unwrap();
+        assert_eq!((one.len(), one.last()), (1, 5));
+        let wide = FrameRange::new(0, 9, 100).unwrap();
+        assert_eq!(wide.indices().collect::<Vec<_>>(), [0]);
+
+        assert_eq!(
+            FrameRange::new(8, 3, 1),
+            Err(RangeError::StartAfterEnd { start: 8, end: 3 })
+        );
+        assert_eq!(FrameRange::new(0, 10, 0), Err(RangeError::ZeroStep));
+        assert_eq!(frame_file_name(42), "frame_000042.png");
+    }
+
+    #[test]
+    fn test_rate_follows_recent_speed_and_the_bar_reads_it() {
+        let start = Instant::now();
+        let mut estimator = RateEstimator::new();
+        assert_eq!(estimator.rate(), None);
+        // Ten seconds at 10 frames a second, then three at 40
+        for tenth in 0..=100 {
+            estimator.record(start + Duration::from_millis(tenth * 100), tenth);
+        }
+        assert!((estimator.rate().unwrap() - 10.0).abs() < 0.01);
+        for tenth in 1..=30 {
+            estimator.record(
+                start + Duration::from_millis(10_000 + tenth * 100),
+                100 + tenth * 4,
+            );
+        }
+        // The window still holds two slow seconds
+        let rate = estimator.rate().unwrap();
+        assert!(rate > 25.0 && rate < 40.0, "{}", rate);
+        let eta = estimator.eta(rate as u64 * 10).unwrap();
+        assert!((eta.as_secs_f64() - 10.0).abs() < 0.2, "{:?}", eta);
+
+        let progress = RenderProgress {
+            done: 412,
+            total: 1000,
+            fps: Some(23.5),
+            eta: Some(Duration::from_secs(85)),
+        };
+        assert_eq!(
+            progress.bar_line(),
+            "[############------------------]  412/1000  41%  23.5 fps  ETA 1:25"
+        );
+    }
+}
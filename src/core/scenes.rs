@@ -0,0 +1,288 @@
+use crate::algorithms::maze;
+use crate::algorithms::sorter_manager::{self, SorterLook};
+use crate::core::orchestrator;
+use crate::core::persist::{PersistentState, SceneState};
+use crate::graphics::accents::{GoldenRing, PrismFlash, ShootingStar, SpawnAccent};
+use crate::graphics::render::BACKGROUND_COLOR;
+use crate::graphics::{rain, shatter, tunnel};
+use crate::physics::{softbody, world};
+
+/// A key binding shown in help text and exported manifests
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HelpEntry {
+    pub key: &'static str,
+    pub action: &'static str,
+}
+
+const fn help(key: &'static str, action: &'static str) -> HelpEntry {
+    HelpEntry { key, action }
+}
+
+/// How much of the frame a scene draws, which decides whether the frame is
+/// cleared before it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoveragePolicy {
+    /// The scene paints every pixel itself, so clearing first would be wasted
+    FullCover,
+    /// The scene draws on a frame cleared to this color
+    NeedsClear([u8; 4]),
+    /// The scene draws over whatever the last frame left
+    Overlay,
+}
+
+/// A named combination of the app's toggles that can be switched to as a whole.
+pub struct SceneInfo {
+    pub id: &'static str,
+    pub name: &'static str,
+    pub description: &'static str,
+    pub help: &'static [HelpEntry],
+    /// Reacts to audio; without playback it runs on the simulated signal
+    pub uses_audio: bool,
+    /// Does much of its work in response to the mouse, so headless runs like
+    /// `bench-all` script a drag across it
+    pub mouse_driven: bool,
+    /// What the scene keeps across switches and restarts, if anything
+    pub state: Option<SceneState>,
+    pub coverage: CoveragePolicy,
+    /// Redraw at most this often; frames in between reuse the last drawing
+    /// under fresh overlays. None redraws every frame.
+    pub max_fps: Option<f32>,
+    /// A rare effect the scene plays now and then, within the shared budget
+    /// in `accents`
+    pub accent: Option<SpawnAccent>,
+    enter: fn(),
+}
+
+impl SceneInfo {
+    /// Sets the toggles this scene is made of
+    pub fn enter(&self) {
+        (self.enter)();
+    }
+}
+
+fn set_toggles(
+    world_on: bool,
+    softbody_on: bool,
+    tunnel_on: bool,
+    maze_on: bool,
+    rain_on: bool,
+    shatter_on: bool,
+    clean: bool,
+) {
+    world::set_world_enabled(world_on);
+    softbody::set_softbody_enabled(softbody_on);
+    tunnel::set_tunnel_enabled(tunnel_on);
+    maze::set_maze_enabled(maze_on);
+    rain::set_rain_enabled(rain_on);
+    shatter::set_shatter_enabled(shatter_on);
+    orchestrator::set_clean_mode(clean);
+}
+
+const COMMON_HELP: &[HelpEntry] = &[
+    help("H", "Show these keys (PgUp/PgDn to page)"),
+    help("F3", "Toggle the debug overlay (Shift+F3: frame diff, Ctrl+F3: FPS cap)"),
+    help("F4", "Toggle clean mode"),
+    help("F5", "Save snapshot (Shift+F5 restores)"),
+    help("F6", "Reset the scene"),
+    help("F7", "Cycle color filter"),
+    help("F8", "Calibrate audio latency (, and . adjust)"),
+    help("F9", "Swap the output between RGBA and BGRA"),
+    help("F10", "Session timeline (click or Left/Right to go back)"),
+    help("Ctrl+.", "Morph to the next preset (Ctrl+, saves)"),
+    help("Ctrl+Z", "Undo a setting change (Ctrl+Shift+Z redoes)"),
+    help("V", "Toggle audio bars"),
+    help("9", "Cycle fallback sound: off, noise, music"),
+    help("O", "Pick the audio output (Up/Down, Enter)"),
+    help("F12", "Start or stop a long exposure (Shift+F12: 2x wallpaper)"),
+    help("E", "Explosion (Shift+E: next pattern)"),
+    help("T", "Day/night theme (Shift+T: follow the schedule, Ctrl+T: from the frame)"),
+    help("Double-click", "Toggle fullscreen"),
+    help("Long-press", "Open the scene menu"),
+    help("Esc", "Close the menu or quit"),
+];
+
+/// Every scene, in the order launchers should list them
+pub const SCENES: &[SceneInfo] = &[
+    SceneInfo {
+        id: "rays",
+        name: "Rays",
+        description: "Two bouncing balls casting rays and shadows, framed by sorting visualizers",
+        help: &[
+            help("Arrows", "Push the yellow ball"),
+            help("C", "Cycle sorter colors"),
+            help("N", "Toggle sorter captions (Shift+N: one shared ring)"),
+            help("I", "Cycle sorter input pattern"),
+            help("R", "Next row of the --sort-image photo"),
+            help("M", "Toggle light mixing of the rays"),
+        ],
+        uses_audio: true,
+        mouse_driven: false,
+        state: Some(SceneState {
+            save: || sorter_manager::sorter_look().save_state(),
+            load: |blob| {
+                let mut look = SorterLook::default();
+                look.load_state(blob)?;
+                sorter_manager::set_sorter_look(look);
+                Ok(())
+            },
+            reset: || sorter_manager::set_sorter_look(SorterLook::default()),
+        }),
+        coverage: CoveragePolicy::NeedsClear(BACKGROUND_COLOR),
+        max_fps: None,
+        accent: Some(PrismFlash::spawn),
+        enter: || set_toggles(false, false, false, false, false, false, false),
+    },
+    SceneInfo {
+        id: "world",
+        name: "World",
+        description: "Drifting lines and particles with gravity, wind, and paint mode",
+        help: &[
+            help("Space", "Switch world mode"),
+            help("Shift+Space", "Cycle arena: frame, circle, hexagon"),
+            help("G", "Cycle gravity"),
+            help("W", "Toggle wind"),
+            help("Drag", "Blow a wind gust along the drag"),
+            help("P", "Toggle paint mode"),
+            help("Ctrl+Z", "Undo stroke"),
+            help("X", "Clear drawing"),
+            help("K", "Toggle audio line width ([ and ] adjust)"),
+            help("- / =", "Waves mode: wave frequency"),
+            help("; / '", "Waves mode: wave amplitude"),
+            help("Drag", "Waves mode: move a wave source"),
+            help("F", "Flock mode: cursor scatters or attracts"),
+            help("Ctrl+Scroll", "Zoom about the cursor"),
+            help("Middle-drag", "Pan the view"),
+            help("0", "Reset zoom and pan"),
+        ],
+        uses_audio: true,
+        mouse_driven: true,
+        state: Some(SceneState {
+            save: world::save_scene_state,
+            load: world::load_scene_state,
+            reset: world::reset_scene_state,
+        }),
+        coverage: CoveragePolicy::FullCover,
+        max_fps: None,
+        accent: Some(ShootingStar::spawn),
+        enter: || set_toggles(true, false, false, false, false, false, false),
+    },
+    SceneInfo {
+        id: "softbody",
+        name: "Soft Body",
+        description: "A pressurized spring-mass blob that wobbles to the music",
+        help: &[help("B", "Toggle the blob")],
+        uses_audio: true,
+        mouse_driven: false,
+        state: None,
+        coverage: CoveragePolicy::NeedsClear(BACKGROUND_COLOR),
+        max_fps: None,
+        accent: None,
+        enter: || set_toggles(false, true, false, false, false, false, false),
+    },
+    SceneInfo {
+        id: "tunnel",
+        name: "Tunnel",
+        description: "Flying down a ringed tunnel that speeds up with the music",
+        help: &[],
+        uses_audio: true,
+        mouse_driven: false,
+        state: None,
+        coverage: CoveragePolicy::FullCover,
+        max_fps: None,
+        accent: Some(GoldenRing::spawn),
+        enter: || set_toggles(false, false, true, false, false, false, false),
+    },
+    SceneInfo {
+        id: "maze",
+        name: "Maze",
+        description: "A maze carved by backtracking, then solved by BFS or A*",
+        help: &[],
+        uses_audio: false,
+        mouse_driven: false,
+        state: None,
+        coverage: CoveragePolicy::NeedsClear(BACKGROUND_COLOR),
+        max_fps: None,
+        accent: None,
+        // Clean so the HUD and edge sorters stay off the grid
+        enter: || set_toggles(false, false, false, true, false, false, true),
+    },
+    SceneInfo {
+        id: "rain",
+        name: "Rain",
+        description: "Columns of glyphs raining down, with bursts on every beat",
+        help: &[],
+        uses_audio: true,
+        mouse_driven: false,
+        state: None,
+        coverage: CoveragePolicy::FullCover,
+        max_fps: None,
+        accent: None,
+        // Clean so the HUD and edge sorters stay off the rain
+        enter: || set_toggles(false, false, false, false, true, false, true),
+    },
+    SceneInfo {
+        id: "shatter",
+        name: "Shatter",
+        description: "A photo bursting into shards that drift apart and piece back together",
+        help: &[
+            help("Mouse", "Push the shards away"),
+            help("--shatter-image", "Shatter your own photo"),
+        ],
+        uses_audio: false,
+        mouse_driven: true,
+        state: None,
+        coverage: CoveragePolicy::FullCover,
+        max_fps: None,
+        accent: None,
+        // Clean so the HUD and edge sorters stay off the picture
+        enter: || set_toggles(false, false, false, false, false, true, true),
+    },
+    SceneInfo {
+        id: "clean",
+        name: "Clean",
+        description: "Only the central visualization, without HUD or edge sorters",
+        help: &[],
+        uses_audio: false,
+        mouse_driven: false,
+        state: None,
+        coverage: CoveragePolicy::NeedsClear(BACKGROUND_COLOR),
+        // A calm backdrop; half rate is plenty and halves its drawing cost
+        max_fps: Some(30.0),
+        accent: None,
+        enter: || set_toggles(false, false, false, false, false, false, true),
+    },
+];
+
+/// Keys that work in every scene
+pub fn common_help() -> &'static [HelpEntry] {
+    COMMON_HELP
+}
+
+/// The scene the current toggles amount to. Toggling a layer by key counts
+/// as switching to its scene. The World wins over everything else since its
+/// background covers the whole frame, and the Tunnel, which does too, comes next.
+/// The Maze, the Rain, and the Shatter are clean as well, so they go before Clean.
+pub fn active_scene() -> &'static SceneInfo {
+    let id = if world::is_world_enabled() {
+        "world"
+    } else if tunnel::is_tunnel_enabled() {
+        "tunnel"
+    } else if maze::is_maze_enabled() {
+        "maze"
+    } else if rain::is_rain_enabled() {
+        "rain"
+    } else if shatter::is_shatter_enabled() {
+        "shatter"
+    } else if orchestrator::is_clean_mode() {
+        "clean"
+    } else if softbody::is_softbody_enabled() {
+        "softbody"
+    } else {
+        "rays"
+    };
+    find_scene(id).unwrap_or(&SCENES[0])
+}
+
+pub fn find_scene(id: &str) -> Option<&'static SceneInfo> {
+    SCENES.iter().find(|scene| scene.id == id)
+}
@@ -0,0 +1,358 @@
+//! A summary of the session printed when the app quits: how long it ran, how
+//! many frames it drew, where the time went, and what happened along the way.
+//! It stays on this machine; nothing is sent anywhere. Everything is counted
+//! by a listener on the event bus, so the systems involved don't know about it.
+
+use crate::algorithms::sorter::SortAlgorithm;
+use crate::core::events::{self, Event};
+use crate::core::scenes;
+use serde_json::{json, Value};
+use std::cmp::Reverse;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Bumped whenever a JSON summary field is renamed or removed
+pub const SUMMARY_VERSION: u32 = 1;
+
+/// Running totals for the session, fed one event at a time
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SessionStats {
+    /// Scene times of the first and latest frames
+    first_frame: Option<f32>,
+    last_frame: f32,
+    frames: u64,
+    /// The scene on screen and when it came on
+    current: Option<(&'static str, f32)>,
+    /// Seconds spent in each scene left so far, in the order first visited
+    scene_seconds: Vec<(&'static str, f32)>,
+    corner_hits: u64,
+    sorter_completions: Vec<(SortAlgorithm, u32)>,
+    explosions: u64,
+    peak_particles: usize,
+}
+
+impl SessionStats {
+    pub const fn new() -> Self {
+        Self {
+            first_frame: None,
+            last_frame: 0.0,
+            frames: 0,
+            current: None,
+            scene_seconds: Vec::new(),
+            corner_hits: 0,
+            sorter_completions: Vec::new(),
+            explosions: 0,
+            peak_particles: 0,
+        }
+    }
+
+    /// Counts `event`, dispatched at scene time `time`
+    pub fn record(&mut self, event: &Event, time: f32) {
+        match event {
+            Event::FrameRendered { particles } => {
+                self.first_frame.get_or_insert(time);
+                self.last_frame = time;
+                self.frames += 1;
+                self.peak_particles = self.peak_particles.max(*particles);
+            }
+            Event::SceneChanged { to, .. } => {
+                if let Some((scene, since)) = self.current {
+                    add_seconds(&mut self.scene_seconds, scene, time - since);
+                }
+                self.current = Some((*to, time));
+            }
+            Event::CornerHit { .. } => self.corner_hits += 1,
+            Event::SorterCompleted { algorithm, .. } => {
+                match self
+                    .sorter_completions
+                    .iter_mut()
+                    .find(|(done, _)| done == algorithm)
+                {
+                    Some((_, count)) => *count += 1,
+                    None => self.sorter_completions.push((algorithm.clone(), 1)),
+                }
+            }
+            Event::Explosion { .. } => self.explosions += 1,
+            Event::Beat { .. }
+            | Event::RecordBroken { .. }
+            | Event::FallbackSoundChanged { .. } => {}
+        }
+    }
+
+    /// The totals as of the latest frame, the scene still on screen included
+    pub fn summary(&self) -> SessionSummary {
+        let runtime = self
+            .first_frame
+            .map_or(0.0, |first| (self.last_frame - first).max(0.0));
+        let mut scenes = self.scene_seconds.clone();
+        if let Some((scene, since)) = self.current {
+            add_seconds(&mut scenes, scene, self.last_frame - since);
+        }
+        let mut sorter_completions = self.sorter_completions.clone();
+        sorter_completions.sort_by_key(|&(_, count)| Reverse(count));
+        SessionSummary {
+            runtime,
+            frames: self.frames,
+            // Frames are the ends of the intervals the runtime is made of
+            average_fps: if runtime > 0.0 {
+                (self.frames - 1) as f32 / runtime
+            } else {
+                0.0
+            },
+            scenes,
+            corner_hits: self.corner_hits,
+            sorter_completions,
+            explosions: self.explosions,
+            peak_particles: self.peak_particles,
+        }
+    }
+}
+
+fn add_seconds(scenes: &mut Vec<(&'static str, f32)>, scene: &'static str, seconds: f32) {
+    let seconds = seconds.max(0.0);
+    match scenes.iter_mut().find(|(id, _)| *id == scene) {
+        Some((_, total)) => *total += seconds,
+        None => scenes.push((scene, seconds)),
+    }
+}
+
+/// What the session amounted to, ready to print
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionSummary {
+    /// Scene time from the first frame to the last, in seconds
+    pub runtime: f32,
+    pub frames: u64,
+    pub average_fps: f32,
+    /// Scene ids and the seconds spent in each, in the order first visited
+    pub scenes: Vec<(&'static str, f32)>,
+    pub corner_hits: u64,
+    /// Finished sorts per algorithm, most first
+    pub sorter_completions: Vec<(SortAlgorithm, u32)>,
+    pub explosions: u64,
+    /// Most burst and World particles alive at once
+    pub peak_particles: usize,
+}
+
+impl SessionSummary {
+    /// Two-column table for the terminal
+    pub fn format_table(&self) -> String {
+        let mut rows = vec![
+            ("Runtime".to_string(), format_duration(self.runtime)),
+            (
+                "Frames".to_string(),
+                format!("{} ({:.1} fps average)", self.frames, self.average_fps),
+            ),
+        ];
+        rows.extend(self.scenes.iter().map(|(id, seconds)| {
+            let name = scenes::find_scene(id).map_or(*id, |scene| scene.name);
+            (format!("  {}", name), format_duration(*seconds))
+        }));
+        rows.push(("Corner hits".to_string(), self.corner_hits.to_string()));
+        rows.push((
+            "Sorts finished".to_string(),
+            self.sorter_completions
+                .iter()
+                .map(|(_, count)| count)
+                .sum::<u32>()
+                .to_string(),
+        ));
+        rows.extend(
+            self.sorter_completions
+                .iter()
+                .map(|(algorithm, count)| (format!("  {}", algorithm.name()), count.to_string())),
+        );
+        rows.push(("Explosions".to_string(), self.explosions.to_string()));
+        rows.push((
+            "Peak particles".to_string(),
+            self.peak_particles.to_string(),
+        ));
+
+        let width = rows.iter().map(|(label, _)| label.len()).max().unwrap_or(0);
+        let mut table = "Session summary\n".to_string();
+        for (label, value) in rows {
+            table += &format!("  {:<width$}  {}\n", label, value);
+        }
+        table
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "version": SUMMARY_VERSION,
+            "runtime_seconds": self.runtime,
+            "frames": self.frames,
+            "average_fps": self.average_fps,
+            "scenes": self
+                .scenes
+                .iter()
+                .map(|(id, seconds)| json!({ "id": id, "seconds": seconds }))
+                .collect::<Vec<_>>(),
+            "corner_hits": self.corner_hits,
+            "sorter_completions": self
+                .sorter_completions
+                .iter()
+                .map(|(algorithm, count)| json!({ "algorithm": algorithm.name(), "count": count }))
+                .collect::<Vec<_>>(),
+            "explosions": self.explosions,
+            "peak_particles": self.peak_particles,
+        })
+    }
+}
+
+/// `1h 02m 03s`, `4m 05s`, or `7.5s`
+fn format_duration(seconds: f32) -> String {
+    let whole = seconds as u64;
+    if whole >= 3600 {
+        format!(
+            "{}h {:02}m {:02}s",
+            whole / 3600,
+            whole / 60 % 60,
+            whole % 60
+        )
+    } else if whole >= 60 {
+        format!("{}m {:02}s", whole / 60, whole % 60)
+    } else {
+        format!("{:.1}s", seconds)
+    }
+}
+
+static STATS: Mutex<SessionStats> = Mutex::new(SessionStats::new());
+/// Set once the exit summary has been printed
+static PRINTED: AtomicBool = AtomicBool::new(false);
+
+fn stats() -> MutexGuard<'static, SessionStats> {
+    STATS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Event bus listener; the orchestrator subscribes it at startup
+pub fn on_event(event: &Event, time: f32) {
+    stats().record(event, time);
+}
+
+/// The session so far. Events still queued on the bus aren't counted yet.
+pub fn summary() -> SessionSummary {
+    stats().summary()
+}
+
+/// Counts the events still queued, then prints the summary. Only the first
+/// call prints, and only if a frame was drawn.
+pub fn print_exit_summary() {
+    if PRINTED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    let last_frame = stats().last_frame;
+    events::drain(last_frame);
+    let summary = summary();
+    if summary.frames > 0 {
+        print!("{}", summary.format_table());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::sorter_manager::SorterEdge;
+    use crate::physics::detect_corner::Corner;
+
+    fn frame(stats: &mut SessionStats, time: f32, particles: usize) {
+        stats.record(&Event::FrameRendered { particles }, time);
+    }
+
+    fn scene(stats: &mut SessionStats, time: f32, to: &'static str) {
+        let from = stats.current.map(|(id, _)| id);
+        stats.record(&Event::SceneChanged { from, to }, time);
+    }
+
+    fn sorted(stats: &mut SessionStats, algorithm: SortAlgorithm) {
+        let edge = SorterEdge::Top;
+        let points = Vec::new();
+        let event = Event::SorterCompleted {
+            algorithm,
+            edge,
+            points,
+        };
+        stats.record(&event, 0.0);
+    }
+
+    #[test]
+    fn test_summary_adds_up_a_session() {
+        let mut stats = SessionStats::new();
+        // Frames at 1s through 11s, rays, then world from 4s, then rays again from 9s
+        for second in 1..=11 {
+            let time = second as f32;
+            frame(&mut stats, time, second * 10 % 70);
+            match second {
+                1 => scene(&mut stats, time, "rays"),
+                4 => scene(&mut stats, time, "world"),
+                9 => scene(&mut stats, time, "rays"),
+                _ => {}
+            }
+        }
+        let corner = Corner::TopLeft;
+        for _ in 0..3 {
+            stats.record(&Event::CornerHit { corner }, 5.0);
+        }
+        sorted(&mut stats, SortAlgorithm::Quick);
+        sorted(&mut stats, SortAlgorithm::Bubble);
+        sorted(&mut stats, SortAlgorithm::Bubble);
+        stats.record(&Event::Explosion { x: 1.0, y: 2.0 }, 6.0);
+        stats.record(&Event::Beat { strength: 1.0 }, 6.0);
+
+        let summary = stats.summary();
+        assert_eq!(summary.runtime, 10.0);
+        assert_eq!(summary.frames, 11);
+        assert_eq!(summary.average_fps, 1.0);
+        assert_eq!(summary.scenes, [("rays", 5.0), ("world", 5.0)]);
+        assert_eq!(summary.corner_hits, 3);
+        assert_eq!(
+            summary.sorter_completions,
+            [(SortAlgorithm::Bubble, 2), (SortAlgorithm::Quick, 1)]
+        );
+        assert_eq!(summary.explosions, 1);
+        assert_eq!(summary.peak_particles, 60);
+        // The scene on screen keeps counting with each frame
+        frame(&mut stats, 13.0, 0);
+        assert_eq!(stats.summary().scenes[0], ("rays", 7.0));
+    }
+
+    #[test]
+    fn test_an_empty_session_has_no_rate() {
+        let summary = SessionStats::new().summary();
+        assert_eq!(summary.frames, 0);
+        assert_eq!(summary.runtime, 0.0);
+        assert_eq!(summary.average_fps, 0.0);
+        assert!(summary.scenes.is_empty());
+    }
+
+    #[test]
+    fn test_table_and_json_show_the_same_numbers() {
+        let summary = SessionSummary {
+            runtime: 3723.0,
+            frames: 223_381,
+            average_fps: 60.0,
+            scenes: vec![("rays", 3600.0), ("world", 123.0)],
+            corner_hits: 4,
+            sorter_completions: vec![(SortAlgorithm::Heap, 7)],
+            explosions: 2,
+            peak_particles: 388,
+        };
+        let table = summary.format_table();
+        for expected in [
+            "Runtime         1h 02m 03s",
+            "Frames          223381 (60.0 fps average)",
+            "  World         2m 03s",
+            "Sorts finished  7",
+            "  Heap Sort     7",
+            "Peak particles  388",
+        ] {
+            assert!(table.contains(expected), "{:?} not in\n{}", expected, table);
+        }
+
+        let json = summary.to_json();
+        assert_eq!(json["version"], SUMMARY_VERSION);
+        assert_eq!(json["frames"], 223_381);
+        assert_eq!(json["scenes"][1]["id"], "world");
+        assert_eq!(json["sorter_completions"][0]["algorithm"], "Heap Sort");
+        assert_eq!(json["peak_particles"], 388);
+        assert_eq!(format_duration(7.46), "7.5s");
+    }
+}
@@ -0,0 +1,766 @@
+//! User settings stored as `key = value` lines in the config directory.
+//! Unknown keys and bad values are skipped with a warning so an old or
+//! hand-edited file never stops the app from starting. Numbers are checked
+//! against `NUMERIC_SETTINGS` and clamped into range.
+
+use crate::algorithms::sorter::InputPattern;
+use crate::algorithms::sorter_manager;
+use crate::audio::audio_handler::{self, BarEnvelope};
+use crate::audio::output_device::{self, DeviceChoice};
+use crate::audio::{self, features};
+use crate::core::accessibility;
+use crate::core::focus::{self, FocusSettings};
+use crate::core::keep_awake;
+use crate::core::pacing::{self, FpsCap};
+use crate::core::timestep;
+use crate::graphics::accents::{self, DEFAULT_INTERVAL_MINUTES};
+use crate::graphics::longexposure::{self, BlendMode, LongExposureSettings};
+use crate::graphics::post::{self, ColorFilter, PostSettings};
+use crate::graphics::theme::{self, format_clock_time, parse_clock_time, Schedule};
+use crate::physics::arena::ArenaShape;
+use crate::physics::flock::FlockWeights;
+use crate::physics::physics::{self, CollisionModel, DEFAULT_MAX_RADIUS_FRACTION};
+use crate::physics::world::{self, MAX_FLOCK_WEIGHT};
+use crate::ui::{intro, toast};
+use log::{info, warn};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Range of a numeric setting, in the units it is written in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NumericSetting {
+    pub key: &'static str,
+    pub default: f32,
+    pub min: f32,
+    pub max: f32,
+    /// Increment for anything that adjusts the value step by step
+    pub step: f32,
+}
+
+impl NumericSetting {
+    /// `value` clamped into range; NaN and infinities fall back to the default
+    pub fn clamp(&self, value: f32) -> f32 {
+        if value.is_finite() {
+            value.clamp(self.min, self.max)
+        } else {
+            self.default
+        }
+    }
+}
+
+/// Every numeric setting the file may hold
+pub const NUMERIC_SETTINGS: &[NumericSetting] = &[
+    NumericSetting {
+        key: "accent_interval_minutes",
+        default: DEFAULT_INTERVAL_MINUTES,
+        min: 1.0,
+        max: 240.0,
+        step: 1.0,
+    },
+    NumericSetting {
+        key: "bar_attack_ms",
+        default: BarEnvelope::DEFAULT.attack * 1000.0,
+        min: 1.0,
+        max: 5000.0,
+        step: 5.0,
+    },
+    NumericSetting {
+        key: "bar_release_ms",
+        default: BarEnvelope::DEFAULT.release * 1000.0,
+        min: 1.0,
+        max: 5000.0,
+        step: 5.0,
+    },
+    NumericSetting {
+        key: "duck_volume",
+        default: FocusSettings::DEFAULT.duck_volume,
+        min: 0.0,
+        max: 1.0,
+        step: 0.05,
+    },
+    NumericSetting {
+        key: "flock_alignment",
+        default: FlockWeights::DEFAULT.alignment,
+        min: 0.0,
+        max: MAX_FLOCK_WEIGHT,
+        step: 0.1,
+    },
+    NumericSetting {
+        key: "flock_cohesion",
+        default: FlockWeights::DEFAULT.cohesion,
+        min: 0.0,
+        max: MAX_FLOCK_WEIGHT,
+        step: 0.1,
+    },
+    NumericSetting {
+        key: "flock_separation",
+        default: FlockWeights::DEFAULT.separation,
+        min: 0.0,
+        max: MAX_FLOCK_WEIGHT,
+        step: 0.1,
+    },
+    NumericSetting {
+        key: "hue_shift_degrees",
+        default: PostSettings::DEFAULT.hue_shift,
+        min: 0.0,
+        max: 360.0,
+        step: 5.0,
+    },
+    NumericSetting {
+        key: "long_exposure_minutes",
+        default: LongExposureSettings::DEFAULT.minutes,
+        min: 1.0,
+        max: 30.0,
+        step: 1.0,
+    },
+    NumericSetting {
+        key: "max_ball_radius_percent",
+        default: DEFAULT_MAX_RADIUS_FRACTION * 100.0,
+        min: 2.0,
+        max: 50.0,
+        step: 1.0,
+    },
+    NumericSetting {
+        key: "visual_latency_ms",
+        default: 0.0,
+        min: -200.0,
+        max: 500.0,
+        step: 5.0,
+    },
+];
+
+pub fn numeric_setting(key: &str) -> Option<&'static NumericSetting> {
+    NUMERIC_SETTINGS.iter().find(|setting| setting.key == key)
+}
+
+/// A value from the file that was out of range and replaced
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Correction {
+    pub key: &'static str,
+    pub value: f32,
+    pub corrected: f32,
+}
+
+/// Everything read from a settings file
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedSettings {
+    pub settings: Settings,
+    pub corrections: Vec<Correction>,
+    /// Lines with keys this version doesn't know, kept so saving doesn't drop them
+    pub unknown: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    pub bar_envelope: BarEnvelope,
+    pub focus: FocusSettings,
+    /// Animate a simulated pattern on the bars when there is no audio
+    pub demo_bars: bool,
+    pub post: PostSettings,
+    /// Set once the intro has been shown
+    pub first_run_done: bool,
+    /// How the edge sorters' arrays are arranged before each run
+    pub sorter_input: InputPattern,
+    /// How long the visuals wait for the audio to reach the speakers
+    pub visual_latency_ms: f32,
+    /// Largest ball radius, as a fraction of the frame's shorter side
+    pub max_ball_radius: f32,
+    pub collision_model: CollisionModel,
+    pub output_device: DeviceChoice,
+    /// Crossfade between the day and night themes by the local clock
+    pub theme_schedule: bool,
+    pub schedule: Schedule,
+    /// Hold off the screensaver even when no audio is playing
+    pub keep_awake: bool,
+    /// Rule weights for the World's Flock mode
+    pub flock: FlockWeights,
+    /// Shape the World's lines and particles are kept inside
+    pub arena: ArenaShape,
+    /// Download and play the soundtrack; off launches straight into the
+    /// simulated spectrum
+    pub audio: bool,
+    /// Shortest wait between two scene accents, like the World's shooting star
+    pub accent_interval_minutes: f32,
+    /// F12 long exposures: length, blend, and whether each is shown when done
+    pub long_exposure: LongExposureSettings,
+    /// Frame-rate cap for the window (Ctrl+F3 cycles it)
+    pub fps_cap: FpsCap,
+    /// Step the ball physics at a fixed 120 Hz, drawing between steps
+    pub fixed_timestep: bool,
+    /// Draw HUD panels on the theme's solid surface instead of see-through
+    pub opaque_hud: bool,
+}
+
+impl Settings {
+    pub const DEFAULT: Self = Self {
+        bar_envelope: BarEnvelope::DEFAULT,
+        focus: FocusSettings::DEFAULT,
+        demo_bars: false,
+        post: PostSettings::DEFAULT,
+        first_run_done: false,
+        sorter_input: InputPattern::Random,
+        visual_latency_ms: 0.0,
+        max_ball_radius: DEFAULT_MAX_RADIUS_FRACTION,
+        collision_model: CollisionModel::Arcade,
+        output_device: DeviceChoice::SystemDefault,
+        theme_schedule: false,
+        schedule: Schedule::DEFAULT,
+        keep_awake: false,
+        flock: FlockWeights::DEFAULT,
+        arena: ArenaShape::Rect,
+        audio: true,
+        accent_interval_minutes: DEFAULT_INTERVAL_MINUTES,
+        long_exposure: LongExposureSettings::DEFAULT,
+        fps_cap: FpsCap::MonitorRefresh,
+        fixed_timestep: false,
+        opaque_hud: false,
+    };
+
+    /// Captures the values currently in effect
+    pub fn current() -> Self {
+        Self {
+            bar_envelope: audio_handler::bar_envelope(),
+            focus: focus::focus_settings(),
+            demo_bars: audio_handler::demo_bars(),
+            post: post::post_settings(),
+            first_run_done: intro::first_run_done(),
+            sorter_input: sorter_manager::get_input_pattern(),
+            visual_latency_ms: features::visual_latency_ms(),
+            max_ball_radius: physics::max_ball_radius_fraction(),
+            collision_model: physics::configured_collision_model(),
+            output_device: output_device::selected_device(),
+            theme_schedule: theme::schedule_enabled(),
+            schedule: theme::schedule(),
+            keep_awake: keep_awake::keep_awake(),
+            flock: world::flock_weights(),
+            arena: world::arena_shape(),
+            audio: audio::audio_enabled(),
+            accent_interval_minutes: accents::interval_minutes(),
+            long_exposure: longexposure::long_exposure_settings(),
+            fps_cap: pacing::fps_cap(),
+            fixed_timestep: timestep::is_fixed_timestep(),
+            opaque_hud: accessibility::is_opaque_hud(),
+        }
+    }
+
+    /// Makes these values the ones in effect
+    pub fn apply(&self) {
+        audio_handler::set_bar_envelope(self.bar_envelope);
+        focus::set_focus_settings(self.focus);
+        audio_handler::set_demo_bars(self.demo_bars);
+        post::set_post_settings(self.post);
+        intro::set_first_run_done(self.first_run_done);
+        sorter_manager::set_input_pattern(self.sorter_input);
+        features::set_visual_latency_ms(self.visual_latency_ms);
+        physics::set_max_ball_radius_fraction(self.max_ball_radius);
+        physics::set_collision_model(self.collision_model);
+        output_device::select_device(self.output_device.clone());
+        theme::set_schedule(self.schedule, self.theme_schedule);
+        keep_awake::set_keep_awake(self.keep_awake);
+        world::set_flock_weights(self.flock);
+        world::set_arena_shape(self.arena);
+        audio::set_audio_enabled(self.audio);
+        accents::set_interval_minutes(self.accent_interval_minutes);
+        longexposure::set_long_exposure_settings(self.long_exposure);
+        pacing::set_fps_cap(self.fps_cap);
+        timestep::set_fixed_timestep(self.fixed_timestep);
+        accessibility::set_opaque_hud(self.opaque_hud);
+    }
+
+    /// Reads settings from `text`, starting from the defaults for missing keys
+    pub fn parse(text: &str) -> Self {
+        Self::parse_checked(text).settings
+    }
+
+    /// `parse`, also returning the numbers that had to be clamped and the
+    /// lines that were not understood
+    pub fn parse_checked(text: &str) -> ParsedSettings {
+        let mut settings = Self::DEFAULT;
+        let mut corrections = Vec::new();
+        let mut unknown = Vec::new();
+        let mut checked_number = |key: &str, value: &str| {
+            let raw: f32 = value.parse().ok()?;
+            let setting = numeric_setting(key)?;
+            let corrected = setting.clamp(raw);
+            // NaN never equals itself, so it is always reported
+            if corrected != raw {
+                corrections.push(Correction {
+                    key: setting.key,
+                    value: raw,
+                    corrected,
+                });
+            }
+            Some(corrected)
+        };
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                warn!("settings line {}: expected `key = value`", number + 1);
+                continue;
+            };
+            let (key, value) = (key.trim(), value.trim());
+            let parsed = match key {
+                "bar_attack_ms" => {
+                    checked_number(key, value).map(|ms| settings.bar_envelope.attack = ms / 1000.0)
+                }
+                "bar_release_ms" => {
+                    checked_number(key, value).map(|ms| settings.bar_envelope.release = ms / 1000.0)
+                }
+                "demo_bars" => parse_bool(value).map(|on| settings.demo_bars = on),
+                "pause_when_unfocused" => parse_bool(value).map(|on| settings.focus.pause = on),
+                "duck_when_unfocused" => parse_bool(value).map(|on| settings.focus.duck = on),
+                "duck_volume" => checked_number(key, value).map(|v| settings.focus.duck_volume = v),
+                "throttle_when_unfocused" => {
+                    parse_bool(value).map(|on| settings.focus.throttle = on)
+                }
+                "color_filter" => ColorFilter::from_name(value).map(|f| settings.post.filter = f),
+                "hue_shift_degrees" => {
+                    checked_number(key, value).map(|d| settings.post.hue_shift = d)
+                }
+                "hue_shift_lfo" => parse_bool(value).map(|on| settings.post.hue_lfo = on),
+                "first_run_done" => parse_bool(value).map(|done| settings.first_run_done = done),
+                "sorter_input" => InputPattern::from_key(value).map(|p| settings.sorter_input = p),
+                "visual_latency_ms" => {
+                    checked_number(key, value).map(|ms| settings.visual_latency_ms = ms)
+                }
+                "max_ball_radius_percent" => checked_number(key, value)
+                    .map(|percent| settings.max_ball_radius = percent / 100.0),
+                "collision_model" => {
+                    CollisionModel::from_name(value).map(|m| settings.collision_model = m)
+                }
+                "output_device" => {
+                    settings.output_device = DeviceChoice::from_name(value);
+                    Some(())
+                }
+                "theme_schedule" => parse_bool(value).map(|on| settings.theme_schedule = on),
+                "day_theme_starts" => {
+                    parse_clock_time(value).map(|m| settings.schedule.day_starts = m)
+                }
+                "night_theme_starts" => {
+                    parse_clock_time(value).map(|m| settings.schedule.night_starts = m)
+                }
+                "keep_awake" => parse_bool(value).map(|on| settings.keep_awake = on),
+                "flock_separation" => {
+                    checked_number(key, value).map(|w| settings.flock.separation = w)
+                }
+                "flock_alignment" => {
+                    checked_number(key, value).map(|w| settings.flock.alignment = w)
+                }
+                "flock_cohesion" => checked_number(key, value).map(|w| settings.flock.cohesion = w),
+                "world_arena" => ArenaShape::from_name(value).map(|shape| settings.arena = shape),
+                "audio" => parse_bool(value).map(|on| settings.audio = on),
+                "accent_interval_minutes" => {
+                    checked_number(key, value).map(|m| settings.accent_interval_minutes = m)
+                }
+                "long_exposure_minutes" => {
+                    checked_number(key, value).map(|m| settings.long_exposure.minutes = m)
+                }
+                "long_exposure_blend" => {
+                    BlendMode::from_name(value).map(|b| settings.long_exposure.blend = b)
+                }
+                "long_exposure_show" => {
+                    parse_bool(value).map(|on| settings.long_exposure.show = on)
+                }
+                "fps_cap" => FpsCap::from_name(value).map(|cap| settings.fps_cap = cap),
+                "fixed_timestep" => parse_bool(value).map(|on| settings.fixed_timestep = on),
+                "opaque_hud" => parse_bool(value).map(|on| settings.opaque_hud = on),
+                _ => {
+                    warn!("settings line {}: unknown key `{}`", number + 1, key);
+                    unknown.push(line.to_string());
+                    continue;
+                }
+            };
+            if parsed.is_none() {
+                warn!(
+                    "settings line {}: `{}` is not a valid value for `{}`",
+                    number + 1,
+                    value,
+                    key
+                );
+            }
+        }
+        ParsedSettings {
+            settings,
+            corrections,
+            unknown,
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        format!(
+            "# stimstation settings\n\
+             # Audio bar rise and fall times\n\
+             bar_attack_ms = {}\n\
+             bar_release_ms = {}\n\
+             # Animate the bars with a simulated pattern when nothing is playing\n\
+             demo_bars = {}\n\
+             # While the window is in the background\n\
+             pause_when_unfocused = {}\n\
+             duck_when_unfocused = {}\n\
+             duck_volume = {}\n\
+             throttle_when_unfocused = {}\n\
+             # Final color filter: none, hue_shift, protanopia, deuteranopia, tritanopia\n\
+             color_filter = {}\n\
+             hue_shift_degrees = {}\n\
+             # Keep slowly turning the hue while the hue_shift filter is on\n\
+             hue_shift_lfo = {}\n\
+             # Set after the intro has been shown once\n\
+             first_run_done = {}\n\
+             # Sorter starting arrays: random, reversed, nearly_sorted:N, few_unique:N, sawtooth\n\
+             sorter_input = {}\n\
+             # Delay the visuals to match audio output latency (F8 calibrates)\n\
+             visual_latency_ms = {}\n\
+             # Balls grow with the music up to this share of the screen's shorter side\n\
+             max_ball_radius_percent = {}\n\
+             # Ball bounces: arcade (extra bouncy) or elastic (energy conserving)\n\
+             collision_model = {}\n\
+             # Audio output: default (follows the system) or a device name; O picks one\n\
+             output_device = {}\n\
+             # Warm the colors at night, fading over 20 minutes around each switch (T overrides)\n\
+             theme_schedule = {}\n\
+             day_theme_starts = {}\n\
+             night_theme_starts = {}\n\
+             # The screensaver stays off while audio plays; this keeps it off always\n\
+             keep_awake = {}\n\
+             # World Flock mode: how hard lines keep apart, match heading, and gather\n\
+             flock_separation = {}\n\
+             flock_alignment = {}\n\
+             flock_cohesion = {}\n\
+             # World arena: rect (the frame), circle, or hex (Shift+Space cycles)\n\
+             world_arena = {}\n\
+             # Download and play the soundtrack; false (or --no-audio) starts instantly\n\
+             audio = {}\n\
+             # At most one rare scene accent (shooting star, prism flash, golden ring) this often\n\
+             accent_interval_minutes = {}\n\
+             # F12 long exposure: minutes each (1-30), blend (average or max), shown when done\n\
+             long_exposure_minutes = {}\n\
+             long_exposure_blend = {}\n\
+             long_exposure_show = {}\n\
+             # Frame-rate cap: 30, 60, 120, unlimited, or monitor (vsync)\n\
+             fps_cap = {}\n\
+             # Step the ball physics at a steady 120 Hz whatever the frame rate\n\
+             fixed_timestep = {}\n\
+             # Solid HUD panels instead of see-through ones over the scene\n\
+             opaque_hud = {}\n",
+            self.bar_envelope.attack * 1000.0,
+            self.bar_envelope.release * 1000.0,
+            self.demo_bars,
+            self.focus.pause,
+            self.focus.duck,
+            self.focus.duck_volume,
+            self.focus.throttle,
+            self.post.filter.name(),
+            self.post.hue_shift,
+            self.post.hue_lfo,
+            self.first_run_done,
+            self.sorter_input.key(),
+            self.visual_latency_ms,
+            self.max_ball_radius * 100.0,
+            self.collision_model.name(),
+            self.output_device.name(),
+            self.theme_schedule,
+            format_clock_time(self.schedule.day_starts),
+            format_clock_time(self.schedule.night_starts),
+            self.keep_awake,
+            self.flock.separation,
+            self.flock.alignment,
+            self.flock.cohesion,
+            self.arena.name(),
+            self.audio,
+            self.accent_interval_minutes,
+            self.long_exposure.minutes,
+            self.long_exposure.blend.name(),
+            self.long_exposure.show,
+            self.fps_cap.name(),
+            self.fixed_timestep,
+            self.opaque_hud,
+        )
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Ok(Self::load_checked(path)?.settings)
+    }
+
+    pub fn load_checked(path: &Path) -> io::Result<ParsedSettings> {
+        Ok(Self::parse_checked(&fs::read_to_string(path)?))
+    }
+
+    /// Writes the settings to `path`. Lines with unknown keys already in the
+    /// file are kept at the end, so a newer version's settings survive.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let unknown = match Self::load_checked(path) {
+            Ok(parsed) => parsed.unknown,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        let mut text = self.to_text();
+        if !unknown.is_empty() {
+            text.push_str("# Not recognized by this version; kept as written\n");
+            for line in unknown {
+                text.push_str(&line);
+                text.push('\n');
+            }
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, text)
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "true" | "on" | "1" => Some(true),
+        "false" | "off" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// `<config dir>/stimstation/settings.conf`
+pub fn settings_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("stimstation").join("settings.conf"))
+}
+
+/// Loads and applies the settings file if there is one; defaults stay otherwise
+pub fn load_from_config_dir() {
+    let Some(path) = settings_path() else {
+        return;
+    };
+    match Settings::load_checked(&path) {
+        Ok(parsed) => {
+            parsed.settings.apply();
+            info!("Loaded settings from {}", path.display());
+            report_corrections(&parsed.corrections);
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => warn!("Could not read settings from {}: {}", path.display(), e),
+    }
+}
+
+/// Warns about clamped values in the log and on screen
+fn report_corrections(corrections: &[Correction]) {
+    if corrections.is_empty() {
+        return;
+    }
+    let mut lines = vec!["Some settings were out of range and have been corrected:".to_string()];
+    for correction in corrections {
+        warn!(
+            "settings: `{}` = {} is out of range, using {}",
+            correction.key, correction.value, correction.corrected
+        );
+        lines.push(format!(
+            "{}: {} -> {}",
+            correction.key, correction.value, correction.corrected
+        ));
+    }
+    toast::show_toast(lines);
+}
+
+/// Changes the settings file at `path` with `change`, keeping every other
+/// stored value as it is
+pub fn update_in(path: &Path, change: impl FnOnce(&mut Settings)) -> io::Result<()> {
+    let mut settings = match Settings::load(path) {
+        Ok(settings) => settings,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Settings::DEFAULT,
+        Err(e) => return Err(e),
+    };
+    change(&mut settings);
+    settings.save(path)
+}
+
+/// `update_in` on the config directory's settings file, warning on failure
+pub fn update_in_config_dir(change: impl FnOnce(&mut Settings)) {
+    let Some(path) = settings_path() else {
+        return;
+    };
+    if let Err(e) = update_in(&path, change) {
+        warn!("Could not save settings to {}: {}", path.display(), e);
+    }
+}
+
+/// Sets `first_run_done` in the settings file at `path`, keeping every other
+/// stored value as it is
+pub fn mark_first_run_done_in(path: &Path) -> io::Result<()> {
+    update_in(path, |settings| settings.first_run_done = true)
+}
+
+/// Remembers that the intro has been seen, so later launches go straight in
+pub fn mark_first_run_done() {
+    intro::set_first_run_done(true);
+    update_in_config_dir(|settings| settings.first_run_done = true);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_settings_round_trip_through_file() {
+        let settings = Settings {
+            bar_envelope: BarEnvelope {
+                attack: 0.012,
+                release: 0.4,
+            },
+            focus: FocusSettings {
+                pause: true,
+                duck: false,
+                duck_volume: 0.5,
+                throttle: false,
+            },
+            demo_bars: true,
+            post: PostSettings {
+                filter: ColorFilter::Deuteranopia,
+                hue_shift: 45.0,
+                hue_lfo: true,
+            },
+            first_run_done: true,
+            sorter_input: InputPattern::NearlySorted(8),
+            visual_latency_ms: 85.0,
+            max_ball_radius: 0.25,
+            collision_model: CollisionModel::Elastic,
+            output_device: DeviceChoice::Named("USB Audio: Headset (hw:2,0)".to_string()),
+            theme_schedule: true,
+            schedule: Schedule {
+                day_starts: 6 * 60 + 30,
+                night_starts: 21 * 60 + 45,
+            },
+            keep_awake: true,
+            flock: FlockWeights {
+                separation: 2.5,
+                alignment: 0.4,
+                cohesion: 3.0,
+            },
+            arena: ArenaShape::Hex,
+            audio: false,
+            accent_interval_minutes: 45.0,
+            long_exposure: LongExposureSettings {
+                minutes: 12.0,
+                blend: BlendMode::Max,
+                show: false,
+            },
+            fps_cap: FpsCap::Fps120,
+            fixed_timestep: true,
+            opaque_hud: true,
+        };
+        let path = std::env::temp_dir()
+            .join(format!("stimstation-settings-{}", std::process::id()))
+            .join("settings.conf");
+        settings.save(&path).unwrap();
+        let loaded = Settings::load(&path).unwrap();
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+
+        assert!((loaded.bar_envelope.attack - 0.012).abs() < 1e-6);
+        assert!((loaded.bar_envelope.release - 0.4).abs() < 1e-6);
+        assert_eq!(loaded.focus, settings.focus);
+        assert!(loaded.demo_bars);
+        assert_eq!(loaded.post, settings.post);
+        assert!(loaded.first_run_done);
+        assert_eq!(loaded.sorter_input, InputPattern::NearlySorted(8));
+        assert_eq!(loaded.visual_latency_ms, 85.0);
+        assert!((loaded.max_ball_radius - 0.25).abs() < 1e-6);
+        assert_eq!(loaded.collision_model, CollisionModel::Elastic);
+        assert_eq!(loaded.output_device, settings.output_device);
+        assert!(loaded.theme_schedule);
+        assert_eq!(loaded.schedule, settings.schedule);
+        assert!(loaded.keep_awake);
+        assert_eq!(loaded.flock, settings.flock);
+        assert_eq!(loaded.arena, ArenaShape::Hex);
+        assert!(!loaded.audio);
+        assert_eq!(loaded.fps_cap, FpsCap::Fps120);
+        assert!(loaded.fixed_timestep);
+        assert!(loaded.opaque_hud);
+    }
+
+    #[test]
+    fn test_intro_shows_once_for_a_fresh_config_dir() {
+        let path = std::env::temp_dir()
+            .join(format!("stimstation-first-run-{}", std::process::id()))
+            .join("settings.conf");
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+        let first_launch = match Settings::load(&path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Settings::DEFAULT,
+            other => panic!("expected no settings file, got {:?}", other),
+        };
+        assert!(intro::should_show_intro(first_launch.first_run_done, false));
+
+        // Marking keeps whatever else the user had stored
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "demo_bars = true\n").unwrap();
+        mark_first_run_done_in(&path).unwrap();
+        let second_launch = Settings::load(&path).unwrap();
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+        assert!(second_launch.demo_bars);
+        assert!(!intro::should_show_intro(
+            second_launch.first_run_done,
+            false
+        ));
+        assert!(intro::should_show_intro(second_launch.first_run_done, true));
+    }
+
+    #[test]
+    fn test_parse_skips_bad_lines() {
+        let settings = Settings::parse("bar_attack_ms = fast\nvolume = 3\nbar_release_ms=100\n");
+        assert_eq!(settings.bar_envelope.attack, BarEnvelope::DEFAULT.attack);
+        assert!((settings.bar_envelope.release - 0.1).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_corrupted_numbers_are_clamped_and_reported() {
+        let parsed = Settings::parse_checked(
+            "bar_attack_ms = 0\nbar_release_ms = 1e9\nduck_volume = NaN\nhue_shift_degrees = -inf\n",
+        );
+        let settings = parsed.settings;
+        assert!((settings.bar_envelope.attack - 0.001).abs() < 1e-6);
+        assert!((settings.bar_envelope.release - 5.0).abs() < 1e-6);
+        assert_eq!(
+            settings.focus.duck_volume,
+            FocusSettings::DEFAULT.duck_volume
+        );
+        assert_eq!(settings.post.hue_shift, PostSettings::DEFAULT.hue_shift);
+        let keys: Vec<&str> = parsed.corrections.iter().map(|c| c.key).collect();
+        assert_eq!(
+            keys,
+            [
+                "bar_attack_ms",
+                "bar_release_ms",
+                "duck_volume",
+                "hue_shift_degrees"
+            ]
+        );
+
+        // In-range values and missing keys need no correction
+        let parsed = Settings::parse_checked("duck_volume = 0.5\n");
+        assert!(parsed.corrections.is_empty());
+        assert_eq!(parsed.settings.bar_envelope, BarEnvelope::DEFAULT);
+        for setting in NUMERIC_SETTINGS {
+            assert_eq!(setting.clamp(setting.default), setting.default);
+        }
+    }
+
+    #[test]
+    fn test_unknown_keys_survive_a_re_save() {
+        let path = std::env::temp_dir()
+            .join(format!("stimstation-unknown-{}", std::process::id()))
+            .join("settings.conf");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "render_scale = 0.5\ndemo_bars = true\n").unwrap();
+        mark_first_run_done_in(&path).unwrap();
+        mark_first_run_done_in(&path).unwrap();
+        let parsed = Settings::load_checked(&path).unwrap();
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+
+        assert!(parsed.settings.demo_bars);
+        assert!(parsed.settings.first_run_done);
+        assert_eq!(parsed.unknown, ["render_scale = 0.5"]);
+    }
+}
@@ -0,0 +1,392 @@
+//! Undo and redo for settings changed while the app runs. Each change is
+//! kept as the `key = value` lines it altered, before and after, so undoing
+//! one puts back only those keys. Quick repeats on the same key fold into one
+//! entry, a preset load is one entry however much it changes, and the oldest
+//! entries fall off once `HISTORY_CAPACITY` is reached.
+
+use crate::core::presets::{self, Preset};
+use crate::core::settings::{self, Settings};
+use crate::ui::toast;
+use std::collections::VecDeque;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Most entries kept for undo
+pub const HISTORY_CAPACITY: usize = 100;
+/// Changes to the same key closer together than this are one entry, in seconds
+pub const COALESCE_SECONDS: f32 = 0.5;
+
+/// One setting's value before and after, as written in the settings file
+#[derive(Debug, Clone, PartialEq)]
+pub struct SettingChange {
+    pub key: String,
+    pub before: String,
+    pub after: String,
+}
+
+/// Something undo can take back in one step
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub changes: Vec<SettingChange>,
+    /// The look before and after, when the entry is a preset load
+    pub preset: Option<(Preset, Preset)>,
+    /// Time of the latest change folded into the entry, in seconds
+    pub time: f32,
+}
+
+impl Entry {
+    /// More than one setting, or a preset; never coalesced
+    pub fn is_composite(&self) -> bool {
+        self.preset.is_some() || self.changes.len() != 1
+    }
+
+    /// Toast lines for undoing (or, with `redo`, redoing) the entry
+    pub fn describe(&self, redo: bool) -> Vec<String> {
+        let verb = if redo { "redo" } else { "undo" };
+        let describe_change = |change: &SettingChange| {
+            // Undo goes from the value set back to the one before it
+            let (from, to) = if redo {
+                (&change.before, &change.after)
+            } else {
+                (&change.after, &change.before)
+            };
+            format!("{} {} \u{2192} {}", change.key, from, to)
+        };
+        match (&self.preset, self.changes.as_slice()) {
+            (None, [change]) => vec![format!("{}: {}", verb, describe_change(change))],
+            (preset, changes) => {
+                let mut lines = vec![match preset {
+                    Some((_, to)) => format!("{}: preset {}", verb, to.name),
+                    None => format!("{}: {} settings", verb, changes.len()),
+                }];
+                lines.extend(changes.iter().map(describe_change));
+                lines
+            }
+        }
+    }
+}
+
+/// Entries to undo, newest last, and the ones undone since the last change
+#[derive(Debug, Clone, PartialEq)]
+pub struct History {
+    undo: VecDeque<Entry>,
+    redo: Vec<Entry>,
+    capacity: usize,
+}
+
+impl History {
+    pub const fn new(capacity: usize) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            capacity,
+        }
+    }
+
+    /// Records `changes` made at `time`. A single change to the key the last
+    /// entry changed, within `COALESCE_SECONDS` of it, extends that entry
+    /// instead. Clears what could be redone.
+    pub fn record(&mut self, changes: Vec<SettingChange>, time: f32) {
+        if changes.is_empty() {
+            return;
+        }
+        self.redo.clear();
+        if let (Some(last), [change]) = (self.undo.back_mut(), changes.as_slice()) {
+            if !last.is_composite()
+                && last.changes[0].key == change.key
+                && time - last.time < COALESCE_SECONDS
+            {
+                last.changes[0].after = change.after.clone();
+                last.time = time;
+                if last.changes[0].after == last.changes[0].before {
+                    // Back where it started; nothing left to undo
+                    self.undo.pop_back();
+                }
+                return;
+            }
+        }
+        self.push(Entry {
+            changes,
+            preset: None,
+            time,
+        });
+    }
+
+    /// Records a preset load from `from` to `to` as one entry, with the
+    /// settings it changed
+    pub fn record_preset(
+        &mut self,
+        from: Preset,
+        to: Preset,
+        changes: Vec<SettingChange>,
+        time: f32,
+    ) {
+        self.redo.clear();
+        self.push(Entry {
+            changes,
+            preset: Some((from, to)),
+            time,
+        });
+    }
+
+    fn push(&mut self, entry: Entry) {
+        if self.undo.len() >= self.capacity {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(entry);
+    }
+
+    /// Takes the newest entry for undoing; it can be redone until the next change
+    pub fn undo(&mut self) -> Option<Entry> {
+        let entry = self.undo.pop_back()?;
+        self.redo.push(entry.clone());
+        Some(entry)
+    }
+
+    /// Takes the entry undone last for redoing
+    pub fn redo(&mut self) -> Option<Entry> {
+        let entry = self.redo.pop()?;
+        self.undo.push_back(entry.clone());
+        Some(entry)
+    }
+
+    pub fn undo_len(&self) -> usize {
+        self.undo.len()
+    }
+
+    pub fn redo_len(&self) -> usize {
+        self.redo.len()
+    }
+}
+
+/// `key = value` pairs of `settings`, in file order
+fn values(settings: &Settings) -> Vec<(String, String)> {
+    settings
+        .to_text()
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.split_once(" = "))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+/// The settings whose written values differ between `before` and `after`
+pub fn diff(before: &Settings, after: &Settings) -> Vec<SettingChange> {
+    if before == after {
+        return Vec::new();
+    }
+    values(before)
+        .into_iter()
+        .zip(values(after))
+        .filter(|((_, was), (_, now))| was != now)
+        .map(|((key, before), (_, after))| SettingChange { key, before, after })
+        .collect()
+}
+
+/// `settings` with the keys in `changes` set to their before values, or with
+/// `redo` to their after values. Keys `only_if` rejects are left alone.
+pub fn with_values(
+    settings: &Settings,
+    changes: &[SettingChange],
+    redo: bool,
+    only_if: impl Fn(&SettingChange, &str) -> bool,
+) -> Settings {
+    let mut text = String::new();
+    for (key, value) in values(settings) {
+        let change = changes
+            .iter()
+            .find(|change| change.key == key && only_if(change, &value));
+        let value = match change {
+            Some(change) if redo => &change.after,
+            Some(change) => &change.before,
+            None => &value,
+        };
+        text.push_str(&format!("{} = {}\n", key, value));
+    }
+    Settings::parse(&text)
+}
+
+static HISTORY: Mutex<History> = Mutex::new(History::new(HISTORY_CAPACITY));
+
+fn history() -> MutexGuard<'static, History> {
+    HISTORY.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Records what changed between `before` and the settings now in effect
+pub fn record_since(before: &Settings, time: f32) {
+    let changes = diff(before, &Settings::current());
+    history().record(changes, time)
+}
+
+/// Records a preset load from the look in effect to `to`
+pub fn record_preset_load(from: Preset, to: Preset, time: f32) {
+    let current = Settings::current();
+    let target = Settings {
+        post: to.post,
+        bar_envelope: to.bar_envelope,
+        ..current.clone()
+    };
+    history().record_preset(from, to, diff(&current, &target), time)
+}
+
+/// Undoes the newest entry and shows what it did. Returns false when there
+/// was nothing to undo.
+pub fn undo() -> bool {
+    let entry = history().undo();
+    entry.map(|entry| restore(&entry, false)).is_some()
+}
+
+/// Redoes the entry undone last and shows what it did
+pub fn redo() -> bool {
+    let entry = history().redo();
+    entry.map(|entry| restore(&entry, true)).is_some()
+}
+
+fn restore(entry: &Entry, redo: bool) {
+    if let Some((from, to)) = entry.preset {
+        presets::stop_morph();
+        if redo { to } else { from }.apply();
+    }
+    with_values(&Settings::current(), &entry.changes, redo, |_, _| true).apply();
+    // Values the file was saved with follow along; others stay as stored
+    settings::update_in_config_dir(|stored| {
+        *stored = with_values(stored, &entry.changes, redo, |change, value| {
+            value == if redo { &change.before } else { &change.after }
+        });
+    });
+    toast::show_toast(entry.describe(redo));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(key: &str, before: &str, after: &str) -> Vec<SettingChange> {
+        vec![SettingChange {
+            key: key.to_string(),
+            before: before.to_string(),
+            after: after.to_string(),
+        }]
+    }
+
+    #[test]
+    fn test_quick_changes_to_one_key_coalesce() {
+        let mut history = History::new(HISTORY_CAPACITY);
+        history.record(change("fps_cap", "60", "120"), 1.0);
+        history.record(change("fps_cap", "120", "unlimited"), 1.3);
+        // The window runs from the latest change, so steady hammering stays one entry
+        history.record(change("fps_cap", "unlimited", "30"), 1.7);
+        assert_eq!(history.undo_len(), 1);
+        // A pause, or another key, starts a new entry
+        history.record(change("fps_cap", "30", "60"), 2.5);
+        history.record(change("color_filter", "none", "hue_shift"), 2.6);
+        assert_eq!(history.undo_len(), 3);
+
+        assert_eq!(
+            history.undo().unwrap().changes,
+            change("color_filter", "none", "hue_shift")
+        );
+        assert_eq!(
+            history.undo().unwrap().changes,
+            change("fps_cap", "30", "60")
+        );
+        let first = history.undo().unwrap();
+        assert_eq!(first.changes, change("fps_cap", "60", "30"));
+        assert_eq!(first.describe(false), ["undo: fps_cap 30 \u{2192} 60"]);
+        assert!(history.undo().is_none());
+
+        // Redo replays the steps in order
+        assert_eq!(history.redo().unwrap().changes[0].after, "30");
+        assert_eq!(history.redo().unwrap().changes[0].after, "60");
+        assert_eq!(history.redo_len(), 1);
+        // A new change drops what was left to redo
+        history.record(change("audio", "true", "false"), 10.0);
+        assert!(history.redo().is_none());
+    }
+
+    #[test]
+    fn test_changing_a_key_back_within_the_window_leaves_nothing() {
+        let mut history = History::new(HISTORY_CAPACITY);
+        history.record(change("opaque_hud", "false", "true"), 0.0);
+        history.record(change("opaque_hud", "true", "false"), 0.2);
+        assert_eq!(history.undo_len(), 0);
+    }
+
+    #[test]
+    fn test_oldest_entries_are_evicted_at_capacity() {
+        let mut history = History::new(3);
+        for i in 0..5 {
+            let (before, after) = (i.to_string(), (i + 1).to_string());
+            history.record(change("fps_cap", &before, &after), i as f32);
+        }
+        assert_eq!(history.undo_len(), 3);
+        let mut undone = Vec::new();
+        while let Some(entry) = history.undo() {
+            undone.push(entry.changes[0].before.clone());
+        }
+        assert_eq!(undone, ["4", "3", "2"]);
+    }
+
+    #[test]
+    fn test_a_preset_load_is_one_composite_entry() {
+        let mut history = History::new(HISTORY_CAPACITY);
+        let before = Settings::DEFAULT;
+        let mut after = before.clone();
+        after.post.hue_shift = 200.0;
+        after.bar_envelope.release = 0.6;
+        let changes = diff(&before, &after);
+        assert_eq!(changes.len(), 2);
+
+        let [calm, _, storm, _] = presets::BUILT_IN;
+        history.record(change("hue_shift_degrees", "0", "10"), 0.0);
+        history.record_preset(calm, storm, changes.clone(), 0.1);
+        // Never folded into, even on one of its keys right after
+        history.record(change("hue_shift_degrees", "200", "210"), 0.2);
+        assert_eq!(history.undo_len(), 3);
+
+        history.undo();
+        let entry = history.undo().unwrap();
+        assert!(entry.is_composite());
+        assert_eq!(entry.preset, Some((calm, storm)));
+        assert_eq!(
+            entry.describe(false)[0],
+            format!("undo: preset {}", storm.name)
+        );
+        // Undoing restores every setting it changed in one step
+        assert_eq!(
+            with_values(&after, &entry.changes, false, |_, _| true),
+            before
+        );
+        assert_eq!(
+            with_values(&before, &entry.changes, true, |_, _| true),
+            after
+        );
+    }
+
+    #[test]
+    fn test_restoring_leaves_other_keys_alone() {
+        let before = Settings::DEFAULT;
+        let after = Settings {
+            fixed_timestep: true,
+            ..before.clone()
+        };
+        let changes = diff(&before, &after);
+        assert_eq!(changes, change("fixed_timestep", "false", "true"));
+        let elsewhere = Settings {
+            fixed_timestep: true,
+            opaque_hud: true,
+            ..before.clone()
+        };
+        let undone = with_values(&elsewhere, &changes, false, |_, _| true);
+        assert!(!undone.fixed_timestep);
+        assert!(undone.opaque_hud);
+        // Keys whose value has moved on since are skipped when asked
+        let moved_on = with_values(&before, &changes, true, |change, value| {
+            value == change.before
+        });
+        assert!(moved_on.fixed_timestep);
+        let skipped = with_values(&elsewhere, &changes, true, |change, value| {
+            value == change.before
+        });
+        assert_eq!(skipped, elsewhere);
+    }
+}
@@ -0,0 +1,81 @@
+use crate::algorithms::sorter::SorterSnapshot;
+use crate::algorithms::sorter_manager;
+use crate::core::scenes;
+use crate::graphics::theme::{self, Theme};
+use crate::physics::fireworks;
+use crate::physics::physics::{self, BallSnapshot};
+use crate::physics::world::{self, WorldSnapshot};
+
+/// Implemented by stateful systems that can be captured into memory and restored later.
+/// Derived data (caches, smoothing buffers) is not captured; it is rebuilt on restore.
+pub trait Snapshottable {
+    type Snapshot: Clone;
+
+    fn snapshot(&self) -> Self::Snapshot;
+    fn restore(&mut self, snapshot: &Self::Snapshot);
+}
+
+/// In-memory copy of every major stateful system, used for quick A/B comparisons.
+#[derive(Debug, Clone)]
+pub struct AppSnapshot {
+    /// Id of the scene on screen
+    pub scene: &'static str,
+    /// Theme in effect; its name identifies it
+    pub theme: Theme,
+    pub world: Option<WorldSnapshot>,
+    pub balls: Option<BallSnapshot>,
+    pub sorters: [Option<SorterSnapshot>; 4],
+}
+
+/// Captures the current scene, theme, world, balls, and sorters.
+pub fn capture() -> AppSnapshot {
+    AppSnapshot {
+        scene: scenes::active_scene().id,
+        theme: theme::current_theme(),
+        world: world::snapshot_world(),
+        balls: physics::snapshot_balls(),
+        sorters: sorter_manager::snapshot_sorters(),
+    }
+}
+
+/// Restores a previously captured state. Systems missing from the snapshot are left as they are.
+/// The theme is picked as if by hand, so a running schedule is suspended. Fireworks set off
+/// since the capture are dropped.
+pub fn restore(snapshot: &AppSnapshot) {
+    if let Some(scene) = scenes::find_scene(snapshot.scene) {
+        scene.enter();
+    }
+    if theme::current_theme() != snapshot.theme {
+        theme::with_theme_state(|themes| themes.pick(snapshot.theme));
+    }
+    if let Some(world_snapshot) = &snapshot.world {
+        world::restore_world(world_snapshot);
+    }
+    if let Some(balls) = &snapshot.balls {
+        physics::restore_balls(balls);
+    }
+    sorter_manager::restore_sorters(&snapshot.sorters);
+    fireworks::clear_fireworks();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restore_brings_back_the_scene_and_theme() {
+        scenes::find_scene("tunnel").unwrap().enter();
+        theme::with_theme_state(|themes| themes.pick(Theme::NIGHT));
+        let saved = capture();
+        assert_eq!(saved.scene, "tunnel");
+        assert_eq!(saved.theme.name, "Night");
+
+        scenes::find_scene("maze").unwrap().enter();
+        theme::with_theme_state(|themes| themes.pick(Theme::DAY));
+        restore(&saved);
+
+        assert_eq!(scenes::active_scene().id, "tunnel");
+        assert_eq!(theme::current_theme(), Theme::NIGHT);
+        assert_eq!(capture().scene, saved.scene);
+    }
+}
@@ -0,0 +1,175 @@
+//! What one `StimStation` owns: which scene is on, the scenes' own state, the
+//! balls and their corner hits, the audio bars, and the per-frame state of
+//! the orchestrator, the World, the forces, the fireworks, and the shake. A
+//! thread drives one station at a time; the modules reach its state through
+//! `current`, and a `StimStation` makes its own current before each call
+//! into them.
+//!
+//! Settings, the theme, the edge sorters, the overlays, and audio playback
+//! are not in here; every station in the process shares them.
+
+use crate::algorithms::maze::MazeState;
+use crate::audio::audio_integration::AudioIntegration;
+use crate::core::bufpool::PooledBuf;
+use crate::core::frame_cap::PaneLayers;
+use crate::core::orchestrator::PhaseTimings;
+use crate::core::timestep::{FixedStep, FIXED_RATE_HZ};
+use crate::graphics::rain::RainState;
+use crate::graphics::screen_shake::ShakeState;
+use crate::graphics::shatter::ShatterState;
+use crate::graphics::tunnel::TunnelState;
+use crate::physics::arena::ArenaShape;
+use crate::physics::detect_corner::CornerTracker;
+use crate::physics::fireworks::Fireworks;
+use crate::physics::flock::FlockWeights;
+use crate::physics::forces::ForceField;
+use crate::physics::physics::BallState;
+use crate::physics::softbody::SoftBodyState;
+use crate::physics::world::{AudioWidthSettings, WorldState};
+use crate::types::Position;
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+pub struct StationState {
+    pub world_enabled: AtomicBool,
+    pub softbody_enabled: AtomicBool,
+    pub tunnel_enabled: AtomicBool,
+    pub maze_enabled: AtomicBool,
+    pub rain_enabled: AtomicBool,
+    pub shatter_enabled: AtomicBool,
+    /// Clean mode hides all HUD and edge elements so only the central visualization is drawn
+    pub clean_mode: AtomicBool,
+    pub world: Mutex<Option<WorldState>>,
+    /// Mouse drags paint strokes instead of spawning World lines
+    pub paint_mode: AtomicBool,
+    pub audio_width: Mutex<AudioWidthSettings>,
+    pub flock_weights: Mutex<FlockWeights>,
+    pub arena_shape: Mutex<ArenaShape>,
+    pub softbody: Mutex<Option<SoftBodyState>>,
+    pub tunnel: Mutex<Option<TunnelState>>,
+    pub maze: Mutex<Option<MazeState>>,
+    pub rain: Mutex<Option<RainState>>,
+    pub shatter: Mutex<Option<ShatterState>>,
+    /// Cursor position in frame pixels for the Shatter scene, if it is over the frame
+    pub shatter_cursor: Mutex<Option<Position>>,
+    pub balls: Mutex<Option<BallState>>,
+    pub corners: Mutex<CornerTracker>,
+    /// Steps the balls when the fixed timestep is on
+    pub clock: Mutex<FixedStep>,
+    pub force_field: Mutex<ForceField>,
+    pub fireworks: Mutex<Option<Fireworks>>,
+    pub shake: Mutex<ShakeState>,
+    /// Each pane's last scene draw, kept for the frames its cap skips
+    pub scene_layers: Mutex<PaneLayers<PooledBuf>>,
+    /// Bumped by input so every pane redraws its scene once, whatever its cap
+    pub forced_renders: AtomicU32,
+    /// Created by `integration::initialize_audio_integration_with`
+    pub audio: Mutex<Option<AudioIntegration>>,
+    /// F3 debug overlay with live buffer usage
+    pub debug_overlay: AtomicBool,
+    /// Rays from the two balls add up like colored light instead of overdrawing
+    pub light_mixing: AtomicBool,
+    /// How many times the window's size the frame being drawn is, as f32 bits
+    pub render_scale: AtomicU32,
+    pub phase_timings: Mutex<PhaseTimings>,
+    /// Full-frame clears done for the last frame, shown in the debug overlay
+    pub frame_clears: AtomicUsize,
+    /// Scene shown by the last update, to notice switches
+    pub last_scene: Mutex<Option<&'static str>>,
+    /// Set once a `StimStation` has taken this state for its own
+    claimed: AtomicBool,
+}
+
+impl StationState {
+    fn new() -> Self {
+        Self {
+            world_enabled: AtomicBool::new(false),
+            softbody_enabled: AtomicBool::new(false),
+            tunnel_enabled: AtomicBool::new(false),
+            maze_enabled: AtomicBool::new(false),
+            rain_enabled: AtomicBool::new(false),
+            shatter_enabled: AtomicBool::new(false),
+            clean_mode: AtomicBool::new(false),
+            world: Mutex::new(None),
+            paint_mode: AtomicBool::new(false),
+            audio_width: Mutex::new(AudioWidthSettings::DEFAULT),
+            flock_weights: Mutex::new(FlockWeights::DEFAULT),
+            arena_shape: Mutex::new(ArenaShape::Rect),
+            softbody: Mutex::new(None),
+            tunnel: Mutex::new(None),
+            maze: Mutex::new(None),
+            rain: Mutex::new(None),
+            shatter: Mutex::new(None),
+            shatter_cursor: Mutex::new(None),
+            balls: Mutex::new(None),
+            corners: Mutex::new(CornerTracker::new()),
+            clock: Mutex::new(FixedStep::new(FIXED_RATE_HZ)),
+            force_field: Mutex::new(ForceField::default()),
+            fireworks: Mutex::new(None),
+            shake: Mutex::new(ShakeState::new()),
+            scene_layers: Mutex::new(PaneLayers::new()),
+            forced_renders: AtomicU32::new(0),
+            audio: Mutex::new(None),
+            debug_overlay: AtomicBool::new(false),
+            light_mixing: AtomicBool::new(false),
+            render_scale: AtomicU32::new(1.0f32.to_bits()),
+            phase_timings: Mutex::new(PhaseTimings::default()),
+            frame_clears: AtomicUsize::new(0),
+            last_scene: Mutex::new(None),
+            claimed: AtomicBool::new(false),
+        }
+    }
+}
+
+thread_local! {
+    /// The state the modules act on from this thread. Until a `StimStation`
+    /// claims it, it is the thread's own, so start-up flags set before the
+    /// station exists carry over to it.
+    static CURRENT: RefCell<Arc<StationState>> = RefCell::new(Arc::new(StationState::new()));
+}
+
+/// The state of the station this thread is driving
+pub fn current() -> Arc<StationState> {
+    CURRENT.with(|current| current.borrow().clone())
+}
+
+/// Locks one part of a station's state. A panic while it was held leaves
+/// the part as it was, which is still fit to draw.
+pub fn lock<T>(part: &Mutex<T>) -> MutexGuard<'_, T> {
+    part.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// State for a new station: the thread's current state if no station has
+/// claimed it yet, otherwise a fresh one, which becomes current
+pub fn claim() -> Arc<StationState> {
+    let state = current();
+    if !state.claimed.swap(true, Ordering::SeqCst) {
+        return state;
+    }
+    let fresh = Arc::new(StationState::new());
+    fresh.claimed.store(true, Ordering::SeqCst);
+    make_current(&fresh);
+    fresh
+}
+
+/// Points this thread's module calls at `state`
+pub fn make_current(state: &Arc<StationState>) {
+    CURRENT.with(|current| {
+        let mut current = current.borrow_mut();
+        if !Arc::ptr_eq(&current, state) {
+            *current = Arc::clone(state);
+        }
+    });
+}
+
+/// Gives the thread a fresh state if `state`, whose station is going away,
+/// is still current
+pub fn release(state: &Arc<StationState>) {
+    CURRENT.with(|current| {
+        let mut current = current.borrow_mut();
+        if Arc::ptr_eq(&current, state) {
+            *current = Arc::new(StationState::new());
+        }
+    });
+}
@@ -0,0 +1,135 @@
+//! Optional fixed timestep for the ball physics. With it on, the balls are
+//! stepped at `FIXED_RATE_HZ` whatever the frame rate, and drawn partway
+//! between their last two steps so the motion stays smooth when the two
+//! rates don't divide evenly.
+
+use crate::core::station_state;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub const FIXED_RATE_HZ: f32 = 120.0;
+/// Most steps run for one frame; a longer stall drops time instead of
+/// spending the next frames catching up
+const MAX_STEPS_PER_FRAME: usize = 8;
+
+static FIXED_TIMESTEP: AtomicBool = AtomicBool::new(false);
+
+pub fn is_fixed_timestep() -> bool {
+    FIXED_TIMESTEP.load(Ordering::Relaxed)
+}
+
+pub fn set_fixed_timestep(enabled: bool) {
+    FIXED_TIMESTEP.store(enabled, Ordering::Relaxed);
+}
+
+/// The steps to run for one frame
+#[derive(Debug, Clone, PartialEq)]
+pub struct Steps {
+    /// Simulation time of each step, oldest first
+    pub times: Vec<f32>,
+    /// How far the frame is past the last step, as a fraction of a step
+    pub alpha: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FixedStep {
+    step: f32,
+    last_time: Option<f32>,
+    /// Frame time not yet simulated
+    accumulator: f32,
+    sim_time: f32,
+}
+
+impl FixedStep {
+    pub const fn new(rate_hz: f32) -> Self {
+        Self {
+            step: 1.0 / rate_hz,
+            last_time: None,
+            accumulator: 0.0,
+            sim_time: 0.0,
+        }
+    }
+
+    /// Advances to the frame at `time`. The first frame, and time running
+    /// backwards as after a restored snapshot, start over with one step.
+    pub fn advance(&mut self, time: f32) -> Steps {
+        let elapsed = self.last_time.map(|last| time - last);
+        self.last_time = Some(time);
+        let Some(elapsed) = elapsed.filter(|&elapsed| elapsed >= 0.0) else {
+            self.accumulator = 0.0;
+            self.sim_time = time;
+            return Steps {
+                times: vec![time],
+                alpha: 1.0,
+            };
+        };
+        self.accumulator += elapsed;
+        let mut times = Vec::new();
+        while self.accumulator >= self.step && times.len() < MAX_STEPS_PER_FRAME {
+            self.sim_time += self.step;
+            self.accumulator -= self.step;
+            times.push(self.sim_time);
+        }
+        self.accumulator = self.accumulator.rem_euclid(self.step);
+        Steps {
+            times,
+            alpha: self.accumulator / self.step,
+        }
+    }
+}
+
+/// Steps the clock of the station being driven to the frame at `time`
+pub fn advance(time: f32) -> Steps {
+    station_state::lock(&station_state::current().clock).advance(time)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interpolated_motion_is_even_at_mismatched_rates() {
+        // A body moving at a steady 600 px/s, stepped at 120 Hz, drawn at 50 fps
+        let speed = 600.0;
+        let mut clock = FixedStep::new(FIXED_RATE_HZ);
+        let (mut previous, mut current) = (0.0f32, 0.0f32);
+        let mut step_counts = Vec::new();
+        let mut drawn = Vec::new();
+        for frame in 0..100 {
+            let steps = clock.advance(frame as f32 / 50.0);
+            for _ in &steps.times {
+                previous = current;
+                current += speed / FIXED_RATE_HZ;
+            }
+            step_counts.push(steps.times.len());
+            drawn.push(previous + (current - previous) * steps.alpha);
+        }
+        // Frames alternate between two and three steps...
+        assert!(step_counts[1..].iter().all(|&n| n == 2 || n == 3));
+        assert!(step_counts.contains(&2) && step_counts.contains(&3));
+        // ...but the drawn position moves the same 12 px every frame
+        for pair in drawn[2..].windows(2) {
+            assert!((pair[1] - pair[0] - 12.0).abs() < 0.05, "{:?}", pair);
+        }
+        // Stepping the position once per frame instead would jump unevenly
+        let uneven: Vec<f32> = step_counts[2..]
+            .iter()
+            .map(|&n| n as f32 * speed / FIXED_RATE_HZ)
+            .collect();
+        assert!(uneven.iter().any(|&d| (d - 12.0).abs() > 2.0));
+    }
+
+    #[test]
+    fn test_stalls_and_rewinds_do_not_pile_up_steps() {
+        let mut clock = FixedStep::new(FIXED_RATE_HZ);
+        assert_eq!(clock.advance(5.0).times, [5.0]);
+        // Half a second is 60 steps; only a frame's worth run
+        let steps = clock.advance(5.5);
+        assert_eq!(steps.times.len(), MAX_STEPS_PER_FRAME);
+        assert!((0.0..1.0).contains(&steps.alpha));
+        assert!(clock.advance(5.5 + 1.0 / 120.0).times.len() <= 1);
+        // A rewind starts over at the new time
+        assert_eq!(clock.advance(1.0).times, [1.0]);
+        // Frames faster than the step rate run no step at all
+        assert!(clock.advance(1.004).times.is_empty());
+    }
+}
@@ -1,3 +1,7 @@
+use crate::graphics::background::BackgroundKind;
+use crate::physics::arena::ArenaShape;
+use crate::physics::flock::Flock;
+use crate::physics::waves::WaveField;
 use glam::Vec2;
 use palette::{Hsv, IntoColor, Srgb};
 use rand::prelude::*;
@@ -17,6 +21,7 @@ pub enum VisualMode {
     Vortex,
     Waves,
     Rainbow,
+    Flock,
 }
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub enum ActiveSide {
@@ -35,15 +40,18 @@ pub struct Line {
     pub vel: [Velocity; 2],
     pub color: Color,
     pub width: f32,
+    /// Width eased toward the audio, which the line is drawn at; None until
+    /// the World first eases it. Kept on the line so it stays with the line
+    /// when others are removed.
+    pub audio_width: Option<f32>,
     pub length: f32,
     pub cycle_speed: f32,
     pub cycle_offset: f32,
 }
-pub type SimplePos = (f32, f32);
 #[derive(Debug)]
 pub struct SimpleLine {
-    pub pos: [SimplePos; 2],
-    pub vel: [SimplePos; 2],
+    pub pos: [Position; 2],
+    pub vel: [Velocity; 2],
     pub color: SimpleColor,
     pub width: f32,
     pub length: f32,
@@ -60,8 +68,8 @@ pub struct Particle {
 }
 #[derive(Debug)]
 pub struct SimpleParticle {
-    pub pos: SimplePos,
-    pub vel: SimplePos,
+    pub pos: Position,
+    pub vel: Velocity,
     pub color: SimpleColor,
     pub life: f32,
     pub size: f32,
@@ -73,9 +81,18 @@ pub struct World {
     pub mouse_pos: Option<Position>,
     pub mouse_active: bool,
     pub background_color: Color,
+    pub background: BackgroundKind,
     pub mode: VisualMode,
     pub target_line_count: usize,
+    /// Target line count to go back to when the Waves mode is left
+    pub lines_before_waves: Option<usize>,
     pub start_time: Instant,
+    /// The interference sources driving the Waves mode
+    pub waves: WaveField,
+    /// Neighbor lookup and cursor role for the Flock mode
+    pub flock: Flock,
+    /// Shape lines and particles are kept inside
+    pub arena: ArenaShape,
 }
 pub type SimpleColor = [u8; 3];
 #[derive(Debug)]
@@ -83,7 +100,7 @@ pub struct SimpleWorld {
     pub lines: Vec<SimpleLine>,
     pub rng: ThreadRng,
     pub start_time: Instant,
-    pub mouse_pos: Option<SimplePos>,
+    pub mouse_pos: Option<Position>,
     pub mouse_active: bool,
     pub background_color: SimpleColor,
     pub mode: VisualMode,
@@ -127,11 +144,18 @@ impl Line {
                 rng.gen_range(150..255),
             ),
             width: rng.gen_range(1.0..3.5),
+            audio_width: None,
             length,
             cycle_speed: rng.gen_range(0.2..1.5),
             cycle_offset: rng.gen_range(0.0..10.0),
         }
     }
+
+    /// Width to draw the line at: its audio width, or its base width before
+    /// it has one
+    pub fn drawn_width(&self) -> f32 {
+        self.audio_width.unwrap_or(self.width)
+    }
 }
 impl Particle {
     pub fn new(pos: Position, rng: &mut impl rand::Rng) -> Self {
@@ -0,0 +1,227 @@
+//! Keeps the window rendering through GPU surface trouble. A lost or
+//! outdated surface (driver hiccups, suspend/resume) and a loop that stops
+//! presenting frames both lead to recreating the surface, a few times with
+//! backoff, before the app gives up and exits with the error.
+
+use log::{info, warn};
+use pixels::wgpu::SurfaceError;
+use std::time::{Duration, Instant};
+
+/// Surface recreations tried before giving up
+pub const RECOVERY_ATTEMPTS: u32 = 3;
+/// Wait before each recreation, counted from the previous one; about a second in all
+const RECOVERY_BACKOFF: [Duration; RECOVERY_ATTEMPTS as usize] = [
+    Duration::ZERO,
+    Duration::from_millis(300),
+    Duration::from_millis(700),
+];
+/// No frame presented for this long counts as a stalled loop
+pub const STALL_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Why a frame could not be presented
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderFault {
+    SurfaceLost,
+    SurfaceOutdated,
+    Timeout,
+    /// Nothing presented for `STALL_TIMEOUT` while not paused
+    Stalled,
+    /// Anything a new surface won't fix, e.g. running out of GPU memory
+    Fatal,
+}
+
+impl RenderFault {
+    pub fn classify(err: &pixels::Error) -> Self {
+        match err {
+            pixels::Error::Surface(SurfaceError::Lost) => RenderFault::SurfaceLost,
+            pixels::Error::Surface(SurfaceError::Outdated) => RenderFault::SurfaceOutdated,
+            pixels::Error::Surface(SurfaceError::Timeout) => RenderFault::Timeout,
+            _ => RenderFault::Fatal,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RenderFault::SurfaceLost => "surface_lost",
+            RenderFault::SurfaceOutdated => "surface_outdated",
+            RenderFault::Timeout => "timeout",
+            RenderFault::Stalled => "stalled",
+            RenderFault::Fatal => "fatal",
+        }
+    }
+
+    pub fn is_recoverable(&self) -> bool {
+        *self != RenderFault::Fatal
+    }
+}
+
+/// What the event loop should do about a fault
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchdogAction {
+    /// Keep going; a recreation is not due yet
+    Continue,
+    /// Recreate the surface and pixel buffer now
+    Recreate,
+    /// Recovery is exhausted or impossible; exit with the error
+    GiveUp,
+}
+
+/// Tracks presents and failures for the event loop, which does the recreating
+#[derive(Debug, Clone)]
+pub struct RenderWatchdog {
+    last_present: Instant,
+    /// Recreations since the last presented frame
+    attempts: u32,
+    last_attempt: Option<Instant>,
+}
+
+impl RenderWatchdog {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            last_present: now,
+            attempts: 0,
+            last_attempt: None,
+        }
+    }
+
+    /// Recreations tried since the last presented frame
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// A frame reached the screen; any recovery in progress worked
+    pub fn presented(&mut self, now: Instant) {
+        if self.attempts > 0 {
+            info!("render: recovered after {} attempt(s)", self.attempts);
+        }
+        self.last_present = now;
+        self.attempts = 0;
+        self.last_attempt = None;
+    }
+
+    /// Decides what to do about a failed present. Only the first failure of
+    /// an episode is logged, so a broken surface doesn't flood the log.
+    pub fn failed(&mut self, fault: RenderFault, now: Instant) -> WatchdogAction {
+        if !fault.is_recoverable() || self.attempts >= RECOVERY_ATTEMPTS {
+            return WatchdogAction::GiveUp;
+        }
+        let due = self
+            .last_attempt
+            .map_or(now, |at| at + RECOVERY_BACKOFF[self.attempts as usize]);
+        if now < due {
+            return WatchdogAction::Continue;
+        }
+        if self.attempts == 0 {
+            warn!(
+                "render: fault={} action=recreate_surface max_attempts={} window={:?}",
+                fault.name(),
+                RECOVERY_ATTEMPTS,
+                RECOVERY_BACKOFF.iter().sum::<Duration>()
+            );
+        }
+        self.attempts += 1;
+        self.last_attempt = Some(now);
+        WatchdogAction::Recreate
+    }
+
+    /// Checks for a loop that stopped presenting. While paused nothing is
+    /// expected, so the timeout starts over on resume.
+    pub fn check_stall(&mut self, now: Instant, paused: bool) -> WatchdogAction {
+        if paused {
+            self.last_present = now;
+            return WatchdogAction::Continue;
+        }
+        if now.saturating_duration_since(self.last_present) < STALL_TIMEOUT {
+            return WatchdogAction::Continue;
+        }
+        self.failed(RenderFault::Stalled, now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(base: Instant, ms: u64) -> Instant {
+        base + Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn test_lost_surface_is_recreated_with_backoff_then_given_up() {
+        let start = Instant::now();
+        let lost = RenderFault::classify(&pixels::Error::Surface(SurfaceError::Lost));
+        assert_eq!(lost, RenderFault::SurfaceLost);
+        let mut watchdog = RenderWatchdog::new(start);
+
+        assert_eq!(
+            watchdog.failed(lost, ms(start, 16)),
+            WatchdogAction::Recreate
+        );
+        // Failures inside the backoff wait for it
+        assert_eq!(
+            watchdog.failed(lost, ms(start, 32)),
+            WatchdogAction::Continue
+        );
+        assert_eq!(
+            watchdog.failed(lost, ms(start, 316)),
+            WatchdogAction::Recreate
+        );
+        assert_eq!(
+            watchdog.failed(lost, ms(start, 900)),
+            WatchdogAction::Continue
+        );
+        assert_eq!(
+            watchdog.failed(lost, ms(start, 1016)),
+            WatchdogAction::Recreate
+        );
+        assert_eq!(watchdog.attempts(), RECOVERY_ATTEMPTS);
+        assert_eq!(
+            watchdog.failed(lost, ms(start, 1100)),
+            WatchdogAction::GiveUp
+        );
+
+        // A presented frame ends the episode, so the next loss starts over
+        watchdog.presented(ms(start, 1200));
+        assert_eq!(
+            watchdog.failed(lost, ms(start, 1300)),
+            WatchdogAction::Recreate
+        );
+
+        let oom = pixels::Error::Surface(SurfaceError::OutOfMemory);
+        assert_eq!(RenderFault::classify(&oom), RenderFault::Fatal);
+        let mut watchdog = RenderWatchdog::new(start);
+        assert_eq!(
+            watchdog.failed(RenderFault::Fatal, start),
+            WatchdogAction::GiveUp
+        );
+    }
+
+    #[test]
+    fn test_stall_only_counts_while_running() {
+        let start = Instant::now();
+        let mut watchdog = RenderWatchdog::new(start);
+        assert_eq!(
+            watchdog.check_stall(ms(start, 1999), false),
+            WatchdogAction::Continue
+        );
+        assert_eq!(
+            watchdog.check_stall(ms(start, 2000), false),
+            WatchdogAction::Recreate
+        );
+
+        // A long pause is not a stall, and the timeout restarts from the resume
+        let mut watchdog = RenderWatchdog::new(start);
+        assert_eq!(
+            watchdog.check_stall(ms(start, 5000), true),
+            WatchdogAction::Continue
+        );
+        assert_eq!(
+            watchdog.check_stall(ms(start, 6000), false),
+            WatchdogAction::Continue
+        );
+        assert_eq!(
+            watchdog.check_stall(ms(start, 7000), false),
+            WatchdogAction::Recreate
+        );
+    }
+}
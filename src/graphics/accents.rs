@@ -0,0 +1,411 @@
+//! Rare accents that keep long sessions interesting: a shooting star across
+//! the World, a prism flash over the rays, a golden ring pulsing down the
+//! Tunnel. Scenes register theirs in the scene table. One budget is shared by
+//! all of them, so there is at most one accent per interval wherever the user
+//! is, and the time of the last one is stored so a restart doesn't fire one
+//! straight away. Reduced motion turns them off.
+
+use crate::core::accessibility;
+use crate::core::persist;
+use crate::core::scenes::SceneInfo;
+use crate::core::sim_rng::sim_rng;
+use crate::core::types::{hsv_to_rgb, Position, Velocity};
+use crate::graphics::draw_ctx::DrawCtx;
+use crate::graphics::render::{draw_line_aa, draw_ring};
+use log::warn;
+use rand::Rng;
+use serde_json::json;
+use std::f32::consts::PI;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const DEFAULT_INTERVAL_MINUTES: f32 = 10.0;
+/// Once the interval has passed, the wait for the next accent averages this
+/// share of an interval more, so they don't arrive like clockwork
+const MEAN_EXTRA_WAIT: f64 = 0.5;
+const STORE_ID: &str = "accents";
+
+/// A short effect drawn over a scene until its lifetime runs out
+pub trait AccentEffect {
+    fn update(&mut self, dt: f32);
+    fn draw(&self, ctx: &mut DrawCtx);
+    fn is_done(&self) -> bool;
+}
+
+/// Starts a scene's accent in a frame of the given size
+pub type SpawnAccent = fn(width: u32, height: u32) -> Box<dyn AccentEffect + Send>;
+
+/// Rises and falls over a lifetime, peaking halfway
+fn envelope(age: f32, lifetime: f32) -> f32 {
+    (PI * (age / lifetime).clamp(0.0, 1.0)).sin()
+}
+
+pub struct ShootingStar {
+    head: Position,
+    velocity: Velocity,
+    age: f32,
+}
+
+impl ShootingStar {
+    const LIFETIME: f32 = 1.2;
+    /// Seconds of travel the tail trails behind the head
+    const TAIL: f32 = 0.18;
+
+    pub fn spawn(width: u32, height: u32) -> Box<dyn AccentEffect + Send> {
+        let mut rng = sim_rng();
+        let (w, h) = (width as f32, height as f32);
+        let leftward = rng.gen_bool(0.5);
+        let angle = rng.gen_range(0.35..0.6f32);
+        let speed = w * 0.8;
+        Box::new(Self {
+            head: Position::new(rng.gen_range(0.2..0.8) * w, rng.gen_range(0.0..0.3) * h),
+            velocity: Velocity::new(
+                if leftward { -1.0 } else { 1.0 } * angle.cos() * speed,
+                angle.sin() * speed,
+            ),
+            age: 0.0,
+        })
+    }
+}
+
+impl AccentEffect for ShootingStar {
+    fn update(&mut self, dt: f32) {
+        self.age += dt;
+        self.head += self.velocity * dt;
+    }
+
+    fn draw(&self, ctx: &mut DrawCtx) {
+        let fade = envelope(self.age, Self::LIFETIME);
+        let segments = 12;
+        for i in 0..segments {
+            // Brightest at the head
+            let near = i as f32 / segments as f32;
+            let from = self.head - self.velocity * Self::TAIL * (1.0 - near);
+            let to = self.head - self.velocity * Self::TAIL * (1.0 - near - 1.0 / segments as f32);
+            let alpha = (255.0 * fade * near * near) as u8;
+            draw_line_aa(
+                ctx,
+                (from.x, from.y),
+                (to.x, to.y),
+                1.0 + near,
+                &[255, 250, 230, alpha],
+            );
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.age >= Self::LIFETIME
+    }
+}
+
+pub struct PrismFlash {
+    center: f32,
+    age: f32,
+}
+
+impl PrismFlash {
+    const LIFETIME: f32 = 0.6;
+    /// Brightness added at the peak of the flash
+    const STRENGTH: f32 = 70.0;
+
+    pub fn spawn(width: u32, _height: u32) -> Box<dyn AccentEffect + Send> {
+        let center = sim_rng().gen_range(0.3..0.7) * width as f32;
+        Box::new(Self { center, age: 0.0 })
+    }
+}
+
+impl AccentEffect for PrismFlash {
+    fn update(&mut self, dt: f32) {
+        self.age += dt;
+    }
+
+    /// A band of spectrum fanning out from a column, added as light
+    fn draw(&self, ctx: &mut DrawCtx) {
+        let fade = envelope(self.age, Self::LIFETIME);
+        let half_width = ctx.width() as f32 * (0.1 + 0.3 * self.age / Self::LIFETIME);
+        let colors: Vec<[u8; 3]> = (0..ctx.width())
+            .map(|x| {
+                let offset = (x as f32 - self.center) / half_width;
+                if offset.abs() >= 1.0 {
+                    return [0; 3];
+                }
+                let color = hsv_to_rgb((offset + 1.0) / 2.0 * 0.8, 1.0, 1.0);
+                let gain = Self::STRENGTH * fade * (1.0 - offset.abs()) / 255.0;
+                [color.red, color.green, color.blue].map(|c| (c as f32 * gain) as u8)
+            })
+            .collect();
+        for y in 0..ctx.height() as i32 {
+            for (x, &rgb) in colors.iter().enumerate() {
+                if rgb != [0; 3] {
+                    ctx.add_pixel(x as i32, y, rgb);
+                }
+            }
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.age >= Self::LIFETIME
+    }
+}
+
+pub struct GoldenRing {
+    center: Position,
+    max_radius: f32,
+    age: f32,
+}
+
+impl GoldenRing {
+    const LIFETIME: f32 = 1.5;
+    const THICKNESS: f32 = 4.0;
+
+    pub fn spawn(width: u32, height: u32) -> Box<dyn AccentEffect + Send> {
+        Box::new(Self {
+            center: Position::new(width as f32 / 2.0, height as f32 / 2.0),
+            max_radius: width.min(height) as f32 * 0.45,
+            age: 0.0,
+        })
+    }
+}
+
+impl AccentEffect for GoldenRing {
+    fn update(&mut self, dt: f32) {
+        self.age += dt;
+    }
+
+    fn draw(&self, ctx: &mut DrawCtx) {
+        let t = (self.age / Self::LIFETIME).clamp(0.0, 1.0);
+        let radius = self.max_radius * t.sqrt();
+        let alpha = (230.0 * (1.0 - t)) as u8;
+        draw_ring(
+            ctx,
+            self.center.x,
+            self.center.y,
+            radius - Self::THICKNESS,
+            radius,
+            &[255, 200, 60, alpha],
+        );
+    }
+
+    fn is_done(&self) -> bool {
+        self.age >= Self::LIFETIME
+    }
+}
+
+/// Decides when the next accent may fire, in wall-clock seconds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AccentBudget {
+    /// Shortest time between two accents
+    pub interval: f64,
+    pub last_trigger: f64,
+}
+
+impl AccentBudget {
+    pub fn new(interval: f64, last_trigger: f64) -> Self {
+        Self {
+            interval,
+            last_trigger,
+        }
+    }
+
+    /// Whether an accent fires on a frame `dt` seconds long ending at `now`.
+    /// `roll` is uniform in 0..1. A clock set back starts the wait over.
+    pub fn try_fire(&mut self, now: f64, dt: f64, roll: f64) -> bool {
+        let since = now - self.last_trigger;
+        if since < 0.0 {
+            self.last_trigger = now;
+            return false;
+        }
+        if since < self.interval || roll >= dt / (self.interval * MEAN_EXTRA_WAIT) {
+            return false;
+        }
+        self.last_trigger = now;
+        true
+    }
+}
+
+/// Reads the time of the last accent stored in `dir`
+pub fn load_last_trigger(dir: &std::path::Path) -> Option<f64> {
+    match persist::read_blob(dir, STORE_ID) {
+        Ok(blob) => blob?.get("last_trigger")?.as_f64(),
+        Err(e) => {
+            warn!("Ignoring stored accent time: {}", e);
+            None
+        }
+    }
+}
+
+pub fn save_last_trigger(dir: &std::path::Path, last_trigger: f64) {
+    let state = json!({ "last_trigger": last_trigger });
+    if let Err(e) = persist::write_blob(dir, STORE_ID, &state) {
+        warn!("Could not save accent time: {}", e);
+    }
+}
+
+struct Accents {
+    budget: AccentBudget,
+    /// The accent on screen and the scene it belongs to
+    active: Option<(&'static str, Box<dyn AccentEffect + Send>)>,
+    last_time: Option<f32>,
+}
+
+/// Minutes between accents, as f32 bits
+static INTERVAL_MINUTES: AtomicU32 = AtomicU32::new(DEFAULT_INTERVAL_MINUTES.to_bits());
+static STORE_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+static ACCENTS: Mutex<Option<Accents>> = Mutex::new(None);
+
+fn store_dir() -> MutexGuard<'static, Option<PathBuf>> {
+    STORE_DIR.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn accents() -> MutexGuard<'static, Option<Accents>> {
+    ACCENTS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+pub fn interval_minutes() -> f32 {
+    f32::from_bits(INTERVAL_MINUTES.load(Ordering::Relaxed))
+}
+
+pub fn set_interval_minutes(minutes: f32) {
+    INTERVAL_MINUTES.store(minutes.to_bits(), Ordering::Relaxed);
+    if let Some(accents) = accents().as_mut() {
+        accents.budget.interval = minutes as f64 * 60.0;
+    }
+}
+
+/// Keeps the time of the last accent in `dir` across restarts. Off by
+/// default, so headless rendering never touches the user's files.
+pub fn enable_persistence(dir: PathBuf) {
+    *store_dir() = Some(dir);
+    *accents() = None;
+}
+
+fn unix_now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |elapsed| elapsed.as_secs_f64())
+}
+
+/// Runs the budget for `scene` and draws its accent, if one is on. Called
+/// once a frame after the scene has drawn.
+pub fn update_and_draw_accents(ctx: &mut DrawCtx, scene: &'static SceneInfo) {
+    let now = unix_now();
+    let mut slot = accents();
+    let accents = slot.get_or_insert_with(|| {
+        // Never fired before counts as fired now, so a first launch waits too
+        let stored = store_dir().as_deref().and_then(load_last_trigger);
+        Accents {
+            budget: AccentBudget::new(interval_minutes() as f64 * 60.0, stored.unwrap_or(now)),
+            active: None,
+            last_time: None,
+        }
+    });
+    let dt = accents
+        .last_time
+        .map_or(0.0, |last| (ctx.time - last).clamp(0.0, 0.1));
+    accents.last_time = Some(ctx.time);
+
+    if accessibility::is_reduced_motion() {
+        accents.active = None;
+        return;
+    }
+    // An accent never follows the user into another scene
+    if accents
+        .active
+        .as_ref()
+        .is_some_and(|(id, _)| *id != scene.id)
+    {
+        accents.active = None;
+    }
+    if let Some(spawn) = scene.accent.filter(|_| accents.active.is_none()) {
+        let roll = sim_rng().gen::<f64>();
+        if accents.budget.try_fire(now, dt as f64, roll) {
+            accents.active = Some((scene.id, spawn(ctx.width(), ctx.height())));
+            if let Some(dir) = store_dir().as_deref() {
+                save_last_trigger(dir, now);
+            }
+        }
+    }
+    if let Some((_, effect)) = accents.active.as_mut() {
+        effect.update(dt);
+        if effect.is_done() {
+            accents.active = None;
+        } else {
+            effect.draw(ctx);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_budget_spaces_accents_by_at_least_the_interval() {
+        let interval = 600.0;
+        let mut budget = AccentBudget::new(interval, 0.0);
+        let mut rng = StdRng::seed_from_u64(3);
+        let dt = 1.0 / 60.0;
+        let mut fired = Vec::new();
+        // Ten hours of frames
+        for frame in 0..60 * 60 * 60 * 10 {
+            let now = frame as f64 * dt;
+            if budget.try_fire(now, dt, rng.gen()) {
+                fired.push(now);
+            }
+        }
+        assert!(fired[0] >= interval);
+        for pair in fired.windows(2) {
+            assert!(pair[1] - pair[0] >= interval);
+        }
+        // About one every interval and a half
+        assert!((30..=50).contains(&fired.len()), "{}", fired.len());
+
+        // A clock set back restarts the wait instead of firing
+        let mut budget = AccentBudget::new(interval, 10_000.0);
+        assert!(!budget.try_fire(5_000.0, dt, 0.0));
+        assert!(!budget.try_fire(5_000.0 + interval - 1.0, dt, 0.0));
+        assert!(budget.try_fire(5_000.0 + interval, dt, 0.0));
+    }
+
+    #[test]
+    fn test_last_trigger_survives_a_restart() {
+        let dir = std::env::temp_dir().join(format!("stimstation-accents-{}", std::process::id()));
+        assert_eq!(load_last_trigger(&dir), None);
+        save_last_trigger(&dir, 1_700_000_000.5);
+        assert_eq!(load_last_trigger(&dir), Some(1_700_000_000.5));
+
+        // Restarting a minute later still waits out the rest of the interval
+        let mut budget = AccentBudget::new(600.0, load_last_trigger(&dir).unwrap());
+        assert!(!budget.try_fire(1_700_000_060.5, 0.1, 0.0));
+        assert!(budget.try_fire(1_700_000_600.5, 0.1, 0.0));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_each_effect_draws_then_ends_with_its_lifetime() {
+        let (width, height) = (160u32, 120u32);
+        for spawn in [
+            ShootingStar::spawn as SpawnAccent,
+            PrismFlash::spawn,
+            GoldenRing::spawn,
+        ] {
+            let mut effect = spawn(width, height);
+            let mut drew = false;
+            let mut frames = 0;
+            while !effect.is_done() {
+                effect.update(1.0 / 60.0);
+                let mut frame = vec![0u8; (width * height * 4) as usize];
+                let mut ctx = DrawCtx::from_legacy(&mut frame, width, height, 0.0, 0, width);
+                effect.draw(&mut ctx);
+                drew |= frame.iter().any(|&b| b != 0);
+                frames += 1;
+                assert!(frames <= 120, "an accent outlived two seconds");
+            }
+            assert!(drew);
+        }
+    }
+}
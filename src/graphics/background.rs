@@ -0,0 +1,269 @@
+use crate::core::types::{hsv_to_rgb, Color};
+use std::error::Error;
+use std::path::PathBuf;
+
+/// What is drawn under the World lines.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BackgroundKind {
+    SolidColor(Color),
+    /// Slowly cycling dark hue
+    HueCycle,
+    /// Linear gradient from `from` to `to`; `angle` is in degrees, 0 = left to right
+    Gradient {
+        from: Color,
+        to: Color,
+        angle: f32,
+    },
+    /// Photo stretched to fill the frame
+    Image(PathBuf),
+}
+
+impl Default for BackgroundKind {
+    fn default() -> Self {
+        BackgroundKind::SolidColor(Color::new(5, 5, 10))
+    }
+}
+
+/// A background prepared for one frame size. Gradients and images are rendered
+/// once into an RGBA buffer so drawing them is a plain copy.
+pub struct Background {
+    kind: BackgroundKind,
+    width: u32,
+    height: u32,
+    pixels: Option<Vec<u8>>,
+}
+
+impl Background {
+    /// Renders the background for a `width` x `height` frame. Fails if an image cannot be loaded.
+    pub fn prepare(kind: &BackgroundKind, width: u32, height: u32) -> Result<Self, Box<dyn Error>> {
+        let pixels = match kind {
+            BackgroundKind::SolidColor(_) | BackgroundKind::HueCycle => None,
+            BackgroundKind::Gradient { from, to, angle } => {
+                Some(gradient_pixels(*from, *to, *angle, width, height))
+            }
+            BackgroundKind::Image(path) => {
+                let image = image::open(path)?.to_rgba8();
+                let (src_width, src_height) = image.dimensions();
+                Some(scale_bilinear(
+                    image.as_raw(),
+                    src_width,
+                    src_height,
+                    width,
+                    height,
+                ))
+            }
+        };
+        Ok(Self {
+            kind: kind.clone(),
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// The prepared RGBA pixels, for gradients and images
+    pub fn pixels(&self) -> Option<&[u8]> {
+        self.pixels.as_deref()
+    }
+
+    /// True if this was prepared for the given kind and frame size
+    pub fn matches(&self, kind: &BackgroundKind, width: u32, height: u32) -> bool {
+        self.kind == *kind && self.width == width && self.height == height
+    }
+
+    pub fn draw(&self, frame: &mut [u8], time: f32, x_offset: usize, buffer_width: u32) {
+        match (&self.kind, &self.pixels) {
+            (_, Some(pixels)) => self.blit(frame, pixels, x_offset, buffer_width),
+            (BackgroundKind::SolidColor(color), None) => self.fill(
+                frame,
+                [color.red, color.green, color.blue, 255],
+                x_offset,
+                buffer_width,
+            ),
+            (_, None) => {
+                let color = hsv_to_rgb((time * 0.02).rem_euclid(1.0), 0.6, 0.12);
+                self.fill(
+                    frame,
+                    [color.red, color.green, color.blue, 255],
+                    x_offset,
+                    buffer_width,
+                )
+            }
+        }
+    }
+
+    fn blit(&self, frame: &mut [u8], pixels: &[u8], x_offset: usize, buffer_width: u32) {
+        if x_offset == 0 && buffer_width == self.width && frame.len() == pixels.len() {
+            // Common case: the frame is exactly our size
+            frame.copy_from_slice(pixels);
+            return;
+        }
+        let row_bytes = self.width as usize * 4;
+        for (y, row) in pixels.chunks_exact(row_bytes).enumerate() {
+            let start = (y * buffer_width as usize + x_offset) * 4;
+            if let Some(dest) = frame.get_mut(start..start + row_bytes) {
+                dest.copy_from_slice(row);
+            }
+        }
+    }
+
+    fn fill(&self, frame: &mut [u8], color: [u8; 4], x_offset: usize, buffer_width: u32) {
+        for y in 0..self.height as usize {
+            let start = (y * buffer_width as usize + x_offset) * 4;
+            let end = start + self.width as usize * 4;
+            if let Some(row) = frame.get_mut(start..end) {
+                for pixel in row.chunks_exact_mut(4) {
+                    pixel.copy_from_slice(&color);
+                }
+            }
+        }
+    }
+}
+
+/// Renders a linear gradient into a new RGBA buffer
+pub fn gradient_pixels(from: Color, to: Color, angle: f32, width: u32, height: u32) -> Vec<u8> {
+    let (dir_y, dir_x) = angle.to_radians().sin_cos();
+    let center_x = (width as f32 - 1.0) / 2.0;
+    let center_y = (height as f32 - 1.0) / 2.0;
+    // Half the length of the frame projected onto the gradient direction
+    let extent = (center_x * dir_x.abs() + center_y * dir_y.abs()).max(f32::EPSILON);
+
+    let mut pixels = Vec::with_capacity(width as usize * height as usize * 4);
+    for y in 0..height {
+        for x in 0..width {
+            let along = (x as f32 - center_x) * dir_x + (y as f32 - center_y) * dir_y;
+            let t = ((along / extent + 1.0) / 2.0).clamp(0.0, 1.0);
+            let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+            pixels.extend_from_slice(&[
+                mix(from.red, to.red),
+                mix(from.green, to.green),
+                mix(from.blue, to.blue),
+                255,
+            ]);
+        }
+    }
+    pixels
+}
+
+/// Resizes an RGBA buffer with bilinear filtering. Works for both shrinking and enlarging.
+pub fn scale_bilinear(
+    src: &[u8],
+    src_width: u32,
+    src_height: u32,
+    dst_width: u32,
+    dst_height: u32,
+) -> Vec<u8> {
+    let mut dst = vec![0; dst_width as usize * dst_height as usize * 4];
+    if src_width == 0 || src_height == 0 {
+        return dst;
+    }
+    let sample =
+        |x: usize, y: usize, channel: usize| src[(y * src_width as usize + x) * 4 + channel] as f32;
+    // Map pixel centers so corners line up with corners
+    let scale_x = src_width as f32 / dst_width.max(1) as f32;
+    let scale_y = src_height as f32 / dst_height.max(1) as f32;
+    let max_x = src_width as usize - 1;
+    let max_y = src_height as usize - 1;
+
+    for y in 0..dst_height as usize {
+        let sy = ((y as f32 + 0.5) * scale_y - 0.5).clamp(0.0, max_y as f32);
+        let y0 = sy.floor() as usize;
+        let y1 = (y0 + 1).min(max_y);
+        let fy = sy - y0 as f32;
+        for x in 0..dst_width as usize {
+            let sx = ((x as f32 + 0.5) * scale_x - 0.5).clamp(0.0, max_x as f32);
+            let x0 = sx.floor() as usize;
+            let x1 = (x0 + 1).min(max_x);
+            let fx = sx - x0 as f32;
+            let out = (y * dst_width as usize + x) * 4;
+            for channel in 0..4 {
+                let top = sample(x0, y0, channel) * (1.0 - fx) + sample(x1, y0, channel) * fx;
+                let bottom = sample(x0, y1, channel) * (1.0 - fx) + sample(x1, y1, channel) * fx;
+                dst[out + channel] = (top * (1.0 - fy) + bottom * fy).round() as u8;
+            }
+        }
+    }
+    dst
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pixel(pixels: &[u8], width: u32, x: u32, y: u32) -> [u8; 4] {
+        let i = ((y * width + x) * 4) as usize;
+        [pixels[i], pixels[i + 1], pixels[i + 2], pixels[i + 3]]
+    }
+
+    #[test]
+    fn test_horizontal_gradient() {
+        let from = Color::new(0, 0, 0);
+        let to = Color::new(200, 100, 50);
+        let pixels = gradient_pixels(from, to, 0.0, 11, 4);
+
+        assert_eq!(pixels.len(), 11 * 4 * 4);
+        assert_eq!(pixel(&pixels, 11, 0, 0), [0, 0, 0, 255]);
+        assert_eq!(pixel(&pixels, 11, 10, 3), [200, 100, 50, 255]);
+        assert_eq!(pixel(&pixels, 11, 5, 2), [100, 50, 25, 255]);
+        // Same colour down a column
+        assert_eq!(pixel(&pixels, 11, 3, 0), pixel(&pixels, 11, 3, 3));
+    }
+
+    #[test]
+    fn test_vertical_gradient() {
+        let from = Color::new(255, 255, 255);
+        let to = Color::new(0, 0, 0);
+        let pixels = gradient_pixels(from, to, 90.0, 4, 9);
+
+        assert_eq!(pixel(&pixels, 4, 0, 0), [255, 255, 255, 255]);
+        assert_eq!(pixel(&pixels, 4, 3, 8), [0, 0, 0, 255]);
+        // Same colour along a row
+        assert_eq!(pixel(&pixels, 4, 0, 4), pixel(&pixels, 4, 3, 4));
+    }
+
+    #[test]
+    fn test_small_image_upscales_to_frame() {
+        // 2x1 image: red on the left, blue on the right
+        let src = [255, 0, 0, 255, 0, 0, 255, 255];
+        let scaled = scale_bilinear(&src, 2, 1, 64, 32);
+
+        assert_eq!(scaled.len(), 64 * 32 * 4);
+        assert_eq!(pixel(&scaled, 64, 0, 0), [255, 0, 0, 255]);
+        assert_eq!(pixel(&scaled, 64, 63, 31), [0, 0, 255, 255]);
+        // The middle is blended
+        let middle = pixel(&scaled, 64, 32, 16);
+        assert!(middle[0] > 0 && middle[2] > 0);
+    }
+
+    #[test]
+    fn test_single_pixel_image_fills_frame() {
+        let scaled = scale_bilinear(&[10, 20, 30, 255], 1, 1, 5, 5);
+        for chunk in scaled.chunks_exact(4) {
+            assert_eq!(chunk, [10, 20, 30, 255]);
+        }
+    }
+
+    #[test]
+    fn test_missing_image_is_an_error() {
+        let kind = BackgroundKind::Image(PathBuf::from("/definitely/not/here.png"));
+        assert!(Background::prepare(&kind, 16, 16).is_err());
+    }
+
+    #[test]
+    fn test_image_background_prescaled_and_copied() {
+        let path = std::env::temp_dir().join("stimstation_background_test.png");
+        image::RgbaImage::from_pixel(2, 2, image::Rgba([40, 80, 120, 255]))
+            .save(&path)
+            .unwrap();
+
+        let kind = BackgroundKind::Image(path.clone());
+        let background = Background::prepare(&kind, 8, 4).unwrap();
+        assert!(background.matches(&kind, 8, 4));
+        assert!(!background.matches(&kind, 8, 5));
+
+        let mut frame = vec![0; 8 * 4 * 4];
+        background.draw(&mut frame, 0.0, 0, 8);
+        assert!(frame.chunks_exact(4).all(|p| p == [40, 80, 120, 255]));
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,536 @@
+use crate::audio::features::FrameFeatures;
+use crate::core::types::Position;
+use image::RgbaImage;
+
+/// Rectangle of the frame buffer a draw call may touch, in buffer pixels.
+/// `x` is the column offset (the old `x_offset`) and `y` the first row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub x: usize,
+    pub y: usize,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Region {
+    pub fn new(x: usize, y: usize, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+}
+
+/// Knobs scenes read to trade detail for speed. The defaults match what the
+/// scenes drew before they were configurable.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QualitySettings {
+    /// Rays cast from each ball
+    pub ray_count: usize,
+    /// Glow radius around each audio bar, in pixels
+    pub bar_glow_radius: i32,
+    /// Smooth circle edges instead of drawing them pixel by pixel
+    pub antialias: bool,
+    /// Tint World lines with the light of nearby particles
+    pub particle_lighting: bool,
+    /// Trail samples drawn behind each ball; 0 turns the trails off
+    pub trail_length: usize,
+}
+
+pub const DEFAULT_QUALITY: QualitySettings = QualitySettings {
+    ray_count: 60,
+    bar_glow_radius: 2,
+    antialias: true,
+    particle_lighting: true,
+    trail_length: 18,
+};
+
+impl Default for QualitySettings {
+    fn default() -> Self {
+        DEFAULT_QUALITY
+    }
+}
+
+/// Frames smaller than this draw simplified scenes: fewer rays and lines, and
+/// no HUD text
+pub const FULL_DETAIL_SIZE: (f32, f32) = (640.0, 320.0);
+
+/// The largest scale, at most 1, at which `content` fits inside `region`.
+/// Layouts made for a large frame multiply their sizes and counts by it.
+pub fn fit_scale(content: (f32, f32), region: (u32, u32)) -> f32 {
+    let width = region.0 as f32 / content.0.max(1.0);
+    let height = region.1 as f32 / content.1.max(1.0);
+    width.min(height).min(1.0)
+}
+
+/// Everything a scene needs to draw: the target buffer and the region of it
+/// to draw into, the frame time, audio features, and quality settings.
+/// Coordinates passed to the pixel helpers are relative to the region and
+/// clipped to it.
+pub struct DrawCtx<'a> {
+    pub frame: &'a mut [u8],
+    pub region: Region,
+    pub buffer_width: u32,
+    pub time: f32,
+    pub features: Option<&'a FrameFeatures>,
+    pub quality: &'a QualitySettings,
+}
+
+impl<'a> DrawCtx<'a> {
+    pub fn new(frame: &'a mut [u8], region: Region, buffer_width: u32, time: f32) -> Self {
+        Self {
+            frame,
+            region,
+            buffer_width,
+            time,
+            features: None,
+            quality: &DEFAULT_QUALITY,
+        }
+    }
+
+    /// Builds a context from the old `(frame, width, height, time, x_offset, buffer_width)`
+    /// parameters, for adapters around functions that now take a `DrawCtx`
+    pub fn from_legacy(
+        frame: &'a mut [u8],
+        width: u32,
+        height: u32,
+        time: f32,
+        x_offset: usize,
+        buffer_width: u32,
+    ) -> Self {
+        Self::new(
+            frame,
+            Region::new(x_offset, 0, width, height),
+            buffer_width,
+            time,
+        )
+    }
+
+    pub fn width(&self) -> u32 {
+        self.region.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.region.height
+    }
+
+    /// A context for `rect`, given relative to this region and clipped to it
+    pub fn sub_region(&mut self, rect: Region) -> DrawCtx<'_> {
+        let x = rect.x.min(self.region.width as usize);
+        let y = rect.y.min(self.region.height as usize);
+        let width = rect.width.min(self.region.width - x as u32);
+        let height = rect.height.min(self.region.height - y as u32);
+        DrawCtx {
+            frame: &mut *self.frame,
+            region: Region::new(self.region.x + x, self.region.y + y, width, height),
+            buffer_width: self.buffer_width,
+            time: self.time,
+            features: self.features,
+            quality: self.quality,
+        }
+    }
+
+    /// The same region with audio features attached
+    pub fn with_features<'b>(&'b mut self, features: &'b FrameFeatures) -> DrawCtx<'b> {
+        DrawCtx {
+            frame: &mut *self.frame,
+            region: self.region,
+            buffer_width: self.buffer_width,
+            time: self.time,
+            features: Some(features),
+            quality: self.quality,
+        }
+    }
+
+    /// The old parameter tuple for functions that are not ported yet: the frame
+    /// starting at the region's first row, width, height, x offset, and stride
+    pub fn legacy(&mut self) -> (&mut [u8], u32, u32, usize, u32) {
+        let start = (self.region.y * self.buffer_width as usize * 4).min(self.frame.len());
+        (
+            &mut self.frame[start..],
+            self.region.width,
+            self.region.height,
+            self.region.x,
+            self.buffer_width,
+        )
+    }
+
+    /// Byte index of region pixel (x, y), or None when it falls outside the region or buffer
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if x < 0 || y < 0 || x >= self.region.width as i32 || y >= self.region.height as i32 {
+            return None;
+        }
+        let row = self.region.y + y as usize;
+        let idx = 4 * (row * self.buffer_width as usize + self.region.x + x as usize);
+        (idx + 3 < self.frame.len()).then_some(idx)
+    }
+
+    pub fn put_pixel(&mut self, x: i32, y: i32, color: [u8; 4]) {
+        if let Some(idx) = self.index(x, y) {
+            self.frame[idx..idx + 4].copy_from_slice(&color);
+        }
+    }
+
+    /// Alpha-blends `color` over the pixel; the result is opaque
+    pub fn blend_pixel(&mut self, x: i32, y: i32, color: &[u8; 4]) {
+        if let Some(idx) = self.index(x, y) {
+            let alpha = color[3] as f32 / 255.0;
+            for (dst, &src) in self.frame[idx..idx + 3].iter_mut().zip(color) {
+                *dst = (*dst as f32 * (1.0 - alpha) + src as f32 * alpha) as u8;
+            }
+            self.frame[idx + 3] = 255;
+        }
+    }
+
+    /// Adds `rgb` to the pixel, saturating at white; for light that accumulates
+    pub fn add_pixel(&mut self, x: i32, y: i32, rgb: [u8; 3]) {
+        if let Some(idx) = self.index(x, y) {
+            for (dst, src) in self.frame[idx..idx + 3].iter_mut().zip(rgb) {
+                *dst = dst.saturating_add(src);
+            }
+        }
+    }
+
+    pub fn fill_rect(&mut self, x: i32, y: i32, width: u32, height: u32, color: [u8; 4]) {
+        for dy in 0..height as i32 {
+            for dx in 0..width as i32 {
+                self.put_pixel(x + dx, y + dy, color);
+            }
+        }
+    }
+
+    /// Fills the region with `color`
+    pub fn clear(&mut self, color: [u8; 4]) {
+        self.fill_rect(0, 0, self.region.width, self.region.height, color);
+    }
+
+    /// Byte range of region row `y` within the frame, clipped to the buffer
+    fn row_bytes(&self, y: usize) -> std::ops::Range<usize> {
+        let start = 4 * ((self.region.y + y) * self.buffer_width as usize + self.region.x);
+        let end = start + 4 * self.region.width as usize;
+        start.min(self.frame.len())..end.min(self.frame.len())
+    }
+
+    /// Region row `y` as RGBA bytes, for scenes that write whole rows
+    pub fn row_mut(&mut self, y: usize) -> &mut [u8] {
+        let range = self.row_bytes(y);
+        &mut self.frame[range]
+    }
+
+    /// Copies the region's pixels, row by row, into `out`
+    pub fn save_region(&self, out: &mut [u8]) {
+        let row_len = 4 * self.region.width as usize;
+        for (y, dst) in out.chunks_exact_mut(row_len).enumerate() {
+            let src = &self.frame[self.row_bytes(y)];
+            dst[..src.len()].copy_from_slice(src);
+        }
+    }
+
+    /// Puts back pixels taken with `save_region` from a region of the same size
+    pub fn restore_region(&mut self, saved: &[u8]) {
+        let row_len = 4 * self.region.width as usize;
+        for (y, src) in saved.chunks_exact(row_len).enumerate() {
+            let range = self.row_bytes(y);
+            let len = range.len();
+            self.frame[range].copy_from_slice(&src[..len]);
+        }
+    }
+
+    /// Fills the triangle with corners `a`, `b`, and `c`, row by row
+    pub fn fill_triangle(&mut self, a: (i32, i32), b: (i32, i32), c: (i32, i32), color: [u8; 4]) {
+        // Corners on pixel centers, so the pixels under them are filled
+        let corner = |(x, y): (i32, i32)| Position::new(x as f32 + 0.5, y as f32 + 0.5);
+        self.fill_triangle_with([corner(a), corner(b), corner(c)], |_| color);
+    }
+
+    /// Fills the triangle `corners` with `image`, mapped affinely: each corner
+    /// shows the texel at its `uvs` coordinate, 0.0..=1.0 across the image
+    pub fn fill_triangle_textured(
+        &mut self,
+        corners: [Position; 3],
+        uvs: [Position; 3],
+        image: &RgbaImage,
+    ) {
+        let [p0, p1, p2] = corners;
+        let (e1, e2) = (p1 - p0, p2 - p0);
+        let area = e1.perp_dot(e2);
+        if area.abs() < f32::EPSILON || image.width() == 0 || image.height() == 0 {
+            return;
+        }
+        // The texture coordinate is a plane over the triangle; these are its slopes
+        let size = Position::new(image.width() as f32, image.height() as f32);
+        let [t0, t1, t2] = uvs.map(|uv| uv * size);
+        let (d1, d2) = (t1 - t0, t2 - t0);
+        let d_dx = (d1 * e2.y - d2 * e1.y) / area;
+        let d_dy = (d2 * e1.x - d1 * e2.x) / area;
+        let texels = image.as_raw();
+        let (max_x, max_y) = (image.width() as i32 - 1, image.height() as i32 - 1);
+        self.fill_triangle_with(corners, |pixel| {
+            let uv = t0 + d_dx * (pixel.x - p0.x) + d_dy * (pixel.y - p0.y);
+            let tx = (uv.x as i32).clamp(0, max_x) as usize;
+            let ty = (uv.y as i32).clamp(0, max_y) as usize;
+            let texel = 4 * (ty * image.width() as usize + tx);
+            texels[texel..texel + 4].try_into().unwrap()
+        });
+    }
+
+    /// Sets each pixel whose center falls inside the triangle `corners` to
+    /// `shade` of that center. Left edges count and right ones don't, so
+    /// triangles sharing an edge leave no gap and no overlap.
+    fn fill_triangle_with(
+        &mut self,
+        corners: [Position; 3],
+        mut shade: impl FnMut(Position) -> [u8; 4],
+    ) {
+        let top = corners[0].y.min(corners[1].y).min(corners[2].y);
+        let bottom = corners[0].y.max(corners[1].y).max(corners[2].y);
+        let row_start = (top - 0.5).ceil().max(0.0) as i32;
+        let row_end = ((bottom - 0.5).ceil() as i32).min(self.region.height as i32);
+        let edges = [
+            (corners[0], corners[1]),
+            (corners[1], corners[2]),
+            (corners[2], corners[0]),
+        ];
+        for y in row_start..row_end {
+            let center_y = y as f32 + 0.5;
+            // Where the row's center line crosses the edges it spans
+            let mut left = f32::INFINITY;
+            let mut right = f32::NEG_INFINITY;
+            for (a, b) in edges {
+                // Top end first, so triangles sharing the edge find the same crossing
+                let (a, b) = if a.y <= b.y { (a, b) } else { (b, a) };
+                if (a.y <= center_y) != (b.y <= center_y) {
+                    let x = a.x + (b.x - a.x) * (center_y - a.y) / (b.y - a.y);
+                    left = left.min(x);
+                    right = right.max(x);
+                }
+            }
+            let x_start = (left - 0.5).ceil().max(0.0) as i32;
+            let x_end = ((right - 0.5).ceil() as i32).min(self.region.width as i32);
+            if x_start >= x_end {
+                continue;
+            }
+            let range = self.row_bytes(y as usize);
+            let row = &mut self.frame[range];
+            for x in x_start..x_end {
+                let idx = 4 * x as usize;
+                if let Some(pixel) = row.get_mut(idx..idx + 4) {
+                    pixel.copy_from_slice(&shade(Position::new(x as f32 + 0.5, center_y)));
+                }
+            }
+        }
+    }
+
+    /// One-pixel blended Bresenham line
+    pub fn draw_line(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, color: &[u8; 4]) {
+        let (mut x, mut y) = (x0, y0);
+        let dx = (x1 - x0).abs();
+        let dy = -(y1 - y0).abs();
+        let sx = if x0 < x1 { 1 } else { -1 };
+        let sy = if y0 < y1 { 1 } else { -1 };
+        let mut err = dx + dy;
+
+        loop {
+            self.blend_pixel(x, y, color);
+            if x == x1 && y == y1 {
+                break;
+            }
+            let e2 = 2 * err;
+            if e2 >= dy {
+                if x == x1 {
+                    break;
+                }
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                if y == y1 {
+                    break;
+                }
+                err += dx;
+                y += sy;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn test_sub_region_clips_to_parent() {
+        let (width, height) = (8u32, 8u32);
+        let mut frame = vec![0u8; (width * height * 4) as usize];
+        let mut ctx = DrawCtx::new(&mut frame, Region::new(0, 0, width, height), width, 0.0);
+
+        // Bottom-right quadrant, asked for more than is left
+        let mut quadrant = ctx.sub_region(Region::new(4, 4, 10, 10));
+        assert_eq!(quadrant.region, Region::new(4, 4, 4, 4));
+        quadrant.fill_rect(-2, -2, 20, 20, [255, 0, 0, 255]);
+
+        let painted: Vec<(usize, usize)> = (0..64)
+            .filter(|i| frame[i * 4] == 255)
+            .map(|i| (i % 8, i / 8))
+            .collect();
+        assert_eq!(painted.len(), 16);
+        assert!(painted.iter().all(|&(x, y)| x >= 4 && y >= 4));
+    }
+
+    #[test]
+    fn test_saved_region_restores_only_that_region() {
+        let (width, height) = (6u32, 4u32);
+        let mut frame = vec![0u8; (width * height * 4) as usize];
+        let mut ctx = DrawCtx::new(&mut frame, Region::new(2, 1, 3, 2), width, 0.0);
+        ctx.fill_rect(0, 0, 3, 2, [7, 7, 7, 255]);
+        let mut saved = vec![0u8; 3 * 2 * 4];
+        ctx.save_region(&mut saved);
+        assert!(saved.iter().all(|&b| b == 7 || b == 255));
+
+        ctx.frame.fill(1);
+        ctx.restore_region(&saved);
+        let restored = (0..24).filter(|i| frame[i * 4] == 7).count();
+        assert_eq!(restored, 6);
+        assert_eq!(frame[4 * (6 + 2)], 7);
+        assert_eq!(frame[0], 1);
+    }
+
+    #[test]
+    fn test_nested_sub_regions_offset_and_clip() {
+        let (width, height) = (10u32, 10u32);
+        let mut frame = vec![0u8; (width * height * 4) as usize];
+        let mut ctx = DrawCtx::new(&mut frame, Region::new(2, 1, 8, 9), width, 0.0);
+        let mut inner = ctx.sub_region(Region::new(3, 3, 2, 2));
+        let mut inner = inner.sub_region(Region::new(1, 1, 5, 5));
+        assert_eq!(inner.region, Region::new(6, 5, 1, 1));
+        inner.put_pixel(0, 0, [9, 9, 9, 255]);
+        inner.put_pixel(1, 0, [9, 9, 9, 255]);
+        assert_eq!(frame.iter().filter(|&&b| b == 9).count(), 3);
+        assert_eq!(frame[4 * (5 * 10 + 6)], 9);
+    }
+
+    #[test]
+    fn test_fit_scale_shrinks_only_what_does_not_fit() {
+        assert_eq!(fit_scale(FULL_DETAIL_SIZE, (1600, 800)), 1.0);
+        assert_eq!(fit_scale(FULL_DETAIL_SIZE, (640, 320)), 1.0);
+        assert_eq!(fit_scale(FULL_DETAIL_SIZE, (160, 120)), 0.25);
+        // The tighter side decides
+        assert_eq!(fit_scale((100.0, 100.0), (50, 200)), 0.5);
+        assert_eq!(fit_scale((0.0, 0.0), (0, 0)), 0.0);
+
+        // A triangle poking out of the region is clipped to it
+        let mut frame = vec![0u8; 8 * 8 * 4];
+        let mut ctx = DrawCtx::new(&mut frame, Region::new(2, 2, 4, 4), 8, 0.0);
+        ctx.fill_triangle((-10, -10), (20, 0), (0, 20), [5, 5, 5, 255]);
+        let painted = (0..64).filter(|i| frame[i * 4] == 5).count();
+        assert_eq!(painted, 16);
+    }
+
+    #[test]
+    fn test_legacy_tuple_starts_at_region_row() {
+        let (width, height) = (6u32, 4u32);
+        let mut frame = vec![0u8; (width * height * 4) as usize];
+        let mut ctx = DrawCtx::new(&mut frame, Region::new(0, 0, width, height), width, 0.0);
+        let mut lower = ctx.sub_region(Region::new(2, 2, 3, 2));
+        let (slice, w, h, x_offset, stride) = lower.legacy();
+        assert_eq!((slice.len(), w, h, x_offset, stride), (48, 3, 2, 2, 6));
+    }
+
+    /// `size` x `size` texels, alternating black and white, each its own shade of red
+    fn checker(size: u32) -> RgbaImage {
+        RgbaImage::from_fn(size, size, |x, y| {
+            let shade = if (x + y) % 2 == 0 { 255 } else { 0 };
+            Rgba([(y * size + x) as u8, shade, shade, 255])
+        })
+    }
+
+    fn pixel(frame: &[u8], width: u32, x: u32, y: u32) -> [u8; 4] {
+        let idx = 4 * (y * width + x) as usize;
+        frame[idx..idx + 4].try_into().unwrap()
+    }
+
+    fn points(points: [(f32, f32); 3]) -> [Position; 3] {
+        points.map(Position::from)
+    }
+
+    #[test]
+    fn test_textured_triangle_maps_texels_one_to_one() {
+        let texture = checker(8);
+        let mut frame = vec![0u8; 8 * 8 * 4];
+        let mut ctx = DrawCtx::new(&mut frame, Region::new(0, 0, 8, 8), 8, 0.0);
+        let corners = points([(0.0, 0.0), (8.0, 0.0), (0.0, 8.0)]);
+        let uvs = points([(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)]);
+        ctx.fill_triangle_textured(corners, uvs, &texture);
+        for y in 0..8 {
+            for x in 0..8 {
+                let inside = x + y < 7;
+                let expected = if inside {
+                    texture.get_pixel(x, y).0
+                } else {
+                    [0; 4]
+                };
+                // The diagonal's pixel centers lie on the edge; only one side owns them
+                if x + y != 7 {
+                    assert_eq!(pixel(&frame, 8, x, y), expected, "({}, {})", x, y);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_textured_triangle_follows_a_mirrored_mapping() {
+        let texture = checker(8);
+        let mut frame = vec![0u8; 8 * 8 * 4];
+        let mut ctx = DrawCtx::new(&mut frame, Region::new(0, 0, 8, 8), 8, 0.0);
+        // Same corners, with the texture flipped left to right
+        let corners = points([(0.0, 0.0), (8.0, 0.0), (0.0, 8.0)]);
+        let uvs = points([(1.0, 0.0), (0.0, 0.0), (1.0, 1.0)]);
+        ctx.fill_triangle_textured(corners, uvs, &texture);
+        for y in 0..8 {
+            for x in (0..8).filter(|x| x + y < 7) {
+                assert_eq!(
+                    pixel(&frame, 8, x, y),
+                    texture.get_pixel(7 - x, y).0,
+                    "({}, {})",
+                    x,
+                    y
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_two_textured_triangles_tile_a_scaled_quad_exactly() {
+        let texture = checker(4);
+        // A border column either side of the region, which must stay as is
+        let mut frame = vec![0u8; 18 * 16 * 4];
+        let mut ctx = DrawCtx::new(&mut frame, Region::new(1, 0, 16, 16), 18, 0.0);
+        let [a, b, c, d] = [(0.0, 0.0), (16.0, 0.0), (16.0, 16.0), (0.0, 16.0)];
+        let [ua, ub, uc, ud] = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+        ctx.fill_triangle_textured(points([a, b, c]), points([ua, ub, uc]), &texture);
+        ctx.fill_triangle_textured(points([a, c, d]), points([ua, uc, ud]), &texture);
+        // Every pixel, the shared diagonal's too, shows its 4x4 block's texel
+        for y in 0..16 {
+            for x in 0..16 {
+                assert_eq!(
+                    pixel(&frame, 18, x + 1, y),
+                    texture.get_pixel(x / 4, y / 4).0,
+                    "({}, {})",
+                    x,
+                    y
+                );
+            }
+            assert_eq!(pixel(&frame, 18, 0, y), [0; 4]);
+            assert_eq!(pixel(&frame, 18, 17, y), [0; 4]);
+        }
+        // Off-region corners are clipped rather than wrapped
+        let mut small = vec![0u8; 4 * 4 * 4];
+        let mut ctx = DrawCtx::new(&mut small, Region::new(0, 0, 4, 4), 4, 0.0);
+        let corners = points([(-8.0, -8.0), (40.0, 0.0), (0.0, 40.0)]);
+        ctx.fill_triangle_textured(corners, points([ua, ub, ud]), &texture);
+        assert!(small.chunks_exact(4).all(|pixel| pixel[3] == 255));
+    }
+}
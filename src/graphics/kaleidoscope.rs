@@ -0,0 +1,88 @@
+//! A four-way mirror over the finished scene: the top-left quarter is
+//! reflected into the other three, so whatever moves there moves
+//! symmetrically everywhere. Off unless something turns it on, like the
+//! energy finale.
+
+use crate::graphics::draw_ctx::DrawCtx;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static KALEIDOSCOPE_ENABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn set_kaleidoscope_enabled(enabled: bool) {
+    KALEIDOSCOPE_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_kaleidoscope_enabled() -> bool {
+    KALEIDOSCOPE_ENABLED.load(Ordering::Relaxed)
+}
+
+/// Mirrors the context's region when the kaleidoscope is on
+pub fn apply_kaleidoscope(ctx: &mut DrawCtx) {
+    if !is_kaleidoscope_enabled() {
+        return;
+    }
+    let height = ctx.height() as usize;
+    let mut top = Vec::new();
+    for y in 0..height.div_ceil(2) {
+        let row = ctx.row_mut(y);
+        mirror_row(row);
+        let bottom = height - 1 - y;
+        if bottom != y {
+            top.clear();
+            top.extend_from_slice(row);
+            let row = ctx.row_mut(bottom);
+            let len = row.len().min(top.len());
+            row[..len].copy_from_slice(&top[..len]);
+        }
+    }
+}
+
+/// Copies the left half of an RGBA row onto the right, reversed
+fn mirror_row(row: &mut [u8]) {
+    let pixels = row.len() / 4;
+    for x in 0..pixels / 2 {
+        let (left, right) = (x * 4, (pixels - 1 - x) * 4);
+        row.copy_within(left..left + 4, right);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphics::draw_ctx::Region;
+
+    #[test]
+    fn test_top_left_quarter_is_mirrored_into_the_rest() {
+        let (width, height) = (6u32, 5u32);
+        // A border column either side of the region, which must stay as is
+        let buffer_width = width + 2;
+        let mut frame: Vec<u8> = (0..buffer_width * height)
+            .flat_map(|i| [i as u8, (i * 3) as u8, 7, 255])
+            .collect();
+        let before = frame.clone();
+        let region = Region::new(1, 0, width, height);
+        let pixel = |frame: &[u8], x: u32, y: u32| {
+            let i = ((y * buffer_width + x + 1) * 4) as usize;
+            [frame[i], frame[i + 1], frame[i + 2], frame[i + 3]]
+        };
+
+        let mut ctx = DrawCtx::new(&mut frame, region, buffer_width, 0.0);
+        apply_kaleidoscope(&mut ctx);
+        assert_eq!(frame, before, "changed the frame while off");
+
+        set_kaleidoscope_enabled(true);
+        let mut ctx = DrawCtx::new(&mut frame, region, buffer_width, 0.0);
+        apply_kaleidoscope(&mut ctx);
+        set_kaleidoscope_enabled(false);
+        for y in 0..height {
+            for x in 0..width {
+                let source = pixel(&before, x.min(width - 1 - x), y.min(height - 1 - y));
+                assert_eq!(pixel(&frame, x, y), source, "at {} {}", x, y);
+            }
+            for border in [0, buffer_width - 1] {
+                let i = ((y * buffer_width + border) * 4) as usize;
+                assert_eq!(frame[i..i + 4], before[i..i + 4]);
+            }
+        }
+    }
+}
@@ -0,0 +1,190 @@
+//! Cheap 2D lighting for the World scene. Particles splat their color into a
+//! grid at 1/8 of the frame resolution, the grid is blurred once, and lines
+//! sample it to pick up the glow of whatever is burning next to them.
+
+use crate::core::bufpool::{self, PooledBuf};
+
+/// Frame pixels per grid cell along each axis
+pub const CELL_SIZE: u32 = 8;
+/// Reach of one particle's light, in cells
+const LIGHT_RADIUS: f32 = 4.0;
+/// How much of the sampled light is added to a line's color
+const LIGHT_STRENGTH: f32 = 0.8;
+
+/// Additive RGB light per cell, kept in pooled buffers and cleared every frame
+pub struct LightGrid {
+    cells: PooledBuf,
+    /// Holds the horizontal pass of the blur
+    scratch: PooledBuf,
+    cols: u32,
+    rows: u32,
+}
+
+impl LightGrid {
+    pub fn new() -> Self {
+        Self {
+            cells: bufpool::get_buffer("light grid", 0),
+            scratch: bufpool::get_buffer("light grid blur", 0),
+            cols: 0,
+            rows: 0,
+        }
+    }
+
+    /// Darkens the grid and sizes it for a `width` x `height` frame
+    pub fn clear(&mut self, width: u32, height: u32) {
+        self.cols = width.div_ceil(CELL_SIZE).max(1);
+        self.rows = height.div_ceil(CELL_SIZE).max(1);
+        self.cells.reset((self.cols * self.rows * 3) as usize);
+    }
+
+    /// Adds light of `color` at frame position (x, y), fading with the square
+    /// of the distance to zero at `LIGHT_RADIUS` cells. `intensity` scales it.
+    pub fn add_light(&mut self, x: f32, y: f32, color: [u8; 3], intensity: f32) {
+        let intensity = intensity.clamp(0.0, 1.0);
+        if intensity <= 0.0 {
+            return;
+        }
+        let (gx, gy) = (x / CELL_SIZE as f32 - 0.5, y / CELL_SIZE as f32 - 0.5);
+        let reach = LIGHT_RADIUS.ceil() as i32;
+        let (cx, cy) = (gx.round() as i32, gy.round() as i32);
+        // Saturating, as a particle flung far off screen rounds to i32::MAX
+        let span = |center: i32, cells: u32| {
+            center.saturating_sub(reach).max(0)..=center.saturating_add(reach).min(cells as i32 - 1)
+        };
+        for row in span(cy, self.rows) {
+            for col in span(cx, self.cols) {
+                let distance = ((col as f32 - gx).powi(2) + (row as f32 - gy).powi(2)).sqrt();
+                let falloff = (1.0 - distance / LIGHT_RADIUS).max(0.0).powi(2) * intensity;
+                if falloff <= 0.0 {
+                    continue;
+                }
+                let idx = ((row as u32 * self.cols + col as u32) * 3) as usize;
+                for (cell, channel) in self.cells[idx..idx + 3].iter_mut().zip(color) {
+                    *cell = cell.saturating_add((channel as f32 * falloff) as u8);
+                }
+            }
+        }
+    }
+
+    /// Softens the grid with a [1 2 1] kernel along each axis
+    pub fn blur(&mut self) {
+        let (cols, rows) = (self.cols as usize, self.rows as usize);
+        self.scratch.reset(self.cells.len());
+        let at = |x: usize, y: usize| (y * cols + x) * 3;
+        for y in 0..rows {
+            for x in 0..cols {
+                let (left, right) = (x.saturating_sub(1), (x + 1).min(cols - 1));
+                for c in 0..3 {
+                    let sum = self.cells[at(left, y) + c] as u32
+                        + 2 * self.cells[at(x, y) + c] as u32
+                        + self.cells[at(right, y) + c] as u32;
+                    self.scratch[at(x, y) + c] = (sum / 4) as u8;
+                }
+            }
+        }
+        for y in 0..rows {
+            let (up, down) = (y.saturating_sub(1), (y + 1).min(rows - 1));
+            for x in 0..cols {
+                for c in 0..3 {
+                    let sum = self.scratch[at(x, up) + c] as u32
+                        + 2 * self.scratch[at(x, y) + c] as u32
+                        + self.scratch[at(x, down) + c] as u32;
+                    self.cells[at(x, y) + c] = (sum / 4) as u8;
+                }
+            }
+        }
+    }
+
+    /// Light at frame position (x, y), interpolated between the four nearest cell centers
+    pub fn sample(&self, x: f32, y: f32) -> [u8; 3] {
+        if self.cells.is_empty() {
+            return [0; 3];
+        }
+        let gx = (x / CELL_SIZE as f32 - 0.5).clamp(0.0, (self.cols - 1) as f32);
+        let gy = (y / CELL_SIZE as f32 - 0.5).clamp(0.0, (self.rows - 1) as f32);
+        let (x0, y0) = (gx as u32, gy as u32);
+        let (x1, y1) = ((x0 + 1).min(self.cols - 1), (y0 + 1).min(self.rows - 1));
+        let (fx, fy) = (gx - x0 as f32, gy - y0 as f32);
+        let cell =
+            |x: u32, y: u32, c: usize| self.cells[((y * self.cols + x) * 3) as usize + c] as f32;
+        std::array::from_fn(|c| {
+            let top = cell(x0, y0, c) * (1.0 - fx) + cell(x1, y0, c) * fx;
+            let bottom = cell(x0, y1, c) * (1.0 - fx) + cell(x1, y1, c) * fx;
+            (top * (1.0 - fy) + bottom * fy).round() as u8
+        })
+    }
+}
+
+impl Default for LightGrid {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// `color` brightened toward `light`; unlit colors come back unchanged
+pub fn lit_color(color: [u8; 4], light: [u8; 3]) -> [u8; 4] {
+    let mut lit = color;
+    for (channel, light) in lit.iter_mut().zip(light) {
+        *channel = channel.saturating_add((light as f32 * LIGHT_STRENGTH) as u8);
+    }
+    lit
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_light_accumulates_and_falls_off() {
+        let mut grid = LightGrid::new();
+        grid.clear(160, 160);
+        // Centered on cell (5, 5)
+        grid.add_light(44.0, 44.0, [200, 100, 0], 1.0);
+        let near = grid.sample(44.0, 44.0);
+        assert_eq!(near, [200, 100, 0]);
+        let mid = grid.sample(44.0 + 2.0 * CELL_SIZE as f32, 44.0);
+        assert!(mid[0] > 0 && mid[0] < near[0], "{:?}", mid);
+        // Past the radius nothing arrives
+        assert_eq!(grid.sample(44.0 + 6.0 * CELL_SIZE as f32, 44.0), [0; 3]);
+
+        // Lights add up and saturate rather than wrap
+        grid.add_light(44.0, 44.0, [200, 100, 0], 1.0);
+        assert_eq!(grid.sample(44.0, 44.0), [255, 200, 0]);
+
+        // Blurring spreads a little light outward but keeps the peak in place
+        grid.blur();
+        let blurred = grid.sample(44.0, 44.0);
+        assert!(blurred[0] > grid.sample(60.0, 44.0)[0]);
+        assert!(grid.sample(44.0 + 4.0 * CELL_SIZE as f32, 44.0)[0] > 0);
+        grid.clear(160, 160);
+        assert_eq!(grid.sample(44.0, 44.0), [0; 3]);
+
+        // Light from far off screen lands nowhere
+        for far in [f32::MAX, f32::MIN, f32::INFINITY, f32::NAN] {
+            grid.add_light(far, far, [255; 3], 1.0);
+            grid.add_light(44.0, far, [255; 3], 1.0);
+        }
+        assert!(grid.cells.iter().all(|&cell| cell == 0));
+    }
+
+    #[test]
+    fn test_sampling_interpolates_between_cell_centers() {
+        let mut grid = LightGrid::new();
+        grid.clear(32, 8);
+        // Cells 0..4 across one row, lit 0, 100, 200, 0
+        grid.cells[3] = 100;
+        grid.cells[6] = 200;
+        let center = |col: f32| (col + 0.5) * CELL_SIZE as f32;
+        assert_eq!(grid.sample(center(1.0), 4.0)[0], 100);
+        assert_eq!(grid.sample(center(1.5), 4.0)[0], 150);
+        assert_eq!(grid.sample(center(1.25), 4.0)[0], 125);
+        assert_eq!(grid.sample(center(2.5), 0.0)[0], 100);
+        // Positions past the outer centers clamp to the edge cells
+        assert_eq!(grid.sample(-20.0, 4.0)[0], 0);
+        assert_eq!(lit_color([10, 20, 30, 255], [0; 3]), [10, 20, 30, 255]);
+        assert_eq!(
+            lit_color([10, 20, 250, 255], [100, 0, 100]),
+            [90, 20, 255, 255]
+        );
+    }
+}
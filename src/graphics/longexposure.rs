@@ -0,0 +1,396 @@
+//! Long exposure, toggled with F12: while the scene runs as usual, every frame
+//! is folded into an accumulation buffer, keeping either the running average
+//! or the brightest value each pixel reached. After the set number of
+//! minutes the exposure is leveled and tone mapped, saved as a PNG next to the
+//! wallpaper captures, optionally shown for a few seconds, and the next one
+//! starts. The balls' and lines' motion comes out as light painting.
+
+use crate::core::capture;
+use crate::graphics::draw_ctx::DrawCtx;
+use crate::ui::toast;
+use image::RgbaImage;
+use log::{info, warn};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+pub const DEFAULT_MINUTES: f32 = 2.0;
+/// How long a finished exposure stays on screen when it is shown
+pub const SHOW_SECONDS: f32 = 4.0;
+/// How far tone mapping lifts the dim end; the white point stays at full
+const EXPOSURE: f32 = 2.0;
+/// Percentiles of the exposure's channel values taken as black and white, so
+/// the static bright edges don't leave the trails dim
+const BLACK_PERCENTILE: f32 = 0.05;
+const WHITE_PERCENTILE: f32 = 0.90;
+/// The white point stays at least this share of the brightest value, so a
+/// mostly black exposure isn't blown out
+const MIN_WHITE: f32 = 0.25;
+const LEVEL_BINS: usize = 1024;
+const CHANNELS: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendMode {
+    /// The mean over the exposure, so where things pass often glows brightest
+    Average,
+    /// The brightest each pixel has been, so every path leaves a full trail.
+    /// Suits sparse scenes; the rays reach everywhere and saturate it.
+    Max,
+}
+
+impl BlendMode {
+    pub const ALL: [Self; 2] = [Self::Average, Self::Max];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Max => "max",
+            Self::Average => "average",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|mode| mode.name() == name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LongExposureSettings {
+    /// Length of each exposure, 1 to 30
+    pub minutes: f32,
+    pub blend: BlendMode,
+    /// Show each finished exposure for `SHOW_SECONDS` before the next starts
+    pub show: bool,
+}
+
+impl LongExposureSettings {
+    pub const DEFAULT: Self = Self {
+        minutes: DEFAULT_MINUTES,
+        blend: BlendMode::Average,
+        show: true,
+    };
+}
+
+/// Extended Reinhard with `value` scaled so `white` lands at full brightness:
+/// 0..=white maps onto 0..=1, lifting the dim end the most
+pub fn tone_map(value: f32, white: f32) -> f32 {
+    if white <= 0.0 {
+        return 0.0;
+    }
+    let x = EXPOSURE * value.max(0.0) / white;
+    (x * (1.0 + x / (EXPOSURE * EXPOSURE)) / (1.0 + x)).min(1.0)
+}
+
+/// Black and white points of channel values in 0..=1, from their histogram
+pub fn levels(values: &[f32]) -> (f32, f32) {
+    let mut histogram = [0usize; LEVEL_BINS];
+    let mut brightest: f32 = 0.0;
+    for &value in values {
+        let value = value.clamp(0.0, 1.0);
+        histogram[(value * (LEVEL_BINS - 1) as f32) as usize] += 1;
+        brightest = brightest.max(value);
+    }
+    let percentile = |p: f32| {
+        let target = (values.len() as f32 * p) as usize;
+        let mut seen = 0;
+        let bin = histogram
+            .iter()
+            .position(|&count| {
+                seen += count;
+                seen > target
+            })
+            .unwrap_or(LEVEL_BINS - 1);
+        bin as f32 / (LEVEL_BINS - 1) as f32
+    };
+    let white = percentile(WHITE_PERCENTILE).max(brightest * MIN_WHITE);
+    let black = percentile(BLACK_PERCENTILE);
+    // A flat exposure has nothing to stretch
+    if black < white {
+        (black, white)
+    } else {
+        (0.0, white)
+    }
+}
+
+/// Per-channel light gathered over many frames
+pub struct Exposure {
+    blend: BlendMode,
+    width: u32,
+    height: u32,
+    accum: Vec<f32>,
+    frames: u32,
+}
+
+impl Exposure {
+    pub fn new(blend: BlendMode, width: u32, height: u32) -> Self {
+        let size = width as usize * height as usize * CHANNELS;
+        Self {
+            blend,
+            width,
+            height,
+            accum: vec![0.0; size],
+            frames: 0,
+        }
+    }
+
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Folds in one frame in a single pass. `frame` starts at the first row
+    /// of the exposed area, which is `x_offset` pixels into rows
+    /// `buffer_width` pixels wide.
+    pub fn accumulate(&mut self, frame: &[u8], x_offset: usize, buffer_width: u32) {
+        let width = self.width as usize;
+        let blend = self.blend;
+        for (y, acc_row) in self.accum.chunks_exact_mut(width * CHANNELS).enumerate() {
+            let start = (y * buffer_width as usize + x_offset) * 4;
+            let Some(row) = frame.get(start..start + width * 4) else {
+                break;
+            };
+            for (acc, px) in acc_row.chunks_exact_mut(CHANNELS).zip(row.chunks_exact(4)) {
+                for (light, &channel) in acc.iter_mut().zip(px) {
+                    let value = channel as f32 / 255.0;
+                    *light = match blend {
+                        BlendMode::Max => light.max(value),
+                        BlendMode::Average => *light + value,
+                    };
+                }
+            }
+        }
+        self.frames += 1;
+    }
+
+    /// Each channel's brightest value or mean, in 0..=1
+    fn values(&self) -> impl Iterator<Item = f32> + '_ {
+        let scale = match self.blend {
+            BlendMode::Max => 1.0,
+            BlendMode::Average => 1.0 / self.frames.max(1) as f32,
+        };
+        self.accum.iter().map(move |light| light * scale)
+    }
+
+    /// Levels and tone maps the exposure into an opaque image
+    pub fn develop(&self) -> RgbaImage {
+        let values: Vec<f32> = self.values().collect();
+        let (black, white) = levels(&values);
+        let mut pixels = Vec::with_capacity(self.width as usize * self.height as usize * 4);
+        for rgb in values.chunks_exact(CHANNELS) {
+            let developed = rgb
+                .iter()
+                .map(|&v| tone_map(v - black, white - black) * 255.0);
+            pixels.extend(developed.map(|v| v.round() as u8));
+            pixels.push(255);
+        }
+        RgbaImage::from_raw(self.width, self.height, pixels).expect("exposure matches its size")
+    }
+}
+
+enum Stage {
+    Exposing {
+        exposure: Exposure,
+        elapsed: f32,
+    },
+    /// The finished exposure on screen for this many more seconds
+    Showing {
+        image: RgbaImage,
+        remaining: f32,
+    },
+}
+
+struct Session {
+    stage: Stage,
+    last_time: Option<f32>,
+}
+
+static SETTINGS: Mutex<LongExposureSettings> = Mutex::new(LongExposureSettings::DEFAULT);
+static SESSION: Mutex<Option<Session>> = Mutex::new(None);
+
+fn session() -> MutexGuard<'static, Option<Session>> {
+    SESSION.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+pub fn long_exposure_settings() -> LongExposureSettings {
+    *SETTINGS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Takes effect from the next exposure
+pub fn set_long_exposure_settings(settings: LongExposureSettings) {
+    *SETTINGS.lock().unwrap_or_else(PoisonError::into_inner) = settings;
+}
+
+pub fn is_long_exposure() -> bool {
+    session().is_some()
+}
+
+/// Starts an exposure, or drops the one in progress; returns whether one is
+/// running now
+pub fn toggle_long_exposure() -> bool {
+    let mut session = session();
+    *session = if session.is_some() {
+        None
+    } else {
+        Some(Session {
+            stage: Stage::Exposing {
+                exposure: Exposure::new(long_exposure_settings().blend, 0, 0),
+                elapsed: 0.0,
+            },
+            last_time: None,
+        })
+    };
+    session.is_some()
+}
+
+fn save(image: &RgbaImage) {
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let Some(dir) = capture::capture_dir() else {
+        warn!("Long exposure not saved: no pictures or data folder");
+        return;
+    };
+    match capture::save_capture(image, &dir, secs) {
+        Ok(path) => {
+            info!("Long exposure saved to {}", path.display());
+            toast::show_toast(vec![
+                "Long exposure saved".to_string(),
+                path.display().to_string(),
+            ]);
+        }
+        Err(e) => warn!("Long exposure not saved: {}", e),
+    }
+}
+
+/// Folds the scene just drawn into the exposure, or puts the finished one
+/// over it while it is shown. Called once a frame after the scene draws and
+/// before the overlays, so the HUD stays out of the picture.
+pub fn update(ctx: &mut DrawCtx) {
+    let mut slot = session();
+    let Some(session) = slot.as_mut() else {
+        return;
+    };
+    let settings = long_exposure_settings();
+    let dt = session
+        .last_time
+        .map_or(0.0, |last| (ctx.time - last).clamp(0.0, 0.1));
+    session.last_time = Some(ctx.time);
+    let size = (ctx.width(), ctx.height());
+    let next = match &mut session.stage {
+        Stage::Exposing { exposure, elapsed } => {
+            // A resize starts over; the old light no longer lines up
+            if exposure.size() != size {
+                *exposure = Exposure::new(settings.blend, size.0, size.1);
+                *elapsed = 0.0;
+            }
+            let (frame, _, _, x_offset, buffer_width) = ctx.legacy();
+            exposure.accumulate(frame, x_offset, buffer_width);
+            *elapsed += dt;
+            if *elapsed < settings.minutes * 60.0 {
+                return;
+            }
+            let image = exposure.develop();
+            save(&image);
+            if settings.show {
+                Some(Stage::Showing {
+                    image,
+                    remaining: SHOW_SECONDS,
+                })
+            } else {
+                None
+            }
+        }
+        Stage::Showing { image, remaining } => {
+            if image.dimensions() == size {
+                ctx.restore_region(image.as_raw());
+            }
+            *remaining -= dt;
+            if *remaining > 0.0 {
+                return;
+            }
+            None
+        }
+    };
+    session.stage = next.unwrap_or_else(|| Stage::Exposing {
+        exposure: Exposure::new(settings.blend, size.0, size.1),
+        elapsed: 0.0,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `width` x 1 frame of gray pixels
+    fn gray_row(levels: &[u8]) -> Vec<u8> {
+        levels.iter().flat_map(|&l| [l, l, l, 255]).collect()
+    }
+
+    #[test]
+    fn test_max_keeps_the_brightest_and_average_the_mean() {
+        let frames = [gray_row(&[0, 255, 40]), gray_row(&[200, 0, 40])];
+        let mut max = Exposure::new(BlendMode::Max, 3, 1);
+        let mut average = Exposure::new(BlendMode::Average, 3, 1);
+        for frame in &frames {
+            max.accumulate(frame, 0, 3);
+            average.accumulate(frame, 0, 3);
+        }
+        assert_eq!(average.frames(), 2);
+        let channel = |e: &Exposure, x: usize| e.values().nth(x * CHANNELS).unwrap();
+        assert!((channel(&max, 0) - 200.0 / 255.0).abs() < 1e-6);
+        assert_eq!(channel(&max, 1), 1.0);
+        assert!((channel(&average, 0) - 100.0 / 255.0).abs() < 1e-6);
+        assert!((channel(&average, 1) - 0.5).abs() < 1e-6);
+        assert!((channel(&average, 2) - 40.0 / 255.0).abs() < 1e-6);
+
+        // Developed, the brightest pixel is full white, the darkest black,
+        // and alpha is opaque
+        let image = max.develop();
+        assert_eq!(image.get_pixel(1, 0).0, [255, 255, 255, 255]);
+        assert_eq!(image.get_pixel(2, 0).0, [0, 0, 0, 255]);
+        assert!(average.develop().get_pixel(2, 0).0[0] < 128);
+
+        // Only the region at the offset is exposed
+        let mut offset = Exposure::new(BlendMode::Max, 1, 2);
+        let wide = [gray_row(&[9, 77]), gray_row(&[9, 33])].concat();
+        offset.accumulate(&wide, 1, 2);
+        let values: Vec<f32> = offset.values().step_by(CHANNELS).collect();
+        assert_eq!(values, [77.0 / 255.0, 33.0 / 255.0]);
+    }
+
+    #[test]
+    fn test_tone_map_spans_zero_to_one_and_lifts_the_dim_end() {
+        let white = 0.8;
+        assert_eq!(tone_map(0.0, white), 0.0);
+        assert!((tone_map(white, white) - 1.0).abs() < 1e-6);
+        assert_eq!(tone_map(2.0, white), 1.0);
+        assert_eq!(tone_map(0.5, 0.0), 0.0);
+        assert_eq!(tone_map(-0.1, white), 0.0);
+        let mut last = 0.0;
+        for i in 1..=100 {
+            let value = white * i as f32 / 100.0;
+            let mapped = tone_map(value, white);
+            assert!(mapped > last && mapped <= 1.0);
+            // Dim trails come out brighter than a straight rescale
+            assert!(mapped >= value / white - 1e-6);
+            last = mapped;
+        }
+
+        // A few bright pixels don't set the white point; a dark exposure
+        // keeps it near its brightest
+        let mut values = vec![0.2; 95];
+        values.extend([1.0; 5]);
+        values[0] = 0.0;
+        let (black, white) = levels(&values);
+        assert!((black - 0.2).abs() < 0.01, "{}", black);
+        assert!((white - 0.25).abs() < 0.01, "{}", white);
+        let mut dark = vec![0.0; 95];
+        dark.extend([0.8; 5]);
+        let (black, white) = levels(&dark);
+        assert_eq!(black, 0.0);
+        assert!((white - 0.2).abs() < 0.01, "{}", white);
+        let (black, white) = levels(&[0.5; 4]);
+        assert_eq!(black, 0.0);
+        assert!((white - 0.5).abs() < 0.01, "{}", white);
+    }
+}
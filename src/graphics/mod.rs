@@ -1,3 +1,19 @@
+pub mod accents;
+pub mod background;
+pub mod draw_ctx;
+pub mod kaleidoscope;
+pub mod light_grid;
+pub mod longexposure;
+pub mod noise;
+pub mod palette_extract;
 pub mod pixel_utils;
+pub mod post;
+pub mod rain;
 pub mod ray_pattern;
 pub mod render;
+pub mod screen_shake;
+pub mod shatter;
+pub mod trail;
+pub mod theme;
+pub mod tunnel;
+pub mod view;
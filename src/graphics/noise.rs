@@ -0,0 +1,26 @@
+use std::f32::consts::E;
+
+/// Smooth pseudo-noise in -1..=1 built from incommensurate sines.
+/// Different seeds give uncorrelated-looking curves over the same `t`.
+pub fn smooth_noise(t: f32, seed: f32) -> f32 {
+    let value = (t + seed).sin() * 0.5
+        + (t * 1.618 + seed * 2.1).sin() * 0.3
+        + (t * E + seed * 3.7).sin() * 0.2;
+    value.clamp(-1.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_noise_is_bounded_and_continuous() {
+        let mut previous = smooth_noise(0.0, 3.0);
+        for i in 1..10_000 {
+            let value = smooth_noise(i as f32 * 0.01, 3.0);
+            assert!((-1.0..=1.0).contains(&value));
+            assert!((value - previous).abs() < 0.05);
+            previous = value;
+        }
+    }
+}
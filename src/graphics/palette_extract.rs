@@ -0,0 +1,538 @@
+//! A theme made from whatever is on screen: the frame is box-filtered to
+//! 1/`DOWNSCALE` size, k-means groups its pixels into `PALETTE_SIZE` dominant
+//! colors, and those are handed out to the theme's roles. Seeding is k-means++
+//! driven by a hash of the pixels, so the same frame always gives the same
+//! palette. Ctrl+T asks for one; pressing it again within `REVERT_WINDOW`
+//! puts back the theme it replaced.
+
+use crate::core::accessibility::{contrast_ratio, relative_luminance, MIN_TEXT_CONTRAST};
+use crate::graphics::theme::{self, Theme, ThemeState};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Colors extracted from a frame
+pub const PALETTE_SIZE: usize = 5;
+/// The frame is averaged in blocks this many pixels square before clustering
+pub const DOWNSCALE: u32 = 8;
+/// Seconds after an extraction during which the key reverts it
+pub const REVERT_WINDOW: f32 = 5.0;
+const MAX_ITERATIONS: usize = 32;
+/// Brightest any channel of the HUD surface may be, so panels stay dark
+const SURFACE_MAX: u8 = 48;
+/// How far the accent pulls the frame's tint away from neutral
+const TINT_STRENGTH: f32 = 0.4;
+
+static FRAME_THEME: Mutex<FrameTheme> = Mutex::new(FrameTheme::new());
+
+fn frame_theme() -> MutexGuard<'static, FrameTheme> {
+    FRAME_THEME.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// One dominant color and the share of the frame it stands for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Swatch {
+    pub color: [u8; 3],
+    pub weight: f32,
+}
+
+/// Result of `kmeans`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Clustering {
+    pub centers: Vec<[f32; 3]>,
+    /// Points nearest each center
+    pub counts: Vec<usize>,
+    /// Assignment passes run, the last one changing nothing when converged
+    pub iterations: usize,
+    pub converged: bool,
+}
+
+/// RGB of each `DOWNSCALE`-square block of an RGBA frame, averaged
+pub fn downsample(frame: &[u8], width: u32, height: u32) -> Vec<[f32; 3]> {
+    let (cols, rows) = (width.div_ceil(DOWNSCALE), height.div_ceil(DOWNSCALE));
+    let mut points = Vec::with_capacity((cols * rows) as usize);
+    for row in 0..rows {
+        for col in 0..cols {
+            let mut sum = [0.0f32; 3];
+            let mut count = 0;
+            for y in row * DOWNSCALE..((row + 1) * DOWNSCALE).min(height) {
+                for x in col * DOWNSCALE..((col + 1) * DOWNSCALE).min(width) {
+                    let i = 4 * (y * width + x) as usize;
+                    if let Some(pixel) = frame.get(i..i + 3) {
+                        for (total, &value) in sum.iter_mut().zip(pixel) {
+                            *total += value as f32;
+                        }
+                        count += 1;
+                    }
+                }
+            }
+            if count > 0 {
+                points.push(sum.map(|total| total / count as f32));
+            }
+        }
+    }
+    points
+}
+
+fn distance2(a: [f32; 3], b: [f32; 3]) -> f32 {
+    (0..3).map(|i| (a[i] - b[i]).powi(2)).sum()
+}
+
+fn nearest_center(centers: &[[f32; 3]], point: [f32; 3]) -> usize {
+    (0..centers.len())
+        .min_by(|&a, &b| distance2(centers[a], point).total_cmp(&distance2(centers[b], point)))
+        .unwrap_or(0)
+}
+
+/// k-means++: each new center is picked with probability proportional to
+/// its squared distance from the nearest center so far. Stops short of `k`
+/// when every point already sits on a center.
+fn seed_centers(points: &[[f32; 3]], k: usize, rng: &mut StdRng) -> Vec<[f32; 3]> {
+    let mut centers = vec![points[rng.gen_range(0..points.len())]];
+    let mut nearest: Vec<f32> = points.iter().map(|&p| distance2(p, centers[0])).collect();
+    while centers.len() < k {
+        let total: f32 = nearest.iter().sum();
+        if total <= 0.0 {
+            break;
+        }
+        let mut target = rng.gen::<f32>() * total;
+        let index = nearest
+            .iter()
+            .position(|&d| {
+                target -= d;
+                target < 0.0
+            })
+            // Rounding can leave a sliver past the end; the last candidate takes it
+            .or_else(|| nearest.iter().rposition(|&d| d > 0.0))
+            .unwrap_or(0);
+        let center = points[index];
+        centers.push(center);
+        for (d, &point) in nearest.iter_mut().zip(points) {
+            *d = d.min(distance2(point, center));
+        }
+    }
+    centers
+}
+
+/// Groups `points` into at most `k` clusters, seeded from `seed`
+pub fn kmeans(points: &[[f32; 3]], k: usize, seed: u64) -> Clustering {
+    if points.is_empty() || k == 0 {
+        return Clustering {
+            centers: Vec::new(),
+            counts: Vec::new(),
+            iterations: 0,
+            converged: true,
+        };
+    }
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut centers = seed_centers(points, k, &mut rng);
+    let mut assignment = vec![usize::MAX; points.len()];
+    let mut iterations = 0;
+    let mut converged = false;
+    while iterations < MAX_ITERATIONS {
+        iterations += 1;
+        let mut changed = false;
+        for (slot, &point) in assignment.iter_mut().zip(points) {
+            let nearest = nearest_center(&centers, point);
+            changed |= *slot != nearest;
+            *slot = nearest;
+        }
+        if !changed {
+            converged = true;
+            break;
+        }
+        let mut sums = vec![([0.0f32; 3], 0usize); centers.len()];
+        for (&cluster, point) in assignment.iter().zip(points) {
+            let (sum, count) = &mut sums[cluster];
+            for (total, value) in sum.iter_mut().zip(point) {
+                *total += value;
+            }
+            *count += 1;
+        }
+        // An emptied cluster keeps its center
+        for (center, (sum, count)) in centers.iter_mut().zip(sums) {
+            if count > 0 {
+                *center = sum.map(|total| total / count as f32);
+            }
+        }
+    }
+    let mut counts = vec![0; centers.len()];
+    for &cluster in &assignment {
+        counts[cluster] += 1;
+    }
+    Clustering {
+        centers,
+        counts,
+        iterations,
+        converged,
+    }
+}
+
+/// FNV-1a over the rounded points, so equal frames seed equally
+fn hash_points(points: &[[f32; 3]]) -> u64 {
+    points
+        .iter()
+        .flatten()
+        .fold(0xcbf2_9ce4_8422_2325, |hash, &value| {
+            (hash ^ value.round() as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+}
+
+/// Dominant colors of an RGBA frame, most common first
+pub fn extract_palette(frame: &[u8], width: u32, height: u32) -> Vec<Swatch> {
+    let points = downsample(frame, width, height);
+    let clustering = kmeans(&points, PALETTE_SIZE, hash_points(&points));
+    let total = points.len().max(1) as f32;
+    let mut swatches: Vec<Swatch> = clustering
+        .centers
+        .iter()
+        .zip(&clustering.counts)
+        .filter(|&(_, &count)| count > 0)
+        .map(|(center, &count)| Swatch {
+            color: center.map(|value| value.round() as u8),
+            weight: count as f32 / total,
+        })
+        .collect();
+    swatches.sort_by(|a, b| b.weight.total_cmp(&a.weight));
+    swatches
+}
+
+/// HSV saturation: 0 for grays and black, 1 for pure hues
+pub fn saturation(rgb: [u8; 3]) -> f32 {
+    let max = rgb.into_iter().max().unwrap_or(0);
+    let min = rgb.into_iter().min().unwrap_or(0);
+    if max == 0 {
+        0.0
+    } else {
+        (max - min) as f32 / max as f32
+    }
+}
+
+fn mix(a: [u8; 3], b: [u8; 3], t: f32) -> [u8; 3] {
+    [0, 1, 2].map(|i| (a[i] as f32 + (b[i] as f32 - a[i] as f32) * t).round() as u8)
+}
+
+/// Gains that lean the frame toward `accent`'s hue; neutral for grays
+fn accent_tint(accent: [u8; 3]) -> [f32; 3] {
+    let max = accent.into_iter().max().unwrap_or(0) as f32;
+    if max == 0.0 {
+        return [1.0; 3];
+    }
+    accent.map(|c| 1.0 - TINT_STRENGTH * (1.0 - c as f32 / max))
+}
+
+/// Hands the palette out to the theme's roles: the darkest color backs the
+/// HUD, the most saturated of the rest tints the frame, and the lightest,
+/// lifted toward white until it reads on the surface, is the light text.
+/// None for an empty palette.
+pub fn theme_from_palette(palette: &[Swatch]) -> Option<Theme> {
+    let luminance = |swatch: &&Swatch| relative_luminance(swatch.color);
+    let darkest = palette
+        .iter()
+        .min_by(|a, b| luminance(a).total_cmp(&luminance(b)))?
+        .color;
+    let lightest = palette
+        .iter()
+        .max_by(|a, b| luminance(a).total_cmp(&luminance(b)))?
+        .color;
+    let accent = palette
+        .iter()
+        .filter(|swatch| swatch.color != darkest)
+        .max_by(|a, b| saturation(a.color).total_cmp(&saturation(b.color)))
+        .map_or(darkest, |swatch| swatch.color);
+
+    let brightest = darkest.into_iter().max().unwrap_or(0);
+    let surface = if brightest > SURFACE_MAX {
+        darkest.map(|c| (c as u32 * SURFACE_MAX as u32 / brightest as u32) as u8)
+    } else {
+        darkest
+    };
+    let surface_luminance = relative_luminance(surface);
+    let light_text = (0..=10)
+        .map(|step| mix(lightest, [255; 3], step as f32 / 10.0))
+        .find(|&color| {
+            contrast_ratio(relative_luminance(color), surface_luminance) >= MIN_TEXT_CONTRAST
+        })
+        .unwrap_or([255; 3]);
+    Some(Theme {
+        name: "From frame",
+        tint: accent_tint(accent),
+        surface,
+        contrast_text: [light_text, mix(darkest, [0; 3], 0.75)],
+    })
+}
+
+/// What pressing the frame theme key did
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PressOutcome {
+    /// The next frame will be sampled
+    Requested,
+    /// The theme from before the last extraction is back
+    Reverted,
+}
+
+/// A theme waiting for the next frame, and the theme state the last
+/// extraction replaced with when it happened
+#[derive(Debug, Clone, Default)]
+pub struct FrameTheme {
+    requested: bool,
+    replaced: Option<(ThemeState, f32)>,
+}
+
+impl FrameTheme {
+    pub const fn new() -> Self {
+        Self {
+            requested: false,
+            replaced: None,
+        }
+    }
+
+    /// Asks for a theme from the next frame or, within `REVERT_WINDOW` of the
+    /// last extraction, puts back what that replaced
+    pub fn press(&mut self, now: f32, themes: &mut ThemeState) -> PressOutcome {
+        match self.replaced.take() {
+            Some((previous, at)) if (0.0..REVERT_WINDOW).contains(&(now - at)) => {
+                *themes = previous;
+                self.requested = false;
+                PressOutcome::Reverted
+            }
+            _ => {
+                self.requested = true;
+                PressOutcome::Requested
+            }
+        }
+    }
+
+    /// Extracts a requested theme from `frame` and picks it, suspending the
+    /// schedule. Returns the palette when it did.
+    pub fn apply(
+        &mut self,
+        frame: &[u8],
+        width: u32,
+        height: u32,
+        now: f32,
+        themes: &mut ThemeState,
+    ) -> Option<Vec<Swatch>> {
+        if !std::mem::take(&mut self.requested) {
+            return None;
+        }
+        let palette = extract_palette(frame, width, height);
+        let theme = theme_from_palette(&palette)?;
+        self.replaced = Some((themes.clone(), now));
+        themes.pick(theme);
+        Some(palette)
+    }
+}
+
+/// The frame theme key, pressed at scene time `now`
+pub fn press_frame_theme(now: f32) -> PressOutcome {
+    theme::with_theme_state(|themes| frame_theme().press(now, themes))
+}
+
+/// Applies a requested frame theme from the composed `frame`; call after
+/// drawing it and before post-processing, so the current tint isn't sampled
+pub fn apply_frame_theme(frame: &[u8], width: u32, height: u32, now: f32) -> Option<Vec<Swatch>> {
+    theme::with_theme_state(|themes| frame_theme().apply(frame, width, height, now, themes))
+}
+
+/// `#rrggbb`
+pub fn hex(color: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::types::{color_to_rgba, hsv_to_rgb};
+
+    /// An RGBA frame colored by `paint(x, y)`
+    fn image(width: u32, height: u32, paint: impl Fn(u32, u32) -> [u8; 3]) -> Vec<u8> {
+        (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .flat_map(|(x, y)| {
+                let [r, g, b] = paint(x, y);
+                [r, g, b, 255]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_kmeans_converges_on_separated_clusters() {
+        // Three blobs of slightly noisy color
+        let colors = [
+            [200.0, 30.0, 30.0],
+            [20.0, 180.0, 40.0],
+            [30.0, 40.0, 220.0],
+        ];
+        let points: Vec<[f32; 3]> = (0..300)
+            .map(|i| {
+                let jitter = (i % 7) as f32 - 3.0;
+                colors[i % 3].map(|c| c + jitter)
+            })
+            .collect();
+        let clustering = kmeans(&points, 3, 42);
+        assert!(clustering.converged);
+        assert!(clustering.iterations < MAX_ITERATIONS);
+        assert_eq!(clustering.counts, [100, 100, 100]);
+        for color in colors {
+            assert!(clustering
+                .centers
+                .iter()
+                .any(|&center| distance2(center, color) < 4.0));
+        }
+        // The same points and seed always cluster the same way
+        assert_eq!(kmeans(&points, 3, 42), clustering);
+
+        // Fewer distinct colors than clusters asked for
+        let two = [[0.0; 3], [255.0; 3]].repeat(10);
+        let clustering = kmeans(&two, 5, 1);
+        assert!(clustering.converged);
+        assert_eq!(clustering.centers.len(), 2);
+    }
+
+    #[test]
+    fn test_downsampling_averages_blocks() {
+        let frame = image(16, 8, |x, _| if x < 8 { [80, 0, 0] } else { [0, 0, 160] });
+        assert_eq!(
+            downsample(&frame, 16, 8),
+            [[80.0, 0.0, 0.0], [0.0, 0.0, 160.0]]
+        );
+        // A thin bright line in a block reads as a dim one
+        let frame = image(8, 8, |x, _| if x == 0 { [255, 255, 0] } else { [0; 3] });
+        let points = downsample(&frame, 8, 8);
+        let [[r, g, b]] = points[..] else {
+            panic!("one block");
+        };
+        assert!((r - 255.0 / 8.0).abs() < 1e-3 && (g - r).abs() < 1e-3 && b == 0.0);
+    }
+
+    #[test]
+    fn test_a_rainbow_gives_a_vivid_multi_hue_theme() {
+        // Hue around the center, like the circular scene
+        let frame = image(128, 128, |x, y| {
+            let angle = (y as f32 - 64.0).atan2(x as f32 - 64.0);
+            let [r, g, b, _] = color_to_rgba(hsv_to_rgb(
+                angle.to_degrees().rem_euclid(360.0) / 360.0,
+                1.0,
+                1.0,
+            ));
+            [r, g, b]
+        });
+        let palette = extract_palette(&frame, 128, 128);
+        assert_eq!(palette.len(), PALETTE_SIZE);
+        let vivid: Vec<_> = palette
+            .iter()
+            .filter(|swatch| saturation(swatch.color) > 0.6)
+            .collect();
+        assert!(vivid.len() >= 4, "{:?}", palette);
+        let theme = theme_from_palette(&palette).unwrap();
+        assert!(saturation(theme.surface) > 0.5);
+        assert!(theme.tint.iter().any(|&gain| gain < 0.8));
+    }
+
+    #[test]
+    fn test_rays_give_a_dark_yellow_green_theme() {
+        // Black, with a yellow and a green ball and rays fanning from each
+        let frame = image(160, 80, |x, y| {
+            let near = |cx: f32, cy: f32| {
+                let (dx, dy) = (x as f32 - cx, y as f32 - cy);
+                dx.hypot(dy) < 6.0 || (dy.atan2(dx).to_degrees().rem_euclid(20.0) < 1.0)
+            };
+            if near(40.0, 40.0) {
+                [255, 255, 150]
+            } else if near(120.0, 40.0) {
+                [150, 255, 150]
+            } else {
+                [0, 0, 0]
+            }
+        });
+        let palette = extract_palette(&frame, 160, 80);
+        let theme = theme_from_palette(&palette).unwrap();
+        // Mostly dark, so the surface is near black
+        assert!(theme.surface.iter().all(|&c| c <= 20), "{:?}", theme);
+        let mean = palette
+            .iter()
+            .map(|swatch| relative_luminance(swatch.color) * swatch.weight)
+            .sum::<f32>();
+        assert!(mean < 0.2, "{:?}", palette);
+        // Tinted toward yellow and green, away from blue
+        let [r, g, b] = theme.tint;
+        assert!(b < g && b <= r, "{:?}", theme.tint);
+        assert!(g >= r);
+        // Light text still reads on the dark surface
+        let contrast = contrast_ratio(
+            relative_luminance(theme.contrast_text[0]),
+            relative_luminance(theme.surface),
+        );
+        assert!(contrast >= MIN_TEXT_CONTRAST);
+    }
+
+    #[test]
+    fn test_roles_follow_lightness_and_saturation() {
+        let palette = [
+            Swatch {
+                color: [40, 40, 40],
+                weight: 0.5,
+            },
+            Swatch {
+                color: [90, 10, 200],
+                weight: 0.2,
+            },
+            Swatch {
+                color: [230, 230, 220],
+                weight: 0.2,
+            },
+            Swatch {
+                color: [120, 130, 120],
+                weight: 0.1,
+            },
+        ];
+        let theme = theme_from_palette(&palette).unwrap();
+        assert_eq!(theme.surface, [40, 40, 40]);
+        assert_eq!(theme.tint, accent_tint([90, 10, 200]));
+        assert_eq!(theme.tint[2], 1.0);
+        assert!(relative_luminance(theme.contrast_text[0]) >= relative_luminance([230, 230, 220]));
+        assert_eq!(theme.contrast_text[1], [10, 10, 10]);
+        // A bright background is darkened to keep HUD panels dark
+        let pale = [Swatch {
+            color: [200, 100, 50],
+            weight: 1.0,
+        }];
+        assert_eq!(theme_from_palette(&pale).unwrap().surface, [48, 24, 12]);
+        assert_eq!(theme_from_palette(&[]), None);
+    }
+
+    #[test]
+    fn test_pressing_again_within_the_window_reverts() {
+        let frame = image(
+            32,
+            32,
+            |x, _| if x < 16 { [10, 10, 30] } else { [250, 120, 0] },
+        );
+        let mut themes = ThemeState::new();
+        themes.resume_schedule();
+        let before = themes.clone();
+        let mut key = FrameTheme::new();
+
+        // Nothing happens until asked
+        assert_eq!(key.apply(&frame, 32, 32, 0.0, &mut themes), None);
+        assert_eq!(key.press(1.0, &mut themes), PressOutcome::Requested);
+        assert!(key.apply(&frame, 32, 32, 1.0, &mut themes).is_some());
+        assert_eq!(themes.theme.name, "From frame");
+        assert!(!themes.following_schedule());
+        // Only once per press
+        assert_eq!(key.apply(&frame, 32, 32, 1.1, &mut themes), None);
+
+        assert_eq!(key.press(3.0, &mut themes), PressOutcome::Reverted);
+        assert_eq!(themes, before);
+
+        // After the window, pressing extracts again instead
+        key.press(10.0, &mut themes);
+        key.apply(&frame, 32, 32, 10.0, &mut themes);
+        assert_eq!(
+            key.press(10.0 + REVERT_WINDOW, &mut themes),
+            PressOutcome::Requested
+        );
+        assert_eq!(themes.theme.name, "From frame");
+    }
+}
@@ -1,4 +1,5 @@
 use crate::core::types::{HEIGHT, WIDTH};
+use crate::text::text_rendering::{self, GlyphStyle};
 pub fn set_pixel_safe(frame: &mut [u8], x: i32, y: i32, width: u32, height: u32, color: [u8; 4]) {
     if x >= 0 && x < width as i32 && y >= 0 && y < height as i32 {
         let idx = 4 * (y as usize * width as usize + x as usize);
@@ -227,32 +228,65 @@ pub fn draw_extra_bright_particle(
         }
     }
 }
+/// Capital letter height of `draw_huge_text`, in pixels
+pub const HUGE_CHAR_HEIGHT: u32 = 50;
+/// Outline stroke at `HUGE_CHAR_HEIGHT`; scales with the letter height. Kept
+/// under half a letter's stem so the letters stay hollow.
+const HUGE_STROKE_WIDTH: u32 = 2;
+
+/// How `draw_huge_text_styled` draws its letters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HugeTextStyle {
+    Filled,
+    /// Only a stroke along each letter's edge
+    Outline,
+}
+
+/// Letters for `draw_huge_text_styled`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HugeLetters {
+    /// Capital letter height in pixels
+    pub char_height: u32,
+    pub style: HugeTextStyle,
+    pub color: [u8; 4],
+}
+
+/// Draws `text` in outlined letters `HUGE_CHAR_HEIGHT` tall, with the top of
+/// the capitals at `y`
 pub fn draw_huge_text(frame: &mut [u8], text: &str, x: i32, y: i32, color: [u8; 4], width: u32) {
-    let char_width = 30;
-    let char_height = 50;
-    let stroke_width = 4;
-    let height = frame.len() / (4 * width as usize);
-    if y + char_height < 0 || y >= height as i32 {
-        return;
-    }
-    for (i, _c) in text.chars().enumerate() {
-        let cx = x + (i as i32 * char_width);
-        if cx + char_width < 0 || cx >= width as i32 {
-            continue;
-        }
-        for dy in 0..char_height {
-            for dx in 0..char_width {
-                let is_border = dx < stroke_width
-                    || dx >= char_width - stroke_width
-                    || dy < stroke_width
-                    || dy >= char_height - stroke_width;
-                if is_border {
-                    set_pixel_safe(frame, cx + dx, y + dy, width, height as u32, color);
-                }
-            }
+    let letters = HugeLetters {
+        char_height: HUGE_CHAR_HEIGHT,
+        style: HugeTextStyle::Outline,
+        color,
+    };
+    draw_huge_text_styled(frame, text, x, y, letters, width);
+}
+
+/// Draws `text` in `letters`, the tops of the capitals at `y`
+pub fn draw_huge_text_styled(
+    frame: &mut [u8],
+    text: &str,
+    x: i32,
+    y: i32,
+    letters: HugeLetters,
+    width: u32,
+) {
+    let char_height = letters.char_height;
+    let stroke = match letters.style {
+        HugeTextStyle::Filled => 0,
+        HugeTextStyle::Outline => {
+            (HUGE_STROKE_WIDTH * char_height).div_ceil(HUGE_CHAR_HEIGHT).max(1)
         }
-    }
+    };
+    let style = GlyphStyle {
+        size: text_rendering::size_for_cap_height(char_height as f32),
+        stroke,
+        color: letters.color,
+    };
+    let baseline = (y + char_height as i32) as f32;
+    text_rendering::draw_text_sized(frame, text, x as f32, baseline, style, width);
 }
+
 pub fn draw_border(
     frame: &mut [u8],
     x: i32,
@@ -0,0 +1,340 @@
+//! Whole-frame filters run after everything else is drawn, including screen
+//! shake. Each filter is a 3x3 color matrix applied with integer math, so
+//! stages can be chained by multiplying their matrices.
+
+use crate::core::presets::{lerp_degrees, switch_at_midpoint, Interpolate};
+use crate::graphics::theme;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Fractional bits of the fixed-point matrix coefficients
+const FIXED_SHIFT: i32 = 12;
+const FIXED_ONE: f32 = (1 << FIXED_SHIFT) as f32;
+/// Rotation speed of the hue LFO, in degrees per second (one turn a minute)
+pub const HUE_LFO_SPEED: f32 = 6.0;
+
+/// Post-process color filter, cycled at runtime
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorFilter {
+    #[default]
+    None,
+    HueShift,
+    Protanopia,
+    Deuteranopia,
+    Tritanopia,
+}
+
+impl ColorFilter {
+    pub const ALL: [ColorFilter; 5] = [
+        ColorFilter::None,
+        ColorFilter::HueShift,
+        ColorFilter::Protanopia,
+        ColorFilter::Deuteranopia,
+        ColorFilter::Tritanopia,
+    ];
+
+    /// Name used in the settings file
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColorFilter::None => "none",
+            ColorFilter::HueShift => "hue_shift",
+            ColorFilter::Protanopia => "protanopia",
+            ColorFilter::Deuteranopia => "deuteranopia",
+            ColorFilter::Tritanopia => "tritanopia",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|filter| filter.name() == name)
+    }
+
+    /// Cycles through `ALL` in order
+    pub fn next(&self) -> Self {
+        let index = Self::ALL.iter().position(|f| f == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PostSettings {
+    pub filter: ColorFilter,
+    /// Rotation used by the hue-shift filter, in degrees
+    pub hue_shift: f32,
+    /// Keep turning the hue at `HUE_LFO_SPEED` on top of `hue_shift`
+    pub hue_lfo: bool,
+}
+
+impl PostSettings {
+    pub const DEFAULT: Self = Self {
+        filter: ColorFilter::None,
+        hue_shift: 30.0,
+        hue_lfo: false,
+    };
+}
+
+/// The hue turns the short way round; the filter and LFO switch at the midpoint
+impl Interpolate for PostSettings {
+    fn interpolate(&self, to: &Self, t: f32) -> Self {
+        Self {
+            filter: switch_at_midpoint(self.filter, to.filter, t),
+            hue_shift: lerp_degrees(self.hue_shift, to.hue_shift, t),
+            hue_lfo: switch_at_midpoint(self.hue_lfo, to.hue_lfo, t),
+        }
+    }
+}
+
+impl Default for PostSettings {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+static POST_SETTINGS: Mutex<PostSettings> = Mutex::new(PostSettings::DEFAULT);
+
+fn locked_settings() -> MutexGuard<'static, PostSettings> {
+    POST_SETTINGS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+pub fn post_settings() -> PostSettings {
+    *locked_settings()
+}
+
+pub fn set_post_settings(settings: PostSettings) {
+    *locked_settings() = PostSettings {
+        hue_shift: settings.hue_shift.rem_euclid(360.0),
+        ..settings
+    };
+}
+
+/// Moves to the next color filter and returns it
+pub fn cycle_color_filter() -> ColorFilter {
+    let mut settings = locked_settings();
+    settings.filter = settings.filter.next();
+    settings.filter
+}
+
+/// Color-blindness simulation matrices from Machado et al. (2009), full severity
+const PROTANOPIA_SIM: [[f32; 3]; 3] = [
+    [0.152286, 1.052583, -0.204868],
+    [0.114503, 0.786281, 0.099216],
+    [-0.003882, -0.048116, 1.051998],
+];
+const DEUTERANOPIA_SIM: [[f32; 3]; 3] = [
+    [0.367322, 0.860646, -0.227968],
+    [0.280085, 0.672501, 0.047413],
+    [-0.011820, 0.042940, 0.968881],
+];
+const TRITANOPIA_SIM: [[f32; 3]; 3] = [
+    [1.255528, -0.076749, -0.178779],
+    [-0.078411, 0.930809, 0.147602],
+    [0.004733, 0.691367, 0.303900],
+];
+/// Where the color information lost to red-green deficiencies is moved:
+/// into green and blue, which remain distinguishable
+const RED_GREEN_SHIFT: [[f32; 3]; 3] = [[0.0, 0.0, 0.0], [0.7, 1.0, 0.0], [0.7, 0.0, 1.0]];
+/// Where information lost to blue-yellow deficiency is moved: into red and green
+const BLUE_YELLOW_SHIFT: [[f32; 3]; 3] = [[1.0, 0.0, 0.7], [0.0, 1.0, 0.7], [0.0, 0.0, 0.0]];
+
+const IDENTITY: [[f32; 3]; 3] = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+fn multiply(a: &[[f32; 3]; 3], b: &[[f32; 3]; 3]) -> [[f32; 3]; 3] {
+    let mut out = [[0.0; 3]; 3];
+    for (i, row) in out.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    out
+}
+
+/// Daltonization: the difference between a color and how it is seen with the
+/// deficiency is moved by `shift` into channels that are still seen, giving
+/// `I + shift * (I - simulation)`
+fn daltonize(simulation: &[[f32; 3]; 3], shift: &[[f32; 3]; 3]) -> [[f32; 3]; 3] {
+    let mut lost = IDENTITY;
+    for (i, row) in lost.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value -= simulation[i][j];
+        }
+    }
+    let mut out = multiply(shift, &lost);
+    for (i, row) in out.iter_mut().enumerate() {
+        row[i] += 1.0;
+    }
+    out
+}
+
+/// Luminance-preserving hue rotation, as used by CSS `hue-rotate`
+fn hue_rotation(degrees: f32) -> [[f32; 3]; 3] {
+    let (s, c) = degrees.to_radians().sin_cos();
+    [
+        [
+            0.213 + c * 0.787 - s * 0.213,
+            0.715 - c * 0.715 - s * 0.715,
+            0.072 - c * 0.072 + s * 0.928,
+        ],
+        [
+            0.213 - c * 0.213 + s * 0.143,
+            0.715 + c * 0.285 + s * 0.140,
+            0.072 - c * 0.072 - s * 0.283,
+        ],
+        [
+            0.213 - c * 0.213 - s * 0.787,
+            0.715 - c * 0.715 + s * 0.715,
+            0.072 + c * 0.928 + s * 0.072,
+        ],
+    ]
+}
+
+/// A 3x3 RGB matrix in fixed point, ready to run over a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColorMatrix([[i32; 3]; 3]);
+
+impl ColorMatrix {
+    pub fn from_f32(matrix: &[[f32; 3]; 3]) -> Self {
+        Self(matrix.map(|row| row.map(|value| (value * FIXED_ONE).round() as i32)))
+    }
+
+    /// The matrix for `filter` at `time` seconds, or None when it leaves colors alone
+    pub fn for_filter(settings: &PostSettings, time: f32) -> Option<Self> {
+        let matrix = match settings.filter {
+            ColorFilter::None => return None,
+            ColorFilter::HueShift => {
+                let lfo = if settings.hue_lfo {
+                    time * HUE_LFO_SPEED
+                } else {
+                    0.0
+                };
+                hue_rotation(settings.hue_shift + lfo)
+            }
+            ColorFilter::Protanopia => daltonize(&PROTANOPIA_SIM, &RED_GREEN_SHIFT),
+            ColorFilter::Deuteranopia => daltonize(&DEUTERANOPIA_SIM, &RED_GREEN_SHIFT),
+            ColorFilter::Tritanopia => daltonize(&TRITANOPIA_SIM, &BLUE_YELLOW_SHIFT),
+        };
+        Some(Self::from_f32(&matrix))
+    }
+
+    pub fn apply(&self, rgb: [u8; 3]) -> [u8; 3] {
+        let [r, g, b] = rgb.map(i32::from);
+        self.0.map(|row| {
+            let value = row[0] * r + row[1] * g + row[2] * b + (1 << (FIXED_SHIFT - 1));
+            (value >> FIXED_SHIFT).clamp(0, 255) as u8
+        })
+    }
+
+    /// Transforms every RGBA pixel of `frame` in place; alpha is left alone
+    pub fn apply_to_frame(&self, frame: &mut [u8]) {
+        for pixel in frame.chunks_exact_mut(4) {
+            let rgb = self.apply([pixel[0], pixel[1], pixel[2]]);
+            pixel[..3].copy_from_slice(&rgb);
+        }
+    }
+}
+
+/// Runs the post stages selected in the settings over a finished frame, then
+/// the day or night theme's tint
+pub fn apply_post(frame: &mut [u8], time: f32) {
+    if let Some(matrix) = ColorMatrix::for_filter(&post_settings(), time) {
+        matrix.apply_to_frame(frame);
+    }
+    if let Some(matrix) = theme::current_theme().matrix() {
+        matrix.apply_to_frame(frame);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filtered(filter: ColorFilter, rgb: [u8; 3]) -> [u8; 3] {
+        let settings = PostSettings {
+            filter,
+            ..PostSettings::DEFAULT
+        };
+        ColorMatrix::for_filter(&settings, 0.0).unwrap().apply(rgb)
+    }
+
+    fn assert_close(actual: [u8; 3], expected: [u8; 3]) {
+        for (a, e) in actual.iter().zip(expected) {
+            assert!(a.abs_diff(e) <= 1, "{:?} vs {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn test_daltonization_matches_reference_colors() {
+        // Reference values worked out in floating point from the published matrices
+        for (filter, cases) in [
+            (
+                ColorFilter::Protanopia,
+                [
+                    ([255, 0, 0], [255, 122, 152]),
+                    ([0, 255, 0], [0, 122, 0]),
+                    ([200, 120, 40], [200, 155, 80]),
+                ],
+            ),
+            (
+                ColorFilter::Deuteranopia,
+                [
+                    ([255, 0, 0], [255, 42, 116]),
+                    ([0, 255, 0], [0, 185, 0]),
+                    ([200, 120, 40], [200, 124, 61]),
+                ],
+            ),
+            (
+                ColorFilter::Tritanopia,
+                [
+                    ([255, 0, 0], [189, 19, 0]),
+                    ([0, 255, 0], [0, 149, 0]),
+                    ([200, 120, 40], [126, 99, 40]),
+                ],
+            ),
+        ] {
+            for (input, expected) in cases {
+                assert_close(filtered(filter, input), expected);
+            }
+            // Grays carry no hue, so nothing is lost or moved
+            assert_close(filtered(filter, [128, 128, 128]), [128, 128, 128]);
+        }
+    }
+
+    #[test]
+    fn test_hue_rotation() {
+        let at = |degrees: f32, rgb| {
+            let settings = PostSettings {
+                filter: ColorFilter::HueShift,
+                hue_shift: degrees,
+                hue_lfo: false,
+            };
+            ColorMatrix::for_filter(&settings, 0.0).unwrap().apply(rgb)
+        };
+        assert_eq!(at(0.0, [200, 120, 40]), [200, 120, 40]);
+        assert_close(at(360.0, [200, 120, 40]), [200, 120, 40]);
+        assert_close(at(180.0, [255, 0, 0]), [0, 109, 109]);
+        assert_close(at(90.0, [128, 128, 128]), [128, 128, 128]);
+
+        // The LFO turns the hue a little each frame, never jumping
+        let settings = PostSettings {
+            filter: ColorFilter::HueShift,
+            hue_shift: 0.0,
+            hue_lfo: true,
+        };
+        let mut previous = [255, 0, 0];
+        for frame in 1..600 {
+            let matrix = ColorMatrix::for_filter(&settings, frame as f32 / 60.0).unwrap();
+            let rgb = matrix.apply([255, 0, 0]);
+            for (a, b) in rgb.iter().zip(previous) {
+                assert!(a.abs_diff(b) <= 4);
+            }
+            previous = rgb;
+        }
+    }
+
+    #[test]
+    fn test_filter_names_round_trip_and_cycle() {
+        let mut filter = ColorFilter::None;
+        for _ in 0..ColorFilter::ALL.len() {
+            assert_eq!(ColorFilter::from_name(filter.name()), Some(filter));
+            filter = filter.next();
+        }
+        assert_eq!(filter, ColorFilter::None);
+    }
+}
@@ -0,0 +1,357 @@
+//! The Rain scene: columns of glyphs falling at their own speeds, a bright
+//! head trailed by fading green, the characters flickering as they fall.
+//! An audio onset sends a burst of extra-fast columns down.
+//!
+//! Glyphs are rasterized once at a fixed size and held, so a frame is only
+//! blending cached coverage. The whole frame is redrawn each time; the trails
+//! cover little of it, which keeps full resolution well inside a frame.
+
+use crate::core::accessibility;
+use crate::core::sim_rng::sim_rng;
+use crate::core::station_state;
+use crate::graphics::draw_ctx::DrawCtx;
+use crate::text::text_rendering::CachedGlyph;
+use rand::Rng;
+use std::sync::atomic::Ordering;
+
+/// Characters the rain is made of
+const ALPHABET: &str = "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ$+-*/=%<>|:";
+const GLYPH_SIZE: f32 = 18.0;
+/// Each column is this wide, and each glyph this tall, in pixels
+const CELL_WIDTH: u32 = 12;
+const CELL_HEIGHT: u32 = 18;
+/// Fall speed in cells per second
+const MIN_SPEED: f32 = 6.0;
+const MAX_SPEED: f32 = 20.0;
+/// Glyphs in a trail, the head included
+const MIN_LENGTH: u32 = 6;
+const MAX_LENGTH: u32 = 28;
+/// Longest wait before a column that left the bottom falls again, in seconds
+const MAX_RESPAWN_DELAY: f32 = 3.0;
+/// Longest wait before a new column first falls, so the rain starts at once
+const FIRST_FALL_DELAY: f32 = 0.5;
+/// Chance per second that a trail glyph changes character
+const MUTATION_RATE: f32 = 1.5;
+/// Columns an onset sends down, how much faster they fall, and how long
+/// before another onset can send more
+const BURST_COLUMNS: usize = 8;
+const BURST_SPEED: f32 = 3.0 * MAX_SPEED;
+const BURST_COOLDOWN: f32 = 0.3;
+/// Speeds are scaled by this while reduced motion is on, and there are no bursts
+const REDUCED_MOTION_SPEED: f32 = 0.4;
+
+const BACKGROUND: [u8; 4] = [0, 0, 0, 255];
+const HEAD_COLOR: [u8; 3] = [225, 255, 225];
+const TRAIL_COLOR: [u8; 3] = [40, 255, 90];
+
+pub fn set_rain_enabled(enabled: bool) {
+    station_state::current()
+        .rain_enabled
+        .store(enabled, Ordering::SeqCst);
+}
+
+pub fn is_rain_enabled() -> bool {
+    station_state::current().rain_enabled.load(Ordering::SeqCst)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Phase {
+    /// Off screen until the delay runs out
+    Waiting { delay: f32 },
+    /// `head` is the row of the lead glyph, fractional while between rows
+    Falling { head: f32, speed: f32, length: u32 },
+}
+
+#[derive(Debug, Clone)]
+struct Column {
+    phase: Phase,
+    /// Index into the alphabet for every row of the column
+    glyphs: Vec<u8>,
+}
+
+impl Column {
+    fn waiting(rows: usize, max_delay: f32, rng: &mut impl Rng) -> Self {
+        Self {
+            phase: Phase::Waiting {
+                delay: rng.gen_range(0.0..max_delay),
+            },
+            glyphs: (0..rows).map(|_| random_glyph(rng)).collect(),
+        }
+    }
+
+    fn start(&mut self, speed: f32, rng: &mut impl Rng) {
+        self.phase = Phase::Falling {
+            head: 0.0,
+            speed,
+            length: rng.gen_range(MIN_LENGTH..=MAX_LENGTH),
+        };
+        if let Some(first) = self.glyphs.first_mut() {
+            *first = random_glyph(rng);
+        }
+    }
+
+    /// Moves the column on by `dt` seconds: counts down a wait, or falls,
+    /// giving each row the head enters a fresh glyph and mutating the trail.
+    /// Once the tail has left the bottom the column waits to fall again.
+    fn update(&mut self, dt: f32, rng: &mut impl Rng) {
+        let rows = self.glyphs.len();
+        match &mut self.phase {
+            Phase::Waiting { delay } => {
+                *delay -= dt;
+                if *delay <= 0.0 {
+                    let speed = rng.gen_range(MIN_SPEED..MAX_SPEED);
+                    self.start(speed, rng);
+                }
+            }
+            Phase::Falling {
+                head,
+                speed,
+                length,
+            } => {
+                let (from, length) = (*head as usize, *length);
+                *head += *speed * dt;
+                let to = *head as usize;
+                if to >= rows + length as usize {
+                    self.phase = Phase::Waiting {
+                        delay: rng.gen_range(0.0..MAX_RESPAWN_DELAY),
+                    };
+                    return;
+                }
+                for row in (from + 1..=to).filter(|&row| row < rows) {
+                    self.glyphs[row] = random_glyph(rng);
+                }
+                let chance = (MUTATION_RATE * dt).min(1.0);
+                for row in to.saturating_sub(length as usize - 1)..=to.min(rows - 1) {
+                    if rng.gen::<f32>() < chance {
+                        self.glyphs[row] = random_glyph(rng);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn random_glyph(rng: &mut impl Rng) -> u8 {
+    rng.gen_range(0..ALPHABET.len()) as u8
+}
+
+/// Every column of a `width` x `height` frame
+struct Rain {
+    width: u32,
+    height: u32,
+    columns: Vec<Column>,
+    /// Seconds until an onset may start another burst
+    burst_cooldown: f32,
+}
+
+impl Rain {
+    fn new(width: u32, height: u32, rng: &mut impl Rng) -> Self {
+        let rows = height.div_ceil(CELL_HEIGHT).max(1) as usize;
+        let count = width.div_ceil(CELL_WIDTH).max(1);
+        Self {
+            width,
+            height,
+            columns: (0..count)
+                .map(|_| Column::waiting(rows, FIRST_FALL_DELAY, rng))
+                .collect(),
+            burst_cooldown: 0.0,
+        }
+    }
+
+    fn update(&mut self, dt: f32, onset: bool, speed_scale: f32, rng: &mut impl Rng) {
+        self.burst_cooldown = (self.burst_cooldown - dt).max(0.0);
+        if onset && self.burst_cooldown == 0.0 && speed_scale >= 1.0 {
+            self.burst(rng);
+        }
+        for column in &mut self.columns {
+            column.update(dt * speed_scale, rng);
+        }
+    }
+
+    /// Sends `BURST_COLUMNS` columns, waiting ones first, down at burst speed
+    fn burst(&mut self, rng: &mut impl Rng) {
+        let mut waiting: Vec<usize> = (0..self.columns.len())
+            .filter(|&i| matches!(self.columns[i].phase, Phase::Waiting { .. }))
+            .collect();
+        if waiting.len() < BURST_COLUMNS {
+            let falling: Vec<usize> = (0..self.columns.len())
+                .filter(|i| !waiting.contains(i))
+                .collect();
+            waiting.extend(falling);
+        }
+        for _ in 0..BURST_COLUMNS.min(waiting.len()) {
+            let i = waiting.swap_remove(rng.gen_range(0..waiting.len()));
+            self.columns[i].start(BURST_SPEED, rng);
+        }
+        self.burst_cooldown = BURST_COOLDOWN;
+    }
+
+    /// Draws every falling column over black: the head in white, the trail
+    /// fading out behind it
+    fn draw(&self, ctx: &mut DrawCtx, glyphs: &[Option<CachedGlyph>]) {
+        ctx.clear(BACKGROUND);
+        for (x, column) in self.columns.iter().enumerate() {
+            let Phase::Falling { head, length, .. } = column.phase else {
+                continue;
+            };
+            let head = head as usize;
+            for k in 0..length as usize {
+                let Some(row) = head.checked_sub(k).filter(|&row| row < column.glyphs.len()) else {
+                    continue;
+                };
+                let Some(glyph) = &glyphs[column.glyphs[row] as usize] else {
+                    continue;
+                };
+                let (color, alpha) = if k == 0 {
+                    (HEAD_COLOR, 1.0)
+                } else {
+                    (TRAIL_COLOR, 1.0 - k as f32 / length as f32)
+                };
+                let left = (x as u32 * CELL_WIDTH) as i32;
+                let baseline = ((row as u32 + 1) * CELL_HEIGHT) as i32 - 4;
+                glyph.draw(ctx, left, baseline, color, alpha);
+            }
+        }
+    }
+}
+
+pub struct RainState {
+    rain: Rain,
+    /// One per alphabet character, None where the font has no glyph for it
+    glyphs: Vec<Option<CachedGlyph>>,
+    last_time: Option<f32>,
+}
+
+impl RainState {
+    fn new(width: u32, height: u32) -> Self {
+        Self {
+            rain: Rain::new(width, height, &mut sim_rng()),
+            glyphs: ALPHABET
+                .chars()
+                .map(|c| CachedGlyph::new(c, GLYPH_SIZE))
+                .collect(),
+            last_time: None,
+        }
+    }
+
+    fn update_and_draw(&mut self, ctx: &mut DrawCtx) {
+        let (width, height, time) = (ctx.width(), ctx.height(), ctx.time);
+        if (self.rain.width, self.rain.height) != (width, height) {
+            self.rain = Rain::new(width, height, &mut sim_rng());
+        }
+        let dt = self
+            .last_time
+            .map_or(0.0, |last| (time - last).clamp(0.0, 0.1));
+        self.last_time = Some(time);
+        let onset = ctx.features.is_some_and(|features| features.onset);
+        let speed_scale = if accessibility::is_reduced_motion() {
+            REDUCED_MOTION_SPEED
+        } else {
+            1.0
+        };
+        self.rain.update(dt, onset, speed_scale, &mut sim_rng());
+        self.rain.draw(ctx, &self.glyphs);
+    }
+}
+
+/// Updates and draws the rain when the Rain scene is on
+pub fn update_and_draw_rain(ctx: &mut DrawCtx) {
+    if !is_rain_enabled() {
+        return;
+    }
+    let station = station_state::current();
+    station_state::lock(&station.rain)
+        .get_or_insert_with(|| RainState::new(ctx.width(), ctx.height()))
+        .update_and_draw(ctx);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphics::draw_ctx::Region;
+    use crate::text::text_rendering::glyphs_rasterized;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_columns_wait_fall_and_respawn() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut column = Column::waiting(10, MAX_RESPAWN_DELAY, &mut rng);
+        column.phase = Phase::Waiting { delay: 0.5 };
+        column.update(0.4, &mut rng);
+        assert!(matches!(column.phase, Phase::Waiting { .. }));
+        column.update(0.2, &mut rng);
+        let Phase::Falling { head, length, .. } = column.phase else {
+            panic!("should be falling: {:?}", column.phase);
+        };
+        assert_eq!(head, 0.0);
+        assert!((MIN_LENGTH..=MAX_LENGTH).contains(&length));
+
+        // At a known speed the head moves a row per tenth of a second
+        column.phase = Phase::Falling {
+            head: 0.0,
+            speed: 10.0,
+            length: 6,
+        };
+        column.update(0.25, &mut rng);
+        assert!(matches!(column.phase, Phase::Falling { head, .. } if (head - 2.5).abs() < 1e-4));
+        // Off the bottom only once the tail has gone: 10 rows plus 6 of trail
+        column.update(1.3, &mut rng);
+        assert!(matches!(column.phase, Phase::Falling { .. }));
+        column.update(0.1, &mut rng);
+        let Phase::Waiting { delay } = column.phase else {
+            panic!("should be waiting: {:?}", column.phase);
+        };
+        assert!((0.0..MAX_RESPAWN_DELAY).contains(&delay));
+        assert!(column.glyphs.iter().all(|&g| (g as usize) < ALPHABET.len()));
+
+        // A new rain has every column falling by the end of the first wait
+        let mut rain = Rain::new(160, 120, &mut rng);
+        rain.update(FIRST_FALL_DELAY, false, 1.0, &mut rng);
+        assert!(rain
+            .columns
+            .iter()
+            .all(|column| matches!(column.phase, Phase::Falling { .. })));
+    }
+
+    #[test]
+    fn test_onset_sends_a_burst_of_fast_columns() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let mut rain = Rain::new(240, 180, &mut rng);
+        for column in &mut rain.columns {
+            column.phase = Phase::Waiting { delay: 10.0 };
+        }
+        let fast = |rain: &Rain| {
+            rain.columns
+                .iter()
+                .filter(|c| matches!(c.phase, Phase::Falling { speed, .. } if speed == BURST_SPEED))
+                .count()
+        };
+        rain.update(0.016, true, 1.0, &mut rng);
+        assert_eq!(fast(&rain), BURST_COLUMNS);
+        // Onsets in quick succession don't pile up, and reduced motion has none
+        rain.update(0.016, true, 1.0, &mut rng);
+        assert_eq!(fast(&rain), BURST_COLUMNS);
+        let mut calm = Rain::new(240, 180, &mut rng);
+        calm.update(0.016, true, REDUCED_MOTION_SPEED, &mut rng);
+        assert_eq!(fast(&calm), 0);
+    }
+
+    #[test]
+    fn test_drawing_after_warmup_rasterizes_nothing() {
+        let (width, height) = (240u32, 180u32);
+        let mut frame = vec![0u8; (width * height * 4) as usize];
+        let region = Region::new(0, 0, width, height);
+        let mut state = RainState::new(width, height);
+        let warmed_up = glyphs_rasterized();
+        for i in 0..120 {
+            let mut ctx = DrawCtx::new(&mut frame, region, width, i as f32 / 30.0);
+            state.update_and_draw(&mut ctx);
+        }
+        assert_eq!(glyphs_rasterized(), warmed_up);
+        // Something green is falling
+        assert!(frame
+            .chunks(4)
+            .any(|pixel| pixel[1] > 100 && pixel[0] < pixel[1]));
+    }
+}
@@ -1,7 +1,11 @@
+use crate::graphics::draw_ctx::DrawCtx;
 use crate::{algorithms::sorter_manager, core::orchestrator, integration, physics};
 
-pub fn set_monitor_dimensions(monitor: &winit::monitor::MonitorHandle) {
-    integration::set_monitor_dimensions(monitor);
+pub fn set_monitor_dimensions(
+    monitor: Option<&winit::monitor::MonitorHandle>,
+    window: winit::dpi::PhysicalSize<u32>,
+) {
+    integration::set_monitor_dimensions(monitor, window);
 }
 
 pub fn draw_frame(
@@ -12,7 +16,13 @@ pub fn draw_frame(
     x_offset: usize,
     buffer_width: u32,
 ) {
-    orchestrator::draw_frame(frame, width, height, time, x_offset, buffer_width);
+    let mut ctx = DrawCtx::from_legacy(frame, width, height, time, x_offset, buffer_width);
+    render(&mut ctx);
+}
+
+/// Draws the ray scene into the context's region
+pub fn render(ctx: &mut DrawCtx) {
+    orchestrator::render_frame(ctx);
 }
 
 pub fn apply_force_yellow(force_x: f32, force_y: f32) {
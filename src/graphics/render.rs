@@ -1,3 +1,8 @@
+use crate::core::bufpool;
+use crate::core::types::Position;
+use crate::graphics::draw_ctx::{DrawCtx, Region};
+use glam::{IVec2, Vec2};
+
 pub trait Drawer {
     fn draw_line(
         &self,
@@ -190,6 +195,69 @@ pub fn draw_line(
     );
 }
 
+/// Draws a line with the given thickness by stamping discs along its path.
+/// Thickness of one pixel or less falls back to a plain Bresenham line.
+pub fn draw_thick_line(
+    ctx: &mut DrawCtx,
+    from: (i32, i32),
+    to: (i32, i32),
+    thickness: f32,
+    color: &[u8; 4],
+) {
+    let (frame, width, height, x_offset, buffer_width) = ctx.legacy();
+    let ((x0, y0), (x1, y1)) = (from, to);
+    let radius = (thickness / 2.0).round() as i32;
+    if radius <= 0 {
+        draw_line_internal(
+            frame,
+            width,
+            height,
+            x0,
+            y0,
+            x1,
+            y1,
+            color,
+            x_offset,
+            buffer_width,
+        );
+        return;
+    }
+
+    let mut x0 = x0;
+    let mut y0 = y0;
+    let dx = (x1 - x0).abs();
+    let dy = -(y1 - y0).abs();
+    let sx = if x0 < x1 { 1 } else { -1 };
+    let sy = if y0 < y1 { 1 } else { -1 };
+    let mut err = dx + dy;
+
+    loop {
+        draw_filled_circle_internal(
+            frame,
+            width,
+            height,
+            x0,
+            y0,
+            radius,
+            color,
+            x_offset,
+            buffer_width,
+        );
+        if x0 == x1 && y0 == y1 {
+            break;
+        }
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            x0 += sx;
+        }
+        if e2 <= dx {
+            err += dx;
+            y0 += sy;
+        }
+    }
+}
+
 fn draw_filled_circle_internal(
     frame: &mut [u8],
     width: u32,
@@ -243,6 +311,123 @@ pub fn draw_filled_circle(
     );
 }
 
+/// How much of a pixel at distance `distance` lies inside radius `radius`,
+/// ramping linearly across a 1px band centred on the edge
+fn edge_coverage(radius: f32, distance: f32) -> f32 {
+    (radius - distance + 0.5).clamp(0.0, 1.0)
+}
+
+/// Anti-aliased filled circle. The center and radius may be fractional, so
+/// small or moving circles don't snap to whole pixels.
+pub fn draw_circle_aa(ctx: &mut DrawCtx, cx: f32, cy: f32, radius: f32, color: &[u8; 4]) {
+    draw_ring(ctx, cx, cy, 0.0, radius, color);
+}
+
+/// Anti-aliased ring covering distances from `inner_radius` to `outer_radius`,
+/// smoothed on both edges. An inner radius of zero or less fills the circle.
+pub fn draw_ring(
+    ctx: &mut DrawCtx,
+    cx: f32,
+    cy: f32,
+    inner_radius: f32,
+    outer_radius: f32,
+    color: &[u8; 4],
+) {
+    if outer_radius <= 0.0 || outer_radius <= inner_radius {
+        return;
+    }
+    // Only visit pixels that are both near the circle and inside the region
+    let reach = outer_radius + 1.0;
+    let x_start = ((cx - reach).floor() as i32).max(0);
+    let x_end = ((cx + reach).ceil() as i32).min(ctx.width() as i32 - 1);
+    let y_start = ((cy - reach).floor() as i32).max(0);
+    let y_end = ((cy + reach).ceil() as i32).min(ctx.height() as i32 - 1);
+
+    for y in y_start..=y_end {
+        for x in x_start..=x_end {
+            let distance = (x as f32 - cx).hypot(y as f32 - cy);
+            let mut coverage = edge_coverage(outer_radius, distance);
+            if inner_radius > 0.0 {
+                coverage *= (distance - inner_radius + 0.5).clamp(0.0, 1.0);
+            }
+            if coverage > 0.0 {
+                let alpha = (color[3] as f32 * coverage).round() as u8;
+                ctx.blend_pixel(x, y, &[color[0], color[1], color[2], alpha]);
+            }
+        }
+    }
+}
+
+/// A filled circle for `draw_filled_circle_subpixel`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SubpixelCircle {
+    /// Fractional center in the context's region
+    pub center: Position,
+    pub radius: f32,
+    pub color: [u8; 4],
+}
+
+/// Filled circle at a fractional center. Edge pixels are covered in
+/// proportion to how much of them lies inside, so a circle moving less than
+/// a pixel per frame glides instead of ticking from pixel to pixel.
+pub fn draw_filled_circle_subpixel(ctx: &mut DrawCtx, circle: SubpixelCircle) {
+    let SubpixelCircle {
+        center,
+        radius,
+        color,
+    } = circle;
+    draw_circle_aa(ctx, center.x, center.y, radius, &color);
+}
+
+/// Anti-aliased line between fractional endpoints with round caps. Lines
+/// thinner than a pixel keep a one-pixel footprint and fade instead.
+pub fn draw_line_aa(
+    ctx: &mut DrawCtx,
+    from: (f32, f32),
+    to: (f32, f32),
+    thickness: f32,
+    color: &[u8; 4],
+) {
+    if thickness <= 0.0 {
+        return;
+    }
+    let radius = (thickness / 2.0).max(0.5);
+    let strength = thickness.min(1.0);
+    let reach = radius + 1.0;
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let length_sq = dx * dx + dy * dy;
+    let y_start = ((from.1.min(to.1) - reach).floor() as i32).max(0);
+    let y_end = ((from.1.max(to.1) + reach).ceil() as i32).min(ctx.height() as i32 - 1);
+
+    for y in y_start..=y_end {
+        // Only the part of the line within reach of this row can cover its pixels
+        let (x_low, x_high) = if dy.abs() < f32::EPSILON {
+            (from.0.min(to.0), from.0.max(to.0))
+        } else {
+            let t0 = ((y as f32 - reach - from.1) / dy).clamp(0.0, 1.0);
+            let t1 = ((y as f32 + reach - from.1) / dy).clamp(0.0, 1.0);
+            let (a, b) = (from.0 + dx * t0, from.0 + dx * t1);
+            (a.min(b), a.max(b))
+        };
+        let x_start = ((x_low - reach).floor() as i32).max(0);
+        let x_end = ((x_high + reach).ceil() as i32).min(ctx.width() as i32 - 1);
+        for x in x_start..=x_end {
+            let (px, py) = (x as f32 - from.0, y as f32 - from.1);
+            let t = if length_sq > 0.0 {
+                ((px * dx + py * dy) / length_sq).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            let distance = (px - dx * t).hypot(py - dy * t);
+            let coverage = edge_coverage(radius, distance) * strength;
+            if coverage > 0.0 {
+                let alpha = (color[3] as f32 * coverage).round() as u8;
+                ctx.blend_pixel(x, y, &[color[0], color[1], color[2], alpha]);
+            }
+        }
+    }
+}
+
 fn draw_shadow_glow_internal(
     frame: &mut [u8],
     width: u32,
@@ -344,100 +529,126 @@ fn put_pixel(
     }
 }
 
+/// Returns the endpoint of ray `i` of `count`, spread uniformly along the viewport border.
+/// The whole set slowly slides along the perimeter over time.
+fn ray_target(i: usize, count: usize, width: u32, height: u32, time: f32) -> Position {
+    let w = (width.max(1) - 1) as f32;
+    let h = (height.max(1) - 1) as f32;
+    let perimeter = 2.0 * (w + h);
+    let jitter = (time * 0.2).sin() * 0.05 / (2.0 * std::f32::consts::PI);
+    let t = (i as f32 / count as f32 + jitter).rem_euclid(1.0);
+    let mut d = t * perimeter;
+
+    // Walk the border clockwise from the top-left corner
+    if d <= w {
+        return Position::new(d, 0.0);
+    }
+    d -= w;
+    if d <= h {
+        return Position::new(w, d);
+    }
+    d -= h;
+    if d <= w {
+        return Position::new(w - d, h);
+    }
+    d -= w;
+    Position::new(0.0, (h - d).max(0.0))
+}
+
+/// Distance from `start` along the unit direction `dir` to the edge of the frame
+fn distance_to_frame_edge(start: Position, dir: Vec2, width: u32, height: u32) -> f32 {
+    let w = (width.max(1) - 1) as f32;
+    let h = (height.max(1) - 1) as f32;
+    let along = |p: f32, d: f32, max: f32| {
+        if d > 0.0 {
+            (max - p) / d
+        } else if d < 0.0 {
+            -p / d
+        } else {
+            f32::INFINITY
+        }
+    };
+    along(start.x, dir.x, w)
+        .min(along(start.y, dir.y, h))
+        .max(0.0)
+}
+
+/// Adapter for callers still passing the raw frame parameters; see `draw_rays`
 pub fn draw_rays_from_ball(
     frame: &mut [u8],
     width: u32,
     height: u32,
-    pos: (f32, f32),
+    pos: Position,
     ray_color: [u8; 4],
     time: f32,
     x_offset: usize,
     buffer_width: u32,
-    other_pos: (f32, f32),
+    other_pos: Position,
 ) {
-    let source_x = pos.0 as i32;
-    let source_y = pos.1 as i32;
-    let center_x = width as i32 / 2;
-    let center_y = height as i32 / 2;
-    let radius = width as i32 / 2 - 20;
-    let count = 60;
+    let mut ctx = DrawCtx::from_legacy(frame, width, height, time, x_offset, buffer_width);
+    draw_rays(&mut ctx, pos, ray_color, other_pos);
+}
 
-    let other_x = other_pos.0 as i32;
-    let other_y = other_pos.1 as i32;
-    let other_radius = 10;
+/// Radius of a ball when it blocks the other ball's rays
+const OCCLUDER_RADIUS: f32 = 10.0;
 
-    let mut shadow_rays: Vec<((i32, i32), (i32, i32))> = Vec::new();
+/// Casts rays from the ball at `pos` to the region border. Rays blocked by the
+/// ball at `other_pos` stop there and leave a dim shadow behind it.
+pub fn draw_rays(ctx: &mut DrawCtx, pos: Position, ray_color: [u8; 4], other_pos: Position) {
+    cast_rays(ctx, pos, ray_color, other_pos, OCCLUDER_RADIUS);
+}
+
+fn cast_rays(
+    ctx: &mut DrawCtx,
+    pos: Position,
+    ray_color: [u8; 4],
+    other_pos: Position,
+    other_radius: f32,
+) {
+    let (width, height, time) = (ctx.width(), ctx.height(), ctx.time);
+    // Rays leave from the pixel the ball is in, and the other ball blocks
+    // them from the pixel it is in
+    let source = pos.as_ivec2();
+    let other = other_pos.as_ivec2();
+    let count = ctx.quality.ray_count;
+
+    let mut shadow_rays: Vec<(IVec2, IVec2)> = Vec::new();
 
     for i in 0..count {
-        let base_angle = (i as f32 / count as f32) * 2.0 * std::f32::consts::PI;
-        let angle = base_angle + (time * 0.2).sin() * 0.05;
-        let end_x = center_x as f32 + angle.cos() * radius as f32;
-        let end_y = center_y as f32 + angle.sin() * radius as f32;
-
-        let ray_dir_x = end_x as f32 - source_x as f32;
-        let ray_dir_y = end_y as f32 - source_y as f32;
-        let ray_length = (ray_dir_x * ray_dir_x + ray_dir_y * ray_dir_y).sqrt();
-        let ray_dir_x = ray_dir_x / ray_length;
-        let ray_dir_y = ray_dir_y / ray_length;
-
-        let oc_x = source_x as f32 - other_x as f32;
-        let oc_y = source_y as f32 - other_y as f32;
-        let a = 1.0;
-        let b = 2.0 * (ray_dir_x * oc_x + ray_dir_y * oc_y);
-        let c = (oc_x * oc_x + oc_y * oc_y) - (other_radius * other_radius) as f32;
-        let discriminant = b * b - 4.0 * a * c;
-
-        if discriminant >= 0.0 {
-            let t1 = (-b - discriminant.sqrt()) / (2.0 * a);
-            let t2 = (-b + discriminant.sqrt()) / (2.0 * a);
-            if (t1 > 0.0 && t1 < ray_length) || (t2 > 0.0 && t2 < ray_length) {
-                let t = t1.max(0.0);
-                let intersect_x = (source_x as f32 + ray_dir_x * t) as i32;
-                let intersect_y = (source_y as f32 + ray_dir_y * t) as i32;
-                draw_line_internal(
-                    frame,
-                    width,
-                    height,
-                    source_x,
-                    source_y,
-                    intersect_x,
-                    intersect_y,
-                    &ray_color,
-                    x_offset,
-                    buffer_width,
-                );
+        let end = ray_target(i, count, width, height, time);
+        let to_end = end - source.as_vec2();
+        let ray_length = to_end.length();
+        if ray_length < f32::EPSILON {
+            continue;
+        }
+        let dir = to_end / ray_length;
 
-                let shadow_length = radius as f32 * 1.2;
-                let shadow_end_x = (intersect_x as f32 + ray_dir_x * shadow_length) as i32;
-                let shadow_end_y = (intersect_y as f32 + ray_dir_y * shadow_length) as i32;
-                shadow_rays.push(((intersect_x, intersect_y), (shadow_end_x, shadow_end_y)));
-            } else {
-                draw_line_internal(
-                    frame,
-                    width,
-                    height,
-                    source_x,
-                    source_y,
-                    end_x as i32,
-                    end_y as i32,
-                    &ray_color,
-                    x_offset,
-                    buffer_width,
-                );
+        // Where the ray enters the other ball: t^2 + b t + c = 0 along `dir`
+        let from_other = source.as_vec2() - other.as_vec2();
+        let b = 2.0 * dir.dot(from_other);
+        let c = from_other.length_squared() - other_radius * other_radius;
+        let discriminant = b * b - 4.0 * c;
+        let blocked_at = (discriminant >= 0.0)
+            .then(|| {
+                let root = discriminant.sqrt();
+                ((-b - root) / 2.0, (-b + root) / 2.0)
+            })
+            .filter(|&(t1, t2)| (t1 > 0.0 && t1 < ray_length) || (t2 > 0.0 && t2 < ray_length))
+            .map(|(t1, _)| t1.max(0.0));
+
+        match blocked_at {
+            Some(t) => {
+                let hit = (source.as_vec2() + dir * t).as_ivec2();
+                ctx.draw_line(source.x, source.y, hit.x, hit.y, &ray_color);
+
+                let shadow_length = distance_to_frame_edge(hit.as_vec2(), dir, width, height);
+                let shadow_end = (hit.as_vec2() + dir * shadow_length).as_ivec2();
+                shadow_rays.push((hit, shadow_end));
+            }
+            None => {
+                let end = end.as_ivec2();
+                ctx.draw_line(source.x, source.y, end.x, end.y, &ray_color);
             }
-        } else {
-            draw_line_internal(
-                frame,
-                width,
-                height,
-                source_x,
-                source_y,
-                end_x as i32,
-                end_y as i32,
-                &ray_color,
-                x_offset,
-                buffer_width,
-            );
         }
     }
 
@@ -448,27 +659,453 @@ pub fn draw_rays_from_ball(
         128,
     ];
 
-    for shadow in shadow_rays {
-        draw_line_internal(
-            frame,
-            width,
-            height,
-            shadow.0 .0,
-            shadow.0 .1,
-            shadow.1 .0,
-            shadow.1 .1,
-            &shadow_color,
-            x_offset,
-            buffer_width,
+    for (start, end) in shadow_rays {
+        ctx.draw_line(start.x, start.y, end.x, end.y, &shadow_color);
+    }
+}
+
+/// One ball's rays for `draw_mixed_rays`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RayLight {
+    pub pos: Position,
+    pub color: [u8; 4],
+    /// Time the ray sweep is taken at
+    pub time: f32,
+}
+
+/// Draws the rays of both balls as colored light. Each ball's rays go into
+/// their own half-resolution buffer, the two are added, and the sum is
+/// upscaled and added onto the frame, so where both shine the colors mix
+/// toward white and in a ball's shadow only the other's color remains.
+pub fn draw_mixed_rays(ctx: &mut DrawCtx, lights: [RayLight; 2]) {
+    let width = ctx.width().div_ceil(2);
+    let height = ctx.height().div_ceil(2);
+    let size = (width * height * 4) as usize;
+    let mut buffers = [
+        bufpool::get_buffer("ray light", size),
+        bufpool::get_buffer("ray light", size),
+    ];
+    for (i, (light, buffer)) in lights.iter().zip(&mut buffers).enumerate() {
+        let other = lights[1 - i].pos;
+        let mut half = DrawCtx::new(buffer, Region::new(0, 0, width, height), width, light.time);
+        half.quality = ctx.quality;
+        cast_rays(
+            &mut half,
+            light.pos / 2.0,
+            light.color,
+            other / 2.0,
+            OCCLUDER_RADIUS / 2.0,
         );
     }
+    let [mut sum, other] = buffers;
+    add_light(&mut sum, &other);
+    add_upscaled_light(ctx, &sum, width, height);
+}
+
+/// Adds the RGB of `light` into `dst` pixel by pixel, saturating at white
+pub fn add_light(dst: &mut [u8], light: &[u8]) {
+    for (d, l) in dst.chunks_exact_mut(4).zip(light.chunks_exact(4)) {
+        for channel in 0..3 {
+            d[channel] = d[channel].saturating_add(l[channel]);
+        }
+    }
 }
 
+/// Full-resolution pixels that half-resolution texel `i` lights when
+/// upscaled bilinearly, with weights in quarters. Pixel centers line up, so
+/// texel i sits between pixels 2i and 2i+1 and reaches one pixel further each
+/// way; past the edge of a buffer of `size` texels the weight is folded back
+/// onto the edge pixel, as if the texels were clamped.
+fn upscale_taps(i: usize, size: usize) -> [(i32, u32); 4] {
+    let last = 2 * size as i32 - 1;
+    let first = 2 * i as i32 - 1;
+    [(0, 1), (1, 3), (2, 3), (3, 1)]
+        .map(|(offset, weight)| ((first + offset).clamp(0, last), weight))
+}
+
+/// Bilinearly upscales a half-resolution light buffer to the region and adds
+/// it onto the frame. Only lit texels are visited, since most of a ray buffer
+/// is dark.
+pub fn add_upscaled_light(ctx: &mut DrawCtx, light: &[u8], light_width: u32, light_height: u32) {
+    let (light_width, light_height) = (light_width as usize, light_height as usize);
+    for (i, texel) in light.chunks_exact(4).enumerate() {
+        if texel[..3] == [0, 0, 0] {
+            continue;
+        }
+        let (tx, ty) = (i % light_width, i / light_width);
+        for (y, wy) in upscale_taps(ty, light_height) {
+            for (x, wx) in upscale_taps(tx, light_width) {
+                let weight = (wx * wy) as u16;
+                let rgb = [0, 1, 2].map(|c| ((texel[c] as u16 * weight + 8) / 16) as u8);
+                ctx.add_pixel(x, y, rgb);
+            }
+        }
+    }
+}
+
+/// Color every frame starts from
+pub const BACKGROUND_COLOR: [u8; 4] = [5, 5, 10, 255];
+
 pub fn clear_frame(frame: &mut [u8]) {
     for pixel in frame.chunks_exact_mut(4) {
-        pixel[0] = 5;
-        pixel[1] = 5;
-        pixel[2] = 10;
-        pixel[3] = 255;
+        pixel.copy_from_slice(&BACKGROUND_COLOR);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphics::draw_ctx::Region;
+
+    fn on_border(p: Position, width: u32, height: u32) -> bool {
+        let w = (width - 1) as f32;
+        let h = (height - 1) as f32;
+        let inside = p.x >= -1.0 && p.x <= w + 1.0 && p.y >= -1.0 && p.y <= h + 1.0;
+        let near_edge = p.x.abs() <= 1.0
+            || (p.x - w).abs() <= 1.0
+            || p.y.abs() <= 1.0
+            || (p.y - h).abs() <= 1.0;
+        inside && near_edge
+    }
+
+    #[test]
+    fn test_ray_targets_lie_on_viewport_border() {
+        // 16:9, 21:9, portrait, and square
+        for &(width, height) in &[(1920, 1080), (2560, 1080), (1080, 1920), (800, 800)] {
+            for &time in &[0.0, 3.7, 12.5] {
+                for i in 0..60 {
+                    let p = ray_target(i, 60, width, height, time);
+                    assert!(
+                        on_border(p, width, height),
+                        "{:?} off border of {}x{}",
+                        p,
+                        width,
+                        height
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_ray_targets_reach_all_edges() {
+        for &(width, height) in &[(2560, 1080), (1080, 1920)] {
+            let targets: Vec<Position> = (0..60)
+                .map(|i| ray_target(i, 60, width, height, 0.0))
+                .collect();
+            let w = (width - 1) as f32;
+            let h = (height - 1) as f32;
+            assert!(targets.iter().any(|p| p.y <= 1.0));
+            assert!(targets.iter().any(|p| p.y >= h - 1.0));
+            assert!(targets.iter().any(|p| p.x <= 1.0));
+            assert!(targets.iter().any(|p| p.x >= w - 1.0));
+        }
+    }
+
+    #[test]
+    fn test_shadow_clipped_to_frame() {
+        let dir = Vec2::new(0.6, 0.8);
+        let start = Position::new(100.0, 100.0);
+        let len = distance_to_frame_edge(start, dir, 400, 300);
+        let end = start + dir * len;
+        assert!(on_border(end, 400, 300));
+    }
+
+    #[test]
+    fn test_ray_adapter_matches_quadrant_ctx() {
+        // Legacy call into the right half of a two-scene buffer...
+        let (width, height, buffer_width) = (200u32, 150u32, 400u32);
+        let mut legacy = vec![0u8; (buffer_width * height * 4) as usize];
+        let (pos, other) = (Position::new(60.0, 70.0), Position::new(90.0, 75.0));
+        let color = [255, 255, 150, 255];
+        draw_rays_from_ball(
+            &mut legacy,
+            width,
+            height,
+            pos,
+            color,
+            1.5,
+            200,
+            buffer_width,
+            other,
+        );
+
+        // ...matches the same rays drawn through a sub-region
+        let mut ported = vec![0u8; legacy.len()];
+        let whole = Region::new(0, 0, buffer_width, height);
+        let mut ctx = DrawCtx::new(&mut ported, whole, buffer_width, 1.5);
+        let mut right = ctx.sub_region(Region::new(200, 0, width, height));
+        draw_rays(&mut right, pos, color, other);
+
+        assert!(legacy.iter().any(|&b| b != 0));
+        assert!(legacy == ported);
+    }
+
+    fn aa_frame(size: u32, draw: impl FnOnce(&mut DrawCtx)) -> Vec<u8> {
+        let mut frame = vec![0u8; (size * size * 4) as usize];
+        let mut ctx = DrawCtx::new(&mut frame, Region::new(0, 0, size, size), size, 0.0);
+        draw(&mut ctx);
+        frame
+    }
+
+    #[test]
+    fn test_aa_circle_coverage_is_symmetric_across_quadrants() {
+        let size = 32u32;
+        let white = [255, 255, 255, 255];
+        for radius in [1.5, 3.3, 7.8] {
+            let frame = aa_frame(size, |ctx| draw_circle_aa(ctx, 16.0, 16.0, radius, &white));
+            let at = |x: i32, y: i32| frame[4 * (y as usize * size as usize + x as usize)];
+            let mut partial = 0;
+            for dy in 0..=9 {
+                for dx in 0..=9 {
+                    let value = at(16 + dx, 16 + dy);
+                    assert_eq!(value, at(16 - dx, 16 + dy));
+                    assert_eq!(value, at(16 + dx, 16 - dy));
+                    assert_eq!(value, at(16 - dx, 16 - dy));
+                    assert_eq!(value, at(16 + dy, 16 + dx));
+                    if value > 0 && value < 255 {
+                        partial += 1;
+                    }
+                }
+            }
+            assert_eq!(at(16, 16), 255);
+            assert!(partial > 0, "radius {} has no soft edge", radius);
+        }
+    }
+
+    /// Total coverage of a white shape drawn on black, in pixels
+    fn covered_area(frame: &[u8]) -> f32 {
+        frame.chunks_exact(4).map(|px| px[0] as f32 / 255.0).sum()
+    }
+
+    #[test]
+    fn test_subpixel_coverage_matches_the_analytic_area() {
+        let size = 64u32;
+        let white = [255, 255, 255, 255];
+        for radius in [2.5, 6.3, 12.0] {
+            for (cx, cy) in [(32.0, 32.0), (31.3, 30.75), (32.5, 29.9)] {
+                let circle = SubpixelCircle {
+                    center: Position::new(cx, cy),
+                    radius,
+                    color: white,
+                };
+                let frame = aa_frame(size, |ctx| draw_filled_circle_subpixel(ctx, circle));
+                let expected = std::f32::consts::PI * radius * radius;
+                let area = covered_area(&frame);
+                assert!(
+                    (area - expected).abs() / expected < 0.03,
+                    "radius {} at ({}, {}): {} vs {}",
+                    radius,
+                    cx,
+                    cy,
+                    area,
+                    expected
+                );
+            }
+        }
+
+        // A capsule: the body plus two half-disc caps
+        for (from, to, thickness) in [
+            ((10.2, 12.7), (50.6, 40.1), 4.0),
+            ((8.0, 30.5), (56.0, 30.5), 3.0),
+            ((20.0, 10.0), (20.0, 50.0), 6.5),
+        ] {
+            let frame = aa_frame(size, |ctx| draw_line_aa(ctx, from, to, thickness, &white));
+            let length = (to.0 - from.0).hypot(to.1 - from.1);
+            let radius = thickness / 2.0;
+            let expected = length * thickness + std::f32::consts::PI * radius * radius;
+            let area = covered_area(&frame);
+            assert!(
+                (area - expected).abs() / expected < 0.03,
+                "{:?}-{:?}: {} vs {}",
+                from,
+                to,
+                area,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_slow_circle_glides_instead_of_ticking() {
+        let size = 48u32;
+        let white = [255, 255, 255, 255];
+        // Intensity-weighted mean x of the frame
+        let centroid = |frame: &[u8]| {
+            let (mut weight, mut sum) = (0.0, 0.0);
+            for (i, px) in frame.chunks_exact(4).enumerate() {
+                let value = px[0] as f32;
+                weight += value;
+                sum += value * (i % size as usize) as f32;
+            }
+            sum / weight
+        };
+        // 5 px/s at 60 fps is a twelfth of a pixel per frame
+        let step = 5.0 / 60.0;
+        let mut last = None;
+        let mut snapped_moves = 0;
+        for i in 0..30 {
+            let cx = 20.0 + step * i as f32;
+            let circle = SubpixelCircle {
+                center: Position::new(cx, 24.0),
+                radius: 6.0,
+                color: white,
+            };
+            let smooth = aa_frame(size, |ctx| draw_filled_circle_subpixel(ctx, circle));
+            let mut snapped = vec![0u8; (size * size * 4) as usize];
+            draw_filled_circle(&mut snapped, size, size, cx as i32, 24, 6, &white, 0, size);
+            let now = (centroid(&smooth), centroid(&snapped));
+            if let Some((smooth_before, snapped_before)) = last {
+                let moved: f32 = now.0 - smooth_before;
+                assert!((moved - step).abs() < 0.03, "frame {} moved {}", i, moved);
+                if now.1 != snapped_before {
+                    snapped_moves += 1;
+                }
+            }
+            last = Some(now);
+        }
+        // Truncated positions only move on the frames that cross a pixel
+        assert!(snapped_moves <= 3);
+    }
+
+    #[test]
+    fn test_thin_rings_have_no_holes() {
+        let size = 64u32;
+        let white = [255, 255, 255, 255];
+        for inner in [2.0, 9.5, 20.25] {
+            let frame = aa_frame(size, |ctx| {
+                draw_ring(ctx, 32.0, 32.0, inner, inner + 1.0, &white)
+            });
+            for step in 0..360 {
+                let angle = (step as f32).to_radians();
+                let x = (32.0 + (inner + 0.5) * angle.cos()).round() as usize;
+                let y = (32.0 + (inner + 0.5) * angle.sin()).round() as usize;
+                assert!(frame[4 * (y * size as usize + x)] > 0);
+            }
+            // The hole stays empty
+            assert_eq!(frame[4 * (32 * size as usize + 32)], 0);
+        }
+    }
+
+    #[test]
+    fn test_aa_shapes_clip_to_region() {
+        let size = 16u32;
+        let mut frame = vec![0u8; (size * size * 4) as usize];
+        let mut ctx = DrawCtx::new(&mut frame, Region::new(0, 0, size, size), size, 0.0);
+        let mut corner = ctx.sub_region(Region::new(8, 8, 8, 8));
+        let white = [255, 255, 255, 255];
+        draw_circle_aa(&mut corner, 0.0, 0.0, 5.0, &white);
+        draw_ring(&mut corner, 8.0, 8.0, 2.0, 6.0, &white);
+        draw_circle_aa(&mut corner, -100.0, 300.0, 4.0, &white);
+
+        for y in 0..size as usize {
+            for x in 0..size as usize {
+                if x < 8 || y < 8 {
+                    assert_eq!(frame[4 * (y * size as usize + x)], 0, "({}, {})", x, y);
+                }
+            }
+        }
+        assert_eq!(frame[4 * (8 * size as usize + 8)], 255);
+    }
+
+    #[test]
+    fn test_light_adds_and_saturates() {
+        let mut yellow = vec![255, 255, 150, 255, 40, 30, 0, 255];
+        let green = [150, 255, 150, 255, 0, 0, 0, 0];
+        add_light(&mut yellow, &green);
+        // Both sources mix to white; where only one shines it keeps its color
+        assert_eq!(&yellow[..3], &[255, 255, 255]);
+        assert_eq!(&yellow[4..7], &[40, 30, 0]);
+    }
+
+    #[test]
+    fn test_upscaled_light_lines_up_with_the_half_res_buffer() {
+        // A single lit texel at (1, 1) of a 4x4 buffer covers full-res pixels 2 and 3
+        let mut light = vec![0u8; 4 * 4 * 4];
+        light[4 * (4 + 1)..][..3].copy_from_slice(&[200, 100, 0]);
+        let mut frame = vec![0u8; 8 * 8 * 4];
+        let mut ctx = DrawCtx::new(&mut frame, Region::new(0, 0, 8, 8), 8, 0.0);
+        add_upscaled_light(&mut ctx, &light, 4, 4);
+        let red = |x: usize, y: usize| frame[4 * (y * 8 + x)];
+
+        // Symmetric about the texel center at 2.5 on both axes
+        for y in 0..8 {
+            for x in 0..6 {
+                assert_eq!(red(x, y), red(5 - x, y), "({}, {})", x, y);
+                assert_eq!(red(x, y), red(y, x));
+            }
+        }
+        assert_eq!(red(2, 2), 113);
+        assert_eq!(red(1, 2), 38);
+        assert_eq!(red(0, 2), 0);
+        assert_eq!(red(6, 6), 0);
+        assert_eq!(frame[4 * (2 * 8 + 2) + 1], 56);
+
+        // A flat buffer stays flat, right up to the edges
+        let flat = vec![80u8; 4 * 4 * 4];
+        let mut frame = vec![0u8; 8 * 8 * 4];
+        let mut ctx = DrawCtx::new(&mut frame, Region::new(0, 0, 8, 8), 8, 0.0);
+        add_upscaled_light(&mut ctx, &flat, 4, 4);
+        assert!(frame.chunks_exact(4).all(|p| p[..3] == [80, 80, 80]));
+    }
+
+    /// FNV-1a over a frame, to pin exact output without storing it
+    fn frame_hash(frame: &[u8]) -> u64 {
+        frame.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        })
+    }
+
+    #[test]
+    fn test_rays_match_the_recorded_frames() {
+        // Hashes recorded before positions moved from tuples to Vec2
+        let cases = [
+            (
+                (60.0, 70.0),
+                (90.0, 75.0),
+                1.5,
+                0xcacb02eab9fdd6f8,
+                0x323b25490f645c79,
+            ),
+            (
+                (150.3, 20.7),
+                (30.2, 120.9),
+                7.25,
+                0x2694bcd603074810,
+                0xa5ec785858e597b1,
+            ),
+            (
+                (10.5, 140.5),
+                (12.0, 130.0),
+                0.0,
+                0xaa75390047279bf7,
+                0xfd0f47dea1b2acf9,
+            ),
+        ];
+        let whole = Region::new(0, 0, 200, 150);
+        for (pos, other, time, single, mixed) in cases {
+            let (pos, other): (Position, Position) = (pos.into(), other.into());
+            let mut frame = vec![0u8; 200 * 150 * 4];
+            let mut ctx = DrawCtx::new(&mut frame, whole, 200, time);
+            draw_rays(&mut ctx, pos, [255, 255, 150, 255], other);
+            assert_eq!(frame_hash(&frame), single, "single rays from {:?}", pos);
+
+            let mut frame = vec![0u8; 200 * 150 * 4];
+            let mut ctx = DrawCtx::new(&mut frame, whole, 200, time);
+            let lights = [
+                RayLight {
+                    pos,
+                    color: [255, 255, 150, 255],
+                    time,
+                },
+                RayLight {
+                    pos: other,
+                    color: [150, 255, 150, 255],
+                    time: time + 0.5,
+                },
+            ];
+            draw_mixed_rays(&mut ctx, lights);
+            assert_eq!(frame_hash(&frame), mixed, "mixed rays from {:?}", pos);
+        }
     }
 }
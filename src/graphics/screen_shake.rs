@@ -0,0 +1,207 @@
+use crate::core::accessibility;
+use crate::core::events::Event;
+use crate::core::station_state;
+use crate::graphics::noise::smooth_noise;
+
+/// Largest translation applied to the frame, in pixels
+pub const MAX_SHAKE_OFFSET: f32 = 6.0;
+/// Trauma lost per second
+const TRAUMA_DECAY: f32 = 1.5;
+/// How fast the shake noise changes, in cycles per second
+const SHAKE_FREQUENCY: f32 = 18.0;
+/// Colour used for the strip uncovered when the frame is translated
+const BACKGROUND: [u8; 4] = [5, 5, 10, 255];
+
+/// Trauma-based camera shake. Trauma is added by impacts and decays over time;
+/// the offset grows with trauma squared so small hits stay subtle.
+#[derive(Debug, Clone)]
+pub struct ScreenShake {
+    trauma: f32,
+    time: f32,
+}
+
+impl ScreenShake {
+    pub const fn new() -> Self {
+        Self {
+            trauma: 0.0,
+            time: 0.0,
+        }
+    }
+
+    /// Adds trauma, clamped to 0..=1
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).clamp(0.0, 1.0);
+    }
+
+    pub fn trauma(&self) -> f32 {
+        self.trauma
+    }
+
+    /// Decays trauma and advances the noise clock
+    pub fn update(&mut self, dt: f32) {
+        self.trauma = (self.trauma - TRAUMA_DECAY * dt).max(0.0);
+        self.time += dt;
+    }
+
+    /// Current frame translation in pixels
+    pub fn offset(&self) -> (i32, i32) {
+        let magnitude = MAX_SHAKE_OFFSET * self.trauma * self.trauma;
+        let t = self.time * SHAKE_FREQUENCY;
+        let dx = (smooth_noise(t, 0.0) * magnitude).clamp(-MAX_SHAKE_OFFSET, MAX_SHAKE_OFFSET);
+        let dy = (smooth_noise(t, 17.3) * magnitude).clamp(-MAX_SHAKE_OFFSET, MAX_SHAKE_OFFSET);
+        (dx.round() as i32, dy.round() as i32)
+    }
+}
+
+impl Default for ScreenShake {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A station's shake and the frame time it was last advanced to
+pub struct ShakeState {
+    shake: ScreenShake,
+    last_time: Option<f32>,
+}
+
+impl ShakeState {
+    pub const fn new() -> Self {
+        Self {
+            shake: ScreenShake::new(),
+            last_time: None,
+        }
+    }
+}
+
+impl Default for ShakeState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Runs `f` on the shake of the station being driven
+fn with_shake_state<R>(f: impl FnOnce(&mut ShakeState) -> R) -> R {
+    let state = station_state::current();
+    let mut shake = station_state::lock(&state.shake);
+    f(&mut shake)
+}
+
+/// Adds trauma to the screen shake. Ignored when reduced motion is on.
+pub fn add_trauma(amount: f32) {
+    if accessibility::is_reduced_motion() {
+        return;
+    }
+    with_shake_state(|state| state.shake.add_trauma(amount));
+}
+
+/// Shakes the screen when a ball hits a corner
+pub fn on_event(event: &Event, _time: f32) {
+    if let Event::CornerHit { .. } = event {
+        add_trauma(0.4);
+    }
+}
+
+/// Advances the shake and translates the finished frame by its offset
+pub fn apply_screen_shake(frame: &mut [u8], width: u32, height: u32, time: f32) {
+    let (dx, dy) = with_shake_state(|state| {
+        let dt = state
+            .last_time
+            .map(|last| (time - last).clamp(0.0, 0.1))
+            .unwrap_or(0.0);
+        state.last_time = Some(time);
+        state.shake.update(dt);
+        state.shake.offset()
+    });
+
+    if accessibility::is_reduced_motion() {
+        return;
+    }
+    translate_frame(frame, width, height, dx, dy);
+}
+
+/// Shifts the frame contents by (dx, dy), filling the uncovered strip with the background colour
+pub fn translate_frame(frame: &mut [u8], width: u32, height: u32, dx: i32, dy: i32) {
+    if dx == 0 && dy == 0 {
+        return;
+    }
+    let width = width as i32;
+    let height = height as i32;
+    let row_bytes = width as usize * 4;
+    if frame.len() < row_bytes * height as usize {
+        return;
+    }
+
+    // Walk rows and columns away from the direction of travel so sources are read before overwritten
+    let row = |i: i32| if dy > 0 { height - 1 - i } else { i };
+    let col = |i: i32| if dx > 0 { width - 1 - i } else { i };
+
+    for y in (0..height).map(row) {
+        for x in (0..width).map(col) {
+            let src_x = x - dx;
+            let src_y = y - dy;
+            let dst = (y as usize * width as usize + x as usize) * 4;
+            if src_x >= 0 && src_x < width && src_y >= 0 && src_y < height {
+                let src = (src_y as usize * width as usize + src_x as usize) * 4;
+                frame.copy_within(src..src + 4, dst);
+            } else {
+                frame[dst..dst + 4].copy_from_slice(&BACKGROUND);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trauma_accumulates_and_clamps() {
+        let mut shake = ScreenShake::new();
+        shake.add_trauma(0.3);
+        shake.add_trauma(0.3);
+        assert!((shake.trauma() - 0.6).abs() < 1e-6);
+        shake.add_trauma(2.0);
+        assert_eq!(shake.trauma(), 1.0);
+    }
+
+    #[test]
+    fn test_trauma_decays_to_zero() {
+        let mut shake = ScreenShake::new();
+        shake.add_trauma(1.0);
+        shake.update(0.2);
+        assert!(shake.trauma() < 1.0 && shake.trauma() > 0.0);
+        for _ in 0..60 {
+            shake.update(1.0 / 60.0);
+        }
+        assert_eq!(shake.trauma(), 0.0);
+        assert_eq!(shake.offset(), (0, 0));
+    }
+
+    #[test]
+    fn test_offset_never_exceeds_cap() {
+        let mut shake = ScreenShake::new();
+        for _ in 0..1000 {
+            shake.add_trauma(1.0);
+            shake.update(0.003);
+            let (dx, dy) = shake.offset();
+            assert!(dx.abs() <= MAX_SHAKE_OFFSET as i32);
+            assert!(dy.abs() <= MAX_SHAKE_OFFSET as i32);
+        }
+    }
+
+    #[test]
+    fn test_translate_frame_moves_pixels() {
+        let (width, height) = (4, 3);
+        let mut frame = vec![0u8; (width * height * 4) as usize];
+        // Mark pixel (1, 1)
+        let idx = (width as usize + 1) * 4;
+        frame[idx..idx + 4].copy_from_slice(&[255, 0, 0, 255]);
+
+        translate_frame(&mut frame, width, height, 2, 1);
+
+        let moved = (2 * width as usize + 3) * 4;
+        assert_eq!(&frame[moved..moved + 4], &[255, 0, 0, 255]);
+        assert_eq!(&frame[0..4], &BACKGROUND);
+    }
+}
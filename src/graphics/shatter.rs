@@ -0,0 +1,461 @@
+//! The Shatter scene: a photo, or a gradient without one, cut into about two
+//! hundred triangular shards that burst apart, drift under gentle forces and
+//! away from the cursor, and ease back into the whole picture, once every
+//! `CYCLE_SECONDS`. Each shard carries its own piece of the image, drawn by
+//! `DrawCtx::fill_triangle_textured`.
+
+use crate::core::accessibility;
+use crate::core::sim_rng::sim_rng;
+use crate::core::station_state;
+use crate::core::types::{Color, Position, Velocity};
+use crate::graphics::background::{gradient_pixels, Background, BackgroundKind};
+use crate::graphics::draw_ctx::DrawCtx;
+use image::RgbaImage;
+use log::warn;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::f32::consts::PI;
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::sync::{Mutex, PoisonError};
+
+/// Grid cells across and down; each is cut into two shards
+const MESH_COLUMNS: usize = 10;
+const MESH_ROWS: usize = 10;
+/// Furthest inner grid points move off the grid, as a share of a cell.
+/// Small enough that no shard can fold over.
+const JITTER: f32 = 0.2;
+/// One shatter and reassembly, in seconds
+pub const CYCLE_SECONDS: f32 = 30.0;
+/// The picture is whole for this long at the start of each cycle
+const WHOLE_SECONDS: f32 = 2.0;
+/// Easing back into place takes the last this many seconds of a cycle
+const REASSEMBLE_SECONDS: f32 = 6.0;
+/// Burst speed in pixels per second, fastest nearest where it starts, and
+/// the fastest spin in radians per second
+const MIN_BURST_SPEED: f32 = 40.0;
+const MAX_BURST_SPEED: f32 = 200.0;
+const MAX_SPIN: f32 = 1.2;
+/// Share of its velocity a shard loses each second
+const DRAG: f32 = 0.5;
+/// Pull toward home per pixel away, per second squared, so shards stay in view
+const TETHER: f32 = 0.08;
+/// The cursor pushes shards within this many pixels, hardest closest
+const REPEL_RADIUS: f32 = 140.0;
+const REPEL_STRENGTH: f32 = 900.0;
+/// Drifting is slowed by this while reduced motion is on
+const REDUCED_MOTION_SPEED: f32 = 0.4;
+
+const BACKGROUND: [u8; 4] = [4, 4, 8, 255];
+/// What shatters when there is no image
+const GRADIENT_FROM: Color = Color::new(20, 40, 120);
+const GRADIENT_TO: Color = Color::new(250, 120, 60);
+const GRADIENT_ANGLE: f32 = 35.0;
+
+static IMAGE_PATH: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+pub fn set_shatter_enabled(enabled: bool) {
+    station_state::current()
+        .shatter_enabled
+        .store(enabled, Ordering::SeqCst);
+}
+
+pub fn is_shatter_enabled() -> bool {
+    station_state::current()
+        .shatter_enabled
+        .load(Ordering::SeqCst)
+}
+
+/// Shatters the image at `path` from the next time the scene is drawn
+pub fn set_shatter_image(path: PathBuf) {
+    *IMAGE_PATH.lock().unwrap_or_else(PoisonError::into_inner) = Some(path);
+    *station_state::lock(&station_state::current().shatter) = None;
+}
+
+pub fn set_cursor(cursor: Option<Position>) {
+    *station_state::lock(&station_state::current().shatter_cursor) = cursor;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Phase {
+    Whole,
+    Drifting,
+    /// `progress` runs 0.0..1.0 on the way back into place
+    Reassembling {
+        progress: f32,
+    },
+}
+
+/// Where `cycle_time` seconds falls in the cycle
+fn phase_at(cycle_time: f32) -> Phase {
+    let t = cycle_time.rem_euclid(CYCLE_SECONDS);
+    let reassemble_from = CYCLE_SECONDS - REASSEMBLE_SECONDS;
+    if t < WHOLE_SECONDS {
+        Phase::Whole
+    } else if t < reassemble_from {
+        Phase::Drifting
+    } else {
+        Phase::Reassembling {
+            progress: (t - reassemble_from) / REASSEMBLE_SECONDS,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Shard {
+    /// Corners where the shard sits in the whole picture
+    home: [Position; 3],
+    /// The corners' image coordinates, 0.0..=1.0
+    uvs: [Position; 3],
+    /// Middle of `home`, which the shard turns about
+    center: Position,
+    offset: Position,
+    velocity: Velocity,
+    angle: f32,
+    spin: f32,
+    /// Offset and angle when reassembly began; they ease to zero from there
+    settle_from: (Position, f32),
+}
+
+impl Shard {
+    fn new(home: [Position; 3], width: f32, height: f32) -> Self {
+        let size = Position::new(width, height);
+        Self {
+            home,
+            uvs: home.map(|corner| corner / size),
+            center: (home[0] + home[1] + home[2]) / 3.0,
+            offset: Position::ZERO,
+            velocity: Velocity::ZERO,
+            angle: 0.0,
+            spin: 0.0,
+            settle_from: (Position::ZERO, 0.0),
+        }
+    }
+
+    /// Where the corners are now
+    fn corners(&self) -> [Position; 3] {
+        if self.offset == Position::ZERO && self.angle == 0.0 {
+            return self.home;
+        }
+        let turn = Position::from_angle(self.angle);
+        let center = self.center + self.offset;
+        self.home
+            .map(|corner| center + turn.rotate(corner - self.center))
+    }
+
+    fn go_home(&mut self) {
+        self.offset = Position::ZERO;
+        self.velocity = Velocity::ZERO;
+        self.angle = 0.0;
+        self.spin = 0.0;
+    }
+}
+
+/// A `width` x `height` picture cut into `MESH_COLUMNS` x `MESH_ROWS` cells
+/// of two triangles, the inner grid points jittered so the cuts look broken
+fn mesh(width: u32, height: u32, rng: &mut impl Rng) -> Vec<Shard> {
+    let (width, height) = (width as f32, height as f32);
+    let (cell_width, cell_height) = (width / MESH_COLUMNS as f32, height / MESH_ROWS as f32);
+    let mut points = Vec::with_capacity((MESH_COLUMNS + 1) * (MESH_ROWS + 1));
+    for row in 0..=MESH_ROWS {
+        for column in 0..=MESH_COLUMNS {
+            let mut x = column as f32 * cell_width;
+            let mut y = row as f32 * cell_height;
+            // Only inner points move, so the picture keeps its straight edges
+            if column > 0 && column < MESH_COLUMNS {
+                x += rng.gen_range(-JITTER..=JITTER) * cell_width;
+            }
+            if row > 0 && row < MESH_ROWS {
+                y += rng.gen_range(-JITTER..=JITTER) * cell_height;
+            }
+            points.push(Position::new(x, y));
+        }
+    }
+    let point = |column: usize, row: usize| points[row * (MESH_COLUMNS + 1) + column];
+    let mut shards = Vec::with_capacity(MESH_COLUMNS * MESH_ROWS * 2);
+    for row in 0..MESH_ROWS {
+        for column in 0..MESH_COLUMNS {
+            let [a, b, c, d] = [
+                point(column, row),
+                point(column + 1, row),
+                point(column + 1, row + 1),
+                point(column, row + 1),
+            ];
+            // Alternating diagonals, so no cut runs straight across the picture
+            let halves = if (row + column) % 2 == 0 {
+                [[a, b, c], [a, c, d]]
+            } else {
+                [[a, b, d], [b, c, d]]
+            };
+            shards.extend(halves.map(|home| Shard::new(home, width, height)));
+        }
+    }
+    shards
+}
+
+/// The image at `IMAGE_PATH` filling `width` x `height`, or the gradient
+fn load_texture(width: u32, height: u32) -> RgbaImage {
+    let path = IMAGE_PATH
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone();
+    let pixels = path
+        .and_then(|path| {
+            match Background::prepare(&BackgroundKind::Image(path.clone()), width, height) {
+                Ok(background) => background.pixels().map(<[u8]>::to_vec),
+                Err(e) => {
+                    warn!(
+                        "Could not load {}: {}; shattering a gradient",
+                        path.display(),
+                        e
+                    );
+                    None
+                }
+            }
+        })
+        .unwrap_or_else(|| {
+            gradient_pixels(GRADIENT_FROM, GRADIENT_TO, GRADIENT_ANGLE, width, height)
+        });
+    RgbaImage::from_raw(width, height, pixels).unwrap_or_else(|| RgbaImage::new(width, height))
+}
+
+pub struct ShatterState {
+    width: u32,
+    height: u32,
+    texture: RgbaImage,
+    shards: Vec<Shard>,
+    phase: Phase,
+    started: Option<f32>,
+    last_time: Option<f32>,
+    rng: StdRng,
+}
+
+impl ShatterState {
+    fn new(texture: RgbaImage) -> Self {
+        let (width, height) = texture.dimensions();
+        let mut rng = StdRng::seed_from_u64(sim_rng().gen());
+        Self {
+            width,
+            height,
+            shards: mesh(width, height, &mut rng),
+            texture,
+            phase: Phase::Whole,
+            started: None,
+            last_time: None,
+            rng,
+        }
+    }
+
+    /// Moves the shards on to `cycle_time` seconds into the cycle, `dt`
+    /// seconds since the last update, pushed away from `cursor`
+    fn update(&mut self, cycle_time: f32, dt: f32, cursor: Option<Position>) {
+        let phase = phase_at(cycle_time);
+        match phase {
+            Phase::Whole => self.shards.iter_mut().for_each(Shard::go_home),
+            Phase::Drifting => {
+                if self.phase != Phase::Drifting {
+                    self.shatter(cursor);
+                }
+                self.drift(dt, cursor);
+            }
+            Phase::Reassembling { progress } => {
+                if !matches!(self.phase, Phase::Reassembling { .. }) {
+                    for shard in &mut self.shards {
+                        // The short way round, however many turns it has made
+                        let angle = (shard.angle + PI).rem_euclid(2.0 * PI) - PI;
+                        shard.settle_from = (shard.offset, angle);
+                    }
+                }
+                let left = 1.0 - progress * progress * (3.0 - 2.0 * progress);
+                for shard in &mut self.shards {
+                    let (offset, angle) = shard.settle_from;
+                    shard.offset = offset * left;
+                    shard.angle = angle * left;
+                    shard.velocity = Velocity::ZERO;
+                }
+            }
+        }
+        self.phase = phase;
+    }
+
+    /// Sends every shard away from the cursor, or from a random point
+    fn shatter(&mut self, cursor: Option<Position>) {
+        let size = Position::new(self.width as f32, self.height as f32);
+        let origin = cursor.unwrap_or_else(|| {
+            Position::new(
+                self.rng.gen_range(0.25..0.75),
+                self.rng.gen_range(0.25..0.75),
+            ) * size
+        });
+        let reach = size.length().max(1.0);
+        for shard in &mut self.shards {
+            let away = shard.center - origin;
+            let distance = away.length();
+            let direction = if distance > 1e-3 {
+                away / distance
+            } else {
+                Position::from_angle(self.rng.gen_range(0.0..2.0 * PI))
+            };
+            let closeness = (1.0 - distance / reach).clamp(0.0, 1.0);
+            let speed = MIN_BURST_SPEED + (MAX_BURST_SPEED - MIN_BURST_SPEED) * closeness;
+            let jitter = self.rng.gen_range(0.8..1.2);
+            shard.velocity = direction * speed * jitter;
+            shard.spin = self.rng.gen_range(-MAX_SPIN..=MAX_SPIN);
+        }
+    }
+
+    fn drift(&mut self, dt: f32, cursor: Option<Position>) {
+        let drag = (1.0 - DRAG * dt).max(0.0);
+        for shard in &mut self.shards {
+            let mut accel = -shard.offset * TETHER;
+            if let Some(cursor) = cursor {
+                let away = shard.center + shard.offset - cursor;
+                let distance = away.length();
+                if distance > 1e-3 && distance < REPEL_RADIUS {
+                    let push = REPEL_STRENGTH * (1.0 - distance / REPEL_RADIUS) / distance;
+                    accel += away * push;
+                }
+            }
+            shard.velocity = (shard.velocity + accel * dt) * drag;
+            shard.offset += shard.velocity * dt;
+            shard.angle += shard.spin * dt;
+        }
+    }
+
+    fn draw(&self, ctx: &mut DrawCtx) {
+        ctx.clear(BACKGROUND);
+        for shard in &self.shards {
+            ctx.fill_triangle_textured(shard.corners(), shard.uvs, &self.texture);
+        }
+    }
+
+    fn update_and_draw(&mut self, ctx: &mut DrawCtx, cursor: Option<Position>) {
+        let time = ctx.time;
+        let started = *self.started.get_or_insert(time);
+        let dt = self
+            .last_time
+            .map_or(0.0, |last| (time - last).clamp(0.0, 0.1));
+        self.last_time = Some(time);
+        let speed_scale = if accessibility::is_reduced_motion() {
+            REDUCED_MOTION_SPEED
+        } else {
+            1.0
+        };
+        self.update(time - started, dt * speed_scale, cursor);
+        self.draw(ctx);
+    }
+}
+
+/// Updates and draws the shards when the Shatter scene is on
+pub fn update_and_draw_shatter(ctx: &mut DrawCtx) {
+    if !is_shatter_enabled() {
+        return;
+    }
+    let (width, height) = (ctx.width(), ctx.height());
+    let origin = Position::new(ctx.region.x as f32, ctx.region.y as f32);
+    let station = station_state::current();
+    let cursor = station_state::lock(&station.shatter_cursor).map(|cursor| cursor - origin);
+    let mut slot = station_state::lock(&station.shatter);
+    let size = slot.as_ref().map(|state| (state.width, state.height));
+    if size != Some((width, height)) {
+        *slot = Some(ShatterState::new(load_texture(width, height)));
+    }
+    if let Some(state) = slot.as_mut() {
+        state.update_and_draw(ctx, cursor);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphics::draw_ctx::Region;
+
+    fn area([a, b, c]: [Position; 3]) -> f32 {
+        (b - a).perp_dot(c - a) / 2.0
+    }
+
+    #[test]
+    fn test_mesh_tiles_the_picture() {
+        let (width, height) = (800, 400);
+        let shards = mesh(width, height, &mut StdRng::seed_from_u64(7));
+        assert_eq!(shards.len(), 200);
+        // No shard is folded over, so their areas add up to the picture's
+        let total: f32 = shards.iter().map(|shard| area(shard.home).abs()).sum();
+        assert!((total - (width * height) as f32).abs() < 1.0, "{}", total);
+        assert!(shards.iter().all(|shard| area(shard.home) != 0.0));
+        for shard in &shards {
+            for (home, uv) in shard.home.iter().zip(shard.uvs) {
+                assert!((0.0..=1.0).contains(&uv.x) && (0.0..=1.0).contains(&uv.y));
+                assert!((uv.x * width as f32 - home.x).abs() < 1e-3);
+                assert!((uv.y * height as f32 - home.y).abs() < 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn test_shards_shatter_and_reassemble_every_cycle() {
+        let (width, height) = (160, 80);
+        let texture = RgbaImage::from_raw(
+            width,
+            height,
+            gradient_pixels(GRADIENT_FROM, GRADIENT_TO, GRADIENT_ANGLE, width, height),
+        )
+        .unwrap();
+        let mut state = ShatterState::new(texture.clone());
+        let moved = |state: &ShatterState| {
+            state
+                .shards
+                .iter()
+                .filter(|shard| shard.corners() != shard.home)
+                .count()
+        };
+        let dt = 1.0 / 60.0;
+        for cycle in 0..2 {
+            for step in 0..(CYCLE_SECONDS / dt) as usize {
+                let t = step as f32 * dt;
+                state.update(cycle as f32 * CYCLE_SECONDS + t, dt, None);
+                if t < WHOLE_SECONDS {
+                    assert_eq!(moved(&state), 0, "{}", t);
+                } else if step == (10.0 / dt) as usize {
+                    assert!(moved(&state) > 150, "{}", t);
+                }
+            }
+            // The end of reassembly is the picture exactly as it was
+            for shard in &state.shards {
+                for (corner, home) in shard.corners().iter().zip(shard.home) {
+                    assert!((*corner - home).abs().max_element() < 0.5);
+                }
+            }
+        }
+
+        // Whole, the shards draw the picture with no seams
+        state.update(0.0, dt, None);
+        let mut frame = vec![0u8; (width * height * 4) as usize];
+        let mut ctx = DrawCtx::new(&mut frame, Region::new(0, 0, width, height), width, 0.0);
+        state.draw(&mut ctx);
+        assert_eq!(frame, texture.into_raw());
+    }
+
+    #[test]
+    fn test_the_cursor_pushes_nearby_shards_away() {
+        let texture = RgbaImage::new(400, 200);
+        let mut calm = ShatterState::new(texture);
+        let mut pushed = ShatterState {
+            shards: calm.shards.clone(),
+            ..ShatterState::new(RgbaImage::new(400, 200))
+        };
+        let cursor = Position::new(200.0, 100.0);
+        let distance = |shard: &Shard| (shard.center + shard.offset).distance(cursor);
+        for _ in 0..30 {
+            calm.drift(1.0 / 60.0, None);
+            pushed.drift(1.0 / 60.0, Some(cursor));
+        }
+        for (calm, pushed) in calm.shards.iter().zip(&pushed.shards) {
+            if distance(calm) < REPEL_RADIUS * 0.9 {
+                assert!(distance(pushed) > distance(calm));
+            } else if distance(calm) > REPEL_RADIUS * 2.0 {
+                assert_eq!(pushed.offset, calm.offset);
+            }
+        }
+    }
+}
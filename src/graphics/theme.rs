@@ -0,0 +1,332 @@
+//! Day and night themes: a tint laid over the finished frame, neutral by day
+//! and warm at night. With the schedule on, the local clock is read once a
+//! minute and the tint crossfades between the two over `TRANSITION_MINUTES`
+//! centered on each switch time. Picking a theme by hand suspends the
+//! schedule until it is turned back on.
+
+use crate::core::presets::{lerp, switch_at_midpoint, Interpolate};
+use crate::graphics::post::ColorMatrix;
+use chrono::Timelike;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Length of each crossfade, centered on the switch time
+pub const TRANSITION_MINUTES: f32 = 20.0;
+/// Frame-time seconds between reads of the local clock
+const CHECK_INTERVAL: f32 = 60.0;
+const MINUTES_PER_DAY: u32 = 24 * 60;
+
+/// Gains for red, green, and blue over the finished frame, and the colors
+/// the HUD falls back on when readability comes first
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Theme {
+    pub name: &'static str,
+    pub tint: [f32; 3],
+    /// Backing of HUD panels in opaque HUD mode
+    pub surface: [u8; 3],
+    /// Light and dark text that HUD text switches to when its own color
+    /// doesn't stand out from what is behind it
+    pub contrast_text: [[u8; 3]; 2],
+}
+
+impl Theme {
+    pub const DAY: Self = Self {
+        name: "Day",
+        tint: [1.0, 1.0, 1.0],
+        surface: [16, 16, 24],
+        contrast_text: [[255, 255, 255], [0, 0, 0]],
+    };
+    pub const NIGHT: Self = Self {
+        name: "Night",
+        tint: [1.0, 0.8, 0.55],
+        surface: [24, 16, 8],
+        contrast_text: [[255, 240, 215], [20, 10, 0]],
+    };
+
+    /// The tint as a color matrix, or None when it leaves colors alone
+    pub fn matrix(&self) -> Option<ColorMatrix> {
+        if self.tint == [1.0; 3] {
+            return None;
+        }
+        let [r, g, b] = self.tint;
+        Some(ColorMatrix::from_f32(&[
+            [r, 0.0, 0.0],
+            [0.0, g, 0.0],
+            [0.0, 0.0, b],
+        ]))
+    }
+}
+
+impl Interpolate for Theme {
+    fn interpolate(&self, to: &Self, t: f32) -> Self {
+        Self {
+            name: switch_at_midpoint(self.name, to.name, t),
+            tint: [0, 1, 2].map(|i| lerp(self.tint[i], to.tint[i], t)),
+            surface: lerp_rgb(self.surface, to.surface, t),
+            contrast_text: [0, 1].map(|i| lerp_rgb(self.contrast_text[i], to.contrast_text[i], t)),
+        }
+    }
+}
+
+fn lerp_rgb(a: [u8; 3], b: [u8; 3], t: f32) -> [u8; 3] {
+    [0, 1, 2].map(|i| lerp(a[i] as f32, b[i] as f32, t).round() as u8)
+}
+
+/// When the day and night themes take over, in minutes after local midnight
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Schedule {
+    pub day_starts: u32,
+    pub night_starts: u32,
+}
+
+impl Schedule {
+    pub const DEFAULT: Self = Self {
+        day_starts: 8 * 60,
+        night_starts: 20 * 60,
+    };
+
+    /// How far the theme is toward night at `minute` after midnight: 0 by
+    /// day, 1 at night, and rising or falling linearly through the window
+    /// around whichever switch time is nearer
+    pub fn night_weight(&self, minute: f32) -> f32 {
+        let from_night = offset_minutes(minute, self.night_starts);
+        let from_day = offset_minutes(minute, self.day_starts);
+        let ramp = |offset: f32| (offset / TRANSITION_MINUTES + 0.5).clamp(0.0, 1.0);
+        if from_night.abs() <= from_day.abs() {
+            ramp(from_night)
+        } else {
+            1.0 - ramp(from_day)
+        }
+    }
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Minutes from `switch` to `minute` the short way round the clock, in -720..720
+fn offset_minutes(minute: f32, switch: u32) -> f32 {
+    let day = MINUTES_PER_DAY as f32;
+    (minute - switch as f32 + day / 2.0).rem_euclid(day) - day / 2.0
+}
+
+/// `"HH:MM"` as minutes after midnight
+pub fn parse_clock_time(text: &str) -> Option<u32> {
+    let (hours, minutes) = text.split_once(':')?;
+    let (hours, minutes): (u32, u32) = (hours.trim().parse().ok()?, minutes.trim().parse().ok()?);
+    (hours < 24 && minutes < 60).then_some(hours * 60 + minutes)
+}
+
+/// Minutes after midnight as `"HH:MM"`
+pub fn format_clock_time(minutes: u32) -> String {
+    format!("{:02}:{:02}", minutes / 60 % 24, minutes % 60)
+}
+
+/// Minutes after midnight on the local clock, seconds included
+pub fn local_minute_of_day() -> f32 {
+    let now = chrono::Local::now();
+    (now.hour() * 60 + now.minute()) as f32 + now.second() as f32 / 60.0
+}
+
+/// The schedule, the theme in effect, and the latch a manual pick sets
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThemeState {
+    pub schedule: Schedule,
+    pub schedule_enabled: bool,
+    /// Set by a manual pick; the schedule is left alone until re-enabled
+    pub suspended: bool,
+    pub theme: Theme,
+    /// Frame time of the last clock read
+    last_check: Option<f32>,
+}
+
+impl ThemeState {
+    pub const fn new() -> Self {
+        Self {
+            schedule: Schedule::DEFAULT,
+            schedule_enabled: false,
+            suspended: false,
+            theme: Theme::DAY,
+            last_check: None,
+        }
+    }
+
+    /// Whether the schedule currently decides the theme
+    pub fn following_schedule(&self) -> bool {
+        self.schedule_enabled && !self.suspended
+    }
+
+    /// Reads `clock` (minutes after midnight) once every `CHECK_INTERVAL`
+    /// seconds of frame time and moves the theme to where the schedule has it
+    pub fn update(&mut self, time: f32, clock: impl FnOnce() -> f32) {
+        if !self.following_schedule() {
+            return;
+        }
+        let due = match self.last_check {
+            Some(last) => time < last || time - last >= CHECK_INTERVAL,
+            None => true,
+        };
+        if due {
+            self.last_check = Some(time);
+            let weight = self.schedule.night_weight(clock());
+            self.theme = Theme::DAY.interpolate(&Theme::NIGHT, weight);
+        }
+    }
+
+    /// Picks a theme by hand, suspending the schedule
+    pub fn pick(&mut self, theme: Theme) {
+        self.theme = theme;
+        self.suspended = true;
+    }
+
+    /// Turns the schedule on, or back on after a manual pick; the clock is
+    /// read again on the next update
+    pub fn resume_schedule(&mut self) {
+        self.schedule_enabled = true;
+        self.suspended = false;
+        self.last_check = None;
+    }
+}
+
+impl Default for ThemeState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+static THEME_STATE: Mutex<ThemeState> = Mutex::new(ThemeState::new());
+
+fn theme_state() -> MutexGuard<'static, ThemeState> {
+    THEME_STATE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Applies the schedule for frame time `time`; called once a frame
+pub fn update(time: f32) {
+    theme_state().update(time, local_minute_of_day)
+}
+
+/// Runs `f` on the theme state, for changes beyond picking a theme
+pub fn with_theme_state<R>(f: impl FnOnce(&mut ThemeState) -> R) -> R {
+    f(&mut theme_state())
+}
+
+pub fn current_theme() -> Theme {
+    theme_state().theme
+}
+
+/// Flips between the day and night themes by hand, suspending the schedule,
+/// and returns the theme picked
+pub fn toggle_theme() -> Theme {
+    let mut state = theme_state();
+    let night = state.theme.name == Theme::NIGHT.name;
+    state.pick(if night { Theme::DAY } else { Theme::NIGHT });
+    state.theme
+}
+
+pub fn resume_schedule() {
+    theme_state().resume_schedule()
+}
+
+pub fn schedule() -> Schedule {
+    theme_state().schedule
+}
+
+pub fn schedule_enabled() -> bool {
+    theme_state().schedule_enabled
+}
+
+/// Sets the switch times and whether the schedule runs; a suspended schedule
+/// stays suspended
+pub fn set_schedule(schedule: Schedule, enabled: bool) {
+    let mut state = theme_state();
+    state.schedule = schedule;
+    state.schedule_enabled = enabled;
+    state.last_check = None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schedule_crossfades_around_each_switch() {
+        let schedule = Schedule::DEFAULT;
+        let at = |hours: u32, minutes: f32| schedule.night_weight((hours * 60) as f32 + minutes);
+        assert_eq!(at(12, 0.0), 0.0);
+        assert_eq!(at(2, 0.0), 1.0);
+        // Halfway at the switch, done 10 minutes either side of it
+        assert_eq!(at(20, 0.0), 0.5);
+        assert_eq!(at(19, 50.0), 0.0);
+        assert_eq!(at(19, 55.0), 0.25);
+        assert_eq!(at(20, 10.0), 1.0);
+        assert_eq!(at(8, 0.0), 0.5);
+        assert_eq!(at(7, 55.0), 0.75);
+        assert_eq!(at(8, 10.0), 0.0);
+
+        // A night that wraps past midnight, and one that doesn't
+        let late = Schedule {
+            day_starts: 6 * 60,
+            night_starts: 23 * 60 + 50,
+        };
+        assert_eq!(late.night_weight(0.0), 1.0);
+        assert_eq!(late.night_weight(23.0 * 60.0 + 50.0), 0.5);
+        assert_eq!(late.night_weight(23.0 * 60.0 + 55.0), 0.75);
+        assert_eq!(late.night_weight(12.0 * 60.0), 0.0);
+        let day_sleeper = Schedule {
+            day_starts: 18 * 60,
+            night_starts: 9 * 60,
+        };
+        assert_eq!(day_sleeper.night_weight(12.0 * 60.0), 1.0);
+        assert_eq!(day_sleeper.night_weight(22.0 * 60.0), 0.0);
+
+        assert_eq!(parse_clock_time("07:30"), Some(450));
+        assert_eq!(parse_clock_time("24:00"), None);
+        assert_eq!(format_clock_time(20 * 60 + 5), "20:05");
+    }
+
+    #[test]
+    fn test_clock_is_read_once_a_minute_and_the_tint_shifts_gradually() {
+        let mut state = ThemeState::new();
+        state.resume_schedule();
+        let mut tints = Vec::new();
+        // Ten minutes either side of 20:00, one frame a second
+        for second in 0..=1200 {
+            let time = second as f32;
+            state.update(time, || 19.0 * 60.0 + 50.0 + time / 60.0);
+            tints.push(state.theme.tint[2]);
+        }
+        assert_eq!(tints[0], Theme::DAY.tint[2]);
+        assert!((tints[1200] - Theme::NIGHT.tint[2]).abs() < 1e-6);
+        // Steady between reads, and never more than a twentieth of the way per read
+        assert_eq!(tints[1], tints[0]);
+        assert_eq!(tints[59], tints[0]);
+        for pair in tints.windows(2) {
+            assert!(pair[1] <= pair[0]);
+            assert!(pair[0] - pair[1] <= 0.45 / 20.0 + 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_manual_pick_suspends_the_schedule_until_resumed() {
+        let mut state = ThemeState::new();
+        let midnight = || 0.0;
+        // Off by default
+        state.update(0.0, midnight);
+        assert_eq!(state.theme, Theme::DAY);
+        state.resume_schedule();
+        state.update(0.0, midnight);
+        assert_eq!(state.theme, Theme::NIGHT);
+
+        state.pick(Theme::DAY);
+        assert!(!state.following_schedule());
+        for minute in 1..=5 {
+            state.update(minute as f32 * 60.0, midnight);
+        }
+        assert_eq!(state.theme, Theme::DAY);
+
+        state.resume_schedule();
+        state.update(301.0, midnight);
+        assert_eq!(state.theme, Theme::NIGHT);
+    }
+}
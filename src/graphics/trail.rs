@@ -0,0 +1,255 @@
+//! Comet tails behind the balls. Positions are sampled at a fixed interval
+//! rather than every frame, so a tail covers the same stretch of time at any
+//! frame rate, and drawn as a tapering, fading polyline with a little
+//! additive glow near the ball.
+
+use crate::core::types::Position;
+use crate::graphics::draw_ctx::{DrawCtx, QualitySettings};
+use glam::Vec2;
+
+/// Samples kept per ball; the quality settings draw at most this many
+pub const TRAIL_CAPACITY: usize = 32;
+/// Seconds between samples
+pub const SAMPLE_INTERVAL: f32 = 1.0 / 30.0;
+/// A sample this close to due counts as due, so rounding in the frame clock
+/// doesn't push it a whole frame late
+const SAMPLE_SLACK: f32 = 1e-4;
+/// Segments nearest the ball that also glow
+const GLOW_SEGMENTS: usize = 3;
+/// Glow reach beyond the tail's edge, as a multiple of its width there
+const GLOW_WIDTH: f32 = 1.5;
+/// Brightness of the glow right at the tail's edge
+const GLOW_STRENGTH: f32 = 0.35;
+/// Tail width at the ball, as a fraction of the ball radius
+const HEAD_WIDTH: f32 = 0.8;
+
+/// Ring buffer of a ball's recent positions, newest last
+#[derive(Debug, Clone)]
+pub struct BallTrail {
+    points: [Position; TRAIL_CAPACITY],
+    /// Slot the next sample goes into
+    head: usize,
+    len: usize,
+    /// Time the next sample is due; None until the first one
+    next_sample: Option<f32>,
+}
+
+impl BallTrail {
+    pub const fn new() -> Self {
+        Self {
+            points: [Position::ZERO; TRAIL_CAPACITY],
+            head: 0,
+            len: 0,
+            next_sample: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Records `pos` if a sample is due at `time`. Samples stay on a fixed
+    /// cadence; after a long gap (a pause, a slow frame) it restarts from now.
+    pub fn sample(&mut self, pos: Position, time: f32) {
+        if self
+            .next_sample
+            .is_some_and(|due| time + SAMPLE_SLACK < due)
+        {
+            return;
+        }
+        self.points[self.head] = pos;
+        self.head = (self.head + 1) % TRAIL_CAPACITY;
+        self.len = (self.len + 1).min(TRAIL_CAPACITY);
+        self.next_sample = Some(match self.next_sample {
+            Some(due) if time - due < SAMPLE_INTERVAL => due + SAMPLE_INTERVAL,
+            _ => time + SAMPLE_INTERVAL,
+        });
+    }
+
+    /// Forgets every sample, e.g. when the ball jumps somewhere else
+    pub fn clear(&mut self) {
+        self.len = 0;
+        self.next_sample = None;
+    }
+
+    /// Multiplies every sample by `factor`, for drawing into a larger frame
+    pub fn scale(&mut self, factor: f32) {
+        for point in &mut self.points {
+            *point *= factor;
+        }
+    }
+
+    /// Samples from newest to oldest
+    pub fn recent(&self) -> impl Iterator<Item = Position> + '_ {
+        (1..=self.len).map(|age| self.points[(self.head + TRAIL_CAPACITY - age) % TRAIL_CAPACITY])
+    }
+}
+
+impl Default for BallTrail {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Draws the tail from the ball at `pos` back through its samples, as long
+/// and as faded as `quality.trail_length` allows
+pub fn draw_trail(
+    ctx: &mut DrawCtx,
+    trail: &BallTrail,
+    pos: Position,
+    color: [u8; 4],
+    ball_radius: f32,
+    quality: &QualitySettings,
+) {
+    let length = quality.trail_length.min(TRAIL_CAPACITY);
+    if length == 0 {
+        return;
+    }
+    // Width and opacity both fall off linearly to nothing at the tail's end
+    let at = |age: usize| 1.0 - age as f32 / length as f32;
+    let mut newer = pos;
+    for (age, older) in trail.recent().take(length).enumerate() {
+        let (start, end) = (at(age), at(age + 1));
+        draw_segment(
+            ctx,
+            newer,
+            older,
+            (
+                ball_radius * HEAD_WIDTH * start,
+                ball_radius * HEAD_WIDTH * end,
+            ),
+            color,
+            (start, end),
+            age < GLOW_SEGMENTS,
+        );
+        newer = older;
+    }
+}
+
+/// Fills the capsule from `a` to `b` with radius and opacity going from their
+/// first to their second value. Only `a` gets a round cap, so the next segment,
+/// which starts at `b`, doesn't blend the joint twice.
+fn draw_segment(
+    ctx: &mut DrawCtx,
+    a: Position,
+    b: Position,
+    radius: (f32, f32),
+    color: [u8; 4],
+    opacity: (f32, f32),
+    glow: bool,
+) {
+    let reach = radius.0.max(radius.1) * if glow { 1.0 + GLOW_WIDTH } else { 1.0 } + 1.0;
+    let along = b - a;
+    let length_sq = along.length_squared().max(f32::EPSILON);
+    let (min, max) = (a.min(b) - reach, a.max(b) + reach);
+    for y in min.y.floor() as i32..=max.y.ceil() as i32 {
+        for x in min.x.floor() as i32..=max.x.ceil() as i32 {
+            let offset = Vec2::new(x as f32, y as f32) - a;
+            let t = offset.dot(along) / length_sq;
+            if t >= 1.0 {
+                continue;
+            }
+            let t = t.max(0.0);
+            let distance = (offset - along * t).length();
+            let r = radius.0 + (radius.1 - radius.0) * t;
+            let alpha = opacity.0 + (opacity.1 - opacity.0) * t;
+            if distance <= r {
+                let mut pixel = color;
+                pixel[3] = (color[3] as f32 * alpha) as u8;
+                ctx.blend_pixel(x, y, &pixel);
+            } else if glow && distance <= r * (1.0 + GLOW_WIDTH) {
+                let falloff = 1.0 - (distance - r) / (r * GLOW_WIDTH);
+                let strength = GLOW_STRENGTH * falloff * alpha;
+                ctx.add_pixel(x, y, [0, 1, 2].map(|c| (color[c] as f32 * strength) as u8));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_samples_follow_time_not_frames() {
+        // One second at 30 and at 144 frames per second keeps the same samples
+        for fps in [30.0, 144.0] {
+            let mut trail = BallTrail::new();
+            let frames = fps as usize;
+            for frame in 0..=frames {
+                let time = frame as f32 / fps;
+                trail.sample(Position::new(time * 100.0, 0.0), time);
+            }
+            assert!(
+                (30..=31).contains(&trail.len()),
+                "{} samples at {} fps",
+                trail.len(),
+                fps
+            );
+        }
+
+        // A long gap restarts the cadence instead of catching up
+        let mut trail = BallTrail::new();
+        trail.sample(Position::new(0.0, 0.0), 0.0);
+        trail.sample(Position::new(1.0, 0.0), 5.0);
+        trail.sample(Position::new(2.0, 0.0), 5.01);
+        assert_eq!(trail.len(), 2);
+    }
+
+    #[test]
+    fn test_ring_buffer_wraps_newest_first() {
+        let mut trail = BallTrail::new();
+        let count = TRAIL_CAPACITY + 5;
+        for i in 0..count {
+            trail.sample(Position::new(i as f32, 0.0), i as f32 * SAMPLE_INTERVAL);
+        }
+        assert_eq!(trail.len(), TRAIL_CAPACITY);
+        let xs: Vec<f32> = trail.recent().map(|point| point.x).collect();
+        let expected: Vec<f32> = (count - TRAIL_CAPACITY..count)
+            .rev()
+            .map(|i| i as f32)
+            .collect();
+        assert_eq!(xs, expected);
+
+        trail.clear();
+        assert!(trail.is_empty());
+        assert_eq!(trail.recent().count(), 0);
+        // The next sample is taken at once rather than waiting out the old cadence
+        trail.sample(Position::splat(7.0), 0.0);
+        assert_eq!(trail.recent().collect::<Vec<_>>(), [Position::splat(7.0)]);
+    }
+
+    #[test]
+    fn test_trail_matches_the_recorded_frame() {
+        use crate::graphics::draw_ctx::{Region, DEFAULT_QUALITY};
+
+        let mut trail = BallTrail::new();
+        for i in 0..40 {
+            let t = i as f32 * 0.05;
+            trail.sample(
+                Position::new(100.0 + 60.0 * t.cos(), 75.0 + 40.0 * (1.3 * t).sin()),
+                t,
+            );
+        }
+        let mut frame = vec![0u8; 200 * 150 * 4];
+        let mut ctx = DrawCtx::new(&mut frame, Region::new(0, 0, 200, 150), 200, 0.0);
+        let head = Position::new(170.0, 80.0);
+        draw_trail(
+            &mut ctx,
+            &trail,
+            head,
+            [255, 255, 0, 255],
+            12.0,
+            &DEFAULT_QUALITY,
+        );
+        // Recorded before positions moved from tuples to Vec2
+        let hash = frame.iter().fold(0xcbf29ce484222325u64, |hash, &byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+        assert_eq!(hash, 0xc6399eb85b62d35d);
+    }
+}
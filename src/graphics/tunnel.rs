@@ -0,0 +1,252 @@
+//! The Tunnel scene: checkered rings receding toward a drifting vanishing
+//! point, flying forward faster as the music gets louder.
+//!
+//! Angle and depth for every offset from the vanishing point are computed
+//! once per resolution, so a frame is a table lookup per pixel plus the
+//! current travel and twist. The table covers every offset the drift can
+//! reach, so moving the vanishing point is just a shifted window into it.
+
+use crate::core::accessibility;
+use crate::core::station_state;
+use crate::core::types::hsv_to_rgb;
+use crate::graphics::draw_ctx::DrawCtx;
+use std::f32::consts::TAU;
+use std::sync::atomic::Ordering;
+
+/// Rings between the edge of the frame and the vanishing point: a pixel at
+/// distance d is DEPTH_SCALE * (half the frame) / d rings deep
+const DEPTH_SCALE: f32 = 4.0;
+/// Depth is stored in 1/256ths of a ring
+const DEPTH_FRACTION_BITS: u32 = 8;
+/// Checker segments around the tunnel
+const SEGMENTS: u32 = 16;
+/// Ring colors cycled along the tunnel; a power of two so picking one is a mask
+const RING_HUES: usize = 16;
+/// Forward speed in rings per second, at silence and added at full loudness
+const BASE_SPEED: f32 = 1.5;
+const AUDIO_SPEED: f32 = 6.0;
+/// Speed cap while reduced motion is on
+const REDUCED_MOTION_MAX_SPEED: f32 = 1.0;
+/// Turns per second the checker pattern rotates
+const TWIST_SPEED: f32 = 0.03;
+/// Seconds per drift loop of the vanishing point, horizontally and vertically
+const DRIFT_PERIOD: (f32, f32) = (23.0, 17.0);
+
+pub fn set_tunnel_enabled(enabled: bool) {
+    station_state::current()
+        .tunnel_enabled
+        .store(enabled, Ordering::SeqCst);
+}
+
+pub fn is_tunnel_enabled() -> bool {
+    station_state::current()
+        .tunnel_enabled
+        .load(Ordering::SeqCst)
+}
+
+/// Angle and depth for each offset from the vanishing point, for a
+/// `width` x `height` frame whose vanishing point stays in the central third
+pub struct TunnelMap {
+    width: u32,
+    height: u32,
+    /// Size of the table: the frame plus the drift range on each side
+    map_width: u32,
+    map_height: u32,
+    /// Angle around the vanishing point, a full turn being 256
+    angle: Vec<u8>,
+    /// Depth in rings, fixed point with `DEPTH_FRACTION_BITS`
+    depth: Vec<u16>,
+    /// Brightness falloff toward the vanishing point, 0-255
+    shade: Vec<u8>,
+}
+
+impl TunnelMap {
+    pub fn new(width: u32, height: u32) -> Self {
+        let (map_width, map_height) = (width + 2 * (width / 6) + 1, height + 2 * (height / 6) + 1);
+        let (center_x, center_y) = ((map_width / 2) as f32, (map_height / 2) as f32);
+        let max_distance = (width.max(height) as f32) / 2.0;
+        let len = (map_width * map_height) as usize;
+        let (mut angle, mut depth, mut shade) = (
+            Vec::with_capacity(len),
+            Vec::with_capacity(len),
+            Vec::with_capacity(len),
+        );
+        for y in 0..map_height {
+            for x in 0..map_width {
+                let (dx, dy) = (x as f32 - center_x, y as f32 - center_y);
+                let distance = (dx * dx + dy * dy).sqrt().max(1.0);
+                let turn = dy.atan2(dx).rem_euclid(TAU) / TAU;
+                angle.push((turn * 256.0).round() as u32 as u8);
+                let rings = DEPTH_SCALE * max_distance / distance;
+                let fixed = rings * (1 << DEPTH_FRACTION_BITS) as f32;
+                depth.push(fixed.min(u16::MAX as f32) as u16);
+                shade.push(((distance / max_distance).min(1.0).sqrt() * 255.0) as u8);
+            }
+        }
+        Self {
+            width,
+            height,
+            map_width,
+            map_height,
+            angle,
+            depth,
+            shade,
+        }
+    }
+
+    pub fn matches(&self, width: u32, height: u32) -> bool {
+        self.width == width && self.height == height
+    }
+
+    /// Table index for frame pixel (x, y) with the vanishing point at `vanish`,
+    /// which must lie in the central third
+    fn index(&self, x: u32, y: u32, vanish: (u32, u32)) -> usize {
+        let map_x = x + self.map_width / 2 - vanish.0;
+        let map_y = y + self.map_height / 2 - vanish.1;
+        (map_y * self.map_width + map_x) as usize
+    }
+}
+
+pub struct TunnelState {
+    map: TunnelMap,
+    /// Rings flown so far
+    travel: f32,
+    last_time: Option<f32>,
+}
+
+/// Where the vanishing point is at `time`: circling slowly inside the central
+/// third of the frame, or fixed at the center with reduced motion
+pub fn vanishing_point(width: u32, height: u32, time: f32, reduced_motion: bool) -> (u32, u32) {
+    let (center_x, center_y) = (width / 2, height / 2);
+    if reduced_motion {
+        return (center_x, center_y);
+    }
+    let (reach_x, reach_y) = ((width / 6) as f32, (height / 6) as f32);
+    let dx = (time * TAU / DRIFT_PERIOD.0).sin() * reach_x;
+    let dy = (time * TAU / DRIFT_PERIOD.1).cos() * reach_y;
+    (
+        (center_x as f32 + dx).round() as u32,
+        (center_y as f32 + dy).round() as u32,
+    )
+}
+
+/// Rings per second at `loudness`, capped with reduced motion
+pub fn forward_speed(loudness: f32, reduced_motion: bool) -> f32 {
+    let speed = BASE_SPEED + loudness.clamp(0.0, 1.0) * AUDIO_SPEED;
+    if reduced_motion {
+        speed.min(REDUCED_MOTION_MAX_SPEED)
+    } else {
+        speed
+    }
+}
+
+/// Draws the tunnel over the whole region when the scene is enabled
+pub fn update_and_draw_tunnel(ctx: &mut DrawCtx) {
+    if !is_tunnel_enabled() {
+        return;
+    }
+    let (width, height, time) = (ctx.width(), ctx.height(), ctx.time);
+    let loudness = ctx.features.map_or(0.0, |features| features.loudness);
+    let reduced_motion = accessibility::is_reduced_motion();
+    let station = station_state::current();
+    let mut slot = station_state::lock(&station.tunnel);
+    let state = slot.get_or_insert_with(|| TunnelState {
+        map: TunnelMap::new(width, height),
+        travel: 0.0,
+        last_time: None,
+    });
+    if !state.map.matches(width, height) {
+        state.map = TunnelMap::new(width, height);
+    }
+    let dt = state
+        .last_time
+        .map_or(0.0, |last| (time - last).clamp(0.0, 0.1));
+    state.last_time = Some(time);
+    state.travel = (state.travel + dt * forward_speed(loudness, reduced_motion)) % RING_HUES as f32;
+
+    let colors: [[u8; 3]; RING_HUES] = std::array::from_fn(|i| {
+        let color = hsv_to_rgb(i as f32 / RING_HUES as f32, 0.7, 1.0);
+        [color.red, color.green, color.blue]
+    });
+    let travel = (state.travel * (1 << DEPTH_FRACTION_BITS) as f32) as u32;
+    let twist = ((time * TWIST_SPEED).rem_euclid(1.0) * 256.0) as u32;
+    let vanish = vanishing_point(width, height, time, reduced_motion);
+    let map = &state.map;
+    for y in 0..height {
+        let row = ctx.row_mut(y as usize);
+        let start = map.index(0, y, vanish);
+        let cells = map.angle[start..]
+            .iter()
+            .zip(&map.depth[start..])
+            .zip(&map.shade[start..]);
+        for (pixel, ((&angle, &depth), &shade)) in row.chunks_exact_mut(4).zip(cells) {
+            let ring = (depth as u32 + travel) >> DEPTH_FRACTION_BITS;
+            let segment = ((angle as u32 + twist) & 0xff) * SEGMENTS / 256;
+            let light = if (ring ^ segment) & 1 == 0 { 255 } else { 150 };
+            let brightness = (light * shade as u32) >> 8;
+            let color = colors[ring as usize & (RING_HUES - 1)];
+            for (dst, channel) in pixel.iter_mut().zip(color) {
+                *dst = ((channel as u32 * brightness) >> 8) as u8;
+            }
+            pixel[3] = 255;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_map_is_point_symmetric_around_its_center() {
+        let map = TunnelMap::new(90, 60);
+        let (cx, cy) = (map.map_width / 2, map.map_height / 2);
+        let at = |x: u32, y: u32| (y * map.map_width + x) as usize;
+        for (dx, dy) in [(10, 0), (0, 7), (13, 5), (30, 20), (3, 25)] {
+            let (a, b) = (at(cx + dx, cy + dy), at(cx - dx, cy - dy));
+            // Opposite points share a depth and lie half a turn apart
+            assert_eq!(map.depth[a], map.depth[b]);
+            assert_eq!(map.shade[a], map.shade[b]);
+            assert_eq!(map.angle[a].wrapping_sub(map.angle[b]), 128);
+            // Mirroring across the horizontal axis negates the angle
+            let mirrored = at(cx + dx, cy - dy);
+            assert_eq!(map.angle[a].wrapping_add(map.angle[mirrored]), 0);
+        }
+        // Depth grows toward the center, which is the deepest and darkest point
+        assert!(map.depth[at(cx + 5, cy)] > map.depth[at(cx + 40, cy)]);
+        assert_eq!(map.depth[at(cx, cy)], *map.depth.iter().max().unwrap());
+        assert_eq!(map.shade[at(cx, cy)], *map.shade.iter().min().unwrap());
+    }
+
+    #[test]
+    fn test_drift_stays_central_and_lookups_in_bounds() {
+        let (width, height) = (90, 60);
+        let map = TunnelMap::new(width, height);
+        for step in 0..400 {
+            let time = step as f32 * 0.25;
+            let (vx, vy) = vanishing_point(width, height, time, false);
+            assert!(
+                (width / 3..=width * 2 / 3).contains(&vx),
+                "{} at {}",
+                vx,
+                time
+            );
+            assert!(
+                (height / 3..=height * 2 / 3).contains(&vy),
+                "{} at {}",
+                vy,
+                time
+            );
+            // The far corner of the frame is the last cell any row reads
+            let last = map.index(width - 1, height - 1, (vx, vy));
+            assert!(last < map.depth.len());
+            assert!(map.index(0, 0, (vx, vy)) < last);
+        }
+        assert_eq!(
+            vanishing_point(width, height, 12.3, true),
+            (width / 2, height / 2)
+        );
+        assert!(forward_speed(1.0, true) <= REDUCED_MOTION_MAX_SPEED);
+        assert!(forward_speed(1.0, false) > forward_speed(0.0, false));
+    }
+}
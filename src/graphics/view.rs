@@ -0,0 +1,111 @@
+//! Zoom and pan for scenes that opt in. Scene positions are mapped through
+//! the view as they are drawn, so lines stay crisp at any zoom, and the cursor
+//! is mapped back so the mouse acts on the point under it.
+
+use crate::core::types::Position;
+use glam::Vec2;
+
+pub const MIN_ZOOM: f32 = 0.5;
+pub const MAX_ZOOM: f32 = 4.0;
+/// Zoom factor for one line of the scroll wheel
+pub const ZOOM_STEP: f32 = 1.1;
+
+/// Maps scene positions to the screen: scaled by `zoom`, then moved by `offset`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ViewTransform {
+    pub zoom: f32,
+    pub offset: Vec2,
+}
+
+impl ViewTransform {
+    pub const IDENTITY: Self = Self {
+        zoom: 1.0,
+        offset: Vec2::ZERO,
+    };
+
+    pub fn is_identity(&self) -> bool {
+        *self == Self::IDENTITY
+    }
+
+    pub fn to_screen(&self, pos: Position) -> Position {
+        pos * self.zoom + self.offset
+    }
+
+    pub fn to_world(&self, pos: Position) -> Position {
+        (pos - self.offset) / self.zoom
+    }
+
+    /// Zooms by `ZOOM_STEP` per scroll line, keeping the scene point under
+    /// `anchor` where it is on screen. Zoom stays within MIN_ZOOM..=MAX_ZOOM.
+    pub fn zoom_about(&mut self, anchor: Position, lines: f32) {
+        let fixed = self.to_world(anchor);
+        self.zoom = (self.zoom * ZOOM_STEP.powf(lines)).clamp(MIN_ZOOM, MAX_ZOOM);
+        self.offset = anchor - fixed * self.zoom;
+    }
+
+    pub fn pan(&mut self, delta: Vec2) {
+        self.offset += delta;
+    }
+
+    /// Whether anything within `reach` screen pixels of the segment from `a`
+    /// to `b` (in screen coordinates) can land in a `width` x `height` frame
+    pub fn segment_visible(a: Position, b: Position, reach: f32, width: u32, height: u32) -> bool {
+        let (min, max) = (a.min(b) - reach, a.max(b) + reach);
+        max.x >= 0.0 && max.y >= 0.0 && min.x < width as f32 && min.y < height as f32
+    }
+}
+
+impl Default for ViewTransform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zoom_keeps_the_anchor_and_round_trips() {
+        let mut view = ViewTransform::IDENTITY;
+        let anchor = Position::new(300.0, 120.0);
+        let under = view.to_world(anchor);
+        view.zoom_about(anchor, 5.0);
+        view.pan(Vec2::new(-40.0, 25.0));
+        view.zoom_about(anchor - Vec2::new(40.0, -25.0), 3.0);
+        assert!(view.zoom > 2.0);
+        assert!(
+            view.to_screen(under)
+                .distance(anchor - Vec2::new(40.0, -25.0))
+                < 1e-3
+        );
+        for pos in [
+            Position::ZERO,
+            Position::new(-50.0, 800.0),
+            Position::new(1e3, 7.5),
+        ] {
+            assert!(view.to_world(view.to_screen(pos)).distance(pos) < 1e-3);
+        }
+
+        // The range is clamped both ways
+        view.zoom_about(anchor, 100.0);
+        assert_eq!(view.zoom, MAX_ZOOM);
+        view.zoom_about(anchor, -100.0);
+        assert_eq!(view.zoom, MIN_ZOOM);
+    }
+
+    #[test]
+    fn test_culling_keeps_segments_that_reach_the_frame() {
+        let visible = |a: (f32, f32), b: (f32, f32), reach| {
+            ViewTransform::segment_visible(a.into(), b.into(), reach, 100, 50)
+        };
+        assert!(visible((10.0, 10.0), (20.0, 20.0), 0.0));
+        // Crossing the frame with both ends outside it
+        assert!(visible((-50.0, 25.0), (150.0, 25.0), 0.0));
+        assert!(!visible((-30.0, 10.0), (-5.0, 40.0), 0.0));
+        assert!(!visible((10.0, 50.0), (90.0, 80.0), 0.0));
+        // A thick line just off the edge still shows
+        assert!(visible((-30.0, 10.0), (-5.0, 40.0), 6.0));
+        assert!(!visible((100.0, 0.0), (120.0, 10.0), 0.0));
+    }
+}
@@ -4,42 +4,267 @@ pub mod core;
 pub mod graphics;
 pub mod physics;
 pub mod text;
+pub mod ui;
 
 // Re-export commonly used types and modules
 pub use core::integration;
 pub use core::orchestrator;
 pub use core::types;
 
+pub use algorithms::sort_machine::{SortMachine, StepResult};
+pub use algorithms::sorter::SortAlgorithm;
+pub use core::embed::{EmbedError, FrameStats, StimConfig, StimStation};
+
 // App module - integrates with the orchestrator
 pub mod app {
+    use crate::core::capture::{self, CaptureOptions};
+    use crate::core::coords::WindowMapper;
+    use crate::core::embed::{StimConfig, StimStation};
+    use crate::core::focus::{self, FocusState, SceneClock};
+    use crate::core::input_record::{InputFrame, InputSource};
+    use crate::core::keep_awake;
+    use crate::core::pacing::{self, FramePacer, PacingReport, SystemClock};
+    use crate::core::pixel_format::{self, PixelFormat};
+    #[cfg(feature = "live-params")]
+    use crate::core::live_params::{LiveParams, PolledFile};
+    use crate::core::settings;
+    use crate::core::snapshot::{self, AppSnapshot};
+    use crate::core::types::Position;
     use crate::integration;
-    use crate::orchestrator;
     use crate::types::{HEIGHT, WIDTH};
+    use crate::ui::gestures::{Gesture, GestureRecognizer};
+    use crate::ui::intro::{self, Intro};
+    use crate::ui::{device_picker, help_overlay, menu, status_icons, timeline, toast};
+    use log::{info, warn};
     use std::sync::Arc;
     use std::time::Instant;
+    use winit::event::MouseButton;
     use winit::keyboard::KeyCode;
+    use winit::window::Fullscreen;
 
     pub struct App {
+        station: StimStation,
         quit: bool,
-        start_time: Instant,
+        clock: SceneClock,
+        focus: FocusState,
+        last_frame: Option<Instant>,
+        pacer: FramePacer,
+        // Refresh rate of the window's monitor, for the pacing line on F3
+        refresh_hz: Option<f32>,
+        snapshot: Option<AppSnapshot>,
+        input_source: InputSource,
+        // Replays advance time by the recorded frame deltas instead of the wall clock
+        replay_time: f32,
+        gestures: GestureRecognizer,
+        // Gesture timestamps; advanced by input frame deltas so replays match
+        input_time: f32,
+        fullscreen_requested: bool,
+        // Shift+F12 was pressed; the capture is taken right after the next frame
+        capture_requested: bool,
+        // Shared by every mouse consumer so they agree on where the cursor is
+        mapper: WindowMapper,
+        intro: Intro,
+        // A key went down since the last input frame, tracked or not
+        key_seen: bool,
+        // What the scenes draw into while the window takes another channel order
+        rgba_frame: Vec<u8>,
+        #[cfg(feature = "preview-server")]
+        preview: Option<crate::core::preview::PreviewServer>,
+        #[cfg(feature = "live-params")]
+        live_params: Option<LiveParams<PolledFile>>,
+        #[cfg(all(target_os = "linux", feature = "v4l2-loopback"))]
+        loopback: Option<crate::core::loopback::Loopback>,
     }
 
     impl App {
         pub fn new(window: &Arc<winit::window::Window>) -> Self {
             // Set monitor dimensions for scaling
-            if let Some(monitor) = window.current_monitor() {
-                integration::set_monitor_dimensions(&monitor);
-            }
+            integration::set_monitor_dimensions(
+                window.current_monitor().as_ref(),
+                window.inner_size(),
+            );
+            let station = StimStation::new(StimConfig::default())
+                .expect("starting a StimStation never fails");
+            let size = window.inner_size();
+            let refresh_hz = window
+                .current_monitor()
+                .and_then(|monitor| monitor.refresh_rate_millihertz())
+                .map(|millihertz| millihertz as f32 / 1000.0);
 
             Self {
+                station,
                 quit: false,
-                start_time: Instant::now(),
+                clock: SceneClock::new(Instant::now()),
+                focus: FocusState::new(focus::focus_settings()),
+                last_frame: None,
+                pacer: FramePacer::new(),
+                refresh_hz,
+                snapshot: None,
+                input_source: InputSource::Live,
+                replay_time: 0.0,
+                gestures: GestureRecognizer::new(),
+                input_time: 0.0,
+                fullscreen_requested: false,
+                capture_requested: false,
+                mapper: WindowMapper::new(WIDTH, HEIGHT, size.width, size.height),
+                intro: if intro::should_show_intro(
+                    intro::first_run_done(),
+                    intro::intro_forced(),
+                ) {
+                    Intro::new()
+                } else {
+                    Intro::finished()
+                },
+                key_seen: false,
+                rgba_frame: Vec::new(),
+                #[cfg(feature = "preview-server")]
+                preview: start_preview_server(),
+                #[cfg(feature = "live-params")]
+                live_params: crate::core::live_params::watch_config_dir(),
+                #[cfg(all(target_os = "linux", feature = "v4l2-loopback"))]
+                loopback: None,
             }
         }
 
+        /// Records live input to a session file or replays one instead of live input
+        pub fn set_input_source(&mut self, source: InputSource) {
+            let recorded = !matches!(source, InputSource::Live);
+            // The intro swallows the keys that skip it, which would put recorded
+            // input out of step with the scene
+            if recorded {
+                self.intro = Intro::finished();
+            }
+            // Arcade bounces add energy, so recordings wouldn't replay the same
+            crate::physics::physics::set_force_elastic(recorded);
+            self.input_source = source;
+            self.replay_time = 0.0;
+        }
+
+        pub fn flush_input_source(&mut self) {
+            self.input_source.flush();
+        }
+
+        /// Call when the window's physical size or scale factor changes
+        pub fn resize(&mut self, width: u32, height: u32) {
+            self.mapper.resize(width, height);
+        }
+
+        /// Applies the unfocused behaviors from the settings when the window loses
+        /// focus and undoes them when it comes back
+        pub fn set_focused(&mut self, focused: bool) {
+            if !self.focus.set_focused(focused) {
+                return;
+            }
+            let now = Instant::now();
+            if self.focus.is_paused() {
+                self.clock.pause(now);
+            } else {
+                self.clock.resume(now);
+            }
+            crate::audio::audio_playback::ramp_output_volume(
+                self.focus.target_volume(),
+                focus::DUCK_RAMP,
+            );
+            self.update_frame_interval();
+            info!("Window {}", if focused { "focused" } else { "unfocused" });
+        }
+
+        /// Paces frames by the frame-rate cap, or by the lower unfocused rate
+        /// while that is the slower of the two
+        fn update_frame_interval(&mut self) {
+            let interval = match (self.focus.frame_interval(), pacing::fps_cap().interval()) {
+                (Some(unfocused), Some(cap)) => Some(unfocused.max(cap)),
+                (unfocused, cap) => unfocused.or(cap),
+            };
+            self.pacer.set_interval(interval);
+        }
+
+        /// When to wake up for the next frame, or None when frames are not rate limited
+        pub fn next_frame_at(&self) -> Option<Instant> {
+            self.pacer.wake_at()
+        }
+
+        /// Whether the next frame is close enough to due to wait out the rest in `draw`
+        pub fn frame_due(&self) -> bool {
+            self.pacer.is_due(Instant::now())
+        }
+
+        /// Also writes every frame drawn to `loopback`
+        #[cfg(all(target_os = "linux", feature = "v4l2-loopback"))]
+        pub fn set_loopback(&mut self, loopback: crate::core::loopback::Loopback) {
+            self.loopback = Some(loopback);
+        }
+
+        /// Draws the next frame in the window's channel order. While paused the
+        /// previous frame is left in place.
         pub fn draw(&mut self, frame: &mut [u8]) {
-            let time = self.start_time.elapsed().as_secs_f32();
-            orchestrator::draw_frame(frame, WIDTH, HEIGHT, time, 0, WIDTH);
+            let format = pixel_format::present_format();
+            if format == PixelFormat::Rgba8 {
+                self.rgba_frame = Vec::new();
+                self.draw_rgba(frame);
+                return;
+            }
+            // Scenes draw over the previous frame, so it has to stay RGBA
+            let mut rgba = std::mem::take(&mut self.rgba_frame);
+            rgba.resize(frame.len(), 0);
+            self.draw_rgba(&mut rgba);
+            format.convert(&rgba, frame);
+            self.rgba_frame = rgba;
+        }
+
+        fn draw_rgba(&mut self, frame: &mut [u8]) {
+            self.update_frame_interval();
+            self.pacer.wait(&mut SystemClock);
+            let now = Instant::now();
+            self.pacer.frame_started(now);
+            pacing::publish_report(self.pacer.stats().map(|stats| PacingReport {
+                cap: pacing::fps_cap(),
+                refresh_hz: self.refresh_hz,
+                stats,
+            }));
+            let dt = self
+                .last_frame
+                .map_or(0.0, |last| (now - last).as_secs_f32().min(0.1));
+            self.last_frame = Some(now);
+            #[cfg(feature = "live-params")]
+            if let Some(live_params) = self.live_params.as_mut() {
+                live_params.update(now);
+            }
+            keep_awake::update(self.focus.is_paused());
+            if self.focus.is_paused() {
+                return;
+            }
+            let time = if self.input_source.is_replaying() {
+                self.replay_time
+            } else {
+                self.clock.time(Instant::now())
+            };
+            if let Err(e) = self.station.render_at(frame, time) {
+                warn!("Frame skipped: {}", e);
+                return;
+            }
+            if std::mem::take(&mut self.capture_requested) {
+                self.save_capture();
+            }
+            // The scene keeps running under the intro
+            self.intro.update(dt);
+            self.intro.draw(frame, WIDTH, HEIGHT);
+            if self.intro.take_completion() {
+                settings::mark_first_run_done();
+            }
+            #[cfg(feature = "preview-server")]
+            if let Some(preview) = self.preview.as_mut() {
+                preview.submit(frame, WIDTH, HEIGHT, 0, WIDTH);
+            }
+            #[cfg(all(target_os = "linux", feature = "v4l2-loopback"))]
+            if let Some(loopback) = self.loopback.as_mut() {
+                loopback.submit(frame);
+            }
+        }
+
+        /// Whether the scene is held still because the window is in the background
+        pub fn is_paused(&self) -> bool {
+            self.focus.is_paused()
         }
 
         pub fn should_quit(&self) -> bool {
@@ -49,40 +274,192 @@ pub mod app {
         pub fn quit(&mut self) {
             self.quit = true;
         }
+
+        /// Called for every key press, including keys the input frame doesn't track
+        pub fn note_key_pressed(&mut self) {
+            self.key_seen = true;
+        }
+
         pub fn handle_input(
             &mut self,
             input: &mut winit_input_helper::WinitInputHelper,
-            _window: &winit::window::Window,
+            window: &winit::window::Window,
         ) {
-            // Add input handling for physics forces, etc.
-            if input.key_pressed(KeyCode::Escape) {
-                self.quit();
+            let mut live = InputFrame::from_helper(input);
+            // Over the letterbox the cursor isn't over the frame at all
+            live.cursor = live.cursor.and_then(|(x, y)| self.mapper.window_to_buffer(x, y));
+            // Any key or click skips the intro and does nothing else
+            let key_seen = std::mem::take(&mut self.key_seen);
+            let any_press = key_seen || live.pressed != 0 || live.buttons_pressed != 0;
+            if self.intro.is_active() && any_press {
+                self.intro.skip();
+                return;
+            }
+            let frame = self.input_source.next_frame(live);
+            if self.input_source.is_replaying() {
+                self.replay_time += frame.dt;
+                // Esc still quits a replay even though live input is ignored
+                if live.key_pressed(KeyCode::Escape) {
+                    self.quit();
+                }
             }
+            self.apply_input(&frame);
+            // Ctrl+F3 may have changed the cap
+            self.update_frame_interval();
+            if std::mem::take(&mut self.fullscreen_requested) {
+                toggle_fullscreen(window);
+            }
+        }
 
-            // Toggle white noise with '9' key
-            if input.key_pressed(KeyCode::Digit9) {
-                let enabled = !crate::audio::audio_playback::is_white_noise_enabled();
-                crate::audio::audio_playback::set_white_noise_enabled(enabled);
-                if enabled {
-                    println!("White noise enabled");
+        /// Applies one frame of input, whether live or replayed
+        pub fn apply_input(&mut self, input: &InputFrame) {
+            // While the scene menu is open its keys move the selection, pick a scene, or close it
+            let menu_keys = menu::menu_keys();
+            if menu::is_menu_open() {
+                for (key, steps) in [(menu_keys.previous, -1), (menu_keys.next, 1)] {
+                    if input.key_pressed(key) {
+                        menu::move_selection(steps);
+                    }
+                }
+                if input.key_pressed(menu_keys.confirm) {
+                    if let Some(scene) = menu::choose_selected() {
+                        info!("Scene: {}", scene.name);
+                    }
+                }
+            }
+
+            // Esc closes the scene menu, the output picker, the timeline, or the key help
+            // if one is open, otherwise quits. The menu's close key closes it too.
+            let close_menu = [menu_keys.close, KeyCode::Escape];
+            if menu::is_menu_open() && close_menu.into_iter().any(|key| input.key_pressed(key)) {
+                menu::close_menu();
+            } else if input.key_pressed(KeyCode::Escape) {
+                if device_picker::is_picker_open() {
+                    device_picker::close_picker();
+                } else if timeline::is_timeline_open() {
+                    timeline::toggle_timeline();
+                } else if help_overlay::is_help_open() {
+                    help_overlay::set_help_open(false);
+                } else {
+                    self.quit();
+                }
+            }
+
+            // F5 captures a snapshot, Shift+F5 restores it
+            if input.key_pressed(KeyCode::F5) {
+                if input.held_shift() {
+                    if let Some(saved) = &self.snapshot {
+                        snapshot::restore(saved);
+                        info!("Snapshot restored");
+                    }
                 } else {
-                    println!("White noise disabled");
+                    self.snapshot = Some(snapshot::capture());
+                    info!("Snapshot captured");
                 }
             }
 
-            // Example: Add force to balls with arrow keys
-            if input.key_held(KeyCode::ArrowLeft) {
-                crate::physics::physics::apply_force_yellow(-0.1, 0.0);
+            // F9 swaps the channel order the window is sent frames in
+            if input.key_pressed(KeyCode::F9) {
+                let format = pixel_format::toggle_requested_format();
+                toast::show_toast(vec![format!("Output: {}", format.name().to_uppercase())]);
+            }
+
+            // Shift+F12 saves a wallpaper of the scene at twice the resolution
+            if input.key_pressed(KeyCode::F12) && input.held_shift() {
+                self.capture_requested = true;
             }
-            if input.key_held(KeyCode::ArrowRight) {
-                crate::physics::physics::apply_force_yellow(0.1, 0.0);
+
+            // Mouse gestures: double-click toggles fullscreen, long-press opens the
+            // scene menu, and dragging over the World sets off a wind gust
+            let held = input.mouse_held(MouseButton::Left);
+            self.input_time += input.dt;
+            for gesture in self.gestures.update(input.cursor, held, self.input_time) {
+                self.apply_gesture(gesture);
             }
-            if input.key_held(KeyCode::ArrowUp) {
-                crate::physics::physics::apply_force_yellow(0.0, -0.1);
+            menu::hover(input.cursor.map(|(x, y)| Position::new(x, y)));
+            timeline::hover(input.cursor.map(|(x, y)| Position::new(x, y)), WIDTH, HEIGHT);
+            // The World ignores the mouse while the menu, the timeline, or a
+            // status icon is in front of it
+            let over_icon = input.cursor.is_some_and(|(x, y)| {
+                status_icons::icon_at(Position::new(x, y)).is_some()
+            });
+            let world_held =
+                held && !menu::is_menu_open() && !timeline::is_timeline_open() && !over_icon;
+            crate::physics::world::handle_mouse(input.cursor, world_held);
+
+            // Scene bindings are the embeddable part; see `StimStation::apply_input`
+            self.station.apply_input(input);
+        }
+    }
+
+    impl App {
+        /// Renders the frame just drawn at high resolution and writes it out.
+        /// Encoding the PNG happens here too, so it adds to the frame's hitch.
+        fn save_capture(&mut self) {
+            let image = self.station.capture(&CaptureOptions::DEFAULT);
+            let secs = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs());
+            let Some(dir) = capture::capture_dir() else {
+                warn!("Capture not saved: no pictures or data folder");
+                return;
+            };
+            match capture::save_capture(&image, &dir, secs) {
+                Ok(path) => {
+                    info!("Capture saved to {}", path.display());
+                    toast::show_toast(vec![
+                        "Capture saved".to_string(),
+                        path.display().to_string(),
+                    ]);
+                }
+                Err(e) => warn!("Capture not saved: {}", e),
             }
-            if input.key_held(KeyCode::ArrowDown) {
-                crate::physics::physics::apply_force_yellow(0.0, 0.1);
+        }
+
+        fn apply_gesture(&mut self, gesture: Gesture) {
+            match gesture {
+                Gesture::Click(pos) | Gesture::DoubleClick(pos) if menu::is_menu_open() => {
+                    if let Some(scene) = menu::click(pos) {
+                        info!("Scene: {}", scene.name);
+                    }
+                }
+                Gesture::Click(pos) if timeline::is_timeline_open() => {
+                    if let Some(scene) = timeline::click(pos, WIDTH, HEIGHT) {
+                        info!("Scene: {}", scene.name);
+                    }
+                }
+                Gesture::Click(pos) if status_icons::icon_at(pos).is_some() => {
+                    status_icons::click(pos);
+                }
+                Gesture::DoubleClick(_) => self.fullscreen_requested = true,
+                Gesture::LongPress(pos) => menu::open_menu_at(pos, WIDTH, HEIGHT),
+                Gesture::DragEnd { start, end }
+                    if crate::physics::world::is_world_enabled()
+                        && !crate::physics::world::is_paint_mode()
+                        && !crate::physics::world::is_waves_mode()
+                        && !menu::is_menu_open() =>
+                {
+                    crate::physics::forces::add_drag_gust(end - start);
+                }
+                _ => {}
             }
         }
     }
+
+    fn toggle_fullscreen(window: &winit::window::Window) {
+        let fullscreen = match window.fullscreen() {
+            Some(_) => None,
+            None => Some(Fullscreen::Borderless(None)),
+        };
+        window.set_fullscreen(fullscreen);
+    }
+
+    /// A failed bind only disables the preview; the app runs as usual
+    #[cfg(feature = "preview-server")]
+    fn start_preview_server() -> Option<crate::core::preview::PreviewServer> {
+        use crate::core::preview::{PreviewServer, PREVIEW_PORT};
+        PreviewServer::start(PREVIEW_PORT)
+            .map_err(|e| warn!("Preview server disabled: {}", e))
+            .ok()
+    }
 }
@@ -1,16 +1,102 @@
-use pixels::{Error, Pixels, SurfaceTexture};
+use log::{error, info, warn};
+use pixels::{Error, Pixels, PixelsBuilder, SurfaceTexture};
+use std::path::Path;
+use std::fmt::Display;
 use std::sync::Arc;
+use std::time::Instant;
+use stimstation::algorithms::sorter_manager::{self, SorterEdge};
 use stimstation::app::App;
+use stimstation::core::input_record::{InputRecorder, InputReplayer, InputSource};
+use stimstation::core::{
+    accessibility, bench, bufpool, doctor, export, frame_cap, logging, orchestrator, pacing,
+    persist, pixel_format, render_export, scenes, session_stats, settings, sim_rng,
+};
+use stimstation::core::pixel_format::PixelFormat;
+use stimstation::core::watchdog::{RenderFault, RenderWatchdog, WatchdogAction};
+use stimstation::graphics::{accents, shatter};
+use stimstation::graphics::background::BackgroundKind;
+use stimstation::physics::world;
 use stimstation::types::{HEIGHT, WIDTH};
 use winit::{
     dpi::LogicalSize,
-    event::{Event, WindowEvent},
+    event::{ElementState, Event, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
-    window::WindowBuilder,
+    window::{Window, WindowBuilder},
 };
 use winit_input_helper::WinitInputHelper;
 
 fn main() -> Result<(), Error> {
+    logging::init();
+    settings::load_from_config_dir();
+    // After the settings file, so the environment's preferences win
+    accessibility::init_from_env();
+    let args: Vec<String> = std::env::args().collect();
+    match args.get(1).map(String::as_str) {
+        Some("export-manifest") => {
+            run_export_manifest(&args);
+            return Ok(());
+        }
+        Some("bench") => {
+            run_bench(&args);
+            return Ok(());
+        }
+        Some("bench-all") => {
+            run_bench_all(&args);
+            return Ok(());
+        }
+        Some("render") => {
+            run_render(&args);
+            return Ok(());
+        }
+        Some("doctor") => {
+            run_doctor(&args);
+            return Ok(());
+        }
+        _ => {}
+    }
+    if args.iter().any(|arg| arg == "--show-intro") {
+        stimstation::ui::intro::force_intro();
+    }
+    if args.iter().any(|arg| arg == "--clean") {
+        stimstation::orchestrator::set_clean_mode(true);
+    }
+    if args.iter().any(|arg| arg == "--no-audio") {
+        stimstation::audio::set_audio_enabled(false);
+    }
+    if let Some(name) = flag_value(&args, "--pixel-format") {
+        match PixelFormat::from_name(name) {
+            Some(format) => pixel_format::set_requested_format(Some(format)),
+            None => warn!("Unknown pixel format `{}`; following the surface", name),
+        }
+    }
+    if let Some(url) = flag_value(&args, "--audio-url") {
+        stimstation::audio::audio_download::set_audio_url(Some(url.clone()));
+    }
+    if let Some(path) = flag_value(&args, "--background") {
+        world::set_world_background(BackgroundKind::Image(path.into()));
+        world::set_world_enabled(true);
+    }
+    if let Some(path) = flag_value(&args, "--shatter-image") {
+        shatter::set_shatter_image(path.into());
+        if let Some(scene) = scenes::find_scene("shatter") {
+            scene.enter();
+        }
+    }
+    if let Some(path) = flag_value(&args, "--sort-image") {
+        match sorter_manager::load_image_dataset(Path::new(path), SorterEdge::Bottom) {
+            Ok(()) => info!("Sorting rows of {}", path),
+            Err(e) => error!("Could not load {}: {}", path, e),
+        }
+    }
+
+    // Seeds the simulation, so before anything draws from it
+    let input_source = input_source_from_args(&args);
+
+    persist::enable_in_config_dir();
+    if let Some(dir) = dirs::config_dir() {
+        accents::enable_persistence(dir.join("stimstation"));
+    }
+
     // Create the event loop and input helper
     let event_loop = EventLoop::new().unwrap();
     let mut input = WinitInputHelper::new();
@@ -27,76 +113,498 @@ fn main() -> Result<(), Error> {
     });
 
     // Initialize the pixel buffer
-    let mut pixels = {
-        let window_size = window.inner_size();
-        let surface_texture = SurfaceTexture::new(
-            window_size.width,
-            window_size.height,
-            Arc::clone(&window),
-        );
-        Pixels::new(WIDTH, HEIGHT, surface_texture)?
-    };
+    let mut pixels = create_pixels(&window)?;
 
     // Create the app and perform initial draw
     let mut app = App::new(&window);
+    if let Some(source) = input_source {
+        app.set_input_source(source);
+    }
+    #[cfg(all(target_os = "linux", feature = "v4l2-loopback"))]
+    if let Some(path) = flag_value(&args, "--loopback") {
+        use stimstation::core::loopback::Loopback;
+        match Loopback::open(Path::new(path), WIDTH, HEIGHT) {
+            Ok(loopback) => app.set_loopback(loopback),
+            Err(e) => error!("Could not open {} for loopback output: {}", path, e),
+        }
+    }
     app.draw(pixels.frame_mut());
 
     if let Err(err) = pixels.render() {
-        eprintln!("Initial render error: {err}");
+        error!("Initial render error: {err}");
         return Err(err);
     }
 
     window.request_redraw();
+    let mut watchdog = RenderWatchdog::new(Instant::now());
+    let mut occluded = false;
+    let mut vsync = pacing::fps_cap().uses_vsync();
+    let mut surface = Some(pixels);
 
     // Run the event loop
     event_loop
         .run(move |event, window_target| {
             window_target.set_control_flow(ControlFlow::Poll);
+            let Some(pixels) = sync_pixel_format(&mut surface, &window) else {
+                app.quit();
+                window_target.exit();
+                return;
+            };
 
             // Handle input events
             if input.update(&event) {
                 if input.close_requested() || app.should_quit() {
+                    app.flush_input_source();
                     window_target.exit();
                     return;
                 }
 
                 if let Some(size) = input.window_resized() {
                     if let Err(err) = pixels.resize_surface(size.width, size.height) {
-                        eprintln!("Pixels resize error: {err}");
+                        error!("Pixels resize error: {err}");
                         app.quit();
                         return;
                     }
+                    app.resize(size.width, size.height);
                 }
 
                 app.handle_input(&mut input, &window);
-                app.draw(pixels.frame_mut());
+                if app.frame_due() {
+                    app.draw(pixels.frame_mut());
+                    sync_vsync(pixels, &mut vsync);
 
-                if let Err(err) = pixels.render() {
-                    eprintln!("Pixels render error: {err}");
-                    app.quit();
-                    return;
+                    if !present(pixels, &window, &mut watchdog) {
+                        app.quit();
+                        return;
+                    }
                 }
 
-                window.request_redraw();
+                // Capped or unfocused, frames are rate limited; sleep until the next one is due
+                match app.next_frame_at() {
+                    Some(due) => window_target.set_control_flow(ControlFlow::WaitUntil(due)),
+                    None => window.request_redraw(),
+                }
             }
 
-            // Handle redraw requests
+            // Handle redraw requests and focus changes
             match event {
                 Event::WindowEvent { event: WindowEvent::RedrawRequested, .. } => {
+                    if !app.frame_due() {
+                        return;
+                    }
                     app.draw(pixels.frame_mut());
+                    sync_vsync(pixels, &mut vsync);
 
-                    if let Err(err) = pixels.render() {
-                        eprintln!("Pixels render error: {err}");
+                    if !present(pixels, &window, &mut watchdog) {
                         app.quit();
                         return;
                     }
 
+                    if app.next_frame_at().is_none() {
+                        window.request_redraw();
+                    }
+                }
+                Event::WindowEvent { event: WindowEvent::KeyboardInput { event: key, .. }, .. }
+                    if key.state == ElementState::Pressed && !key.repeat =>
+                {
+                    app.note_key_pressed();
+                }
+                Event::WindowEvent { event: WindowEvent::Focused(focused), .. } => {
+                    app.set_focused(focused);
                     window.request_redraw();
                 }
+                Event::WindowEvent { event: WindowEvent::Occluded(hidden), .. } => {
+                    occluded = hidden;
+                }
+                // A loop that stopped presenting gets the same recovery as a lost surface
+                Event::AboutToWait => {
+                    let idle = app.is_paused() || occluded || window.is_minimized() == Some(true);
+                    let action = watchdog.check_stall(Instant::now(), idle);
+                    if !recover(pixels, &window, action, "no frame presented for 2s") {
+                        app.quit();
+                        window_target.exit();
+                    }
+                }
                 _ => {}
             }
         })
         .unwrap();
 
+    session_stats::print_exit_summary();
+    persist::save_scenes();
+    stimstation::core::keep_awake::release();
+    stimstation::audio::audio_analysis::shutdown_analysis_thread();
+    log::logger().flush();
     Ok(())
 }
+
+/// Creates the surface and pixel buffer for `window`, at startup and whenever
+/// the render watchdog asks for a fresh surface. The buffer takes the channel
+/// order asked for, or else the one the surface prefers.
+fn create_pixels(window: &Arc<Window>) -> Result<Pixels<'static>, Error> {
+    let build = |format: PixelFormat| {
+        let window_size = window.inner_size();
+        let surface_texture =
+            SurfaceTexture::new(window_size.width, window_size.height, Arc::clone(window));
+        PixelsBuilder::new(WIDTH, HEIGHT, surface_texture)
+            .enable_vsync(pacing::fps_cap().uses_vsync())
+            .texture_format(format.texture_format())
+            .build()
+    };
+    let tried = pixel_format::requested_format().unwrap_or(pixel_format::present_format());
+    let pixels = build(tried)?;
+    // What the surface prefers is only known once there is one
+    let wanted = pixel_format::resolve(pixels.render_texture_format());
+    if wanted == tried {
+        pixel_format::set_present_format(tried);
+        return Ok(pixels);
+    }
+    // A window has one surface at a time
+    drop(pixels);
+    let pixels = build(wanted)?;
+    pixel_format::set_present_format(wanted);
+    info!("Presenting {} frames to match the surface", wanted.name());
+    Ok(pixels)
+}
+
+/// Rebuilds the pixel buffer when a different channel order was asked for.
+/// A window has one surface at a time, so the old buffer goes first; if the
+/// new one can't be built, the order asked for is dropped and the buffer is
+/// rebuilt as it was. None once there is no buffer left.
+fn sync_pixel_format<'a>(
+    surface: &'a mut Option<Pixels<'static>>,
+    window: &Arc<Window>,
+) -> Option<&'a mut Pixels<'static>> {
+    if pixel_format::switch_pending() {
+        *surface = None;
+        *surface = match create_pixels(window) {
+            Ok(fresh) => Some(fresh),
+            Err(e) => {
+                warn!("Could not switch the pixel format: {e}");
+                pixel_format::set_requested_format(Some(pixel_format::present_format()));
+                create_pixels(window)
+                    .map_err(|e| error!("Could not rebuild the pixel buffer: {e}"))
+                    .ok()
+            }
+        };
+    }
+    surface.as_mut()
+}
+
+/// Turns vsync on for the monitor-refresh cap and off for the others, which
+/// the app paces itself. `vsync` is what the surface currently uses.
+fn sync_vsync(pixels: &mut Pixels<'static>, vsync: &mut bool) {
+    let wanted = pacing::fps_cap().uses_vsync();
+    if wanted != *vsync {
+        pixels.enable_vsync(wanted);
+        *vsync = wanted;
+    }
+}
+
+/// Presents the frame, handing failures to the watchdog. Returns false once
+/// rendering can't be recovered and the app should exit.
+fn present(
+    pixels: &mut Pixels<'static>,
+    window: &Arc<Window>,
+    watchdog: &mut RenderWatchdog,
+) -> bool {
+    let started = Instant::now();
+    match pixels.render() {
+        Ok(()) => {
+            orchestrator::record_present_time(started.elapsed());
+            watchdog.presented(Instant::now());
+            true
+        }
+        Err(err) => {
+            let action = watchdog.failed(RenderFault::classify(&err), Instant::now());
+            recover(pixels, window, action, err)
+        }
+    }
+}
+
+/// Carries out the watchdog's `action`; `reason` is reported if it gives up
+fn recover(
+    pixels: &mut Pixels<'static>,
+    window: &Arc<Window>,
+    action: WatchdogAction,
+    reason: impl Display,
+) -> bool {
+    match action {
+        WatchdogAction::Continue => true,
+        WatchdogAction::Recreate => {
+            match create_pixels(window) {
+                Ok(fresh) => *pixels = fresh,
+                // Counts as a failed attempt; the next frame's failure retries
+                Err(e) => warn!("render: surface recreation failed: {e}"),
+            }
+            true
+        }
+        WatchdogAction::GiveUp => {
+            error!("Rendering failed and could not be recovered: {reason}");
+            false
+        }
+    }
+}
+
+/// `stimstation export-manifest [--out manifest.json] [--thumbs thumbs/]`
+fn run_export_manifest(args: &[String]) {
+    let out = flag_value(args, "--out").map_or("manifest.json", String::as_str);
+    let thumbs = flag_value(args, "--thumbs").map_or("thumbs", String::as_str);
+    match export::export_manifest(Path::new(out), Path::new(thumbs)) {
+        Ok(count) => info!("Exported {} scenes to {}", count, out),
+        Err(e) => {
+            error!("Manifest export failed: {}", e);
+            log::logger().flush();
+            std::process::exit(1);
+        }
+    }
+    log::logger().flush();
+}
+
+/// `stimstation bench [--frames N] [--mem] [--light-mixing] [--scene ID] [--no-frame-cap]
+/// [--max-ball-scale] [--long-exposure] [--swizzle] [--shatter]`: headless render timing, with
+/// `--mem` the scratch buffer report, with `--swizzle` the cost of converting frames to BGRA, and
+/// with `--shatter` the Shatter scene's 200 shards alone at 800x400
+fn run_bench(args: &[String]) {
+    if args.iter().any(|arg| arg == "--light-mixing") {
+        stimstation::orchestrator::set_light_mixing(true);
+    }
+    if args.iter().any(|arg| arg == "--max-ball-scale") {
+        stimstation::physics::physics::set_force_max_ball_scale(true);
+    }
+    if args.iter().any(|arg| arg == "--no-frame-cap") {
+        frame_cap::set_frame_caps_enabled(false);
+    }
+    // Measures what accumulating costs; a bench is too short to finish an exposure
+    if args.iter().any(|arg| arg == "--long-exposure") {
+        stimstation::graphics::longexposure::toggle_long_exposure();
+    }
+    if let Some(id) = flag_value(args, "--scene") {
+        match scenes::find_scene(id) {
+            Some(scene) => scene.enter(),
+            None => {
+                error!("No scene called `{}`", id);
+                log::logger().flush();
+                std::process::exit(1);
+            }
+        }
+    }
+    let frames = flag_value(args, "--frames")
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(bench::DEFAULT_BENCH_FRAMES);
+    let report = bench::run_bench(frames);
+    println!(
+        "{} frames in {:.2?} ({:.2} ms/frame)",
+        report.frames,
+        report.total,
+        report.average_ms()
+    );
+    if args.iter().any(|arg| arg == "--mem") {
+        for line in bufpool::report_lines() {
+            println!("{}", line);
+        }
+    }
+    if args.iter().any(|arg| arg == "--swizzle") {
+        let swizzle = bench::run_swizzle_bench(frames, PixelFormat::Bgra8);
+        println!(
+            "BGRA swizzle: {} frames in {:.2?} ({:.3} ms/frame)",
+            swizzle.frames,
+            swizzle.total,
+            swizzle.average_ms()
+        );
+    }
+    if args.iter().any(|arg| arg == "--shatter") {
+        let shatter = bench::run_shatter_bench(frames);
+        println!(
+            "Shatter at 800x400: {} frames in {:.2?} ({:.3} ms/frame)",
+            shatter.frames,
+            shatter.total,
+            shatter.average_ms()
+        );
+    }
+}
+
+/// `stimstation bench-all [--seconds S] [--out report.json] [--compare old.json]`: every
+/// scene at every bench size, as a table and a JSON report. Exits with 1 when the
+/// comparison finds frame times more than 10% slower.
+fn run_bench_all(args: &[String]) {
+    let seconds = flag_value(args, "--seconds")
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(bench::DEFAULT_BENCH_SECONDS);
+    let out = flag_value(args, "--out").map_or("bench-report.json", String::as_str);
+    let baseline = flag_value(args, "--compare").map(|path| {
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .and_then(|json| bench::BenchAllReport::from_json(&json))
+            .unwrap_or_else(|| fail(format!("`{}` is not a bench-all report", path)))
+    });
+
+    let report = bench::run_bench_all(seconds)
+        .unwrap_or_else(|e| fail(format!("Benchmark failed: {}", e)));
+    for line in report.table_lines() {
+        println!("{}", line);
+    }
+    let json = serde_json::to_string_pretty(&report.to_json()).unwrap();
+    if let Err(e) = std::fs::write(out, json) {
+        fail(format!("Could not write {}: {}", out, e));
+    }
+    info!("Wrote {}", out);
+
+    let Some(baseline) = baseline else {
+        log::logger().flush();
+        return;
+    };
+    let regressions = bench::compare_reports(&baseline, &report, bench::REGRESSION_THRESHOLD);
+    for regression in &regressions {
+        println!(
+            "REGRESSION {} {}x{} {}: {:.2} -> {:.2} ms (+{:.0}%)",
+            regression.scene,
+            regression.width,
+            regression.height,
+            regression.metric,
+            regression.before,
+            regression.after,
+            regression.slowdown() * 100.0
+        );
+    }
+    log::logger().flush();
+    if !regressions.is_empty() {
+        std::process::exit(1);
+    }
+    println!("No regressions against the baseline");
+}
+
+/// `stimstation render [--scene ID] [--out frames/] [--frames N] [--fps F] [--size WxH]
+/// [--start-frame A] [--end-frame B] [--every N] [--seed S] [--with-audio]`: numbered PNG
+/// frames and a render_manifest.json. Re-running into the same folder continues from the
+/// first missing frame. With audio, frames are drawn in real time while the soundtrack
+/// plays, and the analyzed audio is saved as audio.wav with an audio.json sync note.
+fn run_render(args: &[String]) {
+    let number = |flag: &str, default: usize| match flag_value(args, flag) {
+        Some(value) => value
+            .parse()
+            .unwrap_or_else(|_| fail(format!("{} needs a whole number, not `{}`", flag, value))),
+        None => default,
+    };
+    let scene = match flag_value(args, "--scene") {
+        Some(id) => scenes::find_scene(id)
+            .unwrap_or_else(|| fail(format!("No scene called `{}`", id))),
+        None => scenes::active_scene(),
+    };
+    let (width, height) = match flag_value(args, "--size") {
+        Some(size) => size
+            .split_once('x')
+            .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+            .filter(|&(w, h)| w > 0 && h > 0)
+            .unwrap_or_else(|| fail(format!("--size needs WIDTHxHEIGHT, not `{}`", size))),
+        None => (WIDTH, HEIGHT),
+    };
+    let frames = number("--frames", render_export::DEFAULT_FRAMES).max(1);
+    let range = render_export::FrameRange::new(
+        number("--start-frame", 0),
+        number("--end-frame", frames - 1),
+        number("--every", 1),
+    )
+    .unwrap_or_else(|e| fail(e.to_string()));
+    let options = render_export::RenderOptions {
+        scene,
+        width,
+        height,
+        fps: number("--fps", render_export::DEFAULT_FPS as usize).max(1) as u32,
+        seed: flag_value(args, "--seed").map(|seed| {
+            seed.parse()
+                .unwrap_or_else(|_| fail(format!("--seed needs a number, not `{}`", seed)))
+        }),
+        range,
+        with_audio: args.iter().any(|arg| arg == "--with-audio"),
+    };
+    let out = flag_value(args, "--out").map_or("frames", String::as_str);
+    let summary = render_export::render_frames(&options, Path::new(out), |progress| {
+        eprint!("\r{}", progress.bar_line());
+    })
+    .unwrap_or_else(|e| fail(format!("Render failed: {}", e)));
+    eprintln!();
+    if summary.resumed > 0 {
+        info!("Kept {} frames from an earlier run", summary.resumed);
+    }
+    if let Some(audio) = summary.audio {
+        info!(
+            "Saved the audio; frame {} starts {:.3}s into it",
+            audio.first_frame,
+            audio.offset_seconds()
+        );
+    }
+    info!(
+        "Rendered {} frames of {} to {} (seed {})",
+        summary.rendered, scene.id, out, summary.seed
+    );
+    log::logger().flush();
+}
+
+/// `stimstation doctor [--json]`: checks rendering, the font, audio, the config
+/// directory, and the clock without opening a window. Exits with 1 when any fails.
+fn run_doctor(args: &[String]) {
+    let results = doctor::run_checks();
+    if args.iter().any(|arg| arg == "--json") {
+        match serde_json::to_string_pretty(&doctor::to_json(&results)) {
+            Ok(json) => println!("{}", json),
+            Err(e) => error!("Could not write the report: {}", e),
+        }
+    } else {
+        print!("{}", doctor::format_table(&results));
+    }
+    log::logger().flush();
+    if results.iter().any(|result| !result.passed) {
+        std::process::exit(1);
+    }
+}
+
+/// Logs `message` and exits with status 1
+fn fail(message: String) -> ! {
+    error!("{}", message);
+    log::logger().flush();
+    std::process::exit(1);
+}
+
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a String> {
+    args.iter()
+        .position(|arg| arg == flag)
+        .and_then(|i| args.get(i + 1))
+}
+
+// --replay takes precedence over --record. Either seeds the simulation with
+// the session's seed.
+fn input_source_from_args(args: &[String]) -> Option<InputSource> {
+    if let Some(path) = flag_value(args, "--replay") {
+        return match InputReplayer::open(Path::new(path)) {
+            Ok(replayer) => {
+                info!(
+                    "Replaying {} frames from {} (seed {})",
+                    replayer.len(),
+                    path,
+                    replayer.header().seed
+                );
+                sim_rng::seed(replayer.header().seed);
+                Some(InputSource::Replay(replayer))
+            }
+            Err(e) => {
+                error!("Failed to open replay {}: {}", path, e);
+                None
+            }
+        };
+    }
+    let path = flag_value(args, "--record")?;
+    let seed = sim_rng::session_seed();
+    sim_rng::seed(seed);
+    match InputRecorder::create(Path::new(path), seed) {
+        Ok(recorder) => {
+            info!("Recording input to {}", path);
+            Some(InputSource::Recording(recorder))
+        }
+        Err(e) => {
+            error!("Failed to create recording {}: {}", path, e);
+            None
+        }
+    }
+}
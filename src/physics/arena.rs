@@ -0,0 +1,415 @@
+//! Shapes the World's lines and particles are kept inside. The frame itself is
+//! the default; the circle and hexagon are inscribed in it. Anything that
+//! leaves is put back on the nearest boundary point and its velocity is
+//! reflected about the boundary normal there.
+
+use crate::core::types::{Position, Velocity};
+use std::f32::consts::{FRAC_PI_3, FRAC_PI_6, TAU};
+
+/// Segments in the circle's drawn outline
+const CIRCLE_OUTLINE_SEGMENTS: usize = 96;
+/// Below this distance outside a corner, the side's own normal is used, since
+/// the direction from the corner is mostly rounding error
+const CORNER_EPSILON: f32 = 1e-3;
+
+pub trait Boundary {
+    /// True on the boundary and inside it
+    fn contains(&self, p: Position) -> bool;
+    /// The nearest point on or inside the boundary; `p` itself when contained
+    fn project_inside(&self, p: Position) -> Position;
+    /// Outward unit normal of the boundary nearest `p`. Outside a corner it
+    /// points from the corner to `p`.
+    fn normal_at(&self, p: Position) -> Velocity;
+}
+
+/// Puts a point that left `boundary` back on it, reversing the velocity along
+/// the normal there and keeping `restitution` of it. Points inside are untouched.
+pub fn collide(boundary: &impl Boundary, pos: &mut Position, vel: &mut Velocity, restitution: f32) {
+    if boundary.contains(*pos) {
+        return;
+    }
+    let normal = boundary.normal_at(*pos);
+    *pos = boundary.project_inside(*pos);
+    let outward = vel.dot(normal);
+    if outward > 0.0 {
+        *vel -= normal * outward * (1.0 + restitution);
+    }
+}
+
+/// Unit direction from `inside` out to `p`, or None when they are too close
+/// for it to mean anything
+fn direction_out(p: Position, inside: Position) -> Option<Velocity> {
+    let out = p - inside;
+    (out.length() > CORNER_EPSILON).then(|| out.normalize())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub min: Position,
+    pub max: Position,
+}
+
+impl Boundary for Rect {
+    fn contains(&self, p: Position) -> bool {
+        (self.min.x..=self.max.x).contains(&p.x) && (self.min.y..=self.max.y).contains(&p.y)
+    }
+
+    fn project_inside(&self, p: Position) -> Position {
+        p.clamp(self.min, self.max)
+    }
+
+    fn normal_at(&self, p: Position) -> Velocity {
+        if let Some(out) = direction_out(p, self.project_inside(p)) {
+            return out;
+        }
+        let sides = [
+            (p.x - self.min.x, -Velocity::X),
+            (self.max.x - p.x, Velocity::X),
+            (p.y - self.min.y, -Velocity::Y),
+            (self.max.y - p.y, Velocity::Y),
+        ];
+        sides
+            .into_iter()
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .map_or(Velocity::Y, |(_, normal)| normal)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Circle {
+    pub center: Position,
+    pub radius: f32,
+}
+
+impl Boundary for Circle {
+    fn contains(&self, p: Position) -> bool {
+        p.distance_squared(self.center) <= self.radius * self.radius
+    }
+
+    fn project_inside(&self, p: Position) -> Position {
+        if self.contains(p) {
+            return p;
+        }
+        self.center + (p - self.center).normalize() * self.radius
+    }
+
+    fn normal_at(&self, p: Position) -> Velocity {
+        (p - self.center).normalize_or(Velocity::Y)
+    }
+}
+
+/// A regular hexagon with flat top and bottom sides, so it is wider than tall
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hex {
+    pub center: Position,
+    /// Distance from the center to each corner, which is also the side length
+    pub radius: f32,
+}
+
+impl Hex {
+    /// Distance from the center to the middle of each side
+    pub fn apothem(&self) -> f32 {
+        self.radius * 3f32.sqrt() / 2.0
+    }
+
+    pub fn corners(&self) -> [Position; 6] {
+        std::array::from_fn(|i| {
+            self.center + Velocity::from_angle(i as f32 * FRAC_PI_3) * self.radius
+        })
+    }
+
+    /// Outward normal of the side `p` is furthest beyond (or, inside, nearest
+    /// to) and how far past that side it is; negative inside
+    fn nearest_side(&self, p: Position) -> (Velocity, f32) {
+        let offset = p - self.center;
+        (0..6)
+            .map(|i| {
+                let normal = Velocity::from_angle(FRAC_PI_6 + i as f32 * FRAC_PI_3);
+                (normal, offset.dot(normal) - self.apothem())
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .expect("a hexagon has sides")
+    }
+}
+
+impl Boundary for Hex {
+    fn contains(&self, p: Position) -> bool {
+        self.nearest_side(p).1 <= 0.0
+    }
+
+    /// Drops `p` onto the line of the side it is beyond, then slides it along
+    /// that line to the side's ends. Beyond a corner both adjacent sides'
+    /// lines put it past their ends, so it lands on the corner.
+    fn project_inside(&self, p: Position) -> Position {
+        let (normal, beyond) = self.nearest_side(p);
+        if beyond <= 0.0 {
+            return p;
+        }
+        let middle = self.center + normal * self.apothem();
+        let along = normal.perp();
+        let offset = (p - middle)
+            .dot(along)
+            .clamp(-self.radius / 2.0, self.radius / 2.0);
+        middle + along * offset
+    }
+
+    fn normal_at(&self, p: Position) -> Velocity {
+        direction_out(p, self.project_inside(p)).unwrap_or(self.nearest_side(p).0)
+    }
+}
+
+/// Which arena the World uses
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ArenaShape {
+    /// The frame's own edges
+    #[default]
+    Rect,
+    Circle,
+    Hex,
+}
+
+impl ArenaShape {
+    pub const ALL: [ArenaShape; 3] = [ArenaShape::Rect, ArenaShape::Circle, ArenaShape::Hex];
+
+    /// Name used in the settings file
+    pub fn name(&self) -> &'static str {
+        match self {
+            ArenaShape::Rect => "rect",
+            ArenaShape::Circle => "circle",
+            ArenaShape::Hex => "hex",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|shape| shape.name() == name)
+    }
+
+    /// Cycles Rect -> Circle -> Hex -> Rect
+    pub fn next(&self) -> Self {
+        match self {
+            ArenaShape::Rect => ArenaShape::Circle,
+            ArenaShape::Circle => ArenaShape::Hex,
+            ArenaShape::Hex => ArenaShape::Rect,
+        }
+    }
+
+    /// This shape as large as it fits, centered in a `width` x `height` frame
+    pub fn fit(&self, width: u32, height: u32) -> Arena {
+        let (width, height) = (width as f32, height as f32);
+        let center = Position::new(width / 2.0, height / 2.0);
+        match self {
+            ArenaShape::Rect => Arena::Rect(Rect {
+                min: Position::ZERO,
+                max: Position::new(width, height),
+            }),
+            ArenaShape::Circle => Arena::Circle(Circle {
+                center,
+                radius: width.min(height) / 2.0,
+            }),
+            // Two radii across and two apothems high
+            ArenaShape::Hex => Arena::Hex(Hex {
+                center,
+                radius: (width / 2.0).min(height / 3f32.sqrt()),
+            }),
+        }
+    }
+}
+
+/// An arena fitted to a frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Arena {
+    Rect(Rect),
+    Circle(Circle),
+    Hex(Hex),
+}
+
+impl Arena {
+    pub fn center(&self) -> Position {
+        match self {
+            Arena::Rect(rect) => (rect.min + rect.max) / 2.0,
+            Arena::Circle(circle) => circle.center,
+            Arena::Hex(hex) => hex.center,
+        }
+    }
+
+    pub fn half_width(&self) -> f32 {
+        match self {
+            Arena::Rect(rect) => (rect.max.x - rect.min.x) / 2.0,
+            Arena::Circle(circle) => circle.radius,
+            Arena::Hex(hex) => hex.radius,
+        }
+    }
+
+    /// Closed loop of points around the boundary for drawing it. Empty for the
+    /// frame, whose edges need no outline.
+    pub fn outline(&self) -> Vec<Position> {
+        match self {
+            Arena::Rect(_) => Vec::new(),
+            Arena::Circle(circle) => (0..CIRCLE_OUTLINE_SEGMENTS)
+                .map(|i| {
+                    let angle = i as f32 / CIRCLE_OUTLINE_SEGMENTS as f32 * TAU;
+                    circle.center + Velocity::from_angle(angle) * circle.radius
+                })
+                .collect(),
+            Arena::Hex(hex) => hex.corners().to_vec(),
+        }
+    }
+}
+
+impl Boundary for Arena {
+    fn contains(&self, p: Position) -> bool {
+        match self {
+            Arena::Rect(rect) => rect.contains(p),
+            Arena::Circle(circle) => circle.contains(p),
+            Arena::Hex(hex) => hex.contains(p),
+        }
+    }
+
+    fn project_inside(&self, p: Position) -> Position {
+        match self {
+            Arena::Rect(rect) => rect.project_inside(p),
+            Arena::Circle(circle) => circle.project_inside(p),
+            Arena::Hex(hex) => hex.project_inside(p),
+        }
+    }
+
+    fn normal_at(&self, p: Position) -> Velocity {
+        match self {
+            Arena::Rect(rect) => rect.normal_at(p),
+            Arena::Circle(circle) => circle.normal_at(p),
+            Arena::Hex(hex) => hex.normal_at(p),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: Velocity, b: Velocity) -> bool {
+        (a - b).length() < 1e-4
+    }
+
+    #[test]
+    fn test_rect_projects_onto_sides_and_corners() {
+        let rect = Rect {
+            min: Position::ZERO,
+            max: Position::new(800.0, 400.0),
+        };
+        assert!(rect.contains(Position::new(800.0, 0.0)));
+        assert!(!rect.contains(Position::new(-0.5, 200.0)));
+
+        let left = Position::new(-30.0, 100.0);
+        assert_eq!(rect.project_inside(left), Position::new(0.0, 100.0));
+        assert!(close(rect.normal_at(left), -Velocity::X));
+        // Inside, the nearest side's normal
+        assert!(close(
+            rect.normal_at(Position::new(700.0, 390.0)),
+            Velocity::Y
+        ));
+        // Beyond a corner the normal points from the corner out to the point
+        let corner = Position::new(830.0, 440.0);
+        assert_eq!(rect.project_inside(corner), Position::new(800.0, 400.0));
+        assert!(close(rect.normal_at(corner), Velocity::new(0.6, 0.8)));
+    }
+
+    #[test]
+    fn test_circle_projects_along_the_radius() {
+        let circle = Circle {
+            center: Position::new(400.0, 200.0),
+            radius: 200.0,
+        };
+        assert!(circle.contains(Position::new(400.0, 0.0)));
+        assert!(!circle.contains(Position::new(10.0, 10.0)));
+
+        let outside = Position::new(400.0 + 300.0, 200.0 + 400.0);
+        let projected = circle.project_inside(outside);
+        assert!(close(
+            projected,
+            Position::new(400.0 + 120.0, 200.0 + 160.0)
+        ));
+        assert!(close(circle.normal_at(outside), Velocity::new(0.6, 0.8)));
+        assert!(close(circle.normal_at(projected), Velocity::new(0.6, 0.8)));
+        let inside = Position::new(450.0, 200.0);
+        assert_eq!(circle.project_inside(inside), inside);
+    }
+
+    #[test]
+    fn test_hex_projects_onto_sides_and_corners() {
+        let hex = Hex {
+            center: Position::ZERO,
+            radius: 100.0,
+        };
+        let apothem = hex.apothem();
+        assert!(hex.contains(Position::new(0.0, apothem)));
+        assert!(hex.contains(Position::new(99.0, 0.0)));
+        // Wider than tall: the corners are left and right, the flat sides top and bottom
+        assert!(!hex.contains(Position::new(0.0, apothem + 1.0)));
+        assert!(!hex.contains(Position::new(90.0, 40.0)));
+
+        let below = Position::new(20.0, 150.0);
+        assert!(close(
+            hex.project_inside(below),
+            Position::new(20.0, apothem)
+        ));
+        assert!(close(hex.normal_at(below), Velocity::Y));
+        // Past the right corner, on the axis: projected onto the corner itself
+        let right = Position::new(130.0, 0.0);
+        assert!(close(hex.project_inside(right), Position::new(100.0, 0.0)));
+        assert!(close(hex.normal_at(right), Velocity::X));
+        // Off the lower right side, its normal is 30 degrees below the x axis
+        let side_normal = Velocity::from_angle(FRAC_PI_6);
+        let on_side = (hex.corners()[0] + hex.corners()[1]) / 2.0;
+        let off_side = on_side + side_normal * 25.0;
+        assert!(close(hex.project_inside(off_side), on_side));
+        assert!(close(hex.normal_at(off_side), side_normal));
+        assert!(close(hex.normal_at(Position::new(10.0, 70.0)), Velocity::Y));
+
+        // Every projection lands on the boundary
+        for i in 0..72 {
+            let p = Velocity::from_angle(i as f32 * 5f32.to_radians()) * 250.0;
+            let projected = hex.project_inside(p);
+            assert!(hex.nearest_side(projected).1.abs() < 1e-3, "{:?}", p);
+        }
+    }
+
+    #[test]
+    fn test_collide_reflects_about_the_normal() {
+        let circle = Circle {
+            center: Position::ZERO,
+            radius: 10.0,
+        };
+        let mut pos = Position::new(12.0, 0.0);
+        let mut vel = Velocity::new(3.0, 1.0);
+        collide(&circle, &mut pos, &mut vel, 0.5);
+        assert!(close(pos, Position::new(10.0, 0.0)));
+        // The normal part reverses and loses half; the tangential part is kept
+        assert!(close(vel, Velocity::new(-1.5, 1.0)));
+
+        // Already heading back inside: only the position changes
+        let mut pos = Position::new(0.0, -11.0);
+        let mut vel = Velocity::new(0.5, 2.0);
+        collide(&circle, &mut pos, &mut vel, 1.0);
+        assert!(close(pos, Position::new(0.0, -10.0)));
+        assert_eq!(vel, Velocity::new(0.5, 2.0));
+    }
+
+    #[test]
+    fn test_shapes_fit_the_frame() {
+        for shape in ArenaShape::ALL {
+            assert_eq!(ArenaShape::from_name(shape.name()), Some(shape));
+            let arena = shape.fit(800, 400);
+            assert_eq!(arena.center(), Position::new(400.0, 200.0));
+            for point in arena.outline() {
+                let inside = point.clamp(Position::ZERO, Position::new(800.0, 400.0));
+                assert!(point.distance(inside) < 1e-3, "{:?}", point);
+            }
+        }
+        assert_eq!(ArenaShape::Circle.fit(800, 400).half_width(), 200.0);
+        let Arena::Hex(hex) = ArenaShape::Hex.fit(800, 400) else {
+            panic!("expected a hexagon");
+        };
+        assert!((hex.apothem() - 200.0).abs() < 1e-3);
+        assert_eq!(ArenaShape::Hex.next().next().next(), ArenaShape::Hex);
+    }
+}
@@ -1,24 +1,247 @@
-static mut CORNER_HITS: u32 = 0;
+//! Corner hits: a ball bouncing off a wall while it is close to the
+//! perpendicular wall too. Each set of balls counts with its own
+//! `CornerTracker`; the main balls use the default one their station keeps,
+//! which the stats text reads. Their hits are published as `Event::CornerHit`.
 
-/// Increment the corner hit counter
-pub fn increment_corner_hit(x: f32, y: f32, width: u32, height: u32) {
-    // corner if x<20 or x>width-20 AND y<20 or y>height-20
-    let is_corner = (x < 20.0 || x > width as f32 - 20.0) && (y < 20.0 || y > height as f32 - 20.0);
-    if is_corner {
-        unsafe {
-            CORNER_HITS += 1;
+use crate::core::events::{self, Event};
+use crate::core::station_state;
+use crate::core::types::{Position, Velocity};
+use crate::physics::physics::WALL_MARGIN;
+
+/// How far past the wall margin a ball may be and still count as in the corner
+pub const CORNER_TOLERANCE: f32 = 10.0;
+/// A corner's contact window closes after this long without a wall contact
+/// in it, even if the ball never turned to leave
+pub const CONTACT_TIMEOUT: f32 = 0.25;
+
+/// Whether (x, y) is close to a side wall and a top or bottom wall at once.
+/// Balls are clamped onto the margin, so a ball touching a wall sits exactly on it.
+pub fn in_corner_zone(x: f32, y: f32, width: u32, height: u32) -> bool {
+    Corner::at(Position::new(x, y), (width, height)).is_some()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Corner {
+    /// The corner whose zone holds `pos`, if any
+    pub fn at(pos: Position, (width, height): (u32, u32)) -> Option<Corner> {
+        let reach = WALL_MARGIN + CORNER_TOLERANCE;
+        let left = pos.x <= reach;
+        let right = pos.x >= width as f32 - reach;
+        let top = pos.y <= reach;
+        let bottom = pos.y >= height as f32 - reach;
+        match (left, right, top, bottom) {
+            (true, _, true, _) => Some(Corner::TopLeft),
+            (_, true, true, _) => Some(Corner::TopRight),
+            (true, _, _, true) => Some(Corner::BottomLeft),
+            (_, true, _, true) => Some(Corner::BottomRight),
+            _ => None,
+        }
+    }
+
+    /// Points out of the corner, away from both of its walls
+    fn outward(self) -> Velocity {
+        match self {
+            Corner::TopLeft => Velocity::new(1.0, 1.0),
+            Corner::TopRight => Velocity::new(-1.0, 1.0),
+            Corner::BottomLeft => Velocity::new(1.0, -1.0),
+            Corner::BottomRight => Velocity::new(-1.0, -1.0),
+        }
+    }
+}
+
+/// Counts corner hits for one set of balls. A hit opens a contact window on
+/// its corner, and further contacts there don't count until the window
+/// closes: when a ball is seen moving away from both walls, or after
+/// `CONTACT_TIMEOUT`. A fast ball whose two axes clamp in different steps is
+/// still heading into the corner after the first, so it counts once.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CornerTracker {
+    hits: u32,
+    /// Seconds advanced so far
+    time: f32,
+    /// When each open window last had a contact, by `Corner as usize`
+    windows: [Option<f32>; 4],
+}
+
+impl Default for CornerTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CornerTracker {
+    pub const fn new() -> Self {
+        Self {
+            hits: 0,
+            time: 0.0,
+            windows: [None; 4],
+        }
+    }
+
+    pub fn hits(&self) -> u32 {
+        self.hits
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    pub fn advance(&mut self, dt: f32) {
+        self.time += dt;
+    }
+
+    /// Records a ball at `pos` that just bounced off a wall of a frame sized
+    /// `bounds`, with `vel` its velocity after the bounce. Returns the corner
+    /// when this is a new hit.
+    pub fn note_wall_contact(
+        &mut self,
+        pos: Position,
+        vel: Velocity,
+        bounds: (u32, u32),
+    ) -> Option<Corner> {
+        let corner = Corner::at(pos, bounds)?;
+        let time = self.time;
+        let window = &mut self.windows[corner as usize];
+        let new_hit = window.is_none_or(|last| time - last > CONTACT_TIMEOUT);
+        let leaving = (vel * corner.outward()).cmpge(Velocity::ZERO).all();
+        *window = (!leaving).then_some(time);
+        if new_hit {
+            self.hits += 1;
         }
+        new_hit.then_some(corner)
+    }
+}
+
+/// Runs `f` on the tracker for the main balls of the station being driven
+pub fn with_default_tracker<R>(f: impl FnOnce(&mut CornerTracker) -> R) -> R {
+    f(&mut station_state::lock(&station_state::current().corners))
+}
+
+/// Counts a wall contact at (x, y) on the default tracker if it is in a corner
+pub fn increment_corner_hit(x: f32, y: f32, width: u32, height: u32) {
+    let hit = with_default_tracker(|tracker| {
+        tracker.note_wall_contact(Position::new(x, y), Velocity::ZERO, (width, height))
+    });
+    if let Some(corner) = hit {
+        events::publish(Event::CornerHit { corner });
     }
 }
 
 /// Reset corner hits counter
 pub fn reset_corner_hits() {
-    unsafe {
-        CORNER_HITS = 0;
-    }
+    with_default_tracker(CornerTracker::reset);
 }
 
 /// Get the total number of corner hits
 pub fn get_corner_hits() -> u32 {
-    unsafe { CORNER_HITS }
+    with_default_tracker(|tracker| tracker.hits())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOUNDS: (u32, u32) = (400, 300);
+
+    /// Moves a ball in steps of `dt`, clamping each axis onto the wall margin
+    /// like the physics does, and notes every wall contact
+    fn fly(tracker: &mut CornerTracker, mut pos: Position, mut vel: Velocity, steps: usize) {
+        let dt = 1.0 / 60.0;
+        for _ in 0..steps {
+            tracker.advance(dt);
+            pos += vel * dt;
+            let mut hit_wall = false;
+            for (p, v, extent) in [
+                (&mut pos.x, &mut vel.x, BOUNDS.0 as f32),
+                (&mut pos.y, &mut vel.y, BOUNDS.1 as f32),
+            ] {
+                if *p < WALL_MARGIN || *p > extent - WALL_MARGIN {
+                    *p = p.clamp(WALL_MARGIN, extent - WALL_MARGIN);
+                    *v = -*v;
+                    hit_wall = true;
+                }
+            }
+            if hit_wall {
+                tracker.note_wall_contact(pos, vel, BOUNDS);
+            }
+        }
+    }
+
+    #[test]
+    fn test_a_diagonal_corner_bounce_counts_once() {
+        // Reaches the left wall a step before the top one, both inside the
+        // corner zone, then flies back out
+        let mut tracker = CornerTracker::new();
+        fly(
+            &mut tracker,
+            Position::new(65.0, 75.0),
+            Velocity::new(-600.0, -600.0),
+            30,
+        );
+        assert_eq!(tracker.hits(), 1);
+
+        // Bounces off both walls of the same corner in one step
+        let mut tracker = CornerTracker::new();
+        fly(
+            &mut tracker,
+            Position::new(60.0, 60.0),
+            Velocity::new(-600.0, -600.0),
+            30,
+        );
+        assert_eq!(tracker.hits(), 1);
+
+        // Skimming the top wall far from the sides is no corner
+        let mut tracker = CornerTracker::new();
+        fly(
+            &mut tracker,
+            Position::new(200.0, 60.0),
+            Velocity::new(0.0, -600.0),
+            30,
+        );
+        assert_eq!(tracker.hits(), 0);
+    }
+
+    #[test]
+    fn test_trackers_count_independently_and_windows_close() {
+        let mut a = CornerTracker::new();
+        let mut b = CornerTracker::new();
+        let corner = Position::new(WALL_MARGIN, WALL_MARGIN);
+        // Heading up after bouncing off the left wall: the top is still to come
+        let into_top = Velocity::new(100.0, -100.0);
+        assert_eq!(
+            a.note_wall_contact(corner, into_top, BOUNDS),
+            Some(Corner::TopLeft)
+        );
+        assert_eq!(a.note_wall_contact(corner, into_top, BOUNDS), None);
+        assert_eq!(b.hits(), 0);
+        // Another set of balls in the same corner counts on its own tracker
+        let bottom_right = Position::new(380.0, 280.0);
+        assert_eq!(
+            b.note_wall_contact(bottom_right, Velocity::new(-1.0, -1.0), BOUNDS),
+            Some(Corner::BottomRight)
+        );
+        assert_eq!(
+            b.note_wall_contact(corner, into_top, BOUNDS),
+            Some(Corner::TopLeft)
+        );
+
+        // The window closes once the ball is seen leaving...
+        assert_eq!(
+            a.note_wall_contact(corner, Velocity::new(1.0, 1.0), BOUNDS),
+            None
+        );
+        assert!(a.note_wall_contact(corner, into_top, BOUNDS).is_some());
+        // ...or after a while without contacts
+        a.advance(CONTACT_TIMEOUT + 0.01);
+        assert!(a.note_wall_contact(corner, into_top, BOUNDS).is_some());
+        assert_eq!(a.hits(), 3);
+        assert_eq!(b.hits(), 2);
+    }
 }
@@ -0,0 +1,329 @@
+use crate::core::bufpool::{self, PooledBuf};
+use crate::core::persist::{point_from_json, PersistError, PersistentState};
+use crate::core::sim_rng::sim_rng;
+use crate::core::types::{color_to_rgba, Color, Line, Position, Velocity};
+use crate::graphics::draw_ctx::DrawCtx;
+use crate::graphics::render::draw_thick_line;
+use crate::graphics::view::ViewTransform;
+use serde_json::{json, Value};
+
+/// Glow pass width as a multiple of the line width
+const GLOW_WIDTH_FACTOR: f32 = 3.0;
+/// Opacity of the glow pass
+const GLOW_ALPHA: u8 = 90;
+/// Width given to a stored line that doesn't record one
+const DEFAULT_STORED_WIDTH: f32 = 2.5;
+
+/// Lines frozen by paint mode. They never update and are drawn from a cached
+/// buffer under the live World lines. Lines are grouped into strokes, one per
+/// mouse-button hold, so a whole stroke can be undone at once.
+pub struct DrawingLayer {
+    strokes: Vec<Vec<Line>>,
+    stroke_open: bool,
+    buffer: PooledBuf,
+    buffer_size: (u32, u32),
+    buffer_view: ViewTransform,
+    dirty: bool,
+}
+
+impl DrawingLayer {
+    pub fn new() -> Self {
+        Self {
+            strokes: Vec::new(),
+            stroke_open: false,
+            buffer: bufpool::get_buffer("drawing layer", 0),
+            buffer_size: (0, 0),
+            buffer_view: ViewTransform::IDENTITY,
+            dirty: false,
+        }
+    }
+
+    /// Starts a new stroke; lines added until `end_stroke` belong to it
+    pub fn begin_stroke(&mut self) {
+        self.end_stroke();
+        self.strokes.push(Vec::new());
+        self.stroke_open = true;
+    }
+
+    /// Adds a frozen line to the open stroke, opening one if needed
+    pub fn add_line(&mut self, line: Line) {
+        if !self.stroke_open {
+            self.begin_stroke();
+        }
+        if let Some(stroke) = self.strokes.last_mut() {
+            stroke.push(line);
+        }
+        self.dirty = true;
+    }
+
+    /// Closes the open stroke. A stroke without lines is discarded.
+    pub fn end_stroke(&mut self) {
+        if self.stroke_open && self.strokes.last().is_some_and(|s| s.is_empty()) {
+            self.strokes.pop();
+        }
+        self.stroke_open = false;
+    }
+
+    /// Removes the most recent stroke. Returns false when there is nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        self.end_stroke();
+        let removed = self.strokes.pop().is_some();
+        self.dirty |= removed;
+        removed
+    }
+
+    pub fn clear(&mut self) {
+        self.strokes.clear();
+        self.stroke_open = false;
+        self.dirty = true;
+    }
+
+    pub fn stroke_count(&self) -> usize {
+        self.strokes.len()
+    }
+
+    /// Lines in each stroke, oldest stroke first
+    pub fn strokes(&self) -> &[Vec<Line>] {
+        &self.strokes
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.strokes.iter().map(Vec::len).sum()
+    }
+
+    /// Copies the drawing, seen through `view`, into `frame`, re-rendering the
+    /// cached layer only when strokes, the frame size, or the view changed.
+    pub fn composite(
+        &mut self,
+        frame: &mut [u8],
+        width: u32,
+        height: u32,
+        x_offset: usize,
+        buffer_width: u32,
+        view: &ViewTransform,
+    ) {
+        if self.strokes.is_empty() {
+            return;
+        }
+        if self.dirty || self.buffer_size != (width, height) || self.buffer_view != *view {
+            self.render(width, height, view);
+        }
+        for y in 0..height as usize {
+            for x in 0..width as usize {
+                let src = 4 * (y * width as usize + x);
+                if self.buffer[src + 3] == 0 {
+                    continue;
+                }
+                let dst = 4 * (y * buffer_width as usize + x + x_offset);
+                if dst + 3 < frame.len() {
+                    frame[dst..dst + 4].copy_from_slice(&self.buffer[src..src + 4]);
+                }
+            }
+        }
+    }
+
+    /// Draws every frozen line into the cached buffer: a wide dim glow, then the core
+    fn render(&mut self, width: u32, height: u32, view: &ViewTransform) {
+        self.buffer.reset((width * height * 4) as usize);
+        for line in self.strokes.iter().flatten() {
+            let (a, b) = (view.to_screen(line.pos[0]), view.to_screen(line.pos[1]));
+            let line_width = line.width * view.zoom;
+            let glow_width = line_width * GLOW_WIDTH_FACTOR;
+            if !ViewTransform::segment_visible(a, b, glow_width, width, height) {
+                continue;
+            }
+            let color = color_to_rgba(line.color);
+            let glow = [color[0], color[1], color[2], GLOW_ALPHA];
+            let passes = [(glow_width, glow), (line_width, color)];
+            let mut ctx = DrawCtx::from_legacy(&mut self.buffer[..], width, height, 0.0, 0, width);
+            for (thickness, color) in passes {
+                draw_thick_line(
+                    &mut ctx,
+                    (a.x as i32, a.y as i32),
+                    (b.x as i32, b.y as i32),
+                    thickness,
+                    &color,
+                );
+            }
+        }
+        self.buffer_size = (width, height);
+        self.buffer_view = *view;
+        self.dirty = false;
+    }
+}
+
+fn line_to_json(line: &Line) -> Value {
+    json!({
+        "from": [line.pos[0].x, line.pos[0].y],
+        "to": [line.pos[1].x, line.pos[1].y],
+        "color": [line.color.red, line.color.green, line.color.blue],
+        "width": line.width,
+    })
+}
+
+/// A frozen line from a stored `{from, to, color, width}` object; only the
+/// end points are required
+fn line_from_json(value: &Value) -> Result<Line, PersistError> {
+    let end = |key| {
+        value
+            .get(key)
+            .and_then(point_from_json)
+            .map(|(x, y)| Position::new(x, y))
+            .ok_or(PersistError::Invalid(
+                "stroke line needs `from` and `to` points",
+            ))
+    };
+    let (from, to) = (end("from")?, end("to")?);
+    let channel = |i: usize| {
+        value["color"]
+            .get(i)
+            .and_then(Value::as_u64)
+            .map_or(255, |c| c.min(255) as u8)
+    };
+    let mut line = Line::new(&mut sim_rng());
+    line.pos = [from, to];
+    line.vel = [Velocity::ZERO; 2];
+    line.length = from.distance(to);
+    line.color = Color::new(channel(0), channel(1), channel(2));
+    line.width = value["width"]
+        .as_f64()
+        .map_or(DEFAULT_STORED_WIDTH, |w| w as f32);
+    Ok(line)
+}
+
+/// Stored as a list of strokes, each a list of lines
+impl PersistentState for DrawingLayer {
+    fn save_state(&self) -> Value {
+        self.strokes
+            .iter()
+            .map(|stroke| stroke.iter().map(line_to_json).collect::<Value>())
+            .collect()
+    }
+
+    fn load_state(&mut self, blob: &Value) -> Result<(), PersistError> {
+        let invalid = || PersistError::Invalid("strokes must be a list of line lists");
+        let strokes = blob
+            .as_array()
+            .ok_or_else(invalid)?
+            .iter()
+            .map(|stroke| match stroke.as_array() {
+                Some(lines) => lines.iter().map(line_from_json).collect(),
+                None => Err(invalid()),
+            })
+            .collect::<Result<Vec<Vec<Line>>, _>>()?;
+        self.strokes = strokes.into_iter().filter(|s| !s.is_empty()).collect();
+        self.stroke_open = false;
+        self.dirty = true;
+        Ok(())
+    }
+}
+
+impl Default for DrawingLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::types::{Color, Position};
+
+    fn line(x: f32) -> Line {
+        let mut line = Line::new(&mut rand::thread_rng());
+        line.pos = [Position::new(x, 10.0), Position::new(x, 40.0)];
+        line.color = Color::new(200, 40, 40);
+        line.width = 2.0;
+        line
+    }
+
+    #[test]
+    fn test_lines_group_into_strokes() {
+        let mut layer = DrawingLayer::new();
+        layer.begin_stroke();
+        layer.add_line(line(10.0));
+        layer.add_line(line(12.0));
+        layer.end_stroke();
+        layer.begin_stroke();
+        layer.add_line(line(30.0));
+        layer.end_stroke();
+
+        // A press with no movement leaves no empty stroke behind
+        layer.begin_stroke();
+        layer.end_stroke();
+
+        assert_eq!(layer.stroke_count(), 2);
+        assert_eq!(layer.strokes()[0].len(), 2);
+        assert_eq!(layer.line_count(), 3);
+    }
+
+    #[test]
+    fn test_undo_removes_newest_stroke_first() {
+        let mut layer = DrawingLayer::new();
+        for x in [10.0, 20.0, 30.0] {
+            layer.begin_stroke();
+            layer.add_line(line(x));
+            layer.end_stroke();
+        }
+
+        assert!(layer.undo());
+        assert_eq!(layer.strokes().last().unwrap()[0].pos[0].x, 20.0);
+        assert!(layer.undo());
+        assert_eq!(layer.strokes().last().unwrap()[0].pos[0].x, 10.0);
+        assert!(layer.undo());
+        assert!(!layer.undo());
+    }
+
+    #[test]
+    fn test_composite_follows_undo() {
+        let (width, height) = (50, 50);
+        let mut layer = DrawingLayer::new();
+        layer.add_line(line(20.0));
+        layer.end_stroke();
+
+        let mut frame = vec![0u8; (width * height * 4) as usize];
+        layer.composite(
+            &mut frame,
+            width,
+            height,
+            0,
+            width,
+            &ViewTransform::IDENTITY,
+        );
+        let idx = 4 * (25 * width as usize + 20);
+        assert_eq!(frame[idx], 200);
+
+        layer.undo();
+        let mut frame = vec![0u8; (width * height * 4) as usize];
+        layer.composite(
+            &mut frame,
+            width,
+            height,
+            0,
+            width,
+            &ViewTransform::IDENTITY,
+        );
+        assert_eq!(frame[idx], 0);
+    }
+
+    #[test]
+    fn test_rerendering_reuses_the_layer_buffer() {
+        let (width, height) = (64, 64);
+        let mut layer = DrawingLayer::new();
+        let mut frame = vec![0u8; (width * height * 4) as usize];
+        for i in 0..50 {
+            // Every stroke dirties the cache, so each composite re-renders it
+            layer.add_line(line(10.0 + i as f32 % 40.0));
+            layer.end_stroke();
+            layer.composite(
+                &mut frame,
+                width,
+                height,
+                0,
+                width,
+                &ViewTransform::IDENTITY,
+            );
+        }
+        assert_eq!(layer.buffer.allocations(), 1);
+    }
+}
@@ -0,0 +1,540 @@
+//! Short-lived particle bursts drawn over the scene, such as the celebration
+//! when an edge sorter finishes or an explosion set off with E. Finished
+//! sorters reach it through the event bus; a spawn budget that refills over
+//! time keeps simultaneous bursts from flooding the frame.
+
+use crate::core::events::{self, Event};
+use crate::core::sim_rng::sim_rng;
+use crate::core::station_state;
+use crate::core::types::{color_to_rgba, Color, Particle, Position, Velocity};
+use crate::graphics::draw_ctx::DrawCtx;
+use crate::graphics::render::draw_circle_aa;
+use crate::text::text_rendering;
+use rand::Rng;
+use std::f32::consts::{FRAC_PI_2, FRAC_PI_3, TAU};
+use std::fmt;
+use std::sync::{Mutex, PoisonError};
+
+/// Particles each burst point sends out
+pub const PARTICLES_PER_POINT: usize = 10;
+/// Most particles that can be spawned at once; also how many the budget
+/// refills per second
+pub const SPAWN_BUDGET: f32 = 240.0;
+/// Upper bound on live particles
+pub const MAX_PARTICLES: usize = 400;
+/// Pixels per second a burst particle starts at, before jitter
+const BURST_SPEED: f32 = 180.0;
+/// Slows particles by this factor per second
+const DRAG: f32 = 0.15;
+const GRAVITY: f32 = 220.0;
+/// Particles an explosion asks for, before the budget and cap
+pub const EXPLOSION_PARTICLES: usize = 200;
+/// Distance from the center the Ring pattern starts at
+const RING_RADIUS: f32 = 30.0;
+/// Turns the Spiral pattern winds through from its slowest particle to its fastest
+const SPIRAL_TURNS: f32 = 2.0;
+/// Size in px the Text pattern's character is rasterized at
+const TEXT_SIZE: f32 = 180.0;
+/// Width of the band inside the character's edge that Text particles start on
+const TEXT_STROKE: u32 = 3;
+/// Text particles move away from the center at this many times their offset
+/// per second, so the character grows without losing its shape
+const TEXT_EXPANSION: f32 = 0.5;
+
+/// A burst of particles from each of `points`, in `color`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Burst {
+    pub points: Vec<(f32, f32)>,
+    pub color: Color,
+}
+
+/// How an explosion's particles start out. Every pattern's velocities are
+/// its positions scaled or a uniform spread, so gravity and drag, which act
+/// on all particles alike, move the shape without breaking it up.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExplosionPattern {
+    /// Random directions and speeds from one point
+    Radial,
+    /// A circle that grows evenly
+    Ring,
+    /// Within half of the spread either side of the direction, both in radians
+    Cone(f32, f32),
+    /// An arm that unwinds as it flies out, slowest particles innermost
+    Spiral,
+    /// The outline of a character, which holds for a moment as it grows
+    Text(char),
+}
+
+impl ExplosionPattern {
+    /// The patterns Shift+E steps through
+    pub const CYCLE: [ExplosionPattern; 5] = [
+        ExplosionPattern::Radial,
+        ExplosionPattern::Ring,
+        ExplosionPattern::Cone(-FRAC_PI_2, FRAC_PI_3),
+        ExplosionPattern::Spiral,
+        ExplosionPattern::Text('S'),
+    ];
+
+    pub fn color(&self) -> Color {
+        match self {
+            ExplosionPattern::Radial => Color::new(255, 170, 60),
+            ExplosionPattern::Ring => Color::new(90, 200, 255),
+            ExplosionPattern::Cone(..) => Color::new(255, 90, 120),
+            ExplosionPattern::Spiral => Color::new(170, 120, 255),
+            ExplosionPattern::Text(_) => Color::new(240, 240, 255),
+        }
+    }
+
+    /// The pattern after this one in `CYCLE`. A pattern set from elsewhere
+    /// counts as the cycle's entry of the same kind.
+    pub fn next(&self) -> ExplosionPattern {
+        let kind = std::mem::discriminant(self);
+        let index = Self::CYCLE
+            .iter()
+            .position(|pattern| std::mem::discriminant(pattern) == kind)
+            .unwrap_or(0);
+        Self::CYCLE[(index + 1) % Self::CYCLE.len()]
+    }
+
+    /// Starting positions and velocities for up to `count` particles around
+    /// `center`. Text gives fewer when its outline has fewer pixels.
+    pub fn launches(
+        &self,
+        center: Position,
+        count: usize,
+        rng: &mut impl Rng,
+    ) -> Vec<(Position, Velocity)> {
+        let direction = |angle: f32| Velocity::new(angle.cos(), angle.sin());
+        match *self {
+            ExplosionPattern::Radial => (0..count)
+                .map(|_| {
+                    let angle = rng.gen_range(0.0..TAU);
+                    (
+                        center,
+                        direction(angle) * BURST_SPEED * rng.gen_range(0.4..1.0),
+                    )
+                })
+                .collect(),
+            ExplosionPattern::Ring => (0..count)
+                .map(|i| {
+                    let out = direction(TAU * i as f32 / count as f32);
+                    (center + out * RING_RADIUS, out * BURST_SPEED)
+                })
+                .collect(),
+            ExplosionPattern::Cone(heading, spread) => (0..count)
+                .map(|_| {
+                    let angle = heading + spread * rng.gen_range(-0.5..=0.5);
+                    (
+                        center,
+                        direction(angle) * BURST_SPEED * rng.gen_range(0.6..1.2),
+                    )
+                })
+                .collect(),
+            ExplosionPattern::Spiral => (0..count)
+                .map(|i| {
+                    let along = i as f32 / count as f32;
+                    let speed = BURST_SPEED * (0.2 + 0.8 * along);
+                    (center, direction(along * SPIRAL_TURNS * TAU) * speed)
+                })
+                .collect(),
+            ExplosionPattern::Text(c) => outline_launches(
+                &text_rendering::glyph_outline_points(c, TEXT_SIZE, TEXT_STROKE),
+                center,
+                count,
+            ),
+        }
+    }
+}
+
+impl fmt::Display for ExplosionPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExplosionPattern::Radial => write!(f, "Radial"),
+            ExplosionPattern::Ring => write!(f, "Ring"),
+            ExplosionPattern::Cone(..) => write!(f, "Cone"),
+            ExplosionPattern::Spiral => write!(f, "Spiral"),
+            ExplosionPattern::Text(c) => write!(f, "Text '{}'", c),
+        }
+    }
+}
+
+/// Up to `count` of the outline `points`, taken evenly across them so the
+/// whole shape is drawn, placed around `center` and drifting away from it
+fn outline_launches(
+    points: &[(f32, f32)],
+    center: Position,
+    count: usize,
+) -> Vec<(Position, Velocity)> {
+    let taken = count.min(points.len());
+    (0..taken)
+        .map(|i| {
+            let (x, y) = points[i * points.len() / taken];
+            let offset = Velocity::new(x, y);
+            (center + offset, offset * TEXT_EXPANSION)
+        })
+        .collect()
+}
+
+#[derive(Debug)]
+pub struct Fireworks {
+    particles: Vec<Particle>,
+    /// Particles that may still be spawned before the budget refills
+    budget: f32,
+    last_time: Option<f32>,
+}
+
+impl Fireworks {
+    pub fn new() -> Self {
+        Self {
+            particles: Vec::new(),
+            budget: SPAWN_BUDGET,
+            last_time: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.particles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.particles.is_empty()
+    }
+
+    /// Particles that may be spawned right now, within both the budget and the cap
+    fn room(&self) -> usize {
+        MAX_PARTICLES
+            .saturating_sub(self.particles.len())
+            .min(self.budget as usize)
+    }
+
+    /// Spawns `burst` as far as the budget and particle cap allow. Points
+    /// take turns, so a clipped burst still spreads along its whole length.
+    /// Returns how many particles were spawned.
+    pub fn spawn(&mut self, burst: &Burst, rng: &mut impl Rng) -> usize {
+        let wanted = burst.points.len() * PARTICLES_PER_POINT;
+        let count = wanted.min(self.room());
+        self.budget -= count as f32;
+        for i in 0..count {
+            let (x, y) = burst.points[i % burst.points.len()];
+            let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+            let speed = BURST_SPEED * rng.gen_range(0.4..1.0);
+            self.particles.push(Particle {
+                pos: Position::new(x, y),
+                vel: Velocity::new(angle.cos(), angle.sin()) * speed,
+                color: burst.color,
+                life: rng.gen_range(0.6..1.2),
+                size: rng.gen_range(1.5..3.0),
+            });
+        }
+        count
+    }
+
+    /// Sets off `pattern` at `center` with as many particles as the budget
+    /// and cap allow. Returns how many were spawned.
+    pub fn explode(
+        &mut self,
+        pattern: &ExplosionPattern,
+        center: Position,
+        rng: &mut impl Rng,
+    ) -> usize {
+        let launches = pattern.launches(center, EXPLOSION_PARTICLES.min(self.room()), rng);
+        self.budget -= launches.len() as f32;
+        let color = pattern.color();
+        for &(pos, vel) in &launches {
+            self.particles.push(Particle {
+                pos,
+                vel,
+                color,
+                life: rng.gen_range(1.2..1.5),
+                size: rng.gen_range(1.5..2.5),
+            });
+        }
+        launches.len()
+    }
+
+    /// Moves and ages the particles by `dt` seconds and refills the budget
+    pub fn update(&mut self, dt: f32) {
+        self.budget = (self.budget + SPAWN_BUDGET * dt).min(SPAWN_BUDGET);
+        let drag = DRAG.powf(dt);
+        for particle in &mut self.particles {
+            particle.vel.y += GRAVITY * dt;
+            particle.vel *= drag;
+            particle.pos += particle.vel * dt;
+            particle.life -= dt;
+        }
+        self.particles.retain(|p| p.life > 0.0);
+    }
+
+    /// Scales positions, speeds, and sizes by `factor`
+    pub fn scale(&mut self, factor: f32) {
+        for particle in &mut self.particles {
+            particle.pos *= factor;
+            particle.vel *= factor;
+            particle.size *= factor;
+        }
+    }
+
+    /// Draws the particles, fading them out over their last second
+    pub fn draw(&self, ctx: &mut DrawCtx) {
+        for particle in &self.particles {
+            let mut color = color_to_rgba(particle.color);
+            color[3] = (particle.life.clamp(0.0, 1.0) * 255.0) as u8;
+            draw_circle_aa(ctx, particle.pos.x, particle.pos.y, particle.size, &color);
+        }
+    }
+}
+
+impl Default for Fireworks {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+static EXPLOSION_PATTERN: Mutex<ExplosionPattern> = Mutex::new(ExplosionPattern::Radial);
+
+/// Runs `f` on the bursts of the station being driven
+fn with_fireworks<R>(f: impl FnOnce(&mut Option<Fireworks>) -> R) -> R {
+    let state = station_state::current();
+    let mut fireworks = station_state::lock(&state.fireworks);
+    f(&mut fireworks)
+}
+
+/// The pattern E sets off
+pub fn explosion_pattern() -> ExplosionPattern {
+    *EXPLOSION_PATTERN
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+pub fn set_explosion_pattern(pattern: ExplosionPattern) {
+    *EXPLOSION_PATTERN
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner) = pattern;
+}
+
+/// Moves to the next pattern in `ExplosionPattern::CYCLE` and returns it
+pub fn cycle_explosion_pattern() -> ExplosionPattern {
+    let pattern = explosion_pattern().next();
+    set_explosion_pattern(pattern);
+    pattern
+}
+
+/// Sets off the selected pattern at (x, y), to appear on the next
+/// `update_and_draw_fireworks`
+pub fn explode_at(x: f32, y: f32) {
+    let pattern = explosion_pattern();
+    with_fireworks(|fireworks| {
+        fireworks.get_or_insert_with(Fireworks::new).explode(
+            &pattern,
+            Position::new(x, y),
+            &mut sim_rng(),
+        )
+    });
+    events::publish(Event::Explosion { x, y });
+}
+
+/// Burst particles still alive
+pub fn particle_count() -> usize {
+    with_fireworks(|fireworks| fireworks.as_ref().map_or(0, Fireworks::len))
+}
+
+/// Queues `bursts` to appear on the next `update_and_draw_fireworks`
+pub fn launch(bursts: &[Burst]) {
+    if bursts.is_empty() {
+        return;
+    }
+    with_fireworks(|fireworks| {
+        let fireworks = fireworks.get_or_insert_with(Fireworks::new);
+        let mut rng = sim_rng();
+        for burst in bursts {
+            fireworks.spawn(burst, &mut rng);
+        }
+    });
+}
+
+/// Pops a burst along each edge a sorter finishes, in its algorithm's color
+pub fn on_event(event: &Event, _time: f32) {
+    if let Event::SorterCompleted {
+        algorithm, points, ..
+    } = event
+    {
+        launch(&[Burst {
+            points: points.clone(),
+            color: algorithm.color(),
+        }]);
+    }
+}
+
+/// Drops every live burst
+pub fn clear_fireworks() {
+    with_fireworks(|fireworks| *fireworks = None);
+}
+
+/// Scales live bursts to a frame `factor` times the size
+pub fn scale_fireworks(factor: f32) {
+    with_fireworks(|fireworks| {
+        if let Some(fireworks) = fireworks.as_mut() {
+            fireworks.scale(factor);
+        }
+    });
+}
+
+/// Advances and draws any live bursts into the context's region
+pub fn update_and_draw_fireworks(ctx: &mut DrawCtx) {
+    with_fireworks(|fireworks| {
+        let Some(fireworks) = fireworks.as_mut() else {
+            return;
+        };
+        let dt = match fireworks.last_time {
+            Some(last) => (ctx.time - last).clamp(0.0, 0.1),
+            None => 0.016,
+        };
+        fireworks.last_time = Some(ctx.time);
+        fireworks.update(dt);
+        fireworks.draw(ctx);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn burst(x: f32) -> Burst {
+        Burst {
+            points: (0..8).map(|i| (x, i as f32 * 10.0)).collect(),
+            color: Color::new(255, 0, 0),
+        }
+    }
+
+    #[test]
+    fn test_simultaneous_bursts_stay_within_the_budget() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut fireworks = Fireworks::new();
+        // A single burst fits whole
+        assert_eq!(
+            fireworks.spawn(&burst(0.0), &mut rng),
+            8 * PARTICLES_PER_POINT
+        );
+
+        // A thousand edges finishing at once don't spawn thousands of particles
+        let spawned: usize = (0..1000)
+            .map(|i| fireworks.spawn(&burst(i as f32), &mut rng))
+            .sum();
+        assert!(fireworks.len() <= SPAWN_BUDGET as usize);
+        assert_eq!(spawned + 8 * PARTICLES_PER_POINT, fireworks.len());
+
+        // The budget comes back over time but the live count stays capped
+        for _ in 0..30 {
+            fireworks.update(0.02);
+            for i in 0..100 {
+                fireworks.spawn(&burst(i as f32), &mut rng);
+            }
+            assert!(fireworks.len() <= MAX_PARTICLES);
+        }
+    }
+
+    #[test]
+    fn test_burst_spreads_over_every_point_and_fades() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut fireworks = Fireworks::new();
+        fireworks.budget = 16.0;
+        let line = burst(50.0);
+        assert_eq!(fireworks.spawn(&line, &mut rng), 16);
+        // Clipped to 16 particles, every one of the 8 points still gets two
+        for &(x, y) in &line.points {
+            let from_point = fireworks
+                .particles
+                .iter()
+                .filter(|p| p.pos == Position::new(x, y))
+                .count();
+            assert_eq!(from_point, 2);
+        }
+        assert!(fireworks.particles.iter().all(|p| p.color == line.color));
+
+        for _ in 0..100 {
+            fireworks.update(0.02);
+        }
+        assert!(fireworks.is_empty());
+    }
+
+    #[test]
+    fn test_explosion_patterns_start_where_they_should() {
+        let mut rng = StdRng::seed_from_u64(11);
+        let center = Position::new(400.0, 300.0);
+        let speed = |v: Velocity| v.length();
+
+        let ring = ExplosionPattern::Ring.launches(center, 60, &mut rng);
+        assert_eq!(ring.len(), 60);
+        for &(pos, vel) in &ring {
+            assert!(((pos - center).length() - RING_RADIUS).abs() < 1e-3);
+            // Straight outward, all at the same speed
+            assert!((pos - center).normalize().dot(vel.normalize()) > 0.999);
+            assert!((speed(vel) - BURST_SPEED).abs() < 1e-3);
+        }
+
+        let (heading, spread) = (-FRAC_PI_2, FRAC_PI_3);
+        let cone = ExplosionPattern::Cone(heading, spread).launches(center, 500, &mut rng);
+        let angles: Vec<f32> = cone.iter().map(|(_, vel)| vel.y.atan2(vel.x)).collect();
+        assert!(cone.iter().all(|&(pos, _)| pos == center));
+        assert!(angles
+            .iter()
+            .all(|angle| (angle - heading).abs() <= spread / 2.0 + 1e-4));
+        // ...and it fills the cone rather than a sliver of it
+        let widest = angles
+            .iter()
+            .map(|a| (a - heading).abs())
+            .fold(0.0, f32::max);
+        assert!(widest > spread / 2.0 * 0.9);
+
+        // The spiral's particles speed up as it winds outward
+        let spiral = ExplosionPattern::Spiral.launches(center, 100, &mut rng);
+        assert!(spiral
+            .windows(2)
+            .all(|pair| speed(pair[1].1) > speed(pair[0].1)));
+
+        // Text keeps to the outline it is given, evenly across it, and grows
+        // from the center without changing shape
+        let outline: Vec<(f32, f32)> = (0..40).map(|i| (i as f32 - 20.0, 10.0)).collect();
+        let text = outline_launches(&outline, center, 10);
+        assert_eq!(text.len(), 10);
+        for (i, &(pos, vel)) in text.iter().enumerate() {
+            let (x, y) = outline[i * 4];
+            assert_eq!(pos, center + Position::new(x, y));
+            assert_eq!(vel, (pos - center) * TEXT_EXPANSION);
+        }
+        assert_eq!(outline_launches(&outline, center, 100).len(), 40);
+        assert!(outline_launches(&[], center, 100).is_empty());
+    }
+
+    #[test]
+    fn test_explosions_respect_the_cap_and_cycle() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let mut fireworks = Fireworks::new();
+        let center = Position::new(100.0, 100.0);
+        assert_eq!(
+            fireworks.explode(&ExplosionPattern::Radial, center, &mut rng),
+            EXPLOSION_PARTICLES
+        );
+        // Mashing E spends the budget, then waits for it
+        let spawned: usize = (0..10)
+            .map(|_| fireworks.explode(&ExplosionPattern::Ring, center, &mut rng))
+            .sum();
+        assert_eq!(spawned, SPAWN_BUDGET as usize - EXPLOSION_PARTICLES);
+        for _ in 0..50 {
+            fireworks.update(0.02);
+            fireworks.explode(&ExplosionPattern::Spiral, center, &mut rng);
+            assert!(fireworks.len() <= MAX_PARTICLES);
+        }
+
+        let mut pattern = ExplosionPattern::CYCLE[0];
+        for expected in ExplosionPattern::CYCLE.iter().cycle().skip(1).take(6) {
+            pattern = pattern.next();
+            assert_eq!(pattern, *expected);
+        }
+        // A pattern set with other settings moves on like its cycle entry
+        assert_eq!(ExplosionPattern::Text('Q').next(), ExplosionPattern::Radial);
+        assert_eq!(ExplosionPattern::Text('Q').to_string(), "Text 'Q'");
+    }
+}
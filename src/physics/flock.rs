@@ -0,0 +1,432 @@
+//! Boids for the World's Flock mode. Each line's midpoint is an agent that
+//! steers away from crowding neighbors, toward their heading, and toward their
+//! center. Neighbors come from a spatial hash, a few per agent at most, and
+//! the cursor either scatters the flock or draws it in.
+
+use crate::core::types::{Line, Position, Velocity};
+use crate::physics::arena::Boundary;
+use crate::physics::sanitize;
+use glam::Vec2;
+use std::collections::HashMap;
+
+/// Distance within which other agents count as neighbors
+pub const NEIGHBOR_RADIUS: f32 = 50.0;
+/// Neighbors closer than this are pushed away
+pub const SEPARATION_RADIUS: f32 = 20.0;
+/// Neighbors considered per agent, so a dense clump stays cheap
+pub const MAX_NEIGHBORS: usize = 12;
+/// Agent speed limits, in pixels per 60 Hz step
+pub const MIN_SPEED: f32 = 1.5;
+pub const MAX_SPEED: f32 = 4.0;
+/// Cap on the combined flocking steer per step
+const MAX_STEER: f32 = 0.2;
+/// Scale alignment and cohesion so a weight of 1 is on par with separation
+const ALIGNMENT_GAIN: f32 = 0.05;
+const COHESION_GAIN: f32 = 0.002;
+/// Reach and strength of the cursor as a predator
+const PREDATOR_RADIUS: f32 = 150.0;
+const PREDATOR_STRENGTH: f32 = 0.6;
+/// Pull of the cursor as an attractor, at any distance
+const ATTRACTOR_STRENGTH: f32 = 0.08;
+/// Agents start turning back this far from a wall
+const EDGE_MARGIN: f32 = 40.0;
+const EDGE_STRENGTH: f32 = 0.15;
+
+/// How strongly each rule steers
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FlockWeights {
+    pub separation: f32,
+    pub alignment: f32,
+    pub cohesion: f32,
+}
+
+impl FlockWeights {
+    pub const DEFAULT: Self = Self {
+        separation: 1.5,
+        alignment: 1.0,
+        cohesion: 1.0,
+    };
+}
+
+impl Default for FlockWeights {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// What the cursor does to the flock
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseRole {
+    Predator,
+    Attractor,
+}
+
+impl MouseRole {
+    pub fn name(&self) -> &'static str {
+        match self {
+            MouseRole::Predator => "predator",
+            MouseRole::Attractor => "attractor",
+        }
+    }
+
+    pub fn toggled(&self) -> Self {
+        match self {
+            MouseRole::Predator => MouseRole::Attractor,
+            MouseRole::Attractor => MouseRole::Predator,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Agent {
+    pub pos: Position,
+    pub vel: Velocity,
+}
+
+impl Agent {
+    /// A line's midpoint, moving at the mean of its ends. A line at rest sets
+    /// off along its own length.
+    pub fn of_line(line: &Line) -> Self {
+        let vel = (line.vel[0] + line.vel[1]) / 2.0;
+        let heading = (line.pos[1] - line.pos[0]).normalize_or(Vec2::X);
+        Self {
+            pos: (line.pos[0] + line.pos[1]) / 2.0,
+            vel: if vel.length_squared() > 1e-6 {
+                vel
+            } else {
+                heading * MIN_SPEED
+            },
+        }
+    }
+}
+
+/// Away from neighbors inside `SEPARATION_RADIUS`, each pushing harder the
+/// closer it is: the sum of offset / distance²
+pub fn separation(agent: &Agent, neighbors: &[Agent]) -> Vec2 {
+    neighbors
+        .iter()
+        .map(|other| agent.pos - other.pos)
+        .filter(|offset| {
+            let dist_sq = offset.length_squared();
+            dist_sq > 0.0 && dist_sq < SEPARATION_RADIUS * SEPARATION_RADIUS
+        })
+        .map(|offset| offset / offset.length_squared())
+        .sum()
+}
+
+/// The neighbors' mean velocity less the agent's own
+pub fn alignment(agent: &Agent, neighbors: &[Agent]) -> Vec2 {
+    if neighbors.is_empty() {
+        return Vec2::ZERO;
+    }
+    let mean = neighbors.iter().map(|other| other.vel).sum::<Vec2>() / neighbors.len() as f32;
+    mean - agent.vel
+}
+
+/// From the agent to the neighbors' center
+pub fn cohesion(agent: &Agent, neighbors: &[Agent]) -> Vec2 {
+    if neighbors.is_empty() {
+        return Vec2::ZERO;
+    }
+    let center = neighbors.iter().map(|other| other.pos).sum::<Vec2>() / neighbors.len() as f32;
+    center - agent.pos
+}
+
+/// `vel` with its speed kept within MIN_SPEED..=MAX_SPEED. A standstill has
+/// no heading to keep, so it sets off along x.
+pub fn limit_speed(vel: Velocity) -> Velocity {
+    if sanitize::safe_normalize(vel) == Velocity::ZERO {
+        return Velocity::X * MIN_SPEED;
+    }
+    vel.clamp_length(MIN_SPEED, MAX_SPEED)
+}
+
+/// Buckets points into square cells one neighbor radius across, so every
+/// neighbor of a point is in its cell or one of the eight around it
+#[derive(Debug, Default)]
+pub struct SpatialHash {
+    cell: f32,
+    cells: HashMap<(i32, i32), Vec<usize>>,
+}
+
+impl SpatialHash {
+    pub fn new(cell: f32) -> Self {
+        Self {
+            cell,
+            cells: HashMap::new(),
+        }
+    }
+
+    fn key(&self, pos: Position) -> (i32, i32) {
+        (
+            (pos.x / self.cell).floor() as i32,
+            (pos.y / self.cell).floor() as i32,
+        )
+    }
+
+    /// Replaces the contents with `points`, indexed by position in the iterator
+    pub fn rebuild(&mut self, points: impl IntoIterator<Item = Position>) {
+        // Keep the buckets' allocations from frame to frame
+        self.cells.values_mut().for_each(Vec::clear);
+        for (index, pos) in points.into_iter().enumerate() {
+            let key = self.key(pos);
+            self.cells.entry(key).or_default().push(index);
+        }
+    }
+
+    /// Indices in the 3x3 block of cells around `pos`
+    pub fn near(&self, pos: Position) -> impl Iterator<Item = usize> + '_ {
+        let (cx, cy) = self.key(pos);
+        (-1..=1)
+            .flat_map(move |dy| (-1..=1).map(move |dx| (cx + dx, cy + dy)))
+            .filter_map(|key| self.cells.get(&key))
+            .flatten()
+            .copied()
+    }
+}
+
+/// The neighbor lookup and the cursor's role, kept between frames
+#[derive(Debug)]
+pub struct Flock {
+    pub mouse: MouseRole,
+    hash: SpatialHash,
+    neighbors: Vec<Agent>,
+}
+
+impl Flock {
+    pub fn new() -> Self {
+        Self {
+            mouse: MouseRole::Predator,
+            hash: SpatialHash::new(NEIGHBOR_RADIUS),
+            neighbors: Vec::with_capacity(MAX_NEIGHBORS),
+        }
+    }
+
+    /// Steering for each agent this step: the three rules weighted and capped,
+    /// then the cursor at `mouse` and the walls of `arena`
+    pub fn steer(
+        &mut self,
+        agents: &[Agent],
+        weights: FlockWeights,
+        mouse: Option<Position>,
+        arena: &impl Boundary,
+    ) -> Vec<Vec2> {
+        self.hash.rebuild(agents.iter().map(|agent| agent.pos));
+        let mut steering = Vec::with_capacity(agents.len());
+        for (index, agent) in agents.iter().enumerate() {
+            self.neighbors.clear();
+            let found = self
+                .hash
+                .near(agent.pos)
+                .filter(|&other| other != index)
+                .map(|other| agents[other])
+                .filter(|other| other.pos.distance_squared(agent.pos) < NEIGHBOR_RADIUS.powi(2))
+                .take(MAX_NEIGHBORS);
+            self.neighbors.extend(found);
+
+            let flocking = separation(agent, &self.neighbors) * weights.separation
+                + alignment(agent, &self.neighbors) * weights.alignment * ALIGNMENT_GAIN
+                + cohesion(agent, &self.neighbors) * weights.cohesion * COHESION_GAIN;
+            let mut steer = flocking.clamp_length_max(MAX_STEER);
+            if let Some(mouse) = mouse {
+                steer += self.mouse_force(agent.pos, mouse);
+            }
+            steer += edge_force(agent.pos, arena);
+            steering.push(steer);
+        }
+        steering
+    }
+
+    fn mouse_force(&self, pos: Position, mouse: Position) -> Vec2 {
+        let offset = pos - mouse;
+        match self.mouse {
+            MouseRole::Predator => {
+                let dist = offset.length();
+                if dist >= PREDATOR_RADIUS {
+                    return Vec2::ZERO;
+                }
+                offset.normalize_or_zero() * (1.0 - dist / PREDATOR_RADIUS) * PREDATOR_STRENGTH
+            }
+            MouseRole::Attractor => -offset.normalize_or_zero() * ATTRACTOR_STRENGTH,
+        }
+    }
+}
+
+impl Default for Flock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Push against the normal of the nearest wall of `arena`, growing as an
+/// agent nears it
+fn edge_force(pos: Position, arena: &impl Boundary) -> Vec2 {
+    let normal = arena.normal_at(pos);
+    // The wall is where a point EDGE_MARGIN out along the normal is put back
+    let wall = arena.project_inside(pos + normal * EDGE_MARGIN);
+    let dist = (wall - pos).dot(normal).clamp(0.0, EDGE_MARGIN);
+    -normal * (1.0 - dist / EDGE_MARGIN) * EDGE_STRENGTH
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::arena::{Circle, Rect};
+
+    fn agent(x: f32, y: f32, vx: f32, vy: f32) -> Agent {
+        Agent {
+            pos: Position::new(x, y),
+            vel: Velocity::new(vx, vy),
+        }
+    }
+
+    #[test]
+    fn test_steering_rules_match_hand_worked_examples() {
+        let me = agent(0.0, 0.0, 1.0, 0.0);
+        let neighbors = [agent(10.0, 0.0, 0.0, 1.0), agent(0.0, -5.0, 0.0, 3.0)];
+        // (-10, 0) / 100 + (0, 5) / 25
+        assert_eq!(separation(&me, &neighbors), Vec2::new(-0.1, 0.2));
+        // Mean velocity (0, 2) less (1, 0)
+        assert_eq!(alignment(&me, &neighbors), Vec2::new(-1.0, 2.0));
+        // Center (5, -2.5)
+        assert_eq!(cohesion(&me, &neighbors), Vec2::new(5.0, -2.5));
+
+        // Past the separation radius only alignment and cohesion see a neighbor
+        let far = [agent(30.0, 40.0, 2.0, 2.0)];
+        assert_eq!(separation(&me, &far), Vec2::ZERO);
+        assert_eq!(cohesion(&me, &far), Vec2::new(30.0, 40.0));
+        assert_eq!(alignment(&me, &[]), Vec2::ZERO);
+    }
+
+    #[test]
+    fn test_walls_push_back_along_the_arena_normal() {
+        let circle = Circle {
+            center: Position::new(400.0, 200.0),
+            radius: 200.0,
+        };
+        // Near the rim on a diagonal, where the frame's edges are far away
+        let outward = Velocity::from_angle(std::f32::consts::FRAC_PI_4);
+        let push = edge_force(circle.center + outward * 190.0, &circle);
+        assert!(push.normalize().dot(-outward) > 0.999, "{:?}", push);
+        assert!((push.length() - EDGE_STRENGTH * 0.75).abs() < 1e-4);
+        let nearer = edge_force(circle.center + outward * 199.0, &circle);
+        assert!(nearer.length() > push.length());
+        assert_eq!(edge_force(circle.center, &circle), Vec2::ZERO);
+    }
+
+    #[test]
+    fn test_hash_finds_neighbors_across_cell_edges() {
+        let mut hash = SpatialHash::new(NEIGHBOR_RADIUS);
+        let points = [
+            Position::new(49.0, 49.0),
+            Position::new(51.0, 51.0),
+            Position::new(-1.0, 20.0),
+            Position::new(400.0, 400.0),
+        ];
+        hash.rebuild(points);
+        let mut near: Vec<usize> = hash.near(points[0]).collect();
+        near.sort();
+        assert_eq!(near, [0, 1, 2]);
+        assert_eq!(hash.near(points[3]).collect::<Vec<_>>(), [3]);
+    }
+
+    /// Thirty agents packed into a cluster, heading every which way
+    fn scrambled_cluster() -> Vec<Agent> {
+        (0..30)
+            .map(|i| Agent {
+                pos: Position::new(400.0 + (i % 6) as f32 * 12.0, 300.0 + (i / 6) as f32 * 12.0),
+                vel: Vec2::from_angle(i as f32 * 2.4) * 2.0,
+            })
+            .collect()
+    }
+
+    fn run(
+        agents: &mut [Agent],
+        flock: &mut Flock,
+        weights: FlockWeights,
+        mouse: Option<Position>,
+    ) {
+        let frame = Rect {
+            min: Position::ZERO,
+            max: Position::new(800.0, 600.0),
+        };
+        let steering = flock.steer(agents, weights, mouse, &frame);
+        for (agent, steer) in agents.iter_mut().zip(steering) {
+            agent.vel = limit_speed(agent.vel + steer);
+            agent.pos += agent.vel;
+        }
+    }
+
+    /// Mean count of neighbors in reach, and how closely each agent's
+    /// neighborhood shares one heading (1 when all agree)
+    fn cohesion_and_order(agents: &[Agent]) -> (f32, f32) {
+        let (mut neighbors, mut order) = (0, 0.0);
+        for agent in agents {
+            let near: Vec<&Agent> = agents
+                .iter()
+                .filter(|other| other.pos.distance(agent.pos) < NEIGHBOR_RADIUS)
+                .collect();
+            neighbors += near.len() - 1;
+            let heading: Vec2 = near.iter().map(|other| other.vel.normalize()).sum();
+            order += heading.length() / near.len() as f32;
+        }
+        let count = agents.len() as f32;
+        (neighbors as f32 / count, order / count)
+    }
+
+    #[test]
+    fn test_flock_forms_groups_and_predator_scatters_them() {
+        // Without the rules the cluster flies apart; with them it settles into
+        // groups that stay together and head the same way
+        let unruled = FlockWeights {
+            separation: 0.0,
+            alignment: 0.0,
+            cohesion: 0.0,
+        };
+        let mut flock = Flock::new();
+        let mut agents = scrambled_cluster();
+        let mut strays = scrambled_cluster();
+        for _ in 0..120 {
+            run(&mut agents, &mut flock, FlockWeights::DEFAULT, None);
+            run(&mut strays, &mut Flock::new(), unruled, None);
+        }
+        let (neighbors, order) = cohesion_and_order(&agents);
+        assert!(
+            neighbors > 4.0 && order > 0.9,
+            "{} neighbors, order {}",
+            neighbors,
+            order
+        );
+        assert!(cohesion_and_order(&strays).0 < 2.0);
+
+        // A predator dropped into the middle of a group breaks it up
+        let group: Vec<usize> = (0..agents.len())
+            .filter(|&i| agents[i].pos.distance(agents[0].pos) < NEIGHBOR_RADIUS)
+            .collect();
+        let spread = |agents: &[Agent]| {
+            let center = group.iter().map(|&i| agents[i].pos).sum::<Vec2>() / group.len() as f32;
+            group
+                .iter()
+                .map(|&i| agents[i].pos.distance(center))
+                .sum::<f32>()
+        };
+        let predator = group.iter().map(|&i| agents[i].pos).sum::<Vec2>() / group.len() as f32;
+        let mut calm = agents.clone();
+        let mut calm_flock = Flock::new();
+        for _ in 0..20 {
+            run(
+                &mut agents,
+                &mut flock,
+                FlockWeights::DEFAULT,
+                Some(predator),
+            );
+            run(&mut calm, &mut calm_flock, FlockWeights::DEFAULT, None);
+        }
+        assert!(
+            spread(&agents) > spread(&calm) * 1.5,
+            "{} vs {}",
+            spread(&agents),
+            spread(&calm)
+        );
+    }
+}
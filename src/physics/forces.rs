@@ -0,0 +1,305 @@
+use crate::core::persist::{PersistError, PersistentState};
+use crate::core::presets::{switch_at_midpoint, Interpolate};
+use crate::core::station_state;
+use crate::core::types::Velocity;
+use crate::graphics::draw_ctx::DrawCtx;
+use crate::graphics::noise::smooth_noise;
+use crate::graphics::render::{draw_filled_circle, draw_thick_line};
+use serde_json::{json, Value};
+
+/// Gravity acceleration, in pixels per 60 Hz step squared
+const GRAVITY_STRENGTH: f32 = 0.15;
+/// Strongest wind gust, in the same units as gravity
+const MAX_WIND_STRENGTH: f32 = 0.08;
+/// How fast the wind direction wanders (noise time per second)
+const WIND_DIRECTION_RATE: f32 = 0.08;
+/// How fast gusts rise and fall (noise time per second)
+const WIND_GUST_RATE: f32 = 0.35;
+/// Fraction of speed kept when bouncing off a wall while gravity is on,
+/// so lines settle instead of bouncing forever
+const GRAVITY_RESTITUTION: f32 = 0.5;
+/// Strongest gust a mouse drag can set off
+const MAX_DRAG_GUST: f32 = 0.3;
+/// Drag length, in pixels, that gives the strongest gust
+const FULL_GUST_DRAG: f32 = 300.0;
+/// Time constant of a drag gust fading out, in seconds
+const DRAG_GUST_DECAY: f32 = 0.5;
+/// Length of the wind arrow at full strength
+const INDICATOR_LENGTH: f32 = 28.0;
+
+/// Direction of the global gravity field
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GravityMode {
+    #[default]
+    Off,
+    Down,
+    Up,
+}
+
+impl GravityMode {
+    /// Cycles Off -> Down -> Up -> Off
+    pub fn next(&self) -> Self {
+        match self {
+            GravityMode::Off => GravityMode::Down,
+            GravityMode::Down => GravityMode::Up,
+            GravityMode::Up => GravityMode::Off,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GravityMode::Off => "off",
+            GravityMode::Down => "down",
+            GravityMode::Up => "up",
+        }
+    }
+
+    pub fn acceleration(&self) -> Velocity {
+        match self {
+            GravityMode::Off => Velocity::ZERO,
+            GravityMode::Down => Velocity::new(0.0, GRAVITY_STRENGTH),
+            GravityMode::Up => Velocity::new(0.0, -GRAVITY_STRENGTH),
+        }
+    }
+}
+
+/// Environmental forces applied to everything in the World scene.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ForceField {
+    pub gravity: GravityMode,
+    pub wind: bool,
+    /// Fading push left by a mouse drag
+    pub gust: Velocity,
+}
+
+impl ForceField {
+    /// Wind at `time` seconds: a slowly turning direction with gusting strength.
+    /// Zero when wind is off.
+    pub fn wind_at(&self, time: f32) -> Velocity {
+        if !self.wind {
+            return Velocity::ZERO;
+        }
+        let angle = smooth_noise(time * WIND_DIRECTION_RATE, 3.1) * std::f32::consts::PI;
+        let gust = 0.5 + 0.5 * smooth_noise(time * WIND_GUST_RATE, 7.7);
+        Velocity::new(angle.cos(), angle.sin()) * gust * MAX_WIND_STRENGTH
+    }
+
+    /// Total acceleration from gravity and wind, per 60 Hz step squared.
+    /// Callers scale it by their step size to stay frame-rate independent.
+    pub fn acceleration(&self, time: f32) -> Velocity {
+        self.gravity.acceleration() + self.wind_at(time) + self.gust
+    }
+
+    /// Fraction of speed kept by a wall bounce
+    pub fn restitution(&self) -> f32 {
+        if self.gravity == GravityMode::Off {
+            1.0
+        } else {
+            GRAVITY_RESTITUTION
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.wind || self.gravity != GravityMode::Off || self.gust != Velocity::ZERO
+    }
+}
+
+/// Gravity and wind are choices, so they switch at the midpoint
+impl Interpolate for ForceField {
+    fn interpolate(&self, to: &Self, t: f32) -> Self {
+        Self {
+            gravity: switch_at_midpoint(self.gravity, to.gravity, t),
+            wind: switch_at_midpoint(self.wind, to.wind, t),
+            gust: switch_at_midpoint(self.gust, to.gust, t),
+        }
+    }
+}
+
+/// Gravity and wind are kept; a fading drag gust is not worth restoring
+impl PersistentState for ForceField {
+    fn save_state(&self) -> Value {
+        json!({ "gravity": self.gravity.name(), "wind": self.wind })
+    }
+
+    fn load_state(&mut self, blob: &Value) -> Result<(), PersistError> {
+        let gravity = match blob.get("gravity").map(Value::as_str) {
+            None => self.gravity,
+            Some(Some("off")) => GravityMode::Off,
+            Some(Some("down")) => GravityMode::Down,
+            Some(Some("up")) => GravityMode::Up,
+            Some(_) => return Err(PersistError::Invalid("gravity must be off, down, or up")),
+        };
+        let wind = match blob.get("wind") {
+            None => self.wind,
+            Some(wind) => wind
+                .as_bool()
+                .ok_or(PersistError::Invalid("wind must be true or false"))?,
+        };
+        self.gravity = gravity;
+        self.wind = wind;
+        self.gust = Velocity::ZERO;
+        Ok(())
+    }
+}
+
+/// Runs `f` on the force field of the station being driven
+fn with_field<R>(f: impl FnOnce(&mut ForceField) -> R) -> R {
+    let state = station_state::current();
+    let mut field = station_state::lock(&state.force_field);
+    f(&mut field)
+}
+
+pub fn force_field() -> ForceField {
+    with_field(|field| *field)
+}
+
+pub fn set_force_field(field: ForceField) {
+    with_field(|current| *current = field);
+}
+
+/// Moves gravity to its next direction and returns it
+pub fn cycle_gravity() -> GravityMode {
+    with_field(|field| {
+        field.gravity = field.gravity.next();
+        field.gravity
+    })
+}
+
+pub fn toggle_wind() -> bool {
+    with_field(|field| {
+        field.wind = !field.wind;
+        field.wind
+    })
+}
+
+/// Sets off a gust along `drag`, stronger for longer drags. It replaces any
+/// gust still fading from an earlier drag.
+pub fn add_drag_gust(drag: Velocity) {
+    let strength = (drag.length() / FULL_GUST_DRAG).min(1.0) * MAX_DRAG_GUST;
+    with_field(|field| field.gust = drag.normalize_or_zero() * strength);
+}
+
+/// Fades the drag gust over `dt` seconds
+pub fn decay_gust(dt: f32) {
+    with_field(|field| {
+        field.gust *= (-dt / DRAG_GUST_DECAY).exp();
+        if field.gust.length() < 1e-4 {
+            field.gust = Velocity::ZERO;
+        }
+    });
+}
+
+/// Draws a small arrow near the bottom-right corner showing the wind direction,
+/// its length following the current gust strength.
+pub fn draw_wind_indicator(
+    frame: &mut [u8],
+    width: u32,
+    height: u32,
+    time: f32,
+    x_offset: usize,
+    buffer_width: u32,
+) {
+    let field = force_field();
+    if !field.wind {
+        return;
+    }
+    // Inside the corner left free by the edge sorters
+    let cx = width as f32 * 0.85 - 40.0;
+    let cy = height as f32 * 0.95 - 40.0;
+    let wind = field.wind_at(time) / MAX_WIND_STRENGTH;
+    let tip_x = cx + wind.x * INDICATOR_LENGTH;
+    let tip_y = cy + wind.y * INDICATOR_LENGTH;
+    let color = [180, 230, 255, 255];
+
+    draw_filled_circle(
+        frame,
+        width,
+        height,
+        cx as i32,
+        cy as i32,
+        2,
+        &color,
+        x_offset,
+        buffer_width,
+    );
+    let mut ctx = DrawCtx::from_legacy(frame, width, height, time, x_offset, buffer_width);
+    draw_thick_line(
+        &mut ctx,
+        (cx as i32, cy as i32),
+        (tip_x as i32, tip_y as i32),
+        2.0,
+        &color,
+    );
+    let dir = wind.normalize_or_zero();
+    if dir == Velocity::ZERO {
+        return;
+    }
+    // Arrow head: two short strokes swept back from the tip
+    for side in [-1.0, 1.0] {
+        let back = -dir * 8.0 + Velocity::new(-dir.y, dir.x) * side * 5.0;
+        draw_thick_line(
+            &mut ctx,
+            (tip_x as i32, tip_y as i32),
+            ((tip_x + back.x) as i32, (tip_y + back.y) as i32),
+            2.0,
+            &color,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gravity_cycles_and_points_the_right_way() {
+        let mut mode = GravityMode::Off;
+        assert_eq!(mode.acceleration(), Velocity::ZERO);
+        mode = mode.next();
+        assert!(mode.acceleration().y > 0.0);
+        mode = mode.next();
+        assert!(mode.acceleration().y < 0.0);
+        assert_eq!(mode.next(), GravityMode::Off);
+    }
+
+    #[test]
+    fn test_wind_is_bounded_and_shifts_smoothly() {
+        let field = ForceField {
+            gravity: GravityMode::Off,
+            wind: true,
+            gust: Velocity::ZERO,
+        };
+        let mut previous = field.wind_at(0.0);
+        for i in 1..6000 {
+            let wind = field.wind_at(i as f32 / 60.0);
+            assert!(wind.length() <= MAX_WIND_STRENGTH + 1e-6);
+            assert!((wind - previous).length() < MAX_WIND_STRENGTH * 0.1);
+            previous = wind;
+        }
+        assert_eq!(ForceField::default().wind_at(5.0), Velocity::ZERO);
+    }
+
+    #[test]
+    fn test_gravity_damps_bounces() {
+        let mut field = ForceField::default();
+        assert_eq!(field.restitution(), 1.0);
+        field.gravity = GravityMode::Down;
+        assert!(field.restitution() < 1.0);
+    }
+
+    #[test]
+    fn test_drag_gust_follows_drag_and_fades() {
+        add_drag_gust(Velocity::new(1000.0, 0.0));
+        let gust = force_field().gust;
+        assert!((gust.x - MAX_DRAG_GUST).abs() < 1e-6 && gust.y == 0.0);
+
+        add_drag_gust(Velocity::new(0.0, -FULL_GUST_DRAG / 2.0));
+        let gust = force_field().gust;
+        assert!(gust.x == 0.0 && (gust.y + MAX_DRAG_GUST / 2.0).abs() < 1e-6);
+
+        for _ in 0..300 {
+            decay_gust(1.0 / 60.0);
+        }
+        assert_eq!(force_field().gust, Velocity::ZERO);
+    }
+}
@@ -1,2 +1,11 @@
+pub mod arena;
 pub mod detect_corner;
+pub mod drawing;
+pub mod fireworks;
+pub mod flock;
+pub mod forces;
 pub mod physics;
+pub mod sanitize;
+pub mod softbody;
+pub mod waves;
+pub mod world;
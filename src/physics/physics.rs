@@ -1,89 +1,558 @@
 #![allow(unsafe_op_in_unsafe_fn)]
-#![allow(static_mut_refs)]
 
-use crate::audio::audio_handler::get_audio_spectrum;
-use crate::graphics::render::draw_filled_circle;
+use crate::audio::features::FrameFeatures;
+use crate::core::events::{self, Event};
+use crate::core::snapshot::Snapshottable;
+use crate::core::station_state;
+use crate::core::types::{Position, Velocity};
+use crate::graphics::draw_ctx::{DrawCtx, QualitySettings};
+use crate::graphics::render::{draw_filled_circle, draw_filled_circle_subpixel, SubpixelCircle};
+use crate::graphics::screen_shake;
+use crate::graphics::trail::{self, BallTrail};
+use crate::physics::detect_corner::{self, Corner, CornerTracker};
+use crate::physics::sanitize::{self, is_sane};
+use glam::Vec2;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+/// Collision impulses above this produce screen shake
+const SHAKE_IMPULSE_THRESHOLD: f32 = 2.0;
+/// Arcade bounces gain energy on purpose
+const ARCADE_RESTITUTION: f32 = 1.2;
+const ELASTIC_RESTITUTION: f32 = 1.0;
+/// Collisions kept in the event log
+pub const COLLISION_LOG_CAPACITY: usize = 100;
+pub const YELLOW_RAY_COLOR: [u8; 4] = [255, 255, 150, 255];
+pub const GREEN_RAY_COLOR: [u8; 4] = [150, 255, 150, 255];
+const YELLOW_BALL_COLOR: [u8; 4] = [255, 255, 0, 255];
+const GREEN_BALL_COLOR: [u8; 4] = [0, 255, 0, 255];
+/// The green ball's rays sweep this many seconds ahead of the yellow ball's
+pub const GREEN_RAY_TIME_OFFSET: f32 = 0.5;
+/// Balls bounce this far from the frame edge
+pub const WALL_MARGIN: f32 = 20.0;
+/// Ball radius at 1080p before audio scaling
+const BASE_BALL_RADIUS: f32 = 10.0;
+/// Pixels per second a velocity of 1 moves a ball at 1080p
+const BASE_SPEED: f32 = 50.0;
+/// Upper bound on physics substeps per frame, however fast the balls go
+pub const MAX_SUBSTEPS: u32 = 8;
+/// Largest ball radius by default, as a fraction of the frame's shorter side
+pub const DEFAULT_MAX_RADIUS_FRACTION: f32 = 0.15;
+/// Radii below this fraction of the maximum follow the audio exactly; above
+/// it they are compressed so they approach the maximum without reaching it
+const KNEE_FRACTION: f32 = 0.6;
+/// Balls closer than this always collide, so small fast balls can't pass
+/// through each other between substeps
+const MIN_COLLISION_DISTANCE: f32 = 60.0;
+/// Audio scale forced by `set_force_max_ball_scale`, enough to fill the frame uncapped
+const PATHOLOGICAL_AUDIO_SCALE: f32 = 100.0;
+/// Bands are 0..=1; levels past that, or below it, move the balls no further
+const MAX_AUDIO_LEVEL: f32 = 1.0;
+
+/// Largest ball radius as a fraction of the frame's shorter side, as f32 bits
+static MAX_RADIUS_FRACTION: AtomicU32 = AtomicU32::new(DEFAULT_MAX_RADIUS_FRACTION.to_bits());
+static FORCE_MAX_SCALE: AtomicBool = AtomicBool::new(false);
+
+/// How the balls bounce off each other
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CollisionModel {
+    /// Extra bouncy with a deflection term; energy grows over time
+    Arcade,
+    /// Equal-mass elastic collision with no deflection, so runs are reproducible
+    Elastic,
+}
+
+impl CollisionModel {
+    pub const ALL: [CollisionModel; 2] = [CollisionModel::Arcade, CollisionModel::Elastic];
+
+    /// Name used in the settings file
+    pub fn name(&self) -> &'static str {
+        match self {
+            CollisionModel::Arcade => "arcade",
+            CollisionModel::Elastic => "elastic",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|model| model.name() == name)
+    }
+
+    fn restitution(&self) -> f32 {
+        match self {
+            CollisionModel::Arcade => ARCADE_RESTITUTION,
+            CollisionModel::Elastic => ELASTIC_RESTITUTION,
+        }
+    }
+}
+
+/// One ball-to-ball collision
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CollisionEvent {
+    /// Scene time at the end of the substep it happened in
+    pub time: f32,
+    pub yellow_pos: Position,
+    pub green_pos: Position,
+    /// Change in velocity along the collision normal, shared by both balls
+    pub impulse: f32,
+}
+
+static COLLISION_MODEL: Mutex<CollisionModel> = Mutex::new(CollisionModel::Arcade);
+/// Recording and replay need the deterministic model whatever the setting says
+static FORCE_ELASTIC: AtomicBool = AtomicBool::new(false);
+static COLLISION_LOG: AtomicBool = AtomicBool::new(false);
+/// How many times faster than their velocities say the balls move, as f32 bits
+static SPEED_BOOST: AtomicU32 = AtomicU32::new(1.0f32.to_bits());
 
 /// Holds the positions and velocities of both balls.
-struct BallState {
-    yellow_pos: Option<(f32, f32)>,
-    green_pos: Option<(f32, f32)>,
-    yellow_vel: Option<(f32, f32)>,
-    green_vel: Option<(f32, f32)>,
+pub struct BallState {
+    yellow_pos: Option<Position>,
+    green_pos: Option<Position>,
+    yellow_vel: Option<Velocity>,
+    green_vel: Option<Velocity>,
+    /// Positions before the latest update, which drawing interpolates from
+    yellow_prev: Option<Position>,
+    green_prev: Option<Position>,
+    /// How far from the previous positions to the current ones the balls
+    /// are drawn; below 1 only on a fixed timestep
+    interpolation: f32,
     last_time: Option<f32>,
+    yellow_trail: BallTrail,
+    green_trail: BallTrail,
+    /// Radii from the latest audio, used both to draw and to collide
+    yellow_radius: f32,
+    green_radius: f32,
+    collision_model: CollisionModel,
+    speed_boost: f32,
+    /// Most recent collisions, oldest first; None while logging is off
+    collision_log: Option<VecDeque<CollisionEvent>>,
 }
 
-// Single static state object (preferably replaced with a higher-level manager).
-static mut BALL_STATE: Option<BallState> = None;
+impl BallState {
+    fn empty() -> Self {
+        Self {
+            yellow_pos: None,
+            green_pos: None,
+            yellow_vel: None,
+            green_vel: None,
+            yellow_prev: None,
+            green_prev: None,
+            interpolation: 1.0,
+            last_time: None,
+            yellow_trail: BallTrail::new(),
+            green_trail: BallTrail::new(),
+            yellow_radius: BASE_BALL_RADIUS,
+            green_radius: BASE_BALL_RADIUS,
+            collision_model: CollisionModel::Arcade,
+            speed_boost: 1.0,
+            collision_log: None,
+        }
+    }
+
+    /// Distance at which the balls touch
+    fn collision_distance(&self) -> f32 {
+        (self.yellow_radius + self.green_radius).max(MIN_COLLISION_DISTANCE)
+    }
+
+    /// Records both balls' positions into their trails at `time`
+    fn sample_trails(&mut self, time: f32) {
+        if let Some(pos) = self.yellow_pos {
+            self.yellow_trail.sample(pos, time);
+        }
+        if let Some(pos) = self.green_pos {
+            self.green_trail.sample(pos, time);
+        }
+    }
+
+    /// Where the balls are drawn: `interpolation` of the way from their
+    /// previous positions to their current ones
+    fn drawn_positions(&self) -> (Option<Position>, Option<Position>) {
+        let lerp = |prev: Option<Position>, pos: Option<Position>| match (prev, pos) {
+            (Some(prev), Some(pos)) => Some(prev.lerp(pos, self.interpolation)),
+            _ => pos,
+        };
+        (
+            lerp(self.yellow_prev, self.yellow_pos),
+            lerp(self.green_prev, self.green_pos),
+        )
+    }
+
+    /// Moves the yellow ball to (x, y) without a trail back to where it was
+    fn teleport_yellow(&mut self, x: f32, y: f32) {
+        self.yellow_pos = Some(Position::new(x, y));
+        self.yellow_prev = None;
+        self.yellow_trail.clear();
+    }
+
+    fn teleport_green(&mut self, x: f32, y: f32) {
+        self.green_pos = Some(Position::new(x, y));
+        self.green_prev = None;
+        self.green_trail.clear();
+    }
+
+    /// Multiplies positions, velocities, and trails by `factor`
+    fn scale(&mut self, factor: f32) {
+        let times = |v: Option<Vec2>| v.map(|v| v * factor);
+        self.yellow_pos = times(self.yellow_pos);
+        self.green_pos = times(self.green_pos);
+        self.yellow_vel = times(self.yellow_vel);
+        self.green_vel = times(self.green_vel);
+        self.yellow_prev = times(self.yellow_prev);
+        self.green_prev = times(self.green_prev);
+        self.yellow_trail.scale(factor);
+        self.green_trail.scale(factor);
+    }
+
+    fn log_collision(&mut self, event: CollisionEvent) {
+        if let Some(log) = self.collision_log.as_mut() {
+            if log.len() == COLLISION_LOG_CAPACITY {
+                log.pop_front();
+            }
+            log.push_back(event);
+        }
+    }
+}
+
+/// Captured positions and velocities of both balls.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BallSnapshot {
+    pub yellow_pos: Option<Position>,
+    pub green_pos: Option<Position>,
+    pub yellow_vel: Option<Velocity>,
+    pub green_vel: Option<Velocity>,
+}
+
+impl Snapshottable for BallState {
+    type Snapshot = BallSnapshot;
+
+    fn snapshot(&self) -> BallSnapshot {
+        BallSnapshot {
+            yellow_pos: self.yellow_pos,
+            green_pos: self.green_pos,
+            yellow_vel: self.yellow_vel,
+            green_vel: self.green_vel,
+        }
+    }
+
+    fn restore(&mut self, snapshot: &BallSnapshot) {
+        self.yellow_pos = snapshot.yellow_pos;
+        self.green_pos = snapshot.green_pos;
+        self.yellow_vel = snapshot.yellow_vel;
+        self.green_vel = snapshot.green_vel;
+        // The balls jump to the saved positions; their old trails would streak,
+        // and a step from the last frame's time would carry them off again
+        self.yellow_prev = None;
+        self.green_prev = None;
+        self.last_time = None;
+        self.yellow_trail.clear();
+        self.green_trail.clear();
+    }
+}
+
+/// Runs `f` on the balls of the station being driven, None before they are first set up
+fn with_ball_state<R>(f: impl FnOnce(&mut Option<BallState>) -> R) -> R {
+    f(&mut station_state::lock(&station_state::current().balls))
+}
 
 /// Initializes both balls if not already initialized.
 pub fn initialize_balls(width: u32, height: u32, scale_x: f32, scale_y: f32) {
-    unsafe {
-        if BALL_STATE.is_none() {
-            BALL_STATE = Some(BallState {
-                yellow_pos: None,
-                green_pos: None,
-                yellow_vel: None,
-                green_vel: None,
-                last_time: None,
-            });
-        }
-        let state = BALL_STATE.as_mut().unwrap();
+    with_ball_state(|state| {
+        let state = state.get_or_insert_with(BallState::empty);
         if state.yellow_pos.is_none() {
-            let quarter_width = width as f32 / 4.0;
-            let quarter_height = height as f32 / 4.0;
-            let vel_scale = (scale_x + scale_y) / 2.0;
-            let base_vel_x = 1.0 * vel_scale;
-            let base_vel_y = 0.5 * vel_scale;
-            state.yellow_pos = Some((quarter_width * 1.5, quarter_height * 1.5));
-            state.yellow_vel = Some((base_vel_x, base_vel_y));
-            state.green_pos = Some((
-                width as f32 - quarter_width * 1.5,
-                height as f32 - quarter_height * 1.5,
-            ));
-            state.green_vel = Some((-base_vel_x, -base_vel_y));
+            let [(yellow_pos, yellow_vel), (green_pos, green_vel)] =
+                start_states(width, height, scale_x, scale_y);
+            state.yellow_pos = Some(yellow_pos);
+            state.yellow_vel = Some(yellow_vel);
+            state.green_pos = Some(green_pos);
+            state.green_vel = Some(green_vel);
+        }
+    });
+}
+
+/// Where the yellow and green balls start, and how fast
+fn start_states(width: u32, height: u32, scale_x: f32, scale_y: f32) -> [(Position, Velocity); 2] {
+    let size = Vec2::new(width as f32, height as f32);
+    let offset = size / 4.0 * 1.5;
+    let vel_scale = (scale_x + scale_y) / 2.0;
+    let base_vel = Velocity::new(1.0, 0.5) * vel_scale;
+    [(offset, base_vel), (size - offset, -base_vel)]
+}
+
+/// Sends a ball holding NaN, infinity, or far-off coordinates back to where
+/// it started, without a trail. Returns how many balls were reset.
+fn reset_broken_balls(state: &mut BallState, starts: [(Position, Velocity); 2]) -> usize {
+    let [yellow, green] = starts;
+    let mut broken = 0;
+    for (pos, vel, prev, trail, (start_pos, start_vel)) in [
+        (
+            &mut state.yellow_pos,
+            &mut state.yellow_vel,
+            &mut state.yellow_prev,
+            &mut state.yellow_trail,
+            yellow,
+        ),
+        (
+            &mut state.green_pos,
+            &mut state.green_vel,
+            &mut state.green_prev,
+            &mut state.green_trail,
+            green,
+        ),
+    ] {
+        if pos.is_none_or(is_sane) && vel.is_none_or(is_sane) {
+            continue;
+        }
+        *pos = Some(start_pos);
+        *vel = Some(start_vel);
+        *prev = None;
+        trail.clear();
+        broken += 1;
+    }
+    broken
+}
+
+/// Scales the balls to a frame `factor` times the size. Scaling by a power of
+/// two and back is exact, so a one-off larger render leaves them as they were.
+pub fn scale_balls(factor: f32) {
+    with_ball_state(|state| {
+        if let Some(state) = state.as_mut() {
+            state.scale(factor);
+        }
+    });
+}
+
+/// Returns the ball positions for drawing or other logic, interpolated
+/// between physics steps on a fixed timestep.
+pub fn get_ball_positions() -> (Option<Position>, Option<Position>) {
+    with_ball_state(|state| state.as_ref().unwrap().drawn_positions())
+}
+
+/// Sets how far between their last two updates the balls are drawn, 0 to 1
+pub fn set_interpolation(alpha: f32) {
+    with_ball_state(|state| {
+        if let Some(state) = state.as_mut() {
+            state.interpolation = alpha.clamp(0.0, 1.0);
         }
+    });
+}
+
+pub fn max_ball_radius_fraction() -> f32 {
+    f32::from_bits(MAX_RADIUS_FRACTION.load(Ordering::Relaxed))
+}
+
+/// Caps the ball radius at `fraction` of the frame's shorter side
+pub fn set_max_ball_radius_fraction(fraction: f32) {
+    MAX_RADIUS_FRACTION.store(fraction.to_bits(), Ordering::Relaxed);
+}
+
+/// Draws both balls as if the audio were as loud as it gets, for benchmarking
+pub fn set_force_max_ball_scale(enabled: bool) {
+    FORCE_MAX_SCALE.store(enabled, Ordering::SeqCst);
+}
+
+/// The collision model in effect, which is Elastic while forced
+pub fn collision_model() -> CollisionModel {
+    if FORCE_ELASTIC.load(Ordering::Relaxed) {
+        return CollisionModel::Elastic;
+    }
+    configured_collision_model()
+}
+
+/// The model from the settings, whether or not Elastic is being forced
+pub fn configured_collision_model() -> CollisionModel {
+    *COLLISION_MODEL
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+pub fn set_collision_model(model: CollisionModel) {
+    *COLLISION_MODEL
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner) = model;
+}
+
+/// Uses the Elastic model regardless of the setting, for recording and replay
+pub fn set_force_elastic(enabled: bool) {
+    FORCE_ELASTIC.store(enabled, Ordering::Relaxed);
+}
+
+/// Starts or stops keeping the last `COLLISION_LOG_CAPACITY` collisions;
+/// stopping discards them
+pub fn set_collision_log_enabled(enabled: bool) {
+    COLLISION_LOG.store(enabled, Ordering::Relaxed);
+}
+
+/// The logged collisions, oldest first
+pub fn collision_events() -> Vec<CollisionEvent> {
+    with_ball_state(|state| {
+        state
+            .as_ref()
+            .and_then(|state| state.collision_log.as_ref())
+            .map_or_else(Vec::new, |log| log.iter().copied().collect())
+    })
+}
+
+/// `value` unchanged up to the knee at `KNEE_FRACTION` of `limit`, then
+/// easing toward `limit` without reaching it. The curve's slope is continuous
+/// at the knee, so growth slows down instead of stopping dead.
+pub fn soft_knee(value: f32, limit: f32) -> f32 {
+    let knee = limit * KNEE_FRACTION;
+    if value <= knee {
+        return value;
     }
+    let headroom = limit - knee;
+    knee + headroom * (1.0 - (-(value - knee) / headroom).exp())
 }
 
-/// Returns the ball positions for drawing or other logic.
-pub fn get_ball_positions() -> (Option<(f32, f32)>, Option<(f32, f32)>) {
-    unsafe {
-        let state = BALL_STATE.as_ref().unwrap();
-        (state.yellow_pos, state.green_pos)
+/// How much the audio grows a ball: the yellow one follows the highs, the
+/// green one the bass
+fn audio_scale(audio: &FrameFeatures, is_yellow: bool) -> f32 {
+    if FORCE_MAX_SCALE.load(Ordering::SeqCst) {
+        return PATHOLOGICAL_AUDIO_SCALE;
+    }
+    if is_yellow {
+        // Yellow ball responds to high frequencies (top quarter of the bands)
+        let audio_value = sanitize::clamp_finite(audio.band_average(6, 8), 0.0, MAX_AUDIO_LEVEL);
+        // Yellow ball: 10x more expressive scaling (normal level)
+        let enhanced_audio = audio_value.powf(0.5); // Square root for smoother scaling
+        let pulse_factor = (audio_value * 10.0).sin() * 0.3 + 1.0;
+        // Range: 0.2 to 5.0 plus pulsing, before the soft knee
+        ((0.2 + enhanced_audio * 4.8) * pulse_factor).max(0.1)
+    } else {
+        // Green ball responds to bass frequencies (bottom quarter of the bands)
+        let audio_value = sanitize::clamp_finite(audio.band_average(0, 2), 0.0, MAX_AUDIO_LEVEL);
+        // Green ball: extreme responsiveness, compact size
+        // Cube root for even more dramatic response
+        let enhanced_audio = audio_value.powf(0.3);
+        // More intense pulsing than the yellow ball
+        let pulse_factor = (audio_value * 20.0).sin() * 0.8 + 1.0;
+        // Range: 0.3 to 3.0 plus pulsing, before the soft knee
+        ((0.3 + enhanced_audio * 2.7) * pulse_factor).max(0.1)
     }
 }
 
-/// Main update step for physics; updates positions and checks collisions.
-pub fn update_physics(width: u32, height: u32, time: f32, scale_x: f32, scale_y: f32) {
+/// Radius of a ball at `audio_scale`, compressed toward a cap of
+/// `max_fraction` of the frame's shorter side
+fn ball_radius(
+    audio_scale: f32,
+    base_radius: f32,
+    width: u32,
+    height: u32,
+    max_fraction: f32,
+) -> f32 {
+    soft_knee(
+        base_radius * audio_scale,
+        width.min(height) as f32 * max_fraction,
+    )
+}
+
+/// Main update step for physics; sizes the balls from the audio, then
+/// updates positions and checks collisions.
+pub fn update_physics(
+    width: u32,
+    height: u32,
+    time: f32,
+    scale_x: f32,
+    scale_y: f32,
+    audio: &FrameFeatures,
+) {
     initialize_balls(width, height, scale_x, scale_y);
     let dt = calculate_delta_time(time);
-    unsafe {
-        update_ball_position(
-            &mut BALL_STATE.as_mut().unwrap().yellow_pos,
-            &mut BALL_STATE.as_mut().unwrap().yellow_vel,
-            width,
-            height,
-            dt,
-            scale_x,
-            scale_y,
-        );
-        update_ball_position(
-            &mut BALL_STATE.as_mut().unwrap().green_pos,
-            &mut BALL_STATE.as_mut().unwrap().green_vel,
-            width,
-            height,
-            dt,
-            scale_x,
-            scale_y,
-        );
-        handle_ball_collision();
+    let starts = start_states(width, height, scale_x, scale_y);
+    let max_fraction = max_ball_radius_fraction();
+    let speed_boost = f32::from_bits(SPEED_BOOST.load(Ordering::Relaxed));
+    with_ball_state(|state| {
+        let state = state.as_mut().unwrap();
+        // Balls set from outside come in broken quietly; what the step breaks is reported
+        reset_broken_balls(state, starts);
+        let base_radius = BASE_BALL_RADIUS * scale_x.max(scale_y);
+        let [yellow, green] = [true, false].map(|is_yellow| {
+            let scale = audio_scale(audio, is_yellow);
+            ball_radius(scale, base_radius, width, height, max_fraction)
+        });
+        state.yellow_radius = yellow;
+        state.green_radius = green;
+        state.collision_model = collision_model();
+        state.speed_boost = speed_boost;
+        match (COLLISION_LOG.load(Ordering::Relaxed), &state.collision_log) {
+            (true, None) => state.collision_log = Some(VecDeque::new()),
+            (false, Some(_)) => state.collision_log = None,
+            _ => {}
+        }
+        state.yellow_prev = state.yellow_pos;
+        state.green_prev = state.green_pos;
+        detect_corner::with_default_tracker(|corners| {
+            step_balls(state, corners, width, height, dt, scale_x, scale_y)
+        });
+        sanitize::report_produced("balls", reset_broken_balls(state, starts));
+        state.sample_trails(time);
+    });
+}
+
+/// What happened during one physics update
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct StepEvents {
+    substeps: u32,
+    collisions: u32,
+    corner_hits: u32,
+}
+
+/// Integration steps needed so no ball moves more than `max_step` in one,
+/// capped at `MAX_SUBSTEPS`
+fn substep_count(max_displacement: f32, max_step: f32) -> u32 {
+    ((max_displacement / max_step).ceil() as u32).clamp(1, MAX_SUBSTEPS)
+}
+
+/// Advances both balls by `dt`, split into substeps when they move fast so
+/// they can't pass through each other or skip a corner between steps
+fn step_balls(
+    state: &mut BallState,
+    corners: &mut CornerTracker,
+    width: u32,
+    height: u32,
+    dt: f32,
+    scale_x: f32,
+    scale_y: f32,
+) -> StepEvents {
+    let base_speed = BASE_SPEED * (scale_x + scale_y) / 2.0 * state.speed_boost;
+    let fastest = [state.yellow_vel, state.green_vel]
+        .into_iter()
+        .flatten()
+        .map(|vel| vel.length() * base_speed)
+        .fold(0.0, f32::max);
+    let max_step = BASE_BALL_RADIUS * scale_x.max(scale_y) / 2.0;
+    let substeps = substep_count(fastest * dt, max_step);
+    let step_dt = dt / substeps as f32;
+
+    let mut step = StepEvents {
+        substeps,
+        ..StepEvents::default()
+    };
+    // `last_time` is already this frame's time
+    let start_time = state.last_time.unwrap_or(0.0) - dt;
+    for substep in 1..=substeps {
+        corners.advance(step_dt);
+        for (pos, vel) in [
+            (&mut state.yellow_pos, &mut state.yellow_vel),
+            (&mut state.green_pos, &mut state.green_vel),
+        ] {
+            if let Some(corner) =
+                update_ball_position(pos, vel, corners, width, height, step_dt, base_speed)
+            {
+                step.corner_hits += 1;
+                events::publish(Event::CornerHit { corner });
+            }
+        }
+        if handle_ball_collision(state, start_time + step_dt * substep as f32) {
+            step.collisions += 1;
+        }
     }
+    step
 }
 
 fn calculate_delta_time(time: f32) -> f32 {
-    unsafe {
-        let state = BALL_STATE.as_mut().unwrap();
+    with_ball_state(|state| {
+        let state = state.as_mut().unwrap();
         let dt = if let Some(last) = state.last_time {
             let delta = time - last;
             if delta > 0.1 {
@@ -96,102 +565,112 @@ fn calculate_delta_time(time: f32) -> f32 {
         };
         state.last_time = Some(time);
         dt
-    }
+    })
 }
 
+/// Moves one ball and bounces it off the walls. Returns the corner when it
+/// reaches one, as `corners` judges it.
 fn update_ball_position(
-    pos: &mut Option<(f32, f32)>,
-    vel: &mut Option<(f32, f32)>,
+    pos: &mut Option<Position>,
+    vel: &mut Option<Velocity>,
+    corners: &mut CornerTracker,
     width: u32,
     height: u32,
     dt: f32,
-    scale_x: f32,
-    scale_y: f32,
-) {
-    if let (Some(pos), Some(vel)) = (pos.as_mut(), vel.as_mut()) {
-        let speed_scale = (scale_x + scale_y) / 2.0;
-        let base_speed = 50.0 * speed_scale;
-        pos.0 += vel.0 * base_speed * dt;
-        pos.1 += vel.1 * base_speed * dt;
-
-        if pos.0 < 20.0 {
-            pos.0 = 20.0;
-            vel.0 = vel.0.abs();
-            crate::physics::detect_corner::increment_corner_hit(pos.0, pos.1, width, height);
-        } else if pos.0 > width as f32 - 20.0 {
-            pos.0 = width as f32 - 20.0;
-            vel.0 = -vel.0.abs();
-            crate::physics::detect_corner::increment_corner_hit(pos.0, pos.1, width, height);
-        }
-        if pos.1 < 20.0 {
-            pos.1 = 20.0;
-            vel.1 = vel.1.abs();
-            crate::physics::detect_corner::increment_corner_hit(pos.0, pos.1, width, height);
-        } else if pos.1 > height as f32 - 20.0 {
-            pos.1 = height as f32 - 20.0;
-            vel.1 = -vel.1.abs();
-            crate::physics::detect_corner::increment_corner_hit(pos.0, pos.1, width, height);
-        }
+    base_speed: f32,
+) -> Option<Corner> {
+    let (Some(pos), Some(vel)) = (pos.as_mut(), vel.as_mut()) else {
+        return None;
+    };
+    *pos += *vel * base_speed * dt;
+
+    let hit_x = bounce_between(&mut pos.x, &mut vel.x, width as f32);
+    let hit_y = bounce_between(&mut pos.y, &mut vel.y, height as f32);
+    if !(hit_x || hit_y) {
+        return None;
     }
+    corners.note_wall_contact(*pos, *vel * base_speed, (width, height))
 }
 
-fn handle_ball_collision() {
-    unsafe {
-        let state = BALL_STATE.as_mut().unwrap();
-        if let (Some(yellow_pos), Some(green_pos), Some(yellow_vel), Some(green_vel)) = (
-            state.yellow_pos.as_mut(),
-            state.green_pos.as_mut(),
-            state.yellow_vel.as_mut(),
-            state.green_vel.as_mut(),
-        ) {
-            let dx = green_pos.0 - yellow_pos.0;
-            let dy = green_pos.1 - yellow_pos.1;
-            let dist_sq = dx * dx + dy * dy;
-            let min_dist = 60.0; // Much larger collision distance to ensure detection
-
-            if dist_sq < min_dist * min_dist && dist_sq > 0.0 {
-                let dist = dist_sq.sqrt();
-                let nx = dx / dist;
-                let ny = dy / dist;
-
-                // Separate the balls to prevent overlap
-                let overlap = min_dist - dist;
-                let separation = overlap * 0.5;
-                yellow_pos.0 -= nx * separation;
-                yellow_pos.1 -= ny * separation;
-                green_pos.0 += nx * separation;
-                green_pos.1 += ny * separation;
-
-                // Calculate relative velocity
-                let rel_vel_x = green_vel.0 - yellow_vel.0;
-                let rel_vel_y = green_vel.1 - yellow_vel.1;
-
-                // Calculate relative velocity along collision normal
-                let vel_along_normal = rel_vel_x * nx + rel_vel_y * ny;
-
-                // Don't resolve if velocities are separating
-                if vel_along_normal > 0.0 {
-                    return;
-                }
+/// Keeps one coordinate `WALL_MARGIN` inside 0..`extent`. A ball past either
+/// wall is put back on it and sent away from it, whichever way it was moving.
+/// Returns true when it hit a wall.
+fn bounce_between(pos: &mut f32, vel: &mut f32, extent: f32) -> bool {
+    if *pos < WALL_MARGIN {
+        *pos = WALL_MARGIN;
+        *vel = vel.abs();
+        true
+    } else if *pos > extent - WALL_MARGIN {
+        *pos = extent - WALL_MARGIN;
+        *vel = -vel.abs();
+        true
+    } else {
+        false
+    }
+}
 
-                // Calculate restitution (bounciness) - make it much more bouncy
-                let restitution = 1.2; // More than 1 for super bouncy effect
-                let impulse_magnitude = -(1.0 + restitution) * vel_along_normal;
+/// Pushes overlapping balls apart and bounces them. Returns true when they
+/// collided this step.
+fn handle_ball_collision(state: &mut BallState, time: f32) -> bool {
+    let min_dist = state.collision_distance();
+    let model = state.collision_model;
+    let (Some(yellow_pos), Some(green_pos), Some(yellow_vel), Some(green_vel)) = (
+        state.yellow_pos.as_mut(),
+        state.green_pos.as_mut(),
+        state.yellow_vel.as_mut(),
+        state.green_vel.as_mut(),
+    ) else {
+        return false;
+    };
+    let delta = *green_pos - *yellow_pos;
+    let dist_sq = delta.length_squared();
 
-                // Apply impulse to both balls (assuming equal mass) - make it more dramatic
-                let impulse_x = impulse_magnitude * nx * 0.5;
-                let impulse_y = impulse_magnitude * ny * 0.5;
+    if dist_sq >= min_dist * min_dist || dist_sq <= 0.0 {
+        return false;
+    }
+    let dist = dist_sq.sqrt();
+    // Unit vector from the yellow ball to the green one
+    let normal = delta / dist;
 
-                // Add some random deflection for more interesting bounces
-                let random_factor = (dist_sq.sin() * 0.1) as f32;
+    // Separate the balls to prevent overlap
+    let overlap = min_dist - dist;
+    let separation = overlap * 0.5;
+    *yellow_pos -= normal * separation;
+    *green_pos += normal * separation;
 
-                yellow_vel.0 -= impulse_x + random_factor;
-                yellow_vel.1 -= impulse_y + random_factor;
-                green_vel.0 += impulse_x + random_factor;
-                green_vel.1 += impulse_y + random_factor;
-            }
-        }
+    // Relative velocity along the collision normal
+    let vel_along_normal = (*green_vel - *yellow_vel).dot(normal);
+
+    // Don't resolve if velocities are separating
+    if vel_along_normal > 0.0 {
+        return false;
     }
+
+    let impulse_magnitude = -(1.0 + model.restitution()) * vel_along_normal;
+    if impulse_magnitude > SHAKE_IMPULSE_THRESHOLD {
+        screen_shake::add_trauma(((impulse_magnitude - SHAKE_IMPULSE_THRESHOLD) * 0.15).min(0.5));
+    }
+
+    // Apply impulse to both balls (assuming equal mass) - make it more dramatic
+    let impulse = normal * impulse_magnitude * 0.5;
+
+    // Arcade adds some deflection for more interesting bounces; the same
+    // amount on both axes, so it is not along the normal
+    let deflection = Vec2::splat(match model {
+        CollisionModel::Arcade => dist_sq.sin() * 0.1,
+        CollisionModel::Elastic => 0.0,
+    });
+
+    *yellow_vel -= impulse + deflection;
+    *green_vel += impulse + deflection;
+    let event = CollisionEvent {
+        time,
+        yellow_pos: *yellow_pos,
+        green_pos: *green_pos,
+        impulse: impulse_magnitude,
+    };
+    state.log_collision(event);
+    true
 }
 
 pub fn draw_balls_with_effects(
@@ -203,61 +682,74 @@ pub fn draw_balls_with_effects(
     scale_y: f32,
     x_offset: usize,
     buffer_width: u32,
-    draw_rays_fn: impl Fn(&mut [u8], u32, u32, (f32, f32), [u8; 4], f32, usize, u32),
+    quality: &QualitySettings,
+    draw_rays_fn: impl Fn(&mut [u8], u32, u32, Position, [u8; 4], f32, usize, u32),
 ) {
-    unsafe {
-        let state = BALL_STATE.as_ref().unwrap();
-        if let Some(yellow_pos) = state.yellow_pos {
+    with_ball_state(|state| {
+        let state = state.as_ref().unwrap();
+        let (yellow_pos, green_pos) = state.drawn_positions();
+        // Trails go under both balls and their rays
+        {
+            let mut ctx = DrawCtx::from_legacy(frame, width, height, time, x_offset, buffer_width);
+            let trail_radius = BASE_BALL_RADIUS * scale_x.max(scale_y);
+            for (pos, ball_trail, color) in [
+                (yellow_pos, &state.yellow_trail, YELLOW_BALL_COLOR),
+                (green_pos, &state.green_trail, GREEN_BALL_COLOR),
+            ] {
+                if let Some(pos) = pos {
+                    trail::draw_trail(&mut ctx, ball_trail, pos, color, trail_radius, quality);
+                }
+            }
+        }
+        let antialias = quality.antialias;
+        if let Some(yellow_pos) = yellow_pos {
             draw_ball_with_effects(
                 frame,
                 width,
                 height,
                 yellow_pos,
-                [255, 255, 0, 255],
-                [255, 255, 150, 255],
+                YELLOW_BALL_COLOR,
+                YELLOW_RAY_COLOR,
                 time,
-                scale_x,
-                scale_y,
+                state.yellow_radius,
                 x_offset,
                 buffer_width,
+                antialias,
                 &draw_rays_fn,
-                true,
             );
         }
-        if let Some(green_pos) = state.green_pos {
+        if let Some(green_pos) = green_pos {
             draw_ball_with_effects(
                 frame,
                 width,
                 height,
                 green_pos,
-                [0, 255, 0, 255],
-                [150, 255, 150, 255],
-                time + 0.5,
-                scale_x,
-                scale_y,
+                GREEN_BALL_COLOR,
+                GREEN_RAY_COLOR,
+                time + GREEN_RAY_TIME_OFFSET,
+                state.green_radius,
                 x_offset,
                 buffer_width,
+                antialias,
                 &draw_rays_fn,
-                false,
             );
         }
-    }
+    });
 }
 
 fn draw_ball_with_effects(
     frame: &mut [u8],
     width: u32,
     height: u32,
-    pos: (f32, f32),
+    pos: Position,
     ball_color: [u8; 4],
     ray_color: [u8; 4],
     time: f32,
-    scale_x: f32,
-    scale_y: f32,
+    ball_radius: f32,
     x_offset: usize,
     buffer_width: u32,
-    draw_rays_fn: &impl Fn(&mut [u8], u32, u32, (f32, f32), [u8; 4], f32, usize, u32),
-    is_yellow: bool,
+    antialias: bool,
+    draw_rays_fn: &impl Fn(&mut [u8], u32, u32, Position, [u8; 4], f32, usize, u32),
 ) {
     draw_rays_fn(
         frame,
@@ -270,97 +762,623 @@ fn draw_ball_with_effects(
         buffer_width,
     );
 
-    // Get audio data for scaling - much more expressive scaling
-    let mut audio_scale = 1.0;
-    if let Some(spectrum) = get_audio_spectrum() {
-        if let Ok(data) = spectrum.lock() {
-            if !data.is_empty() {
-                // Use different frequency ranges for each ball - swapped frequency ranges
-                let audio_value = if is_yellow {
-                    // Yellow ball responds to high frequencies (last quarter of spectrum)
-                    let start = (data.len() * 3) / 4;
-                    let end = data.len();
-                    let mut high_avg = 0.0;
-                    for i in start..end {
-                        high_avg += data[i];
-                    }
-                    high_avg / (end - start) as f32
-                } else {
-                    // Green ball responds to bass frequencies (first quarter of spectrum)
-                    let bass_range = data.len() / 4;
-                    let mut bass_avg = 0.0;
-                    for i in 0..bass_range {
-                        bass_avg += data[i];
-                    }
-                    bass_avg / bass_range as f32
-                };
-
-                if is_yellow {
-                    // Yellow ball: 10x more expressive scaling (normal level)
-                    let enhanced_audio = audio_value.powf(0.5); // Square root for smoother scaling
-                    audio_scale = 0.2 + enhanced_audio * 4.8; // Range: 0.2 to 5.0
-                                                              // Add some dynamic pulsing based on audio peaks
-                    let pulse_factor = (audio_value * 10.0).sin() * 0.3 + 1.0;
-                    audio_scale *= pulse_factor;
-
-                    // Remove size cap to allow unlimited ball growth
-                    audio_scale = audio_scale.max(0.1);
-                } else {
-                    // Green ball: 100x more responsive but much smaller (extreme responsiveness, compact size)
-                    let enhanced_audio = audio_value.powf(0.3); // Cube root for even more dramatic response
-                    audio_scale = 0.3 + enhanced_audio * 2.7; // Range: 0.3 to 3.0 (much smaller range but same responsiveness)
-                                                              // Add much more intense dynamic pulsing
-                    let pulse_factor = (audio_value * 20.0).sin() * 0.8 + 1.0; // More intense pulsing
-                    audio_scale *= pulse_factor;
-
-                    // Remove size cap to allow unlimited ball growth
-                    audio_scale = audio_scale.max(0.1);
-                }
-            }
-        }
+    if antialias {
+        let mut ctx = DrawCtx::from_legacy(frame, width, height, time, x_offset, buffer_width);
+        draw_filled_circle_subpixel(
+            &mut ctx,
+            SubpixelCircle {
+                center: pos,
+                radius: ball_radius,
+                color: ball_color,
+            },
+        );
+    } else {
+        // Hard-edged, but centered where the ball is rather than where its
+        // position truncates to
+        draw_filled_circle(
+            frame,
+            width,
+            height,
+            pos.x.round() as i32,
+            pos.y.round() as i32,
+            ball_radius.round() as i32,
+            &ball_color,
+            x_offset,
+            buffer_width,
+        );
     }
 
-    let base_ball_radius = 10.0 * scale_x.max(scale_y);
-    let ball_radius = (base_ball_radius * audio_scale) as i32;
-    draw_filled_circle(
-        frame,
-        width,
-        height,
-        pos.0 as i32,
-        pos.1 as i32,
-        ball_radius,
-        &ball_color,
-        x_offset,
-        buffer_width,
-    );
-
     // Remove glow effect completely - no more glow drawing
 }
 
+/// Captures both balls, or None before the first physics update.
+pub fn snapshot_balls() -> Option<BallSnapshot> {
+    with_ball_state(|state| state.as_ref().map(|state| state.snapshot()))
+}
+
+/// Restores both balls from a snapshot.
+pub fn restore_balls(snapshot: &BallSnapshot) {
+    with_ball_state(|state| state.get_or_insert_with(BallState::empty).restore(snapshot));
+}
+
 pub fn apply_force_yellow(force_x: f32, force_y: f32) {
-    unsafe {
-        if let Some(vel) = BALL_STATE.as_mut().unwrap().yellow_vel {
-            BALL_STATE.as_mut().unwrap().yellow_vel = Some((vel.0 + force_x, vel.1 + force_y));
+    with_ball_state(|state| {
+        if let Some(vel) = state.as_mut().and_then(|state| state.yellow_vel.as_mut()) {
+            *vel += Velocity::new(force_x, force_y);
         }
-    }
+    });
 }
 
 pub fn apply_force_green(force_x: f32, force_y: f32) {
-    unsafe {
-        if let Some(vel) = BALL_STATE.as_mut().unwrap().green_vel {
-            BALL_STATE.as_mut().unwrap().green_vel = Some((vel.0 + force_x, vel.1 + force_y));
+    with_ball_state(|state| {
+        if let Some(vel) = state.as_mut().and_then(|state| state.green_vel.as_mut()) {
+            *vel += Velocity::new(force_x, force_y);
         }
-    }
+    });
+}
+
+/// Moves both balls `factor` times as fast until it is set back to 1.0. Their
+/// velocities are left alone, so bounces in between don't change what 1.0 restores.
+pub fn set_ball_speed_boost(factor: f32) {
+    SPEED_BOOST.store(factor.to_bits(), Ordering::Relaxed);
 }
 
 pub fn teleport_yellow(x: f32, y: f32) {
-    unsafe {
-        BALL_STATE.as_mut().unwrap().yellow_pos = Some((x, y));
-    }
+    with_ball_state(|state| {
+        if let Some(state) = state.as_mut() {
+            state.teleport_yellow(x, y);
+        }
+    });
 }
 
 pub fn teleport_green(x: f32, y: f32) {
-    unsafe {
-        BALL_STATE.as_mut().unwrap().green_pos = Some((x, y));
+    with_ball_state(|state| {
+        if let Some(state) = state.as_mut() {
+            state.teleport_green(x, y);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::features::FEATURE_BANDS;
+
+    /// Both balls from `(position, velocity)` pairs
+    fn balls(yellow: ((f32, f32), (f32, f32)), green: ((f32, f32), (f32, f32))) -> BallState {
+        BallState {
+            yellow_pos: Some(yellow.0.into()),
+            yellow_vel: Some(yellow.1.into()),
+            green_pos: Some(green.0.into()),
+            green_vel: Some(green.1.into()),
+            ..BallState::empty()
+        }
+    }
+
+    #[test]
+    fn test_normal_speeds_take_a_single_substep() {
+        let mut state = balls(
+            ((400.0, 300.0), (1.0, 0.5)),
+            ((1200.0, 500.0), (-1.0, -0.5)),
+        );
+        let events = step_balls(
+            &mut state,
+            &mut CornerTracker::new(),
+            1600,
+            800,
+            1.0 / 60.0,
+            1.0,
+            1.0,
+        );
+        assert_eq!(events.substeps, 1);
+    }
+
+    #[test]
+    fn test_fast_balls_collide_instead_of_passing_through() {
+        // Each ball covers 200px in the frame, far more than the collision distance
+        let mut state = balls(
+            ((500.0, 400.0), (40.0, 0.0)),
+            ((800.0, 400.0), (-40.0, 0.0)),
+        );
+        let events = step_balls(
+            &mut state,
+            &mut CornerTracker::new(),
+            1600,
+            800,
+            0.1,
+            1.0,
+            1.0,
+        );
+        assert_eq!(events.substeps, MAX_SUBSTEPS);
+        assert_eq!(events.collisions, 1);
+        let (yellow, green) = (state.yellow_pos.unwrap(), state.green_pos.unwrap());
+        assert!(yellow.x < green.x, "{:?} passed {:?}", yellow, green);
+        assert!(state.yellow_vel.unwrap().x < 0.0);
+    }
+
+    #[test]
+    fn test_speed_boost_moves_balls_further_without_touching_velocities() {
+        let start = ((400.0, 300.0), (1.0, 0.5));
+        let other = ((1200.0, 500.0), (-1.0, -0.5));
+        let (mut normal, mut boosted) = (balls(start, other), balls(start, other));
+        boosted.speed_boost = 3.0;
+        for state in [&mut normal, &mut boosted] {
+            step_balls(
+                state,
+                &mut CornerTracker::new(),
+                1600,
+                800,
+                1.0 / 60.0,
+                1.0,
+                1.0,
+            );
+        }
+        let moved = |state: &BallState| state.yellow_pos.unwrap() - Position::from(start.0);
+        assert!((moved(&boosted) - moved(&normal) * 3.0).length() < 1e-3);
+        assert_eq!(boosted.yellow_vel, normal.yellow_vel);
+        assert_eq!(boosted.yellow_vel, Some(Velocity::from(start.1)));
+    }
+
+    #[test]
+    fn test_fast_ball_into_a_corner_counts_once() {
+        let mut state = balls(
+            ((120.0, 110.0), (-30.0, -30.0)),
+            ((1200.0, 500.0), (0.0, 0.0)),
+        );
+        let mut corners = CornerTracker::new();
+        let mut corner_hits = 0;
+        for _ in 0..10 {
+            corner_hits +=
+                step_balls(&mut state, &mut corners, 1600, 800, 0.1, 1.0, 1.0).corner_hits;
+        }
+        assert_eq!(corners.hits(), 1);
+        assert_eq!(corner_hits, 1);
+
+        // Hitting a wall away from the corners is not a corner hit
+        let mut state = balls(
+            ((800.0, 100.0), (0.0, -30.0)),
+            ((1200.0, 500.0), (0.0, 0.0)),
+        );
+        assert_eq!(
+            step_balls(
+                &mut state,
+                &mut CornerTracker::new(),
+                1600,
+                800,
+                0.1,
+                1.0,
+                1.0
+            )
+            .corner_hits,
+            0
+        );
+        assert!(state.yellow_vel.unwrap().y > 0.0);
+    }
+
+    #[test]
+    fn test_soft_knee_compresses_instead_of_clamping() {
+        let limit = 100.0;
+        let knee = limit * KNEE_FRACTION;
+        // Untouched below the knee
+        assert_eq!(soft_knee(10.0, limit), 10.0);
+        assert_eq!(soft_knee(knee, limit), knee);
+        // Continuous with slope 1 at the knee, then flattening out
+        let just_above = soft_knee(knee + 0.01, limit);
+        assert!((just_above - (knee + 0.01)).abs() < 1e-3);
+        let samples: Vec<f32> = [70.0, 100.0, 200.0]
+            .iter()
+            .map(|&r| soft_knee(r, limit))
+            .collect();
+        assert!(
+            samples.windows(2).all(|pair| pair[0] < pair[1]),
+            "{:?}",
+            samples
+        );
+        assert!(samples.iter().all(|&r| r < limit), "{:?}", samples);
+        assert!(soft_knee(1e6, limit) <= limit);
+        // Louder still grows the ball, but by less than it used to
+        assert!(samples[1] - samples[0] < 30.0);
+
+        // The pathological scale stays inside the cap at any resolution
+        for (width, height) in [(1280, 720), (3840, 2160)] {
+            let radius = ball_radius(PATHOLOGICAL_AUDIO_SCALE, 20.0, width, height, 0.15);
+            assert!(
+                radius <= height as f32 * 0.15,
+                "{} at {}x{}",
+                radius,
+                width,
+                height
+            );
+        }
+    }
+
+    #[test]
+    fn test_collision_distance_follows_the_drawn_radius() {
+        // 150px apart: small balls pass by, big ones touch
+        let place = || balls(((500.0, 400.0), (1.0, 0.0)), ((650.0, 400.0), (-1.0, 0.0)));
+        let mut small = place();
+        assert_eq!(small.collision_distance(), MIN_COLLISION_DISTANCE);
+        assert!(!handle_ball_collision(&mut small, 0.0));
+
+        let mut big = place();
+        big.yellow_radius = 90.0;
+        big.green_radius = 80.0;
+        assert_eq!(big.collision_distance(), 170.0);
+        assert!(handle_ball_collision(&mut big, 0.0));
+        // Pushed apart until their edges meet
+        let gap = big.green_pos.unwrap().x - big.yellow_pos.unwrap().x;
+        assert!((gap - 170.0).abs() < 1e-3, "{}", gap);
+        assert!(big.yellow_vel.unwrap().x < 0.0);
+    }
+
+    fn kinetic_energy(state: &BallState) -> f32 {
+        [state.yellow_vel, state.green_vel]
+            .into_iter()
+            .flatten()
+            .map(|vel| 0.5 * vel.length_squared())
+            .sum()
+    }
+
+    #[test]
+    fn test_elastic_collisions_never_add_energy() {
+        // Big balls in a small box collide every few frames
+        let mut state = balls(((80.0, 80.0), (7.0, 3.0)), ((220.0, 200.0), (-5.0, 6.0)));
+        state.yellow_radius = 40.0;
+        state.green_radius = 40.0;
+        state.collision_model = CollisionModel::Elastic;
+        state.collision_log = Some(VecDeque::new());
+        let initial = kinetic_energy(&state);
+        let dt = 1.0 / 60.0;
+        let mut previous = initial;
+        let mut collisions = 0;
+        let mut frame = 0;
+        while collisions < 3000 {
+            frame += 1;
+            assert!(frame < 200_000, "only {} collisions", collisions);
+            state.last_time = Some(frame as f32 * dt);
+            collisions += step_balls(
+                &mut state,
+                &mut CornerTracker::new(),
+                300,
+                300,
+                dt,
+                1.0,
+                1.0,
+            )
+            .collisions;
+            let energy = kinetic_energy(&state);
+            // Rounding is the only slack
+            assert!(
+                energy <= previous * (1.0 + 1e-5),
+                "{} after {}",
+                energy,
+                previous
+            );
+            previous = energy;
+        }
+        assert!(
+            previous <= initial * (1.0 + 1e-3),
+            "{} from {}",
+            previous,
+            initial
+        );
+
+        let log = state.collision_log.as_ref().unwrap();
+        assert_eq!(log.len(), COLLISION_LOG_CAPACITY);
+        assert!(log
+            .iter()
+            .zip(log.iter().skip(1))
+            .all(|(a, b)| a.time <= b.time));
+        assert!(log.back().unwrap().time <= frame as f32 * dt);
+        assert!(log.iter().all(|event| event.impulse > 0.0));
+    }
+
+    #[test]
+    fn test_arcade_is_the_default_and_gains_energy() {
+        assert_eq!(BallState::empty().collision_model, CollisionModel::Arcade);
+        let head_on = || balls(((500.0, 400.0), (1.0, 0.0)), ((550.0, 400.0), (-1.0, 0.0)));
+        let mut arcade = head_on();
+        let mut elastic = head_on();
+        elastic.collision_model = CollisionModel::Elastic;
+        assert!(handle_ball_collision(&mut arcade, 1.0));
+        assert!(handle_ball_collision(&mut elastic, 1.0));
+        assert!(kinetic_energy(&arcade) > kinetic_energy(&head_on()));
+        assert_eq!(kinetic_energy(&elastic), kinetic_energy(&head_on()));
+        // Equal masses head on swap velocities
+        assert_eq!(elastic.yellow_vel, Some(Velocity::new(-1.0, 0.0)));
+        assert_eq!(elastic.green_vel, Some(Velocity::new(1.0, 0.0)));
+        // Nothing is logged unless logging is on
+        assert!(elastic.collision_log.is_none());
+
+        for model in CollisionModel::ALL {
+            assert_eq!(CollisionModel::from_name(model.name()), Some(model));
+        }
+    }
+
+    #[test]
+    fn test_teleport_drops_the_trail_instead_of_streaking() {
+        let mut state = balls(((100.0, 100.0), (5.0, 0.0)), ((600.0, 400.0), (0.0, 0.0)));
+        let dt = 1.0 / 60.0;
+        for frame in 0..30 {
+            step_balls(
+                &mut state,
+                &mut CornerTracker::new(),
+                1600,
+                800,
+                dt,
+                1.0,
+                1.0,
+            );
+            state.sample_trails(frame as f32 * dt);
+        }
+        assert!(state.yellow_trail.len() > 10);
+        let green_samples = state.green_trail.len();
+
+        state.teleport_yellow(1200.0, 600.0);
+        assert!(state.yellow_trail.is_empty());
+        state.sample_trails(30.0 * dt);
+        // Only the new position is in the trail, so nothing links it to the old one
+        assert_eq!(
+            state.yellow_trail.recent().collect::<Vec<_>>(),
+            [Position::new(1200.0, 600.0)]
+        );
+        assert!(state.green_trail.len() >= green_samples);
+    }
+
+    #[test]
+    fn test_pathological_balls_and_audio_stay_finite() {
+        let awkward = [
+            0.0,
+            -1.0e-30,
+            1.0e30,
+            f32::INFINITY,
+            f32::NEG_INFINITY,
+            f32::NAN,
+        ];
+        for &level in &awkward {
+            let audio = FrameFeatures {
+                bands: [level; FEATURE_BANDS],
+                ..FrameFeatures::default()
+            };
+            for is_yellow in [true, false] {
+                let scale = audio_scale(&audio, is_yellow);
+                let radius = ball_radius(
+                    scale,
+                    BASE_BALL_RADIUS,
+                    1600,
+                    800,
+                    DEFAULT_MAX_RADIUS_FRACTION,
+                );
+                assert!(
+                    radius.is_finite() && radius > 0.0,
+                    "{} gave {}",
+                    level,
+                    radius
+                );
+            }
+        }
+
+        let starts = start_states(1600, 800, 1.0, 1.0);
+        for &x in &awkward {
+            for &v in &awkward {
+                // The green ball sits exactly on the yellow one
+                let mut state = balls(((x, 300.0), (v, 0.5)), ((x, 300.0), (1.0, v)));
+                reset_broken_balls(&mut state, starts);
+                step_balls(
+                    &mut state,
+                    &mut CornerTracker::new(),
+                    1600,
+                    800,
+                    1.0 / 60.0,
+                    1.0,
+                    1.0,
+                );
+                assert_eq!(reset_broken_balls(&mut state, starts), 0, "{} {}", x, v);
+                let (yellow, green) = state.drawn_positions();
+                assert!(yellow.unwrap().is_finite() && green.unwrap().is_finite());
+            }
+        }
+    }
+
+    #[test]
+    fn test_trajectories_match_the_recorded_runs() {
+        // Both balls' position and velocity every 150 frames of a 900-frame
+        // run at a fixed dt, and the collision count, recorded while the
+        // physics still did its math on (f32, f32) tuples
+        type Sample = [(f32, f32); 4];
+        // A ball's starting position and velocity
+        type Start = ((f32, f32), (f32, f32));
+        // Model, frame size, ball radius, both starts, collisions, and samples
+        type Run = (
+            CollisionModel,
+            (u32, u32),
+            f32,
+            Start,
+            Start,
+            u32,
+            [Sample; 6],
+        );
+        let runs: [Run; 3] = [
+            (
+                CollisionModel::Arcade,
+                (1600, 800),
+                10.0,
+                ((400.0, 300.0), (3.0, 1.5)),
+                ((1200.0, 500.0), (-2.5, -1.0)),
+                1,
+                [
+                    [
+                        (775.0, 487.5),
+                        (3.0, 1.5),
+                        (887.4979, 374.99847),
+                        (-2.5, -1.0),
+                    ],
+                    [
+                        (1150.0, 675.0),
+                        (3.0, 1.5),
+                        (575.001, 249.99707),
+                        (-2.5, -1.0),
+                    ],
+                    [
+                        (1525.0, 698.75),
+                        (3.0, -1.5),
+                        (262.50037, 124.9978),
+                        (-2.5, -1.0),
+                    ],
+                    [
+                        (1262.5, 511.25),
+                        (-3.0, -1.5),
+                        (88.750015, 39.999996),
+                        (2.5, 1.0),
+                    ],
+                    [
+                        (887.5, 323.75),
+                        (-3.0, -1.5),
+                        (401.25046, 164.99992),
+                        (2.5, 1.0),
+                    ],
+                    [
+                        (556.0647, 75.42468),
+                        (-2.0046353, -2.8914418),
+                        (670.1843, 350.82437),
+                        (1.5046355, 2.3914418),
+                    ],
+                ],
+            ),
+            (
+                CollisionModel::Elastic,
+                (300, 300),
+                40.0,
+                ((80.0, 80.0), (7.0, 3.0)),
+                ((220.0, 200.0), (-5.0, 6.0)),
+                16,
+                [
+                    [
+                        (163.25003, 47.3551),
+                        (-8.443875, 1.8409204),
+                        (261.3164, 104.801674),
+                        (6.495659, 1.4554701),
+                    ],
+                    [
+                        (31.90256, 41.92121),
+                        (-3.6050234, -7.033114),
+                        (149.76373, 63.883305),
+                        (1.656807, -7.334444),
+                    ],
+                    [
+                        (55.060883, 84.305954),
+                        (3.0052185, -8.861621),
+                        (243.31978, 242.53186),
+                        (-1.0606384, 5.5059366),
+                    ],
+                    [
+                        (197.23083, 31.999577),
+                        (-5.842535, 3.1998858),
+                        (60.196053, 141.07892),
+                        (-7.7323, 3.8518777),
+                    ],
+                    [
+                        (89.8768, 174.06978),
+                        (-7.480259, 4.2996244),
+                        (99.19872, 56.58237),
+                        (5.590494, -3.6476326),
+                    ],
+                    [
+                        (66.07174, 259.51566),
+                        (6.504245, 6.5085335),
+                        (28.885765, 51.70484),
+                        (0.18597329, -5.8565416),
+                    ],
+                ],
+            ),
+            (
+                CollisionModel::Arcade,
+                (640, 360),
+                30.0,
+                ((100.0, 100.0), (9.0, 4.0)),
+                ((500.0, 250.0), (-6.0, 5.0)),
+                7,
+                [
+                    [
+                        (314.50717, 336.39175),
+                        (5.230071, -8.65979),
+                        (47.433563, 71.06526),
+                        (8.230071, 7.65979),
+                    ],
+                    [
+                        (372.66925, 217.35799),
+                        (-2.262369, -8.330429),
+                        (64.75308, 222.15923),
+                        (-11.197773, 9.330429),
+                    ],
+                    [
+                        (89.871475, 219.00485),
+                        (-2.262369, 8.330429),
+                        (172.41402, 102.937195),
+                        (11.197773, 9.330429),
+                    ],
+                    [
+                        (240.92932, 322.90396),
+                        (7.2967615, 16.036102),
+                        (101.911224, 276.8647),
+                        (-1.6386423, 1.6247563),
+                    ],
+                    [
+                        (257.06082, 318.53143),
+                        (6.3274918, -12.881109),
+                        (63.281387, 239.01907),
+                        (11.985611, 1.5302372),
+                    ],
+                    [
+                        (318.49103, 73.27081),
+                        (1.1619549, 17.434086),
+                        (595.72473, 126.34188),
+                        (8.883039, 9.816167),
+                    ],
+                ],
+            ),
+        ];
+        for (model, (width, height), radius, yellow, green, expected_collisions, expected) in runs {
+            let mut state = balls(yellow, green);
+            state.yellow_radius = radius;
+            state.green_radius = radius;
+            state.collision_model = model;
+            let dt = 1.0 / 60.0;
+            let mut collisions = 0;
+            let mut samples = Vec::new();
+            for frame in 1..=900 {
+                state.last_time = Some(frame as f32 * dt);
+                collisions += step_balls(
+                    &mut state,
+                    &mut CornerTracker::new(),
+                    width,
+                    height,
+                    dt,
+                    1.0,
+                    1.0,
+                )
+                .collisions;
+                if frame % 150 == 0 {
+                    samples.push([
+                        state.yellow_pos.unwrap(),
+                        state.yellow_vel.unwrap(),
+                        state.green_pos.unwrap(),
+                        state.green_vel.unwrap(),
+                    ]);
+                }
+            }
+            assert_eq!(collisions, expected_collisions, "{:?}", model);
+            for (sample, expected) in samples.iter().zip(&expected) {
+                for (actual, &expected) in sample.iter().zip(expected) {
+                    assert!(
+                        actual.distance(expected.into()) < 1e-3,
+                        "{:?}: {} vs {:?}",
+                        model,
+                        actual,
+                        expected
+                    );
+                }
+            }
+        }
     }
 }
@@ -0,0 +1,107 @@
+//! Keeps NaN and infinity out of the simulation. One bad coordinate makes a
+//! line vanish for good and can spread through blending to the whole frame.
+//!
+//! Math that divides by a distance or normalizes goes through the helpers
+//! here. Each system also checks its state around its update: whatever comes
+//! in broken is reset quietly, and whatever the update itself breaks is
+//! reported. Debug builds stop at the report, naming the subsystem; release
+//! builds log it once and carry on with the reset values.
+
+use glam::Vec2;
+use log::warn;
+use std::sync::{Mutex, PoisonError};
+
+/// Furthest a coordinate or speed may be from zero. Nothing that far out is
+/// on screen, and lengths of anything much larger overflow when squared.
+pub const MAX_COORD: f32 = 1.0e6;
+
+/// Subsystems that have been reported already, so release builds log once
+static REPORTED: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
+
+/// Finite and within `MAX_COORD` on both axes
+pub fn is_sane(v: Vec2) -> bool {
+    v.is_finite() && v.abs().max_element() < MAX_COORD
+}
+
+/// `v` scaled to length 1, or zero when it has no direction to keep: zero,
+/// too short to invert, or not finite
+pub fn safe_normalize(v: Vec2) -> Vec2 {
+    v.try_normalize().unwrap_or(Vec2::ZERO)
+}
+
+/// `numerator / dist`, capped at `max`. At or below zero distance that is
+/// `max`, and a NaN distance gives zero.
+pub fn inv_dist_clamped(numerator: f32, dist: f32, max: f32) -> f32 {
+    if dist.is_nan() {
+        0.0
+    } else if dist <= 0.0 {
+        max
+    } else {
+        (numerator / dist).min(max)
+    }
+}
+
+/// `value` clamped to `min..=max`, with NaN taken as `min`
+pub fn clamp_finite(value: f32, min: f32, max: f32) -> f32 {
+    if value.is_nan() {
+        min
+    } else {
+        value.clamp(min, max)
+    }
+}
+
+/// Called after an update with the number of things its own math left NaN or
+/// infinite in `subsystem`, which have been reset already
+#[track_caller]
+pub fn report_produced(subsystem: &'static str, broken: usize) {
+    if broken == 0 {
+        return;
+    }
+    if cfg!(debug_assertions) {
+        panic!(
+            "{}: update produced {} NaN or infinite values",
+            subsystem, broken
+        );
+    }
+    let mut reported = REPORTED.lock().unwrap_or_else(PoisonError::into_inner);
+    if !reported.contains(&subsystem) {
+        reported.push(subsystem);
+        warn!(
+            "{}: update produced {} NaN or infinite values; reset them",
+            subsystem, broken
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_helpers_never_return_nan() {
+        let awkward = [
+            0.0,
+            -0.0,
+            f32::MIN_POSITIVE / 4.0,
+            -1.0,
+            1.0e30,
+            f32::INFINITY,
+            f32::NEG_INFINITY,
+            f32::NAN,
+        ];
+        for &x in &awkward {
+            for &y in &awkward {
+                let dir = safe_normalize(Vec2::new(x, y));
+                assert!(dir.is_finite(), "{} {}", x, y);
+                assert!(dir == Vec2::ZERO || (dir.length() - 1.0).abs() < 1e-5);
+            }
+            let inv = inv_dist_clamped(40.0, x, 0.5);
+            assert!(inv.is_finite() && inv <= 0.5, "{} gave {}", x, inv);
+            assert!(clamp_finite(x, 0.0, 10.0).is_finite());
+        }
+        assert_eq!(inv_dist_clamped(40.0, 160.0, 0.5), 0.25);
+        assert_eq!(inv_dist_clamped(40.0, 0.0, 0.5), 0.5);
+        assert!(!is_sane(Vec2::new(0.0, 2.0 * MAX_COORD)));
+        assert!(is_sane(Vec2::new(-800.0, 400.0)));
+    }
+}
@@ -0,0 +1,413 @@
+use crate::audio::features::FrameFeatures;
+use crate::core::station_state;
+use crate::core::types::{Position, Velocity};
+use crate::graphics::draw_ctx::{fit_scale, DrawCtx};
+use crate::graphics::render::draw_line_aa;
+use std::sync::atomic::Ordering;
+
+/// Number of point masses around the perimeter
+pub const SOFTBODY_POINTS: usize = 40;
+/// Resting radius of the blob in pixels
+const REST_RADIUS: f32 = 80.0;
+/// Stiffness of the springs between neighbouring perimeter points
+const EDGE_STIFFNESS: f32 = 2000.0;
+/// Stiffness of the spokes from the center mass to each perimeter point
+const SPOKE_STIFFNESS: f32 = 60.0;
+/// Damping along each spring, proportional to the relative speed of its ends
+const SPRING_DAMPING: f32 = 8.0;
+/// Outward pressure per pixel of perimeter at the resting area
+const PRESSURE: f32 = 30.0;
+/// How much full-scale loudness raises the pressure
+const PRESSURE_PULSE: f32 = 1.5;
+/// The center mass is heavier so the spokes don't make it the stiffest part
+const CENTER_MASS: f32 = 4.0;
+/// Fraction of normal speed kept after a wall hit
+const WALL_RESTITUTION: f32 = 0.6;
+/// Fraction of tangential speed kept after a wall hit
+const WALL_FRICTION: f32 = 0.98;
+/// Longest physics step; frames are split into substeps no longer than this
+const MAX_SUBSTEP: f32 = 1.0 / 240.0;
+/// Below this speed the blob gets nudged so it keeps wandering
+const MIN_DRIFT_SPEED: f32 = 90.0;
+
+const FILL_COLOR: [u8; 4] = [40, 110, 150, 255];
+const OUTLINE_COLOR: [u8; 4] = [120, 220, 255, 255];
+const OUTLINE_THICKNESS: f32 = 2.0;
+
+/// A ring of point masses held together by edge springs, spokes to a center mass,
+/// and an internal gas pressure that keeps it inflated.
+#[derive(Debug, Clone)]
+pub struct SoftBody {
+    pub points: Vec<Position>,
+    pub velocities: Vec<Velocity>,
+    pub center: Position,
+    pub center_vel: Velocity,
+    rest_edge: f32,
+    rest_spoke: f32,
+    rest_area: f32,
+}
+
+impl SoftBody {
+    /// Creates a round blob at `center` moving with `velocity`.
+    pub fn new(center: Position, velocity: Velocity) -> Self {
+        Self::with_radius(center, velocity, REST_RADIUS)
+    }
+
+    /// A blob resting at `radius` pixels instead of the usual size
+    pub fn with_radius(center: Position, velocity: Velocity, radius: f32) -> Self {
+        let points: Vec<Position> = (0..SOFTBODY_POINTS)
+            .map(|i| {
+                let angle = i as f32 / SOFTBODY_POINTS as f32 * std::f32::consts::TAU;
+                center + Velocity::new(angle.cos(), angle.sin()) * radius
+            })
+            .collect();
+        let rest_edge = points[0].distance(points[1]);
+        let mut body = Self {
+            velocities: vec![velocity; SOFTBODY_POINTS],
+            center,
+            center_vel: velocity,
+            rest_edge,
+            rest_spoke: radius,
+            rest_area: 0.0,
+            points,
+        };
+        body.rest_area = body.area();
+        body
+    }
+
+    /// Scales the blob and its rest shape about the origin by `factor`
+    pub fn scale(&mut self, factor: f32) {
+        for (point, velocity) in self.points.iter_mut().zip(&mut self.velocities) {
+            *point *= factor;
+            *velocity *= factor;
+        }
+        self.center *= factor;
+        self.center_vel *= factor;
+        self.rest_edge *= factor;
+        self.rest_spoke *= factor;
+        self.rest_area *= factor * factor;
+    }
+
+    /// Signed area of the perimeter polygon (positive while the ring isn't inverted).
+    pub fn area(&self) -> f32 {
+        let n = self.points.len();
+        (0..n)
+            .map(|i| {
+                let a = self.points[i];
+                let b = self.points[(i + 1) % n];
+                a.x * b.y - b.x * a.y
+            })
+            .sum::<f32>()
+            * 0.5
+    }
+
+    /// Pressure force on each perimeter point. The gas pushes each edge outward in
+    /// proportion to its length, harder the more the area is squeezed below rest.
+    pub fn pressure_forces(&self, pressure_scale: f32) -> Vec<Velocity> {
+        let n = self.points.len();
+        // Clamped so an inverted or collapsed ring can't produce a huge force
+        let area = self.area().max(self.rest_area * 0.1);
+        let pressure = PRESSURE * pressure_scale * self.rest_area / area;
+        let mut forces = vec![Velocity::ZERO; n];
+        for i in 0..n {
+            let j = (i + 1) % n;
+            let edge = self.points[j] - self.points[i];
+            // Outward normal scaled by edge length
+            let normal = Velocity::new(edge.y, -edge.x);
+            let force = normal * pressure * 0.5;
+            forces[i] += force;
+            forces[j] += force;
+        }
+        forces
+    }
+
+    /// Advances the blob by `dt` seconds inside a `width` x `height` box,
+    /// split into substeps so stiff springs stay stable at any frame rate.
+    pub fn step(&mut self, dt: f32, width: u32, height: u32, pressure_scale: f32) {
+        let substeps = (dt / MAX_SUBSTEP).ceil().max(1.0) as usize;
+        let h = dt / substeps as f32;
+        for _ in 0..substeps {
+            self.substep(h, width, height, pressure_scale);
+        }
+    }
+
+    fn substep(&mut self, h: f32, width: u32, height: u32, pressure_scale: f32) {
+        let n = self.points.len();
+        let mut forces = self.pressure_forces(pressure_scale);
+        let mut center_force = Velocity::ZERO;
+
+        for i in 0..n {
+            let j = (i + 1) % n;
+            let f = spring_force(
+                self.points[i],
+                self.points[j],
+                self.velocities[i],
+                self.velocities[j],
+                self.rest_edge,
+                EDGE_STIFFNESS,
+            );
+            forces[i] += f;
+            forces[j] -= f;
+
+            let f = spring_force(
+                self.center,
+                self.points[i],
+                self.center_vel,
+                self.velocities[i],
+                self.rest_spoke,
+                SPOKE_STIFFNESS,
+            );
+            center_force += f;
+            forces[i] -= f;
+        }
+
+        // Semi-implicit Euler: velocities first, then positions with the new velocities
+        for ((point, velocity), force) in
+            self.points.iter_mut().zip(&mut self.velocities).zip(forces)
+        {
+            *velocity += force * h;
+            *point += *velocity * h;
+            collide_with_walls(point, velocity, width, height);
+        }
+        self.center_vel += center_force / CENTER_MASS * h;
+        self.center += self.center_vel * h;
+    }
+
+    /// Kinetic energy plus spring and gas potential energy, for stability checks.
+    pub fn energy(&self, pressure_scale: f32) -> f32 {
+        let n = self.points.len();
+        let kinetic = self
+            .velocities
+            .iter()
+            .map(|v| 0.5 * v.length_squared())
+            .sum::<f32>()
+            + 0.5 * CENTER_MASS * self.center_vel.length_squared();
+        let springs: f32 = (0..n)
+            .map(|i| {
+                let edge = self.points[i].distance(self.points[(i + 1) % n]) - self.rest_edge;
+                let spoke = self.center.distance(self.points[i]) - self.rest_spoke;
+                0.5 * EDGE_STIFFNESS * edge * edge + 0.5 * SPOKE_STIFFNESS * spoke * spoke
+            })
+            .sum();
+        let area = self.area().max(self.rest_area * 0.1);
+        let gas = -PRESSURE * pressure_scale * self.rest_area * (area / self.rest_area).ln();
+        kinetic + springs + gas
+    }
+
+    /// Average velocity of the perimeter points.
+    pub fn mean_velocity(&self) -> Velocity {
+        self.velocities.iter().copied().sum::<Velocity>() / self.velocities.len() as f32
+    }
+
+    /// Speeds the whole blob up along its current heading when it has slowed down,
+    /// so wall losses don't leave it sitting still.
+    pub fn keep_moving(&mut self) {
+        let mean = self.mean_velocity();
+        let speed = mean.length();
+        if speed >= MIN_DRIFT_SPEED {
+            return;
+        }
+        let heading = if speed > 1.0 {
+            mean / speed
+        } else {
+            Velocity::new(0.8, 0.6)
+        };
+        let boost = heading * (MIN_DRIFT_SPEED - speed);
+        for v in &mut self.velocities {
+            *v += boost;
+        }
+        self.center_vel += boost;
+    }
+}
+
+/// Force on `a` from a damped spring to `b`; `b` receives the opposite force.
+fn spring_force(
+    a: Position,
+    b: Position,
+    va: Velocity,
+    vb: Velocity,
+    rest: f32,
+    k: f32,
+) -> Velocity {
+    let delta = b - a;
+    let dist = delta.length();
+    if dist <= f32::EPSILON {
+        return Velocity::ZERO;
+    }
+    let dir = delta / dist;
+    let stretch = dist - rest;
+    let closing = (vb - va).dot(dir);
+    dir * (k * stretch + SPRING_DAMPING * closing)
+}
+
+fn collide_with_walls(pos: &mut Position, vel: &mut Velocity, width: u32, height: u32) {
+    let max_x = width as f32;
+    let max_y = height as f32;
+    if pos.x < 0.0 || pos.x > max_x {
+        pos.x = pos.x.clamp(0.0, max_x);
+        vel.x = -vel.x * WALL_RESTITUTION;
+        vel.y *= WALL_FRICTION;
+    }
+    if pos.y < 0.0 || pos.y > max_y {
+        pos.y = pos.y.clamp(0.0, max_y);
+        vel.y = -vel.y * WALL_RESTITUTION;
+        vel.x *= WALL_FRICTION;
+    }
+}
+
+pub struct SoftBodyState {
+    body: SoftBody,
+    last_time: Option<f32>,
+}
+
+pub fn set_softbody_enabled(enabled: bool) {
+    station_state::current()
+        .softbody_enabled
+        .store(enabled, Ordering::SeqCst);
+}
+
+pub fn is_softbody_enabled() -> bool {
+    station_state::current()
+        .softbody_enabled
+        .load(Ordering::SeqCst)
+}
+
+/// Flips the blob scene and returns the new state
+pub fn toggle_softbody() -> bool {
+    !station_state::current()
+        .softbody_enabled
+        .fetch_xor(true, Ordering::SeqCst)
+}
+
+/// Scales the blob to a frame `factor` times the size
+pub fn scale_softbody(factor: f32) {
+    if let Some(state) = station_state::lock(&station_state::current().softbody).as_mut() {
+        state.body.scale(factor);
+    }
+}
+
+/// Updates and draws the blob into the context's region when the soft-body
+/// scene is enabled. Loudness raises the internal pressure so the blob pulses
+/// with the music. A region too small for the usual blob gets a smaller one.
+pub fn update_and_draw_softbody(ctx: &mut DrawCtx, audio: &FrameFeatures) {
+    if !is_softbody_enabled() {
+        return;
+    }
+
+    let (width, height, time) = (ctx.width(), ctx.height(), ctx.time);
+    let station = station_state::current();
+    let mut slot = station_state::lock(&station.softbody);
+    let state = slot.get_or_insert_with(|| {
+        // Room to move about as well as to fit
+        let room = 4.0 * REST_RADIUS;
+        let fit = fit_scale((room, room), (width, height));
+        SoftBodyState {
+            body: SoftBody::with_radius(
+                Position::new(width as f32 / 2.0, height as f32 / 2.0),
+                Velocity::new(180.0, 120.0) * fit,
+                REST_RADIUS * fit,
+            ),
+            last_time: None,
+        }
+    });
+    let dt = match state.last_time {
+        Some(last) => (time - last).clamp(0.0, 0.1),
+        None => 0.016,
+    };
+    state.last_time = Some(time);
+
+    let pressure_scale = 1.0 + audio.loudness.clamp(0.0, 1.0) * PRESSURE_PULSE;
+    state.body.step(dt, width, height, pressure_scale);
+    state.body.keep_moving();
+
+    draw_softbody(ctx, &state.body);
+}
+
+/// Fills the blob as a triangle fan around its center, then outlines the perimeter.
+fn draw_softbody(ctx: &mut DrawCtx, body: &SoftBody) {
+    let n = body.points.len();
+    let corner = |p: Position| (p.x as i32, p.y as i32);
+    for i in 0..n {
+        let (a, b) = (body.points[i], body.points[(i + 1) % n]);
+        ctx.fill_triangle(corner(body.center), corner(a), corner(b), FILL_COLOR);
+    }
+    for i in 0..n {
+        let (a, b) = (body.points[i], body.points[(i + 1) % n]);
+        draw_line_aa(ctx, a.into(), b.into(), OUTLINE_THICKNESS, &OUTLINE_COLOR);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOX_WIDTH: u32 = 800;
+    const BOX_HEIGHT: u32 = 600;
+
+    fn run_bounded(frame_rate: f32) {
+        let mut body = SoftBody::new(Position::new(400.0, 300.0), Velocity::new(300.0, 200.0));
+        let initial = body.energy(1.0);
+        for _ in 0..(frame_rate as usize * 10) {
+            body.step(1.0 / frame_rate, BOX_WIDTH, BOX_HEIGHT, 1.0);
+            let energy = body.energy(1.0);
+            assert!(energy.is_finite());
+            // Walls and damping only remove energy; allow a little integration error
+            assert!(
+                energy <= initial * 1.02 + 1.0,
+                "energy grew from {} to {} at {} Hz",
+                initial,
+                energy,
+                frame_rate
+            );
+        }
+        assert!(body.area() > 0.0);
+        for p in &body.points {
+            assert!(p.x >= 0.0 && p.x <= BOX_WIDTH as f32);
+            assert!(p.y >= 0.0 && p.y <= BOX_HEIGHT as f32);
+        }
+    }
+
+    #[test]
+    fn test_energy_stays_bounded_at_60_hz() {
+        run_bounded(60.0);
+    }
+
+    #[test]
+    fn test_energy_stays_bounded_at_144_hz() {
+        run_bounded(144.0);
+    }
+
+    #[test]
+    fn test_pressure_pushes_outward_and_grows_when_squeezed() {
+        let center = Position::new(400.0, 300.0);
+        let mut body = SoftBody::new(center, Velocity::ZERO);
+        let rest_forces = body.pressure_forces(1.0);
+        for (p, f) in body.points.iter().zip(&rest_forces) {
+            assert!((*p - center).dot(*f) > 0.0);
+        }
+
+        for p in &mut body.points {
+            *p = center + (*p - center) * 0.7;
+        }
+        let squeezed = body.pressure_forces(1.0);
+        assert!(squeezed[0].length() > rest_forces[0].length());
+        for (p, f) in body.points.iter().zip(&squeezed) {
+            assert!((*p - center).dot(*f) > 0.0);
+        }
+    }
+
+    #[test]
+    fn test_pressure_forces_cancel_out() {
+        let body = SoftBody::new(Position::new(200.0, 200.0), Velocity::ZERO);
+        let total: Velocity = body.pressure_forces(2.0).into_iter().sum();
+        assert!(total.length() < 1e-2);
+    }
+
+    #[test]
+    fn test_keep_moving_restores_drift_speed() {
+        let mut body = SoftBody::new(Position::new(400.0, 300.0), Velocity::new(10.0, 0.0));
+        body.keep_moving();
+        assert!((body.mean_velocity().length() - MIN_DRIFT_SPEED).abs() < 1e-3);
+        assert!(body.mean_velocity().x > 0.0);
+    }
+}
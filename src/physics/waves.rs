@@ -0,0 +1,178 @@
+//! Interference field for the World's Waves mode. Two coherent sources send
+//! out circular waves, and line endpoints are pushed down the gradient of
+//! their sum. The field is only evaluated at endpoints, never per pixel.
+//! Where the waves cancel the lines stay calm, so the nodal lines of the
+//! pattern show up as still bands between swaying ones.
+
+use crate::core::types::{Position, Velocity};
+use std::f32::consts::TAU;
+
+/// Distance between wave crests, in pixels
+const WAVELENGTH: f32 = 140.0;
+/// Endpoint acceleration, per 60 Hz step squared, for a unit field gradient
+const FORCE_SCALE: f32 = 0.6;
+/// Fraction of endpoint speed lost per 60 Hz step, so the waves, not the
+/// lines' starting velocities, decide how they move
+pub const WAVE_DAMPING: f32 = 0.04;
+
+pub const DEFAULT_FREQUENCY: f32 = 0.6;
+pub const MIN_FREQUENCY: f32 = 0.1;
+pub const MAX_FREQUENCY: f32 = 3.0;
+pub const FREQUENCY_STEP: f32 = 0.1;
+pub const DEFAULT_AMPLITUDE: f32 = 1.0;
+pub const MIN_AMPLITUDE: f32 = 0.0;
+pub const MAX_AMPLITUDE: f32 = 3.0;
+pub const AMPLITUDE_STEP: f32 = 0.25;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WaveField {
+    /// Where the waves start; None until placed for a frame size
+    pub sources: Option<[Position; 2]>,
+    /// Oscillations per second at each source
+    pub frequency: f32,
+    pub amplitude: f32,
+}
+
+impl WaveField {
+    pub const DEFAULT: Self = Self {
+        sources: None,
+        frequency: DEFAULT_FREQUENCY,
+        amplitude: DEFAULT_AMPLITUDE,
+    };
+
+    /// The sources, first putting them a third of `half_width` either side of
+    /// `center` if they have not been placed yet. For the whole frame that is
+    /// 1/3 and 2/3 of the way across.
+    pub fn place(&mut self, center: Position, half_width: f32) -> [Position; 2] {
+        *self.sources.get_or_insert_with(|| {
+            let offset = Velocity::new(half_width / 3.0, 0.0);
+            [center - offset, center + offset]
+        })
+    }
+
+    /// Phase of a wave `distance` pixels from its source at `time`
+    fn phase(&self, distance: f32, time: f32) -> f32 {
+        TAU * (distance / WAVELENGTH - self.frequency * time)
+    }
+
+    /// Summed displacement of both waves at `p`
+    pub fn value(&self, sources: [Position; 2], p: Position, time: f32) -> f32 {
+        sources
+            .iter()
+            .map(|&source| self.amplitude * self.phase(p.distance(source), time).sin())
+            .sum()
+    }
+
+    /// Gradient of `value` at `p`, pointing where the field rises fastest.
+    /// Each wave's slope runs along the line from its source.
+    pub fn gradient(&self, sources: [Position; 2], p: Position, time: f32) -> Velocity {
+        let wavenumber = TAU / WAVELENGTH;
+        sources.iter().fold(Velocity::ZERO, |sum, &source| {
+            let offset = p - source;
+            let distance = offset.length();
+            if distance < f32::EPSILON {
+                return sum;
+            }
+            let slope = self.amplitude * wavenumber * self.phase(distance, time).cos();
+            sum + offset / distance * slope
+        })
+    }
+
+    /// Acceleration of an endpoint at `p`: down the field's slope
+    pub fn force(&self, sources: [Position; 2], p: Position, time: f32) -> Velocity {
+        self.gradient(sources, p, time) * -FORCE_SCALE
+    }
+
+    /// Index of the source nearer to `p`, or None before they are placed
+    pub fn nearest_source(&self, p: Position) -> Option<usize> {
+        let [a, b] = self.sources?;
+        Some(if p.distance(a) <= p.distance(b) { 0 } else { 1 })
+    }
+
+    pub fn adjust_frequency(&mut self, delta: f32) -> f32 {
+        self.frequency = (self.frequency + delta).clamp(MIN_FREQUENCY, MAX_FREQUENCY);
+        self.frequency
+    }
+
+    pub fn adjust_amplitude(&mut self, delta: f32) -> f32 {
+        self.amplitude = (self.amplitude + delta).clamp(MIN_AMPLITUDE, MAX_AMPLITUDE);
+        self.amplitude
+    }
+}
+
+impl Default for WaveField {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCES: [Position; 2] = [Position { x: 0.0, y: 0.0 }, Position { x: 700.0, y: 0.0 }];
+
+    #[test]
+    fn test_waves_add_up_and_cancel() {
+        let field = WaveField::DEFAULT;
+        // Midway, both waves arrive in phase and the sum doubles
+        let middle = Position::new(350.0, 0.0);
+        for time in [0.0, 0.1, 0.37] {
+            let one = field.amplitude * field.phase(350.0, time).sin();
+            assert!((field.value(SOURCES, middle, time) - 2.0 * one).abs() < 1e-4);
+        }
+        // Half a wavelength off center the path difference is one wavelength:
+        // constructive again. A quarter off it is half a wavelength: they cancel.
+        let node = Position::new(350.0 + WAVELENGTH / 4.0, 0.0);
+        for time in [0.0, 0.2, 0.45, 1.3] {
+            assert!(field.value(SOURCES, node, time).abs() < 1e-3);
+        }
+
+        let mut quiet = field;
+        quiet.amplitude = 0.0;
+        assert_eq!(quiet.value(SOURCES, middle, 0.3), 0.0);
+        assert_eq!(quiet.gradient(SOURCES, middle, 0.3), Velocity::ZERO);
+    }
+
+    #[test]
+    fn test_gradient_points_uphill_along_the_rays() {
+        let field = WaveField::DEFAULT;
+        let sources = [Position::new(0.0, 0.0), Position::new(0.0, 0.0)];
+        // At a quarter wavelength and time 0 the phase is pi/2: a crest, slope zero.
+        // An eighth of a wavelength in, the field is still rising outward.
+        let rising = Position::new(0.0, WAVELENGTH / 8.0);
+        let gradient = field.gradient(sources, rising, 0.0);
+        assert!(
+            gradient.x.abs() < 1e-6 && gradient.y > 0.0,
+            "{:?}",
+            gradient
+        );
+        assert!(field.force(sources, rising, 0.0).y < 0.0);
+        let crest = Position::new(WAVELENGTH / 4.0, 0.0);
+        assert!(field.gradient(sources, crest, 0.0).length() < 1e-4);
+
+        // Matches a finite difference of the field anywhere
+        let field = WaveField {
+            sources: Some(SOURCES),
+            ..WaveField::DEFAULT
+        };
+        let p = Position::new(233.0, 151.0);
+        let h = 0.01;
+        let numeric = Velocity::new(
+            (field.value(SOURCES, Position::new(p.x + h, p.y), 0.7)
+                - field.value(SOURCES, Position::new(p.x - h, p.y), 0.7))
+                / (2.0 * h),
+            (field.value(SOURCES, Position::new(p.x, p.y + h), 0.7)
+                - field.value(SOURCES, Position::new(p.x, p.y - h), 0.7))
+                / (2.0 * h),
+        );
+        let analytic = field.gradient(SOURCES, p, 0.7);
+        assert!(
+            (numeric - analytic).length() < 1e-3,
+            "{:?} vs {:?}",
+            numeric,
+            analytic
+        );
+        assert_eq!(field.nearest_source(Position::new(600.0, 50.0)), Some(1));
+    }
+}
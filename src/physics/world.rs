@@ -0,0 +1,1377 @@
+use crate::audio::audio_handler::{smooth_toward, AUDIO_VIZ_BARS};
+use crate::audio::features::frame_spectrum;
+use crate::core::persist::{PersistError, PersistentState};
+use crate::core::presets::{lerp, switch_at_midpoint, Interpolate};
+use crate::core::sim_rng::sim_rng;
+use crate::core::snapshot::Snapshottable;
+use crate::core::station_state;
+use crate::core::types::{
+    color_to_rgba, hsv_to_rgb, Color, Line, Particle, Position, Velocity, VisualMode, World,
+    MAX_LINES,
+};
+use crate::graphics::background::{Background, BackgroundKind};
+use crate::graphics::light_grid::{lit_color, LightGrid};
+use crate::graphics::draw_ctx::DrawCtx;
+use crate::graphics::render::{draw_circle_aa, draw_line_aa};
+use crate::graphics::view::ViewTransform;
+use crate::physics::arena::{self, Arena, ArenaShape, Boundary};
+use crate::physics::drawing::DrawingLayer;
+use crate::physics::flock::{self, Agent, Flock, FlockWeights, MouseRole};
+use crate::physics::forces::{self, force_field, ForceField, GravityMode};
+use crate::physics::sanitize::{self, is_sane};
+use crate::physics::waves::{WaveField, AMPLITUDE_STEP, FREQUENCY_STEP, WAVE_DAMPING};
+use log::warn;
+use rand::Rng;
+use serde_json::{json, Value};
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+/// How quickly a line thickens toward a louder band (per second)
+const WIDTH_ATTACK: f32 = 12.0;
+/// How quickly a line eases back toward its base width (per second)
+const WIDTH_RELEASE: f32 = 5.0;
+/// Pull that keeps both ends of a line near its rest length
+const SPRING_STIFFNESS: f32 = 0.02;
+/// Speed cap per line endpoint, in pixels per 60 Hz step
+const MAX_ENDPOINT_SPEED: f32 = 6.0;
+/// Speed cap per particle, in pixels per 60 Hz step
+const MAX_PARTICLE_SPEED: f32 = 8.0;
+/// Band level past which lines widen no further, so a runaway spectrum can't
+/// blow the widths up
+const MAX_WIDTH_LEVEL: f32 = 4.0;
+/// Particles dropped in per second while gravity is on
+const RAIN_PER_SECOND: f32 = 40.0;
+/// Lifetime of a rain particle, long enough to cross the screen
+const RAIN_LIFE: f32 = 4.0;
+/// Upper bound on live particles
+const MAX_PARTICLES: usize = 400;
+/// Distance the cursor must travel while held before another line is spawned
+const SPAWN_SPACING: f32 = 12.0;
+/// Width of mouse-spawned lines
+const SPAWNED_LINE_WIDTH: f32 = 2.5;
+/// Lines kept while in Waves mode, enough for the interference bands to read
+const WAVE_LINE_COUNT: usize = 240;
+/// Radius of the rings marking the wave sources
+const SOURCE_MARKER_RADIUS: f32 = 6.0;
+/// Faint outline drawn around the circle and hexagon arenas
+const ARENA_OUTLINE_COLOR: [u8; 4] = [255, 255, 255, 40];
+const ARENA_OUTLINE_WIDTH: f32 = 1.5;
+/// Upper bound on each Flock mode weight
+pub const MAX_FLOCK_WEIGHT: f32 = 5.0;
+
+/// Settings for audio-reactive line thickness.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AudioWidthSettings {
+    pub enabled: bool,
+    /// How much a full-scale band widens a line, as a multiple of its base width
+    pub sensitivity: f32,
+}
+
+impl AudioWidthSettings {
+    pub const DEFAULT: Self = Self {
+        enabled: true,
+        sensitivity: 1.5,
+    };
+}
+
+impl Interpolate for AudioWidthSettings {
+    fn interpolate(&self, to: &Self, t: f32) -> Self {
+        Self {
+            enabled: switch_at_midpoint(self.enabled, to.enabled, t),
+            sensitivity: lerp(self.sensitivity, to.sensitivity, t),
+        }
+    }
+}
+
+pub struct WorldState {
+    world: World,
+    background: Option<Background>,
+    drawing: DrawingLayer,
+    /// Particle light for the current frame, used while `lighting` is on
+    light: LightGrid,
+    lighting: bool,
+    // Where the last mouse-spawned line ended; None while the button is up
+    last_spawn: Option<Position>,
+    last_time: Option<f32>,
+    /// Zoom and pan the World is drawn through
+    view: ViewTransform,
+    // Screen position of the middle-button drag last frame; None while it is up
+    last_pan: Option<Position>,
+}
+
+impl World {
+    /// Scales lines, particles, and wave sources about the origin by `factor`
+    pub fn scale(&mut self, factor: f32) {
+        for line in &mut self.lines {
+            for end in 0..2 {
+                line.pos[end] *= factor;
+                line.vel[end] *= factor;
+            }
+            line.width *= factor;
+            line.audio_width = line.audio_width.map(|width| width * factor);
+            line.length *= factor;
+        }
+        for particle in &mut self.particles {
+            particle.pos *= factor;
+            particle.vel *= factor;
+            particle.size *= factor;
+        }
+        if let Some(pos) = self.mouse_pos.as_mut() {
+            *pos *= factor;
+        }
+        if let Some(sources) = self.waves.sources.as_mut() {
+            for source in sources {
+                *source *= factor;
+            }
+        }
+    }
+
+    /// Creates a world with `line_count` randomly placed lines (capped at MAX_LINES).
+    /// The Waves mode raises the target to `WAVE_LINE_COUNT` for as long as it is on.
+    pub fn new(line_count: usize) -> Self {
+        let mut rng = sim_rng();
+        let target_line_count = line_count.min(MAX_LINES);
+        Self {
+            lines: (0..target_line_count)
+                .map(|_| Line::new(&mut rng))
+                .collect(),
+            particles: Vec::new(),
+            mouse_pos: None,
+            mouse_active: false,
+            background_color: Color::new(5, 5, 10),
+            background: BackgroundKind::default(),
+            mode: VisualMode::Normal,
+            target_line_count,
+            lines_before_waves: None,
+            start_time: Instant::now(),
+            waves: WaveField::DEFAULT,
+            flock: Flock::new(),
+            arena: ArenaShape::Rect,
+        }
+    }
+
+    /// Advances lines and particles by `dt` seconds inside the arena fitted to a
+    /// `width` x `height` area, under the gravity and wind of `field`.
+    pub fn update(&mut self, width: u32, height: u32, dt: f32, field: &ForceField) {
+        let step = dt * 60.0;
+        let arena = self.arena.fit(width, height);
+        let center = arena.center();
+        let elapsed = self.start_time.elapsed().as_secs_f32();
+        let environment = field.acceleration(elapsed);
+        let restitution = field.restitution();
+        let wave_sources = self.waves.place(center, arena.half_width());
+        // What comes in broken is reset quietly; what this update breaks is reported
+        self.sanitize(&arena);
+
+        if self.mode == VisualMode::Flock {
+            self.update_flock(&arena, step, environment, restitution);
+        } else {
+            for line in &mut self.lines {
+                for end in 0..2 {
+                    let mut force = Velocity::ZERO;
+                    match self.mode {
+                        VisualMode::Vortex => {
+                            let offset = line.pos[end] - center;
+                            force += Velocity::new(-offset.y, offset.x).normalize_or_zero() * 0.05;
+                        }
+                        VisualMode::Waves => {
+                            force += self.waves.force(wave_sources, line.pos[end], elapsed);
+                            line.vel[end] *= 1.0 - WAVE_DAMPING * step;
+                        }
+                        VisualMode::Normal | VisualMode::Rainbow | VisualMode::Flock => {}
+                    }
+                    if self.mouse_active {
+                        if let Some(mouse) = self.mouse_pos {
+                            let to_mouse = mouse - line.pos[end];
+                            let dist = to_mouse.length();
+                            if dist > 1.0 {
+                                force += sanitize::safe_normalize(to_mouse)
+                                    * sanitize::inv_dist_clamped(40.0, dist, 0.5);
+                            }
+                        }
+                    }
+                    line.vel[end] += (force + environment) * step;
+                }
+
+                // Spring between the two endpoints; ends on top of each other
+                // have no direction to pull in
+                let delta = line.pos[1] - line.pos[0];
+                let pull = sanitize::safe_normalize(delta)
+                    * (delta.length() - line.length)
+                    * SPRING_STIFFNESS;
+                line.vel[0] += pull * step;
+                line.vel[1] -= pull * step;
+
+                for end in 0..2 {
+                    line.vel[end] = line.vel[end].clamp_length_max(MAX_ENDPOINT_SPEED);
+                    line.pos[end] += line.vel[end] * step;
+                    arena::collide(&arena, &mut line.pos[end], &mut line.vel[end], restitution);
+                }
+            }
+        }
+
+        if field.gravity != GravityMode::Off {
+            self.spawn_rain(&arena, height, dt, field.gravity);
+        }
+        // Rain falls out through the frame's edges; the other arenas hold it in
+        let closed = self.arena != ArenaShape::Rect;
+        for particle in &mut self.particles {
+            particle.vel += environment * step;
+            particle.vel = particle.vel.clamp_length_max(MAX_PARTICLE_SPEED);
+            particle.pos += particle.vel * step;
+            if closed {
+                arena::collide(&arena, &mut particle.pos, &mut particle.vel, restitution);
+            }
+            particle.vel *= 0.98;
+            particle.life -= dt;
+        }
+        self.particles.retain(|p| p.life > 0.0);
+
+        // Ease the line count toward the target, one line per update
+        if self.lines.len() < self.target_line_count {
+            let mut line = Line::new(&mut sim_rng());
+            line.pos = line.pos.map(|end| arena.project_inside(end));
+            self.lines.push(line);
+        } else if self.lines.len() > self.target_line_count {
+            self.lines.remove(0);
+        }
+        sanitize::report_produced("world", self.sanitize(&arena));
+    }
+
+    /// Puts lines holding NaN, infinity, or far-off coordinates back inside
+    /// `arena` as new lines, and drops such particles. Returns how many.
+    fn sanitize(&mut self, arena: &Arena) -> usize {
+        if self.mouse_pos.is_some_and(|mouse| !is_sane(mouse)) {
+            self.mouse_pos = None;
+        }
+        let mut rng = sim_rng();
+        let mut broken = 0;
+        for line in &mut self.lines {
+            let sane = line.pos.into_iter().chain(line.vel).all(is_sane)
+                && line.length.abs() < sanitize::MAX_COORD
+                && line.width.is_finite();
+            if !sane {
+                *line = Line::new(&mut rng);
+                line.pos = line.pos.map(|end| arena.project_inside(end));
+                broken += 1;
+            }
+        }
+        let particles = self.particles.len();
+        self.particles
+            .retain(|p| is_sane(p.pos) && is_sane(p.vel) && p.life.is_finite());
+        broken + particles - self.particles.len()
+    }
+
+    /// Moves each line as a boid: its midpoint steers with the flock and the
+    /// line turns to point along its velocity
+    fn update_flock(&mut self, arena: &Arena, step: f32, environment: Velocity, restitution: f32) {
+        let agents: Vec<Agent> = self.lines.iter().map(Agent::of_line).collect();
+        let steering = self
+            .flock
+            .steer(&agents, flock_weights(), self.mouse_pos, arena);
+        for ((line, agent), steer) in self.lines.iter_mut().zip(agents).zip(steering) {
+            let mut vel = flock::limit_speed(agent.vel + (steer + environment) * step);
+            let mut mid = agent.pos + vel * step;
+            arena::collide(arena, &mut mid, &mut vel, restitution);
+            let half = vel.normalize_or(Velocity::X) * line.length / 2.0;
+            line.pos = [mid - half, mid + half];
+            line.vel = [vel; 2];
+            // The midpoint is inside, but the ends of the line around it may not be
+            for (end, end_vel) in line.pos.iter_mut().zip(&mut line.vel) {
+                arena::collide(arena, end, end_vel, restitution);
+            }
+        }
+    }
+
+    /// Drops particles in from the edge gravity pulls away from, up to MAX_PARTICLES.
+    /// Each starts at the arena point nearest its spot on the frame edge.
+    fn spawn_rain(&mut self, arena: &Arena, height: u32, dt: f32, gravity: GravityMode) {
+        let mut rng = sim_rng();
+        let expected = RAIN_PER_SECOND * dt;
+        let count = expected as usize + rng.gen_bool(expected.fract() as f64) as usize;
+        let y = if gravity == GravityMode::Up {
+            height as f32
+        } else {
+            0.0
+        };
+        let (middle, half) = (arena.center().x, arena.half_width());
+        for _ in 0..count {
+            if self.particles.len() >= MAX_PARTICLES {
+                break;
+            }
+            let x = rng.gen_range(middle - half..middle + half);
+            let mut drop = Particle::new(arena.project_inside(Position::new(x, y)), &mut rng);
+            drop.vel *= 0.2;
+            drop.life = RAIN_LIFE;
+            self.particles.push(drop);
+        }
+    }
+
+    /// Sets what is drawn under the lines. Images are loaded on the next draw.
+    pub fn set_background(&mut self, kind: BackgroundKind) {
+        self.background = kind;
+    }
+
+    /// Cycles Normal -> Vortex -> Waves -> Rainbow -> Flock -> Normal. The Waves mode
+    /// fills in more lines so its interference pattern has enough to show on.
+    pub fn next_mode(&mut self) {
+        self.mode = match self.mode {
+            VisualMode::Normal => VisualMode::Vortex,
+            VisualMode::Vortex => {
+                self.lines_before_waves = Some(self.target_line_count);
+                self.target_line_count = self.target_line_count.max(WAVE_LINE_COUNT);
+                VisualMode::Waves
+            }
+            VisualMode::Waves => {
+                if let Some(count) = self.lines_before_waves.take() {
+                    self.target_line_count = count;
+                }
+                VisualMode::Rainbow
+            }
+            VisualMode::Rainbow => VisualMode::Flock,
+            VisualMode::Flock => VisualMode::Normal,
+        };
+    }
+
+    /// Color used to draw the line at `index` at the current moment.
+    fn line_color(&self, index: usize) -> [u8; 4] {
+        let line = &self.lines[index];
+        let t = self.start_time.elapsed().as_secs_f32() * line.cycle_speed + line.cycle_offset;
+        if self.mode == VisualMode::Rainbow {
+            color_to_rgba(hsv_to_rgb(t.rem_euclid(1.0), 0.8, 1.0))
+        } else {
+            let pulse = 0.7 + 0.3 * t.sin();
+            [
+                (line.color.red as f32 * pulse) as u8,
+                (line.color.green as f32 * pulse) as u8,
+                (line.color.blue as f32 * pulse) as u8,
+                255,
+            ]
+        }
+    }
+}
+
+/// Captured World lines, particles, and mode. The clock is stored as elapsed time so
+/// color cycling resumes where it was captured.
+#[derive(Debug, Clone)]
+pub struct WorldSnapshot {
+    pub lines: Vec<Line>,
+    pub particles: Vec<Particle>,
+    pub mode: VisualMode,
+    pub target_line_count: usize,
+    pub lines_before_waves: Option<usize>,
+    pub waves: WaveField,
+    pub elapsed: Duration,
+}
+
+impl Snapshottable for World {
+    type Snapshot = WorldSnapshot;
+
+    fn snapshot(&self) -> WorldSnapshot {
+        WorldSnapshot {
+            lines: self.lines.clone(),
+            particles: self.particles.clone(),
+            mode: self.mode,
+            target_line_count: self.target_line_count,
+            lines_before_waves: self.lines_before_waves,
+            waves: self.waves,
+            elapsed: self.start_time.elapsed(),
+        }
+    }
+
+    fn restore(&mut self, snapshot: &WorldSnapshot) {
+        self.lines = snapshot.lines.clone();
+        self.particles = snapshot.particles.clone();
+        self.mode = snapshot.mode;
+        self.target_line_count = snapshot.target_line_count;
+        self.lines_before_waves = snapshot.lines_before_waves;
+        self.waves = snapshot.waves;
+        self.start_time = Instant::now()
+            .checked_sub(snapshot.elapsed)
+            .unwrap_or_else(Instant::now);
+    }
+}
+
+/// Spectrum band that drives the line at `index`.
+/// Line `i` follows spectrum band `i % AUDIO_VIZ_BARS`.
+pub fn band_for_line(index: usize) -> usize {
+    index % AUDIO_VIZ_BARS
+}
+
+/// Eases every line's audio width toward its target. Without a spectrum (or with the
+/// effect disabled) widths ease back to each line's base width.
+pub fn update_line_widths(
+    lines: &mut [Line],
+    spectrum: Option<&[f32]>,
+    settings: AudioWidthSettings,
+    dt: f32,
+) {
+    for (i, line) in lines.iter_mut().enumerate() {
+        let level = match spectrum {
+            Some(bands) if settings.enabled => bands.get(band_for_line(i)).copied().unwrap_or(0.0),
+            _ => 0.0,
+        };
+        let level = sanitize::clamp_finite(level, 0.0, MAX_WIDTH_LEVEL);
+        let target = line.width * (1.0 + settings.sensitivity * level);
+        let current = line
+            .audio_width
+            .filter(|w| w.is_finite())
+            .unwrap_or(line.width);
+        let eased = smooth_toward(current, target, dt, WIDTH_ATTACK, WIDTH_RELEASE);
+        line.audio_width = Some(eased);
+    }
+}
+
+pub fn set_world_enabled(enabled: bool) {
+    station_state::current()
+        .world_enabled
+        .store(enabled, Ordering::SeqCst);
+}
+
+pub fn is_world_enabled() -> bool {
+    station_state::current()
+        .world_enabled
+        .load(Ordering::SeqCst)
+}
+
+/// Runs `f` on the World of the station being driven, None until it is first shown
+fn with_world_state<R>(f: impl FnOnce(&mut Option<WorldState>) -> R) -> R {
+    f(&mut station_state::lock(&station_state::current().world))
+}
+
+/// Runs `f` on the audio width settings of the station being driven
+fn with_audio_width<R>(f: impl FnOnce(&mut AudioWidthSettings) -> R) -> R {
+    f(&mut station_state::lock(
+        &station_state::current().audio_width,
+    ))
+}
+
+pub fn audio_width_settings() -> AudioWidthSettings {
+    with_audio_width(|settings| *settings)
+}
+
+pub fn set_audio_width_settings(settings: AudioWidthSettings) {
+    with_audio_width(|current| {
+        *current = AudioWidthSettings {
+            sensitivity: settings.sensitivity.clamp(0.0, 5.0),
+            ..settings
+        }
+    });
+}
+
+pub fn toggle_audio_width() -> bool {
+    with_audio_width(|settings| {
+        settings.enabled = !settings.enabled;
+        settings.enabled
+    })
+}
+
+/// Adjusts the audio width sensitivity, keeping it within 0.0..=5.0.
+pub fn adjust_audio_width_sensitivity(delta: f32) -> f32 {
+    with_audio_width(|settings| {
+        settings.sensitivity = (settings.sensitivity + delta).clamp(0.0, 5.0);
+        settings.sensitivity
+    })
+}
+
+pub fn flock_weights() -> FlockWeights {
+    *station_state::lock(&station_state::current().flock_weights)
+}
+
+/// Sets the Flock mode's rule weights, each kept within 0.0..=MAX_FLOCK_WEIGHT
+pub fn set_flock_weights(weights: FlockWeights) {
+    let clamp = |weight: f32| weight.clamp(0.0, MAX_FLOCK_WEIGHT);
+    *station_state::lock(&station_state::current().flock_weights) = FlockWeights {
+        separation: clamp(weights.separation),
+        alignment: clamp(weights.alignment),
+        cohesion: clamp(weights.cohesion),
+    };
+}
+
+pub fn arena_shape() -> ArenaShape {
+    *station_state::lock(&station_state::current().arena_shape)
+}
+
+pub fn set_arena_shape(shape: ArenaShape) {
+    *station_state::lock(&station_state::current().arena_shape) = shape;
+}
+
+/// Moves the World to the next arena shape and returns it
+pub fn cycle_arena_shape() -> ArenaShape {
+    let shape = arena_shape().next();
+    set_arena_shape(shape);
+    shape
+}
+
+/// Switches the cursor between scattering the flock and drawing it in.
+/// None unless the World is in Flock mode.
+pub fn toggle_flock_mouse() -> Option<MouseRole> {
+    with_world_state(|state| {
+        let state = state
+            .as_mut()
+            .filter(|state| state.world.mode == VisualMode::Flock)?;
+        state.world.flock.mouse = state.world.flock.mouse.toggled();
+        Some(state.world.flock.mouse)
+    })
+}
+
+pub fn next_world_mode() {
+    with_world_state(|state| {
+        if let Some(state) = state.as_mut() {
+            state.world.next_mode();
+        }
+    });
+}
+
+/// Live World particles, rain included
+pub fn particle_count() -> usize {
+    with_world_state(|state| {
+        state
+            .as_ref()
+            .map_or(0, |state| state.world.particles.len())
+    })
+}
+
+pub fn is_waves_mode() -> bool {
+    with_world_state(|state| {
+        state
+            .as_ref()
+            .is_some_and(|state| state.world.mode == VisualMode::Waves)
+    })
+}
+
+/// Steps the wave frequency up or down. None unless the World is in Waves mode.
+pub fn adjust_wave_frequency(steps: f32) -> Option<f32> {
+    with_waves(|waves| waves.adjust_frequency(steps * FREQUENCY_STEP))
+}
+
+/// Steps the wave amplitude up or down. None unless the World is in Waves mode.
+pub fn adjust_wave_amplitude(steps: f32) -> Option<f32> {
+    with_waves(|waves| waves.adjust_amplitude(steps * AMPLITUDE_STEP))
+}
+
+/// Runs `f` on the wave field. None unless the World is in Waves mode.
+fn with_waves<R>(f: impl FnOnce(&mut WaveField) -> R) -> Option<R> {
+    with_world_state(|state| {
+        state
+            .as_mut()
+            .filter(|state| state.world.mode == VisualMode::Waves)
+            .map(|state| f(&mut state.world.waves))
+    })
+}
+
+/// Captures the World, or None if it has never been shown.
+pub fn snapshot_world() -> Option<WorldSnapshot> {
+    with_world_state(|state| state.as_ref().map(|state| state.world.snapshot()))
+}
+
+/// Scales the World to a frame `factor` times the size, smoothed line widths
+/// included. Painted strokes keep their size.
+pub fn scale_world(factor: f32) {
+    with_world_state(|state| {
+        if let Some(state) = state.as_mut() {
+            state.world.scale(factor);
+            state.view.offset *= factor;
+            if let Some(pos) = state.last_pan.as_mut() {
+                *pos *= factor;
+            }
+        }
+    });
+}
+
+/// Restores the World from a snapshot. Smoothed line widths are rebuilt from scratch.
+pub fn restore_world(snapshot: &WorldSnapshot) {
+    with_world_state(|state| {
+        let state = state.get_or_insert_with(new_world_state);
+        state.world.restore(snapshot);
+        for line in &mut state.world.lines {
+            line.audio_width = None;
+        }
+        state.last_time = None;
+    });
+}
+
+fn new_world_state() -> WorldState {
+    WorldState {
+        world: World::new(MAX_LINES / 2),
+        background: None,
+        drawing: DrawingLayer::new(),
+        light: LightGrid::new(),
+        lighting: false,
+        last_spawn: None,
+        last_time: None,
+        view: ViewTransform::IDENTITY,
+        last_pan: None,
+    }
+}
+
+pub fn set_paint_mode(enabled: bool) {
+    station_state::current()
+        .paint_mode
+        .store(enabled, Ordering::SeqCst);
+}
+
+pub fn is_paint_mode() -> bool {
+    station_state::current().paint_mode.load(Ordering::SeqCst)
+}
+
+/// Flips paint mode and returns the new state
+pub fn toggle_paint_mode() -> bool {
+    !station_state::current()
+        .paint_mode
+        .fetch_xor(true, Ordering::SeqCst)
+}
+
+/// Removes the most recent painted stroke. Returns false when there was none.
+pub fn undo_stroke() -> bool {
+    with_world_state(|state| state.as_mut().is_some_and(|state| state.drawing.undo()))
+}
+
+/// Removes every painted stroke
+pub fn clear_drawing() {
+    with_world_state(|state| {
+        if let Some(state) = state.as_mut() {
+            state.drawing.clear();
+        }
+    });
+}
+
+/// Painted strokes and forces, for scene persistence
+pub fn save_scene_state() -> Value {
+    let strokes = with_world_state(|state| {
+        state
+            .as_ref()
+            .map_or(json!([]), |state| state.drawing.save_state())
+    });
+    json!({ "strokes": strokes, "forces": force_field().save_state() })
+}
+
+/// Restores what `save_scene_state` stored. Nothing changes if any part is invalid.
+pub fn load_scene_state(blob: &Value) -> Result<(), PersistError> {
+    let mut field = ForceField::default();
+    if let Some(forces) = blob.get("forces") {
+        field.load_state(forces)?;
+    }
+    let mut drawing = DrawingLayer::new();
+    if let Some(strokes) = blob.get("strokes") {
+        drawing.load_state(strokes)?;
+    }
+    with_world_state(|state| state.get_or_insert_with(new_world_state).drawing = drawing);
+    forces::set_force_field(field);
+    Ok(())
+}
+
+/// Clears the drawing, turns gravity and wind off, and resets the view
+pub fn reset_scene_state() {
+    clear_drawing();
+    reset_view();
+    forces::set_force_field(ForceField::default());
+}
+
+/// Feeds the mouse into the World. While the button is held the cursor attracts
+/// lines and leaves a trail of new lines; in paint mode the trail is frozen into
+/// the drawing layer instead, one stroke per hold. In Waves mode a held drag
+/// moves the nearer wave source instead.
+pub fn handle_mouse(cursor: Option<(f32, f32)>, held: bool) {
+    if !is_world_enabled() {
+        return;
+    }
+    with_world_state(|state| {
+        let state = state.get_or_insert_with(new_world_state);
+        let elapsed = state.world.start_time.elapsed().as_secs_f32();
+        apply_mouse(state, cursor, held, is_paint_mode(), elapsed);
+    });
+}
+
+fn apply_mouse(
+    state: &mut WorldState,
+    cursor: Option<(f32, f32)>,
+    held: bool,
+    paint: bool,
+    elapsed: f32,
+) {
+    // The mouse acts on the point of the World under it, wherever the view is
+    let cursor = cursor.map(|(x, y)| state.view.to_world(Position::new(x, y)));
+    state.world.mouse_pos = cursor;
+    if state.world.mode == VisualMode::Waves && !paint {
+        state.world.mouse_active = false;
+        state.last_spawn = None;
+        if let (Some(pos), true) = (cursor, held) {
+            drag_wave_source(&mut state.world.waves, pos);
+        }
+        return;
+    }
+    state.world.mouse_active = held && !paint;
+
+    let pos = match cursor {
+        Some(pos) if held => pos,
+        _ => {
+            if state.last_spawn.take().is_some() {
+                state.drawing.end_stroke();
+            }
+            return;
+        }
+    };
+    match state.last_spawn {
+        None => {
+            if paint {
+                state.drawing.begin_stroke();
+            }
+            state.last_spawn = Some(pos);
+        }
+        Some(last) if last.distance(pos) >= SPAWN_SPACING => {
+            let line = line_between(last, pos, elapsed);
+            if paint {
+                state.drawing.add_line(line);
+            } else {
+                state.world.lines.push(line);
+            }
+            state.last_spawn = Some(pos);
+        }
+        Some(_) => {}
+    }
+}
+
+/// Zooms the view by `lines` scroll-wheel lines, keeping the point under
+/// `anchor` (in frame pixels) in place
+pub fn zoom_view(anchor: (f32, f32), lines: f32) {
+    if !is_world_enabled() {
+        return;
+    }
+    with_world_state(|state| {
+        let state = state.get_or_insert_with(new_world_state);
+        state.view.zoom_about(anchor.into(), lines);
+    });
+}
+
+/// Pans the view by however far the cursor moved since the last frame while
+/// `held` (the middle button) stays down
+pub fn pan_view(cursor: Option<(f32, f32)>, held: bool) {
+    if !is_world_enabled() {
+        return;
+    }
+    with_world_state(|state| {
+        let state = state.get_or_insert_with(new_world_state);
+        let cursor = cursor.filter(|_| held).map(Position::from);
+        if let (Some(last), Some(pos)) = (state.last_pan, cursor) {
+            state.view.pan(pos - last);
+        }
+        state.last_pan = cursor;
+    });
+}
+
+pub fn reset_view() {
+    with_world_state(|state| {
+        if let Some(state) = state.as_mut() {
+            state.view = ViewTransform::IDENTITY;
+        }
+    });
+}
+
+pub fn world_view() -> ViewTransform {
+    with_world_state(|state| {
+        state
+            .as_ref()
+            .map_or(ViewTransform::IDENTITY, |state| state.view)
+    })
+}
+
+/// Moves the source nearer to `pos` onto it. Sources not yet placed stay put.
+fn drag_wave_source(waves: &mut WaveField, pos: Position) {
+    if let (Some(index), Some(sources)) = (waves.nearest_source(pos), waves.sources.as_mut()) {
+        sources[index] = pos;
+    }
+}
+
+/// A motionless line from `a` to `b`, its hue drifting with time so strokes vary
+fn line_between(a: Position, b: Position, elapsed: f32) -> Line {
+    let mut line = Line::new(&mut sim_rng());
+    line.pos = [a, b];
+    line.vel = [Velocity::ZERO; 2];
+    line.length = a.distance(b);
+    line.width = SPAWNED_LINE_WIDTH;
+    line.color = hsv_to_rgb((elapsed * 0.05).rem_euclid(1.0), 0.8, 1.0);
+    line
+}
+
+/// Sets the World background; used by settings and the `--background` flag.
+pub fn set_world_background(kind: BackgroundKind) {
+    with_world_state(|state| {
+        state
+            .get_or_insert_with(new_world_state)
+            .world
+            .set_background(kind)
+    });
+}
+
+/// Returns the prepared background, re-rendering it when the kind or frame size changed.
+/// A background that fails to load is replaced by the default solid colour.
+fn prepared_background(state: &mut WorldState, width: u32, height: u32) -> &Background {
+    let stale = !matches!(
+        &state.background,
+        Some(background) if background.matches(&state.world.background, width, height)
+    );
+    if stale {
+        let prepared = match Background::prepare(&state.world.background, width, height) {
+            Ok(background) => background,
+            Err(e) => {
+                warn!(
+                    "Could not load background {:?}: {}; using solid colour",
+                    state.world.background, e
+                );
+                state.world.background = BackgroundKind::default();
+                Background::prepare(&state.world.background, width, height)
+                    .expect("solid backgrounds always prepare")
+            }
+        };
+        state.background = Some(prepared);
+    }
+    state.background.as_ref().unwrap()
+}
+
+/// Updates and draws the World lines when the World layer is enabled.
+pub fn update_and_draw_world(
+    frame: &mut [u8],
+    width: u32,
+    height: u32,
+    time: f32,
+    x_offset: usize,
+    buffer_width: u32,
+    lighting: bool,
+) {
+    if !is_world_enabled() {
+        return;
+    }
+
+    let (arena, audio_width) = (arena_shape(), audio_width_settings());
+    with_world_state(|state| {
+        let state = state.get_or_insert_with(new_world_state);
+        let dt = match state.last_time {
+            Some(last) => (time - last).clamp(0.0, 0.1),
+            None => 0.016,
+        };
+        state.last_time = Some(time);
+
+        // The wave sources are placed again to suit a new arena
+        if state.world.arena != arena {
+            state.world.arena = arena;
+            state.world.waves.sources = None;
+        }
+        forces::decay_gust(dt);
+        state.world.update(width, height, dt, &force_field());
+        let spectrum = frame_spectrum();
+        update_line_widths(&mut state.world.lines, spectrum.as_deref(), audio_width, dt);
+
+        state.lighting = lighting;
+        draw_world_layers(state, frame, width, height, time, x_offset, buffer_width);
+    });
+}
+
+/// Draws the background, then the painted drawing layer, then the arena outline and
+/// the live lines and particles.
+/// With lighting on, each line is tinted by the particle light at its midpoint.
+fn draw_world_layers(
+    state: &mut WorldState,
+    frame: &mut [u8],
+    width: u32,
+    height: u32,
+    time: f32,
+    x_offset: usize,
+    buffer_width: u32,
+) {
+    let lighting = state.lighting;
+    let view = state.view;
+    prepared_background(state, width, height).draw(frame, time, x_offset, buffer_width);
+    state
+        .drawing
+        .composite(frame, width, height, x_offset, buffer_width, &view);
+
+    if lighting {
+        state.light.clear(width, height);
+        for particle in &state.world.particles {
+            let color = color_to_rgba(particle.color);
+            state.light.add_light(
+                particle.pos.x,
+                particle.pos.y,
+                [color[0], color[1], color[2]],
+                particle.life,
+            );
+        }
+        state.light.blur();
+    }
+
+    // Positions stay fractional so slow lines and particles glide rather than step.
+    // They go through the view as they are drawn, and what it puts off screen is skipped.
+    let mut ctx = DrawCtx::from_legacy(frame, width, height, time, x_offset, buffer_width);
+    let outline = state.world.arena.fit(width, height).outline();
+    for (i, &corner) in outline.iter().enumerate() {
+        let next = outline[(i + 1) % outline.len()];
+        let (a, b) = (view.to_screen(corner), view.to_screen(next));
+        draw_line_aa(
+            &mut ctx,
+            a.into(),
+            b.into(),
+            ARENA_OUTLINE_WIDTH,
+            &ARENA_OUTLINE_COLOR,
+        );
+    }
+    for (i, line) in state.world.lines.iter().enumerate() {
+        let thickness = line.drawn_width() * view.zoom;
+        let (a, b) = (view.to_screen(line.pos[0]), view.to_screen(line.pos[1]));
+        if !ViewTransform::segment_visible(a, b, thickness, width, height) {
+            continue;
+        }
+        let mut color = state.world.line_color(i);
+        if lighting {
+            let mid_x = (line.pos[0].x + line.pos[1].x) / 2.0;
+            let mid_y = (line.pos[0].y + line.pos[1].y) / 2.0;
+            color = lit_color(color, state.light.sample(mid_x, mid_y));
+        }
+        draw_line_aa(&mut ctx, a.into(), b.into(), thickness, &color);
+    }
+    for particle in &state.world.particles {
+        let (pos, size) = (view.to_screen(particle.pos), particle.size * view.zoom);
+        if !ViewTransform::segment_visible(pos, pos, size, width, height) {
+            continue;
+        }
+        let mut color = color_to_rgba(particle.color);
+        color[3] = (particle.life.clamp(0.0, 1.0) * 255.0) as u8;
+        draw_circle_aa(&mut ctx, pos.x, pos.y, size, &color);
+    }
+    if state.world.mode == VisualMode::Waves {
+        for source in state.world.waves.sources.iter().flatten() {
+            let source = view.to_screen(*source);
+            draw_circle_aa(
+                &mut ctx,
+                source.x,
+                source.y,
+                SOURCE_MARKER_RADIUS,
+                &[255, 255, 255, 180],
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn test_lines(count: usize) -> Vec<Line> {
+        let mut rng = rand::thread_rng();
+        (0..count)
+            .map(|_| {
+                let mut line = Line::new(&mut rng);
+                line.width = 2.0;
+                line
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_band_assignment_wraps() {
+        assert_eq!(band_for_line(0), 0);
+        assert_eq!(band_for_line(5), 5);
+        assert_eq!(band_for_line(AUDIO_VIZ_BARS), 0);
+        assert_eq!(band_for_line(AUDIO_VIZ_BARS + 3), 3);
+    }
+
+    #[test]
+    fn test_loud_band_widens_only_its_lines() {
+        let mut lines = test_lines(4);
+        let mut spectrum = vec![0.0; AUDIO_VIZ_BARS];
+        spectrum[1] = 1.0;
+        for _ in 0..60 {
+            update_line_widths(
+                &mut lines,
+                Some(&spectrum),
+                AudioWidthSettings::DEFAULT,
+                1.0 / 60.0,
+            );
+        }
+
+        assert!((lines[0].drawn_width() - 2.0).abs() < 1e-3);
+        assert!(lines[1].drawn_width() > 4.5);
+    }
+
+    #[test]
+    fn test_widths_stay_with_their_lines_when_one_is_removed() {
+        let mut world = World::new(0);
+        world.lines = test_lines(6);
+        world.target_line_count = 6;
+        let mut spectrum = vec![0.0; AUDIO_VIZ_BARS];
+        spectrum[1] = 1.0;
+        spectrum[3] = 0.5;
+        for _ in 0..60 {
+            update_line_widths(
+                &mut world.lines,
+                Some(&spectrum),
+                AudioWidthSettings::DEFAULT,
+                1.0 / 60.0,
+            );
+        }
+        let widths_before: Vec<(f32, f32)> = world
+            .lines
+            .iter()
+            .map(|line| (line.cycle_offset, line.drawn_width()))
+            .collect();
+
+        // The World culls its oldest line when the target drops
+        world.target_line_count = 5;
+        world.update(800, 400, 1.0 / 60.0, &ForceField::default());
+
+        assert_eq!(world.lines.len(), 5);
+        for (line, &(offset, width)) in world.lines.iter().zip(&widths_before[1..]) {
+            assert_eq!(line.cycle_offset, offset);
+            assert_eq!(line.drawn_width(), width);
+        }
+    }
+
+    #[test]
+    fn test_widths_ease_back_within_a_second() {
+        let mut lines = test_lines(2);
+        let spectrum = vec![1.0; AUDIO_VIZ_BARS];
+        for _ in 0..60 {
+            update_line_widths(
+                &mut lines,
+                Some(&spectrum),
+                AudioWidthSettings::DEFAULT,
+                1.0 / 60.0,
+            );
+        }
+        let peak = lines[0].drawn_width();
+        for _ in 0..60 {
+            update_line_widths(&mut lines, None, AudioWidthSettings::DEFAULT, 1.0 / 60.0);
+        }
+        let settled = lines[0].drawn_width();
+
+        assert!(peak > 4.0);
+        assert!((settled - 2.0) / (peak - 2.0) < 0.02);
+    }
+
+    #[test]
+    fn test_cycling_modes_leaves_the_line_count_where_it_was() {
+        let mut world = World::new(MAX_LINES / 2);
+        for _ in 0..2 {
+            for _ in 0..5 {
+                world.next_mode();
+                let expected = if world.mode == VisualMode::Waves {
+                    WAVE_LINE_COUNT
+                } else {
+                    MAX_LINES / 2
+                };
+                assert_eq!(world.target_line_count, expected, "{:?}", world.mode);
+            }
+            assert_eq!(world.mode, VisualMode::Normal);
+        }
+    }
+
+    #[test]
+    fn test_restore_replays_same_frames() {
+        let mut world = World::new(20);
+        world.mode = VisualMode::Vortex;
+        for _ in 0..10 {
+            world.update(800, 400, 1.0 / 60.0, &ForceField::default());
+        }
+        let snapshot = world.snapshot();
+        let mut expected = Vec::new();
+        for _ in 0..3 {
+            world.update(800, 400, 1.0 / 60.0, &ForceField::default());
+            expected.push(world.lines.iter().map(|l| l.pos).collect::<Vec<_>>());
+        }
+
+        // Radically change the scene before restoring
+        world.mode = VisualMode::Waves;
+        world.lines.truncate(2);
+        for _ in 0..50 {
+            world.update(800, 400, 1.0 / 60.0, &ForceField::default());
+        }
+        world.restore(&snapshot);
+
+        for frame in expected {
+            world.update(800, 400, 1.0 / 60.0, &ForceField::default());
+            let positions: Vec<_> = world.lines.iter().map(|l| l.pos).collect();
+            assert_eq!(positions, frame);
+        }
+    }
+
+    #[test]
+    fn test_gravity_pulls_lines_down() {
+        let mut world = World::new(0);
+        let mut line = test_lines(1).remove(0);
+        line.pos = [Position::new(100.0, 100.0), Position::new(150.0, 100.0)];
+        line.vel = [Velocity::ZERO; 2];
+        line.length = 50.0;
+        world.lines.push(line);
+        world.target_line_count = 1;
+        let field = ForceField {
+            gravity: GravityMode::Down,
+            wind: false,
+            gust: Velocity::ZERO,
+        };
+        world.update(800, 400, 1.0 / 60.0, &field);
+
+        assert!(world.lines[0].vel[0].y > 0.0);
+        assert!(world.lines[0].vel[1].y > 0.0);
+        assert!(world.lines[0].vel[0].x.abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_extreme_forces_stay_clamped() {
+        let mut world = World::new(20);
+        let field = ForceField {
+            gravity: GravityMode::Down,
+            wind: true,
+            gust: Velocity::ZERO,
+        };
+        // A huge dt stands in for an extreme gravity setting
+        for _ in 0..200 {
+            world.update(800, 400, 1.0, &field);
+        }
+
+        for line in &world.lines {
+            for end in 0..2 {
+                assert!(line.vel[end].length() <= MAX_ENDPOINT_SPEED + 1e-3);
+                assert!((0.0..=800.0).contains(&line.pos[end].x));
+                assert!((0.0..=400.0).contains(&line.pos[end].y));
+            }
+        }
+        assert!(world.particles.len() <= MAX_PARTICLES);
+        for particle in &world.particles {
+            assert!(particle.vel.length() <= MAX_PARTICLE_SPEED);
+            assert!(particle.pos.is_finite());
+        }
+    }
+
+    #[test]
+    fn test_circle_arena_holds_everything_for_10k_frames() {
+        let mut rng = StdRng::seed_from_u64(720);
+        let mut world = World::new(0);
+        world.lines = (0..60).map(|_| Line::new(&mut rng)).collect();
+        world.target_line_count = world.lines.len();
+        world.particles = (0..80)
+            .map(|_| {
+                let mut particle = Particle::new(Position::new(400.0, 200.0), &mut rng);
+                particle.life = f32::MAX;
+                particle
+            })
+            .collect();
+        world.arena = ArenaShape::Circle;
+        let field = ForceField {
+            gravity: GravityMode::Off,
+            wind: true,
+            gust: Velocity::ZERO,
+        };
+        let circle = ArenaShape::Circle.fit(800, 400);
+        let (center, radius) = (circle.center(), circle.half_width());
+
+        for frame in 0..10_000 {
+            // Every mode gets a turn, Flock included
+            if frame % 2_000 == 1_999 {
+                world.next_mode();
+            }
+            world.update(800, 400, 1.0 / 60.0, &field);
+            let lines = world.lines.iter().flat_map(|line| line.pos);
+            for pos in lines.chain(world.particles.iter().map(|particle| particle.pos)) {
+                assert!(
+                    pos.distance(center) <= radius + 1e-3,
+                    "frame {}: {:?} escaped",
+                    frame,
+                    pos
+                );
+            }
+        }
+        assert_eq!(world.particles.len(), 80);
+    }
+
+    #[test]
+    fn test_pathological_input_never_leaves_nan_after_a_frame() {
+        let mut rng = StdRng::seed_from_u64(723);
+        let awkward = [
+            f32::NAN,
+            f32::INFINITY,
+            f32::NEG_INFINITY,
+            1.0e30,
+            -1.0e-30,
+            0.0,
+        ];
+        let field = ForceField {
+            gravity: GravityMode::Down,
+            wind: true,
+            gust: Velocity::ZERO,
+        };
+        for (round, shape) in ArenaShape::ALL.into_iter().cycle().take(30).enumerate() {
+            let mut world = World::new(0);
+            world.arena = shape;
+            for _ in 0..round % 5 {
+                world.next_mode();
+            }
+            world.lines = (0..40)
+                .map(|i| {
+                    let mut line = Line::new(&mut rng);
+                    let pick = |rng: &mut StdRng| awkward[rng.gen_range(0..awkward.len())];
+                    match i % 5 {
+                        0 => line.pos[rng.gen_range(0..2)].x = pick(&mut rng),
+                        1 => line.vel[rng.gen_range(0..2)].y = pick(&mut rng),
+                        // Both ends in one spot, with no rest length
+                        2 => {
+                            line.pos[1] = line.pos[0];
+                            line.length = 0.0;
+                        }
+                        3 => line.length = pick(&mut rng),
+                        _ => {}
+                    }
+                    line
+                })
+                .collect();
+            world.target_line_count = world.lines.len();
+            world.particles = (0..20)
+                .map(|i| {
+                    let mut particle = Particle::new(Position::new(400.0, 200.0), &mut rng);
+                    if i % 2 == 0 {
+                        particle.pos.y = awkward[i % awkward.len()];
+                    }
+                    particle
+                })
+                .collect();
+            // The cursor sits exactly on an endpoint, or nowhere real
+            world.mouse_active = true;
+            world.mouse_pos = Some(if round % 2 == 0 {
+                world.lines[2].pos[0]
+            } else {
+                Position::new(f32::NAN, 10.0)
+            });
+
+            world.update(800, 400, 1.0 / 60.0, &field);
+            for line in &world.lines {
+                assert!(
+                    line.pos.into_iter().chain(line.vel).all(is_sane),
+                    "round {}: {:?}",
+                    round,
+                    line
+                );
+                assert!(line.length.is_finite());
+            }
+            for particle in &world.particles {
+                assert!(is_sane(particle.pos) && is_sane(particle.vel));
+            }
+
+            let spectrum: Vec<f32> = (0..AUDIO_VIZ_BARS)
+                .map(|i| awkward[(i + round) % awkward.len()])
+                .collect();
+            let settings = AudioWidthSettings::DEFAULT;
+            update_line_widths(&mut world.lines, Some(&spectrum), settings, 1.0 / 60.0);
+            for line in &world.lines {
+                assert!(line.drawn_width().is_finite(), "round {}", round);
+            }
+        }
+    }
+
+    fn empty_state() -> WorldState {
+        let mut state = new_world_state();
+        state.world.lines.clear();
+        state.world.target_line_count = 0;
+        state
+    }
+
+    #[test]
+    fn test_paint_strokes_follow_button_holds() {
+        let mut state = empty_state();
+        for (x, held) in [(10.0, true), (30.0, true), (50.0, true), (50.0, false)] {
+            apply_mouse(&mut state, Some((x, 20.0)), held, true, 0.0);
+        }
+        for (x, held) in [(100.0, true), (140.0, true), (140.0, false)] {
+            apply_mouse(&mut state, Some((x, 20.0)), held, true, 0.0);
+        }
+
+        assert_eq!(state.drawing.stroke_count(), 2);
+        assert_eq!(state.drawing.strokes()[0].len(), 2);
+        assert_eq!(state.drawing.strokes()[1].len(), 1);
+        // Painted lines never join the simulation or count toward MAX_LINES
+        assert!(state.world.lines.is_empty());
+    }
+
+    #[test]
+    fn test_spawned_lines_join_simulation_outside_paint_mode() {
+        let mut state = empty_state();
+        for x in [10.0, 30.0, 50.0] {
+            apply_mouse(&mut state, Some((x, 20.0)), true, false, 0.0);
+        }
+        apply_mouse(&mut state, Some((50.0, 20.0)), false, false, 0.0);
+
+        assert_eq!(state.world.lines.len(), 2);
+        assert_eq!(state.drawing.stroke_count(), 0);
+    }
+
+    #[test]
+    fn test_live_lines_draw_over_drawing_layer() {
+        let (width, height) = (100, 100);
+        let mut state = empty_state();
+        let mut frozen = line_between(Position::new(10.0, 50.0), Position::new(90.0, 50.0), 0.0);
+        frozen.color = Color::new(255, 0, 0);
+        state.drawing.add_line(frozen);
+        state.drawing.end_stroke();
+        let mut live = line_between(Position::new(50.0, 10.0), Position::new(50.0, 90.0), 0.0);
+        live.color = Color::new(0, 0, 255);
+        state.world.lines.push(live);
+        state.world.mode = VisualMode::Normal;
+
+        let mut frame = vec![0u8; width * height * 4];
+        draw_world_layers(&mut state, &mut frame, 100, 100, 0.0, 0, 100);
+        let pixel = |x: usize, y: usize| {
+            let idx = 4 * (y * width + x);
+            [frame[idx], frame[idx + 1], frame[idx + 2]]
+        };
+
+        assert_eq!(pixel(20, 50), [255, 0, 0]);
+        assert_eq!(pixel(50, 50), pixel(50, 20));
+        assert_eq!(pixel(50, 50)[0], 0);
+    }
+
+    #[test]
+    fn test_zoomed_view_draws_and_aims_through_the_transform() {
+        let (width, height) = (100, 100);
+        let mut state = empty_state();
+        let mut live = line_between(Position::new(10.0, 20.0), Position::new(40.0, 20.0), 0.0);
+        live.color = Color::new(0, 0, 255);
+        state.world.lines.push(live);
+        state.world.mode = VisualMode::Normal;
+        state.view = ViewTransform {
+            zoom: 2.0,
+            offset: Position::new(0.0, 10.0),
+        };
+
+        let mut frame = vec![0u8; width * height * 4];
+        draw_world_layers(&mut state, &mut frame, 100, 100, 0.0, 0, 100);
+        let pixel = |x: usize, y: usize| {
+            let idx = 4 * (y * width + x);
+            [frame[idx], frame[idx + 1], frame[idx + 2]]
+        };
+        // The line lands at twice its length, moved down by the offset
+        let background = pixel(90, 90);
+        assert_eq!(pixel(70, 50), pixel(25, 50));
+        assert_ne!(pixel(70, 50), background);
+        assert_eq!(pixel(30, 20), background);
+
+        // The cursor attracts toward the World point under it
+        let cursor = (70.0, 50.0);
+        apply_mouse(&mut state, Some(cursor), true, false, 0.0);
+        let target = state.world.mouse_pos.unwrap();
+        assert_eq!(target, Position::new(35.0, 20.0));
+        assert_eq!(state.view.to_screen(target), cursor.into());
+    }
+
+    #[test]
+    fn test_disabled_ignores_spectrum() {
+        let mut lines = test_lines(1);
+        let spectrum = vec![1.0; AUDIO_VIZ_BARS];
+        let settings = AudioWidthSettings {
+            enabled: false,
+            sensitivity: 1.5,
+        };
+        update_line_widths(&mut lines, Some(&spectrum), settings, 0.5);
+
+        assert_eq!(lines[0].drawn_width(), 2.0);
+    }
+}
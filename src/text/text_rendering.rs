@@ -1,19 +1,34 @@
+use crate::core::accessibility::{hud_backing, readable_text_color, sample_luminance};
 use crate::core::types::HEIGHT;
+use crate::graphics::draw_ctx::DrawCtx;
 use crate::graphics::pixel_utils::{blend_pixel_safe, draw_rectangle_safe};
-use ab_glyph::{Font, FontArc, PxScale};
+use crate::graphics::theme::current_theme;
+use ab_glyph::{Font, FontArc, PxScale, ScaleFont};
 use font_kit::source::SystemSource;
 use once_cell::sync::Lazy;
+use std::cell::Cell;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
 
-static FONT: Lazy<FontArc> = Lazy::new(|| {
+static FONT: Lazy<FontArc> = Lazy::new(|| load_system_font().unwrap());
+
+/// The system's monospace font, which all HUD text is drawn in
+pub fn load_system_font() -> Result<FontArc, String> {
     let handle = SystemSource::new()
         .select_best_match(
             &[font_kit::family_name::FamilyName::Monospace],
             &Default::default(),
         )
-        .unwrap();
-    let font_data = handle.load().unwrap().copy_font_data().unwrap();
-    FontArc::try_from_vec((*font_data).clone()).unwrap()
-});
+        .map_err(|e| format!("no monospace font found: {}", e))?;
+    let font = handle
+        .load()
+        .map_err(|e| format!("the monospace font can't be loaded: {}", e))?;
+    let font_data = font
+        .copy_font_data()
+        .ok_or("the monospace font has no data")?;
+    FontArc::try_from_vec((*font_data).clone())
+        .map_err(|e| format!("the monospace font can't be parsed: {}", e))
+}
 
 pub fn draw_text_with_background(
     frame: &mut [u8],
@@ -25,8 +40,8 @@ pub fn draw_text_with_background(
     width: u32,
 ) {
     let text_width = estimate_text_width(text);
-    let text_height = 20.0; // Should match the font size
-    let padding = 5.0;
+    let text_height = FONT_SIZE;
+    let padding = BACKGROUND_PADDING;
 
     draw_rectangle_safe(
         frame,
@@ -34,12 +49,435 @@ pub fn draw_text_with_background(
         (y - text_height - padding) as i32,
         (text_width + 2.0 * padding) as u32,
         (text_height + 2.0 * padding) as u32,
-        bg_color,
+        hud_backing(bg_color),
         width,
         HEIGHT,
     );
 
-    draw_text_ab_glyph(frame, text, x, y, text_color, width);
+    draw_text_styled(frame, text, x, y, &TextStyle::outlined(text_color), width);
+}
+
+/// Font size used by every overlay text call
+const FONT_SIZE: f32 = 20.0;
+/// Background `draw_text_with_background` adds around the text
+const BACKGROUND_PADDING: f32 = 5.0;
+
+/// Size of `lines` drawn with `draw_text_with_background`, `line_height`
+/// apart, backgrounds included
+pub fn background_text_size(lines: &[String], line_height: f32) -> (f32, f32) {
+    let widest = lines
+        .iter()
+        .map(|line| estimate_text_width(line))
+        .fold(0.0, f32::max);
+    let height = match lines.len() {
+        0 => 0.0,
+        n => FONT_SIZE + 2.0 * BACKGROUND_PADDING + (n - 1) as f32 * line_height,
+    };
+    (widest + 2.0 * BACKGROUND_PADDING, height)
+}
+
+/// Where `draw_text_with_background` puts the first line's text and baseline
+/// for a block whose background starts at `left`, `top`
+pub fn background_text_origin(left: f32, top: f32) -> (f32, f32) {
+    (
+        left + BACKGROUND_PADDING,
+        top + FONT_SIZE + BACKGROUND_PADDING,
+    )
+}
+/// Coverage below this is treated as empty
+const COVERAGE_THRESHOLD: f32 = 0.05;
+
+/// Rasterized coverage for a single glyph, positioned relative to the pen origin.
+/// Rasterized once and shared by the fill, outline, and shadow passes.
+#[derive(Debug, Clone)]
+struct GlyphCoverage {
+    min_x: i32,
+    min_y: i32,
+    width: usize,
+    height: usize,
+    coverage: Vec<f32>,
+}
+
+/// Keyed by character, size in px (as bits), and stroke width, 0 being filled
+type GlyphKey = (char, u32, u32);
+/// Size in px (as bits) and stroke width of a key
+type GlyphSize = (u32, u32);
+
+/// Sizes the glyph cache holds glyphs at; a banner growing every frame
+/// would otherwise leave a full alphabet behind at each size
+const CACHED_SIZES: usize = 16;
+
+#[derive(Default)]
+struct GlyphCache {
+    glyphs: HashMap<GlyphKey, Option<Arc<GlyphCoverage>>>,
+    /// Cached sizes, least recently drawn at first
+    sizes: VecDeque<GlyphSize>,
+}
+
+impl GlyphCache {
+    /// Marks `size` as just drawn at, dropping the glyphs of the least
+    /// recently drawn size when a new one doesn't fit
+    fn touch(&mut self, size: GlyphSize) {
+        if let Some(i) = self.sizes.iter().position(|&cached| cached == size) {
+            self.sizes.remove(i);
+        } else if self.sizes.len() >= CACHED_SIZES {
+            if let Some(evicted) = self.sizes.pop_front() {
+                self.glyphs
+                    .retain(|&(_, size, stroke), _| (size, stroke) != evicted);
+            }
+        }
+        self.sizes.push_back(size);
+    }
+}
+
+static GLYPH_CACHE: Lazy<Mutex<GlyphCache>> = Lazy::new(|| Mutex::new(GlyphCache::default()));
+
+thread_local! {
+    /// Glyphs rasterized on this thread, so cache misses can be counted
+    /// without other threads' drawing getting in the way
+    static RASTERIZED: Cell<usize> = const { Cell::new(0) };
+}
+
+/// How many glyphs this thread has rasterized; cache hits don't count
+pub fn glyphs_rasterized() -> usize {
+    RASTERIZED.with(Cell::get)
+}
+
+fn cached_glyph(c: char) -> Option<Arc<GlyphCoverage>> {
+    cached_glyph_at(c, FONT_SIZE, 0)
+}
+
+/// `c` at `size` px, filled or, with a `stroke` above 0, only the band that
+/// wide inside its edge
+fn cached_glyph_at(c: char, size: f32, stroke: u32) -> Option<Arc<GlyphCoverage>> {
+    let mut cache = GLYPH_CACHE.lock().ok()?;
+    cache.touch((size.to_bits(), stroke));
+    cache
+        .glyphs
+        .entry((c, size.to_bits(), stroke))
+        .or_insert_with(|| {
+            let mut glyph = rasterize_glyph(c, size)?;
+            if stroke > 0 {
+                glyph.coverage = edge_band(&glyph.coverage, glyph.width, glyph.height, stroke);
+            }
+            Some(Arc::new(glyph))
+        })
+        .clone()
+}
+
+fn rasterize_glyph(c: char, size: f32) -> Option<GlyphCoverage> {
+    RASTERIZED.with(|count| count.set(count.get() + 1));
+    let font = &*FONT;
+    let glyph = font.glyph_id(c).with_scale(PxScale::from(size));
+    let outlined = font.outline_glyph(glyph)?;
+    let bounds = outlined.px_bounds();
+    let width = bounds.width().ceil() as usize;
+    let height = bounds.height().ceil() as usize;
+    let mut coverage = vec![0.0; width * height];
+    outlined.draw(|gx, gy, intensity| {
+        let idx = gy as usize * width + gx as usize;
+        if idx < coverage.len() {
+            coverage[idx] = intensity;
+        }
+    });
+    Some(GlyphCoverage {
+        min_x: bounds.min.x as i32,
+        min_y: bounds.min.y as i32,
+        width,
+        height,
+        coverage,
+    })
+}
+
+/// Grows a coverage bitmap by `radius` pixels in every direction.
+/// Each output pixel takes the strongest coverage within a disc of that radius.
+/// Returns the dilated bitmap, which is `2 * radius` larger on each axis.
+fn dilate_coverage(
+    coverage: &[f32],
+    width: usize,
+    height: usize,
+    radius: u32,
+) -> (Vec<f32>, usize, usize) {
+    let r = radius as i32;
+    let out_width = width + 2 * radius as usize;
+    let out_height = height + 2 * radius as usize;
+    let mut out = vec![0.0f32; out_width * out_height];
+    for y in 0..height {
+        for x in 0..width {
+            let value = coverage[y * width + x];
+            if value <= 0.0 {
+                continue;
+            }
+            for dy in -r..=r {
+                for dx in -r..=r {
+                    if dx * dx + dy * dy > r * r {
+                        continue;
+                    }
+                    let ox = (x as i32 + r + dx) as usize;
+                    let oy = (y as i32 + r + dy) as usize;
+                    let slot = &mut out[oy * out_width + ox];
+                    *slot = slot.max(value);
+                }
+            }
+        }
+    }
+    (out, out_width, out_height)
+}
+
+/// The part of a coverage bitmap within `radius` pixels of its edge: the
+/// coverage minus its erosion, so a shape's outline traced inward
+fn edge_band(coverage: &[f32], width: usize, height: usize, radius: u32) -> Vec<f32> {
+    let r = radius as i32;
+    let at = |x: i32, y: i32| {
+        if x < 0 || y < 0 || x >= width as i32 || y >= height as i32 {
+            0.0
+        } else {
+            coverage[y as usize * width + x as usize]
+        }
+    };
+    let mut band = vec![0.0; coverage.len()];
+    for y in 0..height as i32 {
+        for x in 0..width as i32 {
+            let value = at(x, y);
+            if value <= 0.0 {
+                continue;
+            }
+            let mut eroded = value;
+            for dy in -r..=r {
+                for dx in -r..=r {
+                    if dx * dx + dy * dy <= r * r {
+                        eroded = eroded.min(at(x + dx, y + dy));
+                    }
+                }
+            }
+            band[y as usize * width + x as usize] = value - eroded;
+        }
+    }
+    band
+}
+
+/// Pixels covered at least half, as offsets from the bitmap's center, row by row
+fn coverage_points(coverage: &[f32], width: usize, height: usize) -> Vec<(f32, f32)> {
+    let (center_x, center_y) = (width as f32 / 2.0, height as f32 / 2.0);
+    coverage
+        .iter()
+        .enumerate()
+        .filter(|&(_, &value)| value >= 0.5)
+        .map(|(i, _)| {
+            (
+                (i % width) as f32 + 0.5 - center_x,
+                (i / width) as f32 + 0.5 - center_y,
+            )
+        })
+        .collect()
+}
+
+/// The pixels of the band `stroke` wide inside `c`'s edge at `size` px, as
+/// offsets from the middle of the glyph, row by row. Empty for characters the
+/// font has no outline for.
+pub fn glyph_outline_points(c: char, size: f32, stroke: u32) -> Vec<(f32, f32)> {
+    cached_glyph_at(c, size, stroke).map_or_else(Vec::new, |glyph| {
+        coverage_points(&glyph.coverage, glyph.width, glyph.height)
+    })
+}
+
+/// A glyph from the cache, held so it can be drawn over and over without
+/// looking it up again
+#[derive(Debug, Clone)]
+pub struct CachedGlyph(Arc<GlyphCoverage>);
+
+impl CachedGlyph {
+    /// `c` at `size` px, rasterized on first use. None for characters the
+    /// font has no outline for, spaces included.
+    pub fn new(c: char, size: f32) -> Option<Self> {
+        cached_glyph_at(c, size, 0).map(Self)
+    }
+
+    /// Blends the glyph in `color` with its pen at (x, baseline), scaling the
+    /// coverage by `alpha`
+    pub fn draw(&self, ctx: &mut DrawCtx, x: i32, baseline: i32, color: [u8; 3], alpha: f32) {
+        let glyph = &self.0;
+        let (left, top) = (x + glyph.min_x, baseline + glyph.min_y);
+        for (i, &intensity) in glyph.coverage.iter().enumerate() {
+            if intensity > COVERAGE_THRESHOLD {
+                let (dx, dy) = ((i % glyph.width) as i32, (i / glyph.width) as i32);
+                let a = (intensity * alpha * 255.0) as u8;
+                ctx.blend_pixel(left + dx, top + dy, &[color[0], color[1], color[2], a]);
+            }
+        }
+    }
+}
+
+/// Fill colour plus optional outline (colour, radius in px) and drop shadow (colour, dx, dy)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextStyle {
+    pub color: [u8; 4],
+    pub outline: Option<([u8; 4], u32)>,
+    pub shadow: Option<([u8; 4], i32, i32)>,
+}
+
+impl TextStyle {
+    /// Single-colour glyphs with no outline or shadow
+    pub fn plain(color: [u8; 4]) -> Self {
+        Self {
+            color,
+            outline: None,
+            shadow: None,
+        }
+    }
+
+    /// Default overlay style: a 1px dark outline keeps text readable over bright scenes
+    pub fn outlined(color: [u8; 4]) -> Self {
+        Self {
+            color,
+            outline: Some(([0, 0, 0, 255], 1)),
+            shadow: None,
+        }
+    }
+
+    pub fn with_shadow(mut self, color: [u8; 4], dx: i32, dy: i32) -> Self {
+        self.shadow = Some((color, dx, dy));
+        self
+    }
+}
+
+/// Size and color of letters drawn straight from the font
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GlyphStyle {
+    /// Font size in px
+    pub size: f32,
+    /// Width of the band drawn inside each letter's edge, 0 filling the letters
+    pub stroke: u32,
+    pub color: [u8; 4],
+}
+
+fn blit_coverage(
+    frame: &mut [u8],
+    coverage: &[f32],
+    coverage_width: usize,
+    x: i32,
+    y: i32,
+    color: [u8; 4],
+    width: u32,
+) {
+    for (i, &intensity) in coverage.iter().enumerate() {
+        if intensity > COVERAGE_THRESHOLD {
+            let px = x + (i % coverage_width) as i32;
+            let py = y + (i / coverage_width) as i32;
+            blend_pixel_safe(frame, px, py, width, HEIGHT, color, intensity);
+        }
+    }
+}
+
+/// Like `blit_coverage`, but mixes toward `color` instead of adding it, so
+/// dark text shows up over a bright background
+fn blit_coverage_over(
+    frame: &mut [u8],
+    coverage: &[f32],
+    coverage_width: usize,
+    x: i32,
+    y: i32,
+    color: [u8; 4],
+    width: u32,
+) {
+    let alpha = color[3] as f32 / 255.0;
+    for (i, &intensity) in coverage.iter().enumerate() {
+        let px = x + (i % coverage_width) as i32;
+        let py = y + (i / coverage_width) as i32;
+        if intensity <= COVERAGE_THRESHOLD || px < 0 || py < 0 || px >= width as i32 {
+            continue;
+        }
+        let idx = 4 * (py as usize * width as usize + px as usize);
+        if py >= HEIGHT as i32 || idx + 3 >= frame.len() {
+            continue;
+        }
+        let amount = intensity.min(1.0) * alpha;
+        for (dst, &src) in frame[idx..idx + 3].iter_mut().zip(&color) {
+            *dst = (*dst as f32 + (src as f32 - *dst as f32) * amount) as u8;
+        }
+    }
+}
+
+/// Draws text in the given style: shadow first, then outline, then fill
+pub fn draw_text_styled(
+    frame: &mut [u8],
+    text: &str,
+    x: f32,
+    y: f32,
+    style: &TextStyle,
+    width: u32,
+) {
+    let mut glyphs = Vec::new();
+    let mut cursor_x = x;
+    for c in text.chars() {
+        if c.is_control() {
+            continue;
+        }
+        if let Some(glyph) = cached_glyph(c) {
+            glyphs.push((glyph, cursor_x));
+        }
+        cursor_x += glyph_advance(c, FONT_SIZE) + 1.0;
+    }
+
+    if let Some((shadow_color, dx, dy)) = style.shadow {
+        for (glyph, pen_x) in &glyphs {
+            blit_coverage(
+                frame,
+                &glyph.coverage,
+                glyph.width,
+                (pen_x + glyph.min_x as f32) as i32 + dx,
+                (y + glyph.min_y as f32) as i32 + dy,
+                shadow_color,
+                width,
+            );
+        }
+    }
+
+    if let Some((outline_color, radius)) = style.outline {
+        for (glyph, pen_x) in &glyphs {
+            let (dilated, dilated_width, _) =
+                dilate_coverage(&glyph.coverage, glyph.width, glyph.height, radius);
+            blit_coverage(
+                frame,
+                &dilated,
+                dilated_width,
+                (pen_x + glyph.min_x as f32) as i32 - radius as i32,
+                (y + glyph.min_y as f32) as i32 - radius as i32,
+                outline_color,
+                width,
+            );
+        }
+    }
+
+    // Text that would fade into what is behind it takes the theme's
+    // high-contrast color for this draw
+    let behind = sample_luminance(
+        frame,
+        width,
+        x as i32,
+        (y - FONT_SIZE) as i32,
+        (cursor_x - x) as u32,
+        FONT_SIZE as u32,
+    );
+    let fill = behind.map_or(style.color, |background| {
+        readable_text_color(style.color, background, &current_theme())
+    });
+    for (glyph, pen_x) in &glyphs {
+        let blit = if fill == style.color {
+            blit_coverage
+        } else {
+            blit_coverage_over
+        };
+        blit(
+            frame,
+            &glyph.coverage,
+            glyph.width,
+            (pen_x + glyph.min_x as f32) as i32,
+            (y + glyph.min_y as f32) as i32,
+            fill,
+            width,
+        );
+    }
 }
 
 pub fn draw_text_ab_glyph(
@@ -50,80 +488,286 @@ pub fn draw_text_ab_glyph(
     color: [u8; 4],
     width: u32,
 ) {
-    let scale = PxScale::from(20.0);
-    let font = &*FONT;
-    let cursor_x = x;
-    let glyphs: Vec<_> = text
-        .chars()
-        .scan(cursor_x, |x_pos, c| {
-            if c.is_control() {
-                return Some(None);
-            }
-            let original_x = *x_pos;
-            let glyph = font.glyph_id(c).with_scale(scale);
-            *x_pos += font.h_advance_unscaled(glyph.id) * scale.x + 1.0;
-            Some(font.outline_glyph(glyph).map(|g| (g, original_x)))
-        })
-        .filter_map(|opt| opt)
-        .collect();
-    for (outlined, x_pos) in glyphs {
-        let bounds = outlined.px_bounds();
-        outlined.draw(|gx, gy, intensity| {
-            let px = bounds.min.x + gx as f32;
-            let py = bounds.min.y + gy as f32;
-            if intensity > 0.05 {
-                blend_pixel_safe(
-                    frame,
-                    (x_pos + px) as i32,
-                    (y + py) as i32,
-                    width,
-                    HEIGHT,
-                    color,
-                    intensity,
-                );
+    draw_text_styled(frame, text, x, y, &TextStyle::plain(color), width);
+}
+/// Width `draw_text_styled` gives `text`, in pixels
+pub fn estimate_text_width(text: &str) -> f32 {
+    text.chars()
+        .filter(|c| !c.is_control())
+        .map(|c| glyph_advance(c, FONT_SIZE) + 1.0)
+        .sum()
+}
+
+/// Width `draw_text_sized` gives `text` at `size` px
+pub fn text_width_sized(text: &str, size: f32) -> f32 {
+    text.chars()
+        .filter(|c| !c.is_control())
+        .map(|c| glyph_advance(c, size))
+        .sum()
+}
+
+/// Breaks `text` at spaces into lines no wider than `max_width`, as
+/// `measure` reports widths. A word too long for any line gets one to itself.
+pub fn wrap_text(text: &str, max_width: f32, measure: impl Fn(&str) -> f32) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for word in text.split_whitespace() {
+        match lines.last_mut() {
+            Some(line) if measure(&format!("{} {}", line, word)) <= max_width => {
+                line.push(' ');
+                line.push_str(word);
             }
-        });
+            _ => lines.push(word.to_string()),
+        }
     }
+    lines
 }
-pub fn estimate_text_width(text: &str) -> f32 {
+
+/// Pen advance of `c` at `size` px
+pub fn glyph_advance(c: char, size: f32) -> f32 {
     let font = &*FONT;
-    let scale = PxScale::from(20.0);
-    let mut width = 0.0;
-    for c in text.chars() {
-        if c.is_control() {
-            continue;
+    font.as_scaled(PxScale::from(size))
+        .h_advance(font.glyph_id(c))
+}
+
+/// Capital letter height as a fraction of the font size
+static CAP_HEIGHT_RATIO: Lazy<f32> = Lazy::new(|| {
+    let reference = 100.0;
+    rasterize_glyph('H', reference).map_or(0.7, |h| h.height as f32 / reference)
+});
+
+/// Font size in px whose capital letters are `cap_height` tall
+pub fn size_for_cap_height(cap_height: f32) -> f32 {
+    cap_height / *CAP_HEIGHT_RATIO
+}
+
+/// Draws `text` in `style` with its baseline at `y`. Glyphs are cached per
+/// size, so a banner can be redrawn every frame. Returns the text's width.
+pub fn draw_text_sized(
+    frame: &mut [u8],
+    text: &str,
+    x: f32,
+    y: f32,
+    style: GlyphStyle,
+    width: u32,
+) -> f32 {
+    let mut pen_x = x;
+    for c in text.chars().filter(|c| !c.is_control()) {
+        if let Some(glyph) = cached_glyph_at(c, style.size, style.stroke) {
+            blit_coverage(
+                frame,
+                &glyph.coverage,
+                glyph.width,
+                (pen_x + glyph.min_x as f32) as i32,
+                (y + glyph.min_y as f32) as i32,
+                style.color,
+                width,
+            );
+        }
+        pen_x += glyph_advance(c, style.size);
+    }
+    pen_x - x
+}
+
+/// Draws one glyph in `style` with its coverage scaled by `alpha`. Large
+/// glyphs are rasterized on every call rather than cached, so this is meant
+/// for the few letters of a title, not running text. Returns the pen advance.
+pub fn draw_glyph_scaled(
+    frame: &mut [u8],
+    c: char,
+    x: f32,
+    y: f32,
+    style: GlyphStyle,
+    alpha: f32,
+    width: u32,
+) -> f32 {
+    if let Some(mut glyph) = rasterize_glyph(c, style.size).filter(|_| alpha > 0.0) {
+        if style.stroke > 0 {
+            glyph.coverage = edge_band(&glyph.coverage, glyph.width, glyph.height, style.stroke);
         }
-        let glyph = font.glyph_id(c).with_scale(scale);
-        width += font.h_advance_unscaled(glyph.id) * scale.x + 1.0;
-    }
-    width
-}
-pub fn draw_keyboard_guide(frame: &mut [u8], width: u32) {
-    let guide_text = [
-        "Keyboard Guide:",
-        "[1-8] - Change Visualization",
-        "[H] - Toggle Help",
-        "[F] or [F11] - Toggle Fullscreen",
-        "[Space] - Toggle Mode",
-        "[Esc] - Show Menu",
-        "[=] - Add Lines",
-        "[-] - Remove Lines",
-        "[E] - Explosion",
-        "[9] - Toggle White Noise",
-        "Right Mouse - Explosion at cursor",
-    ];
-    let mut y = 30.0;
-    let line_height = 25.0;
-    for line in guide_text.iter() {
-        draw_text_with_background(
+        let coverage: Vec<f32> = glyph.coverage.iter().map(|c| c * alpha).collect();
+        blit_coverage(
             frame,
-            line,
-            10.0,
-            y,
-            [255, 255, 255, 255],
-            [0, 0, 0, 128],
+            &coverage,
+            glyph.width,
+            (x + glyph.min_x as f32) as i32,
+            (y + glyph.min_y as f32) as i32,
+            style.color,
             width,
         );
-        y += line_height;
+    }
+    glyph_advance(c, style.size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A small "plus" shaped glyph with partial coverage on one arm
+    fn plus_glyph() -> (Vec<f32>, usize, usize) {
+        let (w, h) = (5, 5);
+        let mut coverage = vec![0.0; w * h];
+        for i in 0..5 {
+            coverage[2 * w + i] = 1.0;
+            coverage[i * w + 2] = 1.0;
+        }
+        coverage[2 * w + 4] = 0.5;
+        (coverage, w, h)
+    }
+
+    #[test]
+    fn test_dilation_strictly_contains_fill() {
+        let (coverage, w, h) = plus_glyph();
+        for radius in 1..=3u32 {
+            let (dilated, dw, dh) = dilate_coverage(&coverage, w, h, radius);
+            let r = radius as usize;
+            assert_eq!((dw, dh), (w + 2 * r, h + 2 * r));
+
+            // Every filled pixel is covered at least as strongly after dilation
+            for y in 0..h {
+                for x in 0..w {
+                    let fill = coverage[y * w + x];
+                    let grown = dilated[(y + r) * dw + (x + r)];
+                    assert!(grown >= fill);
+                }
+            }
+
+            // And dilation adds coverage the fill did not have
+            let fill_count = coverage.iter().filter(|&&c| c > COVERAGE_THRESHOLD).count();
+            let grown_count = dilated.iter().filter(|&&c| c > COVERAGE_THRESHOLD).count();
+            assert!(grown_count > fill_count);
+        }
+    }
+
+    #[test]
+    fn test_coverage_points_sample_only_covered_pixels() {
+        let (coverage, w, h) = plus_glyph();
+        let points = coverage_points(&coverage, w, h);
+        // The half-covered tip counts; the empty corners don't
+        assert_eq!(points.len(), 9);
+        assert!(points.contains(&(0.0, 0.0)));
+        assert!(points.contains(&(2.0, 0.0)));
+        assert!(!points.contains(&(2.0, 2.0)));
+        for (x, y) in points {
+            let (px, py) = ((x + 2.5) as usize, (y + 2.5) as usize);
+            assert!(coverage[py * w + px] >= 0.5);
+        }
+    }
+
+    #[test]
+    fn test_wrap_breaks_at_spaces_within_the_width() {
+        let chars = |text: &str| text.chars().count() as f32;
+        assert_eq!(
+            wrap_text("the quick brown fox jumps", 10.0, chars),
+            ["the quick", "brown fox", "jumps"]
+        );
+        // Too long to fit, but never split mid-word
+        assert_eq!(
+            wrap_text("a pneumatic drill", 5.0, chars),
+            ["a", "pneumatic", "drill"]
+        );
+        assert!(wrap_text("   ", 10.0, chars).is_empty());
+    }
+
+    #[test]
+    fn test_cached_glyphs_rasterize_once() {
+        // A size nothing else draws at, so the first lookup is a miss
+        let before = glyphs_rasterized();
+        let glyph = CachedGlyph::new('R', 23.75).unwrap();
+        assert_eq!(glyphs_rasterized(), before + 1);
+        assert!(CachedGlyph::new('R', 23.75).is_some());
+        assert!(CachedGlyph::new(' ', 23.75).is_none());
+        assert_eq!(glyphs_rasterized(), before + 2);
+
+        let (width, height) = (40u32, 40u32);
+        let mut frame = vec![0u8; (width * height * 4) as usize];
+        let region = crate::graphics::draw_ctx::Region::new(0, 0, width, height);
+        let mut ctx = DrawCtx::new(&mut frame, region, width, 0.0);
+        glyph.draw(&mut ctx, 8, 30, [0, 255, 0], 1.0);
+        assert!(frame.chunks(4).any(|pixel| pixel[1] > 200 && pixel[0] == 0));
+        assert_eq!(glyphs_rasterized(), before + 2);
+    }
+
+    #[test]
+    fn test_glyph_cache_drops_the_least_recently_drawn_size() {
+        // Sizes nothing else draws at, so every first lookup is a miss
+        let sizes: Vec<f32> = (0..=CACHED_SIZES).map(|i| 31.5 + i as f32 / 64.0).collect();
+        let before = glyphs_rasterized();
+        for &size in &sizes {
+            cached_glyph_at('Q', size, 0);
+        }
+        assert_eq!(glyphs_rasterized(), before + sizes.len());
+
+        cached_glyph_at('Q', sizes[CACHED_SIZES], 0);
+        assert_eq!(
+            glyphs_rasterized(),
+            before + sizes.len(),
+            "dropped a recent size"
+        );
+        cached_glyph_at('Q', sizes[0], 0);
+        assert_eq!(
+            glyphs_rasterized(),
+            before + sizes.len() + 1,
+            "kept the oldest size"
+        );
+        let cache = GLYPH_CACHE.lock().unwrap();
+        assert!(cache.sizes.len() <= CACHED_SIZES);
+    }
+
+    #[test]
+    fn test_zero_radius_dilation_is_identity() {
+        let (coverage, w, h) = plus_glyph();
+        let (dilated, dw, dh) = dilate_coverage(&coverage, w, h, 0);
+        assert_eq!((dw, dh), (w, h));
+        assert_eq!(dilated, coverage);
+    }
+
+    #[test]
+    fn test_outlined_style_defaults() {
+        let style = TextStyle::outlined([255, 255, 255, 255]);
+        assert_eq!(style.outline, Some(([0, 0, 0, 255], 1)));
+        assert_eq!(style.shadow, None);
+        let shadowed = style.with_shadow([0, 0, 0, 128], 2, 2);
+        assert_eq!(shadowed.shadow, Some(([0, 0, 0, 128], 2, 2)));
+    }
+
+    #[test]
+    fn test_huge_text_draws_distinct_letters() {
+        use crate::graphics::pixel_utils::{draw_huge_text_styled, HugeLetters, HugeTextStyle};
+        let (width, height) = (100, 80);
+        let render = |text: &str, style| {
+            let mut frame = vec![0u8; width * height * 4];
+            let letters = HugeLetters {
+                char_height: 50,
+                style,
+                color: [255; 4],
+            };
+            draw_huge_text_styled(&mut frame, text, 10, 10, letters, width as u32);
+            frame
+                .chunks(4)
+                .map(|pixel| pixel[0] > 0)
+                .collect::<Vec<bool>>()
+        };
+        let letters = ["A", "B", "O", "1"].map(|c| render(c, HugeTextStyle::Outline));
+        for (i, letter) in letters.iter().enumerate() {
+            assert!(letter.iter().any(|&lit| lit));
+            for other in &letters[i + 1..] {
+                assert_ne!(letter, other);
+            }
+        }
+
+        // Capitals are as tall as asked, their tops at y
+        let filled = render("H", HugeTextStyle::Filled);
+        let rows: Vec<usize> = (0..height)
+            .filter(|y| filled[y * width..(y + 1) * width].iter().any(|&lit| lit))
+            .collect();
+        let (top, bottom) = (rows[0], *rows.last().unwrap());
+        assert!(top.abs_diff(10) <= 1, "top {}", top);
+        assert!((bottom + 1 - top).abs_diff(50) <= 2, "{}..{}", top, bottom);
+
+        // The outline is the edge of the filled letter, not all of it
+        let outline = render("H", HugeTextStyle::Outline);
+        assert!(outline.iter().zip(&filled).all(|(&o, &f)| !o || f));
+        let count = |pixels: &[bool]| pixels.iter().filter(|&&lit| lit).count();
+        assert!(count(&outline) < count(&filled));
     }
 }
@@ -0,0 +1,135 @@
+//! Audio/visual latency calibration. A click plays once a second and a square
+//! flashes `visual_latency_ms` after it enters the analysis; the user nudges
+//! the delay until flash and click line up. Closing saves the value.
+
+use crate::audio::audio_playback::is_playing;
+use crate::audio::click_track;
+use crate::audio::features;
+use crate::core::settings::{self, numeric_setting};
+use crate::graphics::draw_ctx::{DrawCtx, Region};
+use crate::text::text_rendering::{draw_text_with_background, estimate_text_width};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+/// Seconds the square stays lit per click
+const FLASH_DURATION: f32 = 0.1;
+const FLASH_SIZE: u32 = 120;
+const FLASH_COLOR: [u8; 4] = [255, 255, 255, 255];
+const TEXT_COLOR: [u8; 4] = [255, 255, 255, 255];
+const TEXT_BACKGROUND: [u8; 4] = [0, 0, 0, 200];
+const LINE_HEIGHT: f32 = 25.0;
+
+static CALIBRATING: AtomicBool = AtomicBool::new(false);
+
+pub fn set_calibrating(enabled: bool) {
+    CALIBRATING.store(enabled, Ordering::Relaxed);
+    click_track::set_clicks_enabled(enabled);
+    if !enabled {
+        let latency = features::visual_latency_ms();
+        settings::update_in_config_dir(|settings| settings.visual_latency_ms = latency);
+    }
+}
+
+pub fn is_calibrating() -> bool {
+    CALIBRATING.load(Ordering::Relaxed)
+}
+
+/// Opens or closes the calibration; closing saves the latency
+pub fn toggle_calibration() {
+    set_calibrating(!is_calibrating());
+}
+
+/// Moves the latency by `steps` of the setting's step, within its range
+pub fn nudge_latency(steps: i32) {
+    let Some(setting) = numeric_setting("visual_latency_ms") else {
+        return;
+    };
+    let latency = features::visual_latency_ms() + steps as f32 * setting.step;
+    features::set_visual_latency_ms(setting.clamp(latency));
+}
+
+/// Whether the square is lit at `now` for clicks played at `clicks`, shown
+/// `latency` seconds after each
+fn flash_active(clicks: &[Instant], now: Instant, latency: f32) -> bool {
+    clicks.iter().any(|click| {
+        let age = now.saturating_duration_since(*click).as_secs_f32();
+        (0.0..FLASH_DURATION).contains(&(age - latency))
+    })
+}
+
+/// Draws the flash square in the middle and the instructions under it
+pub fn draw_calibration(
+    frame: &mut [u8],
+    width: u32,
+    height: u32,
+    x_offset: usize,
+    buffer_width: u32,
+) {
+    let latency = features::visual_latency_ms();
+    let center_x = width as i32 / 2;
+    let center_y = height as i32 / 2;
+    let square_top = center_y - FLASH_SIZE as i32 / 2;
+    if flash_active(
+        &click_track::recent_clicks(),
+        Instant::now(),
+        // Like the features, a negative latency shows the newest spectrum
+        latency.max(0.0) / 1000.0,
+    ) {
+        let mut ctx = DrawCtx::new(
+            frame,
+            Region::new(x_offset, 0, width, height),
+            buffer_width,
+            0.0,
+        );
+        ctx.fill_rect(
+            center_x - FLASH_SIZE as i32 / 2,
+            square_top,
+            FLASH_SIZE,
+            FLASH_SIZE,
+            FLASH_COLOR,
+        );
+    }
+    let mut lines = vec![
+        format!("Visual latency: {:.0} ms", latency),
+        ", and . adjust until the flash meets the click; F8 saves".to_string(),
+    ];
+    if !is_playing() {
+        lines.push("Calibration needs audio output; none is playing".to_string());
+    }
+    for (i, line) in lines.iter().enumerate() {
+        let x = x_offset as f32 + (width as f32 - estimate_text_width(line)) / 2.0;
+        let y = (square_top + FLASH_SIZE as i32) as f32 + 40.0 + i as f32 * LINE_HEIGHT;
+        draw_text_with_background(
+            frame,
+            line,
+            x.max(x_offset as f32),
+            y,
+            TEXT_COLOR,
+            TEXT_BACKGROUND,
+            buffer_width,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_flash_follows_each_click_by_the_latency() {
+        let click = Instant::now();
+        let at = |ms: u64| click + Duration::from_millis(ms);
+        assert!(flash_active(&[click], at(0), 0.0));
+        assert!(!flash_active(&[click], at(100), 0.0));
+        // With 150 ms of latency the flash waits, then lasts its usual length
+        assert!(!flash_active(&[click], at(149), 0.15));
+        assert!(flash_active(&[click], at(150), 0.15));
+        assert!(flash_active(&[click], at(249), 0.15));
+        assert!(!flash_active(&[click], at(251), 0.15));
+        // An older click still counts once the latency reaches it
+        let clicks = [click, at(1000)];
+        assert!(flash_active(&clicks, at(1300), 0.3));
+        assert!(!flash_active(&[], at(0), 0.0));
+    }
+}
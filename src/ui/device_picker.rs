@@ -0,0 +1,208 @@
+//! Audio output picker, opened with O. Lists the system default and every
+//! output device; Up and Down move the highlight and Enter switches playback
+//! to it and saves the choice.
+
+use crate::audio::output_device::{self, DeviceChoice, DeviceInfo};
+use crate::core::accessibility::hud_backing;
+use crate::core::settings;
+use crate::graphics::pixel_utils::draw_rectangle_safe;
+use crate::text::text_rendering::{draw_text_styled, estimate_text_width, TextStyle};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+const ITEM_HEIGHT: f32 = 28.0;
+const MIN_WIDTH: f32 = 260.0;
+const PADDING: f32 = 8.0;
+/// Distance from the item's top to the text baseline
+const BASELINE: f32 = 20.0;
+const BACKGROUND: [u8; 4] = [10, 10, 20, 220];
+const HIGHLIGHT: [u8; 4] = [70, 90, 160, 220];
+const TEXT_COLOR: [u8; 4] = [235, 235, 245, 255];
+const TITLE_COLOR: [u8; 4] = [170, 180, 220, 255];
+const TITLE: &str = "Audio output (Up/Down, Enter)";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PickerState {
+    choices: Vec<DeviceChoice>,
+    labels: Vec<String>,
+    highlighted: usize,
+}
+
+impl PickerState {
+    /// The system default first, then each device, highlighting `chosen`
+    fn new(devices: &[DeviceInfo], chosen: &DeviceChoice, playing: Option<&str>) -> Self {
+        let mut choices = vec![DeviceChoice::SystemDefault];
+        choices.extend(devices.iter().map(|d| DeviceChoice::Named(d.name.clone())));
+        let labels = choices
+            .iter()
+            .map(|choice| {
+                let mut label = match choice {
+                    DeviceChoice::SystemDefault => "System default".to_string(),
+                    DeviceChoice::Named(name) => name.clone(),
+                };
+                if choice == chosen {
+                    label.insert_str(0, "* ");
+                }
+                if matches!(choice, DeviceChoice::Named(name) if Some(name.as_str()) == playing) {
+                    label.push_str(" (playing)");
+                }
+                label
+            })
+            .collect();
+        let highlighted = choices.iter().position(|c| c == chosen).unwrap_or(0);
+        Self {
+            choices,
+            labels,
+            highlighted,
+        }
+    }
+
+    /// Moves the highlight, wrapping around the ends
+    fn step(&mut self, steps: i32) {
+        let count = self.choices.len() as i32;
+        self.highlighted = (self.highlighted as i32 + steps).rem_euclid(count) as usize;
+    }
+}
+
+static PICKER: Mutex<Option<PickerState>> = Mutex::new(None);
+
+fn picker() -> MutexGuard<'static, Option<PickerState>> {
+    PICKER.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+pub fn is_picker_open() -> bool {
+    picker().is_some()
+}
+
+/// Opens the picker with the devices present now, or closes it. Returns
+/// whether it is open.
+pub fn toggle_picker() -> bool {
+    let mut picker = picker();
+    *picker = match *picker {
+        Some(_) => None,
+        None => Some(PickerState::new(
+            &output_device::list_output_devices(),
+            &output_device::selected_device(),
+            output_device::current_device().as_deref(),
+        )),
+    };
+    picker.is_some()
+}
+
+pub fn close_picker() {
+    *picker() = None;
+}
+
+pub fn move_highlight(steps: i32) {
+    if let Some(picker) = picker().as_mut() {
+        picker.step(steps);
+    }
+}
+
+/// Switches to the highlighted device, saves it, and closes the picker.
+/// Returns the choice made.
+pub fn choose_highlighted() -> Option<DeviceChoice> {
+    let picker = picker().take()?;
+    let choice = picker.choices[picker.highlighted].clone();
+    output_device::select_device(choice.clone());
+    let saved = choice.clone();
+    settings::update_in_config_dir(|settings| settings.output_device = saved);
+    Some(choice)
+}
+
+/// Draws the list centered in the frame
+pub fn draw_picker(frame: &mut [u8], width: u32, height: u32, x_offset: usize, buffer_width: u32) {
+    let slot = picker();
+    let Some(picker) = slot.as_ref() else {
+        return;
+    };
+    let text_width = picker
+        .labels
+        .iter()
+        .map(|label| estimate_text_width(label))
+        .fold(estimate_text_width(TITLE), f32::max);
+    let panel_width = (text_width + 2.0 * PADDING).max(MIN_WIDTH);
+    let panel_height = (picker.labels.len() + 1) as f32 * ITEM_HEIGHT + 2.0 * PADDING;
+    let left = x_offset as f32 + ((width as f32 - panel_width) / 2.0).max(0.0);
+    let top = ((height as f32 - panel_height) / 2.0).max(0.0);
+    draw_rectangle_safe(
+        frame,
+        left as i32,
+        top as i32,
+        panel_width as u32,
+        panel_height as u32,
+        hud_backing(BACKGROUND),
+        buffer_width,
+        height,
+    );
+    draw_text_styled(
+        frame,
+        TITLE,
+        left + PADDING,
+        top + PADDING + BASELINE,
+        &TextStyle::plain(TITLE_COLOR),
+        buffer_width,
+    );
+    for (i, label) in picker.labels.iter().enumerate() {
+        let item_top = top + PADDING + (i + 1) as f32 * ITEM_HEIGHT;
+        if i == picker.highlighted {
+            draw_rectangle_safe(
+                frame,
+                left as i32,
+                item_top as i32,
+                panel_width as u32,
+                ITEM_HEIGHT as u32,
+                HIGHLIGHT,
+                buffer_width,
+                height,
+            );
+        }
+        draw_text_styled(
+            frame,
+            label,
+            left + PADDING,
+            item_top + BASELINE,
+            &TextStyle::plain(TEXT_COLOR),
+            buffer_width,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_picker_lists_default_first_and_wraps() {
+        let devices = [
+            DeviceInfo {
+                name: "Speakers".to_string(),
+                is_default: true,
+            },
+            DeviceInfo {
+                name: "Headphones".to_string(),
+                is_default: false,
+            },
+        ];
+        let chosen = DeviceChoice::Named("Headphones".to_string());
+        let mut picker = PickerState::new(&devices, &chosen, Some("Speakers"));
+        assert_eq!(
+            picker.labels,
+            ["System default", "Speakers (playing)", "* Headphones"]
+        );
+        // Opening starts on the current choice
+        assert_eq!(picker.choices[picker.highlighted], chosen);
+        picker.step(1);
+        assert_eq!(
+            picker.choices[picker.highlighted],
+            DeviceChoice::SystemDefault
+        );
+        picker.step(-1);
+        picker.step(-1);
+        assert_eq!(picker.highlighted, 1);
+
+        // A chosen device that is gone starts on the default
+        let picker = PickerState::new(&devices[..1], &chosen, None);
+        assert_eq!(picker.highlighted, 0);
+        assert_eq!(picker.labels.len(), 2);
+    }
+}
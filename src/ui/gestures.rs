@@ -0,0 +1,331 @@
+//! Turns raw pointer input into clicks, double-clicks, drags, and long presses.
+//! Timestamps are passed in, in seconds, so recordings replay the same gestures
+//! and tests can drive the recognizer with a synthetic timeline.
+
+use crate::core::types::Position;
+use glam::Vec2;
+
+/// Longest gap between two clicks that still counts as a double-click, in seconds
+pub const DOUBLE_CLICK_WINDOW: f32 = 0.4;
+/// Furthest the second click of a double-click may land from the first, in pixels
+pub const DOUBLE_CLICK_TOLERANCE: f32 = 8.0;
+/// Distance the pointer must move while pressed before a press becomes a drag
+pub const DRAG_THRESHOLD: f32 = 6.0;
+/// How long a press must be held in place to count as a long press, in seconds
+pub const LONG_PRESS_TIME: f32 = 0.6;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PointerEvent {
+    Pressed(Position),
+    Moved(Position),
+    Released(Position),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Gesture {
+    Click(Position),
+    DoubleClick(Position),
+    /// Emitted at the press position once the pointer passes the drag threshold
+    DragStart(Position),
+    DragMove {
+        pos: Position,
+        delta: Vec2,
+    },
+    DragEnd {
+        start: Position,
+        end: Position,
+    },
+    LongPress(Position),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PressState {
+    Idle,
+    Pressed {
+        origin: Position,
+        at: f32,
+        long_pressed: bool,
+    },
+    Dragging {
+        origin: Position,
+        last: Position,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct GestureRecognizer {
+    state: PressState,
+    /// Position and time of the last click, while a double-click is still possible
+    last_click: Option<(Position, f32)>,
+    /// Button state from the previous `update`, to find press and release edges
+    was_held: bool,
+}
+
+impl GestureRecognizer {
+    pub fn new() -> Self {
+        Self {
+            state: PressState::Idle,
+            last_click: None,
+            was_held: false,
+        }
+    }
+
+    /// Feeds one pointer event at time `now`
+    pub fn handle(&mut self, event: PointerEvent, now: f32) -> Vec<Gesture> {
+        let mut gestures = self.tick(now);
+        match (event, self.state) {
+            (PointerEvent::Pressed(pos), _) => {
+                self.state = PressState::Pressed {
+                    origin: pos,
+                    at: now,
+                    long_pressed: false,
+                };
+            }
+            (PointerEvent::Moved(pos), PressState::Pressed { origin, .. }) => {
+                if pos.distance(origin) >= DRAG_THRESHOLD {
+                    self.state = PressState::Dragging { origin, last: pos };
+                    self.last_click = None;
+                    gestures.push(Gesture::DragStart(origin));
+                    gestures.push(Gesture::DragMove {
+                        pos,
+                        delta: pos - origin,
+                    });
+                }
+            }
+            (PointerEvent::Moved(pos), PressState::Dragging { origin, last }) => {
+                if pos != last {
+                    self.state = PressState::Dragging { origin, last: pos };
+                    gestures.push(Gesture::DragMove {
+                        pos,
+                        delta: pos - last,
+                    });
+                }
+            }
+            (PointerEvent::Released(pos), PressState::Dragging { origin, .. }) => {
+                self.state = PressState::Idle;
+                gestures.push(Gesture::DragEnd {
+                    start: origin,
+                    end: pos,
+                });
+            }
+            (
+                PointerEvent::Released(_),
+                PressState::Pressed {
+                    origin,
+                    long_pressed,
+                    ..
+                },
+            ) => {
+                self.state = PressState::Idle;
+                if !long_pressed {
+                    gestures.push(self.click(origin, now));
+                }
+            }
+            (PointerEvent::Moved(_) | PointerEvent::Released(_), PressState::Idle) => {}
+        }
+        gestures
+    }
+
+    /// Fires a long press once the pointer has been held in place long enough.
+    /// Call every frame; events also check this themselves.
+    pub fn tick(&mut self, now: f32) -> Vec<Gesture> {
+        match self.state {
+            PressState::Pressed {
+                origin,
+                at,
+                long_pressed: false,
+            } if now - at >= LONG_PRESS_TIME => {
+                self.state = PressState::Pressed {
+                    origin,
+                    at,
+                    long_pressed: true,
+                };
+                self.last_click = None;
+                vec![Gesture::LongPress(origin)]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Derives events from per-frame button and cursor state, as polled input provides it
+    pub fn update(&mut self, cursor: Option<(f32, f32)>, held: bool, now: f32) -> Vec<Gesture> {
+        let was_held = std::mem::replace(&mut self.was_held, held);
+        let Some((x, y)) = cursor else {
+            return self.tick(now);
+        };
+        let pos = Position::new(x, y);
+        let event = match (was_held, held) {
+            (false, true) => PointerEvent::Pressed(pos),
+            (true, false) => PointerEvent::Released(pos),
+            _ => PointerEvent::Moved(pos),
+        };
+        self.handle(event, now)
+    }
+
+    /// A click, or a double-click when it closely follows the previous one.
+    /// The click completing a double-click can't start another.
+    fn click(&mut self, pos: Position, now: f32) -> Gesture {
+        match self.last_click.take() {
+            Some((last, at))
+                if now - at <= DOUBLE_CLICK_WINDOW
+                    && pos.distance(last) <= DOUBLE_CLICK_TOLERANCE =>
+            {
+                Gesture::DoubleClick(pos)
+            }
+            _ => {
+                self.last_click = Some((pos, now));
+                Gesture::Click(pos)
+            }
+        }
+    }
+}
+
+impl Default for GestureRecognizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(x: f32, y: f32) -> Position {
+        Position::new(x, y)
+    }
+
+    /// Runs a timeline of (time, event) pairs and collects every gesture
+    fn run(events: &[(f32, PointerEvent)]) -> Vec<Gesture> {
+        let mut recognizer = GestureRecognizer::new();
+        events
+            .iter()
+            .flat_map(|&(now, event)| recognizer.handle(event, now))
+            .collect()
+    }
+
+    #[test]
+    fn test_double_click_needs_both_time_and_distance() {
+        use PointerEvent::*;
+        let quick = run(&[
+            (0.0, Pressed(at(10.0, 10.0))),
+            (0.05, Released(at(10.0, 10.0))),
+            (0.3, Pressed(at(14.0, 12.0))),
+            (0.35, Released(at(14.0, 12.0))),
+        ]);
+        assert_eq!(
+            quick,
+            vec![
+                Gesture::Click(at(10.0, 10.0)),
+                Gesture::DoubleClick(at(14.0, 12.0))
+            ]
+        );
+
+        let slow = run(&[
+            (0.0, Pressed(at(10.0, 10.0))),
+            (0.05, Released(at(10.0, 10.0))),
+            (0.5, Pressed(at(10.0, 10.0))),
+            (0.55, Released(at(10.0, 10.0))),
+        ]);
+        assert!(slow.iter().all(|g| matches!(g, Gesture::Click(_))));
+
+        let far = run(&[
+            (0.0, Pressed(at(10.0, 10.0))),
+            (0.05, Released(at(10.0, 10.0))),
+            (0.2, Pressed(at(30.0, 10.0))),
+            (0.25, Released(at(30.0, 10.0))),
+        ]);
+        assert!(far.iter().all(|g| matches!(g, Gesture::Click(_))));
+
+        // A third quick click starts over instead of making a second double-click
+        let triple = run(&[
+            (0.0, Pressed(at(10.0, 10.0))),
+            (0.05, Released(at(10.0, 10.0))),
+            (0.1, Pressed(at(10.0, 10.0))),
+            (0.15, Released(at(10.0, 10.0))),
+            (0.2, Pressed(at(10.0, 10.0))),
+            (0.25, Released(at(10.0, 10.0))),
+        ]);
+        assert_eq!(triple[2], Gesture::Click(at(10.0, 10.0)));
+    }
+
+    #[test]
+    fn test_small_moves_stay_a_click_and_larger_ones_drag() {
+        use PointerEvent::*;
+        let jitter = run(&[
+            (0.0, Pressed(at(50.0, 50.0))),
+            (0.02, Moved(at(53.0, 52.0))),
+            (0.04, Released(at(53.0, 52.0))),
+        ]);
+        assert_eq!(jitter, vec![Gesture::Click(at(50.0, 50.0))]);
+
+        let drag = run(&[
+            (0.0, Pressed(at(50.0, 50.0))),
+            (0.02, Moved(at(54.0, 50.0))),
+            (0.04, Moved(at(60.0, 50.0))),
+            (0.06, Moved(at(70.0, 50.0))),
+            (0.08, Released(at(70.0, 50.0))),
+        ]);
+        assert_eq!(
+            drag,
+            vec![
+                Gesture::DragStart(at(50.0, 50.0)),
+                Gesture::DragMove {
+                    pos: at(60.0, 50.0),
+                    delta: Vec2::new(10.0, 0.0),
+                },
+                Gesture::DragMove {
+                    pos: at(70.0, 50.0),
+                    delta: Vec2::new(10.0, 0.0),
+                },
+                Gesture::DragEnd {
+                    start: at(50.0, 50.0),
+                    end: at(70.0, 50.0),
+                },
+            ]
+        );
+
+        // A drag never counts toward a double-click
+        let mut recognizer = GestureRecognizer::new();
+        for (now, event) in [
+            (0.0, Pressed(at(50.0, 50.0))),
+            (0.02, Moved(at(70.0, 50.0))),
+            (0.04, Released(at(70.0, 50.0))),
+        ] {
+            recognizer.handle(event, now);
+        }
+        recognizer.handle(Pressed(at(70.0, 50.0)), 0.1);
+        let gestures = recognizer.handle(Released(at(70.0, 50.0)), 0.12);
+        assert_eq!(gestures, vec![Gesture::Click(at(70.0, 50.0))]);
+    }
+
+    #[test]
+    fn test_long_press_fires_once_and_swallows_the_click() {
+        let mut recognizer = GestureRecognizer::new();
+        let origin = at(20.0, 20.0);
+        recognizer.handle(PointerEvent::Pressed(origin), 1.0);
+        assert!(recognizer.tick(1.5).is_empty());
+        assert_eq!(recognizer.tick(1.6), vec![Gesture::LongPress(origin)]);
+        assert!(recognizer.tick(2.5).is_empty());
+        assert!(recognizer
+            .handle(PointerEvent::Released(origin), 2.6)
+            .is_empty());
+
+        // Dragging before the deadline cancels the long press
+        recognizer.handle(PointerEvent::Pressed(origin), 3.0);
+        recognizer.handle(PointerEvent::Moved(at(40.0, 20.0)), 3.1);
+        assert!(recognizer.tick(4.0).is_empty());
+    }
+
+    #[test]
+    fn test_update_finds_edges_in_polled_state() {
+        let mut recognizer = GestureRecognizer::new();
+        let cursor = Some((5.0, 5.0));
+        assert!(recognizer.update(cursor, false, 0.0).is_empty());
+        assert!(recognizer.update(cursor, true, 0.016).is_empty());
+        assert!(recognizer.update(cursor, true, 0.032).is_empty());
+        assert_eq!(
+            recognizer.update(cursor, false, 0.048),
+            vec![Gesture::Click(at(5.0, 5.0))]
+        );
+    }
+}
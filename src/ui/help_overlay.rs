@@ -0,0 +1,231 @@
+//! Key help toggled with H. Lists the active scene's bindings and then the
+//! ones every scene shares, straight from the scene registry, so it stays in
+//! step with the exported manifest. PgUp and PgDn page through lists that
+//! don't fit the frame.
+
+use crate::core::accessibility::hud_backing;
+use crate::core::scenes::{self, HelpEntry, SceneInfo};
+use crate::graphics::pixel_utils::draw_rectangle_safe;
+use crate::text::text_rendering::{draw_text_styled, estimate_text_width, TextStyle};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+const LINE_HEIGHT: f32 = 24.0;
+const PADDING: f32 = 12.0;
+/// Space kept between the panel and the frame edges
+const MARGIN: f32 = 20.0;
+/// Distance from a line's top to its text baseline
+const BASELINE: f32 = 17.0;
+/// Gap between the key column and the action column
+const COLUMN_GAP: f32 = 16.0;
+const BACKGROUND: [u8; 4] = [10, 10, 20, 200];
+const TITLE_COLOR: [u8; 4] = [255, 220, 120, 255];
+const KEY_COLOR: [u8; 4] = [150, 200, 255, 255];
+const TEXT_COLOR: [u8; 4] = [235, 235, 245, 255];
+
+static HELP_OPEN: AtomicBool = AtomicBool::new(false);
+
+struct Paging {
+    /// Page shown, kept within the page count of the last drawn frame
+    page: usize,
+    /// Pages in the last drawn frame
+    count: usize,
+}
+
+static PAGING: Mutex<Paging> = Mutex::new(Paging { page: 0, count: 1 });
+
+fn paging() -> MutexGuard<'static, Paging> {
+    PAGING.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+pub fn is_help_open() -> bool {
+    HELP_OPEN.load(Ordering::Relaxed)
+}
+
+pub fn set_help_open(open: bool) {
+    HELP_OPEN.store(open, Ordering::Relaxed);
+}
+
+/// Shows or hides the help, starting over from its first page
+pub fn toggle_help() -> bool {
+    paging().page = 0;
+    !HELP_OPEN.fetch_xor(true, Ordering::Relaxed)
+}
+
+/// Moves `steps` pages forward, or back for negative steps, stopping at either end
+pub fn turn_page(steps: i32) {
+    let mut paging = paging();
+    let last = paging.count.saturating_sub(1) as i64;
+    paging.page = (paging.page as i64 + steps as i64).clamp(0, last) as usize;
+}
+
+/// Every binding the help lists for `scene`: its own first, then the shared ones
+pub fn help_entries(scene: &SceneInfo) -> Vec<HelpEntry> {
+    scene
+        .help
+        .iter()
+        .chain(scenes::common_help())
+        .copied()
+        .collect()
+}
+
+/// Binding lines that fit under the title in a frame `height` pixels tall
+fn lines_per_page(height: u32) -> usize {
+    let room = height as f32 - 2.0 * (MARGIN + PADDING) - LINE_HEIGHT;
+    ((room / LINE_HEIGHT).floor() as usize).max(1)
+}
+
+/// `entries` split into pages of `per_page` lines; an empty list is one empty page
+fn paginate(entries: &[HelpEntry], per_page: usize) -> Vec<&[HelpEntry]> {
+    if entries.is_empty() {
+        return vec![&[]];
+    }
+    entries.chunks(per_page.max(1)).collect()
+}
+
+/// Heading naming the scene, plus the page when there is more than one
+fn title(scene: &SceneInfo, page: usize, pages: usize) -> String {
+    if pages > 1 {
+        format!("Keys: {} ({}/{}, PgUp/PgDn)", scene.name, page + 1, pages)
+    } else {
+        format!("Keys: {}", scene.name)
+    }
+}
+
+/// Draws the current page centered on a translucent panel sized to its text
+pub fn draw_help(frame: &mut [u8], width: u32, height: u32, x_offset: usize, buffer_width: u32) {
+    if !is_help_open() {
+        return;
+    }
+    let scene = scenes::active_scene();
+    let entries = help_entries(scene);
+    let pages = paginate(&entries, lines_per_page(height));
+    let page = {
+        let mut paging = paging();
+        paging.count = pages.len();
+        paging.page = paging.page.min(pages.len() - 1);
+        paging.page
+    };
+    let title = title(scene, page, pages.len());
+
+    // Sized from every entry, not just this page's, so paging doesn't resize the panel
+    let key_width = entries
+        .iter()
+        .map(|entry| estimate_text_width(entry.key))
+        .fold(0.0, f32::max);
+    let action_width = entries
+        .iter()
+        .map(|entry| estimate_text_width(entry.action))
+        .fold(0.0, f32::max);
+    let content_width = (key_width + COLUMN_GAP + action_width).max(estimate_text_width(&title));
+    let panel_width = (content_width + 2.0 * PADDING).min(width as f32 - 2.0 * MARGIN);
+    let panel_height = 2.0 * PADDING + LINE_HEIGHT * (1 + pages[page].len()) as f32;
+    let left = ((width as f32 - panel_width) / 2.0).max(0.0);
+    let top = ((height as f32 - panel_height) / 2.0).max(0.0);
+    let screen_left = x_offset as f32 + left;
+
+    draw_rectangle_safe(
+        frame,
+        screen_left as i32,
+        top as i32,
+        panel_width as u32,
+        panel_height as u32,
+        hud_backing(BACKGROUND),
+        buffer_width,
+        height,
+    );
+    let text_left = screen_left + PADDING;
+    draw_text_styled(
+        frame,
+        &title,
+        text_left,
+        top + PADDING + BASELINE,
+        &TextStyle::plain(TITLE_COLOR),
+        buffer_width,
+    );
+    for (i, entry) in pages[page].iter().enumerate() {
+        let baseline = top + PADDING + (i + 1) as f32 * LINE_HEIGHT + BASELINE;
+        draw_text_styled(
+            frame,
+            entry.key,
+            text_left,
+            baseline,
+            &TextStyle::plain(KEY_COLOR),
+            buffer_width,
+        );
+        draw_text_styled(
+            frame,
+            entry.action,
+            text_left + key_width + COLUMN_GAP,
+            baseline,
+            &TextStyle::plain(TEXT_COLOR),
+            buffer_width,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The overlay's text for `entries`, one string per page
+    fn page_text(entries: &[HelpEntry], per_page: usize) -> Vec<String> {
+        paginate(entries, per_page)
+            .iter()
+            .map(|page| {
+                page.iter()
+                    .map(|entry| format!("{} {}", entry.key, entry.action))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_content_comes_from_the_scene_registry() {
+        let pairs = |id| {
+            help_entries(scenes::find_scene(id).unwrap())
+                .iter()
+                .map(|entry| (entry.key, entry.action))
+                .collect::<Vec<_>>()
+        };
+        // The scene's own keys come first, then the shared ones
+        let softbody = pairs("softbody");
+        assert_eq!(softbody[0], ("B", "Toggle the blob"));
+        assert_eq!(softbody[1], ("H", "Show these keys (PgUp/PgDn to page)"));
+        assert!(softbody.contains(&("F6", "Reset the scene")));
+        assert_eq!(softbody.last(), Some(&("Esc", "Close the menu or quit")));
+        let world = pairs("world");
+        assert_eq!(world[0], ("Space", "Switch world mode"));
+        assert!(world.contains(&("G", "Cycle gravity")));
+        assert!(!world.contains(&("B", "Toggle the blob")));
+        // A scene without keys of its own lists only the shared ones
+        assert_eq!(
+            pairs("tunnel")[0],
+            ("H", "Show these keys (PgUp/PgDn to page)")
+        );
+
+        // Rebinding an entry changes what the overlay says
+        let mut entries = help_entries(&scenes::SCENES[0]);
+        let before = page_text(&entries, 100).concat();
+        entries[0].key = "Q";
+        let after = page_text(&entries, 100).concat();
+        assert_ne!(before, after);
+        assert!(after.starts_with(&format!("Q {}", entries[0].action)));
+    }
+
+    #[test]
+    fn test_long_lists_page_without_dropping_entries() {
+        let entries = help_entries(scenes::find_scene("world").unwrap());
+        let per_page = 5;
+        let pages = paginate(&entries, per_page);
+        assert_eq!(pages.len(), entries.len().div_ceil(per_page));
+        assert!(pages.iter().all(|page| page.len() <= per_page));
+        assert_eq!(pages.concat(), entries);
+
+        // A short frame still shows a line per page, a tall one fits everything
+        assert_eq!(lines_per_page(40), 1);
+        assert_eq!(paginate(&entries, lines_per_page(4000)).len(), 1);
+        assert_eq!(paginate(&[], 5).len(), 1);
+    }
+}
@@ -0,0 +1,304 @@
+//! Keeps the HUD elements along the frame's edges from overlapping. Each
+//! frame every element with something to show asks for a spot by anchor,
+//! priority, and measured size. Higher priorities are placed first, right at
+//! their anchor; later ones stack inward past what is already there, or are
+//! hidden when the frame has no room left. Nothing carries over between
+//! frames, so an element hidden in a small window comes back as it grows.
+
+use std::sync::Mutex;
+
+/// Space between the frame's edge and the outermost elements
+pub const EDGE_MARGIN: f32 = 8.0;
+/// Space kept between stacked elements
+const GAP: f32 = 6.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HudAnchor {
+    TopLeft,
+    TopCenter,
+    TopRight,
+    BottomLeft,
+    BottomCenter,
+    BottomRight,
+}
+
+impl HudAnchor {
+    fn is_top(self) -> bool {
+        matches!(
+            self,
+            HudAnchor::TopLeft | HudAnchor::TopCenter | HudAnchor::TopRight
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HudElement {
+    StatusIcons,
+    DebugOverlay,
+    Toast,
+    AlgorithmStats,
+}
+
+/// One element asking for room this frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HudRequest {
+    pub element: HudElement,
+    pub anchor: HudAnchor,
+    /// Higher is placed first and hidden last
+    pub priority: u8,
+    /// Width and height, backgrounds included
+    pub size: (f32, f32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HudRect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl HudRect {
+    pub fn right(&self) -> f32 {
+        self.x + self.width
+    }
+
+    pub fn bottom(&self) -> f32 {
+        self.y + self.height
+    }
+
+    pub fn intersects(&self, other: &HudRect) -> bool {
+        self.x < other.right()
+            && other.x < self.right()
+            && self.y < other.bottom()
+            && other.y < self.bottom()
+    }
+
+    fn grown(&self, by: f32) -> HudRect {
+        HudRect {
+            x: self.x - by,
+            y: self.y - by,
+            width: self.width + 2.0 * by,
+            height: self.height + 2.0 * by,
+        }
+    }
+}
+
+/// Where each element that got room this frame draws
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HudLayout {
+    placed: Vec<(HudElement, HudRect)>,
+}
+
+impl HudLayout {
+    /// The element's draw region; None when it asked for none or was hidden
+    pub fn region(&self, element: HudElement) -> Option<HudRect> {
+        self.placed
+            .iter()
+            .find_map(|&(placed, rect)| (placed == element).then_some(rect))
+    }
+
+    pub fn placed(&self) -> &[(HudElement, HudRect)] {
+        &self.placed
+    }
+}
+
+/// Assigns non-overlapping regions inside a `width` x `height` frame.
+/// Equal priorities are placed in the order they were asked for.
+pub fn solve(requests: &[HudRequest], width: u32, height: u32) -> HudLayout {
+    let mut order: Vec<&HudRequest> = requests.iter().collect();
+    order.sort_by_key(|request| std::cmp::Reverse(request.priority));
+    let mut layout = HudLayout::default();
+    for request in order {
+        if let Some(rect) = place(request, width as f32, height as f32, &layout.placed) {
+            layout.placed.push((request.element, rect));
+        }
+    }
+    layout
+}
+
+/// The request's spot at its anchor, moved inward past everything placed so
+/// far; None when that runs out of the frame
+fn place(
+    request: &HudRequest,
+    width: f32,
+    height: f32,
+    placed: &[(HudElement, HudRect)],
+) -> Option<HudRect> {
+    let (w, h) = request.size;
+    if w > width - 2.0 * EDGE_MARGIN {
+        return None;
+    }
+    let x = match request.anchor {
+        HudAnchor::TopLeft | HudAnchor::BottomLeft => EDGE_MARGIN,
+        HudAnchor::TopCenter | HudAnchor::BottomCenter => ((width - w) / 2.0).max(EDGE_MARGIN),
+        HudAnchor::TopRight | HudAnchor::BottomRight => width - EDGE_MARGIN - w,
+    };
+    let top = request.anchor.is_top();
+    let mut rect = HudRect {
+        x,
+        y: if top {
+            EDGE_MARGIN
+        } else {
+            height - EDGE_MARGIN - h
+        },
+        width: w,
+        height: h,
+    };
+    // Each move clears one more placed element for good, so this ends
+    while let Some(blocker) = placed
+        .iter()
+        .map(|&(_, placed)| placed)
+        .find(|placed| placed.grown(GAP).intersects(&rect))
+    {
+        rect.y = if top {
+            blocker.bottom() + GAP
+        } else {
+            blocker.y - GAP - h
+        };
+    }
+    (rect.y >= EDGE_MARGIN && rect.bottom() <= height - EDGE_MARGIN).then_some(rect)
+}
+
+/// The layout of the last frame drawn, for hit tests between frames
+static CURRENT_LAYOUT: Mutex<Option<HudLayout>> = Mutex::new(None);
+
+pub fn set_current_layout(layout: HudLayout) {
+    if let Ok(mut current) = CURRENT_LAYOUT.lock() {
+        *current = Some(layout);
+    }
+}
+
+/// Where `element` was drawn last frame
+pub fn current_region(element: HudElement) -> Option<HudRect> {
+    CURRENT_LAYOUT.lock().ok()?.as_ref()?.region(element)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(
+        element: HudElement,
+        anchor: HudAnchor,
+        priority: u8,
+        size: (f32, f32),
+    ) -> HudRequest {
+        HudRequest {
+            element,
+            anchor,
+            priority,
+            size,
+        }
+    }
+
+    /// Every element at about the size it measures with a few lines up
+    fn all_elements() -> Vec<HudRequest> {
+        vec![
+            request(HudElement::Toast, HudAnchor::TopCenter, 2, (380.0, 55.0)),
+            request(
+                HudElement::StatusIcons,
+                HudAnchor::TopRight,
+                3,
+                (34.0, 16.0),
+            ),
+            request(
+                HudElement::AlgorithmStats,
+                HudAnchor::TopLeft,
+                1,
+                (210.0, 100.0),
+            ),
+            request(
+                HudElement::DebugOverlay,
+                HudAnchor::BottomLeft,
+                3,
+                (470.0, 130.0),
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_elements_never_overlap_at_any_window_size() {
+        let requests = all_elements();
+        let sizes = [
+            (1920, 1080),
+            (800, 600),
+            (640, 360),
+            (480, 320),
+            (400, 240),
+            (320, 200),
+            (160, 120),
+        ];
+        for (width, height) in sizes {
+            let layout = solve(&requests, width, height);
+            let placed = layout.placed();
+            for (i, (a, rect)) in placed.iter().enumerate() {
+                assert!(rect.x >= 0.0 && rect.right() <= width as f32, "{:?}", a);
+                assert!(rect.y >= 0.0 && rect.bottom() <= height as f32, "{:?}", a);
+                for (b, other) in &placed[i + 1..] {
+                    assert!(
+                        !rect.intersects(other),
+                        "{:?} and {:?} overlap at {}x{}",
+                        a,
+                        b,
+                        width,
+                        height
+                    );
+                }
+            }
+        }
+
+        // Everything fits in a large window; a tiny one keeps only the
+        // highest priority that fits, and growing brings the rest back
+        assert_eq!(solve(&requests, 1920, 1080).placed().len(), 4);
+        let tiny = solve(&requests, 160, 120);
+        assert_eq!(
+            tiny.placed().iter().map(|&(e, _)| e).collect::<Vec<_>>(),
+            [HudElement::StatusIcons]
+        );
+        assert_eq!(solve(&requests, 800, 600).placed().len(), 4);
+    }
+
+    #[test]
+    fn test_lower_priorities_stack_inward_or_hide() {
+        let stats = request(
+            HudElement::AlgorithmStats,
+            HudAnchor::TopLeft,
+            1,
+            (200.0, 100.0),
+        );
+        let toast = request(HudElement::Toast, HudAnchor::TopCenter, 2, (300.0, 50.0));
+        // Asked for first, but placed second
+        let layout = solve(&[stats, toast], 400, 400);
+        let toast_rect = layout.region(HudElement::Toast).unwrap();
+        assert_eq!((toast_rect.x, toast_rect.y), (50.0, EDGE_MARGIN));
+        let stats_rect = layout.region(HudElement::AlgorithmStats).unwrap();
+        assert_eq!(
+            (stats_rect.x, stats_rect.y),
+            (EDGE_MARGIN, toast_rect.bottom() + GAP)
+        );
+
+        // Bottom anchors stack upward
+        let debug = request(
+            HudElement::DebugOverlay,
+            HudAnchor::BottomLeft,
+            3,
+            (100.0, 40.0),
+        );
+        let below = request(HudElement::Toast, HudAnchor::BottomLeft, 2, (100.0, 40.0));
+        let layout = solve(&[debug, below], 400, 400);
+        let debug_rect = layout.region(HudElement::DebugOverlay).unwrap();
+        assert_eq!(debug_rect.bottom(), 400.0 - EDGE_MARGIN);
+        assert_eq!(
+            layout.region(HudElement::Toast).unwrap().bottom(),
+            debug_rect.y - GAP
+        );
+
+        // Too short for both: the lower priority is hidden, not squeezed
+        let short = solve(&[stats, toast], 400, 150);
+        assert!(short.region(HudElement::Toast).is_some());
+        assert_eq!(short.region(HudElement::AlgorithmStats), None);
+        // Too narrow for an element at all
+        assert_eq!(solve(&[toast], 310, 400).region(HudElement::Toast), None);
+    }
+}
@@ -0,0 +1,319 @@
+//! Intro shown on first launch: the title fades in letter by letter with a
+//! burst of sparks, then a hint box points out the controls. The scene keeps
+//! running underneath the whole time; any key skips ahead.
+
+use crate::core::accessibility::hud_backing;
+use crate::core::sim_rng::sim_rng;
+use crate::graphics::pixel_utils::{blend_pixel_safe, draw_rectangle_safe};
+use crate::text::text_rendering::{
+    draw_glyph_scaled, draw_text_styled, estimate_text_width, glyph_advance, GlyphStyle, TextStyle,
+};
+use rand::Rng;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+const TITLE: &str = "StimStation";
+const TITLE_SIZE: f32 = 72.0;
+/// Length of the title animation, in seconds
+pub const SPLASH_DURATION: f32 = 3.0;
+/// How long the hint stays up unless a key dismisses it
+pub const HINT_DURATION: f32 = 8.0;
+/// Delay between one letter starting to fade in and the next
+const LETTER_STAGGER: f32 = 0.12;
+const LETTER_FADE: f32 = 0.4;
+/// The backdrop and title fade out over the end of the splash
+const SPLASH_FADE_OUT: f32 = 0.5;
+const SPARKS_PER_LETTER: usize = 10;
+/// Per-second velocity falloff of the sparks
+const SPARK_DRAG: f32 = 2.5;
+const BACKDROP: [u8; 4] = [8, 8, 16, 255];
+const TITLE_STYLE: GlyphStyle = GlyphStyle {
+    size: TITLE_SIZE,
+    stroke: 0,
+    color: [235, 235, 255, 255],
+};
+const SPARK_COLOR: [u8; 4] = [255, 200, 120, 255];
+const HINT_BACKGROUND: [u8; 4] = [10, 10, 20, 200];
+const HINT_TEXT: [u8; 4] = [235, 235, 245, 255];
+const HINT_LINE_HEIGHT: f32 = 26.0;
+const HINT_PADDING: f32 = 12.0;
+const HINT_LINES: [&str; 4] = [
+    "Welcome to StimStation",
+    "Long-press anywhere for the scene menu; Esc closes it or quits",
+    "F3 debug info, F4 clean mode, F7 color filters",
+    "Press any key to begin",
+];
+
+static FIRST_RUN_DONE: AtomicBool = AtomicBool::new(false);
+static INTRO_FORCED: AtomicBool = AtomicBool::new(false);
+
+/// Whether the intro has been seen, as stored in the settings
+pub fn first_run_done() -> bool {
+    FIRST_RUN_DONE.load(Ordering::Relaxed)
+}
+
+pub fn set_first_run_done(done: bool) {
+    FIRST_RUN_DONE.store(done, Ordering::Relaxed);
+}
+
+/// Shows the intro even after the first run (`--show-intro`)
+pub fn force_intro() {
+    INTRO_FORCED.store(true, Ordering::Relaxed);
+}
+
+pub fn intro_forced() -> bool {
+    INTRO_FORCED.load(Ordering::Relaxed)
+}
+
+pub fn should_show_intro(first_run_done: bool, forced: bool) -> bool {
+    forced || !first_run_done
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntroPhase {
+    Splash,
+    Hint,
+    Done,
+}
+
+/// A spark thrown off a letter as it appears, positioned relative to that letter
+#[derive(Debug, Clone, Copy)]
+struct Spark {
+    letter: usize,
+    offset: (f32, f32),
+    velocity: (f32, f32),
+    life: f32,
+    max_life: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Intro {
+    phase: IntroPhase,
+    /// Seconds spent in the current phase
+    elapsed: f32,
+    sparks: Vec<Spark>,
+    /// Set once the intro has ended and the caller has been told
+    completion_reported: bool,
+}
+
+impl Intro {
+    /// An intro that starts with the title animation
+    pub fn new() -> Self {
+        Self {
+            phase: IntroPhase::Splash,
+            elapsed: 0.0,
+            sparks: Vec::new(),
+            completion_reported: false,
+        }
+    }
+
+    /// An intro that has already run, for launches that skip it
+    pub fn finished() -> Self {
+        Self {
+            phase: IntroPhase::Done,
+            completion_reported: true,
+            ..Self::new()
+        }
+    }
+
+    pub fn phase(&self) -> IntroPhase {
+        self.phase
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.phase != IntroPhase::Done
+    }
+
+    fn enter(&mut self, phase: IntroPhase) {
+        self.phase = phase;
+        self.elapsed = 0.0;
+        self.sparks.clear();
+    }
+
+    /// Jumps from the title to the hint, or from the hint to the scene
+    pub fn skip(&mut self) {
+        match self.phase {
+            IntroPhase::Splash => self.enter(IntroPhase::Hint),
+            IntroPhase::Hint => self.enter(IntroPhase::Done),
+            IntroPhase::Done => {}
+        }
+    }
+
+    /// True once, the first time this is asked after the intro has ended
+    pub fn take_completion(&mut self) -> bool {
+        let done = self.phase == IntroPhase::Done && !self.completion_reported;
+        self.completion_reported |= done;
+        done
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        let before = self.elapsed;
+        self.elapsed += dt;
+        match self.phase {
+            IntroPhase::Splash => {
+                for letter in 0..TITLE.chars().count() {
+                    let start = letter as f32 * LETTER_STAGGER;
+                    if start >= before && start < self.elapsed {
+                        self.spawn_sparks(letter);
+                    }
+                }
+                let drag = (-SPARK_DRAG * dt).exp();
+                for spark in &mut self.sparks {
+                    spark.offset.0 += spark.velocity.0 * dt;
+                    spark.offset.1 += spark.velocity.1 * dt;
+                    spark.velocity.0 *= drag;
+                    spark.velocity.1 *= drag;
+                    spark.life -= dt;
+                }
+                self.sparks.retain(|spark| spark.life > 0.0);
+                if self.elapsed >= SPLASH_DURATION {
+                    self.enter(IntroPhase::Hint);
+                }
+            }
+            IntroPhase::Hint if self.elapsed >= HINT_DURATION => self.enter(IntroPhase::Done),
+            _ => {}
+        }
+    }
+
+    fn spawn_sparks(&mut self, letter: usize) {
+        let mut rng = sim_rng();
+        for _ in 0..SPARKS_PER_LETTER {
+            let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+            let speed = rng.gen_range(40.0..120.0);
+            let max_life = rng.gen_range(0.6..1.2);
+            self.sparks.push(Spark {
+                letter,
+                offset: (0.0, 0.0),
+                velocity: (angle.cos() * speed, angle.sin() * speed),
+                life: max_life,
+                max_life,
+            });
+        }
+    }
+
+    pub fn draw(&self, frame: &mut [u8], width: u32, height: u32) {
+        match self.phase {
+            IntroPhase::Splash => self.draw_splash(frame, width, height),
+            IntroPhase::Hint => draw_hint(frame, width, height),
+            IntroPhase::Done => {}
+        }
+    }
+
+    fn draw_splash(&self, frame: &mut [u8], width: u32, height: u32) {
+        let fade = ((SPLASH_DURATION - self.elapsed) / SPLASH_FADE_OUT).clamp(0.0, 1.0);
+        let backdrop = [
+            BACKDROP[0],
+            BACKDROP[1],
+            BACKDROP[2],
+            (BACKDROP[3] as f32 * fade) as u8,
+        ];
+        draw_rectangle_safe(frame, 0, 0, width, height, backdrop, width, height);
+
+        let advances: Vec<f32> = TITLE
+            .chars()
+            .map(|c| glyph_advance(c, TITLE_SIZE))
+            .collect();
+        let baseline = height as f32 / 2.0 + TITLE_SIZE / 3.0;
+        let mut pen_x = (width as f32 - advances.iter().sum::<f32>()) / 2.0;
+        let mut centers = Vec::with_capacity(advances.len());
+        for (i, c) in TITLE.chars().enumerate() {
+            let appear = (self.elapsed - i as f32 * LETTER_STAGGER) / LETTER_FADE;
+            let alpha = appear.clamp(0.0, 1.0) * fade;
+            draw_glyph_scaled(frame, c, pen_x, baseline, TITLE_STYLE, alpha, width);
+            centers.push((pen_x + advances[i] / 2.0, baseline - TITLE_SIZE * 0.35));
+            pen_x += advances[i];
+        }
+
+        for spark in &self.sparks {
+            let (cx, cy) = centers[spark.letter];
+            let x = (cx + spark.offset.0) as i32;
+            let y = (cy + spark.offset.1) as i32;
+            let intensity = spark.life / spark.max_life * fade;
+            for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                blend_pixel_safe(frame, x + dx, y + dy, width, height, SPARK_COLOR, intensity);
+            }
+        }
+    }
+}
+
+impl Default for Intro {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn draw_hint(frame: &mut [u8], width: u32, height: u32) {
+    let text_width = HINT_LINES
+        .iter()
+        .map(|line| estimate_text_width(line))
+        .fold(0.0, f32::max);
+    let box_width = text_width + 2.0 * HINT_PADDING;
+    let box_height = HINT_LINES.len() as f32 * HINT_LINE_HEIGHT + 2.0 * HINT_PADDING;
+    let left = (width as f32 - box_width) / 2.0;
+    let top = (height as f32 - box_height) / 2.0;
+    draw_rectangle_safe(
+        frame,
+        left as i32,
+        top as i32,
+        box_width as u32,
+        box_height as u32,
+        hud_backing(HINT_BACKGROUND),
+        width,
+        height,
+    );
+    for (i, line) in HINT_LINES.iter().enumerate() {
+        draw_text_styled(
+            frame,
+            line,
+            left + HINT_PADDING,
+            top + HINT_PADDING + (i + 1) as f32 * HINT_LINE_HEIGHT - 6.0,
+            &TextStyle::outlined(HINT_TEXT),
+            width,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_first_run_flag_decides_the_intro() {
+        assert!(should_show_intro(false, false));
+        assert!(!should_show_intro(true, false));
+        assert!(should_show_intro(true, true));
+    }
+
+    #[test]
+    fn test_intro_runs_its_phases_in_order() {
+        let mut intro = Intro::new();
+        intro.update(1.0 / 60.0);
+        assert_eq!(intro.sparks.len(), SPARKS_PER_LETTER);
+        for _ in 0..(SPLASH_DURATION * 60.0) as usize {
+            intro.update(1.0 / 60.0);
+        }
+        assert_eq!(intro.phase(), IntroPhase::Hint);
+        assert!(!intro.take_completion());
+        intro.update(HINT_DURATION);
+        assert_eq!(intro.phase(), IntroPhase::Done);
+        assert!(intro.take_completion());
+        assert!(!intro.take_completion());
+    }
+
+    #[test]
+    fn test_skip_goes_straight_to_the_hint_then_the_scene() {
+        let mut intro = Intro::new();
+        intro.update(0.5);
+        intro.skip();
+        assert_eq!(intro.phase(), IntroPhase::Hint);
+        assert!(intro.sparks.is_empty());
+        intro.skip();
+        assert!(!intro.is_active());
+        assert!(intro.take_completion());
+
+        // An intro that never ran has nothing to skip or report
+        let mut finished = Intro::finished();
+        finished.skip();
+        assert!(!finished.is_active());
+        assert!(!finished.take_completion());
+    }
+}
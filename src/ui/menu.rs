@@ -0,0 +1,408 @@
+//! Pop-up scene menu opened with a long press. Lists every scene with the
+//! selected one's description below and a footer naming the menu's keys;
+//! clicking a scene or pressing the confirm key switches to it, clicking
+//! anywhere else closes the menu.
+
+use crate::core::accessibility::hud_backing;
+use crate::core::input_record;
+use crate::core::scenes::{self, SceneInfo};
+use crate::core::types::Position;
+use crate::graphics::pixel_utils::draw_rectangle_safe;
+use crate::text::text_rendering::{
+    draw_text_sized, draw_text_styled, text_width_sized, wrap_text, GlyphStyle, TextStyle,
+};
+use std::fmt;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use winit::keyboard::KeyCode;
+
+const ITEM_HEIGHT: f32 = 28.0;
+const MENU_WIDTH: f32 = 260.0;
+const PADDING: f32 = 8.0;
+/// Distance from the item's top to the text baseline
+const BASELINE: f32 = 20.0;
+/// Font size of the description and the footer
+const SMALL_SIZE: f32 = 14.0;
+const SMALL_LINE_HEIGHT: f32 = 17.0;
+const SMALL_BASELINE: f32 = 13.0;
+const BACKGROUND: [u8; 4] = [10, 10, 20, 220];
+const HOVER: [u8; 4] = [70, 90, 160, 220];
+const TEXT_COLOR: [u8; 4] = [235, 235, 245, 255];
+const DESCRIPTION_COLOR: [u8; 4] = [190, 195, 215, 255];
+const FOOTER_COLOR: [u8; 4] = [140, 150, 185, 255];
+
+/// Keys that drive the open menu
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MenuKeys {
+    pub previous: KeyCode,
+    pub next: KeyCode,
+    pub confirm: KeyCode,
+    pub close: KeyCode,
+}
+
+impl MenuKeys {
+    pub const DEFAULT: Self = Self {
+        previous: KeyCode::ArrowUp,
+        next: KeyCode::ArrowDown,
+        confirm: KeyCode::Enter,
+        close: KeyCode::Escape,
+    };
+
+    /// The footer line naming these keys
+    pub fn footer(&self) -> String {
+        format!(
+            "{}/{} select · {} confirm · {} close",
+            key_label(self.previous),
+            key_label(self.next),
+            key_label(self.confirm),
+            key_label(self.close)
+        )
+    }
+
+    /// The first of these keys the app doesn't track, if any
+    fn untracked(&self) -> Option<KeyCode> {
+        [self.previous, self.next, self.confirm, self.close]
+            .into_iter()
+            .find(|&key| !input_record::is_tracked(key))
+    }
+}
+
+/// A menu key the app never sees pressed, so the menu couldn't be driven with it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UntrackedKey(pub KeyCode);
+
+impl fmt::Display for UntrackedKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} isn't a key the app tracks", key_label(self.0))
+    }
+}
+
+impl std::error::Error for UntrackedKey {}
+
+/// Short name of a key as the help texts write it: "Up", "W", "5", "Esc"
+pub fn key_label(key: KeyCode) -> String {
+    if key == KeyCode::Escape {
+        return "Esc".to_string();
+    }
+    let name = format!("{:?}", key);
+    ["Arrow", "Key", "Digit"]
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .map(str::to_string)
+        .unwrap_or(name)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct MenuState {
+    /// Top-left corner, kept inside the frame
+    origin: Position,
+    size: (f32, f32),
+    /// Set by the keys or by hovering over an item
+    selected: Option<usize>,
+    /// Where the cursor was last seen, so a still mouse doesn't undo the keys
+    cursor: Option<Position>,
+}
+
+static MENU: Mutex<Option<MenuState>> = Mutex::new(None);
+static MENU_KEYS: Mutex<MenuKeys> = Mutex::new(MenuKeys::DEFAULT);
+
+fn menu() -> MutexGuard<'static, Option<MenuState>> {
+    MENU.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+pub fn menu_keys() -> MenuKeys {
+    *MENU_KEYS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Rebinds the menu's keys, unless one of them is a key the app doesn't track
+pub fn set_menu_keys(keys: MenuKeys) -> Result<(), UntrackedKey> {
+    if let Some(key) = keys.untracked() {
+        return Err(UntrackedKey(key));
+    }
+    *MENU_KEYS.lock().unwrap_or_else(PoisonError::into_inner) = keys;
+    Ok(())
+}
+
+/// Size of a panel listing `items` in a frame `frame_width` wide. The
+/// description area fits the longest of `descriptions`, so the panel keeps
+/// its size as the selection moves.
+fn panel_size(
+    items: usize,
+    descriptions: &[&str],
+    footer: &str,
+    frame_width: u32,
+    measure: impl Fn(&str) -> f32,
+) -> (f32, f32) {
+    let width = MENU_WIDTH.min(frame_width as f32);
+    let text_width = width - 2.0 * PADDING;
+    let description_lines = descriptions
+        .iter()
+        .map(|text| wrap_text(text, text_width, &measure).len())
+        .max()
+        .unwrap_or(0);
+    let footer_lines = wrap_text(footer, text_width, &measure).len();
+    let text_lines = (description_lines + footer_lines) as f32;
+    // Padding above the list, below it, between the description and the
+    // footer, and at the bottom
+    let height = items as f32 * ITEM_HEIGHT + text_lines * SMALL_LINE_HEIGHT + 4.0 * PADDING;
+    (width, height)
+}
+
+fn small_width(text: &str) -> f32 {
+    text_width_sized(text, SMALL_SIZE)
+}
+
+fn menu_size(frame_width: u32) -> (f32, f32) {
+    let descriptions: Vec<&str> = scenes::SCENES.iter().map(|s| s.description).collect();
+    panel_size(
+        scenes::SCENES.len(),
+        &descriptions,
+        &menu_keys().footer(),
+        frame_width,
+        small_width,
+    )
+}
+
+/// Places a menu of `size` with its corner at `pos`, shifted back inside a
+/// `width` x `height` frame
+fn place(pos: Position, size: (f32, f32), width: u32, height: u32) -> Position {
+    let (menu_width, menu_height) = size;
+    Position::new(
+        pos.x.min(width as f32 - menu_width).max(0.0),
+        pos.y.min(height as f32 - menu_height).max(0.0),
+    )
+}
+
+/// Index of the scene under `pos` for a menu at `origin`
+fn item_at(origin: Position, menu_width: f32, pos: Position) -> Option<usize> {
+    let local = pos - origin;
+    if local.x < 0.0 || local.x >= menu_width || local.y < PADDING {
+        return None;
+    }
+    let index = ((local.y - PADDING) / ITEM_HEIGHT) as usize;
+    (index < scenes::SCENES.len()).then_some(index)
+}
+
+/// Opens the menu with its corner at `pos` in a `width` x `height` frame,
+/// with the scene on screen selected
+pub fn open_menu_at(pos: Position, width: u32, height: u32) {
+    let size = menu_size(width);
+    let active = scenes::active_scene().id;
+    *menu() = Some(MenuState {
+        origin: place(pos, size, width, height),
+        size,
+        selected: scenes::SCENES.iter().position(|scene| scene.id == active),
+        cursor: None,
+    });
+}
+
+pub fn close_menu() {
+    *menu() = None;
+}
+
+pub fn is_menu_open() -> bool {
+    menu().is_some()
+}
+
+/// Selects the item the cursor moves onto
+pub fn hover(cursor: Option<Position>) {
+    if let Some(menu) = menu().as_mut() {
+        if cursor != menu.cursor {
+            menu.cursor = cursor;
+            if let Some(index) = cursor.and_then(|pos| item_at(menu.origin, menu.size.0, pos)) {
+                menu.selected = Some(index);
+            }
+        }
+    }
+}
+
+/// Moves the selection, wrapping around the ends
+pub fn move_selection(steps: i32) {
+    if let Some(menu) = menu().as_mut() {
+        let count = scenes::SCENES.len() as i32;
+        let from = menu
+            .selected
+            .map_or(if steps > 0 { -1 } else { 0 }, |i| i as i32);
+        menu.selected = Some((from + steps).rem_euclid(count) as usize);
+    }
+}
+
+/// Switches to the selected scene and closes the menu. Returns the scene.
+pub fn choose_selected() -> Option<&'static SceneInfo> {
+    let menu = menu().take()?;
+    let scene = &scenes::SCENES[menu.selected?];
+    scene.enter();
+    Some(scene)
+}
+
+/// Handles a click while the menu is open: picks the scene under `pos` or
+/// closes the menu. Returns the chosen scene, if any.
+pub fn click(pos: Position) -> Option<&'static SceneInfo> {
+    let menu = menu().take()?;
+    let scene = &scenes::SCENES[item_at(menu.origin, menu.size.0, pos)?];
+    scene.enter();
+    Some(scene)
+}
+
+pub fn draw_menu(frame: &mut [u8], _width: u32, height: u32, x_offset: usize, buffer_width: u32) {
+    let Some(menu) = *menu() else {
+        return;
+    };
+    let (menu_width, menu_height) = menu.size;
+    let origin = menu.origin;
+    let left = origin.x + x_offset as f32;
+    draw_rectangle_safe(
+        frame,
+        left as i32,
+        origin.y as i32,
+        menu_width as u32,
+        menu_height as u32,
+        hud_backing(BACKGROUND),
+        buffer_width,
+        height,
+    );
+    for (i, scene) in scenes::SCENES.iter().enumerate() {
+        let top = origin.y + PADDING + i as f32 * ITEM_HEIGHT;
+        if menu.selected == Some(i) {
+            draw_rectangle_safe(
+                frame,
+                left as i32,
+                top as i32,
+                menu_width as u32,
+                ITEM_HEIGHT as u32,
+                HOVER,
+                buffer_width,
+                height,
+            );
+        }
+        draw_text_styled(
+            frame,
+            scene.name,
+            left + PADDING,
+            top + BASELINE,
+            &TextStyle::plain(TEXT_COLOR),
+            buffer_width,
+        );
+    }
+
+    let text_width = menu_width - 2.0 * PADDING;
+    let mut top = origin.y + 2.0 * PADDING + scenes::SCENES.len() as f32 * ITEM_HEIGHT;
+    let mut draw_lines = |lines: Vec<String>, color: [u8; 4], top: &mut f32| {
+        for line in lines {
+            let style = GlyphStyle {
+                size: SMALL_SIZE,
+                stroke: 0,
+                color,
+            };
+            draw_text_sized(
+                frame,
+                &line,
+                left + PADDING,
+                *top + SMALL_BASELINE,
+                style,
+                buffer_width,
+            );
+            *top += SMALL_LINE_HEIGHT;
+        }
+    };
+    if let Some(scene) = menu.selected.map(|i| &scenes::SCENES[i]) {
+        let lines = wrap_text(scene.description, text_width, small_width);
+        draw_lines(lines, DESCRIPTION_COLOR, &mut top);
+    }
+    // The footer sits at the bottom whatever the selected description's length
+    let footer = wrap_text(&menu_keys().footer(), text_width, small_width);
+    let mut footer_top = origin.y + menu_height - PADDING - footer.len() as f32 * SMALL_LINE_HEIGHT;
+    draw_lines(footer, FOOTER_COLOR, &mut footer_top);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every character 7 px wide, close to the small font
+    fn chars(text: &str) -> f32 {
+        text.chars().count() as f32 * 7.0
+    }
+
+    #[test]
+    fn test_menu_stays_inside_frame_and_maps_items() {
+        let size = menu_size(800);
+        let (menu_width, menu_height) = size;
+        let origin = place(Position::new(790.0, 390.0), size, 800, 400);
+        assert_eq!(
+            origin,
+            Position::new(800.0 - menu_width, 400.0 - menu_height)
+        );
+
+        let first = origin + Position::new(10.0, PADDING + 1.0);
+        assert_eq!(item_at(origin, menu_width, first), Some(0));
+        let last = first + Position::new(0.0, ITEM_HEIGHT * (scenes::SCENES.len() - 1) as f32);
+        assert_eq!(
+            item_at(origin, menu_width, last),
+            Some(scenes::SCENES.len() - 1)
+        );
+        assert_eq!(
+            item_at(origin, menu_width, origin - Position::new(1.0, 0.0)),
+            None
+        );
+        // The description and footer below the list are not items
+        assert_eq!(
+            item_at(
+                origin,
+                menu_width,
+                origin + Position::new(10.0, menu_height - 1.0)
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_panel_grows_to_fit_wrapped_descriptions() {
+        let footer = MenuKeys::DEFAULT.footer();
+        // 244 px of text fit 34 characters a line
+        let short = panel_size(3, &["Bouncing balls"], &footer, 800, chars);
+        let footer_lines = wrap_text(&footer, MENU_WIDTH - 2.0 * PADDING, chars).len();
+        assert_eq!(footer_lines, 2);
+        let list = 3.0 * ITEM_HEIGHT + 4.0 * PADDING;
+        assert_eq!(short, (MENU_WIDTH, list + 3.0 * SMALL_LINE_HEIGHT));
+
+        // The longest description decides, however short the others are
+        let long = "Two bouncing balls casting rays and shadows, framed by sorting visualizers";
+        let (_, tall) = panel_size(3, &["Balls", long, "Rain"], &footer, 800, chars);
+        assert_eq!(tall, list + (3.0 + 2.0) * SMALL_LINE_HEIGHT);
+
+        // A narrow frame narrows the panel, which wraps into more lines
+        let (width, narrow) = panel_size(3, &["Balls", long], &footer, 120, chars);
+        assert_eq!(width, 120.0);
+        let text_width = 120.0 - 2.0 * PADDING;
+        let lines =
+            wrap_text(long, text_width, chars).len() + wrap_text(&footer, text_width, chars).len();
+        assert!(lines > 5);
+        assert_eq!(narrow, list + lines as f32 * SMALL_LINE_HEIGHT);
+    }
+
+    #[test]
+    fn test_footer_follows_the_key_bindings() {
+        assert_eq!(
+            MenuKeys::DEFAULT.footer(),
+            "Up/Down select · Enter confirm · Esc close"
+        );
+        let remapped = MenuKeys {
+            confirm: KeyCode::Space,
+            previous: KeyCode::KeyW,
+            next: KeyCode::KeyX,
+            ..MenuKeys::DEFAULT
+        };
+        assert_eq!(remapped.footer(), "W/X select · Space confirm · Esc close");
+        assert_eq!(remapped.untracked(), None);
+        assert_eq!(key_label(KeyCode::Digit5), "5");
+        assert_eq!(key_label(KeyCode::F3), "F3");
+
+        // S is never recorded, so the menu can't be bound to it
+        let untracked = MenuKeys {
+            next: KeyCode::KeyS,
+            ..remapped
+        };
+        assert_eq!(set_menu_keys(untracked), Err(UntrackedKey(KeyCode::KeyS)));
+        assert_eq!(menu_keys(), MenuKeys::DEFAULT);
+    }
+}
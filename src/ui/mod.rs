@@ -0,0 +1,11 @@
+pub mod calibration;
+pub mod device_picker;
+pub mod gestures;
+pub mod help_overlay;
+pub mod hud_layout;
+pub mod intro;
+pub mod menu;
+pub mod status_icons;
+pub mod timeline;
+pub mod toast;
+pub mod utility_window;
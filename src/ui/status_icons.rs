@@ -0,0 +1,360 @@
+//! A row of small audio status icons in the top-right corner: a speaker with
+//! volume arcs while sound is playing, crossed out in red when muted or when
+//! audio is off, and a glyph for the fallback sound that is on. Each icon only
+//! appears while its part of the audio is active or changed from the default,
+//! and clicking one toggles it. The row's spot comes from the HUD layout.
+//! Hidden in clean mode.
+
+use crate::audio::audio_playback::{self, FallbackSound};
+use crate::core::accessibility::hud_backing;
+use crate::core::orchestrator;
+use crate::core::types::Position;
+use crate::graphics::pixel_utils::{
+    draw_circle, draw_rectangle_safe, draw_triangle_filled, set_pixel_safe,
+};
+use crate::ui::hud_layout::{self, HudAnchor, HudElement, HudRect, HudRequest};
+use crate::ui::toast;
+use log::warn;
+
+/// Side of each icon's square, in buffer pixels
+pub const ICON_SIZE: f32 = 12.0;
+const GAP: f32 = 6.0;
+/// Backdrop drawn around each icon
+const BACKDROP_PAD: f32 = 2.0;
+/// Small and clickable, so it keeps its corner
+const ICONS_PRIORITY: u8 = 3;
+/// Clicks this close outside an icon still hit it
+const HIT_PADDING: f32 = 3.0;
+pub const MAX_ARCS: u8 = 3;
+const ICON_COLOR: [u8; 4] = [230, 235, 245, 255];
+const SLASH_COLOR: [u8; 4] = [235, 50, 50, 255];
+const BACKDROP: [u8; 4] = [0, 0, 0, 190];
+
+/// What the audio module reports, as far as the icons care
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AudioStatus {
+    /// False under `--no-audio`
+    pub enabled: bool,
+    pub playing: bool,
+    pub muted: bool,
+    /// Output volume before muting, lowered while ducked
+    pub volume: f32,
+    pub fallback: FallbackSound,
+}
+
+impl AudioStatus {
+    pub fn current() -> Self {
+        Self {
+            enabled: crate::audio::audio_enabled(),
+            playing: audio_playback::is_playing(),
+            muted: audio_playback::is_muted(),
+            volume: audio_playback::output_volume(),
+            fallback: audio_playback::fallback_sound(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusIcon {
+    /// Volume arcs, up to `MAX_ARCS`, and a red slash when nothing can be heard
+    Speaker { arcs: u8, slashed: bool },
+    /// The fallback sound that is on
+    Sound(FallbackSound),
+}
+
+/// The icons to show for `status`, rightmost first
+pub fn icons(status: &AudioStatus) -> Vec<StatusIcon> {
+    let mut icons = Vec::new();
+    let silenced = status.muted || !status.enabled;
+    if status.playing || silenced {
+        let arcs = if silenced {
+            0
+        } else {
+            (status.volume.clamp(0.0, 1.0) * MAX_ARCS as f32).ceil() as u8
+        };
+        icons.push(StatusIcon::Speaker {
+            arcs,
+            slashed: silenced,
+        });
+    }
+    if status.fallback != FallbackSound::Off {
+        icons.push(StatusIcon::Sound(status.fallback));
+    }
+    icons
+}
+
+/// Room the row asks for in the top-right corner; None when it is empty
+pub fn hud_request(icons: &[StatusIcon]) -> Option<HudRequest> {
+    let count = icons.len() as f32;
+    (!icons.is_empty()).then_some(HudRequest {
+        element: HudElement::StatusIcons,
+        anchor: HudAnchor::TopRight,
+        priority: ICONS_PRIORITY,
+        size: (
+            count * ICON_SIZE + (count - 1.0) * GAP + 2.0 * BACKDROP_PAD,
+            ICON_SIZE + 2.0 * BACKDROP_PAD,
+        ),
+    })
+}
+
+/// Top-left corner of each icon in the row's `region`, rightmost first
+pub fn layout(icons: &[StatusIcon], region: HudRect) -> Vec<(StatusIcon, Position)> {
+    icons
+        .iter()
+        .enumerate()
+        .map(|(i, &icon)| {
+            let x = region.right() - BACKDROP_PAD - ICON_SIZE - i as f32 * (ICON_SIZE + GAP);
+            (icon, Position::new(x, region.y + BACKDROP_PAD))
+        })
+        .collect()
+}
+
+/// The icon whose padded square holds `pos`
+pub fn hit(icons: &[StatusIcon], region: HudRect, pos: Position) -> Option<StatusIcon> {
+    layout(icons, region)
+        .into_iter()
+        .find_map(|(icon, corner)| {
+            let lo = corner - Position::splat(HIT_PADDING);
+            let hi = corner + Position::splat(ICON_SIZE + HIT_PADDING);
+            let inside = (lo.x..hi.x).contains(&pos.x) && (lo.y..hi.y).contains(&pos.y);
+            inside.then_some(icon)
+        })
+}
+
+/// The icon under `pos` right now, if the row is showing where the last
+/// frame drew it
+pub fn icon_at(pos: Position) -> Option<StatusIcon> {
+    if orchestrator::is_clean_mode() {
+        return None;
+    }
+    let region = hud_layout::current_region(HudElement::StatusIcons)?;
+    hit(&icons(&AudioStatus::current()), region, pos)
+}
+
+/// Toggles what the icon under `pos` stands for. Returns false if there is no
+/// icon there.
+pub fn click(pos: Position) -> bool {
+    let Some(icon) = icon_at(pos) else {
+        return false;
+    };
+    match icon {
+        StatusIcon::Speaker { .. } if !crate::audio::audio_enabled() => {
+            toast::show_toast(vec![crate::audio::AUDIO_OFF_NOTICE.to_string()]);
+        }
+        StatusIcon::Speaker { .. } => {
+            audio_playback::toggle_mute();
+        }
+        StatusIcon::Sound(_) => {
+            if let Err(e) = audio_playback::cycle_fallback_sound() {
+                warn!("Fallback sound unavailable: {}", e);
+            }
+        }
+    }
+    true
+}
+
+/// Plots a one-pixel line between two points
+fn draw_thin_line(frame: &mut [u8], from: Position, to: Position, color: [u8; 4], w: u32, h: u32) {
+    let steps = ((to - from).abs().max_element() * 2.0).ceil().max(1.0) as usize;
+    for i in 0..=steps {
+        let p = from.lerp(to, i as f32 / steps as f32);
+        set_pixel_safe(frame, p.x.round() as i32, p.y.round() as i32, w, h, color);
+    }
+}
+
+fn draw_speaker(frame: &mut [u8], x: i32, y: i32, arcs: u8, slashed: bool, w: u32, h: u32) {
+    draw_rectangle_safe(frame, x + 1, y + 4, 3, 4, ICON_COLOR, w, h);
+    draw_triangle_filled(
+        frame,
+        x + 3,
+        y + 4,
+        x + 6,
+        y + 1,
+        x + 6,
+        y + 10,
+        w,
+        h,
+        ICON_COLOR,
+    );
+    draw_triangle_filled(
+        frame,
+        x + 3,
+        y + 4,
+        x + 3,
+        y + 7,
+        x + 6,
+        y + 10,
+        w,
+        h,
+        ICON_COLOR,
+    );
+    let center = Position::new(x as f32 + 4.0, y as f32 + 5.5);
+    for arc in 0..arcs.min(MAX_ARCS) {
+        let radius = 4.0 + arc as f32 * 1.75;
+        for step in -14..=14 {
+            let angle = step as f32 * 0.05;
+            let p = center + Position::new(angle.cos(), angle.sin()) * radius;
+            set_pixel_safe(
+                frame,
+                p.x.round() as i32,
+                p.y.round() as i32,
+                w,
+                h,
+                ICON_COLOR,
+            );
+        }
+    }
+    if slashed {
+        for dx in 0..2 {
+            let from = Position::new((x + dx) as f32, y as f32);
+            let to = Position::new((x + dx) as f32 + 10.0, y as f32 + 11.0);
+            draw_thin_line(frame, from, to, SLASH_COLOR, w, h);
+        }
+    }
+}
+
+fn draw_sound(frame: &mut [u8], x: i32, y: i32, sound: FallbackSound, w: u32, h: u32) {
+    let (fx, fy) = (x as f32, y as f32);
+    match sound {
+        // A jagged trace for noise
+        FallbackSound::WhiteNoise => {
+            let heights = [6.0, 2.0, 9.0, 4.0, 11.0, 1.0, 8.0, 5.0, 10.0];
+            for (i, pair) in heights.windows(2).enumerate() {
+                let from = Position::new(fx + i as f32 * 1.4, fy + pair[0]);
+                let to = Position::new(fx + (i + 1) as f32 * 1.4, fy + pair[1]);
+                draw_thin_line(frame, from, to, ICON_COLOR, w, h);
+            }
+        }
+        // A quaver for the music
+        FallbackSound::Generative => {
+            draw_circle(frame, x + 4, y + 9, 2, ICON_COLOR, w);
+            let stem = Position::new(fx + 6.0, fy + 9.0);
+            let top = Position::new(fx + 6.0, fy + 1.0);
+            draw_thin_line(frame, stem, top, ICON_COLOR, w, h);
+            draw_thin_line(
+                frame,
+                top,
+                Position::new(fx + 10.0, fy + 5.0),
+                ICON_COLOR,
+                w,
+                h,
+            );
+        }
+        FallbackSound::Off => {}
+    }
+}
+
+/// Draws the `icons` row in its `region` of the HUD layout
+pub fn draw_status_icons(
+    frame: &mut [u8],
+    icons: &[StatusIcon],
+    region: HudRect,
+    height: u32,
+    x_offset: usize,
+    buffer_width: u32,
+) {
+    for (icon, corner) in layout(icons, region) {
+        let (x, y) = (corner.x as i32 + x_offset as i32, corner.y as i32);
+        let pad = BACKDROP_PAD as i32;
+        let side = ICON_SIZE as u32 + 2 * pad as u32;
+        draw_rectangle_safe(
+            frame,
+            x - pad,
+            y - pad,
+            side,
+            side,
+            hud_backing(BACKDROP),
+            buffer_width,
+            height,
+        );
+        match icon {
+            StatusIcon::Speaker { arcs, slashed } => {
+                draw_speaker(frame, x, y, arcs, slashed, buffer_width, height)
+            }
+            StatusIcon::Sound(sound) => draw_sound(frame, x, y, sound, buffer_width, height),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLAYING: AudioStatus = AudioStatus {
+        enabled: true,
+        playing: true,
+        muted: false,
+        volume: 1.0,
+        fallback: FallbackSound::Off,
+    };
+
+    #[test]
+    fn test_icons_follow_the_audio_state() {
+        let speaker = |arcs, slashed| StatusIcon::Speaker { arcs, slashed };
+        assert_eq!(icons(&PLAYING), [speaker(3, false)]);
+        // Ducked while the window is in the background
+        let ducked = AudioStatus {
+            volume: 0.3,
+            ..PLAYING
+        };
+        assert_eq!(icons(&ducked), [speaker(1, false)]);
+        let muted = AudioStatus {
+            muted: true,
+            ..PLAYING
+        };
+        assert_eq!(icons(&muted), [speaker(0, true)]);
+        // --no-audio is crossed out even though nothing ever played
+        let disabled = AudioStatus {
+            enabled: false,
+            playing: false,
+            ..PLAYING
+        };
+        assert_eq!(icons(&disabled), [speaker(0, true)]);
+
+        // Nothing to report before playback starts
+        let idle = AudioStatus {
+            playing: false,
+            ..PLAYING
+        };
+        assert!(icons(&idle).is_empty());
+        let music = AudioStatus {
+            fallback: FallbackSound::Generative,
+            ..PLAYING
+        };
+        assert_eq!(
+            icons(&music),
+            [
+                speaker(3, false),
+                StatusIcon::Sound(FallbackSound::Generative)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_clicks_hit_the_padded_icon_squares() {
+        let row = [
+            StatusIcon::Speaker {
+                arcs: 3,
+                slashed: false,
+            },
+            StatusIcon::Sound(FallbackSound::WhiteNoise),
+        ];
+        let region = hud_layout::solve(&[hud_request(&row).unwrap()], 800, 600)
+            .region(HudElement::StatusIcons)
+            .unwrap();
+        let placed = layout(&row, region);
+        assert_eq!(placed[0].1, Position::new(778.0, 10.0));
+        assert_eq!(placed[1].1, Position::new(760.0, 10.0));
+
+        let at = |x, y| hit(&row, region, Position::new(x, y));
+        assert_eq!(at(784.0, 16.0), Some(row[0]));
+        assert_eq!(at(775.0, 7.0), Some(row[0]));
+        assert_eq!(at(774.9, 7.0), Some(row[1]));
+        assert_eq!(at(757.0, 24.9), Some(row[1]));
+        assert_eq!(at(756.9, 16.0), None);
+        assert_eq!(at(784.0, 25.0), None);
+        assert_eq!(at(793.0, 16.0), None);
+        assert_eq!(hit(&[], region, Position::new(784.0, 16.0)), None);
+        assert_eq!(hud_request(&[]), None);
+    }
+}
@@ -0,0 +1,439 @@
+//! Session timeline opened with F10: a strip along the bottom of the frame
+//! with one colored segment per stretch of time spent in a scene, and ticks
+//! for notable moments. Clicking a segment, or stepping through them with the
+//! arrow keys, switches back to its scene.
+
+use crate::core::accessibility::hud_backing;
+use crate::core::events::Event;
+use crate::core::scenes::{self, SceneInfo};
+use crate::core::types::{hsv_to_rgb, Position};
+use crate::graphics::pixel_utils::draw_rectangle_safe;
+use crate::text::text_rendering::{draw_text_with_background, estimate_text_width};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Segments kept before the shortest ones are folded into their neighbors
+pub const MAX_SEGMENTS: usize = 256;
+/// Event ticks kept; the oldest are dropped first
+pub const MAX_MARKS: usize = 256;
+const STRIP_MARGIN: f32 = 40.0;
+const STRIP_HEIGHT: f32 = 24.0;
+const MARK_HEIGHT: f32 = 6.0;
+const BACKGROUND: [u8; 4] = [10, 10, 20, 200];
+const HIGHLIGHT: [u8; 4] = [255, 255, 255, 90];
+const MARK_COLOR: [u8; 4] = [255, 230, 120, 255];
+const TEXT_COLOR: [u8; 4] = [235, 235, 245, 255];
+const TEXT_BACKGROUND: [u8; 4] = [0, 0, 0, 200];
+
+/// From `start` until the next segment starts, `scene` was on screen
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Segment {
+    pub start: f32,
+    pub scene: &'static str,
+}
+
+/// Something worth spotting on the strip, like a ball hitting a corner
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mark {
+    pub at: f32,
+    pub label: &'static str,
+}
+
+/// Where the strip sits in the frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Strip {
+    pub left: f32,
+    pub top: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Strip {
+    /// The strip for a `width` x `height` frame, along its bottom edge
+    pub fn for_frame(width: u32, height: u32) -> Self {
+        Self {
+            left: STRIP_MARGIN,
+            top: height as f32 - STRIP_MARGIN - STRIP_HEIGHT,
+            width: (width as f32 - 2.0 * STRIP_MARGIN).max(1.0),
+            height: STRIP_HEIGHT,
+        }
+    }
+
+    fn contains(&self, pos: Position) -> bool {
+        (self.left..self.left + self.width).contains(&pos.x)
+            && (self.top..self.top + self.height).contains(&pos.y)
+    }
+}
+
+/// Append-only log of the scenes shown this session, in session seconds
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timeline {
+    segments: Vec<Segment>,
+    marks: Vec<Mark>,
+    /// Latest time recorded; the current segment runs until here
+    now: f32,
+}
+
+impl Timeline {
+    pub const fn new() -> Self {
+        Self {
+            segments: Vec::new(),
+            marks: Vec::new(),
+            now: 0.0,
+        }
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    pub fn marks(&self) -> &[Mark] {
+        &self.marks
+    }
+
+    /// Notes that `scene` is on screen at `now`, opening a segment when it changed
+    pub fn record_scene(&mut self, scene: &'static str, now: f32) {
+        self.now = self.now.max(now);
+        if self.segments.last().map(|segment| segment.scene) != Some(scene) {
+            self.segments.push(Segment {
+                start: self.now,
+                scene,
+            });
+            self.compact();
+        }
+    }
+
+    pub fn record_event(&mut self, label: &'static str, now: f32) {
+        if self.marks.len() >= MAX_MARKS {
+            self.marks.remove(0);
+        }
+        self.marks.push(Mark {
+            at: now.max(self.now),
+            label,
+        });
+    }
+
+    /// Seconds from the first segment's start to the latest time
+    pub fn span(&self) -> f32 {
+        self.segments
+            .first()
+            .map_or(0.0, |first| self.now - first.start)
+    }
+
+    /// When segment `index` ends: where the next one starts, or now
+    fn end_of(&self, index: usize) -> f32 {
+        self.segments
+            .get(index + 1)
+            .map_or(self.now, |next| next.start)
+    }
+
+    /// Keeps at most `MAX_SEGMENTS` by folding the shortest finished segment
+    /// into the one before it, then merging neighbors that show the same scene.
+    /// The session's overall span and the current segment are never lost.
+    fn compact(&mut self) {
+        while self.segments.len() > MAX_SEGMENTS {
+            let last = self.segments.len() - 1;
+            let shortest = (0..last)
+                .min_by(|&a, &b| {
+                    let length = |i| self.end_of(i) - self.segments[i].start;
+                    length(a).total_cmp(&length(b))
+                })
+                .unwrap_or(0);
+            if shortest == 0 {
+                // Nothing before it to grow, so the next segment starts earlier instead
+                let start = self.segments[0].start;
+                self.segments.remove(0);
+                self.segments[0].start = start;
+            } else {
+                self.segments.remove(shortest);
+            }
+            self.segments
+                .dedup_by(|later, earlier| later.scene == earlier.scene);
+        }
+    }
+
+    /// Horizontal extent of segment `index` on `strip`, as (left, width)
+    pub fn segment_span(&self, index: usize, strip: &Strip) -> (f32, f32) {
+        let (Some(first), Some(segment)) = (self.segments.first(), self.segments.get(index)) else {
+            return (strip.left, 0.0);
+        };
+        let span = self.span();
+        if span <= 0.0 {
+            return (strip.left, strip.width);
+        }
+        let x = |t: f32| strip.left + (t - first.start) / span * strip.width;
+        let left = x(segment.start);
+        (left, x(self.end_of(index)) - left)
+    }
+
+    /// Index of the segment under `pos` on `strip`
+    pub fn segment_at(&self, strip: &Strip, pos: Position) -> Option<usize> {
+        if !strip.contains(pos) {
+            return None;
+        }
+        let first = self.segments.first()?;
+        let t = first.start + (pos.x - strip.left) / strip.width * self.span();
+        self.segments.iter().rposition(|segment| segment.start <= t)
+    }
+}
+
+impl Default for Timeline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct TimelineView {
+    /// Segment picked last by click or arrow keys
+    selected: Option<usize>,
+    hovered: Option<usize>,
+}
+
+static TIMELINE: Mutex<Timeline> = Mutex::new(Timeline::new());
+static VIEW: Mutex<Option<TimelineView>> = Mutex::new(None);
+
+fn timeline() -> MutexGuard<'static, Timeline> {
+    TIMELINE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn view() -> MutexGuard<'static, Option<TimelineView>> {
+    VIEW.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Called every frame with the scene on screen and the session time
+pub fn track(scene: &'static SceneInfo, now: f32) {
+    timeline().record_scene(scene.id, now);
+}
+
+/// Marks corner hits on the strip
+pub fn on_event(event: &Event, now: f32) {
+    if let Event::CornerHit { .. } = event {
+        timeline().record_event("Corner hit", now);
+    }
+}
+
+pub fn is_timeline_open() -> bool {
+    view().is_some()
+}
+
+/// Opens or closes the strip and returns whether it is open
+pub fn toggle_timeline() -> bool {
+    let mut view = view();
+    *view = match *view {
+        Some(_) => None,
+        None => Some(TimelineView {
+            selected: None,
+            hovered: None,
+        }),
+    };
+    view.is_some()
+}
+
+/// Highlights the segment under the cursor in a `width` x `height` frame
+pub fn hover(cursor: Option<Position>, width: u32, height: u32) {
+    if let Some(view) = view().as_mut() {
+        let strip = Strip::for_frame(width, height);
+        view.hovered = cursor.and_then(|pos| timeline().segment_at(&strip, pos));
+    }
+}
+
+/// Handles a click while the strip is open: switches to the scene of the
+/// segment under `pos`. Returns the scene switched to, if any.
+pub fn click(pos: Position, width: u32, height: u32) -> Option<&'static SceneInfo> {
+    let mut slot = view();
+    let view = slot.as_mut()?;
+    let index = timeline().segment_at(&Strip::for_frame(width, height), pos)?;
+    view.selected = Some(index);
+    enter_segment(index)
+}
+
+/// Moves the selection `steps` segments back or forward and switches to its scene
+pub fn step(steps: i32) -> Option<&'static SceneInfo> {
+    let mut slot = view();
+    let view = slot.as_mut()?;
+    let last = timeline().segments().len().checked_sub(1)?;
+    let from = view.selected.unwrap_or(last).min(last);
+    let index = (from as i64 + steps as i64).clamp(0, last as i64) as usize;
+    view.selected = Some(index);
+    enter_segment(index)
+}
+
+fn enter_segment(index: usize) -> Option<&'static SceneInfo> {
+    let scene = scenes::find_scene(timeline().segments().get(index)?.scene)?;
+    scene.enter();
+    Some(scene)
+}
+
+/// Color of a scene's segments, spread around the hue wheel in menu order
+fn scene_color(id: &str) -> [u8; 4] {
+    let index = scenes::SCENES
+        .iter()
+        .position(|scene| scene.id == id)
+        .unwrap_or(0);
+    let color = hsv_to_rgb(index as f32 / scenes::SCENES.len() as f32, 0.6, 0.9);
+    [color.red, color.green, color.blue, 255]
+}
+
+/// "12:34" for a time in seconds
+fn clock(seconds: f32) -> String {
+    let seconds = seconds.max(0.0) as u32;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+pub fn draw_timeline(
+    frame: &mut [u8],
+    width: u32,
+    height: u32,
+    x_offset: usize,
+    buffer_width: u32,
+) {
+    let Some(view) = *view() else {
+        return;
+    };
+    let timeline = timeline();
+    let strip = Strip::for_frame(width, height);
+    let rect = |frame: &mut [u8], left: f32, top: f32, w: f32, h: f32, color: [u8; 4]| {
+        draw_rectangle_safe(
+            frame,
+            (left + x_offset as f32) as i32,
+            top as i32,
+            w.ceil().max(1.0) as u32,
+            h as u32,
+            color,
+            buffer_width,
+            height,
+        );
+    };
+    rect(
+        frame,
+        strip.left - 4.0,
+        strip.top - 4.0 - MARK_HEIGHT,
+        strip.width + 8.0,
+        strip.height + 8.0 + MARK_HEIGHT,
+        hud_backing(BACKGROUND),
+    );
+    for (i, segment) in timeline.segments().iter().enumerate() {
+        let (left, span) = timeline.segment_span(i, &strip);
+        rect(
+            frame,
+            left,
+            strip.top,
+            span,
+            strip.height,
+            scene_color(segment.scene),
+        );
+        if view.selected == Some(i) || view.hovered == Some(i) {
+            rect(frame, left, strip.top, span, strip.height, HIGHLIGHT);
+        }
+    }
+    if let Some(first) = timeline.segments().first() {
+        let span = timeline.span().max(f32::EPSILON);
+        for mark in timeline.marks() {
+            let x = strip.left + (mark.at - first.start) / span * strip.width;
+            rect(
+                frame,
+                x,
+                strip.top - MARK_HEIGHT,
+                1.0,
+                MARK_HEIGHT,
+                MARK_COLOR,
+            );
+        }
+    }
+
+    let Some(index) = view.hovered.or(view.selected) else {
+        return;
+    };
+    let Some(segment) = timeline.segments().get(index) else {
+        return;
+    };
+    let name = scenes::find_scene(segment.scene).map_or(segment.scene, |scene| scene.name);
+    let label = format!(
+        "{}  {} - {}",
+        name,
+        clock(segment.start - timeline.segments()[0].start),
+        clock(timeline.end_of(index) - timeline.segments()[0].start)
+    );
+    let (left, span) = timeline.segment_span(index, &strip);
+    let text_width = estimate_text_width(&label);
+    let x = (left + span / 2.0 - text_width / 2.0).clamp(0.0, (width as f32 - text_width).max(0.0));
+    draw_text_with_background(
+        frame,
+        &label,
+        x + x_offset as f32,
+        strip.top - MARK_HEIGHT - 16.0,
+        TEXT_COLOR,
+        TEXT_BACKGROUND,
+        buffer_width,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_long_sessions_compact_but_keep_their_shape() {
+        let mut timeline = Timeline::new();
+        timeline.record_scene("rays", 0.0);
+        // An hour-long stretch in the World, then thousands of quick flips
+        timeline.record_scene("world", 10.0);
+        let mut now = 3610.0;
+        for i in 0..5000 {
+            let scene = if i % 2 == 0 { "tunnel" } else { "softbody" };
+            timeline.record_scene(scene, now);
+            now += 1.0 + (i % 7) as f32;
+        }
+        timeline.record_scene("clean", now);
+
+        let segments = timeline.segments();
+        assert!(segments.len() <= MAX_SEGMENTS, "{}", segments.len());
+        assert_eq!(segments[0].start, 0.0);
+        assert_eq!(segments.last().unwrap().scene, "clean");
+        // The long World stretch survives intact
+        let world = segments.iter().position(|s| s.scene == "world").unwrap();
+        assert_eq!(segments[world].start, 10.0);
+        assert!(segments[world + 1].start >= 3610.0);
+        // Starts stay in order and neighbors always differ
+        for pair in segments.windows(2) {
+            assert!(pair[0].start < pair[1].start);
+            assert_ne!(pair[0].scene, pair[1].scene);
+        }
+
+        for i in 0..MAX_MARKS + 10 {
+            timeline.record_event("Corner hit", i as f32);
+        }
+        assert_eq!(timeline.marks().len(), MAX_MARKS);
+        assert_eq!(timeline.marks()[0].at, now);
+    }
+
+    #[test]
+    fn test_segments_are_hit_where_they_are_drawn() {
+        let mut timeline = Timeline::new();
+        timeline.record_scene("rays", 100.0);
+        timeline.record_scene("world", 130.0);
+        timeline.record_scene("rays", 130.0);
+        timeline.record_scene("tunnel", 175.0);
+        timeline.record_scene("tunnel", 200.0);
+        // A zero-length World visit still opens a segment
+        assert_eq!(timeline.segments().len(), 4);
+
+        let strip = Strip::for_frame(180, 200);
+        assert_eq!(strip.width, 100.0);
+        let y = strip.top + 1.0;
+        let at = |x: f32| timeline.segment_at(&strip, Position::new(strip.left + x, y));
+        assert_eq!(at(0.0), Some(0));
+        assert_eq!(at(29.0), Some(0));
+        assert_eq!(at(31.0), Some(2));
+        assert_eq!(at(74.0), Some(2));
+        assert_eq!(at(76.0), Some(3));
+        assert_eq!(at(99.9), Some(3));
+        assert_eq!(at(100.0), None);
+        assert_eq!(
+            timeline.segment_at(&strip, Position::new(strip.left + 10.0, strip.top - 1.0)),
+            None
+        );
+        assert_eq!(timeline.segment_span(3, &strip), (strip.left + 75.0, 25.0));
+        assert_eq!(clock(125.0), "2:05");
+    }
+}
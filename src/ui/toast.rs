@@ -0,0 +1,133 @@
+//! A short message shown over the scene for a few seconds, e.g. settings
+//! that had to be corrected on load. A new toast replaces the old one.
+
+use crate::core::events::Event;
+use crate::text::text_rendering::{
+    background_text_origin, background_text_size, draw_text_with_background, estimate_text_width,
+};
+use crate::ui::hud_layout::{HudAnchor, HudElement, HudRect, HudRequest};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Seconds a toast stays up, including its fade-out
+pub const TOAST_DURATION: f32 = 6.0;
+const TOAST_FADE: f32 = 1.0;
+/// Below the status icons, above the leaderboard
+const TOAST_PRIORITY: u8 = 2;
+const TOAST_LINE_HEIGHT: f32 = 25.0;
+const TOAST_TEXT: [u8; 4] = [255, 230, 160, 255];
+const TOAST_BACKGROUND: [u8; 4] = [30, 20, 0, 200];
+
+static TOAST: Mutex<Option<Toast>> = Mutex::new(None);
+
+struct Toast {
+    lines: Vec<String>,
+    /// Scene time of the first frame it was drawn on
+    shown_at: Option<f32>,
+}
+
+fn locked_toast() -> MutexGuard<'static, Option<Toast>> {
+    TOAST.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Shows `lines` from the next frame on
+pub fn show_toast(lines: Vec<String>) {
+    *locked_toast() = Some(Toast {
+        lines,
+        shown_at: None,
+    });
+}
+
+/// Confirms changes made without other feedback, like the fallback sound
+pub fn on_event(event: &Event, _time: f32) {
+    if let Event::FallbackSoundChanged { sound } = event {
+        show_toast(vec![format!("Fallback sound: {}", sound.name())]);
+    }
+}
+
+pub fn is_toast_visible() -> bool {
+    locked_toast().is_some()
+}
+
+/// Room the toast asks for along the top edge, while one is up
+pub fn hud_request() -> Option<HudRequest> {
+    let slot = locked_toast();
+    let toast = slot.as_ref()?;
+    Some(HudRequest {
+        element: HudElement::Toast,
+        anchor: HudAnchor::TopCenter,
+        priority: TOAST_PRIORITY,
+        size: background_text_size(&toast.lines, TOAST_LINE_HEIGHT),
+    })
+}
+
+/// Opacity of a toast first drawn at `shown_at`, `time` later on
+fn toast_alpha(shown_at: f32, time: f32) -> f32 {
+    ((TOAST_DURATION - (time - shown_at)) / TOAST_FADE).clamp(0.0, 1.0)
+}
+
+/// Draws the toast's lines centered in `region`, its spot in the HUD layout;
+/// `frame` starts at the region's first row. Without a region the toast is
+/// hidden for lack of room, but its time still runs out.
+pub fn draw_toast(
+    frame: &mut [u8],
+    region: Option<HudRect>,
+    time: f32,
+    x_offset: usize,
+    buffer_width: u32,
+) {
+    let mut slot = locked_toast();
+    let Some(toast) = slot.as_mut() else {
+        return;
+    };
+    // A rewound clock (replays, restored snapshots) restarts the toast
+    let shown_at = match toast.shown_at {
+        Some(at) if at <= time => at,
+        _ => time,
+    };
+    toast.shown_at = Some(shown_at);
+    let alpha = toast_alpha(shown_at, time);
+    if alpha <= 0.0 {
+        *slot = None;
+        return;
+    }
+    let Some(region) = region else {
+        return;
+    };
+    let fade = |color: [u8; 4]| {
+        [
+            color[0],
+            color[1],
+            color[2],
+            (color[3] as f32 * alpha) as u8,
+        ]
+    };
+    let (left, baseline) = background_text_origin(region.x, region.y);
+    let text_width = region.width - 2.0 * (left - region.x);
+    for (i, line) in toast.lines.iter().enumerate() {
+        let x = x_offset as f32 + left + (text_width - estimate_text_width(line)) / 2.0;
+        let y = baseline + i as f32 * TOAST_LINE_HEIGHT;
+        draw_text_with_background(
+            frame,
+            line,
+            x,
+            y,
+            fade(TOAST_TEXT),
+            fade(TOAST_BACKGROUND),
+            buffer_width,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toast_holds_then_fades_out() {
+        assert_eq!(toast_alpha(2.0, 2.0), 1.0);
+        assert_eq!(toast_alpha(2.0, 2.0 + TOAST_DURATION - TOAST_FADE), 1.0);
+        let halfway = toast_alpha(2.0, 2.0 + TOAST_DURATION - TOAST_FADE / 2.0);
+        assert!((halfway - 0.5).abs() < 1e-4);
+        assert_eq!(toast_alpha(2.0, 2.0 + TOAST_DURATION), 0.0);
+    }
+}
@@ -0,0 +1,259 @@
+//! Small borderless windows shown outside the main app, like the audio
+//! download progress and its error report. `run_utility_window` owns the
+//! event loop, sizing, pixels setup, redraw loop, and closing; callers only
+//! draw. Each title can only be open once at a time.
+
+use log::error;
+use pixels::{Pixels, SurfaceTexture};
+use std::fmt;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use winit::{
+    dpi::LogicalSize,
+    error::{EventLoopError, OsError},
+    event::{Event, WindowEvent},
+    event_loop::{ControlFlow, EventLoop},
+    window::WindowBuilder,
+};
+
+/// Size used when there is no monitor to take half of
+const FALLBACK_SIZE: (u32, u32) = (800, 600);
+const FRAME_INTERVAL: Duration = Duration::from_millis(16);
+
+/// What the draw callback wants after drawing a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowCommand {
+    /// Keep the window open
+    Continue,
+    /// Close once this command has been given for the whole duration, so the
+    /// final state stays readable; going back to `Continue` starts over
+    CloseAfter(Duration),
+    Close,
+}
+
+/// When a window closes on its own. The user can always close it earlier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DurationPolicy {
+    /// Close this long after opening, whatever the draw callback says
+    AutoClose(Duration),
+    /// Stay open until the draw callback reports a final status
+    WaitForStatus,
+}
+
+/// Tracks a window's closing, apart from the event loop so it can be tested
+#[derive(Debug)]
+struct CloseTimer {
+    policy: DurationPolicy,
+    opened: Instant,
+    /// When the callback started asking to close after a delay
+    finishing_since: Option<Instant>,
+}
+
+impl CloseTimer {
+    fn new(policy: DurationPolicy, now: Instant) -> Self {
+        Self {
+            policy,
+            opened: now,
+            finishing_since: None,
+        }
+    }
+
+    /// Whether the window should close at `now`, given the latest command
+    fn should_close(&mut self, command: WindowCommand, now: Instant) -> bool {
+        if let DurationPolicy::AutoClose(after) = self.policy {
+            if now.duration_since(self.opened) >= after {
+                return true;
+            }
+        }
+        match command {
+            WindowCommand::Continue => {
+                self.finishing_since = None;
+                false
+            }
+            WindowCommand::CloseAfter(linger) => {
+                let since = *self.finishing_since.get_or_insert(now);
+                now.duration_since(since) >= linger
+            }
+            WindowCommand::Close => true,
+        }
+    }
+}
+
+/// Titles of the utility windows open right now
+static OPEN_WINDOWS: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
+
+/// Marks a window as open until dropped. Dropping also runs when a window
+/// panics, so a crashed window never blocks the next one.
+struct InstanceGuard(&'static str);
+
+impl InstanceGuard {
+    /// None if a window with this title is already open
+    fn acquire(title: &'static str) -> Option<Self> {
+        // A panic elsewhere while holding the lock leaves the list intact
+        let mut open = OPEN_WINDOWS.lock().unwrap_or_else(PoisonError::into_inner);
+        if open.contains(&title) {
+            return None;
+        }
+        open.push(title);
+        Some(Self(title))
+    }
+}
+
+impl Drop for InstanceGuard {
+    fn drop(&mut self) {
+        let mut open = OPEN_WINDOWS.lock().unwrap_or_else(PoisonError::into_inner);
+        open.retain(|&title| title != self.0);
+    }
+}
+
+#[derive(Debug)]
+pub enum UtilityWindowError {
+    /// A window with this title is already open
+    AlreadyOpen(&'static str),
+    EventLoop(EventLoopError),
+    Window(OsError),
+    Pixels(pixels::Error),
+}
+
+impl fmt::Display for UtilityWindowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UtilityWindowError::AlreadyOpen(title) => write!(f, "`{}` is already open", title),
+            UtilityWindowError::EventLoop(e) => write!(f, "event loop error: {}", e),
+            UtilityWindowError::Window(e) => write!(f, "could not create the window: {}", e),
+            UtilityWindowError::Pixels(e) => write!(f, "could not set up drawing: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for UtilityWindowError {}
+
+impl From<EventLoopError> for UtilityWindowError {
+    fn from(e: EventLoopError) -> Self {
+        UtilityWindowError::EventLoop(e)
+    }
+}
+
+impl From<OsError> for UtilityWindowError {
+    fn from(e: OsError) -> Self {
+        UtilityWindowError::Window(e)
+    }
+}
+
+impl From<pixels::Error> for UtilityWindowError {
+    fn from(e: pixels::Error) -> Self {
+        UtilityWindowError::Pixels(e)
+    }
+}
+
+/// Opens a borderless window half the size of the primary monitor and calls
+/// `draw(frame, width, height)` about 60 times a second until `policy`, the
+/// callback, or the user closes it. Returns once the window is closed.
+pub fn run_utility_window(
+    title: &'static str,
+    policy: DurationPolicy,
+    mut draw: impl FnMut(&mut [u8], u32, u32) -> WindowCommand,
+) -> Result<(), UtilityWindowError> {
+    let _guard = InstanceGuard::acquire(title).ok_or(UtilityWindowError::AlreadyOpen(title))?;
+    let event_loop = EventLoop::new()?;
+    let (window_width, window_height) =
+        event_loop
+            .primary_monitor()
+            .map_or(FALLBACK_SIZE, |monitor| {
+                let size = monitor.size();
+                (size.width / 2, size.height / 2)
+            });
+    let window = Arc::new(
+        WindowBuilder::new()
+            .with_title(title)
+            .with_inner_size(LogicalSize::new(window_width as f64, window_height as f64))
+            .with_resizable(false)
+            .with_decorations(false)
+            .build(&event_loop)?,
+    );
+    let size = window.inner_size();
+    let surface_texture = SurfaceTexture::new(size.width, size.height, Arc::clone(&window));
+    let mut pixels = Pixels::new(size.width, size.height, surface_texture)?;
+
+    let mut timer = CloseTimer::new(policy, Instant::now());
+    let mut command = WindowCommand::Continue;
+    event_loop.run(move |event, window_target| {
+        match event {
+            Event::WindowEvent {
+                event: WindowEvent::CloseRequested,
+                ..
+            } => window_target.exit(),
+            Event::WindowEvent {
+                event: WindowEvent::RedrawRequested,
+                ..
+            } => {
+                let (width, height) = (pixels.texture().width(), pixels.texture().height());
+                command = draw(pixels.frame_mut(), width, height);
+                if let Err(err) = pixels.render() {
+                    error!("Render error in {}: {err}", title);
+                    window_target.exit();
+                }
+            }
+            _ => {}
+        }
+        if timer.should_close(command, Instant::now()) {
+            window_target.exit();
+        }
+        window.request_redraw();
+        window_target.set_control_flow(ControlFlow::WaitUntil(Instant::now() + FRAME_INTERVAL));
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auto_close_and_wait_for_status_policies() {
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        let linger = WindowCommand::CloseAfter(Duration::from_millis(1500));
+
+        // Auto-close ignores the callback until its time is up
+        let mut timer = CloseTimer::new(DurationPolicy::AutoClose(Duration::from_secs(5)), start);
+        assert!(!timer.should_close(WindowCommand::Continue, at(4999)));
+        assert!(timer.should_close(WindowCommand::Continue, at(5000)));
+
+        // Waiting for status never times out on its own...
+        let mut timer = CloseTimer::new(DurationPolicy::WaitForStatus, start);
+        assert!(!timer.should_close(WindowCommand::Continue, at(600_000)));
+        // ...and lingers on a final status, counted from when it first appeared
+        assert!(!timer.should_close(linger, at(600_100)));
+        assert!(!timer.should_close(linger, at(601_500)));
+        assert!(timer.should_close(linger, at(601_600)));
+
+        // A status that goes back to running restarts the linger
+        let mut timer = CloseTimer::new(DurationPolicy::WaitForStatus, start);
+        assert!(!timer.should_close(linger, at(100)));
+        assert!(!timer.should_close(WindowCommand::Continue, at(1000)));
+        assert!(!timer.should_close(linger, at(1700)));
+        assert!(timer.should_close(linger, at(3200)));
+        assert!(timer.should_close(WindowCommand::Close, at(3201)));
+    }
+
+    #[test]
+    fn test_one_window_per_title_even_after_a_panic() {
+        let first = InstanceGuard::acquire("test progress").unwrap();
+        assert!(InstanceGuard::acquire("test progress").is_none());
+        let other = InstanceGuard::acquire("test error");
+        assert!(other.is_some());
+        drop(first);
+        assert!(InstanceGuard::acquire("test progress").is_some());
+
+        // A window that panics while holding the list doesn't lock everyone out
+        let _ = std::thread::spawn(|| {
+            let _guard = InstanceGuard::acquire("test crash").unwrap();
+            let _open = OPEN_WINDOWS.lock().unwrap();
+            panic!("window crashed");
+        })
+        .join();
+        assert!(OPEN_WINDOWS.is_poisoned());
+        assert!(InstanceGuard::acquire("test crash").is_some());
+    }
+}
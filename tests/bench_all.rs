@@ -0,0 +1,29 @@
+//! Runs in its own process: benchmarking starts a `StimStation`, which
+//! initializes the orchestrator, and the library's unit tests must not do that.
+
+use stimstation::core::bench::{self, BenchAllReport, BENCH_SIZES, REGRESSION_THRESHOLD};
+use stimstation::core::scenes::SCENES;
+
+#[test]
+fn test_bench_all_covers_every_scene_without_a_display() {
+    // Zero seconds still runs the minimum frame count for every scene
+    let report = bench::run_bench_all(0.0).unwrap();
+    assert_eq!(report.results.len(), SCENES.len() * BENCH_SIZES.len());
+    for ((width, height), scene) in BENCH_SIZES
+        .iter()
+        .flat_map(|size| SCENES.iter().map(move |scene| (*size, scene)))
+    {
+        let result = report
+            .results
+            .iter()
+            .find(|r| r.scene == scene.id && r.width == width && r.height == height)
+            .unwrap_or_else(|| panic!("no result for {} at {}x{}", scene.id, width, height));
+        assert!(result.frames >= 20);
+        assert!(result.avg_ms > 0.0);
+        assert!(result.p95_ms <= result.p99_ms);
+    }
+
+    let parsed = BenchAllReport::from_json(&report.to_json()).unwrap();
+    assert_eq!(parsed, report);
+    assert!(bench::compare_reports(&report, &parsed, REGRESSION_THRESHOLD).is_empty());
+}
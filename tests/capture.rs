@@ -0,0 +1,64 @@
+//! Runs in its own process: a capture draws through the orchestrator, which
+//! the library's unit tests must not set up.
+
+use stimstation::core::capture::{self, CaptureOptions};
+use stimstation::physics::physics::get_ball_positions;
+use stimstation::{StimConfig, StimStation};
+
+/// Mean difference per channel between two frames of the same size
+fn mean_difference(a: &[u8], b: &[u8]) -> f32 {
+    let total: u64 = a.iter().zip(b).map(|(x, y)| x.abs_diff(*y) as u64).sum();
+    total as f32 / a.len() as f32
+}
+
+#[test]
+fn test_capture_is_twice_the_frame_and_leaves_the_scene_alone() {
+    let config = StimConfig {
+        width: 480,
+        height: 240,
+        audio_playback: false,
+    };
+    let mut station = StimStation::new(config).unwrap();
+    let mut frame = vec![0; config.frame_len()];
+    // Rays and text keep their pixel width, so they come out thinner than in
+    // the live frame; the maze scales whole and matches it
+    for (scene, tolerance) in [("rays", 40.0), ("maze", 1.0)] {
+        station.set_scene(scene).unwrap();
+        for _ in 0..20 {
+            station.render(&mut frame, 1.0 / 60.0).unwrap();
+        }
+        let balls = get_ball_positions();
+        let stats = station.stats();
+
+        for render_scale in [2, 4] {
+            let options = CaptureOptions {
+                render_scale,
+                high_quality: false,
+            };
+            let image = station.capture(&options);
+            assert_eq!(
+                image.dimensions(),
+                capture::capture_size(config.width, config.height),
+                "{}",
+                scene
+            );
+            assert_eq!(image.dimensions(), (960, 480));
+            // Filtered down to the live size it is the same picture
+            let small = capture::downsample_box(image.as_raw(), 960, 480, 2);
+            let difference = mean_difference(&small, &frame);
+            assert!(
+                difference < tolerance,
+                "{} at {}x: {}",
+                scene,
+                render_scale,
+                difference
+            );
+        }
+
+        // The live scene is where it was, and the next frame draws at the usual size
+        assert_eq!(get_ball_positions(), balls, "{}", scene);
+        assert_eq!(station.stats(), stats);
+        station.render(&mut frame, 1.0 / 60.0).unwrap();
+        assert_eq!(station.stats().scene, scene);
+    }
+}
@@ -0,0 +1,112 @@
+//! Runs in its own process: a `StimStation` initializes the orchestrator,
+//! which the library's unit tests must not do.
+
+use stimstation::{EmbedError, StimConfig, StimStation};
+use winit::keyboard::KeyCode;
+
+#[test]
+fn test_embedded_station_renders_and_reports_stats() {
+    let config = StimConfig {
+        audio_playback: false,
+        ..StimConfig::default()
+    };
+    let mut station = StimStation::new(config).unwrap();
+
+    assert_eq!(
+        station.set_scene("nope"),
+        Err(EmbedError::UnknownScene("nope".into()))
+    );
+    station.set_scene("world").unwrap();
+    assert_eq!(station.scene(), "world");
+
+    let mut frame = vec![0; config.frame_len()];
+    assert!(matches!(
+        station.render(&mut frame[1..], 0.016),
+        Err(EmbedError::FrameSize { .. })
+    ));
+    station.handle_cursor(100.0, 100.0);
+    station.handle_key(KeyCode::Space, true);
+    for _ in 0..3 {
+        station.render(&mut frame, 1.0 / 60.0).unwrap();
+    }
+    let stats = station.stats();
+    assert_eq!(stats.frames, 3);
+    assert!((stats.time - 3.0 / 60.0).abs() < 1e-5);
+    assert_eq!(stats.scene, "world");
+    assert!(frame.chunks_exact(4).any(|px| px[..3] != [0, 0, 0]));
+}
+
+#[test]
+fn test_two_stations_keep_their_own_scenes() {
+    let config = StimConfig {
+        audio_playback: false,
+        ..StimConfig::default()
+    };
+    let mut world = StimStation::new(config).unwrap();
+    let mut tunnel = StimStation::new(config).unwrap();
+    world.set_scene("world").unwrap();
+    tunnel.set_scene("tunnel").unwrap();
+
+    let mut world_frame = vec![0; config.frame_len()];
+    let mut tunnel_frame = vec![0; config.frame_len()];
+    for frame in 1..=3 {
+        world.render(&mut world_frame, 1.0 / 60.0).unwrap();
+        tunnel.render(&mut tunnel_frame, 1.0 / 30.0).unwrap();
+        assert_eq!(world.stats().frames, frame);
+        assert_eq!(tunnel.stats().frames, frame);
+    }
+    assert_eq!(world.scene(), "world");
+    assert_eq!(tunnel.scene(), "tunnel");
+    assert_eq!(world.stats().scene, "world");
+    assert_eq!(tunnel.stats().scene, "tunnel");
+    assert!((world.stats().time - 3.0 / 60.0).abs() < 1e-5);
+    assert!((tunnel.stats().time - 3.0 / 30.0).abs() < 1e-5);
+    assert_ne!(world_frame, tunnel_frame);
+
+    // Dropping one leaves the other as it was
+    drop(tunnel);
+    world.render(&mut world_frame, 1.0 / 60.0).unwrap();
+    assert_eq!(world.scene(), "world");
+    assert_eq!(world.stats().frames, 4);
+}
+
+#[test]
+fn test_stations_on_two_threads_keep_their_own_scenes() {
+    let config = StimConfig {
+        audio_playback: false,
+        ..StimConfig::default()
+    };
+    let run = move |scene: &'static str, dt: f32| {
+        std::thread::spawn(move || {
+            let mut station = StimStation::new(config).unwrap();
+            station.set_scene(scene).unwrap();
+            let mut frame = vec![0; config.frame_len()];
+            for _ in 0..3 {
+                station.render(&mut frame, dt).unwrap();
+            }
+            station.stats()
+        })
+    };
+    let world = run("world", 1.0 / 60.0);
+    let tunnel = run("tunnel", 1.0 / 30.0);
+    let (world, tunnel) = (world.join().unwrap(), tunnel.join().unwrap());
+    assert_eq!((world.scene, world.frames), ("world", 3));
+    assert_eq!((tunnel.scene, tunnel.frames), ("tunnel", 3));
+    assert!((world.time - 3.0 / 60.0).abs() < 1e-5);
+    assert!((tunnel.time - 3.0 / 30.0).abs() < 1e-5);
+}
+
+#[test]
+fn test_arrow_key_before_the_first_frame() {
+    let config = StimConfig {
+        audio_playback: false,
+        ..StimConfig::default()
+    };
+    let mut station = StimStation::new(config).unwrap();
+    station.handle_key(KeyCode::ArrowLeft, true);
+
+    let mut frame = vec![0; config.frame_len()];
+    station.render(&mut frame, 1.0 / 60.0).unwrap();
+    station.render(&mut frame, 1.0 / 60.0).unwrap();
+    assert_eq!(station.stats().frames, 2);
+}
@@ -0,0 +1,43 @@
+//! Runs in its own process: exporting initializes the orchestrator, which the
+//! library's unit tests must not do.
+
+use serde_json::Value;
+use stimstation::core::export::{self, MANIFEST_VERSION, THUMBNAIL_HEIGHT, THUMBNAIL_WIDTH};
+use stimstation::core::scenes::SCENES;
+
+fn assert_help(help: &Value) {
+    for entry in help.as_array().expect("help is an array") {
+        assert!(entry["key"].as_str().is_some_and(|k| !k.is_empty()));
+        assert!(entry["action"].as_str().is_some_and(|a| !a.is_empty()));
+    }
+}
+
+#[test]
+fn test_manifest_matches_schema_and_thumbnails_decode() {
+    let dir = std::env::temp_dir().join(format!("stimstation-export-{}", std::process::id()));
+    let out = dir.join("manifest.json");
+    let thumbs = dir.join("thumbs");
+
+    let count = export::export_manifest(&out, &thumbs).unwrap();
+    assert_eq!(count, SCENES.len());
+
+    let manifest: Value = serde_json::from_str(&std::fs::read_to_string(&out).unwrap()).unwrap();
+    assert_eq!(manifest["version"], MANIFEST_VERSION);
+    assert_help(&manifest["common_help"]);
+
+    let scenes = manifest["scenes"].as_array().expect("scenes is an array");
+    assert_eq!(scenes.len(), SCENES.len());
+    for (scene, info) in scenes.iter().zip(SCENES) {
+        assert_eq!(scene["id"], info.id);
+        assert!(scene["name"].is_string());
+        assert!(scene["description"].is_string());
+        assert!(scene["uses_audio"].is_boolean());
+        assert_help(&scene["help"]);
+
+        let thumbnail = image::open(scene["thumbnail"].as_str().unwrap()).unwrap();
+        assert_eq!(thumbnail.width(), THUMBNAIL_WIDTH);
+        assert_eq!(thumbnail.height(), THUMBNAIL_HEIGHT);
+    }
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,54 @@
+//! Runs in its own process: `--no-audio` is process-wide, and a `StimStation`
+//! initializes the orchestrator, which the library's unit tests must not do.
+
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use stimstation::audio::{self, audio_download, audio_playback};
+use stimstation::ui::{device_picker, toast};
+use stimstation::{StimConfig, StimStation};
+use winit::keyboard::KeyCode;
+
+/// Counts download attempts instead of touching the network
+struct CountingDownloader(Arc<AtomicUsize>);
+
+impl audio_download::Downloader for CountingDownloader {
+    fn download(&mut self, _url: &str, _path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        Err("no network in tests".into())
+    }
+}
+
+#[test]
+fn test_no_audio_reaches_the_first_frame_without_downloading() {
+    let downloads = Arc::new(AtomicUsize::new(0));
+    audio_download::set_downloader(Box::new(CountingDownloader(downloads.clone())));
+    audio::set_audio_enabled(false);
+
+    let started = Instant::now();
+    // Asks for playback, which --no-audio overrides
+    let config = StimConfig::default();
+    let mut station = StimStation::new(config).unwrap();
+    let mut frame = vec![0; config.frame_len()];
+    station.render(&mut frame, 1.0 / 60.0).unwrap();
+    assert!(started.elapsed() < Duration::from_secs(1));
+
+    assert!(!audio_playback::is_playing());
+    assert!(audio_playback::start().is_err());
+    assert!(futures::executor::block_on(audio_download::ensure_audio_file()).is_err());
+
+    // Keys that need audio explain themselves instead of acting
+    station.handle_key(KeyCode::Digit9, true);
+    station.handle_key(KeyCode::KeyO, true);
+    station.render(&mut frame, 1.0 / 60.0).unwrap();
+    assert!(toast::is_toast_visible());
+    assert_eq!(
+        audio_playback::fallback_sound(),
+        audio_playback::FallbackSound::Off
+    );
+    assert!(!device_picker::is_picker_open());
+    assert!(!audio_playback::is_playing());
+
+    assert_eq!(downloads.load(Ordering::SeqCst), 0);
+}
@@ -0,0 +1,103 @@
+//! Runs in its own process: rendering creates a `StimStation`, which the
+//! library's unit tests must not do.
+
+use serde_json::Value;
+use stimstation::core::render_export::{
+    self, frame_file_name, FrameRange, RenderOptions, RENDER_MANIFEST_NAME, RENDER_MANIFEST_VERSION,
+};
+use stimstation::core::scenes;
+
+fn assert_manifest_schema(manifest: &Value, options: &RenderOptions) {
+    assert_eq!(manifest["version"], RENDER_MANIFEST_VERSION);
+    assert_eq!(manifest["scene"], options.scene.id);
+    assert_eq!(manifest["fps"], options.fps);
+    assert_eq!(manifest["width"], options.width);
+    assert_eq!(manifest["height"], options.height);
+    assert!(manifest["seed"].is_u64());
+    let range = &manifest["frame_range"];
+    for key in ["start", "end", "every"] {
+        assert!(range[key].is_u64(), "frame_range.{}", key);
+    }
+    assert_eq!(manifest["frame_count"], options.range.len());
+    let pattern = manifest["frame_pattern"].as_str().unwrap();
+    assert_eq!(pattern.replace("%06d", "000003"), frame_file_name(3));
+}
+
+#[test]
+fn test_an_interrupted_export_resumes_at_the_first_missing_frame() {
+    let dir = std::env::temp_dir().join(format!("stimstation-render-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let options = RenderOptions {
+        scene: scenes::find_scene("rays").unwrap(),
+        width: 96,
+        height: 64,
+        fps: 30,
+        seed: Some(1234),
+        range: FrameRange::new(4, 40, 3).unwrap(),
+        with_audio: false,
+    };
+
+    let mut reported = Vec::new();
+    let first = render_export::render_frames(&options, &dir, |progress| {
+        reported.push(progress.done);
+    })
+    .unwrap();
+    assert_eq!(first.rendered, 13);
+    assert_eq!(first.resumed, 0);
+    assert_eq!(reported, (1..=13).collect::<Vec<_>>());
+    for index in 0..=41 {
+        let written = dir.join(frame_file_name(index)).exists();
+        assert_eq!(written, options.range.contains(index), "frame {}", index);
+    }
+    let frame = image::open(dir.join(frame_file_name(4))).unwrap();
+    assert_eq!((frame.width(), frame.height()), (96, 64));
+
+    // Interrupted after frame 19: the later frames are missing. A resume
+    // without a seed keeps the first run's.
+    for index in [22, 25, 28, 31, 34, 37, 40] {
+        std::fs::remove_file(dir.join(frame_file_name(index))).unwrap();
+    }
+    let kept = std::fs::metadata(dir.join(frame_file_name(19)))
+        .unwrap()
+        .modified()
+        .unwrap();
+    let resumed = render_export::render_frames(
+        &RenderOptions {
+            seed: None,
+            ..options
+        },
+        &dir,
+        |_| {},
+    )
+    .unwrap();
+    assert_eq!((resumed.rendered, resumed.resumed), (7, 6));
+    assert_eq!(resumed.seed, 1234);
+    let modified = std::fs::metadata(dir.join(frame_file_name(19)))
+        .unwrap()
+        .modified()
+        .unwrap();
+    assert_eq!(modified, kept);
+    assert!(options
+        .range
+        .indices()
+        .all(|i| dir.join(frame_file_name(i)).exists()));
+
+    let text = std::fs::read_to_string(dir.join(RENDER_MANIFEST_NAME)).unwrap();
+    let manifest: Value = serde_json::from_str(&text).unwrap();
+    assert_manifest_schema(&manifest, &options);
+    assert_eq!(manifest["frame_range"]["end"], 40);
+    assert_eq!(manifest["seed"], 1234);
+    // No audio was asked for, so none is listed
+    assert!(manifest["audio"].is_null());
+
+    // Nothing left to do, and a different size won't mix into these frames
+    let done = render_export::render_frames(&options, &dir, |_| {}).unwrap();
+    assert_eq!((done.rendered, done.resumed), (0, 13));
+    let other_size = RenderOptions {
+        width: 128,
+        ..options
+    };
+    assert!(render_export::render_frames(&other_size, &dir, |_| {}).is_err());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
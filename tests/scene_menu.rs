@@ -0,0 +1,31 @@
+//! Runs in its own process: the scene menu and the theme are shared by every
+//! station, so the other embed tests would see them change.
+
+use stimstation::graphics::theme;
+use stimstation::types::Position;
+use stimstation::ui::menu;
+use stimstation::{StimConfig, StimStation};
+use winit::keyboard::KeyCode;
+
+#[test]
+fn test_scene_keys_do_nothing_behind_the_open_menu() {
+    let config = StimConfig {
+        audio_playback: false,
+        ..StimConfig::default()
+    };
+    let mut station = StimStation::new(config).unwrap();
+    let mut frame = vec![0; config.frame_len()];
+    station.render(&mut frame, 1.0 / 60.0).unwrap();
+    let before = theme::current_theme();
+
+    menu::open_menu_at(Position::new(100.0, 100.0), config.width, config.height);
+    station.handle_key(KeyCode::KeyT, true);
+    station.render(&mut frame, 1.0 / 60.0).unwrap();
+    station.handle_key(KeyCode::KeyT, false);
+    assert_eq!(theme::current_theme(), before);
+
+    menu::close_menu();
+    station.handle_key(KeyCode::KeyT, true);
+    station.render(&mut frame, 1.0 / 60.0).unwrap();
+    assert_ne!(theme::current_theme(), before);
+}
@@ -0,0 +1,78 @@
+//! Runs in its own process: a `StimStation` initializes the orchestrator,
+//! which the library's unit tests must not do.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use stimstation::core::events::{self, Event};
+use stimstation::core::session_stats;
+use stimstation::{StimConfig, StimStation};
+use winit::keyboard::KeyCode;
+
+static CORNER_HITS: AtomicU64 = AtomicU64::new(0);
+static SORTS_FINISHED: AtomicU64 = AtomicU64::new(0);
+
+#[test]
+fn test_summary_matches_a_scripted_session() {
+    let config = StimConfig {
+        width: 320,
+        height: 200,
+        audio_playback: false,
+    };
+    let mut station = StimStation::new(config).unwrap();
+    // Counted separately to check the summary against
+    events::subscribe(|event, _| match event {
+        Event::CornerHit { .. } => {
+            CORNER_HITS.fetch_add(1, Ordering::SeqCst);
+        }
+        Event::SorterCompleted { .. } => {
+            SORTS_FINISHED.fetch_add(1, Ordering::SeqCst);
+        }
+        _ => {}
+    });
+    let mut frame = vec![0; config.frame_len()];
+    let dt = 1.0 / 60.0;
+
+    // Two seconds of rays, one of the World with three explosions, then half
+    // a second of rays again
+    station.set_scene("rays").unwrap();
+    for _ in 0..120 {
+        station.render(&mut frame, dt).unwrap();
+    }
+    station.set_scene("world").unwrap();
+    for i in 0..60 {
+        station.handle_key(KeyCode::KeyE, i % 20 == 0);
+        station.render(&mut frame, dt).unwrap();
+    }
+    station.set_scene("rays").unwrap();
+    for _ in 0..30 {
+        station.render(&mut frame, dt).unwrap();
+    }
+    // Sorters finish while drawing, after the frame's events went out
+    events::drain(station.stats().time);
+
+    let summary = session_stats::summary();
+    assert_eq!(summary.frames, 210);
+    assert!(
+        (summary.runtime - 209.0 * dt).abs() < 1e-3,
+        "{}",
+        summary.runtime
+    );
+    assert!(
+        (summary.average_fps - 60.0).abs() < 0.1,
+        "{}",
+        summary.average_fps
+    );
+    let scenes: Vec<&str> = summary.scenes.iter().map(|(id, _)| *id).collect();
+    assert_eq!(scenes, ["rays", "world"]);
+    assert!((summary.scenes[0].1 - 149.0 * dt).abs() < 1e-3);
+    assert!((summary.scenes[1].1 - 60.0 * dt).abs() < 1e-3);
+    assert_eq!(summary.explosions, 3);
+    assert!(summary.peak_particles > 0);
+    assert_eq!(summary.corner_hits, CORNER_HITS.load(Ordering::SeqCst));
+    let sorts: u32 = summary.sorter_completions.iter().map(|(_, n)| n).sum();
+    assert_eq!(sorts as u64, SORTS_FINISHED.load(Ordering::SeqCst));
+
+    let table = summary.format_table();
+    assert!(table.contains("210 (60.0 fps average)"), "{}", table);
+    assert!(table.contains("Explosions"));
+    assert_eq!(summary.to_json()["explosions"], 3);
+}
@@ -0,0 +1,21 @@
+//! Runs in its own process: the bench draws through the Shatter scene's
+//! global state, which the library's unit tests switch on and off.
+
+use stimstation::core::bench;
+
+/// One frame at 60 fps, in milliseconds
+const FRAME_BUDGET_MS: f64 = 1000.0 / 60.0;
+
+#[test]
+fn test_shatter_draws_200_shards_at_60_fps() {
+    let report = bench::run_shatter_bench(300);
+    assert_eq!(report.frames, 300);
+    // Unoptimized builds are several times slower; time it with `--release`
+    if !cfg!(debug_assertions) {
+        assert!(
+            report.average_ms() < FRAME_BUDGET_MS,
+            "{:.2} ms/frame",
+            report.average_ms()
+        );
+    }
+}
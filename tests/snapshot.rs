@@ -0,0 +1,73 @@
+//! Runs in its own process: rendering creates a `StimStation`, which the
+//! library's unit tests must not do.
+
+use stimstation::core::energy::{self, EnergyGains};
+use stimstation::core::{sim_rng, snapshot};
+use stimstation::{StimConfig, StimStation};
+use winit::keyboard::KeyCode;
+
+const DT: f32 = 0.016;
+
+/// Renders `count` frames, `DT` apart from `start`, returning each frame's bytes
+fn render_from(station: &mut StimStation, start: f32, count: usize) -> Vec<Vec<u8>> {
+    (0..count)
+        .map(|i| {
+            let mut frame = vec![0; station.config().frame_len()];
+            station
+                .render_at(&mut frame, start + i as f32 * DT)
+                .unwrap();
+            frame
+        })
+        .collect()
+}
+
+#[test]
+fn test_restored_snapshot_renders_the_same_frames() {
+    let config = StimConfig {
+        audio_playback: false,
+        ..StimConfig::default()
+    };
+    // Neither the energy meter nor the audio bars are part of a snapshot
+    energy::set_energy_gains(EnergyGains {
+        explosion: 0.0,
+        corner_hit: 0.0,
+        sort_completed: 0.0,
+        beat: 0.0,
+    });
+    stimstation::audio::set_viz_enabled(false);
+    sim_rng::seed(627);
+    let mut station = StimStation::new(config).unwrap();
+    // The balls and sorters; the World's line colors follow the wall clock
+    station.set_scene("rays").unwrap();
+    render_from(&mut station, 0.0, 30);
+
+    let saved = snapshot::capture();
+    assert!(saved.balls.is_some());
+    assert!(saved.sorters.iter().all(Option::is_some));
+    // Restoring drops the trails and smoothing, so do it here too
+    snapshot::restore(&saved);
+    sim_rng::seed(628);
+    let expected = render_from(&mut station, 30.0 * DT, 3);
+
+    // Push the balls off course and let the sorters run on, though not so
+    // far that one finishes: the leaderboard counts finishes for the session
+    station.handle_key(KeyCode::ArrowLeft, true);
+    station.handle_key(KeyCode::ArrowUp, true);
+    let later = render_from(&mut station, 33.0 * DT, 10);
+    station.handle_key(KeyCode::ArrowLeft, false);
+    station.handle_key(KeyCode::ArrowUp, false);
+    assert!(later.iter().all(|frame| !expected.contains(frame)));
+    assert_ne!(snapshot::capture().balls, saved.balls);
+    assert_ne!(snapshot::capture().sorters, saved.sorters);
+
+    snapshot::restore(&saved);
+    sim_rng::seed(628);
+    let restored = render_from(&mut station, 30.0 * DT, 3);
+    for (i, (restored, expected)) in restored.iter().zip(&expected).enumerate() {
+        assert!(
+            restored == expected,
+            "frame {} after the restore differs",
+            i
+        );
+    }
+}
@@ -0,0 +1,147 @@
+//! Runs in its own process: the scenes draw through the orchestrator, which
+//! the library's unit tests must not set up.
+
+use std::collections::HashSet;
+use std::sync::{Mutex, MutexGuard};
+use stimstation::audio::audio_handler::set_demo_bars;
+use stimstation::core::scenes::{self, SCENES};
+use stimstation::graphics::draw_ctx::{DrawCtx, Region};
+use stimstation::{integration, orchestrator};
+use stimstation::{StimConfig, StimStation};
+
+/// A value no scene draws, filling the buffer around the region
+const SENTINEL: [u8; 4] = [1, 2, 3, 4];
+const MARGIN: u32 = 40;
+
+/// Only one station may run at a time, so the tests take turns. The station
+/// comes first in the tuple so it is dropped before the turn is handed on.
+static STATION: Mutex<()> = Mutex::new(());
+
+fn station(config: StimConfig) -> (StimStation, MutexGuard<'static, ()>) {
+    let turn = STATION
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    (StimStation::new(config).unwrap(), turn)
+}
+
+/// A frame drawn into the middle of a larger buffer, so anything written past
+/// its edges shows up as a changed border
+struct CheckedFrame {
+    buffer: Vec<u8>,
+    region: Region,
+    buffer_width: u32,
+}
+
+impl CheckedFrame {
+    fn new(width: u32, height: u32) -> Self {
+        let (buffer_width, buffer_height) = (width + 2 * MARGIN, height + 2 * MARGIN);
+        Self {
+            buffer: SENTINEL.repeat((buffer_width * buffer_height) as usize),
+            region: Region::new(MARGIN as usize, MARGIN as usize, width, height),
+            buffer_width,
+        }
+    }
+
+    fn render(&mut self, time: f32) {
+        self.draw(time, orchestrator::render_frame);
+    }
+
+    fn draw(&mut self, time: f32, draw: impl FnOnce(&mut DrawCtx)) {
+        let mut ctx = DrawCtx::new(&mut self.buffer, self.region, self.buffer_width, time);
+        draw(&mut ctx);
+    }
+
+    fn pixels(&self) -> impl Iterator<Item = (bool, &[u8])> {
+        let region = self.region;
+        let width = self.buffer_width as usize;
+        self.buffer
+            .chunks_exact(4)
+            .enumerate()
+            .map(move |(i, pixel)| {
+                let (x, y) = (i % width, i / width);
+                let inside = x >= region.x
+                    && x < region.x + region.width as usize
+                    && y >= region.y
+                    && y < region.y + region.height as usize;
+                (inside, pixel)
+            })
+    }
+
+    /// Pixels outside the region that are no longer the sentinel
+    fn written_outside(&self) -> usize {
+        self.pixels()
+            .filter(|&(inside, pixel)| !inside && pixel != SENTINEL)
+            .count()
+    }
+
+    fn colors_inside(&self) -> usize {
+        self.pixels()
+            .filter(|&(inside, _)| inside)
+            .map(|(_, pixel)| [pixel[0], pixel[1], pixel[2]])
+            .collect::<HashSet<_>>()
+            .len()
+    }
+}
+
+#[test]
+fn test_every_scene_stays_inside_a_160x120_frame() {
+    let config = StimConfig {
+        width: 160,
+        height: 120,
+        audio_playback: false,
+    };
+    let _station = station(config);
+    for scene in SCENES {
+        scene.enter();
+        assert_eq!(scenes::active_scene().id, scene.id);
+        let mut frame = CheckedFrame::new(config.width, config.height);
+        for i in 0..60 {
+            frame.render(i as f32 / 60.0);
+        }
+        assert_eq!(frame.written_outside(), 0, "{}", scene.id);
+        // Something drawn over the background, not a blank or single-color
+        // frame; the maze is flat-shaded so a handful of colors is enough
+        assert!(frame.colors_inside() >= 3, "{}", scene.id);
+    }
+}
+
+#[test]
+fn test_audio_bars_stay_inside_their_pane() {
+    let config = StimConfig {
+        width: 160,
+        height: 120,
+        audio_playback: false,
+    };
+    let _station = station(config);
+    set_demo_bars(true);
+    // Half-width panes of a split frame, panes of different heights, and a
+    // pane too narrow for one pixel per band
+    for (width, height) in [(800, 800), (400, 600), (123, 45), (30, 200), (3, 3)] {
+        let mut frame = CheckedFrame::new(width, height);
+        for i in 0..30 {
+            frame.draw(i as f32 / 30.0, integration::draw_audio);
+        }
+        assert_eq!(frame.written_outside(), 0, "{width}x{height}");
+        assert!(frame.colors_inside() > 1, "{width}x{height}");
+
+        // Drawn into the right half of the pane, the left half is untouched
+        let mut frame = CheckedFrame::new(width, height);
+        let right = Region::new(width as usize / 2, 0, width - width / 2, height);
+        for i in 0..30 {
+            frame.draw(i as f32 / 30.0, |ctx| {
+                integration::draw_audio_in(ctx, right)
+            });
+        }
+        let region = frame.region;
+        let written_left = frame
+            .pixels()
+            .enumerate()
+            .filter(|&(i, (inside, pixel))| {
+                let x = i % frame.buffer_width as usize;
+                inside && x < region.x + right.x && pixel != SENTINEL
+            })
+            .count();
+        assert_eq!(written_left, 0, "{width}x{height}");
+    }
+    set_demo_bars(false);
+}